@@ -0,0 +1,680 @@
+// Copyright 2026 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! A JSON-RPC 2.0 server for managing a running VM.
+//!
+//! Each request and response is a single line of JSON. Supported methods:
+//!
+//! - `status`: returns `{"state": "running"}`, or `paused`, `shutdown`,
+//!   `reboot-pending`.
+//! - `pause`, `resume`: pause or resume all VCPUs.
+//! - `shutdown`: stop the VM.
+//! - `power-off`: press the ACPI power button so that the guest can shut
+//!   itself down, with optional params `{"timeout": 30}`. The VM is stopped
+//!   if it is still running after `timeout` seconds.
+//! - `reset`: reset the VM and boot it again.
+//! - `query-devices`: list PCI devices with their BDFs and names.
+//! - `query-memory`: report the memory size and the guest memory layout.
+//! - `query-metrics`: report VM exits per VCPU and per emulated region, and
+//!   queue counters of VirtIO devices. With params `{"format": "prometheus"}`,
+//!   the result is a string in the Prometheus text format.
+//! - `set-link`: set the link state of a VirtIO net device, with params
+//!   `{"name": "virtio-net-0", "up": false}`.
+//! - `set-balloon`: set the size of a VirtIO balloon device, i.e. memory
+//!   the guest is asked to give up, with params
+//!   `{"name": "virtio-balloon", "size": 536870912}`.
+//! - `query-balloon`: report the target and actual sizes of a balloon and
+//!   the memory statistics from the guest, with params
+//!   `{"name": "virtio-balloon"}`. The statistics are refreshed after each
+//!   query.
+//! - `resize-mem`: change the size of memory the guest is asked to plug
+//!   through a virtio-mem device, with params
+//!   `{"name": "virtio-mem", "size": 1073741824}`.
+//! - `snapshot`: write a snapshot of the paused VM to a directory, with
+//!   params `{"path": "/path/to/dir"}`.
+//! - `migrate`: live-migrate the running VM to another alioth process
+//!   started with `--incoming`, with params `{"to": "uds,path=/tmp/m.sock"}`.
+//!   It returns after the destination has taken over and the VM has shut
+//!   down.
+//! - `device-add`: hot-plug a device on `x86_64`, with params
+//!   `{"name": "scratch-0", "type": "blk", "args": "path=/tmp/disk.img"}`.
+//!   `type` is `blk`, `net` or `vfio`, and `args` are the same as those of
+//!   `--blk file,...`, `--net tap,...` and `--vfio-cdev`.
+//! - `device-del`: ask the guest to release a PCI device, with params
+//!   `{"name": "scratch-0"}`. The device is removed once the guest ejects
+//!   it.
+
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::io::{BufRead, BufReader, Write};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+use alioth::board::{BoardState, MigrationAddr};
+use alioth::hv::Hypervisor;
+use alioth::mem::{MemRegionEntry, MemRegionType};
+use alioth::metrics::{Metrics, RegionMetrics, VcpuMetrics, VirtioMetrics};
+#[cfg(target_os = "linux")]
+use alioth::vfio::CdevParam;
+use alioth::virtio::dev::blk::BlkFileParam;
+#[cfg(target_os = "linux")]
+use alioth::virtio::dev::net::tap::NetTapParam;
+use alioth::vm::Machine;
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+
+const PARSE_ERROR: i32 = -32700;
+const INVALID_REQUEST: i32 = -32600;
+const METHOD_NOT_FOUND: i32 = -32601;
+const INVALID_PARAMS: i32 = -32602;
+const SERVER_ERROR: i32 = -32000;
+
+/// Seconds to wait for the guest to power off before stopping the VM.
+pub const DEFAULT_POWER_OFF_TIMEOUT: u64 = 30;
+
+/// Operations on a VM that are exposed through the API socket.
+pub trait VmControl: Send + Sync + 'static {
+    type Error: std::error::Error;
+
+    fn state(&self) -> BoardState;
+    fn pause(&self) -> Result<(), Self::Error>;
+    fn resume(&self) -> Result<(), Self::Error>;
+    fn shutdown(&self) -> Result<(), Self::Error>;
+    fn power_off(&self, timeout: Duration) -> Result<(), Self::Error>;
+    fn reboot(&self) -> Result<(), Self::Error>;
+    fn devices(&self) -> Vec<DeviceInfo>;
+    fn memory(&self) -> MemoryInfo;
+    fn metrics(&self) -> Metrics;
+    fn set_net_link(&self, name: &str, up: bool) -> Result<(), Self::Error>;
+    fn set_balloon_size(&self, name: &str, size: u64) -> Result<(), Self::Error>;
+    fn balloon(&self, name: &str) -> Result<BalloonInfo, Self::Error>;
+    fn resize_mem(&self, name: &str, size: u64) -> Result<(), Self::Error>;
+    fn snapshot(&self, dir: &Path) -> Result<(), Self::Error>;
+    fn migrate(&self, addr: &MigrationAddr) -> Result<(), Self::Error>;
+    fn add_device(&self, name: &str, param: HotplugParam) -> Result<(), Self::Error>;
+    fn remove_device(&self, name: &str) -> Result<(), Self::Error>;
+}
+
+/// A device that can be hot-plugged through the API.
+#[derive(Debug, PartialEq, Eq)]
+pub enum HotplugParam {
+    Blk(BlkFileParam),
+    #[cfg(target_os = "linux")]
+    Net(NetTapParam),
+    #[cfg(target_os = "linux")]
+    Vfio(CdevParam),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct DeviceInfo {
+    pub bdf: String,
+    pub name: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct MemoryRegionInfo {
+    pub gpa: u64,
+    pub size: u64,
+    #[serde(rename = "type")]
+    pub type_: &'static str,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct MemoryInfo {
+    pub size: u64,
+    pub regions: Vec<MemoryRegionInfo>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct BalloonInfo {
+    pub size: u64,
+    pub actual: u64,
+    pub stats: BTreeMap<String, u64>,
+}
+
+fn region_type(type_: MemRegionType) -> &'static str {
+    match type_ {
+        MemRegionType::Hidden => "hidden",
+        MemRegionType::Ram => "ram",
+        MemRegionType::Reserved => "reserved",
+        MemRegionType::Acpi => "acpi",
+        MemRegionType::Pmem => "pmem",
+        MemRegionType::Hotplug => "hotplug",
+    }
+}
+
+fn state_name(state: BoardState) -> &'static str {
+    match state {
+        BoardState::Paused => "paused",
+        BoardState::Running => "running",
+        BoardState::Shutdown => "shutdown",
+        BoardState::RebootPending => "reboot-pending",
+    }
+}
+
+impl<H> VmControl for Machine<H>
+where
+    H: Hypervisor + 'static,
+    Machine<H>: Send + Sync,
+{
+    type Error = alioth::vm::Error;
+
+    fn state(&self) -> BoardState {
+        Machine::state(self)
+    }
+
+    fn pause(&self) -> Result<(), Self::Error> {
+        Machine::pause(self)
+    }
+
+    fn resume(&self) -> Result<(), Self::Error> {
+        Machine::resume(self)
+    }
+
+    fn shutdown(&self) -> Result<(), Self::Error> {
+        Machine::shutdown(self)
+    }
+
+    fn power_off(&self, timeout: Duration) -> Result<(), Self::Error> {
+        Machine::power_off(self, timeout)
+    }
+
+    fn reboot(&self) -> Result<(), Self::Error> {
+        Machine::reboot(self)
+    }
+
+    fn devices(&self) -> Vec<DeviceInfo> {
+        self.pci_devices()
+            .into_iter()
+            .map(|(bdf, dev)| DeviceInfo {
+                bdf: bdf.to_string(),
+                name: dev.name().to_owned(),
+            })
+            .collect()
+    }
+
+    fn memory(&self) -> MemoryInfo {
+        let (size, entries) = self.mem_regions();
+        let regions = entries
+            .into_iter()
+            .map(|(gpa, MemRegionEntry { size, type_ })| MemoryRegionInfo {
+                gpa,
+                size,
+                type_: region_type(type_),
+            })
+            .collect();
+        MemoryInfo { size, regions }
+    }
+
+    fn metrics(&self) -> Metrics {
+        Machine::metrics(self)
+    }
+
+    fn set_net_link(&self, name: &str, up: bool) -> Result<(), Self::Error> {
+        Machine::set_net_link(self, name, up)
+    }
+
+    fn set_balloon_size(&self, name: &str, size: u64) -> Result<(), Self::Error> {
+        Machine::set_balloon_size(self, name, size)
+    }
+
+    fn balloon(&self, name: &str) -> Result<BalloonInfo, Self::Error> {
+        let status = self.balloon_status(name)?;
+        let stats: Vec<_> = status.stats.iter().map(|(t, v)| (t.name(), *v)).collect();
+        Ok(BalloonInfo {
+            size: status.size,
+            actual: status.actual,
+            stats: lowercase_map(&stats),
+        })
+    }
+
+    fn resize_mem(&self, name: &str, size: u64) -> Result<(), Self::Error> {
+        Machine::resize_virtio_mem(self, name, size)
+    }
+
+    fn snapshot(&self, dir: &Path) -> Result<(), Self::Error> {
+        Machine::snapshot(self, dir)
+    }
+
+    fn migrate(&self, addr: &MigrationAddr) -> Result<(), Self::Error> {
+        Machine::migrate(self, addr)
+    }
+
+    fn add_device(&self, name: &str, param: HotplugParam) -> Result<(), Self::Error> {
+        match param {
+            HotplugParam::Blk(p) => self.hotplug_virtio_dev(name, p),
+            #[cfg(target_os = "linux")]
+            HotplugParam::Net(p) => self.hotplug_virtio_dev(name, p),
+            #[cfg(target_os = "linux")]
+            HotplugParam::Vfio(p) => self.hotplug_vfio_cdev(name.into(), p),
+        }
+    }
+
+    fn remove_device(&self, name: &str) -> Result<(), Self::Error> {
+        self.unplug_pci_dev(name)
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct SetLinkParams {
+    name: String,
+    up: bool,
+}
+
+#[derive(Debug, Deserialize)]
+struct SetBalloonParams {
+    name: String,
+    size: u64,
+}
+
+#[derive(Debug, Deserialize)]
+struct QueryBalloonParams {
+    name: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct ResizeMemParams {
+    name: String,
+    size: u64,
+}
+
+#[derive(Debug, Deserialize)]
+struct PowerOffParams {
+    timeout: u64,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum MetricsFormat {
+    Json,
+    Prometheus,
+}
+
+#[derive(Debug, Deserialize)]
+struct MetricsParams {
+    format: MetricsFormat,
+}
+
+#[derive(Debug, Deserialize)]
+struct SnapshotParams {
+    path: PathBuf,
+}
+
+#[derive(Debug, Deserialize)]
+struct MigrateParams {
+    to: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct DeviceAddParams {
+    name: String,
+    #[serde(rename = "type")]
+    type_: String,
+    args: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct DeviceDelParams {
+    name: String,
+}
+
+fn parse_hotplug_param(type_: &str, args: &str) -> Result<HotplugParam, RpcError> {
+    let objects = HashMap::new();
+    let param = match type_ {
+        "blk" => serde_aco::from_args(args, &objects).map(HotplugParam::Blk),
+        #[cfg(target_os = "linux")]
+        "net" => serde_aco::from_args(args, &objects).map(HotplugParam::Net),
+        #[cfg(target_os = "linux")]
+        "vfio" => serde_aco::from_args(args, &objects).map(HotplugParam::Vfio),
+        _ => {
+            let msg = format!("unknown device type {type_:?}");
+            return Err(rpc_error(INVALID_PARAMS, msg));
+        }
+    };
+    param.map_err(|e| rpc_error(INVALID_PARAMS, e.to_string()))
+}
+
+fn regions_json(regions: &[RegionMetrics]) -> Value {
+    let region = |r: &RegionMetrics| json!({ "start": r.start, "size": r.size, "exits": r.exits });
+    regions.iter().map(region).collect()
+}
+
+fn lowercase_map(values: &[(&str, u64)]) -> BTreeMap<String, u64> {
+    let pair = |(name, val): &(&str, u64)| (name.to_lowercase(), *val);
+    values.iter().map(pair).collect()
+}
+
+fn metrics_json(metrics: &Metrics) -> Value {
+    let vcpu = |v: &VcpuMetrics| json!({ "index": v.index, "exits": lowercase_map(&v.exits) });
+    let virtio = |d: &VirtioMetrics| {
+        json!({
+            "name": &*d.name,
+            "notifications": d.notifications,
+            "interrupts": d.interrupts,
+            "requests": d.requests,
+            "read_bytes": d.read_bytes,
+            "written_bytes": d.written_bytes,
+            "guest_stats": lowercase_map(&d.guest_stats),
+        })
+    };
+    json!({
+        "vcpus": metrics.vcpus.iter().map(vcpu).collect::<Value>(),
+        "io_regions": regions_json(&metrics.io_regions),
+        "mmio_regions": regions_json(&metrics.mmio_regions),
+        "virtio": metrics.virtio.iter().map(virtio).collect::<Value>(),
+    })
+}
+
+fn escape_label(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+/// Appends a metric family to `text`. Each sample is given as its labels
+/// and its value.
+fn write_family<I>(text: &mut String, name: &str, type_: &str, help: &str, samples: I)
+where
+    I: IntoIterator<Item = (String, u64)>,
+{
+    text.push_str(&format!("# HELP alioth_{name} {help}\n"));
+    text.push_str(&format!("# TYPE alioth_{name} {type_}\n"));
+    for (labels, val) in samples {
+        text.push_str(&format!("alioth_{name}{{{labels}}} {val}\n"));
+    }
+}
+
+/// Formats the metrics in the Prometheus text exposition format.
+fn metrics_prometheus(metrics: &Metrics) -> String {
+    let mut text = String::new();
+
+    let vcpu_exits = metrics.vcpus.iter().flat_map(|v| {
+        let exit =
+            |(reason, n): &(&str, u64)| (format!(r#"vcpu="{}",reason="{reason}""#, v.index), *n);
+        v.exits.iter().map(exit)
+    });
+    let help = "VM exits of a VCPU by reason.";
+    write_family(&mut text, "vcpu_exits_total", "counter", help, vcpu_exits);
+
+    let region = |r: &RegionMetrics| {
+        (
+            format!(r#"start="{:#x}",size="{:#x}""#, r.start, r.size),
+            r.exits,
+        )
+    };
+    let help = "VM exits caused by accesses to an emulated port range.";
+    let io_exits = metrics.io_regions.iter().map(region);
+    write_family(&mut text, "io_exits_total", "counter", help, io_exits);
+    let help = "VM exits caused by accesses to an emulated MMIO region.";
+    let mmio_exits = metrics.mmio_regions.iter().map(region);
+    write_family(&mut text, "mmio_exits_total", "counter", help, mmio_exits);
+
+    let device = |d: &VirtioMetrics| format!(r#"device="{}""#, escape_label(&d.name));
+    let samples = |get: fn(&VirtioMetrics) -> u64| {
+        let sample = move |d: &VirtioMetrics| (device(d), get(d));
+        metrics.virtio.iter().map(sample)
+    };
+    let help = "Queue notifications from the guest.";
+    let notifications = samples(|d| d.notifications);
+    write_family(
+        &mut text,
+        "virtio_notifications_total",
+        "counter",
+        help,
+        notifications,
+    );
+    let help = "Queue interrupts sent to the guest.";
+    let interrupts = samples(|d| d.interrupts);
+    write_family(
+        &mut text,
+        "virtio_interrupts_total",
+        "counter",
+        help,
+        interrupts,
+    );
+    let help = "Requests completed by the device.";
+    let requests = samples(|d| d.requests);
+    write_family(
+        &mut text,
+        "virtio_requests_total",
+        "counter",
+        help,
+        requests,
+    );
+    let help = "Bytes the device read from guest buffers.";
+    let read_bytes = samples(|d| d.read_bytes);
+    write_family(
+        &mut text,
+        "virtio_read_bytes_total",
+        "counter",
+        help,
+        read_bytes,
+    );
+    let help = "Bytes the device wrote to guest buffers.";
+    let written_bytes = samples(|d| d.written_bytes);
+    write_family(
+        &mut text,
+        "virtio_written_bytes_total",
+        "counter",
+        help,
+        written_bytes,
+    );
+
+    let guest_stats = metrics.virtio.iter().flat_map(|d| {
+        let stat = move |(name, val): &(&str, u64)| {
+            let labels = format!(r#"{},name="{}""#, device(d), name.to_lowercase());
+            (labels, *val)
+        };
+        d.guest_stats.iter().map(stat)
+    });
+    let help = "Statistics reported by the guest driver.";
+    write_family(&mut text, "virtio_guest_stat", "gauge", help, guest_stats);
+
+    text
+}
+
+#[derive(Debug, Serialize)]
+struct RpcError {
+    code: i32,
+    message: String,
+}
+
+fn rpc_error(code: i32, message: impl Into<String>) -> RpcError {
+    RpcError {
+        code,
+        message: message.into(),
+    }
+}
+
+fn vm_error<E: std::error::Error>(e: E) -> RpcError {
+    log::error!("API: {e:?}");
+    rpc_error(SERVER_ERROR, e.to_string())
+}
+
+fn call<V: VmControl + ?Sized>(vm: &V, method: &str, params: Value) -> Result<Value, RpcError> {
+    let result = match method {
+        "status" => json!({ "state": state_name(vm.state()) }),
+        "pause" => vm.pause().map(|_| Value::Null).map_err(vm_error)?,
+        "resume" => vm.resume().map(|_| Value::Null).map_err(vm_error)?,
+        "shutdown" => vm.shutdown().map(|_| Value::Null).map_err(vm_error)?,
+        "power-off" => {
+            let p: Option<PowerOffParams> = serde_json::from_value(params)
+                .map_err(|e| rpc_error(INVALID_PARAMS, e.to_string()))?;
+            let timeout = p.map_or(DEFAULT_POWER_OFF_TIMEOUT, |p| p.timeout);
+            vm.power_off(Duration::from_secs(timeout))
+                .map_err(vm_error)?;
+            Value::Null
+        }
+        "reset" => vm.reboot().map(|_| Value::Null).map_err(vm_error)?,
+        "query-devices" => json!(vm.devices()),
+        "query-memory" => json!(vm.memory()),
+        "query-metrics" => {
+            let p: Option<MetricsParams> = serde_json::from_value(params)
+                .map_err(|e| rpc_error(INVALID_PARAMS, e.to_string()))?;
+            let metrics = vm.metrics();
+            match p.map_or(MetricsFormat::Json, |p| p.format) {
+                MetricsFormat::Json => metrics_json(&metrics),
+                MetricsFormat::Prometheus => Value::String(metrics_prometheus(&metrics)),
+            }
+        }
+        "set-link" => {
+            let p: SetLinkParams = serde_json::from_value(params)
+                .map_err(|e| rpc_error(INVALID_PARAMS, e.to_string()))?;
+            vm.set_net_link(&p.name, p.up).map_err(vm_error)?;
+            Value::Null
+        }
+        "set-balloon" => {
+            let p: SetBalloonParams = serde_json::from_value(params)
+                .map_err(|e| rpc_error(INVALID_PARAMS, e.to_string()))?;
+            vm.set_balloon_size(&p.name, p.size).map_err(vm_error)?;
+            Value::Null
+        }
+        "query-balloon" => {
+            let p: QueryBalloonParams = serde_json::from_value(params)
+                .map_err(|e| rpc_error(INVALID_PARAMS, e.to_string()))?;
+            json!(vm.balloon(&p.name).map_err(vm_error)?)
+        }
+        "resize-mem" => {
+            let p: ResizeMemParams = serde_json::from_value(params)
+                .map_err(|e| rpc_error(INVALID_PARAMS, e.to_string()))?;
+            vm.resize_mem(&p.name, p.size).map_err(vm_error)?;
+            Value::Null
+        }
+        "snapshot" => {
+            let p: SnapshotParams = serde_json::from_value(params)
+                .map_err(|e| rpc_error(INVALID_PARAMS, e.to_string()))?;
+            vm.snapshot(&p.path).map_err(vm_error)?;
+            Value::Null
+        }
+        "migrate" => {
+            let p: MigrateParams = serde_json::from_value(params)
+                .map_err(|e| rpc_error(INVALID_PARAMS, e.to_string()))?;
+            let addr: MigrationAddr = serde_aco::from_args(&p.to, &HashMap::new())
+                .map_err(|e| rpc_error(INVALID_PARAMS, e.to_string()))?;
+            vm.migrate(&addr).map_err(vm_error)?;
+            Value::Null
+        }
+        "device-add" => {
+            let p: DeviceAddParams = serde_json::from_value(params)
+                .map_err(|e| rpc_error(INVALID_PARAMS, e.to_string()))?;
+            let param = parse_hotplug_param(&p.type_, &p.args)?;
+            vm.add_device(&p.name, param).map_err(vm_error)?;
+            Value::Null
+        }
+        "device-del" => {
+            let p: DeviceDelParams = serde_json::from_value(params)
+                .map_err(|e| rpc_error(INVALID_PARAMS, e.to_string()))?;
+            vm.remove_device(&p.name).map_err(vm_error)?;
+            Value::Null
+        }
+        _ => {
+            return Err(rpc_error(
+                METHOD_NOT_FOUND,
+                format!("unknown method {method:?}"),
+            ));
+        }
+    };
+    Ok(result)
+}
+
+/// Handles one line of request and returns the response.
+pub fn handle_request<V: VmControl + ?Sized>(vm: &V, line: &str) -> Value {
+    let request: Value = match serde_json::from_str(line) {
+        Ok(request) => request,
+        Err(e) => return response(Value::Null, Err(rpc_error(PARSE_ERROR, e.to_string()))),
+    };
+    let id = request.get("id").cloned().unwrap_or(Value::Null);
+    let (Some("2.0"), Some(method)) = (
+        request.get("jsonrpc").and_then(Value::as_str),
+        request.get("method").and_then(Value::as_str),
+    ) else {
+        return response(id, Err(rpc_error(INVALID_REQUEST, "invalid request")));
+    };
+    let params = request.get("params").cloned().unwrap_or(Value::Null);
+    response(id, call(vm, method, params))
+}
+
+fn response(id: Value, result: Result<Value, RpcError>) -> Value {
+    match result {
+        Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+        Err(error) => json!({ "jsonrpc": "2.0", "id": id, "error": error }),
+    }
+}
+
+fn serve_client<V: VmControl + ?Sized>(vm: &V, conn: UnixStream) -> std::io::Result<()> {
+    let mut writer = conn.try_clone()?;
+    for line in BufReader::new(conn).lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let resp = handle_request(vm, &line);
+        writeln!(writer, "{resp}")?;
+    }
+    Ok(())
+}
+
+/// Serves the API on a Unix domain socket until the process exits.
+#[derive(Debug)]
+pub struct ApiServer {
+    path: Box<Path>,
+}
+
+impl ApiServer {
+    pub fn new<V: VmControl>(path: Box<Path>, vm: Arc<V>) -> std::io::Result<Self> {
+        let listener = UnixListener::bind(&path)?;
+        thread::Builder::new()
+            .name("api".to_owned())
+            .spawn(move || {
+                for conn in listener.incoming() {
+                    let conn = match conn {
+                        Ok(conn) => conn,
+                        Err(e) => {
+                            log::error!("API: failed to accept a client: {e}");
+                            continue;
+                        }
+                    };
+                    let vm = vm.clone();
+                    let spawned =
+                        thread::Builder::new()
+                            .name("api-client".to_owned())
+                            .spawn(move || {
+                                if let Err(e) = serve_client(&*vm, conn) {
+                                    log::error!("API: {e}");
+                                }
+                            });
+                    if let Err(e) = spawned {
+                        log::error!("API: failed to serve a client: {e}");
+                    }
+                }
+            })?;
+        Ok(ApiServer { path })
+    }
+}
+
+impl Drop for ApiServer {
+    fn drop(&mut self) {
+        if let Err(e) = fs::remove_file(&self.path) {
+            log::error!("API: failed to remove {}: {e}", self.path.display());
+        }
+    }
+}
+
+#[cfg(test)]
+#[path = "api_test.rs"]
+mod tests;
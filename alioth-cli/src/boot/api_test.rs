@@ -0,0 +1,454 @@
+// Copyright 2026 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::BTreeMap;
+use std::io::{BufRead, BufReader, Error, Write};
+use std::os::unix::net::UnixStream;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use alioth::board::{BoardState, MigrationAddr, MigrationTcpParam};
+use alioth::metrics::{Metrics, RegionMetrics, VcpuMetrics, VirtioMetrics};
+use alioth::virtio::dev::blk::BlkFileParam;
+use alioth::virtio::worker::WorkerApi;
+use pretty_assertions::assert_eq;
+use rstest::rstest;
+use serde_json::{Value, json};
+use tempfile::TempDir;
+
+use crate::boot::api::{
+    ApiServer, BalloonInfo, DeviceInfo, HotplugParam, MemoryInfo, MemoryRegionInfo, VmControl,
+    handle_request,
+};
+
+#[derive(Debug)]
+struct FakeVm {
+    state: Mutex<BoardState>,
+    links: Mutex<Vec<(String, bool)>>,
+    mem_sizes: Mutex<Vec<(String, u64)>>,
+    balloon_sizes: Mutex<Vec<(String, u64)>>,
+    snapshots: Mutex<Vec<PathBuf>>,
+    migrations: Mutex<Vec<MigrationAddr>>,
+    devices: Mutex<Vec<(String, HotplugParam)>>,
+    power_offs: Mutex<Vec<Duration>>,
+}
+
+impl FakeVm {
+    fn new() -> Self {
+        FakeVm {
+            state: Mutex::new(BoardState::Running),
+            links: Mutex::new(vec![]),
+            mem_sizes: Mutex::new(vec![]),
+            balloon_sizes: Mutex::new(vec![]),
+            snapshots: Mutex::new(vec![]),
+            migrations: Mutex::new(vec![]),
+            devices: Mutex::new(vec![]),
+            power_offs: Mutex::new(vec![]),
+        }
+    }
+
+    fn change(&self, from: BoardState, to: BoardState) -> Result<(), Error> {
+        let mut state = self.state.lock().unwrap();
+        if *state != from {
+            return Err(Error::other(format!("not {from:?}")));
+        }
+        *state = to;
+        Ok(())
+    }
+}
+
+impl VmControl for FakeVm {
+    type Error = Error;
+
+    fn state(&self) -> BoardState {
+        *self.state.lock().unwrap()
+    }
+
+    fn pause(&self) -> Result<(), Error> {
+        self.change(BoardState::Running, BoardState::Paused)
+    }
+
+    fn resume(&self) -> Result<(), Error> {
+        self.change(BoardState::Paused, BoardState::Running)
+    }
+
+    fn shutdown(&self) -> Result<(), Error> {
+        *self.state.lock().unwrap() = BoardState::Shutdown;
+        Ok(())
+    }
+
+    fn power_off(&self, timeout: Duration) -> Result<(), Error> {
+        if self.state() != BoardState::Running {
+            return Err(Error::other("not Running"));
+        }
+        self.power_offs.lock().unwrap().push(timeout);
+        Ok(())
+    }
+
+    fn reboot(&self) -> Result<(), Error> {
+        self.change(BoardState::Running, BoardState::RebootPending)
+    }
+
+    fn devices(&self) -> Vec<DeviceInfo> {
+        vec![DeviceInfo {
+            bdf: "00:01.0".to_owned(),
+            name: "virtio-net-0".to_owned(),
+        }]
+    }
+
+    fn memory(&self) -> MemoryInfo {
+        MemoryInfo {
+            size: 1 << 30,
+            regions: vec![MemoryRegionInfo {
+                gpa: 0,
+                size: 1 << 30,
+                type_: "ram",
+            }],
+        }
+    }
+
+    fn metrics(&self) -> Metrics {
+        Metrics {
+            vcpus: vec![VcpuMetrics {
+                index: 0,
+                exits: vec![("io", 3), ("mmio", 5)],
+            }],
+            io_regions: vec![RegionMetrics {
+                start: 0x3f8,
+                size: 0x8,
+                exits: 3,
+            }],
+            mmio_regions: vec![],
+            virtio: vec![VirtioMetrics {
+                name: "balloon".into(),
+                notifications: 2,
+                interrupts: 1,
+                requests: 4,
+                read_bytes: 40,
+                written_bytes: 0,
+                guest_stats: vec![("MEMFREE", 1 << 20)],
+            }],
+        }
+    }
+
+    fn set_net_link(&self, name: &str, up: bool) -> Result<(), Error> {
+        self.links.lock().unwrap().push((name.to_owned(), up));
+        Ok(())
+    }
+
+    fn set_balloon_size(&self, name: &str, size: u64) -> Result<(), Error> {
+        self.balloon_sizes
+            .lock()
+            .unwrap()
+            .push((name.to_owned(), size));
+        Ok(())
+    }
+
+    fn balloon(&self, name: &str) -> Result<BalloonInfo, Error> {
+        if name != "balloon" {
+            return Err(Error::other("not a balloon"));
+        }
+        Ok(BalloonInfo {
+            size: 1 << 28,
+            actual: 1 << 27,
+            stats: BTreeMap::from([("memfree".to_owned(), 1 << 20)]),
+        })
+    }
+
+    fn resize_mem(&self, name: &str, size: u64) -> Result<(), Error> {
+        self.mem_sizes.lock().unwrap().push((name.to_owned(), size));
+        Ok(())
+    }
+
+    fn snapshot(&self, dir: &Path) -> Result<(), Error> {
+        if self.state() != BoardState::Paused {
+            return Err(Error::other("not Paused"));
+        }
+        self.snapshots.lock().unwrap().push(dir.to_owned());
+        Ok(())
+    }
+
+    fn migrate(&self, addr: &MigrationAddr) -> Result<(), Error> {
+        self.change(BoardState::Running, BoardState::Shutdown)?;
+        self.migrations.lock().unwrap().push(addr.clone());
+        Ok(())
+    }
+
+    fn add_device(&self, name: &str, param: HotplugParam) -> Result<(), Error> {
+        self.devices.lock().unwrap().push((name.to_owned(), param));
+        Ok(())
+    }
+
+    fn remove_device(&self, name: &str) -> Result<(), Error> {
+        let mut devices = self.devices.lock().unwrap();
+        let Some(index) = devices.iter().position(|(n, _)| n == name) else {
+            return Err(Error::other(format!("{name} does not exist")));
+        };
+        devices.remove(index);
+        Ok(())
+    }
+}
+
+#[rstest]
+#[case(r#"{"jsonrpc":"2.0","id":1,"method":"status"}"#, json!({"state": "running"}))]
+#[case(
+    r#"{"jsonrpc":"2.0","id":1,"method":"query-devices"}"#,
+    json!([{"bdf": "00:01.0", "name": "virtio-net-0"}])
+)]
+#[case(
+    r#"{"jsonrpc":"2.0","id":1,"method":"query-memory"}"#,
+    json!({"size": 1 << 30, "regions": [{"gpa": 0, "size": 1 << 30, "type": "ram"}]})
+)]
+#[case(
+    r#"{"jsonrpc":"2.0","id":1,"method":"query-metrics"}"#,
+    json!({
+        "vcpus": [{"index": 0, "exits": {"io": 3, "mmio": 5}}],
+        "io_regions": [{"start": 0x3f8, "size": 8, "exits": 3}],
+        "mmio_regions": [],
+        "virtio": [{
+            "name": "balloon",
+            "notifications": 2,
+            "interrupts": 1,
+            "requests": 4,
+            "read_bytes": 40,
+            "written_bytes": 0,
+            "guest_stats": {"memfree": 1 << 20},
+        }],
+    })
+)]
+fn test_handle_query(#[case] request: &str, #[case] result: Value) {
+    let vm = FakeVm::new();
+    let resp = handle_request(&vm, request);
+    assert_eq!(resp, json!({"jsonrpc": "2.0", "id": 1, "result": result}));
+}
+
+#[rstest]
+#[case("{", Value::Null, -32700)]
+#[case(r#"{"id":2,"method":"status"}"#, json!(2), -32600)]
+#[case(r#"{"jsonrpc":"2.0","id":"a"}"#, json!("a"), -32600)]
+#[case(r#"{"jsonrpc":"2.0","id":3,"method":"hotplug"}"#, json!(3), -32601)]
+#[case(r#"{"jsonrpc":"2.0","id":4,"method":"set-link"}"#, json!(4), -32602)]
+#[case(r#"{"jsonrpc":"2.0","id":5,"method":"resume"}"#, json!(5), -32000)]
+#[case(r#"{"jsonrpc":"2.0","id":6,"method":"snapshot"}"#, json!(6), -32602)]
+#[case(
+    r#"{"jsonrpc":"2.0","id":7,"method":"snapshot","params":{"path":"/tmp/s"}}"#,
+    json!(7),
+    -32000
+)]
+#[case(
+    r#"{"jsonrpc":"2.0","id":8,"method":"migrate","params":{"to":"ftp,addr=a"}}"#,
+    json!(8),
+    -32602
+)]
+#[case(
+    r#"{"jsonrpc":"2.0","id":9,"method":"device-add","params":{"name":"d","type":"scsi","args":""}}"#,
+    json!(9),
+    -32602
+)]
+#[case(
+    r#"{"jsonrpc":"2.0","id":10,"method":"device-add","params":{"name":"d","type":"blk","args":"readonly=true"}}"#,
+    json!(10),
+    -32602
+)]
+#[case(
+    r#"{"jsonrpc":"2.0","id":11,"method":"device-del","params":{"name":"d"}}"#,
+    json!(11),
+    -32000
+)]
+#[case(
+    r#"{"jsonrpc":"2.0","id":12,"method":"power-off","params":{"timeout":"1s"}}"#,
+    json!(12),
+    -32602
+)]
+#[case(
+    r#"{"jsonrpc":"2.0","id":13,"method":"query-metrics","params":{"format":"xml"}}"#,
+    json!(13),
+    -32602
+)]
+#[case(
+    r#"{"jsonrpc":"2.0","id":15,"method":"set-balloon","params":{"name":"balloon","size":"1g"}}"#,
+    json!(15),
+    -32602
+)]
+#[case(
+    r#"{"jsonrpc":"2.0","id":16,"method":"query-balloon","params":{"name":"net0"}}"#,
+    json!(16),
+    -32000
+)]
+#[case(
+    r#"{"jsonrpc":"2.0","id":14,"method":"resize-mem","params":{"name":"virtio-mem"}}"#,
+    json!(14),
+    -32602
+)]
+fn test_handle_error(#[case] request: &str, #[case] id: Value, #[case] code: i32) {
+    let vm = FakeVm::new();
+    let resp = handle_request(&vm, request);
+    assert_eq!(resp["id"], id);
+    assert_eq!(resp["error"]["code"], code);
+    assert!(resp.get("result").is_none());
+}
+
+#[test]
+fn test_handle_state_change() {
+    let vm = FakeVm::new();
+    let call = |method: &str| {
+        let request = format!(r#"{{"jsonrpc":"2.0","id":0,"method":"{method}"}}"#);
+        handle_request(&vm, &request)
+    };
+    assert_eq!(call("pause")["result"], Value::Null);
+    assert_eq!(vm.state(), BoardState::Paused);
+    assert_eq!(call("status")["result"]["state"], "paused");
+    let resp = handle_request(
+        &vm,
+        r#"{"jsonrpc":"2.0","id":0,"method":"snapshot","params":{"path":"/tmp/s"}}"#,
+    );
+    assert_eq!(resp["result"], Value::Null);
+    assert_eq!(*vm.snapshots.lock().unwrap(), [PathBuf::from("/tmp/s")]);
+    assert_eq!(call("resume")["result"], Value::Null);
+    assert_eq!(call("reset")["result"], Value::Null);
+    assert_eq!(call("status")["result"]["state"], "reboot-pending");
+    assert_eq!(call("shutdown")["result"], Value::Null);
+    assert_eq!(vm.state(), BoardState::Shutdown);
+}
+
+#[test]
+fn test_handle_power_off() {
+    let vm = FakeVm::new();
+    let request = r#"{"jsonrpc":"2.0","id":0,"method":"power-off"}"#;
+    assert_eq!(handle_request(&vm, request)["result"], Value::Null);
+    let request = r#"{"jsonrpc":"2.0","id":1,"method":"power-off","params":{"timeout":5}}"#;
+    assert_eq!(handle_request(&vm, request)["result"], Value::Null);
+    assert_eq!(
+        *vm.power_offs.lock().unwrap(),
+        [Duration::from_secs(30), Duration::from_secs(5)]
+    );
+}
+
+#[test]
+fn test_handle_metrics_prometheus() {
+    let vm = FakeVm::new();
+    let request =
+        r#"{"jsonrpc":"2.0","id":0,"method":"query-metrics","params":{"format":"prometheus"}}"#;
+    let resp = handle_request(&vm, request);
+    let text = resp["result"].as_str().unwrap();
+    let samples: Vec<_> = text.lines().filter(|l| !l.starts_with('#')).collect();
+    assert_eq!(
+        samples,
+        [
+            r#"alioth_vcpu_exits_total{vcpu="0",reason="io"} 3"#,
+            r#"alioth_vcpu_exits_total{vcpu="0",reason="mmio"} 5"#,
+            r#"alioth_io_exits_total{start="0x3f8",size="0x8"} 3"#,
+            r#"alioth_virtio_notifications_total{device="balloon"} 2"#,
+            r#"alioth_virtio_interrupts_total{device="balloon"} 1"#,
+            r#"alioth_virtio_requests_total{device="balloon"} 4"#,
+            r#"alioth_virtio_read_bytes_total{device="balloon"} 40"#,
+            r#"alioth_virtio_written_bytes_total{device="balloon"} 0"#,
+            r#"alioth_virtio_guest_stat{device="balloon",name="memfree"} 1048576"#,
+        ]
+    );
+    assert!(text.contains("# TYPE alioth_mmio_exits_total counter\n"));
+    assert!(text.contains("# TYPE alioth_virtio_guest_stat gauge\n"));
+}
+
+#[test]
+fn test_handle_migrate() {
+    let vm = FakeVm::new();
+    let request =
+        r#"{"jsonrpc":"2.0","id":0,"method":"migrate","params":{"to":"tcp,addr=127.0.0.1:4444"}}"#;
+    assert_eq!(handle_request(&vm, request)["result"], Value::Null);
+    assert_eq!(vm.state(), BoardState::Shutdown);
+    let addr = MigrationAddr::Tcp(MigrationTcpParam {
+        addr: "127.0.0.1:4444".to_owned(),
+    });
+    assert_eq!(*vm.migrations.lock().unwrap(), [addr]);
+
+    let resp = handle_request(&vm, request);
+    assert_eq!(resp["error"]["code"], -32000);
+}
+
+#[test]
+fn test_handle_hotplug() {
+    let vm = FakeVm::new();
+    let request = r#"{"jsonrpc":"2.0","id":0,"method":"device-add","params":{"name":"scratch-0","type":"blk","args":"path=/tmp/disk.img,readonly=true"}}"#;
+    assert_eq!(handle_request(&vm, request)["result"], Value::Null);
+    let param = HotplugParam::Blk(BlkFileParam {
+        path: Path::new("/tmp/disk.img").into(),
+        readonly: true,
+        api: WorkerApi::Mio,
+    });
+    assert_eq!(
+        *vm.devices.lock().unwrap(),
+        [("scratch-0".to_owned(), param)]
+    );
+
+    let request = r#"{"jsonrpc":"2.0","id":1,"method":"device-del","params":{"name":"scratch-0"}}"#;
+    assert_eq!(handle_request(&vm, request)["result"], Value::Null);
+    assert!(vm.devices.lock().unwrap().is_empty());
+}
+
+#[test]
+fn test_api_server() {
+    let temp_dir = TempDir::new().unwrap();
+    let path = temp_dir.path().join("api.sock");
+    let vm = Arc::new(FakeVm::new());
+    let server = ApiServer::new(path.clone().into(), vm.clone()).unwrap();
+
+    let conn = UnixStream::connect(&path).unwrap();
+    let mut writer = conn.try_clone().unwrap();
+    let mut reader = BufReader::new(conn);
+    let mut call = |request: &str| {
+        writeln!(writer, "{request}").unwrap();
+        let mut line = String::new();
+        reader.read_line(&mut line).unwrap();
+        serde_json::from_str::<Value>(&line).unwrap()
+    };
+
+    let resp =
+        call(r#"{"jsonrpc":"2.0","id":7,"method":"set-link","params":{"name":"net0","up":false}}"#);
+    assert_eq!(resp, json!({"jsonrpc": "2.0", "id": 7, "result": null}));
+    assert_eq!(*vm.links.lock().unwrap(), [("net0".to_owned(), false)]);
+
+    let resp = call(
+        r#"{"jsonrpc":"2.0","id":9,"method":"resize-mem","params":{"name":"virtio-mem","size":1073741824}}"#,
+    );
+    assert_eq!(resp, json!({"jsonrpc": "2.0", "id": 9, "result": null}));
+    let resp = call(
+        r#"{"jsonrpc":"2.0","id":10,"method":"set-balloon","params":{"name":"balloon","size":268435456}}"#,
+    );
+    assert_eq!(resp, json!({"jsonrpc": "2.0", "id": 10, "result": null}));
+    assert_eq!(
+        *vm.balloon_sizes.lock().unwrap(),
+        [("balloon".to_owned(), 1 << 28)]
+    );
+
+    let resp =
+        call(r#"{"jsonrpc":"2.0","id":11,"method":"query-balloon","params":{"name":"balloon"}}"#);
+    assert_eq!(
+        resp["result"],
+        json!({"size": 1 << 28, "actual": 1 << 27, "stats": {"memfree": 1 << 20}})
+    );
+
+    assert_eq!(
+        *vm.mem_sizes.lock().unwrap(),
+        [("virtio-mem".to_owned(), 1 << 30)]
+    );
+
+    let resp = call(r#"{"jsonrpc":"2.0","id":8,"method":"pause"}"#);
+    assert_eq!(resp["result"], Value::Null);
+    assert_eq!(vm.state(), BoardState::Paused);
+
+    drop(server);
+    assert!(!path.exists());
+}
@@ -12,17 +12,28 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+mod api;
 mod config;
 
 use std::collections::HashMap;
 use std::ffi::CString;
+use std::fs;
+use std::io;
 use std::mem;
 use std::path::{Path, PathBuf};
+use std::ptr::null_mut;
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
 
-use alioth::board::{BoardConfig, CpuConfig};
+use alioth::board::{CpuConfig, MigrationAddr, NumaNodeConfig};
+use alioth::device::console::ConsoleParam;
 #[cfg(target_arch = "x86_64")]
 use alioth::device::fw_cfg::FwCfgItemParam;
+#[cfg(target_arch = "x86_64")]
+use alioth::device::serial::{ComPort, SerialParam};
 use alioth::errors::{DebugTrace, trace_error};
+use alioth::gdb::GdbAddr;
 #[cfg(target_os = "macos")]
 use alioth::hv::Hvf;
 #[cfg(target_os = "linux")]
@@ -31,22 +42,30 @@ use alioth::hv::{Coco, HvConfig, Hypervisor};
 use alioth::loader::{Executable, Payload};
 use alioth::mem::{MemBackend, MemConfig};
 #[cfg(target_os = "linux")]
+use alioth::utils::thread::ThreadConfig;
+#[cfg(target_os = "linux")]
 use alioth::vfio::{CdevParam, ContainerParam, GroupParam, IoasParam};
 #[cfg(target_os = "linux")]
 use alioth::virtio::DeviceId;
 use alioth::virtio::dev::balloon::BalloonParam;
 use alioth::virtio::dev::blk::BlkFileParam;
+use alioth::virtio::dev::console::{ConsolePortParam, VirtioConsoleParam};
 use alioth::virtio::dev::entropy::EntropyParam;
+use alioth::virtio::dev::iommu::VirtioIommuParam;
+use alioth::virtio::dev::mem::VirtioMemParam;
+use alioth::virtio::dev::pmem::PmemParam;
 #[cfg(target_os = "linux")]
 use alioth::virtio::vu::frontend::VuFrontendParam;
 use alioth::virtio::worker::WorkerApi;
 use alioth::vm::Machine;
 use clap::Args;
+use serde::de::DeserializeOwned;
 use serde_aco::help_text;
 use snafu::{ResultExt, Snafu};
 
 use crate::objects::{DOC_OBJECTS, parse_objects};
 
+use self::api::{ApiServer, DEFAULT_POWER_OFF_TIMEOUT};
 use self::config::{BlkParam, Config, FsParam, NetParam, VsockParam};
 
 #[trace_error]
@@ -58,6 +77,21 @@ pub enum Error {
         arg: String,
         error: serde_aco::Error,
     },
+    #[snafu(display("Failed to read config file {path:?}"))]
+    ReadConfig {
+        path: Box<Path>,
+        error: std::io::Error,
+    },
+    #[snafu(display("Failed to parse TOML config file {path:?}"))]
+    ParseToml {
+        path: Box<Path>,
+        error: toml::de::Error,
+    },
+    #[snafu(display("Failed to parse JSON config file {path:?}"))]
+    ParseJson {
+        path: Box<Path>,
+        error: serde_json::Error,
+    },
     #[snafu(display("Failed to parse objects"), context(false))]
     ParseObjects { source: crate::objects::Error },
     #[cfg(target_os = "linux")]
@@ -69,11 +103,23 @@ pub enum Error {
     BootVm { source: alioth::vm::Error },
     #[snafu(display("VM did not shutdown peacefully"))]
     WaitVm { source: alioth::vm::Error },
+    #[snafu(display("Failed to create API socket {path:?}"))]
+    ApiSocket {
+        path: Box<Path>,
+        error: std::io::Error,
+    },
+    #[snafu(display("Failed to set up the SIGTERM handler"))]
+    Sigterm { error: std::io::Error },
 }
 
 #[derive(Args, Debug, Clone, Default)]
 #[command(arg_required_else_help = true, alias("run"))]
 pub struct BootArgs {
+    /// Path to a VM config file in TOML, or JSON if it ends with `.json`.
+    /// Values given by other flags override those in the file.
+    #[arg(long, value_name = "PATH")]
+    config: Option<Box<Path>>,
+
     #[arg(long, help(
         help_text::<HvConfig>("Specify the Hypervisor to run on.")
     ), value_name = "HV")]
@@ -100,24 +146,51 @@ pub struct BootArgs {
     #[arg(short, long, value_name = "PATH")]
     initramfs: Option<Box<Path>>,
 
-    /// DEPRECATED: Use --cpu instead.
-    #[arg(long, default_value_t = 1)]
-    num_cpu: u16,
+    /// DEPRECATED: Use --cpu instead. [default: 1]
+    #[arg(long)]
+    num_cpu: Option<u16>,
 
     #[arg(short('p'), long, help(
         help_text::<CpuConfig>("Configure the VCPUs of the guest.")
     ))]
     cpu: Option<Box<str>>,
 
-    /// DEPRECATED: Use --memory instead.
-    #[arg(long, default_value = "1G")]
-    mem_size: String,
+    /// DEPRECATED: Use --memory instead. [default: 1G]
+    #[arg(long)]
+    mem_size: Option<String>,
 
     #[arg(short, long, help(
         help_text::<MemConfig>("Specify the memory of the guest.")
     ))]
     memory: Option<String>,
 
+    #[arg(long, help(
+        help_text::<NumaNodeConfig>("Add a NUMA node to the guest.")
+    ))]
+    numa: Vec<String>,
+
+    #[cfg(target_os = "linux")]
+    #[arg(long, help(
+        help_text::<ThreadConfig>("Configure the host threads of VirtIO device workers.")
+    ))]
+    worker: Option<String>,
+
+    #[arg(long, help(
+        help_text::<ConsoleParam>("Connect the guest serial console to a backend. [default: stdio]")
+    ))]
+    console: Option<String>,
+
+    #[arg(long, help(
+        help_text::<ConsolePortParam>("Add a port to the VirtIO console device.")
+    ), value_name = "PORT")]
+    virtio_console: Vec<String>,
+
+    #[cfg(target_arch = "x86_64")]
+    #[arg(long, help(
+        help_text::<SerialParam>("Add an extra 16550A UART, e.g. COM2.")
+    ), value_name = "UART")]
+    serial: Vec<String>,
+
     /// Add a pvpanic device.
     #[arg(long)]
     pvpanic: bool,
@@ -142,6 +215,11 @@ pub struct BootArgs {
     ))]
     blk: Vec<String>,
 
+    #[arg(long, help(
+        help_text::<PmemParam>("Add a VirtIO persistent memory device.")
+    ))]
+    pmem: Vec<String>,
+
     #[arg(long, help(
         help_text::<Coco>("Enable confidential compute supported by host platform.")
     ))]
@@ -181,6 +259,42 @@ pub struct BootArgs {
     #[arg(long, help(help_text::<BalloonParam>("Add a VirtIO balloon device.")))]
     balloon: Option<String>,
 
+    #[arg(long, help(help_text::<VirtioMemParam>(
+        "Add a VirtIO memory device for the memory hotplug region."
+    )))]
+    virtio_mem: Option<String>,
+
+    #[arg(long, help(help_text::<VirtioIommuParam>(
+        "Add a VirtIO IOMMU device. VirtIO and VFIO devices are placed behind it."
+    )))]
+    virtio_iommu: Option<String>,
+
+    /// Path to a Unix domain socket serving the JSON-RPC management API.
+    #[arg(long, value_name = "PATH")]
+    api_socket: Option<Box<Path>>,
+
+    /// Seconds to wait for the guest to power off after the first SIGTERM
+    /// presses the ACPI power button, before stopping the VM. A second
+    /// SIGTERM stops the VM right away. [default: 30]
+    #[arg(long, value_name = "SECONDS")]
+    power_off_timeout: Option<u64>,
+
+    /// Serve a GDB remote stub at a TCP port, `HOST:PORT` or a Unix domain
+    /// socket path. The VM halts before its first instruction until a
+    /// debugger attaches.
+    #[arg(long, value_name = "PORT|ADDR|PATH")]
+    gdb: Option<GdbAddr>,
+
+    /// Path to a directory with a snapshot to boot from. The VM must be
+    /// configured with the same CPUs, memory and devices as the snapshot.
+    #[arg(long, value_name = "PATH")]
+    restore: Option<Box<Path>>,
+
+    #[arg(long, conflicts_with = "restore", help(
+        help_text::<MigrationAddr>("Wait for a live migration from another alioth process. The VM must be configured the same as the source.")
+    ), value_name = "ADDR")]
+    incoming: Option<String>,
+
     #[arg(short, long("object"), help = DOC_OBJECTS, value_name = "OBJECT")]
     objects: Vec<String>,
 }
@@ -253,63 +367,130 @@ fn parse_cpu_arg(
     Ok(config)
 }
 
-fn parse_payload_arg(args: &mut BootArgs) -> Payload {
-    let mut payload = Payload {
-        firmware: args.firmware.take(),
-        initramfs: args.initramfs.take(),
-        cmdline: args.cmdline.take(),
-        ..Default::default()
-    };
-    payload.executable = args.kernel.take().map(Executable::Linux);
+fn parse_payload_arg(args: &mut BootArgs, payload: &mut Payload) {
+    if let Some(firmware) = args.firmware.take() {
+        payload.firmware = Some(firmware);
+    }
+    if let Some(initramfs) = args.initramfs.take() {
+        payload.initramfs = Some(initramfs);
+    }
+    if let Some(cmdline) = args.cmdline.take() {
+        payload.cmdline = Some(cmdline);
+    }
+    if let Some(kernel) = args.kernel.take() {
+        payload.executable = Some(Executable::Linux(kernel));
+        return;
+    }
     #[cfg(target_arch = "x86_64")]
-    if payload.executable.is_none() {
-        payload.executable = args.pvh.take().map(Executable::Pvh);
+    if let Some(pvh) = args.pvh.take() {
+        payload.executable = Some(Executable::Pvh(pvh));
     }
-    payload
+}
+
+fn load_config(path: Box<Path>) -> Result<Config, Error> {
+    let content = fs::read_to_string(&path).context(error::ReadConfig { path: &*path })?;
+    let is_json = path.extension().is_some_and(|ext| ext == "json");
+    if is_json {
+        serde_json::from_str(&content).context(error::ParseJson { path })
+    } else {
+        toml::from_str(&content).context(error::ParseToml { path })
+    }
+}
+
+/// Parses `args` into `list`, replacing the items from the config file.
+fn parse_list_arg<T>(
+    args: Vec<String>,
+    objects: &HashMap<&str, &str>,
+    list: &mut Vec<T>,
+) -> Result<(), Error>
+where
+    T: DeserializeOwned,
+{
+    if args.is_empty() {
+        return Ok(());
+    }
+    list.clear();
+    for arg in args {
+        let param = serde_aco::from_args(&arg, objects).context(error::ParseArg { arg })?;
+        list.push(param);
+    }
+    Ok(())
 }
 
 fn parse_args(mut args: BootArgs, objects: HashMap<&str, &str>) -> Result<Config, Error> {
-    let payload = parse_payload_arg(&mut args);
+    let from_file = args.config.is_some();
+    let mut config = match args.config.take() {
+        Some(path) => load_config(path)?,
+        None => Config::default(),
+    };
+
+    parse_payload_arg(&mut args, &mut config.payload);
 
-    let mut board_config = BoardConfig::default();
     if let Some(arg) = args.coco {
         let param = serde_aco::from_args(&arg, &objects).context(error::ParseArg { arg })?;
-        board_config.coco = Some(param);
-    };
-    board_config.mem = parse_mem_arg(args.memory, args.mem_size, &objects)?;
-    board_config.cpu = parse_cpu_arg(args.cpu, args.num_cpu, &objects)?;
-
-    let mut config = Config {
-        board: board_config,
-        pvpanic: args.pvpanic,
-        payload,
-        ..Default::default()
+        config.board.coco = Some(param);
     };
+    // The deprecated flags override the config file like the others, but
+    // their default values only apply when there is no config file.
+    if args.memory.is_some() || args.mem_size.is_some() || !from_file {
+        let mem_size = args.mem_size.unwrap_or_else(|| "1G".to_owned());
+        config.board.mem = parse_mem_arg(args.memory, mem_size, &objects)?;
+    }
+    if args.cpu.is_some() || args.num_cpu.is_some() || !from_file {
+        let num_cpu = args.num_cpu.unwrap_or(1);
+        config.board.cpu = parse_cpu_arg(args.cpu, num_cpu, &objects)?;
+    }
 
-    #[cfg(target_arch = "x86_64")]
-    for arg in args.fw_cfg {
+    parse_list_arg(args.numa, &objects, &mut config.board.numa)?;
+    #[cfg(target_os = "linux")]
+    if let Some(arg) = args.worker {
+        let param = serde_aco::from_args(&arg, &objects).context(error::ParseArg { arg })?;
+        config.board.worker = param;
+    }
+
+    if args.pvpanic {
+        config.pvpanic = true;
+    }
+
+    if let Some(arg) = args.console {
         let param = serde_aco::from_args(&arg, &objects).context(error::ParseArg { arg })?;
-        config.fw_cfg.push(param);
+        config.console = param;
     }
 
+    #[cfg(target_arch = "x86_64")]
+    parse_list_arg(args.serial, &objects, &mut config.serial)?;
+
+    if !args.virtio_console.is_empty() {
+        let mut ports = vec![];
+        parse_list_arg(args.virtio_console, &objects, &mut ports)?;
+        config.virtio_console = Some(VirtioConsoleParam { ports });
+    }
+
+    #[cfg(target_arch = "x86_64")]
+    parse_list_arg(args.fw_cfg, &objects, &mut config.fw_cfg)?;
+
     if args.entropy {
         config.entropy = Some(EntropyParam::default());
     }
 
+    if !args.net.is_empty() {
+        config.net.clear();
+    }
     for arg in args.net {
         let param = parse_net_arg(&arg, &objects).context(error::ParseArg { arg })?;
         config.net.push(param);
     }
 
+    if !args.blk.is_empty() {
+        config.blk.clear();
+    }
     for arg in args.blk {
         let param = parse_blk_arg(&arg, &objects);
         config.blk.push(param);
     }
 
-    for arg in args.fs {
-        let param = serde_aco::from_args(&arg, &objects).context(error::ParseArg { arg })?;
-        config.fs.push(param);
-    }
+    parse_list_arg(args.fs, &objects, &mut config.fs)?;
+    parse_list_arg(args.pmem, &objects, &mut config.pmem)?;
 
     if let Some(arg) = args.vsock {
         let param = serde_aco::from_args(&arg, &objects).context(error::ParseArg { arg })?;
@@ -321,26 +502,24 @@ fn parse_args(mut args: BootArgs, objects: HashMap<&str, &str>) -> Result<Config
         config.balloon = Some(param);
     }
 
-    #[cfg(target_os = "linux")]
-    for arg in args.vfio_ioas {
+    if let Some(arg) = args.virtio_mem {
         let param = serde_aco::from_args(&arg, &objects).context(error::ParseArg { arg })?;
-        config.vfio_ioas.push(param);
+        config.virtio_mem = Some(param);
     }
-    #[cfg(target_os = "linux")]
-    for arg in args.vfio_cdev {
+
+    if let Some(arg) = args.virtio_iommu {
         let param = serde_aco::from_args(&arg, &objects).context(error::ParseArg { arg })?;
-        config.vfio_cdev.push(param);
+        config.virtio_iommu = Some(param);
     }
+
     #[cfg(target_os = "linux")]
-    for arg in args.vfio_container {
-        let param = serde_aco::from_args(&arg, &objects).context(error::ParseArg { arg })?;
-        config.vfio_container.push(param);
-    }
+    parse_list_arg(args.vfio_ioas, &objects, &mut config.vfio_ioas)?;
     #[cfg(target_os = "linux")]
-    for arg in args.vfio_group {
-        let param = serde_aco::from_args(&arg, &objects).context(error::ParseArg { arg })?;
-        config.vfio_group.push(param);
-    }
+    parse_list_arg(args.vfio_cdev, &objects, &mut config.vfio_cdev)?;
+    #[cfg(target_os = "linux")]
+    parse_list_arg(args.vfio_container, &objects, &mut config.vfio_container)?;
+    #[cfg(target_os = "linux")]
+    parse_list_arg(args.vfio_group, &objects, &mut config.vfio_group)?;
 
     Ok(config)
 }
@@ -349,9 +528,18 @@ fn create<H: Hypervisor>(hypervisor: &H, config: Config) -> Result<Machine<H>, a
     let vm = Machine::new(hypervisor, config.board)?;
 
     #[cfg(target_arch = "x86_64")]
-    vm.add_com1()?;
+    let pty = vm.add_serial(ComPort::Com1, &config.console)?;
     #[cfg(target_arch = "aarch64")]
-    vm.add_pl011()?;
+    let pty = vm.add_pl011(&config.console)?;
+    if let Some(path) = pty {
+        eprintln!("Guest console is connected to {}", path.display());
+    }
+    #[cfg(target_arch = "x86_64")]
+    for param in &config.serial {
+        if let Some(path) = vm.add_serial(param.port, &param.backend)? {
+            eprintln!("Guest {:?} is connected to {}", param.port, path.display());
+        }
+    }
     #[cfg(target_arch = "aarch64")]
     vm.add_pl031();
 
@@ -359,6 +547,10 @@ fn create<H: Hypervisor>(hypervisor: &H, config: Config) -> Result<Machine<H>, a
         vm.add_pvpanic()?;
     }
 
+    if let Some(param) = config.virtio_iommu {
+        vm.add_virtio_iommu("virtio-iommu", param)?;
+    }
+
     #[cfg(target_arch = "x86_64")]
     if config.payload.firmware.is_some() {
         vm.add_cmos()?;
@@ -370,6 +562,15 @@ fn create<H: Hypervisor>(hypervisor: &H, config: Config) -> Result<Machine<H>, a
         vm.add_fw_cfg(config.fw_cfg.into_iter())?;
     };
 
+    if let Some(param) = config.virtio_console {
+        for (index, path) in vm.add_virtio_console("virtio-console", param)? {
+            eprintln!(
+                "Guest virtio-console port {index} is connected to {}",
+                path.display()
+            );
+        }
+    }
+
     if let Some(param) = config.entropy {
         vm.add_virtio_dev("virtio-entropy", param)?;
     }
@@ -405,6 +606,10 @@ fn create<H: Hypervisor>(hypervisor: &H, config: Config) -> Result<Machine<H>, a
         }?;
     }
 
+    for (index, param) in config.pmem.into_iter().enumerate() {
+        vm.add_virtio_dev(format!("virtio-pmem-{index}"), param)?;
+    }
+
     for (index, param) in config.fs.into_iter().enumerate() {
         match param {
             FsParam::Dir(p) => vm.add_virtio_dev(format!("virtio-fs-{index}"), p),
@@ -433,6 +638,10 @@ fn create<H: Hypervisor>(hypervisor: &H, config: Config) -> Result<Machine<H>, a
         vm.add_virtio_dev("virtio-balloon", param)?;
     }
 
+    if let Some(param) = config.virtio_mem {
+        vm.add_virtio_mem("virtio-mem", param)?;
+    }
+
     #[cfg(target_os = "linux")]
     for param in config.vfio_ioas.into_iter() {
         vm.add_vfio_ioas(param)?;
@@ -456,7 +665,54 @@ fn create<H: Hypervisor>(hypervisor: &H, config: Config) -> Result<Machine<H>, a
     Ok(vm)
 }
 
+/// Blocks SIGTERM in the calling thread and the threads it creates later,
+/// so that SIGTERM is only taken by `sigwait()` in `handle_sigterm()`.
+fn block_sigterm() -> io::Result<libc::sigset_t> {
+    let mut set: libc::sigset_t = unsafe { mem::zeroed() };
+    unsafe {
+        libc::sigemptyset(&mut set);
+        libc::sigaddset(&mut set, libc::SIGTERM);
+    }
+    match unsafe { libc::pthread_sigmask(libc::SIG_BLOCK, &set, null_mut()) } {
+        0 => Ok(set),
+        e => Err(io::Error::from_raw_os_error(e)),
+    }
+}
+
+/// Powers off the VM on the first SIGTERM, and stops it on the second.
+fn handle_sigterm<H>(set: libc::sigset_t, vm: Arc<Machine<H>>, timeout: Duration) -> io::Result<()>
+where
+    H: Hypervisor + 'static,
+    Machine<H>: Send + Sync,
+{
+    thread::Builder::new()
+        .name("sigterm".to_owned())
+        .spawn(move || {
+            for count in 0.. {
+                let mut signal = 0;
+                let ret = unsafe { libc::sigwait(&set, &mut signal) };
+                if ret != 0 {
+                    log::error!("sigwait(): {}", io::Error::from_raw_os_error(ret));
+                    return;
+                }
+                let ret = if count == 0 {
+                    log::info!("Received SIGTERM, powering off the VM");
+                    vm.power_off(timeout)
+                } else {
+                    log::info!("Received SIGTERM again, stopping the VM");
+                    vm.shutdown()
+                };
+                if let Err(e) = ret {
+                    log::error!("Failed to stop the VM: {e:?}");
+                }
+            }
+        })?;
+    Ok(())
+}
+
 pub fn boot(mut args: BootArgs) -> Result<(), Error> {
+    let sigterm = block_sigterm().context(error::Sigterm)?;
+
     let object_args = mem::take(&mut args.objects);
     let objects = parse_objects(&object_args)?;
 
@@ -472,11 +728,48 @@ pub fn boot(mut args: BootArgs) -> Result<(), Error> {
         HvConfig::Hvf => Hvf {},
     };
 
+    let api_socket = args.api_socket.take();
+    let power_off_timeout = args
+        .power_off_timeout
+        .take()
+        .unwrap_or(DEFAULT_POWER_OFF_TIMEOUT);
+    let gdb = args.gdb.take();
+    let restore = args.restore.take();
+    let incoming = match args.incoming.take() {
+        Some(arg) => {
+            let addr: MigrationAddr =
+                serde_aco::from_args(&arg, &objects).context(error::ParseArg { arg })?;
+            Some(addr)
+        }
+        None => None,
+    };
     let config = parse_args(args, objects)?;
 
-    let vm = create(&hypervisor, config).context(error::CreateVm)?;
+    let vm = Arc::new(create(&hypervisor, config).context(error::CreateVm)?);
+
+    let _api_server = match api_socket {
+        Some(path) => {
+            let server = ApiServer::new(path.clone(), vm.clone());
+            Some(server.context(error::ApiSocket { path })?)
+        }
+        None => None,
+    };
 
-    vm.boot().context(error::BootVm)?;
+    let timeout = Duration::from_secs(power_off_timeout);
+    handle_sigterm(sigterm, vm.clone(), timeout).context(error::Sigterm)?;
+
+    let _gdb_server = match gdb {
+        Some(addr) => Some(vm.add_gdb_stub(&addr).context(error::CreateVm)?),
+        None => None,
+    };
+
+    if let Some(dir) = restore {
+        vm.restore(&dir).context(error::BootVm)?;
+    } else if let Some(addr) = incoming {
+        vm.incoming(&addr).context(error::BootVm)?;
+    } else {
+        vm.boot().context(error::BootVm)?;
+    }
     vm.wait().context(error::WaitVm)?;
     Ok(())
 }
@@ -13,30 +13,45 @@
 // limitations under the License.
 
 use std::collections::HashMap;
+use std::fs;
 use std::path::Path;
 
-use alioth::board::{BoardConfig, CpuConfig, CpuTopology};
+#[cfg(target_arch = "x86_64")]
+use alioth::arch::cpuid::CpuModel;
+use alioth::board::{BoardConfig, CpuConfig, CpuTopology, NumaNodeConfig};
+use alioth::device::console::{ConsoleParam, ConsolePathParam};
 #[cfg(target_arch = "x86_64")]
 use alioth::device::fw_cfg::{FwCfgContentParam, FwCfgItemParam};
 use alioth::device::net::MacAddr;
+#[cfg(target_arch = "x86_64")]
+use alioth::device::serial::{ComPort, SerialParam};
 use alioth::loader::{Executable, Payload};
-use alioth::mem::{MemBackend, MemConfig};
+#[cfg(target_os = "linux")]
+use alioth::mem::{HugePageSize, HugetlbParam};
+use alioth::mem::{MemBackend, MemConfig, MemFileParam};
+#[cfg(target_os = "linux")]
+use alioth::utils::thread::{SchedPolicy, ThreadConfig};
 #[cfg(target_os = "linux")]
 use alioth::vfio::{CdevParam, ContainerParam, GroupParam, IoasParam};
 use alioth::virtio::dev::balloon::BalloonParam;
 use alioth::virtio::dev::blk::BlkFileParam;
+use alioth::virtio::dev::console::{ConsolePortParam, VirtioConsoleParam};
 use alioth::virtio::dev::entropy::EntropyParam;
 use alioth::virtio::dev::fs::shared_dir::SharedDirParam;
 #[cfg(target_os = "linux")]
 use alioth::virtio::dev::fs::vu::VuFsParam;
+use alioth::virtio::dev::iommu::VirtioIommuParam;
+use alioth::virtio::dev::mem::VirtioMemParam;
 #[cfg(target_os = "linux")]
 use alioth::virtio::dev::net::tap::NetTapParam;
 #[cfg(target_os = "macos")]
 use alioth::virtio::dev::net::vmnet::NetVmnetParam;
+use alioth::virtio::dev::pmem::PmemParam;
 use alioth::virtio::dev::vsock::UdsVsockParam;
 use alioth::virtio::worker::WorkerApi;
 use pretty_assertions::assert_eq;
 use rstest::rstest;
+use tempfile::TempDir;
 
 use crate::boot::{
     BootArgs, parse_args, parse_blk_arg, parse_cpu_arg, parse_mem_arg, parse_net_arg,
@@ -53,7 +68,18 @@ fn test_parse_args() {
         cmdline: Some(c"console=ttyS0".into()),
         initramfs: Some(Path::new("initramfs.cpio").into()),
         cpu: Some("count=16,topology=id_topo".into()),
-        memory: Some("size=128G,backend=anon,shared=true".into()),
+        memory: Some("size=128G,backend=anon,shared=true,hotplug_size=64G".into()),
+        numa: vec![
+            "size=64G,cpus=id_node0_cpus".into(),
+            "size=64G,cpus=id_node1_cpus,distances=id_node1_dist".into(),
+        ],
+        console: Some("uds,path=console.sock".into()),
+        #[cfg(target_arch = "x86_64")]
+        serial: vec!["port=com2,backend=pty".into()],
+        virtio_console: vec![
+            "console=true,backend=null".into(),
+            "name=org.qemu.guest_agent.0,backend=id_ga".into(),
+        ],
         pvpanic: true,
         #[cfg(target_arch = "x86_64")]
         fw_cfg: vec![
@@ -79,8 +105,13 @@ fn test_parse_args() {
             #[cfg(target_os = "linux")]
             "vu,socket=fs.vsock,tag=vufs".into(),
         ],
+        pmem: vec!["path=rootfs.img,readonly=true".into()],
         vsock: Some("uds,cid=3,path=vsock_3.sock".into()),
-        balloon: Some("free_page_reporting=true".into()),
+        balloon: Some("free_page_reporting=true,size=1G".into()),
+        virtio_mem: Some("block_size=4M,requested_size=8G,node=1".into()),
+        virtio_iommu: Some("bypass=false".into()),
+        #[cfg(target_os = "linux")]
+        worker: Some("cpus=id_worker_cpus,sched_policy=rr,sched_priority=1".into()),
         #[cfg(target_os = "linux")]
         vfio_cdev: vec!["path=/dev/vfio/devices/vfio0,ioas=default".into()],
         #[cfg(target_os = "linux")]
@@ -93,6 +124,12 @@ fn test_parse_args() {
     };
     let objects = HashMap::from([
         ("id_topo", "smt=true,sockets=1,cores=8"),
+        ("id_ga", "uds,path=ga.sock"),
+        ("id_node0_cpus", "0,1,2,3,4,5,6,7"),
+        ("id_node1_cpus", "8,9,10,11,12,13,14,15"),
+        ("id_node1_dist", "21,10"),
+        #[cfg(target_os = "linux")]
+        ("id_worker_cpus", "4,5"),
         #[cfg(target_os = "linux")]
         ("id_gpus", "0000:06:0d.0,0000:06:0d.1"),
     ]);
@@ -106,15 +143,35 @@ fn test_parse_args() {
                     cores: 8,
                     sockets: 1,
                 },
+                ..Default::default()
             },
             mem: MemConfig {
                 size: 128 << 30,
                 backend: MemBackend::Anonymous,
                 shared: true,
-                #[cfg(target_os = "linux")]
-                transparent_hugepage: false,
+                hotplug_size: 64 << 30,
+                ..Default::default()
             },
             coco: None,
+            numa: vec![
+                NumaNodeConfig {
+                    size: 64 << 30,
+                    cpus: vec![0, 1, 2, 3, 4, 5, 6, 7],
+                    ..Default::default()
+                },
+                NumaNodeConfig {
+                    size: 64 << 30,
+                    cpus: vec![8, 9, 10, 11, 12, 13, 14, 15],
+                    distances: vec![21, 10],
+                    ..Default::default()
+                },
+            ],
+            #[cfg(target_os = "linux")]
+            worker: ThreadConfig {
+                cpus: vec![4, 5],
+                sched_policy: SchedPolicy::RoundRobin,
+                sched_priority: 1,
+            },
         },
         payload: Payload {
             executable: Some(Executable::Linux(Path::new("vmlinuz").into())),
@@ -169,14 +226,52 @@ fn test_parse_args() {
                 dax_window: 0,
             }),
         ],
+        pmem: vec![PmemParam {
+            path: Path::new("rootfs.img").into(),
+            readonly: true,
+        }],
         vsock: Some(VsockParam::Uds(UdsVsockParam {
             cid: 3,
             path: Path::new("vsock_3.sock").into(),
         })),
+        console: ConsoleParam::Uds(ConsolePathParam {
+            path: Path::new("console.sock").into(),
+        }),
+        #[cfg(target_arch = "x86_64")]
+        serial: vec![SerialParam {
+            port: ComPort::Com2,
+            backend: ConsoleParam::Pty,
+        }],
+        virtio_console: Some(VirtioConsoleParam {
+            ports: vec![
+                ConsolePortParam {
+                    name: None,
+                    console: true,
+                    backend: ConsoleParam::Null,
+                },
+                ConsolePortParam {
+                    name: Some("org.qemu.guest_agent.0".into()),
+                    console: false,
+                    backend: ConsoleParam::Uds(ConsolePathParam {
+                        path: Path::new("ga.sock").into(),
+                    }),
+                },
+            ],
+        }),
         entropy: Some(EntropyParam::default()),
         balloon: Some(BalloonParam {
             free_page_reporting: true,
+            size: 1 << 30,
+            deflate_on_oom: true,
+            #[cfg(target_os = "linux")]
+            stats_interval: 5,
+        }),
+        virtio_mem: Some(VirtioMemParam {
+            block_size: 4 << 20,
+            requested_size: 8 << 30,
+            node: Some(1),
         }),
+        virtio_iommu: Some(VirtioIommuParam { bypass: false }),
         pvpanic: true,
         #[cfg(target_arch = "x86_64")]
         fw_cfg: vec![
@@ -242,6 +337,7 @@ fn test_parse_net_arg(#[case] arg: &str, #[case] want: NetParam) {
     CpuConfig {
         count: 16,
         topology: CpuTopology { smt: false, cores: 8, sockets: 2 },
+        ..Default::default()
     }
 )]
 #[case(
@@ -250,9 +346,36 @@ fn test_parse_net_arg(#[case] arg: &str, #[case] want: NetParam) {
     HashMap::new(),
     CpuConfig {
         count: 16,
-        topology: CpuTopology::default(),
+        ..Default::default()
     }
 )]
+#[cfg_attr(target_os = "linux", case(
+    Some("count=2,affinity=id_affinity,sched_policy=fifo,sched_priority=10".into()),
+    0,
+    HashMap::from([
+        ("id_affinity", "id_cpus_0,id_cpus_1"),
+        ("id_cpus_0", "0,1"),
+        ("id_cpus_1", "2,3"),
+    ]),
+    CpuConfig {
+        count: 2,
+        affinity: vec![vec![0, 1], vec![2, 3]],
+        sched_policy: SchedPolicy::Fifo,
+        sched_priority: 10,
+        ..Default::default()
+    }
+))]
+#[cfg_attr(target_arch = "x86_64", case(
+    Some("count=1,model=x86-64-v3,features=id_features".into()),
+    0,
+    HashMap::from([("id_features", "-avx512f,+x2apic")]),
+    CpuConfig {
+        count: 1,
+        model: CpuModel::X86_64V3,
+        features: vec!["-avx512f".into(), "+x2apic".into()],
+        ..Default::default()
+    }
+))]
 fn test_parse_cpu_arg(
     #[case] arg: Option<Box<str>>,
     #[case] num_cpu: u16,
@@ -282,15 +405,52 @@ fn test_parse_cpu_arg(
         size: 32 << 30,
         backend: MemBackend::Memfd,
         shared: true,
-        transparent_hugepage: true
+        transparent_hugepage: true,
+        ..Default::default()
     }
 ))]
+#[cfg_attr(target_os = "linux", case(
+    Some("size=4g,backend=hugetlb,prealloc=true,mlock=true".into()),
+    "",
+    MemConfig {
+        size: 4 << 30,
+        backend: MemBackend::Hugetlb(HugetlbParam::default()),
+        prealloc: true,
+        mlock: true,
+        ..Default::default()
+    }
+))]
+#[cfg_attr(target_os = "linux", case(
+    Some("size=4g,backend=id_hugetlb_1g".into()),
+    "",
+    MemConfig {
+        size: 4 << 30,
+        backend: MemBackend::Hugetlb(HugetlbParam {
+            page_size: HugePageSize::Size1G,
+        }),
+        ..Default::default()
+    }
+))]
+#[case(
+    Some("size=4g,backend=id_file".into()),
+    "",
+    MemConfig {
+        size: 4 << 30,
+        backend: MemBackend::File(MemFileParam {
+            path: Path::new("/dev/shm/guest.mem").into(),
+        }),
+        ..Default::default()
+    }
+)]
 fn test_parse_mem_arg(
     #[case] arg: Option<String>,
     #[case] mem_size: &str,
     #[case] want: MemConfig,
 ) {
-    let objects = HashMap::new();
+    let objects = HashMap::from([
+        ("id_hugetlb_1g", "hugetlb,page_size=1g"),
+        ("id_file", "file,path=/dev/shm/guest.mem"),
+    ]);
     let config = parse_mem_arg(arg, mem_size.to_owned(), &objects).unwrap();
     assert_eq!(config, want);
 }
@@ -325,7 +485,9 @@ fn test_parse_mem_arg(
     }
 ))]
 fn test_parse_payload_arg(#[case] mut args: BootArgs, #[case] want: Payload) {
-    assert_eq!(parse_payload_arg(&mut args), want);
+    let mut payload = Payload::default();
+    parse_payload_arg(&mut args, &mut payload);
+    assert_eq!(payload, want);
 }
 
 #[rstest]
@@ -365,3 +527,148 @@ fn test_parse_blk_arg(#[case] arg: &str, #[case] want: BlkParam) {
     let objects = HashMap::new();
     assert_eq!(parse_blk_arg(arg, &objects), want);
 }
+
+const CONFIG_TOML: &str = r#"
+pvpanic = true
+console = "pty"
+
+[board.cpu]
+count = 4
+
+[board.mem]
+size = 2147483648
+
+[payload]
+executable = { Linux = "vmlinuz" }
+cmdline = "console=ttyS0"
+
+[[blk]]
+File = { path = "disk.img", readonly = true }
+"#;
+
+const CONFIG_JSON: &str = r#"{
+    "pvpanic": true,
+    "console": "pty",
+    "board": { "cpu": { "count": 4 }, "mem": { "size": 2147483648 } },
+    "payload": { "executable": { "Linux": "vmlinuz" }, "cmdline": "console=ttyS0" },
+    "blk": [{ "File": { "path": "disk.img", "readonly": true } }]
+}"#;
+
+fn config_from_file() -> Config {
+    Config {
+        board: BoardConfig {
+            cpu: CpuConfig {
+                count: 4,
+                ..Default::default()
+            },
+            mem: MemConfig {
+                size: 2 << 30,
+                ..Default::default()
+            },
+            ..Default::default()
+        },
+        payload: Payload {
+            executable: Some(Executable::Linux(Path::new("vmlinuz").into())),
+            cmdline: Some(c"console=ttyS0".into()),
+            ..Default::default()
+        },
+        console: ConsoleParam::Pty,
+        blk: vec![BlkParam::File(BlkFileParam {
+            path: Path::new("disk.img").into(),
+            readonly: true,
+            api: WorkerApi::Mio,
+        })],
+        pvpanic: true,
+        ..Default::default()
+    }
+}
+
+#[rstest]
+#[case("vm.toml", CONFIG_TOML)]
+#[case("vm.json", CONFIG_JSON)]
+fn test_parse_config_file(#[case] name: &str, #[case] content: &str) {
+    let temp_dir = TempDir::new().unwrap();
+    let path = temp_dir.path().join(name);
+    fs::write(&path, content).unwrap();
+
+    let args = BootArgs {
+        config: Some(path.into()),
+        ..Default::default()
+    };
+    let config = parse_args(args, HashMap::new()).unwrap();
+    assert_eq!(config, config_from_file());
+}
+
+#[test]
+fn test_parse_config_file_override() {
+    let temp_dir = TempDir::new().unwrap();
+    let path = temp_dir.path().join("vm.toml");
+    fs::write(&path, CONFIG_TOML).unwrap();
+
+    let args = BootArgs {
+        config: Some(path.into()),
+        cmdline: Some(c"console=hvc0".into()),
+        cpu: Some("count=2".into()),
+        blk: vec!["file,path=root.img".into()],
+        entropy: true,
+        ..Default::default()
+    };
+    let config = parse_args(args, HashMap::new()).unwrap();
+
+    let mut want = config_from_file();
+    want.payload.cmdline = Some(c"console=hvc0".into());
+    want.board.cpu.count = 2;
+    want.blk = vec![BlkParam::File(BlkFileParam {
+        path: Path::new("root.img").into(),
+        readonly: false,
+        api: WorkerApi::Mio,
+    })];
+    want.entropy = Some(EntropyParam::default());
+    assert_eq!(config, want);
+}
+
+#[test]
+fn test_parse_config_file_deprecated_override() {
+    let temp_dir = TempDir::new().unwrap();
+    let path = temp_dir.path().join("vm.toml");
+    fs::write(&path, CONFIG_TOML).unwrap();
+
+    let args = BootArgs {
+        config: Some(path.into()),
+        num_cpu: Some(8),
+        mem_size: Some("4G".into()),
+        ..Default::default()
+    };
+    let config = parse_args(args, HashMap::new()).unwrap();
+
+    let mut want = config_from_file();
+    want.board.cpu.count = 8;
+    want.board.mem = MemConfig {
+        size: 4 << 30,
+        #[cfg(target_os = "linux")]
+        backend: MemBackend::Memfd,
+        #[cfg(target_os = "macos")]
+        backend: MemBackend::Anonymous,
+        ..Default::default()
+    };
+    assert_eq!(config, want);
+}
+
+#[rstest]
+#[case("vm.toml", "board = 1")]
+#[case("vm.json", "{")]
+#[case("vm.toml", "pvpanik = true")]
+#[case("vm.toml", "[board]\ncpus = 4")]
+#[case("vm.toml", "[board.cpu]\ncont = 4")]
+#[case("vm.json", r#"{ "board": { "mem": { "sise": 1024 } } }"#)]
+fn test_parse_config_file_error(#[case] name: &str, #[case] content: &str) {
+    let temp_dir = TempDir::new().unwrap();
+    let path = temp_dir.path().join(name);
+    fs::write(&path, content).unwrap();
+
+    let args = BootArgs {
+        config: Some(path.into()),
+        ..Default::default()
+    };
+    assert!(parse_args(args, HashMap::new()).is_err());
+}
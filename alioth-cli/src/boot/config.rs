@@ -16,17 +16,24 @@
 use std::path::Path;
 
 use alioth::board::BoardConfig;
+use alioth::device::console::ConsoleParam;
 #[cfg(target_arch = "x86_64")]
 use alioth::device::fw_cfg::FwCfgItemParam;
+#[cfg(target_arch = "x86_64")]
+use alioth::device::serial::SerialParam;
 use alioth::loader::Payload;
 #[cfg(target_os = "linux")]
 use alioth::vfio::{CdevParam, ContainerParam, GroupParam, IoasParam};
 use alioth::virtio::dev::balloon::BalloonParam;
 use alioth::virtio::dev::blk::BlkFileParam;
+use alioth::virtio::dev::console::VirtioConsoleParam;
 use alioth::virtio::dev::entropy::EntropyParam;
 use alioth::virtio::dev::fs::shared_dir::SharedDirParam;
+use alioth::virtio::dev::iommu::VirtioIommuParam;
+use alioth::virtio::dev::mem::VirtioMemParam;
 #[cfg(target_os = "macos")]
 use alioth::virtio::dev::net::vmnet::NetVmnetParam;
+use alioth::virtio::dev::pmem::PmemParam;
 use alioth::virtio::dev::vsock::UdsVsockParam;
 #[cfg(target_os = "linux")]
 use alioth::virtio::dev::{fs::vu::VuFsParam, net::tap::NetTapParam, vsock::VhostVsockParam};
@@ -93,17 +100,26 @@ pub enum VsockParam {
 }
 
 #[derive(Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
 pub struct Config {
     pub board: BoardConfig,
 
     pub payload: Payload,
 
+    pub console: ConsoleParam,
+    #[cfg(target_arch = "x86_64")]
+    pub serial: Vec<SerialParam>,
+    pub virtio_console: Option<VirtioConsoleParam>,
+
     pub net: Vec<NetParam>,
     pub blk: Vec<BlkParam>,
     pub fs: Vec<FsParam>,
+    pub pmem: Vec<PmemParam>,
     pub vsock: Option<VsockParam>,
     pub entropy: Option<EntropyParam>,
     pub balloon: Option<BalloonParam>,
+    pub virtio_mem: Option<VirtioMemParam>,
+    pub virtio_iommu: Option<VirtioIommuParam>,
     pub pvpanic: bool,
 
     #[cfg(target_arch = "x86_64")]
@@ -118,7 +118,8 @@ where
     let param: P = serde_aco::from_args(&args.param, &objects)
         .context(error::ParseArg { arg: &args.param })?;
     let dev = param.build(name.clone()).context(error::CreateVirtio)?;
-    let dev = VirtioDevice::new(name, dev, memory, false).context(error::CreateVirtio)?;
+    let dev =
+        VirtioDevice::new(name, dev, memory, false, [].into()).context(error::CreateVirtio)?;
     Ok(dev)
 }
 
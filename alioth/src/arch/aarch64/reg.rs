@@ -52,6 +52,7 @@ pub enum Reg {
     Sp,
     Pc,
     Pstate,
+    SpEl1,
 }
 
 pub const fn encode(op0: u16, op1: u16, crn: u16, crm: u16, op2: u16) -> u16 {
@@ -72,8 +73,19 @@ consts! {
         /// System Control Register
         /// https://developer.arm.com/documentation/ddi0601/latest/AArch64-Registers/SCTLR-EL1--System-Control-Register--EL1-
         SCTLR_EL1 = encode(3, 0, 1, 0, 0);
+        /// Translation Table Base Register 0 (EL1)
+        /// https://developer.arm.com/documentation/ddi0601/latest/AArch64-Registers/TTBR0-EL1--Translation-Table-Base-Register-0--EL1-
+        TTBR0_EL1 = encode(3, 0, 2, 0, 0);
+        /// Translation Table Base Register 1 (EL1)
+        /// https://developer.arm.com/documentation/ddi0601/latest/AArch64-Registers/TTBR1-EL1--Translation-Table-Base-Register-1--EL1-
+        TTBR1_EL1 = encode(3, 0, 2, 0, 1);
+        /// Translation Control Register (EL1)
+        /// https://developer.arm.com/documentation/ddi0601/latest/AArch64-Registers/TCR-EL1--Translation-Control-Register--EL1-
+        TCR_EL1 = encode(3, 0, 2, 0, 2);
         /// Stack Pointer (EL0)
         SP_EL0 = encode(3, 0, 4, 1, 0);
+        /// Stack Pointer (EL1)
+        SP_EL1 = encode(3, 4, 4, 1, 0);
         /// Exception Syndrome Register (EL2)
         ESR_EL2 = encode(3, 4, 5, 2, 0);
     }
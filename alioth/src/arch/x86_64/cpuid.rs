@@ -12,9 +12,14 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use std::arch::x86_64::CpuidResult;
+
 use bitfield::bitfield;
+use serde::Deserialize;
+use serde_aco::Help;
 
 use crate::bitflags;
+use crate::sys::kvm::{KVM_CPUID_FEATURES, KvmCpuidFeature};
 
 #[derive(Debug, Default, Clone, Hash, PartialEq, Eq)]
 pub struct CpuidIn {
@@ -24,19 +29,106 @@ pub struct CpuidIn {
 
 bitflags! {
     pub struct Cpuid1Ecx(u32) {
+        SSE3 = 1 << 0;
+        PCLMULQDQ = 1 << 1;
+        VMX = 1 << 5;
+        SSSE3 = 1 << 9;
+        FMA = 1 << 12;
+        CX16 = 1 << 13;
+        PCID = 1 << 17;
+        SSE4_1 = 1 << 19;
+        SSE4_2 = 1 << 20;
+        X2APIC = 1 << 21;
+        MOVBE = 1 << 22;
+        POPCNT = 1 << 23;
         TSC_DEADLINE = 1 << 24;
+        AES = 1 << 25;
+        XSAVE = 1 << 26;
+        AVX = 1 << 28;
+        F16C = 1 << 29;
+        RDRAND = 1 << 30;
         HYPERVISOR = 1 << 31;
     }
 }
 
+bitflags! {
+    pub struct Cpuid1Edx(u32) {
+        FPU = 1 << 0;
+        VME = 1 << 1;
+        DE = 1 << 2;
+        PSE = 1 << 3;
+        TSC = 1 << 4;
+        MSR = 1 << 5;
+        PAE = 1 << 6;
+        MCE = 1 << 7;
+        CX8 = 1 << 8;
+        APIC = 1 << 9;
+        SEP = 1 << 11;
+        MTRR = 1 << 12;
+        PGE = 1 << 13;
+        MCA = 1 << 14;
+        CMOV = 1 << 15;
+        PAT = 1 << 16;
+        PSE36 = 1 << 17;
+        CLFSH = 1 << 19;
+        MMX = 1 << 23;
+        FXSR = 1 << 24;
+        SSE = 1 << 25;
+        SSE2 = 1 << 26;
+        SS = 1 << 27;
+        HTT = 1 << 28;
+    }
+}
+
 bitflags! {
     pub struct Cpuid7Index0Ebx(u32) {
+        FSGSBASE = 1 << 0;
         TSC_ADJUST = 1 << 1;
+        BMI1 = 1 << 3;
+        AVX2 = 1 << 5;
+        SMEP = 1 << 7;
+        BMI2 = 1 << 8;
+        ERMS = 1 << 9;
+        INVPCID = 1 << 10;
+        AVX512F = 1 << 16;
+        AVX512DQ = 1 << 17;
+        RDSEED = 1 << 18;
+        ADX = 1 << 19;
+        SMAP = 1 << 20;
+        AVX512IFMA = 1 << 21;
+        CLFLUSHOPT = 1 << 23;
+        CLWB = 1 << 24;
+        AVX512CD = 1 << 28;
+        SHA = 1 << 29;
+        AVX512BW = 1 << 30;
+        AVX512VL = 1 << 31;
+    }
+}
+
+bitflags! {
+    pub struct Cpuid7Index0Ecx(u32) {
+        AVX512VBMI = 1 << 1;
+        UMIP = 1 << 2;
+        PKU = 1 << 3;
+        AVX512_VBMI2 = 1 << 6;
+        GFNI = 1 << 8;
+        VAES = 1 << 9;
+        VPCLMULQDQ = 1 << 10;
+        AVX512_VNNI = 1 << 11;
+        AVX512_BITALG = 1 << 12;
+        AVX512_VPOPCNTDQ = 1 << 14;
+        LA57 = 1 << 16;
+        RDPID = 1 << 22;
     }
 }
 
 bitflags! {
     pub struct Cpuid7Index0Edx(u32) {
+        FSRM = 1 << 4;
+        MD_CLEAR = 1 << 10;
+        AMX_BF16 = 1 << 22;
+        AMX_TILE = 1 << 24;
+        AMX_INT8 = 1 << 25;
         IBRS_IBPB = 1 << 26;
         SPEC_CTRL_ST_PREDICTORS = 1 << 27;
         L1D_FLUSH_INTERFACE = 1 << 28;
@@ -46,6 +138,27 @@ bitflags! {
     }
 }
 
+bitflags! {
+    pub struct CpuidExt1Ecx(u32) {
+        LAHF_LM = 1 << 0;
+        SVM = 1 << 2;
+        ABM = 1 << 5;
+        SSE4A = 1 << 6;
+        MISALIGNSSE = 1 << 7;
+        PREFETCHW = 1 << 8;
+    }
+}
+
+bitflags! {
+    pub struct CpuidExt1Edx(u32) {
+        SYSCALL = 1 << 11;
+        NX = 1 << 20;
+        PDPE1GB = 1 << 26;
+        RDTSCP = 1 << 27;
+        LM = 1 << 29;
+    }
+}
+
 bitflags! {
     pub struct CpuidExt8Ebx(u32) {
         SSBD_VIRT_SPEC_CTRL = 1 << 25;
@@ -73,3 +186,252 @@ bitflags! {
         NO_SMM_CTL_MSR = 1 << 9;
     }
 }
+
+bitflags! {
+    /// XSAVE state components in CPUID 0xd, see Intel SDM Vol. 1, Sec. 13.1.
+    pub struct XsaveComponent(u64) {
+        X87 = 1 << 0;
+        SSE = 1 << 1;
+        AVX = 1 << 2;
+        OPMASK = 1 << 5;
+        ZMM_HI256 = 1 << 6;
+        HI16_ZMM = 1 << 7;
+        PKRU = 1 << 9;
+        XTILECFG = 1 << 17;
+        XTILEDATA = 1 << 18;
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum CpuidReg {
+    Eax,
+    Ebx,
+    Ecx,
+    Edx,
+}
+
+impl CpuidReg {
+    pub fn get_mut(self, out: &mut CpuidResult) -> &mut u32 {
+        match self {
+            CpuidReg::Eax => &mut out.eax,
+            CpuidReg::Ebx => &mut out.ebx,
+            CpuidReg::Ecx => &mut out.ecx,
+            CpuidReg::Edx => &mut out.edx,
+        }
+    }
+}
+
+/// Feature bits in one register of a CPUID leaf.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CpuFeature {
+    pub leaf: CpuidIn,
+    pub reg: CpuidReg,
+    pub bits: u32,
+}
+
+macro_rules! feature_regs {
+    ($($FlagTy:ident => ($func:expr, $index:expr, $reg:ident);)*) => {
+        $(
+            impl From<$FlagTy> for CpuFeature {
+                fn from(flags: $FlagTy) -> Self {
+                    CpuFeature {
+                        leaf: CpuidIn {
+                            func: $func,
+                            index: $index,
+                        },
+                        reg: CpuidReg::$reg,
+                        bits: flags.bits(),
+                    }
+                }
+            }
+        )*
+
+        impl CpuFeature {
+            /// Looks up a feature by its case-insensitive name, e.g. `avx512f`.
+            pub fn from_name(name: &str) -> Option<Self> {
+                let name = name.to_ascii_uppercase();
+                $(
+                    if let Some(flags) = $FlagTy::from_name(&name) {
+                        return Some(flags.into());
+                    }
+                )*
+                None
+            }
+
+            /// Returns the names of the feature bits.
+            pub fn names(&self) -> String {
+                $(
+                    if self.leaf.func == $func
+                        && self.leaf.index == $index
+                        && self.reg == CpuidReg::$reg
+                    {
+                        let flags = $FlagTy::from_bits_retain(self.bits);
+                        return format!("{flags:?}").to_ascii_lowercase();
+                    }
+                )*
+                format!("{:x?} {:?} {:#x}", self.leaf, self.reg, self.bits)
+            }
+
+            /// Returns all feature bits with a name, one entry per register.
+            pub fn known() -> Vec<Self> {
+                vec![$($FlagTy::all().into(),)*]
+            }
+        }
+    };
+}
+
+feature_regs! {
+    Cpuid1Ecx => (0x1, None, Ecx);
+    Cpuid1Edx => (0x1, None, Edx);
+    Cpuid7Index0Ebx => (0x7, Some(0), Ebx);
+    Cpuid7Index0Ecx => (0x7, Some(0), Ecx);
+    Cpuid7Index0Edx => (0x7, Some(0), Edx);
+    CpuidExt1Ecx => (0x8000_0001, None, Ecx);
+    CpuidExt1Edx => (0x8000_0001, None, Edx);
+    KvmCpuidFeature => (KVM_CPUID_FEATURES, None, Eax);
+}
+
+impl CpuFeature {
+    /// Parses a feature edit like `+x2apic` or `-avx512f`, returning the
+    /// feature and whether it is added.
+    pub fn from_edit(edit: &str) -> Option<(Self, bool)> {
+        let (add, name) = if let Some(name) = edit.strip_prefix('+') {
+            (true, name)
+        } else {
+            (false, edit.strip_prefix('-')?)
+        };
+        let feature = CpuFeature::from_alias(name).or_else(|| CpuFeature::from_name(name))?;
+        Some((feature, add))
+    }
+
+    /// Looks up a group of feature bits that are enabled together.
+    fn from_alias(name: &str) -> Option<Self> {
+        let flags = match name.to_ascii_lowercase().as_str() {
+            "kvmclock" => {
+                KvmCpuidFeature::CLOCKSOURCE
+                    | KvmCpuidFeature::CLOCKSOURCE2
+                    | KvmCpuidFeature::CLOCKSOURCE_STABLE_BIT
+            }
+            // Asynchronous page faults, delivered by #PF, #VE or interrupts.
+            "async_pf" => {
+                KvmCpuidFeature::ASYNC_PF
+                    | KvmCpuidFeature::ASYNC_PF_VMEXIT
+                    | KvmCpuidFeature::ASYNC_PF_INT
+            }
+            _ => return None,
+        };
+        Some(flags.into())
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Help)]
+pub enum CpuModel {
+    /// All features supported by the host and the hypervisor.
+    #[default]
+    #[serde(alias = "host")]
+    Host,
+    /// x86-64 baseline: SSE2, NX, and long mode.
+    #[serde(alias = "x86-64-v1")]
+    X86_64V1,
+    /// x86-64-v1 with SSE4.2, SSSE3, POPCNT, and CMPXCHG16B.
+    #[serde(alias = "x86-64-v2")]
+    X86_64V2,
+    /// x86-64-v2 with AVX2, BMI1, BMI2, F16C, FMA, LZCNT, and MOVBE.
+    #[serde(alias = "x86-64-v3")]
+    X86_64V3,
+    /// x86-64-v3 with AVX512F, AVX512BW, AVX512CD, AVX512DQ, and AVX512VL.
+    #[serde(alias = "x86-64-v4")]
+    X86_64V4,
+}
+
+impl CpuModel {
+    /// Returns the name of the model, e.g. `x86-64-v2`.
+    pub fn name(&self) -> &'static str {
+        match self {
+            CpuModel::Host => "host",
+            CpuModel::X86_64V1 => "x86-64-v1",
+            CpuModel::X86_64V2 => "x86-64-v2",
+            CpuModel::X86_64V3 => "x86-64-v3",
+            CpuModel::X86_64V4 => "x86-64-v4",
+        }
+    }
+
+    /// Returns the features required by the model, or `None` for the host
+    /// model.
+    pub fn features(&self) -> Option<Vec<CpuFeature>> {
+        let level = match self {
+            CpuModel::Host => return None,
+            CpuModel::X86_64V1 => 1,
+            CpuModel::X86_64V2 => 2,
+            CpuModel::X86_64V3 => 3,
+            CpuModel::X86_64V4 => 4,
+        };
+        let mut leaf1_ecx = Cpuid1Ecx::empty();
+        let leaf1_edx = Cpuid1Edx::FPU
+            | Cpuid1Edx::CX8
+            | Cpuid1Edx::CMOV
+            | Cpuid1Edx::MMX
+            | Cpuid1Edx::FXSR
+            | Cpuid1Edx::SSE
+            | Cpuid1Edx::SSE2;
+        let mut leaf7_ebx = Cpuid7Index0Ebx::empty();
+        let mut ext1_ecx = CpuidExt1Ecx::empty();
+        let ext1_edx = CpuidExt1Edx::SYSCALL | CpuidExt1Edx::LM;
+        if level >= 2 {
+            leaf1_ecx |= Cpuid1Ecx::SSE3
+                | Cpuid1Ecx::SSSE3
+                | Cpuid1Ecx::SSE4_1
+                | Cpuid1Ecx::SSE4_2
+                | Cpuid1Ecx::POPCNT
+                | Cpuid1Ecx::CX16;
+            ext1_ecx |= CpuidExt1Ecx::LAHF_LM;
+        }
+        if level >= 3 {
+            leaf1_ecx |= Cpuid1Ecx::AVX | Cpuid1Ecx::FMA | Cpuid1Ecx::F16C | Cpuid1Ecx::MOVBE;
+            leaf7_ebx |= Cpuid7Index0Ebx::AVX2 | Cpuid7Index0Ebx::BMI1 | Cpuid7Index0Ebx::BMI2;
+            ext1_ecx |= CpuidExt1Ecx::ABM;
+        }
+        if level >= 4 {
+            leaf7_ebx |= Cpuid7Index0Ebx::AVX512F
+                | Cpuid7Index0Ebx::AVX512BW
+                | Cpuid7Index0Ebx::AVX512CD
+                | Cpuid7Index0Ebx::AVX512DQ
+                | Cpuid7Index0Ebx::AVX512VL;
+        }
+        Some(vec![
+            leaf1_ecx.into(),
+            leaf1_edx.into(),
+            leaf7_ebx.into(),
+            ext1_ecx.into(),
+            ext1_edx.into(),
+        ])
+    }
+
+    /// Returns the system features and speculation controls a CPU model
+    /// keeps if the host has them. Other feature bits not required by the
+    /// model are cleared.
+    pub fn optional_features() -> Vec<CpuFeature> {
+        vec![
+            (Cpuid1Ecx::X2APIC
+                | Cpuid1Ecx::TSC_DEADLINE
+                | Cpuid1Ecx::XSAVE
+                | Cpuid1Ecx::HYPERVISOR)
+                .into(),
+            Cpuid1Edx::all().into(),
+            (Cpuid7Index0Edx::MD_CLEAR
+                | Cpuid7Index0Edx::IBRS_IBPB
+                | Cpuid7Index0Edx::SPEC_CTRL_ST_PREDICTORS
+                | Cpuid7Index0Edx::L1D_FLUSH_INTERFACE
+                | Cpuid7Index0Edx::ARCH_CAPABILITIES
+                | Cpuid7Index0Edx::CORE_CAPABILITIES
+                | Cpuid7Index0Edx::SPEC_CTRL_SSBD)
+                .into(),
+            CpuidExt1Edx::NX.into(),
+            KvmCpuidFeature::all().into(),
+        ]
+    }
+}
+
+#[cfg(test)]
+#[path = "cpuid_test.rs"]
+mod tests;
@@ -0,0 +1,75 @@
+// Copyright 2026 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use rstest::rstest;
+
+use crate::arch::cpuid::{
+    CpuFeature, CpuModel, Cpuid1Ecx, Cpuid7Index0Ebx, CpuidExt1Edx, CpuidIn, CpuidReg,
+};
+use crate::sys::kvm::KvmCpuidFeature;
+
+const KVMCLOCK: KvmCpuidFeature = KvmCpuidFeature::CLOCKSOURCE
+    .union(KvmCpuidFeature::CLOCKSOURCE2)
+    .union(KvmCpuidFeature::CLOCKSOURCE_STABLE_BIT);
+
+#[rstest]
+#[case("+x2apic", Some((Cpuid1Ecx::X2APIC.into(), true)))]
+#[case("-avx512f", Some((Cpuid7Index0Ebx::AVX512F.into(), false)))]
+#[case("-NX", Some((CpuidExt1Edx::NX.into(), false)))]
+#[case("-steal_time", Some((KvmCpuidFeature::STEAL_TIME.into(), false)))]
+#[case("-kvmclock", Some((KVMCLOCK.into(), false)))]
+#[case("avx2", None)]
+#[case("+unknown", None)]
+fn test_cpu_feature_from_edit(#[case] edit: &str, #[case] want: Option<(CpuFeature, bool)>) {
+    assert_eq!(CpuFeature::from_edit(edit), want);
+}
+
+#[test]
+fn test_cpu_feature_names() {
+    let feature: CpuFeature = (Cpuid7Index0Ebx::AVX512F | Cpuid7Index0Ebx::AVX512BW).into();
+    assert_eq!(
+        feature,
+        CpuFeature {
+            leaf: CpuidIn {
+                func: 0x7,
+                index: Some(0)
+            },
+            reg: CpuidReg::Ebx,
+            bits: (1 << 16) | (1 << 30),
+        }
+    );
+    assert_eq!(feature.names(), "avx512f | avx512bw");
+}
+
+#[test]
+fn test_cpu_model_features() {
+    assert_eq!(CpuModel::Host.features(), None);
+
+    let v2 = CpuModel::X86_64V2.features().unwrap();
+    let v3 = CpuModel::X86_64V3.features().unwrap();
+    assert_eq!(v2.len(), v3.len());
+    for (f2, f3) in v2.iter().zip(&v3) {
+        assert_eq!((&f2.leaf, f2.reg), (&f3.leaf, f3.reg));
+        assert_eq!(f2.bits & !f3.bits, 0);
+    }
+    let avx2 = CpuFeature::from(Cpuid7Index0Ebx::AVX2);
+    let has_avx2 = |features: &[CpuFeature]| {
+        let f = features
+            .iter()
+            .find(|f| f.leaf == avx2.leaf && f.reg == avx2.reg);
+        f.unwrap().bits & avx2.bits != 0
+    };
+    assert!(!has_avx2(&v2));
+    assert!(has_avx2(&v3));
+}
@@ -36,8 +36,9 @@ pub const EBDA_END: u64 = 0xA_0000;
 
 pub const KERNEL_IMAGE_START: u64 = 0x100_0000; // 16 MiB
 
+pub const RAM_32_START: u64 = 0x0;
 pub const RAM_32_END: u64 = 0x8000_0000; // 2 GiB
-pub const RAM_32_SIZE: u64 = RAM_32_END; // 2 GiB
+pub const RAM_32_SIZE: u64 = RAM_32_END - RAM_32_START; // 2 GiB
 
 pub const PCIE_MMIO_32_PREFETCHABLE_START: u64 = 0x8000_0000; // 2 GiB
 pub const PCIE_MMIO_32_PREFETCHABLE_END: u64 = 0xa000_0000; // 2.5 GiB, size = 512 MiB
@@ -64,6 +65,9 @@ pub const PORT_CMOS_REG: u16 = 0x70;
 pub const PORT_CMOS_DATA: u16 = 0x71;
 
 pub const PORT_COM1: u16 = 0x3f8;
+pub const PORT_COM2: u16 = 0x2f8;
+pub const PORT_COM3: u16 = 0x3e8;
+pub const PORT_COM4: u16 = 0x2e8;
 
 pub const PORT_FWDBG: u16 = 0x402;
 
@@ -77,5 +81,8 @@ pub const PORT_ACPI_SLEEP_STATUS: u16 = 0x601;
 pub const PORT_ACPI_RESET: u16 = 0x604;
 pub const PORT_ACPI_TIMER: u16 = 0x608;
 
+pub const PORT_PCI_HOTPLUG: u16 = 0x610;
+pub const PORT_ACPI_GED: u16 = 0x620;
+
 pub const PORT_PCI_ADDRESS: u16 = 0xcf8;
 pub const PORT_PCI_DATA: u16 = 0xcfc;
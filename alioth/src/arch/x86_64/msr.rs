@@ -27,6 +27,21 @@ pub const IA32_GS_BASE: u32 = 0xc000_0101;
 pub const IA32_KERNEL_GS_BASE: u32 = 0xc000_0102;
 pub const IA32_TSC_AUX: u32 = 0xc000_0103;
 pub const IA32_MISC_ENABLE: u32 = 0x0000_01a0;
+pub const IA32_TSC: u32 = 0x0000_0010;
+pub const IA32_SYSENTER_CS: u32 = 0x0000_0174;
+pub const IA32_SYSENTER_ESP: u32 = 0x0000_0175;
+pub const IA32_SYSENTER_EIP: u32 = 0x0000_0176;
+pub const IA32_PAT: u32 = 0x0000_0277;
+pub const IA32_TSC_DEADLINE: u32 = 0x0000_06e0;
+
+// KVM paravirtual MSRs, linux/Documentation/virt/kvm/x86/msr.rst.
+pub const MSR_KVM_WALL_CLOCK_NEW: u32 = 0x4b56_4d00;
+pub const MSR_KVM_SYSTEM_TIME_NEW: u32 = 0x4b56_4d01;
+pub const MSR_KVM_ASYNC_PF_EN: u32 = 0x4b56_4d02;
+pub const MSR_KVM_STEAL_TIME: u32 = 0x4b56_4d03;
+pub const MSR_KVM_PV_EOI_EN: u32 = 0x4b56_4d04;
+pub const MSR_KVM_POLL_CONTROL: u32 = 0x4b56_4d05;
+pub const MSR_KVM_ASYNC_PF_INT: u32 = 0x4b56_4d06;
 
 bitflags! {
     pub struct Efer(u32) {
@@ -19,39 +19,58 @@ mod aarch64;
 #[path = "board_x86_64/board_x86_64.rs"]
 mod x86_64;
 
+mod debug;
+mod migration;
+mod snapshot;
+
+use std::cmp::{max, min};
+use std::collections::BTreeSet;
 #[cfg(target_os = "linux")]
 use std::collections::HashMap;
 use std::ffi::CStr;
+use std::fs::OpenOptions;
+use std::path::Path;
 use std::sync::Arc;
 use std::sync::mpsc::Sender;
 use std::thread::JoinHandle;
 
-use libc::{MAP_PRIVATE, MAP_SHARED};
+use libc::{MAP_PRIVATE, MAP_SHARED, PROT_READ, PROT_WRITE};
+#[cfg(target_os = "linux")]
+use libc::{MFD_HUGE_1GB, MFD_HUGE_2MB, MFD_HUGETLB, pthread_self};
 use parking_lot::{Condvar, Mutex, RwLock, RwLockReadGuard};
 use serde::Deserialize;
 use serde_aco::Help;
 use snafu::{ResultExt, Snafu};
 
+use crate::align_up;
 #[cfg(target_arch = "x86_64")]
-use crate::arch::cpuid::CpuidIn;
+use crate::arch::cpuid::{CpuFeature, CpuModel, CpuidIn};
 #[cfg(target_arch = "x86_64")]
 use crate::arch::layout::PORT_PCI_ADDRESS;
 use crate::arch::layout::{
     MEM_64_START, PCIE_CONFIG_START, PCIE_MMIO_32_NON_PREFETCHABLE_END,
     PCIE_MMIO_32_NON_PREFETCHABLE_START, PCIE_MMIO_32_PREFETCHABLE_END,
-    PCIE_MMIO_32_PREFETCHABLE_START, RAM_32_SIZE,
+    PCIE_MMIO_32_PREFETCHABLE_START, RAM_32_SIZE, RAM_32_START,
 };
-use crate::device::MmioDev;
 #[cfg(target_arch = "x86_64")]
 use crate::device::fw_cfg::FwCfg;
+use crate::device::{self, MmioDev};
 use crate::errors::{DebugTrace, trace_error};
 use crate::hv::{Coco, Hypervisor, Vcpu, Vm, VmConfig, VmEntry, VmExit};
 #[cfg(target_arch = "x86_64")]
 use crate::loader::xen;
 use crate::loader::{Executable, InitState, Payload, linux};
+#[cfg(target_os = "linux")]
+use crate::mem::HugePageSize;
 use crate::mem::mapped::ArcMemPages;
 use crate::mem::{MemBackend, MemConfig, MemRegion, MemRegionType, Memory};
+use crate::metrics::ExitCounters;
+use crate::pci::Bdf;
 use crate::pci::bus::PciBus;
+#[cfg(target_arch = "x86_64")]
+use crate::pci::hotplug::PciHotplug;
+#[cfg(target_os = "linux")]
+use crate::utils::thread::{SchedPolicy, ThreadConfig};
 #[cfg(target_os = "linux")]
 use crate::vfio::container::Container;
 #[cfg(target_os = "linux")]
@@ -59,6 +78,9 @@ use crate::vfio::iommu::Ioas;
 
 #[cfg(target_arch = "aarch64")]
 use self::aarch64::ArchBoard;
+use self::debug::DebugSync;
+pub use self::migration::{MigrationAddr, MigrationStream, MigrationTcpParam, MigrationUdsParam};
+use self::snapshot::SnapshotData;
 #[cfg(target_arch = "x86_64")]
 use self::x86_64::ArchBoard;
 
@@ -74,6 +96,19 @@ pub enum Error {
     Loader { source: Box<crate::loader::Error> },
     #[snafu(display("Invalid CPU topology"))]
     InvalidCpuTopology,
+    #[cfg(target_arch = "x86_64")]
+    #[snafu(display("Invalid CPU feature {name:?}, want +name or -name"))]
+    InvalidCpuFeature { name: Box<str> },
+    #[cfg(target_arch = "x86_64")]
+    #[snafu(display("Host does not support CPU features {names}"))]
+    MissingCpuFeature { names: String },
+    #[snafu(display("CPU affinity does not match the number of VCPUs"))]
+    InvalidCpuAffinity,
+    #[cfg(target_os = "linux")]
+    #[snafu(display("Invalid priority {priority} for scheduling policy {policy:?}"))]
+    InvalidSchedPriority { policy: SchedPolicy, priority: u8 },
+    #[snafu(display("Failed to configure the thread of VCPU-{index}"))]
+    VcpuThread { index: u16, error: std::io::Error },
     #[snafu(display("Failed to create VCPU-{index}"))]
     CreateVcpu {
         index: u16,
@@ -108,6 +143,46 @@ pub enum Error {
     Firmware { source: Box<crate::firmware::Error> },
     #[snafu(display("Unknown firmware metadata"))]
     UnknownFirmwareMetadata,
+    #[snafu(display("Failed to access snapshot file {path:?}"))]
+    SnapshotFile {
+        path: Box<Path>,
+        error: std::io::Error,
+    },
+    #[snafu(display("Failed to snapshot or restore device {name}"))]
+    SnapshotDevice {
+        name: String,
+        source: Box<crate::device::Error>,
+    },
+    #[snafu(display("Failed to pause or resume device {name}"))]
+    PauseDevice {
+        name: String,
+        source: Box<crate::device::Error>,
+    },
+    #[snafu(display("Invalid snapshot: {msg}"))]
+    InvalidSnapshot { msg: String },
+    #[snafu(display("Snapshots are not supported for {msg}"))]
+    SnapshotUnsupported { msg: &'static str },
+    #[snafu(display("Board has no power button"))]
+    NoPowerButton,
+    #[snafu(display("Board has not booted"))]
+    NotBooted,
+    #[snafu(display("Board has already booted"))]
+    Booted,
+    #[snafu(display("Failed to transfer migration data"))]
+    Migration { error: std::io::Error },
+    #[snafu(display("Invalid VCPU index {index}"))]
+    InvalidVcpu { index: u16 },
+    #[snafu(display("Invalid NUMA config: {msg}"))]
+    InvalidNuma { msg: String },
+    #[snafu(display("Memory size {size:#x} is not aligned to page size {align:#x}"))]
+    InvalidMemSize { size: u64, align: u64 },
+    #[snafu(display("Memory hotplug is not supported with confidential computing"))]
+    MemHotplugCoco,
+    #[snafu(display("Failed to open memory file {path:?}"))]
+    MemFile {
+        path: Box<Path>,
+        error: std::io::Error,
+    },
 }
 
 type Result<T, E = Error> = std::result::Result<T, E>;
@@ -144,7 +219,8 @@ const fn default_cpu_count() -> u16 {
     1
 }
 
-#[derive(Debug, Default, PartialEq, Eq, Deserialize, Help)]
+#[derive(Debug, PartialEq, Eq, Deserialize, Help)]
+#[serde(deny_unknown_fields)]
 pub struct CpuConfig {
     /// Number of VCPUs assigned to the guest. [default: 1]
     #[serde(default = "default_cpu_count")]
@@ -152,6 +228,52 @@ pub struct CpuConfig {
     /// Architecture specific CPU topology.
     #[serde(default)]
     pub topology: CpuTopology,
+    /// CPU model exposed to the guest. [default: host]
+    #[cfg(target_arch = "x86_64")]
+    #[serde(default)]
+    pub model: CpuModel,
+    /// CPU features added with `+` or removed with `-` on top of the model,
+    /// e.g. `features=id_features` with `-o id_features,-avx512f,+x2apic`.
+    /// KVM paravirtual features like `kvmclock`, `pv_eoi`, `pv_tlb_flush`,
+    /// `pv_send_ipi` and `steal_time` are enabled by default and can be
+    /// removed the same way. [default: none]
+    #[cfg(target_arch = "x86_64")]
+    #[serde(default)]
+    pub features: Vec<Box<str>>,
+    /// Host CPUs of each VCPU thread, indexed by VCPU, e.g.
+    /// `affinity=id_affinity` with `-o id_affinity,id_cpus_0,id_cpus_1`
+    /// and `-o id_cpus_0,2,3`. [default: no pinning]
+    #[cfg(target_os = "linux")]
+    #[serde(default)]
+    pub affinity: Vec<Vec<u32>>,
+    /// Scheduling policy of VCPU threads. [default: other]
+    #[cfg(target_os = "linux")]
+    #[serde(default)]
+    pub sched_policy: SchedPolicy,
+    /// Real-time priority of VCPU threads from 1 to 99, only for fifo and
+    /// rr. [default: 0]
+    #[cfg(target_os = "linux")]
+    #[serde(default)]
+    pub sched_priority: u8,
+}
+
+impl Default for CpuConfig {
+    fn default() -> Self {
+        CpuConfig {
+            count: default_cpu_count(),
+            topology: CpuTopology::default(),
+            #[cfg(target_arch = "x86_64")]
+            model: CpuModel::default(),
+            #[cfg(target_arch = "x86_64")]
+            features: Vec::new(),
+            #[cfg(target_os = "linux")]
+            affinity: Vec::new(),
+            #[cfg(target_os = "linux")]
+            sched_policy: SchedPolicy::default(),
+            #[cfg(target_os = "linux")]
+            sched_priority: 0,
+        }
+    }
 }
 
 impl CpuConfig {
@@ -168,10 +290,106 @@ impl CpuConfig {
         if count != self.count {
             return error::InvalidCpuTopology.fail();
         }
+        #[cfg(target_os = "linux")]
+        if !self.affinity.is_empty() && self.affinity.len() != self.count as usize {
+            return error::InvalidCpuAffinity.fail();
+        }
+        #[cfg(target_os = "linux")]
+        check_sched(self.sched_policy, self.sched_priority)?;
+        #[cfg(target_arch = "x86_64")]
+        for feature in &self.features {
+            if CpuFeature::from_edit(feature).is_none() {
+                return error::InvalidCpuFeature {
+                    name: feature.clone(),
+                }
+                .fail();
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the host thread config of a VCPU.
+    #[cfg(target_os = "linux")]
+    pub fn thread_config(&self, index: u16) -> ThreadConfig {
+        ThreadConfig {
+            cpus: self
+                .affinity
+                .get(index as usize)
+                .cloned()
+                .unwrap_or_default(),
+            sched_policy: self.sched_policy,
+            sched_priority: self.sched_priority,
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn check_sched(policy: SchedPolicy, priority: u8) -> Result<()> {
+    if policy.is_valid_priority(priority) {
         Ok(())
+    } else {
+        error::InvalidSchedPriority { policy, priority }.fail()
     }
 }
 
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize, Help)]
+pub struct NumaNodeConfig {
+    /// Memory size of the node in bytes.
+    pub size: u64,
+    /// Indexes of the VCPUs in the node, e.g. `cpus=id_cpus` with
+    /// `-o id_cpus,0,1`. [default: none]
+    #[serde(default)]
+    pub cpus: Vec<u16>,
+    /// Distances from the node to every node, indexed by node. The distance
+    /// to itself must be 10. [default: 10 to itself, 20 to others]
+    #[serde(default)]
+    pub distances: Vec<u8>,
+    /// Host NUMA node to allocate the memory from.
+    #[cfg(target_os = "linux")]
+    #[serde(default)]
+    pub host_node: Option<u32>,
+}
+
+/// A guest physical range of RAM in a NUMA node.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NumaMemRange {
+    pub node: u32,
+    pub start: u64,
+    pub size: u64,
+}
+
+/// The PCI devices behind a virtio-iommu device.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IommuTopology {
+    /// BDF of the virtio-iommu device.
+    pub bdf: Bdf,
+    /// Devices whose DMA is not translated by the virtio-iommu device.
+    pub bypass: BTreeSet<Bdf>,
+}
+
+impl IommuTopology {
+    /// Returns the inclusive ranges of BDFs translated by the virtio-iommu
+    /// device.
+    pub fn endpoint_ranges(&self) -> Vec<(u16, u16)> {
+        let excluded: BTreeSet<u16> = self.bypass.iter().chain([&self.bdf]).map(|b| b.0).collect();
+        let mut ranges = Vec::new();
+        let mut start = 0u32;
+        for bdf in excluded {
+            if u32::from(bdf) > start {
+                ranges.push((start as u16, bdf - 1));
+            }
+            start = u32::from(bdf) + 1;
+        }
+        if start <= u32::from(u16::MAX) {
+            ranges.push((start as u16, u16::MAX));
+        }
+        ranges
+    }
+}
+
+pub const NUMA_DISTANCE_LOCAL: u8 = 10;
+pub const NUMA_DISTANCE_REMOTE: u8 = 20;
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum BoardState {
     Paused,
@@ -185,24 +403,148 @@ struct MpSync {
     state: BoardState,
     fatal: bool,
     count: u16,
+    booted: bool,
+    /// VCPU states collected for a snapshot, indexed by VCPU.
+    vcpu_states: Option<Vec<Option<Result<Vec<u8>>>>>,
+    debug: DebugSync,
 }
 
 pub const PCIE_MMIO_64_SIZE: u64 = 1 << 40;
 
 #[derive(Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
 pub struct BoardConfig {
     pub mem: MemConfig,
     pub cpu: CpuConfig,
     pub coco: Option<Coco>,
+    pub numa: Vec<NumaNodeConfig>,
+    /// Host thread config of VirtIO device workers.
+    #[cfg(target_os = "linux")]
+    pub worker: ThreadConfig,
 }
 
 impl BoardConfig {
+    /// Returns the guest physical address of the memory hotplug region,
+    /// which starts at the first 1GiB boundary after RAM.
+    pub fn mem_hotplug_start(&self) -> u64 {
+        align_up!(self.mem.size.saturating_sub(RAM_32_SIZE) + MEM_64_START, 30)
+    }
+
     pub fn pcie_mmio_64_start(&self) -> u64 {
-        (self.mem.size.saturating_sub(RAM_32_SIZE) + MEM_64_START).next_power_of_two()
+        if self.mem.hotplug_size == 0 {
+            (self.mem.size.saturating_sub(RAM_32_SIZE) + MEM_64_START).next_power_of_two()
+        } else {
+            (self.mem_hotplug_start() + self.mem.hotplug_size).next_power_of_two()
+        }
     }
 
     pub fn config_fixup(&mut self) -> Result<()> {
-        self.cpu.fixup()
+        self.cpu.fixup()?;
+        #[cfg(target_os = "linux")]
+        check_sched(self.worker.sched_policy, self.worker.sched_priority)?;
+        let align = self.mem.page_size();
+        if self.mem.size & (align - 1) != 0 {
+            return error::InvalidMemSize {
+                size: self.mem.size,
+                align,
+            }
+            .fail();
+        }
+        if self.mem.hotplug_size & (align - 1) != 0 {
+            return error::InvalidMemSize {
+                size: self.mem.hotplug_size,
+                align,
+            }
+            .fail();
+        }
+        if self.mem.hotplug_size != 0 && self.coco.is_some() {
+            return error::MemHotplugCoco.fail();
+        }
+        self.numa_fixup()
+    }
+
+    fn numa_fixup(&mut self) -> Result<()> {
+        if self.numa.is_empty() {
+            return Ok(());
+        }
+        let num_nodes = self.numa.len();
+        let page_size = self.mem.page_size();
+        let total_size: u64 = self.numa.iter().map(|n| n.size).sum();
+        if total_size != self.mem.size {
+            let msg = format!(
+                "total node size {total_size:#x} differs from memory size {:#x}",
+                self.mem.size
+            );
+            return error::InvalidNuma { msg }.fail();
+        }
+        let mut assigned = vec![false; self.cpu.count as usize];
+        for (index, node) in self.numa.iter_mut().enumerate() {
+            if node.size == 0 || node.size & (page_size - 1) != 0 {
+                let msg = format!("node {index}: size {:#x} is not page aligned", node.size);
+                return error::InvalidNuma { msg }.fail();
+            }
+            for &cpu in &node.cpus {
+                match assigned.get_mut(cpu as usize) {
+                    Some(a @ false) => *a = true,
+                    Some(true) => {
+                        let msg = format!("VCPU {cpu} is in more than one node");
+                        return error::InvalidNuma { msg }.fail();
+                    }
+                    None => return error::InvalidVcpu { index: cpu }.fail(),
+                }
+            }
+            if node.distances.is_empty() {
+                node.distances = (0..num_nodes)
+                    .map(|i| {
+                        if i == index {
+                            NUMA_DISTANCE_LOCAL
+                        } else {
+                            NUMA_DISTANCE_REMOTE
+                        }
+                    })
+                    .collect();
+            } else if node.distances.len() != num_nodes
+                || node.distances[index] != NUMA_DISTANCE_LOCAL
+            {
+                let msg = format!("node {index}: invalid distances {:?}", node.distances);
+                return error::InvalidNuma { msg }.fail();
+            }
+        }
+        if let Some(cpu) = assigned.iter().position(|a| !a) {
+            let msg = format!("VCPU {cpu} is not in any node");
+            return error::InvalidNuma { msg }.fail();
+        }
+        Ok(())
+    }
+
+    /// Returns the NUMA node of a VCPU.
+    pub fn numa_node_of_cpu(&self, index: u16) -> Option<u32> {
+        let node = self.numa.iter().position(|n| n.cpus.contains(&index))?;
+        Some(node as u32)
+    }
+
+    /// Splits the guest RAM into the ranges of each NUMA node. Nodes take
+    /// the RAM below `RAM_32_SIZE` first, in the order of their indexes.
+    pub fn numa_mem_ranges(&self) -> Vec<NumaMemRange> {
+        let mut ranges = vec![];
+        let mut offset = 0;
+        for (node, config) in self.numa.iter().enumerate() {
+            let end = offset + config.size;
+            let mut push = |start: u64, end: u64, base: u64| {
+                if start < end {
+                    ranges.push(NumaMemRange {
+                        node: node as u32,
+                        start: base + start,
+                        size: end - start,
+                    });
+                }
+            };
+            push(offset, min(end, RAM_32_SIZE), RAM_32_START);
+            let high_start = max(offset, RAM_32_SIZE);
+            push(high_start, end, MEM_64_START - RAM_32_SIZE);
+            offset = end;
+        }
+        ranges
     }
 }
 
@@ -216,12 +558,16 @@ where
     pub vm: V,
     pub memory: Memory,
     pub vcpus: Arc<RwLock<Vec<VcpuHandle>>>,
+    pub vcpu_exits: Box<[ExitCounters]>,
     pub arch: ArchBoard<V>,
     pub config: BoardConfig,
     pub payload: RwLock<Option<Payload>>,
     pub io_devs: RwLock<Vec<(u16, Arc<dyn MmioDev>)>>,
     pub mmio_devs: RwLock<Vec<(u64, Arc<dyn MmioDev>)>>,
     pub pci_bus: PciBus,
+    pub iommu: Mutex<Option<IommuTopology>>,
+    #[cfg(target_arch = "x86_64")]
+    pub pci_hotplug: Arc<PciHotplug<V::MsiSender>>,
     #[cfg(target_arch = "x86_64")]
     pub fw_cfg: Mutex<Option<Arc<Mutex<FwCfg>>>>,
     #[cfg(target_os = "linux")]
@@ -231,6 +577,7 @@ where
 
     mp_sync: Mutex<MpSync>,
     cond_var: Condvar,
+    snapshot: Mutex<Option<SnapshotData>>,
 }
 
 impl<V> Board<V>
@@ -249,17 +596,28 @@ where
         let mut vm = hv.create_vm(&vm_config)?;
         let vm_memory = Arc::new(vm.create_vm_memory()?);
         let arch = ArchBoard::new(hv, &vm, &config)?;
+        let pci_bus = PciBus::new();
+        #[cfg(target_arch = "x86_64")]
+        let pci_hotplug = Arc::new(PciHotplug::new(
+            pci_bus.segment.clone(),
+            arch.io_apic.clone(),
+        ));
 
+        let vcpu_exits = (0..config.cpu.count).map(|_| ExitCounters::default());
         let board = Board {
             vm,
             memory: Memory::new(vm_memory.clone()),
+            vcpu_exits: vcpu_exits.collect(),
             arch,
             config,
             payload: RwLock::new(None),
             vcpus: Arc::new(RwLock::new(Vec::new())),
             io_devs: RwLock::new(Vec::new()),
             mmio_devs: RwLock::new(Vec::new()),
-            pci_bus: PciBus::new(),
+            pci_bus,
+            iommu: Mutex::new(None),
+            #[cfg(target_arch = "x86_64")]
+            pci_hotplug,
             #[cfg(target_arch = "x86_64")]
             fw_cfg: Mutex::new(None),
             #[cfg(target_os = "linux")]
@@ -271,8 +629,12 @@ where
                 state: BoardState::Paused,
                 count: 0,
                 fatal: false,
+                booted: false,
+                vcpu_states: None,
+                debug: DebugSync::default(),
             }),
             cond_var: Condvar::new(),
+            snapshot: Mutex::new(None),
         };
 
         board.coco_init(vm_memory)?;
@@ -295,10 +657,33 @@ where
             }
             .fail();
         }
+        self.resume_devices()?;
         self.cond_var.notify_all();
         Ok(())
     }
 
+    /// Stops PCI devices from accessing guest memory while the board is
+    /// paused. Devices that cannot pause are skipped.
+    fn pause_devices(&self) -> Result<()> {
+        for (_, dev) in self.pci_bus.segment.devices() {
+            match dev.pause() {
+                Ok(()) | Err(device::Error::NotPausable { .. }) => {}
+                Err(e) => return Err(e).context(error::PauseDevice { name: dev.name() }),
+            }
+        }
+        Ok(())
+    }
+
+    fn resume_devices(&self) -> Result<()> {
+        for (_, dev) in self.pci_bus.segment.devices() {
+            match dev.resume() {
+                Ok(()) | Err(device::Error::NotPausable { .. }) => {}
+                Err(e) => return Err(e).context(error::PauseDevice { name: dev.name() }),
+            }
+        }
+        Ok(())
+    }
+
     pub fn pause(&self) -> Result<()> {
         let vcpus = self.vcpus.read();
         let mut mp_sync = self.mp_sync.lock();
@@ -311,6 +696,44 @@ where
         }
         mp_sync.state = BoardState::Paused;
         self.stop_other_vcpus(None, &vcpus)?;
+        self.pause_devices()
+    }
+
+    pub fn state(&self) -> BoardState {
+        self.mp_sync.lock().state
+    }
+
+    /// Stops all VCPUs and powers off the board.
+    pub fn shutdown(&self) -> Result<()> {
+        self.stop_with(BoardState::Shutdown)
+    }
+
+    /// Stops all VCPUs and restarts the board from the payload.
+    pub fn reboot(&self) -> Result<()> {
+        self.stop_with(BoardState::RebootPending)
+    }
+
+    fn stop_with(&self, state: BoardState) -> Result<()> {
+        let vcpus = self.vcpus.read();
+        let mut mp_sync = self.mp_sync.lock();
+        match mp_sync.state {
+            BoardState::Running => {
+                mp_sync.state = state;
+                self.stop_other_vcpus(None, &vcpus)?;
+            }
+            BoardState::Paused => {
+                mp_sync.state = state;
+                self.resume_devices()?;
+                self.cond_var.notify_all();
+            }
+            current => {
+                return error::UnexpectedState {
+                    state: current,
+                    want: BoardState::Running,
+                }
+                .fail();
+            }
+        }
         Ok(())
     }
 
@@ -375,10 +798,22 @@ where
         Ok(())
     }
 
+    /// Returns the entry that brings a VCPU to the state of the board.
+    fn pending_entry(&self) -> VmEntry {
+        let mp_sync = self.mp_sync.lock();
+        match mp_sync.state {
+            BoardState::Shutdown => VmEntry::Shutdown,
+            BoardState::RebootPending => VmEntry::Reboot,
+            BoardState::Paused => VmEntry::Pause,
+            BoardState::Running => VmEntry::None,
+        }
+    }
+
     fn vcpu_loop(&self, vcpu: &mut <V as Vm>::Vcpu, index: u16) -> Result<BoardState> {
-        let mut vm_entry = VmEntry::None;
+        let mut vm_entry = self.pending_entry();
         loop {
             let vm_exit = vcpu.run(vm_entry).context(error::RunVcpu { index })?;
+            self.vcpu_exits[index as usize].count(&vm_exit);
             vm_entry = match vm_exit {
                 #[cfg(target_arch = "x86_64")]
                 VmExit::Io { port, write, size } => self.memory.handle_io(port, write, size)?,
@@ -386,14 +821,10 @@ where
                 VmExit::Shutdown => break Ok(BoardState::Shutdown),
                 VmExit::Reboot => break Ok(BoardState::RebootPending),
                 VmExit::Paused => break Ok(BoardState::Paused),
-                VmExit::Interrupted => {
-                    let mp_sync = self.mp_sync.lock();
-                    match mp_sync.state {
-                        BoardState::Shutdown => VmEntry::Shutdown,
-                        BoardState::RebootPending => VmEntry::Reboot,
-                        BoardState::Paused => VmEntry::Pause,
-                        BoardState::Running => VmEntry::None,
-                    }
+                VmExit::Interrupted => self.pending_entry(),
+                VmExit::Debug => {
+                    self.debug_stop(index);
+                    break Ok(BoardState::Paused);
                 }
                 VmExit::ConvertMemory { gpa, size, private } => {
                     self.memory.mark_private_memory(gpa, size, private)?;
@@ -434,8 +865,10 @@ where
 
     fn boot_init_sync(&self, index: u16, vcpu: &mut V::Vcpu) -> Result<()> {
         let vcpus = self.vcpus.read();
+        let restoring = self.has_snapshot();
         if index == 0 {
             self.create_ram()?;
+            self.create_hotplug_ram()?;
             for (port, dev) in self.io_devs.read().iter() {
                 self.memory.add_io_dev(*port, dev.clone())?;
             }
@@ -443,13 +876,39 @@ where
                 self.memory.add_mmio_dev(*addr, dev.clone())?;
             }
             self.add_pci_devs()?;
-            let init_state = self.load_payload(vcpu)?;
-            self.init_boot_vcpu(vcpu, &init_state)?;
-            self.create_firmware_data(&init_state)?;
+            if !restoring || self.payload.read().is_some() {
+                let init_state = self.load_payload(vcpu)?;
+                self.init_boot_vcpu(vcpu, &init_state)?;
+                self.create_firmware_data(&init_state)?;
+            } else {
+                self.create_firmware_data(&InitState::default())?;
+            }
+            self.restore_snapshot()?;
         }
         self.init_ap(index, vcpu, &vcpus)?;
         self.coco_finalize(index, &vcpus)?;
-        self.sync_vcpus(&vcpus)
+        if restoring {
+            // VCPU states of an incoming migration arrive at VCPU-0.
+            self.sync_vcpus(&vcpus)?;
+            if let Some(data) = self.take_vcpu_snapshot(index) {
+                self.restore_vcpu_state(vcpu, &data)?;
+            }
+        }
+        if index == 0 {
+            self.halt_on_request(&mut self.mp_sync.lock());
+        }
+        self.sync_vcpus(&vcpus)?;
+        if index == 0 {
+            // A later reboot starts from the payload again.
+            self.clear_snapshot();
+            self.mp_sync.lock().booted = true;
+            if restoring {
+                // Restored devices start after VCPUs so that no interrupt
+                // is lost.
+                self.resume_devices()?;
+            }
+        }
+        Ok(())
     }
 
     fn stop_other_vcpus(&self, current: Option<u16>, vcpus: &VcpuGuard) -> Result<()> {
@@ -472,6 +931,12 @@ where
     }
 
     fn run_vcpu_inner(&self, index: u16, event_tx: &Sender<u16>) -> Result<(), Error> {
+        #[cfg(target_os = "linux")]
+        {
+            let config = self.config.cpu.thread_config(index);
+            let thread = unsafe { pthread_self() };
+            config.apply(thread).context(error::VcpuThread { index })?;
+        }
         let mut vcpu = self.create_vcpu(index)?;
         self.notify_vmm(index, event_tx)?;
         self.init_vcpu(index, &mut vcpu)?;
@@ -507,15 +972,21 @@ where
                 loop {
                     match mp_sync.state {
                         BoardState::Running => break,
-                        BoardState::Paused => self.cond_var.wait(&mut mp_sync),
+                        BoardState::Paused => {
+                            self.save_vcpu_on_request(index, &vcpu, &mut mp_sync);
+                            self.run_vcpu_tasks(index, &mut vcpu, &mut mp_sync);
+                            self.cond_var.wait(&mut mp_sync)
+                        }
                         BoardState::RebootPending | BoardState::Shutdown => break 'pause request,
                     }
                 }
             };
 
             if index == 0 {
+                self.mp_sync.lock().booted = false;
                 self.pci_bus.segment.reset().context(error::ResetPci)?;
                 self.memory.reset()?;
+                self.arch_reset()?;
             }
             self.reset_vcpu(index, &mut vcpu)?;
 
@@ -547,14 +1018,45 @@ where
         log::warn!("VCPU-{index} reported error {ret:?}, unblocking other VCPUs...");
         let mut mp_sync = self.mp_sync.lock();
         mp_sync.fatal = true;
-        if mp_sync.count > 0 {
+        if mp_sync.count > 0 || mp_sync.vcpu_states.is_some() || mp_sync.debug.has_tasks() {
             self.cond_var.notify_all();
         }
         ret
     }
 
-    fn create_ram_pages(
+    /// Reserves the memory hotplug region. Its pages are inaccessible until
+    /// the guest plugs them through virtio-mem.
+    fn create_hotplug_ram(&self) -> Result<()> {
+        let size = self.config.mem.hotplug_size;
+        if size == 0 {
+            return Ok(());
+        }
+        let start = self.config.mem_hotplug_start();
+        let pages = self.map_ram_pages(start, size, c"ram-hotplug")?;
+        let region = MemRegion::with_ram(pages, MemRegionType::Hotplug);
+        self.memory.add_region(start, Arc::new(region))?;
+        let ram = self.memory.ram_bus();
+        ram.lock_layout().set_accessible(start, size, false)?;
+        Ok(())
+    }
+
+    /// Creates the RAM pages at guest physical address `gpa`, binding the
+    /// parts in NUMA nodes to their host nodes.
+    fn create_ram_pages(&self, gpa: u64, size: u64, name: &CStr) -> Result<ArcMemPages> {
+        let pages = self.map_ram_pages(gpa, size, name)?;
+        #[cfg(target_os = "linux")]
+        if self.config.mem.prealloc {
+            pages.populate()?;
+        }
+        if self.config.mem.mlock {
+            pages.mlock()?;
+        }
+        Ok(pages)
+    }
+
+    fn map_ram_pages(
         &self,
+        #[cfg_attr(not(target_os = "linux"), allow(unused_variables))] gpa: u64,
         size: u64,
         #[cfg_attr(not(target_os = "linux"), allow(unused_variables))] name: &CStr,
     ) -> Result<ArcMemPages> {
@@ -563,12 +1065,56 @@ where
         } else {
             Some(MAP_PRIVATE)
         };
-        let pages = match self.config.mem.backend {
+        let pages = match &self.config.mem.backend {
+            #[cfg(target_os = "linux")]
+            MemBackend::Memfd => ArcMemPages::from_memfd(name, size as usize, None, None),
             #[cfg(target_os = "linux")]
-            MemBackend::Memfd => ArcMemPages::from_memfd(name, size as usize, None),
+            MemBackend::Hugetlb(param) => {
+                let page_size = match param.page_size {
+                    HugePageSize::Size2M => MFD_HUGE_2MB,
+                    HugePageSize::Size1G => MFD_HUGE_1GB,
+                };
+                let flags = Some(MFD_HUGETLB | page_size);
+                ArcMemPages::from_memfd(name, size as usize, None, flags)
+            }
+            MemBackend::File(param) => {
+                // The file holds RAM below RAM_32_SIZE, followed by RAM above
+                // MEM_64_START.
+                let offset = if gpa >= MEM_64_START {
+                    gpa - MEM_64_START + RAM_32_SIZE
+                } else {
+                    gpa - RAM_32_START
+                };
+                let path = param.path.as_ref();
+                let file = OpenOptions::new()
+                    .read(true)
+                    .write(true)
+                    .create(true)
+                    .truncate(false)
+                    .open(path)
+                    .context(error::MemFile { path })?;
+                let meta = file.metadata().context(error::MemFile { path })?;
+                if meta.is_file() && meta.len() < offset + size {
+                    file.set_len(offset + size)
+                        .context(error::MemFile { path })?;
+                }
+                let prot = PROT_READ | PROT_WRITE;
+                ArcMemPages::from_file(file, offset as i64, size as usize, prot)
+            }
             MemBackend::Anonymous => ArcMemPages::from_anonymous(size as usize, None, mmap_flag),
         }?;
         #[cfg(target_os = "linux")]
+        for range in self.config.numa_mem_ranges() {
+            let node = &self.config.numa[range.node as usize];
+            let Some(host_node) = node.host_node else {
+                continue;
+            };
+            if range.start < gpa || range.start >= gpa + size {
+                continue;
+            }
+            pages.mbind(range.start - gpa, range.size, host_node)?;
+        }
+        #[cfg(target_os = "linux")]
         if self.config.mem.transparent_hugepage {
             pages.madvise_hugepage()?;
         }
@@ -24,7 +24,7 @@ use crate::arch::layout::{
     RAM_32_SIZE, RAM_32_START,
 };
 use crate::arch::reg::MpidrEl1;
-use crate::board::{Board, BoardConfig, CpuTopology, PCIE_MMIO_64_SIZE, Result, VcpuGuard};
+use crate::board::{Board, BoardConfig, CpuTopology, PCIE_MMIO_64_SIZE, Result, VcpuGuard, error};
 use crate::firmware::dt::{DeviceTree, Node, PropVal};
 use crate::hv::{GicV2, GicV2m, GicV3, Hypervisor, Its, Vcpu, Vm};
 use crate::loader::{Executable, InitState, Payload};
@@ -131,12 +131,32 @@ where
         Ok(())
     }
 
+    pub fn save_vcpu_state(&self, _vcpu: &V::Vcpu) -> Result<Vec<u8>> {
+        error::SnapshotUnsupported { msg: "aarch64" }.fail()
+    }
+
+    pub fn restore_vcpu_state(&self, _vcpu: &mut V::Vcpu, _data: &[u8]) -> Result<()> {
+        error::SnapshotUnsupported { msg: "aarch64" }.fail()
+    }
+
+    pub fn press_power_button(&self) -> Result<()> {
+        error::NoPowerButton.fail()
+    }
+
+    pub fn get_guest_clock(&self) -> Result<u64> {
+        error::SnapshotUnsupported { msg: "aarch64" }.fail()
+    }
+
+    pub fn set_guest_clock(&self, _ns: u64) -> Result<()> {
+        error::SnapshotUnsupported { msg: "aarch64" }.fail()
+    }
+
     pub fn create_ram(&self) -> Result<()> {
         let mem_size = self.config.mem.size;
         let memory = &self.memory;
 
         let low_mem_size = std::cmp::min(mem_size, RAM_32_SIZE);
-        let pages_low = self.create_ram_pages(low_mem_size, c"ram-low")?;
+        let pages_low = self.create_ram_pages(RAM_32_START, low_mem_size, c"ram-low")?;
         memory.add_region(
             RAM_32_START,
             Arc::new(MemRegion::with_ram(pages_low, MemRegionType::Ram)),
@@ -144,7 +164,7 @@ where
 
         let high_mem_size = mem_size.saturating_sub(RAM_32_SIZE);
         if high_mem_size > 0 {
-            let pages_high = self.create_ram_pages(high_mem_size, c"ram-high")?;
+            let pages_high = self.create_ram_pages(MEM_64_START, high_mem_size, c"ram-high")?;
             memory.add_region(
                 MEM_64_START,
                 Arc::new(MemRegion::with_ram(pages_high, MemRegionType::Ram)),
@@ -175,6 +195,10 @@ where
         Ok(())
     }
 
+    pub fn arch_reset(&self) -> Result<()> {
+        Ok(())
+    }
+
     fn create_chosen_node(&self, init_state: &InitState, root: &mut Node) {
         let payload = self.payload.read();
         let Some(payload) = payload.as_ref() else {
@@ -204,6 +228,21 @@ where
     }
 
     pub fn create_memory_node(&self, root: &mut Node) {
+        if !self.config.numa.is_empty() {
+            for range in self.config.numa_mem_ranges() {
+                let start = range.start;
+                let node = Node {
+                    props: HashMap::from([
+                        ("device_type", PropVal::Str("memory")),
+                        ("reg", PropVal::U64List(vec![start, range.size])),
+                        ("numa-node-id", PropVal::U32(range.node)),
+                    ]),
+                    nodes: Vec::new(),
+                };
+                root.nodes.push((format!("memory@{start:x}"), node));
+            }
+            return;
+        }
         let regions = self.memory.mem_region_entries();
         for (start, region) in regions {
             if region.type_ != MemRegionType::Ram {
@@ -265,19 +304,21 @@ where
         let cpus_nodes = (0..(self.config.cpu.count))
             .map(|index| {
                 let mpidr = self.encode_cpu_identity(index);
-                (
-                    format!("cpu@{mpidr:x}"),
-                    Node {
-                        props: HashMap::from([
-                            ("device_type", PropVal::Str("cpu")),
-                            ("compatible", PropVal::Str("arm,arm-v8")),
-                            ("enable-method", PropVal::Str("psci")),
-                            ("reg", PropVal::U64(mpidr)),
-                            ("phandle", PropVal::PHandle(PHANDLE_CPU | index as u32)),
-                        ]),
-                        nodes: Vec::new(),
-                    },
-                )
+                let mut props = HashMap::from([
+                    ("device_type", PropVal::Str("cpu")),
+                    ("compatible", PropVal::Str("arm,arm-v8")),
+                    ("enable-method", PropVal::Str("psci")),
+                    ("reg", PropVal::U64(mpidr)),
+                    ("phandle", PropVal::PHandle(PHANDLE_CPU | index as u32)),
+                ]);
+                if let Some(node_id) = self.config.numa_node_of_cpu(index) {
+                    props.insert("numa-node-id", PropVal::U32(node_id));
+                }
+                let node = Node {
+                    props,
+                    nodes: Vec::new(),
+                };
+                (format!("cpu@{mpidr:x}"), node)
             })
             .chain([("cpu-map".to_owned(), cpu_map_node)])
             .collect();
@@ -292,6 +333,23 @@ where
         root.nodes.push(("cpus".to_owned(), cpus));
     }
 
+    fn create_distance_map_node(&self, root: &mut Node) {
+        let mut matrix = vec![];
+        for (from, node) in self.config.numa.iter().enumerate() {
+            for (to, distance) in node.distances.iter().enumerate() {
+                matrix.extend([from as u32, to as u32, *distance as u32]);
+            }
+        }
+        let node = Node {
+            props: HashMap::from([
+                ("compatible", PropVal::Str("numa-distance-map-v1")),
+                ("distance-matrix", PropVal::U32List(matrix)),
+            ]),
+            nodes: Vec::new(),
+        };
+        root.nodes.push(("distance-map".to_owned(), node));
+    }
+
     fn create_clock_node(&self, root: &mut Node) {
         let node = Node {
             props: HashMap::from([
@@ -464,11 +522,12 @@ where
             return;
         };
         let pcie_mmio_64_start = self.config.pcie_mmio_64_start();
+        let iommu = self.iommu.lock().clone();
         let prefetchable = 1 << 30;
         let io = 0b01 << 24;
         let mem_32 = 0b10 << 24;
         let mem_64 = 0b11 << 24;
-        let node = Node {
+        let mut node = Node {
             props: HashMap::from([
                 ("compatible", PropVal::Str("pci-host-ecam-generic")),
                 ("device_type", PropVal::Str("pci")),
@@ -519,6 +578,33 @@ where
             ]),
             nodes: Vec::new(),
         };
+        if let Some(iommu) = iommu {
+            // Identity map from RID (BDF) to the endpoint ID of each device
+            // behind the virtio-iommu device.
+            // Documentation/devicetree/bindings/pci/pci-iommu.txt
+            let mut iommu_map = vec![];
+            for (start, end) in iommu.endpoint_ranges() {
+                let (start, end) = (u32::from(start), u32::from(end));
+                iommu_map.extend([start, PHANDLE_IOMMU, start, end - start + 1]);
+            }
+            node.props.insert("iommu-map", PropVal::U32List(iommu_map));
+            // Documentation/devicetree/bindings/virtio/iommu.txt
+            let bdf = iommu.bdf;
+            let iommu_node = Node {
+                props: HashMap::from([
+                    ("compatible", PropVal::Str("virtio,pci-iommu")),
+                    (
+                        "reg",
+                        PropVal::U32List(vec![u32::from(bdf.0) << 8, 0, 0, 0, 0]),
+                    ),
+                    ("#iommu-cells", PropVal::U32(1)),
+                    ("phandle", PropVal::PHandle(PHANDLE_IOMMU)),
+                ]),
+                nodes: Vec::new(),
+            };
+            let name = format!("iommu@{:x},{:x}", bdf.dev(), bdf.func());
+            node.nodes.push((name, iommu_node));
+        }
         root.nodes
             .push((format!("pci@{PCIE_CONFIG_START:x}"), node));
     }
@@ -539,6 +625,9 @@ where
         self.create_pl031_node(root);
         self.create_memory_node(root);
         self.create_cpu_nodes(root);
+        if !self.config.numa.is_empty() {
+            self.create_distance_map_node(root);
+        }
         self.create_gic_node(root);
         if self.arch.msi.is_some() {
             self.create_pci_bridge_node(root);
@@ -558,6 +647,7 @@ where
 const PHANDLE_GIC: u32 = 1;
 const PHANDLE_CLOCK: u32 = 2;
 const PHANDLE_MSI: u32 = 3;
+const PHANDLE_IOMMU: u32 = 4;
 const PHANDLE_CPU: u32 = 1 << 31;
 
 #[cfg(test)]
@@ -15,13 +15,26 @@
 use assert_matches::assert_matches;
 use rstest::rstest;
 
-use crate::board::{CpuConfig, CpuTopology, Error};
+use crate::arch::layout::{MEM_64_START, RAM_32_SIZE, RAM_32_START};
+#[cfg(target_arch = "x86_64")]
+use crate::arch::sev::SevPolicy;
+use crate::board::{
+    BoardConfig, CpuConfig, CpuTopology, Error, IommuTopology, MemConfig, NumaMemRange,
+    NumaNodeConfig,
+};
+#[cfg(target_arch = "x86_64")]
+use crate::hv::Coco;
+#[cfg(target_os = "linux")]
+use crate::mem::{HugePageSize, HugetlbParam, MemBackend};
+use crate::pci::Bdf;
+#[cfg(target_os = "linux")]
+use crate::utils::thread::SchedPolicy;
 
 #[test]
 fn test_cpu_topology_fixup() {
     let mut empty = CpuConfig {
         count: 2,
-        topology: CpuTopology::default(),
+        ..Default::default()
     };
     empty.fixup().unwrap();
     assert_matches!(
@@ -32,7 +45,8 @@ fn test_cpu_topology_fixup() {
                 smt: false,
                 cores: 2,
                 sockets: 1
-            }
+            },
+            ..
         }
     );
 
@@ -43,6 +57,7 @@ fn test_cpu_topology_fixup() {
             cores: 2,
             sockets: 1,
         },
+        ..Default::default()
     };
     assert_matches!(invalid.fixup(), Err(Error::InvalidCpuTopology { .. }))
 }
@@ -66,3 +81,214 @@ fn test_cpu_topology_encode_decode(
     let (socket_id, core_id, thread_id) = ids;
     assert_eq!(topology.decode(socket_id, core_id, thread_id), index);
 }
+
+fn numa_config(sizes: &[u64], cpus: &[&[u16]]) -> BoardConfig {
+    BoardConfig {
+        mem: MemConfig {
+            size: sizes.iter().sum(),
+            ..Default::default()
+        },
+        cpu: CpuConfig {
+            count: cpus.iter().map(|c| c.len() as u16).sum(),
+            ..Default::default()
+        },
+        numa: sizes
+            .iter()
+            .zip(cpus)
+            .map(|(&size, cpus)| NumaNodeConfig {
+                size,
+                cpus: cpus.to_vec(),
+                ..Default::default()
+            })
+            .collect(),
+        ..Default::default()
+    }
+}
+
+#[test]
+fn test_numa_fixup() {
+    let mut config = numa_config(&[1 << 30, 1 << 30], &[&[0, 2], &[1, 3]]);
+    config.config_fixup().unwrap();
+    assert_eq!(config.numa[0].distances, [10, 20]);
+    assert_eq!(config.numa[1].distances, [20, 10]);
+    assert_eq!(config.numa_node_of_cpu(2), Some(0));
+    assert_eq!(config.numa_node_of_cpu(3), Some(1));
+    assert_eq!(config.numa_node_of_cpu(4), None);
+}
+
+#[rstest]
+#[case(2, vec![1 << 30, 1 << 30], vec![vec![0], vec![0]])]
+#[case(3, vec![1 << 30, 1 << 30], vec![vec![0], vec![1]])]
+#[case(2, vec![1 << 30, 0], vec![vec![0], vec![1]])]
+#[case(2, vec![(1 << 30) + 0x800, 0x800], vec![vec![0], vec![1]])]
+fn test_numa_fixup_invalid(
+    #[case] count: u16,
+    #[case] sizes: Vec<u64>,
+    #[case] cpus: Vec<Vec<u16>>,
+) {
+    let cpus: Vec<&[u16]> = cpus.iter().map(|c| c.as_slice()).collect();
+    let mut config = numa_config(&sizes, &cpus);
+    config.cpu.count = count;
+    assert_matches!(config.config_fixup(), Err(Error::InvalidNuma { .. }));
+}
+
+#[test]
+fn test_numa_fixup_invalid_distances() {
+    let mut config = numa_config(&[1 << 30, 1 << 30], &[&[0], &[1]]);
+    config.numa[0].distances = vec![10, 20, 20];
+    assert_matches!(config.config_fixup(), Err(Error::InvalidNuma { .. }));
+
+    config.numa[0].distances = vec![12, 20];
+    assert_matches!(config.config_fixup(), Err(Error::InvalidNuma { .. }));
+
+    config.mem.size += 1 << 30;
+    config.numa[0].distances = vec![];
+    assert_matches!(config.config_fixup(), Err(Error::InvalidNuma { .. }));
+}
+
+#[test]
+fn test_numa_mem_ranges() {
+    let size_0 = RAM_32_SIZE + (1 << 30);
+    let size_1 = 1 << 30;
+    let config = numa_config(&[size_0, size_1], &[&[0], &[1]]);
+    assert_eq!(
+        config.numa_mem_ranges(),
+        [
+            NumaMemRange {
+                node: 0,
+                start: RAM_32_START,
+                size: RAM_32_SIZE,
+            },
+            NumaMemRange {
+                node: 0,
+                start: MEM_64_START,
+                size: 1 << 30,
+            },
+            NumaMemRange {
+                node: 1,
+                start: MEM_64_START + (1 << 30),
+                size: 1 << 30,
+            },
+        ]
+    );
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn test_mem_size_fixup() {
+    let mut config = BoardConfig {
+        mem: MemConfig {
+            size: 3 << 20,
+            backend: MemBackend::Hugetlb(HugetlbParam {
+                page_size: HugePageSize::Size2M,
+            }),
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    assert_matches!(
+        config.config_fixup(),
+        Err(Error::InvalidMemSize {
+            size: 0x30_0000,
+            align: 0x20_0000,
+            ..
+        })
+    );
+    config.mem.size = 4 << 20;
+    config.config_fixup().unwrap();
+}
+
+#[rstest]
+#[case(Bdf::new(0, 2, 0), &[], &[(0, 0x0f), (0x11, 0xffff)])]
+#[case(Bdf::new(0, 0, 0), &[Bdf::new(0, 1, 0)], &[(1, 0x07), (0x09, 0xffff)])]
+#[case(Bdf::new(0, 3, 0), &[Bdf::new(0, 1, 0), Bdf::new(0, 2, 0)], &[(0, 0x07), (0x09, 0x0f), (0x11, 0x17), (0x19, 0xffff)])]
+#[case(Bdf::new(0xff, 0x1f, 7), &[Bdf::new(0, 0, 0)], &[(1, 0xfffe)])]
+fn test_iommu_endpoint_ranges(
+    #[case] bdf: Bdf,
+    #[case] bypass: &[Bdf],
+    #[case] ranges: &[(u16, u16)],
+) {
+    let topology = IommuTopology {
+        bdf,
+        bypass: bypass.iter().copied().collect(),
+    };
+    assert_eq!(topology.endpoint_ranges(), ranges);
+}
+
+#[test]
+fn test_mem_hotplug_layout() {
+    let mut config = BoardConfig {
+        mem: MemConfig {
+            size: RAM_32_SIZE + (1 << 30) + (2 << 20),
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    assert_eq!(config.mem_hotplug_start(), MEM_64_START + (2 << 30));
+    assert_eq!(config.pcie_mmio_64_start(), 8 << 30);
+
+    config.mem.hotplug_size = 4 << 30;
+    config.config_fixup().unwrap();
+    assert_eq!(config.mem_hotplug_start(), MEM_64_START + (2 << 30));
+    assert_eq!(config.pcie_mmio_64_start(), 16 << 30);
+
+    config.mem.hotplug_size = (4 << 30) + 0x800;
+    assert_matches!(
+        config.config_fixup(),
+        Err(Error::InvalidMemSize { align: 0x1000, .. })
+    );
+}
+
+#[test]
+#[cfg(target_arch = "x86_64")]
+fn test_mem_hotplug_coco() {
+    let mut config = BoardConfig {
+        mem: MemConfig {
+            hotplug_size: 1 << 30,
+            ..Default::default()
+        },
+        coco: Some(Coco::AmdSev {
+            policy: SevPolicy(0x1),
+        }),
+        ..Default::default()
+    };
+    assert_matches!(config.config_fixup(), Err(Error::MemHotplugCoco { .. }));
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn test_cpu_affinity_fixup() {
+    let mut config = CpuConfig {
+        count: 2,
+        affinity: vec![vec![0]],
+        ..Default::default()
+    };
+    assert_matches!(config.fixup(), Err(Error::InvalidCpuAffinity { .. }));
+
+    config.affinity.push(vec![1, 2]);
+    config.fixup().unwrap();
+    assert_eq!(config.thread_config(1).cpus, [1, 2]);
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn test_sched_priority_fixup() {
+    let mut config = BoardConfig::default();
+    config.cpu.sched_policy = SchedPolicy::Fifo;
+    assert_matches!(
+        config.config_fixup(),
+        Err(Error::InvalidSchedPriority { priority: 0, .. })
+    );
+    config.cpu.sched_priority = 10;
+    config.config_fixup().unwrap();
+
+    config.worker.sched_priority = 10;
+    assert_matches!(
+        config.config_fixup(),
+        Err(Error::InvalidSchedPriority {
+            policy: SchedPolicy::Other,
+            priority: 10,
+            ..
+        })
+    );
+}
@@ -17,6 +17,7 @@ mod tdx;
 
 use std::arch::x86_64::{__cpuid, CpuidResult};
 use std::collections::HashMap;
+use std::iter::zip;
 use std::mem::{offset_of, size_of, size_of_val};
 use std::path::Path;
 use std::sync::Arc;
@@ -24,28 +25,234 @@ use std::sync::atomic::{AtomicU32, AtomicU64};
 
 use parking_lot::Mutex;
 use snafu::ResultExt;
-use zerocopy::{FromZeros, IntoBytes};
+use zerocopy::{FromBytes, FromZeros, Immutable, IntoBytes};
 
-use crate::arch::cpuid::{Cpuid1Ecx, CpuidIn};
+use crate::align_up;
+use crate::arch::cpuid::{
+    CpuFeature, CpuModel, Cpuid1Ecx, Cpuid7Index0Ebx, Cpuid7Index0Ecx, Cpuid7Index0Edx, CpuidIn,
+    XsaveComponent,
+};
 use crate::arch::layout::{
-    BIOS_DATA_END, EBDA_END, EBDA_START, IOAPIC_START, MEM_64_START, PORT_ACPI_RESET,
-    PORT_ACPI_SLEEP_CONTROL, PORT_ACPI_TIMER, RAM_32_SIZE,
+    BIOS_DATA_END, EBDA_END, EBDA_START, IOAPIC_START, MEM_64_START,
+    PCIE_MMIO_32_NON_PREFETCHABLE_END, PCIE_MMIO_32_NON_PREFETCHABLE_START,
+    PCIE_MMIO_32_PREFETCHABLE_END, PCIE_MMIO_32_PREFETCHABLE_START, PORT_ACPI_GED, PORT_ACPI_RESET,
+    PORT_ACPI_SLEEP_CONTROL, PORT_ACPI_TIMER, PORT_PCI_ADDRESS, PORT_PCI_HOTPLUG, RAM_32_SIZE,
+    RAM_32_START,
+};
+use crate::arch::msr::{
+    IA32_CSTAR, IA32_FMASK, IA32_KERNEL_GS_BASE, IA32_LSTAR, IA32_MISC_ENABLE, IA32_PAT, IA32_STAR,
+    IA32_SYSENTER_CS, IA32_SYSENTER_EIP, IA32_SYSENTER_ESP, IA32_TSC, IA32_TSC_AUX,
+    IA32_TSC_DEADLINE, MSR_KVM_ASYNC_PF_EN, MSR_KVM_ASYNC_PF_INT, MSR_KVM_POLL_CONTROL,
+    MSR_KVM_PV_EOI_EN, MSR_KVM_STEAL_TIME, MSR_KVM_SYSTEM_TIME_NEW, MSR_KVM_WALL_CLOCK_NEW,
+    MiscEnable,
+};
+use crate::arch::reg::{DtReg, DtRegVal, Reg, SReg, SegAccess, SegReg, SegRegVal};
+use crate::board::{
+    Board, BoardConfig, CpuConfig, CpuTopology, PCIE_MMIO_64_SIZE, Result, VcpuGuard, error,
 };
-use crate::arch::msr::{IA32_MISC_ENABLE, MiscEnable};
-use crate::board::{Board, BoardConfig, CpuTopology, PCIE_MMIO_64_SIZE, Result, VcpuGuard, error};
 use crate::device::ioapic::IoApic;
-use crate::firmware::acpi::bindings::{
-    AcpiTableFadt, AcpiTableHeader, AcpiTableRsdp, AcpiTableXsdt3,
+use crate::device::serial::ComPort;
+use crate::firmware::acpi::aml::{
+    AddressSpace, And, Arg, Buffer, Device, EisaId, Field, FieldAccess, If, Interrupt, Io,
+    IrqNoFlags, LEqual, Local, MemAttr, Method, MethodCall, Name, NameString, Notify, OpRegion,
+    Package, RegionSpace, ResourceTemplate, Return, Store, Uuid,
+};
+use crate::firmware::acpi::bindings::{AcpiTableFadt, AcpiTableHeader, AcpiTableRsdp};
+use crate::firmware::acpi::reg::{
+    AcpiGed, AcpiPmTimer, FadtReset, FadtSleepControl, GED_EVENT_POWER_BUTTON,
 };
-use crate::firmware::acpi::reg::{AcpiPmTimer, FadtReset, FadtSleepControl};
 use crate::firmware::acpi::{
-    AcpiTable, create_fadt, create_madt, create_mcfg, create_rsdp, create_xsdt,
+    AcpiTable, create_dsdt, create_fadt, create_madt, create_mcfg, create_rsdp, create_slit,
+    create_srat, create_viot, create_xsdt,
 };
-use crate::hv::{Coco, Hypervisor, Vcpu, Vm};
+use crate::hv::{Coco, Hypervisor, LapicState, MpState, Vcpu, VcpuEvents, Vm, XsaveState};
 use crate::loader::{Executable, InitState, Payload, firmware};
 use crate::mem::mapped::ArcMemPages;
 use crate::mem::{MemRange, MemRegion, MemRegionEntry, MemRegionType};
-use crate::utils::wrapping_sum;
+use crate::pci::hotplug::{HOTPLUG_B0EJ, HOTPLUG_IRQ, HOTPLUG_SLOTS};
+
+const SNAPSHOT_REGS: [Reg; 18] = [
+    Reg::Rax,
+    Reg::Rbx,
+    Reg::Rcx,
+    Reg::Rdx,
+    Reg::Rsi,
+    Reg::Rdi,
+    Reg::Rsp,
+    Reg::Rbp,
+    Reg::R8,
+    Reg::R9,
+    Reg::R10,
+    Reg::R11,
+    Reg::R12,
+    Reg::R13,
+    Reg::R14,
+    Reg::R15,
+    Reg::Rip,
+    Reg::Rflags,
+];
+
+const SNAPSHOT_SREGS: [SReg; 7] = [
+    SReg::Cr0,
+    SReg::Cr2,
+    SReg::Cr3,
+    SReg::Cr4,
+    SReg::Cr8,
+    SReg::Efer,
+    SReg::ApicBase,
+];
+
+const SNAPSHOT_SEG_REGS: [SegReg; 8] = [
+    SegReg::Cs,
+    SegReg::Ds,
+    SegReg::Es,
+    SegReg::Fs,
+    SegReg::Gs,
+    SegReg::Ss,
+    SegReg::Tr,
+    SegReg::Ldtr,
+];
+
+const SNAPSHOT_DT_REGS: [DtReg; 2] = [DtReg::Gdtr, DtReg::Idtr];
+
+/// MSRs saved in a snapshot, in the order they are restored. The TSC goes
+/// first since the TSC deadline is relative to it.
+const SNAPSHOT_MSRS: [u32; 20] = [
+    IA32_TSC,
+    IA32_SYSENTER_CS,
+    IA32_SYSENTER_ESP,
+    IA32_SYSENTER_EIP,
+    IA32_STAR,
+    IA32_LSTAR,
+    IA32_CSTAR,
+    IA32_FMASK,
+    IA32_KERNEL_GS_BASE,
+    IA32_TSC_AUX,
+    IA32_MISC_ENABLE,
+    IA32_PAT,
+    IA32_TSC_DEADLINE,
+    MSR_KVM_WALL_CLOCK_NEW,
+    MSR_KVM_SYSTEM_TIME_NEW,
+    MSR_KVM_ASYNC_PF_INT,
+    MSR_KVM_ASYNC_PF_EN,
+    MSR_KVM_STEAL_TIME,
+    MSR_KVM_PV_EOI_EN,
+    MSR_KVM_POLL_CONTROL,
+];
+
+#[repr(C)]
+#[derive(Debug, Default, IntoBytes, FromBytes, Immutable)]
+struct SegRegState {
+    base: u64,
+    limit: u32,
+    access: u32,
+    selector: u16,
+    _reserved: [u8; 6],
+}
+
+#[repr(C)]
+#[derive(Debug, Default, IntoBytes, FromBytes, Immutable)]
+struct DtRegState {
+    base: u64,
+    limit: u16,
+    _reserved: [u8; 6],
+}
+
+/// State of a VCPU in a snapshot.
+#[repr(C)]
+#[derive(Debug, IntoBytes, FromBytes, Immutable)]
+struct VcpuState {
+    regs: [u64; SNAPSHOT_REGS.len()],
+    sregs: [u64; SNAPSHOT_SREGS.len()],
+    seg_regs: [SegRegState; SNAPSHOT_SEG_REGS.len()],
+    dt_regs: [DtRegState; SNAPSHOT_DT_REGS.len()],
+    msrs: [u64; SNAPSHOT_MSRS.len()],
+    mp_state: u32,
+    _reserved: u32,
+    lapic: [u8; 1024],
+    /// XCR0, the only extended control register KVM supports.
+    xcr0: u64,
+    xsave: [u32; 1024],
+    events: [u8; 64],
+}
+
+fn get_vcpu_state(vcpu: &impl Vcpu) -> Result<VcpuState> {
+    let mut state = VcpuState::new_zeroed();
+    for (val, reg) in zip(&mut state.regs, SNAPSHOT_REGS) {
+        *val = vcpu.get_reg(reg)?;
+    }
+    for (val, reg) in zip(&mut state.sregs, SNAPSHOT_SREGS) {
+        *val = vcpu.get_sreg(reg)?;
+    }
+    for (val, reg) in zip(&mut state.seg_regs, SNAPSHOT_SEG_REGS) {
+        let seg = vcpu.get_seg_reg(reg)?;
+        val.base = seg.base;
+        val.limit = seg.limit;
+        val.access = seg.access.0;
+        val.selector = seg.selector;
+    }
+    for (val, reg) in zip(&mut state.dt_regs, SNAPSHOT_DT_REGS) {
+        let dt = vcpu.get_dt_reg(reg)?;
+        val.base = dt.base;
+        val.limit = dt.limit;
+    }
+    let mut msrs = SNAPSHOT_MSRS.map(|index| (index, 0));
+    vcpu.get_msrs(&mut msrs)?;
+    for (val, (_, msr)) in zip(&mut state.msrs, msrs) {
+        *val = msr;
+    }
+    state.mp_state = vcpu.get_mp_state()?.raw();
+    state.lapic = vcpu.get_lapic()?.0;
+    for (index, val) in vcpu.get_xcrs()? {
+        if index == 0 {
+            state.xcr0 = val;
+        }
+    }
+    state.xsave = vcpu.get_xsave()?.0;
+    state.events = vcpu.get_vcpu_events()?.0;
+    Ok(state)
+}
+
+/// Applies `state` to a VCPU. On `reset`, the TSC is left untouched so that
+/// it keeps counting across reboots.
+fn set_vcpu_state(vcpu: &mut impl Vcpu, state: &VcpuState, reset: bool) -> Result<()> {
+    let sregs: Vec<_> = zip(SNAPSHOT_SREGS, state.sregs).collect();
+    let seg_regs: Vec<_> = zip(SNAPSHOT_SEG_REGS, &state.seg_regs)
+        .map(|(reg, val)| {
+            let seg = SegRegVal {
+                selector: val.selector,
+                base: val.base,
+                limit: val.limit,
+                access: SegAccess(val.access),
+            };
+            (reg, seg)
+        })
+        .collect();
+    let dt_regs: Vec<_> = zip(SNAPSHOT_DT_REGS, &state.dt_regs)
+        .map(|(reg, val)| {
+            let dt = DtRegVal {
+                base: val.base,
+                limit: val.limit,
+            };
+            (reg, dt)
+        })
+        .collect();
+    vcpu.set_sregs(&sregs, &seg_regs, &dt_regs)?;
+    let regs: Vec<_> = zip(SNAPSHOT_REGS, state.regs).collect();
+    vcpu.set_regs(&regs)?;
+    // XCR0 enables the components that the XSAVE area restores.
+    vcpu.set_xcrs(&[(0, state.xcr0)])?;
+    vcpu.set_xsave(&XsaveState(state.xsave))?;
+    // The LAPIC goes before MSRs so that the TSC deadline is not dropped.
+    vcpu.set_lapic(&LapicState(state.lapic))?;
+    let msrs: Vec<_> = zip(SNAPSHOT_MSRS, state.msrs)
+        .filter(|(i, _)| !reset || *i != IA32_TSC)
+        .collect();
+    vcpu.set_msrs(&msrs)?;
+    vcpu.set_mp_state(MpState::from(state.mp_state))?;
+    vcpu.set_vcpu_events(&VcpuEvents(state.events))?;
+    Ok(())
+}
 
 pub struct ArchBoard<V>
 where
@@ -54,6 +261,8 @@ where
     cpuids: HashMap<CpuidIn, CpuidResult>,
     sev_ap_eip: AtomicU32,
     tdx_hob: AtomicU64,
+    reset_states: Mutex<HashMap<u16, VcpuState>>,
+    ged: Arc<AcpiGed>,
     pub(crate) io_apic: Arc<IoApic<V::MsiSender>>,
 }
 
@@ -141,6 +350,8 @@ impl<V: Vm> ArchBoard<V> {
             cpuids.insert(CpuidIn { func, index: None }, host_cpuid);
         }
 
+        adjust_cpu_features(&mut cpuids, &config.cpu)?;
+
         if let Some(coco) = &config.coco
             && matches!(coco, Coco::AmdSev { .. } | Coco::AmdSnp { .. })
         {
@@ -151,11 +362,354 @@ impl<V: Vm> ArchBoard<V> {
             cpuids,
             sev_ap_eip: AtomicU32::new(0),
             tdx_hob: AtomicU64::new(0),
+            reset_states: Mutex::new(HashMap::new()),
+            ged: Arc::new(AcpiGed::new()),
             io_apic: Arc::new(IoApic::new(vm.create_msi_sender()?)),
         })
     }
 }
 
+/// Masks the CPUID feature bits with the CPU model and then applies the
+/// feature edits. A named CPU model also replaces the host identity.
+fn adjust_cpu_features(cpuids: &mut HashMap<CpuidIn, CpuidResult>, cpu: &CpuConfig) -> Result<()> {
+    let supported = cpuids.clone();
+    let supported_bits = |feature: &CpuFeature| {
+        let mut out = supported.get(&feature.leaf).copied()?;
+        Some(*feature.reg.get_mut(&mut out))
+    };
+    let check_supported = |feature: &CpuFeature| {
+        let missing = feature.bits & !supported_bits(feature).unwrap_or(0);
+        if missing == 0 {
+            return Ok(());
+        }
+        let names = CpuFeature {
+            bits: missing,
+            ..feature.clone()
+        }
+        .names();
+        error::MissingCpuFeature { names }.fail()
+    };
+
+    if let Some(features) = cpu.model.features() {
+        let mut masks = HashMap::new();
+        for feature in &features {
+            check_supported(feature)?;
+        }
+        for feature in features.into_iter().chain(CpuModel::optional_features()) {
+            *masks.entry((feature.leaf, feature.reg)).or_default() |= feature.bits;
+        }
+        // Only the bits of the model and the optional features are kept, so
+        // that new host features, e.g. TSX or WAITPKG, stay hidden.
+        for known in CpuFeature::known() {
+            let Some(out) = cpuids.get_mut(&known.leaf) else {
+                continue;
+            };
+            let mask = masks.get(&(known.leaf, known.reg)).copied().unwrap_or(0);
+            *known.reg.get_mut(out) &= mask;
+        }
+        // Subleaf 1 of leaf 7 holds features like AVX-VNNI, none of which is
+        // part of a model.
+        let leaf7_1 = CpuidIn {
+            func: 0x7,
+            index: Some(1),
+        };
+        if let Some(out) = cpuids.get_mut(&leaf7_1) {
+            *out = CpuidResult {
+                eax: 0,
+                ebx: 0,
+                ecx: 0,
+                edx: 0,
+            };
+        }
+    }
+
+    for edit in &cpu.features {
+        let Some((feature, add)) = CpuFeature::from_edit(edit) else {
+            return error::InvalidCpuFeature { name: edit.clone() }.fail();
+        };
+        if add {
+            check_supported(&feature)?;
+        }
+        let Some(out) = cpuids.get_mut(&feature.leaf) else {
+            continue;
+        };
+        let reg = feature.reg.get_mut(out);
+        if add {
+            *reg |= feature.bits;
+        } else {
+            *reg &= !feature.bits;
+        }
+    }
+
+    mask_xsave_components(cpuids);
+    if cpu.model != CpuModel::Host {
+        set_model_identity(cpuids, cpu);
+    }
+    Ok(())
+}
+
+/// Removes the XSAVE state components of disabled features from CPUID 0xd,
+/// and the AMX tile information in CPUID 0x1d and 0x1e if AMX is disabled.
+fn mask_xsave_components(cpuids: &mut HashMap<CpuidIn, CpuidResult>) {
+    let enabled = |feature: CpuFeature| {
+        let Some(mut out) = cpuids.get(&feature.leaf).copied() else {
+            return false;
+        };
+        *feature.reg.get_mut(&mut out) & feature.bits == feature.bits
+    };
+    let xsave = enabled(Cpuid1Ecx::XSAVE.into());
+    let amx = xsave && enabled(Cpuid7Index0Edx::AMX_TILE.into());
+    let avx512 = XsaveComponent::OPMASK | XsaveComponent::ZMM_HI256 | XsaveComponent::HI16_ZMM;
+    let mut disabled = XsaveComponent::empty();
+    if !enabled(Cpuid1Ecx::AVX.into()) {
+        disabled |= XsaveComponent::AVX | avx512;
+    }
+    if !enabled(Cpuid7Index0Ebx::AVX512F.into()) {
+        disabled |= avx512;
+    }
+    if !enabled(Cpuid7Index0Ecx::PKU.into()) {
+        disabled |= XsaveComponent::PKRU;
+    }
+    if !amx {
+        disabled |= XsaveComponent::XTILECFG | XsaveComponent::XTILEDATA;
+        cpuids.retain(|leaf, _| !matches!(leaf.func, 0x1d | 0x1e));
+    }
+    if !xsave {
+        cpuids.retain(|leaf, _| leaf.func != 0xd);
+        return;
+    }
+
+    let leaf0 = CpuidIn {
+        func: 0xd,
+        index: Some(0),
+    };
+    let Some(out) = cpuids.get(&leaf0) else {
+        return;
+    };
+    let xcr0 = ((out.edx as u64) << 32 | out.eax as u64) & !disabled.bits();
+    // The legacy region and the XSAVE header
+    let mut max_size = 576;
+    for index in 2..u64::BITS {
+        let leaf = CpuidIn {
+            func: 0xd,
+            index: Some(index),
+        };
+        if disabled.bits() & (1 << index) != 0 {
+            cpuids.remove(&leaf);
+        } else if xcr0 & (1 << index) != 0
+            && let Some(component) = cpuids.get(&leaf)
+        {
+            max_size = max_size.max(component.eax + component.ebx);
+        }
+    }
+    let Some(out) = cpuids.get_mut(&leaf0) else {
+        return;
+    };
+    out.eax = xcr0 as u32;
+    out.edx = (xcr0 >> 32) as u32;
+    out.ecx = max_size;
+}
+
+/// Caches that named CPU models report, so that guests see the same cache
+/// hierarchy on every host.
+struct ModelCache {
+    level: u32,
+    /// 1 for data, 2 for instruction, and 3 for unified caches.
+    kind: u32,
+    size: u32,
+    ways: u32,
+    /// Shared by the threads of a socket instead of a core.
+    per_socket: bool,
+}
+
+const MODEL_CACHE_LINE: u32 = 64;
+
+const MODEL_CACHES: [ModelCache; 4] = [
+    ModelCache {
+        level: 1,
+        kind: 1,
+        size: 32 << 10,
+        ways: 8,
+        per_socket: false,
+    },
+    ModelCache {
+        level: 1,
+        kind: 2,
+        size: 32 << 10,
+        ways: 8,
+        per_socket: false,
+    },
+    ModelCache {
+        level: 2,
+        kind: 3,
+        size: 1 << 20,
+        ways: 16,
+        per_socket: false,
+    },
+    ModelCache {
+        level: 3,
+        kind: 3,
+        size: 16 << 20,
+        ways: 16,
+        per_socket: true,
+    },
+];
+
+// Family 15, model 6, stepping 1
+const MODEL_SIGNATURE: u32 = 0xf61;
+
+/// Encodes the associativity of a cache in CPUID 0x8000_0005 and
+/// 0x8000_0006.
+fn amd_cache_assoc(ways: u32) -> u32 {
+    match ways {
+        1 | 2 | 4 => ways,
+        8 => 0x6,
+        16 => 0x8,
+        _ => 0xf,
+    }
+}
+
+/// Sets the CPU signature, the brand string, and the caches of a named CPU
+/// model. The vendor stays the host's, since guests pick vendor-specific
+/// instructions, e.g. SYSENTER or SYSCALL, that the host CPU runs natively.
+fn set_model_identity(cpuids: &mut HashMap<CpuidIn, CpuidResult>, cpu: &CpuConfig) {
+    let threads_per_core = 1 + cpu.topology.smt as u32;
+    let threads_per_socket = cpu.topology.cores as u32 * threads_per_core;
+
+    let leaf1 = CpuidIn {
+        func: 0x1,
+        index: None,
+    };
+    if let Some(out) = cpuids.get_mut(&leaf1) {
+        out.eax = MODEL_SIGNATURE;
+        // The APIC ID in bits 31:24 is patched later in init_vcpu(). The
+        // CLFLUSH line size is in 8-byte units.
+        let logical = threads_per_socket.next_power_of_two().min(0xff);
+        out.ebx = (out.ebx & 0xff00_0000) | logical << 16 | (MODEL_CACHE_LINE / 8) << 8;
+    }
+    let ext1 = CpuidIn {
+        func: 0x8000_0001,
+        index: None,
+    };
+    if let Some(out) = cpuids.get_mut(&ext1) {
+        out.eax = MODEL_SIGNATURE;
+    }
+    let ext8 = CpuidIn {
+        func: 0x8000_0008,
+        index: None,
+    };
+    if let Some(out) = cpuids.get_mut(&ext8) {
+        // Bits 15:12: width of the APIC ID within a socket, as in
+        // encode_x2apic_id(); bits 7:0: threads per socket minus 1.
+        let core_width = (cpu.topology.cores as u32)
+            .next_power_of_two()
+            .trailing_zeros();
+        let apic_id_width = core_width + cpu.topology.smt as u32;
+        let threads = threads_per_socket.saturating_sub(1).min(0xff);
+        out.ecx = (out.ecx & !0xf0ff) | apic_id_width << 12 | threads;
+    }
+
+    let mut brand = [0u32; 12];
+    let name = format!("Alioth Virtual CPU {}", cpu.model.name());
+    brand.as_mut_bytes()[..name.len()].copy_from_slice(name.as_bytes());
+    for (func, regs) in zip(0x8000_0002.., brand.chunks_exact(4)) {
+        let [eax, ebx, ecx, edx] = regs.try_into().unwrap();
+        cpuids.insert(
+            CpuidIn { func, index: None },
+            CpuidResult { eax, ebx, ecx, edx },
+        );
+    }
+
+    // Leaf 0x2 points to leaf 0x4 with the descriptor 0xff.
+    let leaf2 = CpuidResult {
+        eax: 0xff01,
+        ebx: 0,
+        ecx: 0,
+        edx: 0,
+    };
+    cpuids.insert(
+        CpuidIn {
+            func: 0x2,
+            index: None,
+        },
+        leaf2,
+    );
+    let has_ext1d = cpuids.keys().any(|leaf| leaf.func == 0x8000_001d);
+    cpuids.retain(|leaf, _| leaf.func != 0x4 && leaf.func != 0x8000_001d);
+    let null_cache = CpuidResult {
+        eax: 0,
+        ebx: 0,
+        ecx: 0,
+        edx: 0,
+    };
+    let caches = MODEL_CACHES.iter().map(|cache| {
+        let sharing = if cache.per_socket {
+            threads_per_socket
+        } else {
+            threads_per_core
+        };
+        // Bit 8: self-initializing
+        let eax = cache.kind | cache.level << 5 | 1 << 8 | sharing.saturating_sub(1) << 14;
+        let ebx = (cache.ways - 1) << 22 | (MODEL_CACHE_LINE - 1);
+        let ecx = cache.size / (cache.ways * MODEL_CACHE_LINE) - 1;
+        CpuidResult {
+            eax,
+            ebx,
+            ecx,
+            edx: 0,
+        }
+    });
+    for (index, out) in caches.chain([null_cache]).enumerate() {
+        let index = Some(index as u32);
+        if has_ext1d {
+            let leaf = CpuidIn {
+                func: 0x8000_001d,
+                index,
+            };
+            cpuids.insert(leaf, out);
+        }
+        let cores = (cpu.topology.cores as u32).saturating_sub(1);
+        let eax = if out.eax == 0 {
+            0
+        } else {
+            out.eax | cores << 26
+        };
+        let leaf = CpuidIn { func: 0x4, index };
+        cpuids.insert(leaf, CpuidResult { eax, ..out });
+    }
+
+    let [l1d, l1i, l2, l3] = &MODEL_CACHES;
+    let l1 = |cache: &ModelCache| {
+        (cache.size >> 10) << 24 | cache.ways << 16 | 1 << 8 | MODEL_CACHE_LINE
+    };
+    let ext5 = CpuidResult {
+        eax: 0,
+        ebx: 0,
+        ecx: l1(l1d),
+        edx: l1(l1i),
+    };
+    cpuids.insert(
+        CpuidIn {
+            func: 0x8000_0005,
+            index: None,
+        },
+        ext5,
+    );
+    let ext6 = CpuidResult {
+        eax: 0,
+        ebx: 0,
+        ecx: (l2.size >> 10) << 16 | amd_cache_assoc(l2.ways) << 12 | 1 << 8 | MODEL_CACHE_LINE,
+        edx: (l3.size >> 19) << 18 | amd_cache_assoc(l3.ways) << 12 | 1 << 8 | MODEL_CACHE_LINE,
+    };
+    cpuids.insert(
+        CpuidIn {
+            func: 0x8000_0006,
+            index: None,
+        },
+        ext6,
+    );
+}
+
 fn encode_x2apic_id(topology: &CpuTopology, index: u16) -> u32 {
     let (socket_id, core_id, thread_id) = topology.encode(index);
 
@@ -258,10 +812,53 @@ where
         }
         vcpu.set_cpuids(cpuids)?;
         vcpu.set_msrs(&[(IA32_MISC_ENABLE, MiscEnable::FAST_STRINGS.bits())])?;
+        if self.config.coco.is_none() {
+            let state = get_vcpu_state(vcpu)?;
+            self.arch.reset_states.lock().insert(index, state);
+        }
         Ok(())
     }
 
-    pub fn reset_vcpu(&self, _index: u16, _vcpu: &mut V::Vcpu) -> Result<()> {
+    /// Brings a VCPU back to the state captured in `init_vcpu()`, which
+    /// also disables the paravirtual features, e.g. kvmclock, that the
+    /// guest had pointed to its memory.
+    pub fn reset_vcpu(&self, index: u16, vcpu: &mut V::Vcpu) -> Result<()> {
+        let reset_states = self.arch.reset_states.lock();
+        let Some(state) = reset_states.get(&index) else {
+            return Ok(());
+        };
+        set_vcpu_state(vcpu, state, true)
+    }
+
+    pub fn save_vcpu_state(&self, vcpu: &V::Vcpu) -> Result<Vec<u8>> {
+        let state = get_vcpu_state(vcpu)?;
+        Ok(state.as_bytes().to_vec())
+    }
+
+    pub fn restore_vcpu_state(&self, vcpu: &mut V::Vcpu, data: &[u8]) -> Result<()> {
+        let Ok(state) = VcpuState::read_from_bytes(data) else {
+            return error::InvalidSnapshot {
+                msg: "bad VCPU state",
+            }
+            .fail();
+        };
+        set_vcpu_state(vcpu, &state, false)
+    }
+
+    /// Notifies the guest of a press of the power button, through the GED
+    /// in the DSDT.
+    pub fn press_power_button(&self) -> Result<()> {
+        self.arch.ged.notify(GED_EVENT_POWER_BUTTON);
+        self.arch.io_apic.service_pin(HOTPLUG_IRQ)?;
+        Ok(())
+    }
+
+    pub fn get_guest_clock(&self) -> Result<u64> {
+        Ok(self.vm.get_clock()?)
+    }
+
+    pub fn set_guest_clock(&self, ns: u64) -> Result<()> {
+        self.vm.set_clock(ns)?;
         Ok(())
     }
 
@@ -270,7 +867,7 @@ where
         let memory = &self.memory;
 
         let low_mem_size = std::cmp::min(config.mem.size, RAM_32_SIZE);
-        let pages_low = self.create_ram_pages(low_mem_size, c"ram-low")?;
+        let pages_low = self.create_ram_pages(RAM_32_START, low_mem_size, c"ram-low")?;
         let region_low = MemRegion {
             ranges: vec![MemRange::Ram(pages_low.clone())],
             entries: if self.config.coco.is_none() {
@@ -304,7 +901,7 @@ where
 
         if config.mem.size > RAM_32_SIZE {
             let mem_hi_size = config.mem.size - RAM_32_SIZE;
-            let mem_hi = self.create_ram_pages(mem_hi_size, c"ram-high")?;
+            let mem_hi = self.create_ram_pages(MEM_64_START, mem_hi_size, c"ram-high")?;
             let region_hi = MemRegion::with_ram(mem_hi.clone(), MemRegionType::Ram);
             memory.add_region(MEM_64_START, Arc::new(region_hi))?;
         }
@@ -338,16 +935,19 @@ where
         }
     }
 
-    fn patch_dsdt(&self, data: &mut [u8; 352]) {
+    fn create_dsdt(&self) -> Vec<u8> {
         let pcie_mmio_64_start = self.config.pcie_mmio_64_start();
-        let pcei_mmio_64_max = pcie_mmio_64_start - 1 + PCIE_MMIO_64_SIZE;
-        data[DSDT_OFFSET_PCI_QWORD_MEM..(DSDT_OFFSET_PCI_QWORD_MEM + 8)]
-            .copy_from_slice(&pcie_mmio_64_start.to_le_bytes());
-        data[(DSDT_OFFSET_PCI_QWORD_MEM + 8)..(DSDT_OFFSET_PCI_QWORD_MEM + 16)]
-            .copy_from_slice(&pcei_mmio_64_max.to_le_bytes());
-        let sum = wrapping_sum(&*data);
-        let checksum = &mut data[offset_of!(AcpiTableHeader, checksum)];
-        *checksum = checksum.wrapping_sub(sum);
+        let pcie_mmio_64_max = pcie_mmio_64_start - 1 + PCIE_MMIO_64_SIZE;
+        create_dsdt(&[
+            &serial_device("_SB.COM1", ComPort::Com1, 1),
+            // SLP_TYPx of the S5 sleep state, see FadtSleepControl
+            &Name::new("_S5", Package::new().with(5u8)),
+            &pci_host_bridge(pcie_mmio_64_start, pcie_mmio_64_max),
+            &Device::new("_SB.PWRB")
+                .with(Name::new("_HID", EisaId::new("PNP0C0C")))
+                .with(Name::new("_UID", 0u8)),
+            &generic_event_device(),
+        ])
     }
 
     fn create_acpi(&self) -> AcpiTable {
@@ -355,16 +955,24 @@ where
         let mut pointers = vec![];
         let mut checksums = vec![];
 
-        let mut xsdt: AcpiTableXsdt3 = AcpiTableXsdt3::new_zeroed();
+        let iommu = self.iommu.lock().clone();
+        let mut num_entries = 3;
+        if !self.config.numa.is_empty() {
+            num_entries += 2;
+        }
+        if iommu.is_some() {
+            num_entries += 1;
+        }
         let offset_xsdt = 0;
-        table_bytes.extend(xsdt.as_bytes());
+        let xsdt_size = size_of::<AcpiTableHeader>() + num_entries * size_of::<u64>();
+        table_bytes.resize(xsdt_size, 0);
 
-        let offset_dsdt = offset_xsdt + size_of_val(&xsdt);
-        let mut dsdt = DSDT_TEMPLATE;
-        self.patch_dsdt(&mut dsdt);
-        table_bytes.extend(dsdt);
+        let offset_dsdt = offset_xsdt + xsdt_size;
+        let dsdt = self.create_dsdt();
+        table_bytes.extend(&dsdt);
+        table_bytes.resize(align_up!(table_bytes.len(), 2), 0);
 
-        let offset_fadt = offset_dsdt + size_of_val(&DSDT_TEMPLATE);
+        let offset_fadt = table_bytes.len();
         debug_assert_eq!(offset_fadt % 4, 0);
         let fadt = create_fadt(offset_dsdt as u64);
         let pointer_fadt_to_dsdt = offset_fadt + offset_of!(AcpiTableFadt, xdsdt);
@@ -389,14 +997,38 @@ where
         let mcfg = create_mcfg();
         table_bytes.extend(mcfg.as_bytes());
 
+        let mut xsdt_entries = vec![offset_fadt as u64, offset_madt as u64, offset_mcfg as u64];
+        if !self.config.numa.is_empty() {
+            let cpus: Vec<_> = zip(0.., apic_ids)
+                .map(|(index, apic_id)| {
+                    let node = self.config.numa_node_of_cpu(index).unwrap_or_default();
+                    (apic_id, node)
+                })
+                .collect();
+            let mems: Vec<_> = (self.config.numa_mem_ranges().into_iter())
+                .map(|r| (r.node, r.start, r.size))
+                .collect();
+            xsdt_entries.push(table_bytes.len() as u64);
+            table_bytes.extend(create_srat(&cpus, &mems));
+            table_bytes.resize(align_up!(table_bytes.len(), 2), 0);
+
+            let distances: Vec<_> = self.config.numa.iter().map(|n| &*n.distances).collect();
+            xsdt_entries.push(table_bytes.len() as u64);
+            table_bytes.extend(create_slit(&distances));
+        }
+        if let Some(iommu) = iommu {
+            table_bytes.resize(align_up!(table_bytes.len(), 2), 0);
+            xsdt_entries.push(table_bytes.len() as u64);
+            table_bytes.extend(create_viot(iommu.bdf.0, &iommu.endpoint_ranges()));
+        }
+
         debug_assert_eq!(offset_xsdt % 4, 0);
-        let xsdt_entries = [offset_fadt as u64, offset_madt as u64, offset_mcfg as u64];
-        xsdt = create_xsdt(xsdt_entries);
-        xsdt.write_to_prefix(&mut table_bytes).unwrap();
+        let xsdt = create_xsdt(&xsdt_entries);
+        table_bytes[offset_xsdt..(offset_xsdt + xsdt_size)].copy_from_slice(&xsdt);
         for index in 0..xsdt_entries.len() {
-            pointers.push(offset_xsdt + offset_of!(AcpiTableXsdt3, entries) + index * 8);
+            pointers.push(offset_xsdt + size_of::<AcpiTableHeader>() + index * 8);
         }
-        checksums.push((offset_xsdt, size_of_val(&xsdt)));
+        checksums.push((offset_xsdt, xsdt_size));
 
         let rsdp = create_rsdp(offset_xsdt as u64);
 
@@ -414,6 +1046,8 @@ where
         memory.add_io_dev(PORT_ACPI_RESET, Arc::new(FadtReset))?;
         memory.add_io_dev(PORT_ACPI_SLEEP_CONTROL, Arc::new(FadtSleepControl))?;
         memory.add_io_dev(PORT_ACPI_TIMER, Arc::new(AcpiPmTimer::new()))?;
+        memory.add_io_dev(PORT_PCI_HOTPLUG, self.pci_hotplug.clone())?;
+        memory.add_io_dev(PORT_ACPI_GED, self.arch.ged.clone())?;
         if self.config.coco.is_none() {
             let ram = memory.ram_bus();
             acpi_table.relocate(EBDA_START + size_of::<AcpiTableRsdp>() as u64);
@@ -445,34 +1079,151 @@ where
         self.mmio_devs.write().push((IOAPIC_START, io_apic));
         Ok(())
     }
+
+    pub fn arch_reset(&self) -> Result<()> {
+        self.arch.io_apic.reset();
+        Ok(())
+    }
 }
 
-const DSDT_TEMPLATE: [u8; 352] = [
-    0x44, 0x53, 0x44, 0x54, 0x5D, 0x01, 0x00, 0x00, 0x02, 0x5D, 0x41, 0x4C, 0x49, 0x4F, 0x54, 0x48,
-    0x41, 0x4C, 0x49, 0x4F, 0x54, 0x48, 0x56, 0x4D, 0x01, 0x00, 0x00, 0x00, 0x49, 0x4E, 0x54, 0x4C,
-    0x28, 0x06, 0x23, 0x20, 0x5B, 0x82, 0x37, 0x2E, 0x5F, 0x53, 0x42, 0x5F, 0x43, 0x4F, 0x4D, 0x31,
-    0x08, 0x5F, 0x48, 0x49, 0x44, 0x0C, 0x41, 0xD0, 0x05, 0x01, 0x08, 0x5F, 0x55, 0x49, 0x44, 0x01,
-    0x08, 0x5F, 0x53, 0x54, 0x41, 0x0A, 0x0F, 0x08, 0x5F, 0x43, 0x52, 0x53, 0x11, 0x10, 0x0A, 0x0D,
-    0x47, 0x01, 0xF8, 0x03, 0xF8, 0x03, 0x00, 0x08, 0x22, 0x10, 0x00, 0x79, 0x00, 0x08, 0x5F, 0x53,
-    0x35, 0x5F, 0x12, 0x04, 0x01, 0x0A, 0x05, 0x5B, 0x82, 0x44, 0x0F, 0x2E, 0x5F, 0x53, 0x42, 0x5F,
-    0x50, 0x43, 0x49, 0x30, 0x08, 0x5F, 0x48, 0x49, 0x44, 0x0C, 0x41, 0xD0, 0x0A, 0x08, 0x08, 0x5F,
-    0x43, 0x49, 0x44, 0x0C, 0x41, 0xD0, 0x0A, 0x03, 0x08, 0x5F, 0x53, 0x45, 0x47, 0x00, 0x08, 0x5F,
-    0x55, 0x49, 0x44, 0x00, 0x14, 0x32, 0x5F, 0x44, 0x53, 0x4D, 0x04, 0xA0, 0x29, 0x93, 0x68, 0x11,
-    0x13, 0x0A, 0x10, 0xD0, 0x37, 0xC9, 0xE5, 0x53, 0x35, 0x7A, 0x4D, 0x91, 0x17, 0xEA, 0x4D, 0x19,
-    0xC3, 0x43, 0x4D, 0xA0, 0x09, 0x93, 0x6A, 0x00, 0xA4, 0x11, 0x03, 0x01, 0x21, 0xA0, 0x07, 0x93,
-    0x6A, 0x0A, 0x05, 0xA4, 0x00, 0xA4, 0x00, 0x08, 0x5F, 0x43, 0x52, 0x53, 0x11, 0x40, 0x09, 0x0A,
-    0x8C, 0x88, 0x0D, 0x00, 0x02, 0x0C, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x01,
-    0x00, 0x47, 0x01, 0xF8, 0x0C, 0xF8, 0x0C, 0x01, 0x08, 0x87, 0x17, 0x00, 0x00, 0x0C, 0x07, 0x00,
-    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x80, 0xFF, 0xFF, 0xFF, 0x9F, 0x00, 0x00, 0x00, 0x00, 0x00,
-    0x00, 0x00, 0x20, 0x87, 0x17, 0x00, 0x00, 0x0C, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
-    0xA0, 0xFF, 0xFF, 0xFF, 0xBF, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x20, 0x8A, 0x2B, 0x00,
-    0x00, 0x0C, 0x07, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x01,
-    0x00, 0x00, 0x00, 0xFF, 0xFF, 0xFF, 0xFF, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
-    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x88, 0x0D, 0x00, 0x01, 0x0C,
-    0x03, 0x00, 0x00, 0x00, 0x10, 0xFF, 0xFF, 0x00, 0x00, 0x00, 0xF0, 0x79, 0x00, 0x00, 0x00, 0x00,
-];
+fn serial_device(path: &str, port: ComPort, uid: u8) -> Device {
+    let base = port.base_port();
+    Device::new(path)
+        .with(Name::new("_HID", EisaId::new("PNP0501")))
+        .with(Name::new("_UID", uid))
+        .with(Name::new("_STA", 0xfu8))
+        .with(Name::new(
+            "_CRS",
+            ResourceTemplate::new()
+                .with(Io {
+                    min: base,
+                    max: base,
+                    align: 0,
+                    len: 8,
+                })
+                .with(IrqNoFlags(port.irq())),
+        ))
+}
+
+fn pci_host_bridge(mmio_64_start: u64, mmio_64_max: u64) -> Device {
+    // PCI Firmware Spec 3.1, Sec. 4.6. _DSM Definitions for PCI
+    let dsm = Method::new("_DSM", 4, false)
+        .with(
+            If::new(LEqual::new(
+                Arg(0),
+                Uuid::new("e5c937d0-3553-4d7a-9117-ea4d19c3434d"),
+            ))
+            // Function 0 returns the bitmap of supported functions 0 and 5.
+            .with(If::new(LEqual::new(Arg(2), 0u8)).with(Return::new(Buffer(vec![0b10_0001]))))
+            // Function 5: the OS preserves resource assignments.
+            .with(If::new(LEqual::new(Arg(2), 5u8)).with(Return::new(0u8))),
+        )
+        .with(Return::new(0u8));
+
+    let crs = ResourceTemplate::new()
+        .with(AddressSpace::<u16>::new_bus_number(0, 0))
+        .with(Io {
+            min: PORT_PCI_ADDRESS,
+            max: PORT_PCI_ADDRESS,
+            align: 1,
+            len: 8,
+        })
+        .with(AddressSpace::new_memory(
+            MemAttr::Prefetchable,
+            PCIE_MMIO_32_PREFETCHABLE_START as u32,
+            (PCIE_MMIO_32_PREFETCHABLE_END - 1) as u32,
+        ))
+        .with(AddressSpace::new_memory(
+            MemAttr::NonCacheable,
+            PCIE_MMIO_32_NON_PREFETCHABLE_START as u32,
+            (PCIE_MMIO_32_NON_PREFETCHABLE_END - 1) as u32,
+        ))
+        .with(AddressSpace::new_memory(
+            MemAttr::Prefetchable,
+            mmio_64_start,
+            mmio_64_max,
+        ))
+        .with(AddressSpace::<u16>::new_io(0x1000, 0xffff));
+
+    // Registers of the hotplug controller, see alioth/src/pci/hotplug.rs.
+    let region = OpRegion::new(
+        "PCST",
+        RegionSpace::SystemIo,
+        PORT_PCI_HOTPLUG as u64,
+        HOTPLUG_B0EJ + 4,
+    );
+    let field = Field::new("PCST", FieldAccess::DWord)
+        .with("PCIU", 32)
+        .with("PCID", 32)
+        .with("B0EJ", 32);
+
+    // Notifies every slot in the bitmap Arg0 with the value Arg1.
+    let mut pcnf = Method::new("PCNF", 2, false);
+    for slot in HOTPLUG_SLOTS {
+        let notify = Notify::new(&format!("S{slot:02}"), Arg(1));
+        pcnf = pcnf.with(If::new(And::new(Arg(0), 1u32 << slot)).with(notify));
+    }
 
-const DSDT_OFFSET_PCI_QWORD_MEM: usize = 0x12b;
+    let mut pci0 = Device::new("_SB.PCI0")
+        .with(Name::new("_HID", EisaId::new("PNP0A08")))
+        .with(Name::new("_CID", EisaId::new("PNP0A03")))
+        .with(Name::new("_SEG", 0u8))
+        .with(Name::new("_UID", 0u8))
+        .with(dsm)
+        .with(Name::new("_CRS", crs))
+        .with(region)
+        .with(field)
+        .with(pcnf)
+        .with(hotplug_notify());
+    for slot in HOTPLUG_SLOTS {
+        pci0 = pci0.with(hotplug_slot(slot));
+    }
+    pci0
+}
+
+// Acknowledges pending events, then sends Device Check (1) and Eject
+// Request (3) notifications.
+fn hotplug_notify() -> Method {
+    Method::new("PCNT", 0, true)
+        .with(Store::new(NameString::new("PCIU"), Local(0)))
+        .with(Store::new(Local(0), NameString::new("PCIU")))
+        .with(Store::new(NameString::new("PCID"), Local(1)))
+        .with(Store::new(Local(1), NameString::new("PCID")))
+        .with(MethodCall::new("PCNF").arg(Local(0)).arg(1u8))
+        .with(MethodCall::new("PCNF").arg(Local(1)).arg(3u8))
+}
+
+// Writing the slot bit to B0EJ asks the hotplug controller to eject it.
+fn hotplug_slot(slot: u8) -> Device {
+    Device::new(&format!("S{slot:02}"))
+        .with(Name::new("_ADR", (slot as u32) << 16))
+        .with(Name::new("_SUN", slot))
+        .with(Method::new("_EJ0", 1, false).with(Store::new(1u32 << slot, NameString::new("B0EJ"))))
+}
+
+fn generic_event_device() -> Device {
+    // Event register, see AcpiGed in alioth/src/firmware/acpi/reg.rs.
+    let region = OpRegion::new("GEDR", RegionSpace::SystemIo, PORT_ACPI_GED as u64, 4);
+    let field = Field::new("GEDR", FieldAccess::DWord).with("GEDE", 32);
+    let evt = Method::new("_EVT", 1, false)
+        .with(Store::new(NameString::new("GEDE"), Local(0)))
+        .with(Store::new(Local(0), NameString::new("GEDE")))
+        .with(
+            If::new(And::new(Local(0), GED_EVENT_POWER_BUTTON))
+                .with(Notify::new("\\_SB.PWRB", 0x80u8)),
+        )
+        .with(MethodCall::new("\\_SB.PCI0.PCNT"));
+    Device::new("_SB.GED0")
+        .with(Name::new("_HID", "ACPI0013"))
+        .with(Name::new("_UID", 0u8))
+        .with(Name::new(
+            "_CRS",
+            ResourceTemplate::new().with(Interrupt(HOTPLUG_IRQ as u32)),
+        ))
+        .with(region)
+        .with(field)
+        .with(evt)
+}
 
 #[cfg(test)]
 #[path = "board_x86_64_test.rs"]
@@ -12,10 +12,22 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use std::arch::x86_64::CpuidResult;
+use std::collections::HashMap;
+
+use assert_matches::assert_matches;
 use rstest::rstest;
 
-use crate::board::CpuTopology;
-use crate::board::x86_64::encode_x2apic_id;
+use crate::arch::cpuid::{
+    CpuModel, Cpuid1Ecx, Cpuid1Edx, Cpuid7Index0Ebx, Cpuid7Index0Ecx, Cpuid7Index0Edx,
+    CpuidExt1Ecx, CpuidExt1Edx, CpuidIn, XsaveComponent,
+};
+use crate::arch::msr::{IA32_TSC, MSR_KVM_POLL_CONTROL, MSR_KVM_SYSTEM_TIME_NEW};
+use crate::board::x86_64::{adjust_cpu_features, encode_x2apic_id, hotplug_notify, hotplug_slot};
+use crate::board::{Board, BoardConfig, CpuConfig, CpuTopology, Error};
+use crate::firmware::acpi::aml::Aml;
+use crate::hv::{Kvm, KvmConfig, MpState, Vcpu};
+use crate::sys::kvm::KvmCpuidFeature;
 
 #[rstest]
 #[case(CpuTopology{smt: false, cores: 1, sockets: 1}, 0, 0)]
@@ -30,3 +42,419 @@ use crate::board::x86_64::encode_x2apic_id;
 fn test_encode_x2apic(#[case] topology: CpuTopology, #[case] index: u16, #[case] x2apic: u32) {
     assert_eq!(encode_x2apic_id(&topology, index), x2apic)
 }
+
+fn test_cpuids() -> HashMap<CpuidIn, CpuidResult> {
+    let leaf1 = CpuidResult {
+        eax: 0,
+        ebx: 0,
+        ecx: (Cpuid1Ecx::SSE3 | Cpuid1Ecx::X2APIC | Cpuid1Ecx::XSAVE | Cpuid1Ecx::AVX).bits(),
+        edx: Cpuid1Edx::all().bits(),
+    };
+    let leaf7 = CpuidResult {
+        eax: 0,
+        ebx: (Cpuid7Index0Ebx::AVX2 | Cpuid7Index0Ebx::AVX512F).bits(),
+        ecx: Cpuid7Index0Ecx::PKU.bits(),
+        edx: (Cpuid7Index0Edx::MD_CLEAR.bits() | 1 << 2),
+    };
+    let ext1 = CpuidResult {
+        eax: 0,
+        ebx: 0,
+        ecx: CpuidExt1Ecx::LAHF_LM.bits(),
+        edx: (CpuidExt1Edx::SYSCALL | CpuidExt1Edx::NX | CpuidExt1Edx::LM).bits(),
+    };
+    let leaf_kvm = CpuidResult {
+        eax: KvmCpuidFeature::all().bits(),
+        ebx: 0,
+        ecx: 0,
+        edx: 0,
+    };
+    // (index, size, offset) of x87 | SSE | AVX | AVX-512 | PKRU
+    let xsave = [
+        (0, 2696, 0x2e7),
+        (2, 256, 576),
+        (5, 64, 1088),
+        (6, 512, 1152),
+        (7, 1024, 1664),
+        (9, 8, 2688),
+    ];
+    let leaf_d = xsave.map(|(index, eax, ebx)| {
+        let leaf = CpuidIn {
+            func: 0xd,
+            index: Some(index),
+        };
+        let out = match index {
+            0 => CpuidResult {
+                eax: ebx,
+                ebx: 576,
+                ecx: eax,
+                edx: 0,
+            },
+            _ => CpuidResult {
+                eax,
+                ebx,
+                ecx: 0,
+                edx: 0,
+            },
+        };
+        (leaf, out)
+    });
+    let mut cpuids = HashMap::from(leaf_d);
+    cpuids.extend([
+        (
+            CpuidIn {
+                func: 0x1,
+                index: None,
+            },
+            leaf1,
+        ),
+        (
+            CpuidIn {
+                func: 0x7,
+                index: Some(0),
+            },
+            leaf7,
+        ),
+        (
+            CpuidIn {
+                func: 0x8000_0001,
+                index: None,
+            },
+            ext1,
+        ),
+        (
+            CpuidIn {
+                func: 0x4000_0001,
+                index: None,
+            },
+            leaf_kvm,
+        ),
+    ]);
+    cpuids
+}
+
+fn xsave_components(cpuids: &HashMap<CpuidIn, CpuidResult>) -> Vec<u32> {
+    let mut indexes: Vec<_> = cpuids
+        .keys()
+        .filter(|leaf| leaf.func == 0xd)
+        .filter_map(|leaf| leaf.index)
+        .collect();
+    indexes.sort();
+    indexes
+}
+
+#[test]
+fn test_adjust_cpu_features() {
+    let mut cpuids = test_cpuids();
+    let cpu = CpuConfig {
+        features: vec!["-avx512f".into(), "+x2apic".into(), "-steal_time".into()],
+        ..Default::default()
+    };
+    adjust_cpu_features(&mut cpuids, &cpu).unwrap();
+    let leaf_kvm = &cpuids[&CpuidIn {
+        func: 0x4000_0001,
+        index: None,
+    }];
+    assert_eq!(
+        leaf_kvm.eax,
+        (KvmCpuidFeature::all() - KvmCpuidFeature::STEAL_TIME).bits()
+    );
+    let leaf7 = &cpuids[&CpuidIn {
+        func: 0x7,
+        index: Some(0),
+    }];
+    assert_eq!(leaf7.ebx, Cpuid7Index0Ebx::AVX2.bits());
+    assert_eq!(leaf7.ecx, Cpuid7Index0Ecx::PKU.bits());
+
+    let leaf_d = &cpuids[&CpuidIn {
+        func: 0xd,
+        index: Some(0),
+    }];
+    let xcr0 = XsaveComponent::X87 | XsaveComponent::SSE | XsaveComponent::AVX;
+    assert_eq!(leaf_d.eax as u64, (xcr0 | XsaveComponent::PKRU).bits());
+    assert_eq!(leaf_d.ecx, 2696);
+    assert_eq!(xsave_components(&cpuids), [0, 2, 9]);
+
+    // The host model keeps the host identity.
+    let leaf1 = &cpuids[&CpuidIn {
+        func: 0x1,
+        index: None,
+    }];
+    assert_eq!(leaf1.eax, 0);
+    assert!(!cpuids.keys().any(|leaf| leaf.func == 0x4));
+}
+
+#[test]
+fn test_adjust_cpu_features_no_xsave() {
+    let mut cpuids = test_cpuids();
+    let cpu = CpuConfig {
+        features: vec!["-xsave".into()],
+        ..Default::default()
+    };
+    adjust_cpu_features(&mut cpuids, &cpu).unwrap();
+    assert_eq!(xsave_components(&cpuids), []);
+}
+
+#[test]
+fn test_adjust_cpu_features_model() {
+    let mut cpuids = test_cpuids();
+    let cpu = CpuConfig {
+        count: 4,
+        topology: CpuTopology {
+            smt: true,
+            cores: 2,
+            sockets: 1,
+        },
+        model: CpuModel::X86_64V1,
+        features: vec!["+avx".into()],
+        ..Default::default()
+    };
+    adjust_cpu_features(&mut cpuids, &cpu).unwrap();
+    let leaf1 = &cpuids[&CpuidIn {
+        func: 0x1,
+        index: None,
+    }];
+    assert_eq!(
+        leaf1.ecx,
+        (Cpuid1Ecx::X2APIC | Cpuid1Ecx::XSAVE | Cpuid1Ecx::AVX).bits()
+    );
+    assert_eq!(leaf1.edx, Cpuid1Edx::all().bits());
+    let leaf7 = &cpuids[&CpuidIn {
+        func: 0x7,
+        index: Some(0),
+    }];
+    // Speculation controls are kept, and bits without a name are cleared.
+    assert_eq!(
+        (leaf7.ebx, leaf7.ecx, leaf7.edx),
+        (0, 0, Cpuid7Index0Edx::MD_CLEAR.bits())
+    );
+    let ext1 = &cpuids[&CpuidIn {
+        func: 0x8000_0001,
+        index: None,
+    }];
+    assert_eq!(ext1.ecx, 0);
+    assert_eq!(
+        ext1.edx,
+        (CpuidExt1Edx::SYSCALL | CpuidExt1Edx::NX | CpuidExt1Edx::LM).bits()
+    );
+    let leaf_kvm = &cpuids[&CpuidIn {
+        func: 0x4000_0001,
+        index: None,
+    }];
+    assert_eq!(leaf_kvm.eax, KvmCpuidFeature::all().bits());
+
+    // PKU is not part of the model and AVX-512 is not enabled.
+    let leaf_d = &cpuids[&CpuidIn {
+        func: 0xd,
+        index: Some(0),
+    }];
+    let xcr0 = XsaveComponent::X87 | XsaveComponent::SSE | XsaveComponent::AVX;
+    assert_eq!((leaf_d.eax as u64, leaf_d.edx), (xcr0.bits(), 0));
+    assert_eq!(leaf_d.ecx, 576 + 256);
+    assert_eq!(xsave_components(&cpuids), [0, 2]);
+
+    // Family 15, model 6, stepping 1, with 64-byte CLFLUSH lines and 4
+    // logical processors per socket.
+    assert_eq!((leaf1.eax, leaf1.ebx), (0xf61, 0x0004_0800));
+    let brand: Vec<u8> = (0x8000_0002..=0x8000_0004)
+        .flat_map(|func| {
+            let out = &cpuids[&CpuidIn { func, index: None }];
+            [out.eax, out.ebx, out.ecx, out.edx]
+        })
+        .flat_map(u32::to_le_bytes)
+        .collect();
+    assert_eq!(brand.len(), 48);
+    assert!(brand.starts_with(b"Alioth Virtual CPU x86-64-v1\0"));
+
+    let leaf4 = |index| {
+        let out = &cpuids[&CpuidIn {
+            func: 0x4,
+            index: Some(index),
+        }];
+        [out.eax, out.ebx, out.ecx, out.edx]
+    };
+    // 32KiB L1 data cache, 8-way, shared by 2 threads
+    assert_eq!(leaf4(0), [0x0400_4121, 0x01c0_003f, 63, 0]);
+    // 16MiB L3 cache, 16-way, shared by 4 threads
+    assert_eq!(leaf4(3), [0x0400_c163, 0x03c0_003f, 16383, 0]);
+    assert_eq!(leaf4(4), [0; 4]);
+    let ext6 = &cpuids[&CpuidIn {
+        func: 0x8000_0006,
+        index: None,
+    }];
+    assert_eq!((ext6.ecx, ext6.edx), (0x0400_8140, 0x0080_8140));
+}
+
+#[test]
+fn test_adjust_cpu_features_model_new_host() {
+    let mut cpuids = test_cpuids();
+    let leaf7 = CpuidIn {
+        func: 0x7,
+        index: Some(0),
+    };
+    let out = cpuids.get_mut(&leaf7).unwrap();
+    // HLE and RTM
+    out.ebx |= 1 << 4 | 1 << 11;
+    // WAITPKG
+    out.ecx |= 1 << 5;
+    out.edx |=
+        (Cpuid7Index0Edx::AMX_BF16 | Cpuid7Index0Edx::AMX_TILE | Cpuid7Index0Edx::AMX_INT8).bits();
+    // AVX-VNNI and AVX512_BF16
+    let leaf7_1 = CpuidIn {
+        func: 0x7,
+        index: Some(1),
+    };
+    let out = CpuidResult {
+        eax: 1 << 4 | 1 << 5,
+        ebx: 0,
+        ecx: 0,
+        edx: 1 << 4,
+    };
+    cpuids.insert(leaf7_1.clone(), out);
+    // XTILECFG and XTILEDATA
+    let leaf_d = CpuidIn {
+        func: 0xd,
+        index: Some(0),
+    };
+    cpuids.get_mut(&leaf_d).unwrap().eax |=
+        (XsaveComponent::XTILECFG | XsaveComponent::XTILEDATA).bits() as u32;
+    for (index, eax, ebx) in [(17, 64, 2752), (18, 8192, 2816)] {
+        let leaf = CpuidIn {
+            func: 0xd,
+            index: Some(index),
+        };
+        let out = CpuidResult {
+            eax,
+            ebx,
+            ecx: 0,
+            edx: 0,
+        };
+        cpuids.insert(leaf, out);
+    }
+    for func in [0x1d, 0x1e] {
+        let out = CpuidResult {
+            eax: 1,
+            ebx: 0x4000,
+            ecx: 0,
+            edx: 0,
+        };
+        cpuids.insert(CpuidIn { func, index: None }, out);
+    }
+
+    let mut host = cpuids.clone();
+    adjust_cpu_features(&mut host, &CpuConfig::default()).unwrap();
+    assert_eq!(host[&leaf7_1].eax, 1 << 4 | 1 << 5);
+    assert!(host.contains_key(&CpuidIn {
+        func: 0x1d,
+        index: None
+    }));
+    assert_eq!(xsave_components(&host), [0, 2, 5, 6, 7, 9, 17, 18]);
+
+    let cpu = CpuConfig {
+        model: CpuModel::X86_64V1,
+        features: vec!["+avx".into()],
+        ..Default::default()
+    };
+    adjust_cpu_features(&mut cpuids, &cpu).unwrap();
+    let leaf7 = &cpuids[&leaf7];
+    assert_eq!(
+        (leaf7.ebx, leaf7.ecx, leaf7.edx),
+        (0, 0, Cpuid7Index0Edx::MD_CLEAR.bits())
+    );
+    let leaf7_1 = &cpuids[&leaf7_1];
+    assert_eq!(
+        (leaf7_1.eax, leaf7_1.ebx, leaf7_1.ecx, leaf7_1.edx),
+        (0, 0, 0, 0)
+    );
+    assert!(!cpuids.keys().any(|leaf| matches!(leaf.func, 0x1d | 0x1e)));
+
+    let leaf_d = &cpuids[&leaf_d];
+    let xcr0 = XsaveComponent::X87 | XsaveComponent::SSE | XsaveComponent::AVX;
+    assert_eq!((leaf_d.eax as u64, leaf_d.edx), (xcr0.bits(), 0));
+    assert_eq!(leaf_d.ecx, 576 + 256);
+    assert_eq!(xsave_components(&cpuids), [0, 2]);
+}
+
+#[rstest]
+#[case(CpuModel::X86_64V2, vec![])]
+#[case(CpuModel::Host, vec!["+avx512bw".into()])]
+fn test_adjust_cpu_features_missing(#[case] model: CpuModel, #[case] features: Vec<Box<str>>) {
+    let mut cpuids = test_cpuids();
+    let cpu = CpuConfig {
+        model,
+        features,
+        ..Default::default()
+    };
+    assert_matches!(
+        adjust_cpu_features(&mut cpuids, &cpu),
+        Err(Error::MissingCpuFeature { .. })
+    );
+}
+
+#[test]
+fn test_hotplug_slot() {
+    assert_eq!(
+        hotplug_slot(3).to_aml_bytes(),
+        [
+            0x5b, 0x82, 0x24, 0x53, 0x30, 0x33, 0x5f, 0x08, 0x5f, 0x41, 0x44, 0x52, 0x0c, 0x00,
+            0x00, 0x03, 0x00, 0x08, 0x5f, 0x53, 0x55, 0x4e, 0x0a, 0x03, 0x14, 0x0d, 0x5f, 0x45,
+            0x4a, 0x30, 0x01, 0x70, 0x0a, 0x08, 0x42, 0x30, 0x45, 0x4a,
+        ]
+    );
+}
+
+#[test]
+fn test_hotplug_notify() {
+    assert_eq!(
+        hotplug_notify().to_aml_bytes(),
+        [
+            0x14, 0x2b, 0x50, 0x43, 0x4e, 0x54, 0x08, 0x70, 0x50, 0x43, 0x49, 0x55, 0x60, 0x70,
+            0x60, 0x50, 0x43, 0x49, 0x55, 0x70, 0x50, 0x43, 0x49, 0x44, 0x61, 0x70, 0x61, 0x50,
+            0x43, 0x49, 0x44, 0x50, 0x43, 0x4e, 0x46, 0x60, 0x01, 0x50, 0x43, 0x4e, 0x46, 0x61,
+            0x0a, 0x03,
+        ]
+    );
+}
+
+#[test]
+#[cfg_attr(not(feature = "test-hv"), ignore)]
+fn test_reset_vcpu() {
+    let kvm = Kvm::new(KvmConfig::default()).unwrap();
+    let mut config = BoardConfig::default();
+    config.cpu.count = 2;
+    let board = Board::new(&kvm, config).unwrap();
+    let mut vcpus = [0, 1].map(|index| board.create_vcpu(index).unwrap());
+    for (index, vcpu) in vcpus.iter_mut().enumerate() {
+        board.init_vcpu(index as u16, vcpu).unwrap();
+    }
+    let [bsp, ap] = &mut vcpus;
+
+    let pv_msrs = [(MSR_KVM_SYSTEM_TIME_NEW, 0x1001), (MSR_KVM_POLL_CONTROL, 0)];
+    bsp.set_msrs(&pv_msrs).unwrap();
+    let mut msrs = pv_msrs.map(|(index, _)| (index, u64::MAX));
+    bsp.get_msrs(&mut msrs).unwrap();
+    assert_eq!(msrs, pv_msrs);
+    let mut lapic = bsp.get_lapic().unwrap();
+    // Task priority register
+    lapic.0[0x80] = 0x20;
+    bsp.set_lapic(&lapic).unwrap();
+    let mut tsc = [(IA32_TSC, 0)];
+    bsp.get_msrs(&mut tsc).unwrap();
+    let tsc_before = tsc[0].1;
+    assert_eq!(ap.get_mp_state().unwrap(), MpState::UNINITIALIZED);
+    ap.set_mp_state(MpState::RUNNABLE).unwrap();
+
+    board.reset_vcpu(0, bsp).unwrap();
+    board.reset_vcpu(1, ap).unwrap();
+
+    let mut msrs = pv_msrs.map(|(index, _)| (index, u64::MAX));
+    bsp.get_msrs(&mut msrs).unwrap();
+    assert_eq!(
+        msrs,
+        [(MSR_KVM_SYSTEM_TIME_NEW, 0), (MSR_KVM_POLL_CONTROL, 1)]
+    );
+    assert_eq!(bsp.get_lapic().unwrap().0[0x80], 0);
+    bsp.get_msrs(&mut tsc).unwrap();
+    assert!(tsc[0].1 >= tsc_before);
+    assert_eq!(bsp.get_mp_state().unwrap(), MpState::RUNNABLE);
+    assert_eq!(ap.get_mp_state().unwrap(), MpState::UNINITIALIZED);
+}
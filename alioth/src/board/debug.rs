@@ -0,0 +1,153 @@
+// Copyright 2026 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Debugger access to a paused board.
+//!
+//! A VCPU can only be accessed from its own thread. A debugger queues tasks
+//! that VCPU threads run while the board is paused.
+
+use std::fmt::{self, Debug, Formatter};
+use std::mem::take;
+use std::sync::mpsc;
+
+use crate::board::{Board, BoardState, MpSync, Result, error};
+use crate::hv::{Vcpu, Vm};
+
+type VcpuFn = Box<dyn FnOnce(&mut dyn Vcpu) + Send>;
+
+struct VcpuTask {
+    index: u16,
+    func: VcpuFn,
+}
+
+impl Debug for VcpuTask {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("VcpuTask")
+            .field("index", &self.index)
+            .finish_non_exhaustive()
+    }
+}
+
+#[derive(Debug, Default)]
+pub(super) struct DebugSync {
+    tasks: Vec<VcpuTask>,
+    /// The VCPU that paused the board at a breakpoint or a single step.
+    stopped: Option<u16>,
+    /// Pauses the board before the first guest instruction.
+    halt_on_boot: bool,
+}
+
+impl DebugSync {
+    pub fn has_tasks(&self) -> bool {
+        !self.tasks.is_empty()
+    }
+}
+
+impl<V> Board<V>
+where
+    V: Vm,
+{
+    /// Pauses the board before it runs the first guest instruction, as if
+    /// VCPU-0 hit a breakpoint.
+    pub fn halt_on_boot(&self) {
+        self.mp_sync.lock().debug.halt_on_boot = true;
+    }
+
+    /// Called by VCPU-0 once the board is ready to run the guest.
+    pub(super) fn halt_on_request(&self, mp_sync: &mut MpSync) {
+        if take(&mut mp_sync.debug.halt_on_boot) && mp_sync.state == BoardState::Running {
+            mp_sync.state = BoardState::Paused;
+            mp_sync.debug.stopped = Some(0);
+        }
+    }
+
+    /// Records that VCPU `index` exited at a breakpoint or a single step.
+    pub(super) fn debug_stop(&self, index: u16) {
+        let mut mp_sync = self.mp_sync.lock();
+        mp_sync.debug.stopped.get_or_insert(index);
+    }
+
+    /// Returns the VCPU that paused the board at a breakpoint or a single
+    /// step since the last call.
+    pub fn take_debug_stop(&self) -> Option<u16> {
+        self.mp_sync.lock().debug.stopped.take()
+    }
+
+    /// Returns true if the board has booted and is paused, such that
+    /// [`Board::run_on_vcpu`] can be used.
+    pub fn is_halted(&self) -> bool {
+        let mp_sync = self.mp_sync.lock();
+        mp_sync.booted && mp_sync.state == BoardState::Paused
+    }
+
+    /// Runs `func` on the thread of VCPU `index` of the paused board and
+    /// returns its result.
+    pub fn run_on_vcpu<F, T>(&self, index: u16, func: F) -> Result<T>
+    where
+        F: FnOnce(&mut dyn Vcpu) -> T + Send + 'static,
+        T: Send + 'static,
+    {
+        let mut mp_sync = self.mp_sync.lock();
+        if index >= self.config.cpu.count {
+            return error::InvalidVcpu { index }.fail();
+        }
+        if mp_sync.state != BoardState::Paused {
+            return error::UnexpectedState {
+                state: mp_sync.state,
+                want: BoardState::Paused,
+            }
+            .fail();
+        }
+        if !mp_sync.booted {
+            return error::NotBooted.fail();
+        }
+        let (tx, rx) = mpsc::channel();
+        let func = Box::new(move |vcpu: &mut dyn Vcpu| {
+            let _ = tx.send(func(vcpu));
+        });
+        mp_sync.debug.tasks.push(VcpuTask { index, func });
+        self.cond_var.notify_all();
+        loop {
+            if let Ok(ret) = rx.try_recv() {
+                break Ok(ret);
+            }
+            if mp_sync.fatal {
+                break error::PeerFailure.fail();
+            }
+            if mp_sync.state != BoardState::Paused {
+                mp_sync.debug.tasks.retain(|t| t.index != index);
+                break error::UnexpectedState {
+                    state: mp_sync.state,
+                    want: BoardState::Paused,
+                }
+                .fail();
+            }
+            self.cond_var.wait(&mut mp_sync);
+        }
+    }
+
+    /// Runs the tasks queued for the calling VCPU.
+    pub(super) fn run_vcpu_tasks(&self, index: u16, vcpu: &mut V::Vcpu, mp_sync: &mut MpSync) {
+        let tasks = &mut mp_sync.debug.tasks;
+        if !tasks.iter().any(|t| t.index == index) {
+            return;
+        }
+        let (mine, others): (Vec<_>, _) = take(tasks).into_iter().partition(|t| t.index == index);
+        *tasks = others;
+        for task in mine {
+            (task.func)(vcpu);
+        }
+        self.cond_var.notify_all();
+    }
+}
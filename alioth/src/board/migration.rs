@@ -0,0 +1,437 @@
+// Copyright 2026 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Pre-copy live migration of a board.
+//!
+//! The source streams guest RAM while the guest keeps running, and then
+//! resends pages dirtied in the meantime, by the guest or by device
+//! emulation, for a few rounds. Once few dirty pages are left, it pauses the
+//! guest and devices, and sends the remaining pages with the states of VCPUs
+//! and devices. The destination restores them at boot
+//! and acknowledges, after which the source shuts down.
+
+use std::fs;
+use std::io::{self, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::Path;
+
+use serde::Deserialize;
+use serde_aco::Help;
+use snafu::ResultExt;
+use zerocopy::{FromBytes, FromZeros, Immutable, IntoBytes};
+
+use crate::arch::layout::PAGE_SIZE;
+use crate::board::snapshot::{RamEntry, SavedState};
+use crate::board::{Board, BoardState, Result, error};
+use crate::consts;
+use crate::hv::Vm;
+
+const MIGRATION_MAGIC: [u8; 8] = *b"ALIOTHMG";
+const MIGRATION_VERSION: u32 = 3;
+
+/// Maximum number of rounds of resending dirty pages.
+const MAX_ROUNDS: u32 = 8;
+/// The guest is paused once a round leaves no more dirty pages than this.
+const STOP_COPY_PAGES: u64 = 256;
+/// Upper bound of the number of RAM regions.
+const MAX_RAM_REGIONS: u64 = 64;
+/// Upper bound of the state of a VCPU or a device.
+const MAX_STATE_SIZE: u64 = 1 << 24;
+
+const ACK: u8 = 0xa5;
+
+#[repr(C)]
+#[derive(Debug, IntoBytes, FromBytes, Immutable)]
+struct MigrationHeader {
+    magic: [u8; 8],
+    version: u32,
+    num_cpus: u32,
+    mem_size: u64,
+    num_ram: u64,
+}
+
+consts! {
+    #[derive(Default, IntoBytes, FromBytes, Immutable)]
+    struct RecordType(u32) {
+        RAM = 1;
+        VCPU = 2;
+        DEVICE = 3;
+        CLOCK = 4;
+        END = 5;
+    }
+}
+
+/// A record in the migration stream.
+///
+/// `arg` is the GPA of a RAM record, the index of a VCPU record, the
+/// length of the name preceding the data of a device record, or the guest
+/// clock of a clock record.
+#[repr(C)]
+#[derive(Debug, Default, IntoBytes, FromBytes, Immutable)]
+struct RecordHeader {
+    type_: RecordType,
+    _reserved: u32,
+    arg: u64,
+    len: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Help)]
+pub struct MigrationUdsParam {
+    /// Path to the socket.
+    pub path: Box<Path>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Help)]
+pub struct MigrationTcpParam {
+    /// Host and port, e.g. 127.0.0.1:4444.
+    pub addr: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Help)]
+pub enum MigrationAddr {
+    /// A Unix domain socket.
+    #[serde(alias = "uds")]
+    Uds(MigrationUdsParam),
+    /// A TCP socket.
+    #[serde(alias = "tcp")]
+    Tcp(MigrationTcpParam),
+}
+
+impl MigrationAddr {
+    /// Connects to a destination waiting at this address.
+    pub fn connect(&self) -> io::Result<MigrationStream> {
+        match self {
+            MigrationAddr::Uds(p) => UnixStream::connect(&p.path).map(MigrationStream::Uds),
+            MigrationAddr::Tcp(p) => TcpStream::connect(&p.addr).map(MigrationStream::Tcp),
+        }
+    }
+
+    /// Waits for a source to connect to this address.
+    pub fn accept(&self) -> io::Result<MigrationStream> {
+        match self {
+            MigrationAddr::Uds(p) => {
+                let listener = UnixListener::bind(&p.path)?;
+                let accepted = listener.accept();
+                fs::remove_file(&p.path)?;
+                let (conn, _) = accepted?;
+                Ok(MigrationStream::Uds(conn))
+            }
+            MigrationAddr::Tcp(p) => {
+                let listener = TcpListener::bind(&p.addr)?;
+                let (conn, _) = listener.accept()?;
+                conn.set_nodelay(true)?;
+                Ok(MigrationStream::Tcp(conn))
+            }
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum MigrationStream {
+    Uds(UnixStream),
+    Tcp(TcpStream),
+}
+
+impl Read for MigrationStream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            MigrationStream::Uds(s) => s.read(buf),
+            MigrationStream::Tcp(s) => s.read(buf),
+        }
+    }
+}
+
+impl Write for MigrationStream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            MigrationStream::Uds(s) => s.write(buf),
+            MigrationStream::Tcp(s) => s.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            MigrationStream::Uds(s) => s.flush(),
+            MigrationStream::Tcp(s) => s.flush(),
+        }
+    }
+}
+
+fn send_record(
+    stream: &mut MigrationStream,
+    type_: RecordType,
+    arg: u64,
+    data: &[u8],
+) -> Result<()> {
+    let header = RecordHeader {
+        type_,
+        arg,
+        len: data.len() as u64,
+        ..Default::default()
+    };
+    stream
+        .write_all(header.as_bytes())
+        .context(error::Migration)?;
+    stream.write_all(data).context(error::Migration)
+}
+
+fn read_data(stream: &mut MigrationStream, len: u64) -> Result<Vec<u8>> {
+    if len > MAX_STATE_SIZE {
+        return error::InvalidSnapshot {
+            msg: format!("state of {len} bytes is too large"),
+        }
+        .fail();
+    }
+    let mut data = vec![0; len as usize];
+    stream.read_exact(&mut data).context(error::Migration)?;
+    Ok(data)
+}
+
+/// Splits dirty bitmaps into runs of pages, as `(gpa, number of pages)`.
+fn dirty_runs(dirty: &[(u64, Vec<u64>)]) -> Vec<(u64, u64)> {
+    let mut runs = vec![];
+    for (addr, bitmap) in dirty {
+        let mut run: Option<(u64, u64)> = None;
+        for (index, word) in bitmap.iter().enumerate() {
+            let mut word = *word;
+            while word != 0 {
+                let page = index as u64 * 64 + word.trailing_zeros() as u64;
+                word &= word - 1;
+                run = match run {
+                    Some((start, num)) if start + num == page => Some((start, num + 1)),
+                    Some((start, num)) => {
+                        runs.push((addr + start * PAGE_SIZE, num));
+                        Some((page, 1))
+                    }
+                    None => Some((page, 1)),
+                };
+            }
+        }
+        if let Some((start, num)) = run {
+            runs.push((addr + start * PAGE_SIZE, num));
+        }
+    }
+    runs
+}
+
+impl<V> Board<V>
+where
+    V: Vm,
+{
+    fn send_ram(&self, stream: &mut MigrationStream, gpa: u64, len: u64) -> Result<()> {
+        let header = RecordHeader {
+            type_: RecordType::RAM,
+            arg: gpa,
+            len,
+            ..Default::default()
+        };
+        stream
+            .write_all(header.as_bytes())
+            .context(error::Migration)?;
+        self.memory.ram_bus().read_range(gpa, len, stream)?;
+        Ok(())
+    }
+
+    /// Sends pages dirtied since the last round and returns their number.
+    fn send_dirty_pages(&self, stream: &mut MigrationStream) -> Result<u64> {
+        let dirty = self.memory.dirty_pages()?;
+        let mut count = 0;
+        for (gpa, num) in dirty_runs(&dirty) {
+            self.send_ram(stream, gpa, num * PAGE_SIZE)?;
+            count += num;
+        }
+        Ok(count)
+    }
+
+    fn send_state(&self, stream: &mut MigrationStream, state: &SavedState) -> Result<()> {
+        for (index, data) in state.vcpus.iter().enumerate() {
+            send_record(stream, RecordType::VCPU, index as u64, data)?;
+        }
+        for (name, data) in &state.devices {
+            let mut record = name.as_bytes().to_vec();
+            record.extend(data);
+            send_record(stream, RecordType::DEVICE, name.len() as u64, &record)?;
+        }
+        send_record(stream, RecordType::CLOCK, state.clock, &[])?;
+        send_record(stream, RecordType::END, 0, &[])?;
+        stream.flush().context(error::Migration)
+    }
+
+    /// Pauses the board and sends the rest of its state.
+    fn stop_and_copy(&self, stream: &mut MigrationStream) -> Result<()> {
+        self.pause()?;
+        let mut mp_sync = self.mp_sync.lock();
+        self.check_saveable(&mp_sync, BoardState::Paused)?;
+        let state = self.save_state(&mut mp_sync)?;
+        let count = self.send_dirty_pages(stream)?;
+        log::info!("migration: sent the last {count} dirty pages");
+        self.send_state(stream, &state)?;
+
+        let mut ack = [0u8];
+        stream.read_exact(&mut ack).context(error::Migration)?;
+        if ack[0] != ACK {
+            return error::InvalidSnapshot {
+                msg: format!("unexpected acknowledgement {:#x}", ack[0]),
+            }
+            .fail();
+        }
+        Ok(())
+    }
+
+    fn migrate_inner(&self, stream: &mut MigrationStream) -> Result<()> {
+        let ram = self.ram_layout();
+        let header = MigrationHeader {
+            magic: MIGRATION_MAGIC,
+            version: MIGRATION_VERSION,
+            num_cpus: self.config.cpu.count as u32,
+            mem_size: self.config.mem.size,
+            num_ram: ram.len() as u64,
+        };
+        stream
+            .write_all(header.as_bytes())
+            .context(error::Migration)?;
+        for &(gpa, size) in &ram {
+            stream
+                .write_all(RamEntry { gpa, size }.as_bytes())
+                .context(error::Migration)?;
+        }
+
+        for (gpa, size) in ram {
+            self.send_ram(stream, gpa, size)?;
+        }
+        for round in 1..=MAX_ROUNDS {
+            let count = self.send_dirty_pages(stream)?;
+            log::info!("migration: round {round}: sent {count} dirty pages");
+            if count <= STOP_COPY_PAGES {
+                break;
+            }
+        }
+
+        let ret = self.stop_and_copy(stream);
+        if ret.is_err()
+            && self.state() == BoardState::Paused
+            && let Err(e) = self.resume()
+        {
+            log::error!("migration: failed to resume: {e:?}");
+        }
+        ret
+    }
+
+    /// Migrates the running board to a destination at the other end of
+    /// `stream`, and shuts it down once the destination has taken over.
+    pub fn migrate(&self, stream: &mut MigrationStream) -> Result<()> {
+        self.check_saveable(&self.mp_sync.lock(), BoardState::Running)?;
+        self.check_devices()?;
+        self.memory.start_dirty_log()?;
+        let ret = self.migrate_inner(stream);
+        if let Err(e) = self.memory.stop_dirty_log() {
+            log::error!("migration: failed to stop dirty log: {e:?}");
+        }
+        ret?;
+        self.shutdown()
+    }
+
+    /// Reads the header of an incoming migration, so that the board restores
+    /// the state from `stream` when it boots.
+    pub fn accept_migration(&self, mut stream: MigrationStream) -> Result<()> {
+        self.check_restorable()?;
+        let mut header = MigrationHeader::new_zeroed();
+        stream
+            .read_exact(header.as_mut_bytes())
+            .context(error::Migration)?;
+        if header.magic != MIGRATION_MAGIC || header.version != MIGRATION_VERSION {
+            return error::InvalidSnapshot { msg: "bad header" }.fail();
+        }
+        self.check_config(header.num_cpus, header.mem_size)?;
+        if header.num_ram > MAX_RAM_REGIONS {
+            return error::InvalidSnapshot { msg: "bad header" }.fail();
+        }
+        let mut ram = vec![];
+        for _ in 0..header.num_ram {
+            let mut entry = RamEntry::new_zeroed();
+            stream
+                .read_exact(entry.as_mut_bytes())
+                .context(error::Migration)?;
+            ram.push((entry.gpa, entry.size));
+        }
+        self.load_incoming(stream, ram);
+        Ok(())
+    }
+
+    /// Receives guest RAM and the states of VCPUs and devices.
+    pub(super) fn receive_migration(
+        &self,
+        stream: &mut MigrationStream,
+        state: &mut SavedState,
+    ) -> Result<()> {
+        let ram_bus = self.memory.ram_bus();
+        state.vcpus = vec![vec![]; self.config.cpu.count as usize];
+        loop {
+            let mut header = RecordHeader::default();
+            stream
+                .read_exact(header.as_mut_bytes())
+                .context(error::Migration)?;
+            match header.type_ {
+                RecordType::RAM => ram_bus.write_range(header.arg, header.len, &mut *stream)?,
+                RecordType::VCPU => {
+                    let data = read_data(stream, header.len)?;
+                    let Some(vcpu) = state.vcpus.get_mut(header.arg as usize) else {
+                        return error::InvalidSnapshot {
+                            msg: format!("unknown VCPU {}", header.arg),
+                        }
+                        .fail();
+                    };
+                    *vcpu = data;
+                }
+                RecordType::DEVICE => {
+                    let mut data = read_data(stream, header.len)?;
+                    let Some(name) = data.get(..header.arg as usize) else {
+                        return error::InvalidSnapshot { msg: "bad device" }.fail();
+                    };
+                    let Ok(name) = String::from_utf8(name.to_vec()) else {
+                        return error::InvalidSnapshot { msg: "bad device" }.fail();
+                    };
+                    data.drain(..header.arg as usize);
+                    state.devices.insert(name, data);
+                }
+                RecordType::CLOCK => state.clock = header.arg,
+                RecordType::END => break,
+                type_ => {
+                    return error::InvalidSnapshot {
+                        msg: format!("unknown record {type_:?}"),
+                    }
+                    .fail();
+                }
+            }
+        }
+        if state.vcpus.iter().any(|v| v.is_empty()) {
+            return error::InvalidSnapshot {
+                msg: "missing VCPU state",
+            }
+            .fail();
+        }
+        Ok(())
+    }
+
+    /// Tells the source that the state has been received and restored.
+    pub(super) fn ack_migration(&self, stream: &mut MigrationStream) -> Result<()> {
+        stream.write_all(&[ACK]).context(error::Migration)?;
+        stream.flush().context(error::Migration)
+    }
+}
+
+#[cfg(test)]
+#[path = "migration_test.rs"]
+mod tests;
@@ -0,0 +1,73 @@
+// Copyright 2026 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::HashMap;
+use std::os::unix::net::UnixStream;
+use std::path::Path;
+
+use assert_matches::assert_matches;
+use rstest::rstest;
+use zerocopy::FromBytes;
+
+use crate::board::Error;
+use crate::board::migration::{
+    MAX_STATE_SIZE, MigrationAddr, MigrationStream, MigrationTcpParam, MigrationUdsParam,
+    RecordHeader, RecordType, dirty_runs, read_data, send_record,
+};
+
+#[rstest]
+#[case(&[], &[])]
+#[case(&[(0x1000_0000, vec![0])], &[])]
+#[case(&[(0, vec![0b1011])], &[(0, 2), (0x3000, 1)])]
+#[case(
+    &[(0, vec![1 << 63, 1]), (0x10_0000, vec![0b110])],
+    &[(0x3f000, 2), (0x10_1000, 2)]
+)]
+fn test_dirty_runs(#[case] dirty: &[(u64, Vec<u64>)], #[case] runs: &[(u64, u64)]) {
+    assert_eq!(dirty_runs(dirty), runs);
+}
+
+#[rstest]
+#[case(
+    "uds,path=/tmp/m.sock",
+    MigrationAddr::Uds(MigrationUdsParam { path: Path::new("/tmp/m.sock").into() })
+)]
+#[case(
+    "tcp,addr=127.0.0.1:4444",
+    MigrationAddr::Tcp(MigrationTcpParam { addr: "127.0.0.1:4444".to_owned() })
+)]
+fn test_migration_addr(#[case] arg: &str, #[case] addr: MigrationAddr) {
+    let parsed: MigrationAddr = serde_aco::from_args(arg, &HashMap::new()).unwrap();
+    assert_eq!(parsed, addr);
+}
+
+#[test]
+fn test_record_round_trip() {
+    let (s0, s1) = UnixStream::pair().unwrap();
+    let mut tx = MigrationStream::Uds(s0);
+    let mut rx = MigrationStream::Uds(s1);
+
+    send_record(&mut tx, RecordType::DEVICE, 3, b"comstate").unwrap();
+    let header = read_data(&mut rx, size_of::<RecordHeader>() as u64).unwrap();
+    let header = RecordHeader::read_from_bytes(&header).unwrap();
+    assert_eq!(header.type_, RecordType::DEVICE);
+    assert_eq!(header.arg, 3);
+    assert_eq!(header.len, 8);
+    assert_eq!(read_data(&mut rx, header.len).unwrap(), b"comstate");
+
+    assert_matches!(
+        read_data(&mut rx, MAX_STATE_SIZE + 1),
+        Err(Error::InvalidSnapshot { .. })
+    );
+}
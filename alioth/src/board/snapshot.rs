@@ -0,0 +1,568 @@
+// Copyright 2026 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Snapshots of a paused board.
+//!
+//! A snapshot is a directory holding a `board` header, one `ram-<gpa>` file
+//! per RAM region, one `vcpu-<index>` file per VCPU, and one file per
+//! emulated device named after its port, address, or BDF.
+
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{BufReader, BufWriter, Write};
+use std::mem::{size_of, take};
+use std::path::Path;
+
+use parking_lot::MutexGuard;
+use snafu::ResultExt;
+use zerocopy::{FromBytes, Immutable, IntoBytes};
+
+use crate::arch::layout::PCIE_CONFIG_START;
+use crate::board::migration::MigrationStream;
+use crate::board::{Board, BoardState, MpSync, Result, error};
+use crate::device::MmioDev;
+use crate::hv::Vm;
+use crate::pci::config::{CommonHeader, DeviceHeader, OFFSET_BAR0, OFFSET_BAR5};
+use crate::pci::{Bdf, Pci};
+
+const SNAPSHOT_MAGIC: [u8; 8] = *b"ALIOTHSS";
+const SNAPSHOT_VERSION: u32 = 3;
+
+const FILE_BOARD: &str = "board";
+
+#[repr(C)]
+#[derive(Debug, Default, IntoBytes, FromBytes, Immutable)]
+struct SnapshotHeader {
+    magic: [u8; 8],
+    version: u32,
+    num_cpus: u32,
+    mem_size: u64,
+    clock: u64,
+}
+
+#[repr(C)]
+#[derive(Debug, IntoBytes, FromBytes, Immutable)]
+pub(super) struct RamEntry {
+    pub gpa: u64,
+    pub size: u64,
+}
+
+/// States of VCPUs and devices of a paused board.
+#[derive(Debug, Default)]
+pub(super) struct SavedState {
+    pub clock: u64,
+    pub vcpus: Vec<Vec<u8>>,
+    pub devices: HashMap<String, Vec<u8>>,
+}
+
+#[derive(Debug)]
+enum SnapshotSource {
+    Dir(Box<Path>),
+    Incoming(MigrationStream),
+}
+
+/// A snapshot waiting to be restored at boot, either loaded from disk or
+/// arriving from a migration source.
+#[derive(Debug)]
+pub struct SnapshotData {
+    source: SnapshotSource,
+    ram: Vec<(u64, u64)>,
+    state: SavedState,
+}
+
+fn io_dev_name(port: u16) -> String {
+    format!("io-{port:#x}")
+}
+
+fn mmio_dev_name(addr: u64) -> String {
+    format!("mmio-{addr:#x}")
+}
+
+fn pci_dev_name(bdf: Bdf) -> String {
+    format!("pci-{bdf}")
+}
+
+fn ram_name(gpa: u64) -> String {
+    format!("ram-{gpa:#x}")
+}
+
+fn vcpu_name(index: u16) -> String {
+    format!("vcpu-{index}")
+}
+
+fn is_dev_name(name: &str) -> bool {
+    ["io-", "mmio-", "pci-"].iter().any(|p| name.starts_with(p))
+}
+
+/// Returns true if a file named `name` is part of the snapshot format.
+fn is_snapshot_name(name: &str) -> bool {
+    name == FILE_BOARD || name.starts_with("ram-") || name.starts_with("vcpu-") || is_dev_name(name)
+}
+
+fn write_file(dir: &Path, name: &str, data: &[u8]) -> Result<()> {
+    let path = dir.join(name);
+    fs::write(&path, data).context(error::SnapshotFile { path })
+}
+
+fn read_file(dir: &Path, name: &str) -> Result<Vec<u8>> {
+    let path = dir.join(name);
+    fs::read(&path).context(error::SnapshotFile { path })
+}
+
+/// Removes files left by a previous snapshot in `dir`.
+fn clear_dir(dir: &Path) -> Result<()> {
+    let entries = fs::read_dir(dir).context(error::SnapshotFile { path: dir })?;
+    for entry in entries {
+        let entry = entry.context(error::SnapshotFile { path: dir })?;
+        let name = entry.file_name();
+        let name = name.to_string_lossy();
+        if is_snapshot_name(&name) {
+            let path = entry.path();
+            fs::remove_file(&path).context(error::SnapshotFile { path })?;
+        }
+    }
+    Ok(())
+}
+
+fn snapshot_pci_dev(dev: &dyn Pci) -> Result<Vec<u8>> {
+    let config = dev.config();
+    let mut data = Vec::new();
+    for offset in (0..config.size()).step_by(4) {
+        let val = config.read(offset, 4)? as u32;
+        data.extend(val.to_le_bytes());
+    }
+    let name = dev.name();
+    let dev_data = dev.snapshot().context(error::SnapshotDevice { name })?;
+    data.extend(dev_data);
+    Ok(data)
+}
+
+fn restore_mmio_dev(
+    devices: &mut HashMap<String, Vec<u8>>,
+    name: String,
+    dev: &dyn MmioDev,
+) -> Result<()> {
+    let Some(data) = devices.remove(&name) else {
+        return error::InvalidSnapshot {
+            msg: format!("missing device {name}"),
+        }
+        .fail();
+    };
+    dev.restore(&data).context(error::SnapshotDevice { name })
+}
+
+impl<V> Board<V>
+where
+    V: Vm,
+{
+    /// Saves the state of the calling VCPU if a snapshot is being taken.
+    pub(super) fn save_vcpu_on_request(&self, index: u16, vcpu: &V::Vcpu, mp_sync: &mut MpSync) {
+        let Some(states) = &mut mp_sync.vcpu_states else {
+            return;
+        };
+        let Some(state) = states.get_mut(index as usize) else {
+            return;
+        };
+        if state.is_none() {
+            *state = Some(self.save_vcpu_state(vcpu));
+            self.cond_var.notify_all();
+        }
+    }
+
+    fn collect_vcpu_states(&self, mp_sync: &mut MutexGuard<MpSync>) -> Result<Vec<Vec<u8>>> {
+        let count = self.config.cpu.count as usize;
+        mp_sync.vcpu_states = Some((0..count).map(|_| None).collect());
+        self.cond_var.notify_all();
+        loop {
+            if mp_sync.fatal {
+                break error::PeerFailure.fail();
+            }
+            if mp_sync.state != BoardState::Paused {
+                break error::UnexpectedState {
+                    state: mp_sync.state,
+                    want: BoardState::Paused,
+                }
+                .fail();
+            }
+            let states = mp_sync.vcpu_states.iter().flatten();
+            if states.flatten().count() == count {
+                let states = mp_sync.vcpu_states.take().into_iter().flatten();
+                break states.flatten().collect();
+            }
+            self.cond_var.wait(mp_sync);
+        }
+    }
+
+    pub(super) fn ram_layout(&self) -> Vec<(u64, u64)> {
+        let ram_bus = self.memory.ram_bus();
+        let layout = ram_bus.lock_layout();
+        layout
+            .iter()
+            .map(|(gpa, pages)| (gpa, pages.size()))
+            .collect()
+    }
+
+    /// Checks that the board is a booted, non-confidential VM in `state`.
+    pub(super) fn check_saveable(&self, mp_sync: &MpSync, state: BoardState) -> Result<()> {
+        if cfg!(not(target_arch = "x86_64")) {
+            return error::SnapshotUnsupported {
+                msg: std::env::consts::ARCH,
+            }
+            .fail();
+        }
+        if self.config.coco.is_some() {
+            return error::SnapshotUnsupported {
+                msg: "confidential VMs",
+            }
+            .fail();
+        }
+        if self.config.mem.hotplug_size != 0 {
+            return error::SnapshotUnsupported {
+                msg: "memory hotplug",
+            }
+            .fail();
+        }
+        if mp_sync.state != state {
+            return error::UnexpectedState {
+                state: mp_sync.state,
+                want: state,
+            }
+            .fail();
+        }
+        if !mp_sync.booted {
+            return error::NotBooted.fail();
+        }
+        Ok(())
+    }
+
+    /// Checks that every device supports snapshots, before a long-running
+    /// migration finds out at the end.
+    pub(super) fn check_devices(&self) -> Result<()> {
+        for (port, dev) in self.io_devs.read().iter() {
+            let name = io_dev_name(*port);
+            dev.snapshot().context(error::SnapshotDevice { name })?;
+        }
+        for (addr, dev) in self.mmio_devs.read().iter() {
+            let name = mmio_dev_name(*addr);
+            dev.snapshot().context(error::SnapshotDevice { name })?;
+        }
+        for (_, dev) in self.pci_bus.segment.devices() {
+            snapshot_pci_dev(dev.as_ref())?;
+        }
+        Ok(())
+    }
+
+    /// Collects the states of VCPUs and devices of the paused board.
+    pub(super) fn save_state(&self, mp_sync: &mut MutexGuard<MpSync>) -> Result<SavedState> {
+        let vcpus = self.collect_vcpu_states(mp_sync);
+        mp_sync.vcpu_states = None;
+        let vcpus = vcpus?;
+
+        let mut devices = HashMap::new();
+        for (port, dev) in self.io_devs.read().iter() {
+            let name = io_dev_name(*port);
+            let data = dev
+                .snapshot()
+                .context(error::SnapshotDevice { name: &name })?;
+            devices.insert(name, data);
+        }
+        for (addr, dev) in self.mmio_devs.read().iter() {
+            let name = mmio_dev_name(*addr);
+            let data = dev
+                .snapshot()
+                .context(error::SnapshotDevice { name: &name })?;
+            devices.insert(name, data);
+        }
+        for (bdf, dev) in self.pci_bus.segment.devices() {
+            let data = snapshot_pci_dev(dev.as_ref())?;
+            devices.insert(pci_dev_name(bdf), data);
+        }
+
+        Ok(SavedState {
+            clock: self.get_guest_clock()?,
+            vcpus,
+            devices,
+        })
+    }
+
+    /// Writes a snapshot of the paused board to `dir`.
+    pub fn snapshot(&self, dir: &Path) -> Result<()> {
+        let mut mp_sync = self.mp_sync.lock();
+        self.check_saveable(&mp_sync, BoardState::Paused)?;
+        let state = self.save_state(&mut mp_sync)?;
+
+        // Keep `mp_sync` locked so that the board cannot be resumed while
+        // the snapshot is being written.
+        fs::create_dir_all(dir).context(error::SnapshotFile { path: dir })?;
+        clear_dir(dir)?;
+
+        let ram_bus = self.memory.ram_bus();
+        let ram = self.ram_layout();
+        for (gpa, size) in &ram {
+            let path = dir.join(ram_name(*gpa));
+            let file = File::create(&path).context(error::SnapshotFile { path: &*path })?;
+            let mut writer = BufWriter::new(file);
+            ram_bus.read_range(*gpa, *size, &mut writer)?;
+            writer.flush().context(error::SnapshotFile { path })?;
+        }
+
+        for (index, data) in state.vcpus.iter().enumerate() {
+            write_file(dir, &vcpu_name(index as u16), data)?;
+        }
+        for (name, data) in &state.devices {
+            write_file(dir, name, data)?;
+        }
+
+        let header = SnapshotHeader {
+            magic: SNAPSHOT_MAGIC,
+            version: SNAPSHOT_VERSION,
+            num_cpus: self.config.cpu.count as u32,
+            mem_size: self.config.mem.size,
+            clock: state.clock,
+        };
+        let mut data = header.as_bytes().to_vec();
+        for (gpa, size) in ram {
+            data.extend(RamEntry { gpa, size }.as_bytes());
+        }
+        // The header is written last so that an incomplete snapshot is
+        // rejected by load_snapshot().
+        write_file(dir, FILE_BOARD, &data)
+    }
+
+    /// Loads a snapshot from `dir`, to be restored instead of loading the
+    /// payload when the board boots.
+    pub fn load_snapshot(&self, dir: &Path) -> Result<()> {
+        self.check_restorable()?;
+
+        let data = read_file(dir, FILE_BOARD)?;
+        let Ok((header, mut entries)) = SnapshotHeader::read_from_prefix(&data) else {
+            return error::InvalidSnapshot { msg: "bad header" }.fail();
+        };
+        if header.magic != SNAPSHOT_MAGIC || header.version != SNAPSHOT_VERSION {
+            return error::InvalidSnapshot { msg: "bad header" }.fail();
+        }
+        self.check_config(header.num_cpus, header.mem_size)?;
+        let mut ram = Vec::new();
+        while !entries.is_empty() {
+            let Ok((entry, remain)) = RamEntry::read_from_prefix(entries) else {
+                return error::InvalidSnapshot { msg: "bad header" }.fail();
+            };
+            ram.push((entry.gpa, entry.size));
+            entries = remain;
+        }
+
+        let mut vcpus = Vec::new();
+        for index in 0..self.config.cpu.count {
+            vcpus.push(read_file(dir, &vcpu_name(index))?);
+        }
+
+        let mut devices = HashMap::new();
+        for entry in fs::read_dir(dir).context(error::SnapshotFile { path: dir })? {
+            let entry = entry.context(error::SnapshotFile { path: dir })?;
+            let Ok(name) = entry.file_name().into_string() else {
+                continue;
+            };
+            if is_dev_name(&name) {
+                let data = read_file(dir, &name)?;
+                devices.insert(name, data);
+            }
+        }
+
+        *self.snapshot.lock() = Some(SnapshotData {
+            source: SnapshotSource::Dir(dir.into()),
+            ram,
+            state: SavedState {
+                clock: header.clock,
+                vcpus,
+                devices,
+            },
+        });
+        Ok(())
+    }
+
+    /// Queues the state arriving from a migration source to be restored at
+    /// boot. `ram` is the layout of guest RAM on the source.
+    pub(super) fn load_incoming(&self, stream: MigrationStream, ram: Vec<(u64, u64)>) {
+        *self.snapshot.lock() = Some(SnapshotData {
+            source: SnapshotSource::Incoming(stream),
+            ram,
+            state: SavedState::default(),
+        });
+    }
+
+    /// Checks that the board is a non-confidential VM yet to boot.
+    pub(super) fn check_restorable(&self) -> Result<()> {
+        if cfg!(not(target_arch = "x86_64")) {
+            return error::SnapshotUnsupported {
+                msg: std::env::consts::ARCH,
+            }
+            .fail();
+        }
+        if self.config.coco.is_some() {
+            return error::SnapshotUnsupported {
+                msg: "confidential VMs",
+            }
+            .fail();
+        }
+        let mp_sync = self.mp_sync.lock();
+        if mp_sync.state != BoardState::Paused || mp_sync.booted {
+            return error::Booted.fail();
+        }
+        Ok(())
+    }
+
+    /// Checks that a saved state comes from a board of the same shape.
+    pub(super) fn check_config(&self, num_cpus: u32, mem_size: u64) -> Result<()> {
+        if num_cpus != self.config.cpu.count as u32 {
+            return error::InvalidSnapshot {
+                msg: "number of CPUs mismatch",
+            }
+            .fail();
+        }
+        if mem_size != self.config.mem.size {
+            return error::InvalidSnapshot {
+                msg: "memory size mismatch",
+            }
+            .fail();
+        }
+        Ok(())
+    }
+
+    pub(super) fn has_snapshot(&self) -> bool {
+        self.snapshot.lock().is_some()
+    }
+
+    /// Takes the saved state of VCPU `index` from a pending snapshot.
+    pub(super) fn take_vcpu_snapshot(&self, index: u16) -> Option<Vec<u8>> {
+        let mut snapshot = self.snapshot.lock();
+        let snapshot = snapshot.as_mut()?;
+        snapshot.state.vcpus.get_mut(index as usize).map(take)
+    }
+
+    pub(super) fn clear_snapshot(&self) {
+        self.snapshot.lock().take();
+    }
+
+    fn restore_pci_dev(&self, bdf: Bdf, dev: &dyn Pci, data: &[u8]) -> Result<()> {
+        let size = dev.config().size() as usize;
+        let Some((config, dev_data)) = data.split_at_checked(size) else {
+            return error::InvalidSnapshot {
+                msg: format!("bad PCI config space of {bdf}"),
+            }
+            .fail();
+        };
+        let name = dev.name();
+        dev.restore(dev_data)
+            .context(error::SnapshotDevice { name })?;
+
+        // Replay BARs and capabilities through the MMIO bus so that BARs are
+        // mapped. Other registers of the header are read-only. The command
+        // register is written last to enable decoding after BARs are in
+        // place.
+        let base = PCIE_CONFIG_START + ((bdf.0 as u64) << 12);
+        let header_size = size_of::<DeviceHeader>();
+        for (index, b) in config.chunks_exact(4).enumerate() {
+            let offset = index * 4;
+            if offset < header_size && !(OFFSET_BAR0..=OFFSET_BAR5).contains(&offset) {
+                continue;
+            }
+            let val = u32::from_le_bytes([b[0], b[1], b[2], b[3]]);
+            self.memory
+                .handle_mmio(base + offset as u64, Some(val as u64), 4)?;
+        }
+        let (offset, size) = CommonHeader::LAYOUT_COMMAND;
+        let command = u16::from_le_bytes([config[offset], config[offset + 1]]);
+        self.memory
+            .handle_mmio(base + offset as u64, Some(command as u64), size as u8)?;
+        Ok(())
+    }
+
+    /// Overwrites guest RAM and device states with a pending snapshot.
+    pub(super) fn restore_snapshot(&self) -> Result<()> {
+        let mut snapshot = self.snapshot.lock();
+        let Some(snapshot) = snapshot.as_mut() else {
+            return Ok(());
+        };
+
+        let ram = self.ram_layout();
+        if ram != snapshot.ram {
+            return error::InvalidSnapshot {
+                msg: "memory layout mismatch",
+            }
+            .fail();
+        }
+        let state = &mut snapshot.state;
+        match &mut snapshot.source {
+            SnapshotSource::Dir(dir) => {
+                let ram_bus = self.memory.ram_bus();
+                for (gpa, size) in ram {
+                    let path = dir.join(ram_name(gpa));
+                    let file = File::open(&path).context(error::SnapshotFile { path: &*path })?;
+                    let len = file.metadata().context(error::SnapshotFile { path })?.len();
+                    if len != size {
+                        return error::InvalidSnapshot {
+                            msg: format!("size of RAM at {gpa:#x} mismatch"),
+                        }
+                        .fail();
+                    }
+                    ram_bus.write_range(gpa, size, BufReader::new(file))?;
+                }
+                self.restore_state(state)
+            }
+            SnapshotSource::Incoming(stream) => {
+                self.receive_migration(stream, state)?;
+                self.restore_state(state)?;
+                self.ack_migration(stream)
+            }
+        }
+    }
+
+    fn restore_state(&self, state: &mut SavedState) -> Result<()> {
+        if state.vcpus.len() != self.config.cpu.count as usize {
+            return error::InvalidSnapshot {
+                msg: "number of CPUs mismatch",
+            }
+            .fail();
+        }
+        let devices = &mut state.devices;
+        for (port, dev) in self.io_devs.read().iter() {
+            restore_mmio_dev(devices, io_dev_name(*port), dev.as_ref())?;
+        }
+        for (addr, dev) in self.mmio_devs.read().iter() {
+            restore_mmio_dev(devices, mmio_dev_name(*addr), dev.as_ref())?;
+        }
+        for (bdf, dev) in self.pci_bus.segment.devices() {
+            let name = pci_dev_name(bdf);
+            let Some(data) = devices.remove(&name) else {
+                return error::InvalidSnapshot {
+                    msg: format!("missing device {name}"),
+                }
+                .fail();
+            };
+            self.restore_pci_dev(bdf, dev.as_ref(), &data)?;
+        }
+        if let Some(name) = devices.keys().next() {
+            return error::InvalidSnapshot {
+                msg: format!("unknown device {name}"),
+            }
+            .fail();
+        }
+
+        self.set_guest_clock(state.clock)
+    }
+}
+
+#[cfg(test)]
+#[path = "snapshot_test.rs"]
+mod tests;
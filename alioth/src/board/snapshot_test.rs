@@ -0,0 +1,44 @@
+// Copyright 2026 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::fs;
+
+use tempfile::TempDir;
+
+use crate::board::snapshot::clear_dir;
+
+#[test]
+fn test_clear_dir() {
+    let dir = TempDir::new().unwrap();
+    let owned = [
+        "board",
+        "ram-0x0",
+        "vcpu-1",
+        "io-0x3f8",
+        "mmio-0x1000",
+        "pci-00:01.0",
+    ];
+    for name in owned.iter().chain(&["notes.txt", "ram"]) {
+        fs::write(dir.path().join(name), b"data").unwrap();
+    }
+
+    clear_dir(dir.path()).unwrap();
+
+    let mut names: Vec<_> = fs::read_dir(dir.path())
+        .unwrap()
+        .map(|e| e.unwrap().file_name().into_string().unwrap())
+        .collect();
+    names.sort();
+    assert_eq!(names, ["notes.txt", "ram"]);
+}
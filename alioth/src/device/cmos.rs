@@ -18,7 +18,7 @@ use bitfield::bitfield;
 use chrono::{DateTime, Datelike, Timelike};
 
 use crate::device::clock::Clock;
-use crate::device::{MmioDev, Pause, Result};
+use crate::device::{MmioDev, Pause, Result, Snapshot, error};
 use crate::mem::emulated::{Action, Mmio};
 use crate::{bitflags, consts, mem};
 
@@ -150,6 +150,20 @@ impl<C: Clock> Pause for Cmos<C> {
     }
 }
 
+impl<C: Clock> Snapshot for Cmos<C> {
+    fn snapshot(&self) -> Result<Vec<u8>> {
+        Ok(vec![self.reg.load(Ordering::Relaxed)])
+    }
+
+    fn restore(&self, data: &[u8]) -> Result<()> {
+        let &[reg] = data else {
+            return error::InvalidSnapshot.fail();
+        };
+        self.reg.store(reg, Ordering::Relaxed);
+        Ok(())
+    }
+}
+
 impl<C: Clock> MmioDev for Cmos<C> {}
 
 #[cfg(test)]
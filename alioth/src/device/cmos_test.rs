@@ -80,3 +80,19 @@ fn test_cmos_upgrade_in_progress() {
         "CMOS update should be in progress"
     );
 }
+
+#[test]
+fn test_cmos_snapshot_restore() {
+    use crate::device::{Error, Snapshot};
+
+    let now = DateTime::from_timestamp_nanos(1_762_559_098_010_000_000);
+    let cmos = Cmos::new(TestClock { now });
+    assert_matches!(cmos.write(0x0, 1, 0xb), Ok(_));
+    let data = cmos.snapshot().unwrap();
+
+    let restored = Cmos::new(TestClock { now });
+    assert_matches!(restored.restore(&data), Ok(()));
+    assert_matches!(restored.read(0x0, 1), Ok(0xb));
+
+    assert_matches!(restored.restore(&[]), Err(Error::InvalidSnapshot { .. }));
+}
@@ -12,18 +12,28 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use std::ffi::CStr;
 use std::fmt::Debug;
+use std::fs::{self, File, OpenOptions};
 use std::io::{self, ErrorKind, Read, Write};
 use std::mem::MaybeUninit;
+use std::os::fd::{AsRawFd, FromRawFd, RawFd};
+use std::os::unix::fs::OpenOptionsExt;
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
 use std::thread::JoinHandle;
+use std::time::Duration;
 
 use libc::{
-    F_GETFL, F_SETFL, O_NONBLOCK, OPOST, STDIN_FILENO, STDOUT_FILENO, TCSANOW, cfmakeraw, fcntl,
-    tcgetattr, tcsetattr, termios,
+    F_GETFL, F_SETFL, O_NOCTTY, O_NONBLOCK, O_RDWR, OPOST, STDIN_FILENO, STDOUT_FILENO, TCSANOW,
+    cfmakeraw, fcntl, grantpt, posix_openpt, tcgetattr, tcsetattr, termios, unlockpt,
 };
 use mio::unix::SourceFd;
 use mio::{Events, Interest, Poll, Registry, Token};
+use parking_lot::Mutex;
+use serde::Deserialize;
+use serde_aco::Help;
 
 use crate::device::Result;
 use crate::ffi;
@@ -33,65 +43,106 @@ pub trait Console: Debug + Send + Sync + 'static {
     const TOKEN_INPUT: Token;
     fn activate(&self, registry: &Registry) -> io::Result<()>;
     fn deactivate(&self, registry: &Registry) -> io::Result<()>;
+    /// Handles an event of a token other than [`Console::TOKEN_INPUT`].
+    fn handle_event(&self, _token: Token, _registry: &Registry) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Help)]
+pub struct ConsolePathParam {
+    /// Path to the file.
+    pub path: Box<Path>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize, Help)]
+pub enum ConsoleParam {
+    /// Standard input and output of the current process.
+    #[default]
+    #[serde(alias = "stdio")]
+    Stdio,
+    /// A new pseudoterminal. The path of its secondary side is printed.
+    #[serde(alias = "pty")]
+    Pty,
+    /// A Unix domain socket accepting one client at a time.
+    #[serde(alias = "uds")]
+    Uds(ConsolePathParam),
+    /// Append guest output to a file and ignore input.
+    #[serde(alias = "file")]
+    File(ConsolePathParam),
+    /// Discard guest output and ignore input.
+    #[serde(alias = "null")]
+    Null,
 }
 
 #[derive(Debug)]
-struct StdinBackup {
+struct TermiosBackup {
+    fd: RawFd,
     termios: Option<termios>,
     flag: Option<i32>,
 }
 
-impl StdinBackup {
-    fn new() -> StdinBackup {
+impl TermiosBackup {
+    fn new(fd: RawFd) -> TermiosBackup {
         let mut termios_backup = None;
         let mut t = MaybeUninit::uninit();
-        match ffi!(unsafe { tcgetattr(STDIN_FILENO, t.as_mut_ptr()) }) {
+        match ffi!(unsafe { tcgetattr(fd, t.as_mut_ptr()) }) {
             Ok(_) => termios_backup = Some(unsafe { t.assume_init() }),
             Err(e) => log::error!("tcgetattr() failed: {e:?}"),
         }
         let mut flag_backup = None;
-        match ffi! { unsafe { fcntl(STDIN_FILENO, F_GETFL) } } {
+        match ffi! { unsafe { fcntl(fd, F_GETFL) } } {
             Ok(f) => flag_backup = Some(f),
-            Err(e) => log::error!("fcntl(STDIN_FILENO, F_GETFL) failed: {e:?}"),
+            Err(e) => log::error!("fcntl({fd}, F_GETFL) failed: {e:?}"),
         }
-        StdinBackup {
+        TermiosBackup {
+            fd,
             termios: termios_backup,
             flag: flag_backup,
         }
     }
 }
 
-impl Drop for StdinBackup {
+impl Drop for TermiosBackup {
     fn drop(&mut self) {
         if let Some(t) = self.termios.take()
-            && let Err(e) = ffi!(unsafe { tcsetattr(STDIN_FILENO, TCSANOW, &t) })
+            && let Err(e) = ffi!(unsafe { tcsetattr(self.fd, TCSANOW, &t) })
         {
             log::error!("Restoring termios: {e:?}");
         }
         if let Some(f) = self.flag.take()
-            && let Err(e) = ffi!(unsafe { fcntl(STDIN_FILENO, F_SETFL, f) })
+            && let Err(e) = ffi!(unsafe { fcntl(self.fd, F_SETFL, f) })
         {
-            log::error!("Restoring stdin flag to {f:#x}: {e:?}")
+            log::error!("Restoring flag of {} to {f:#x}: {e:?}", self.fd)
         }
     }
 }
 
+fn set_raw_mode(fd: RawFd) -> Result<()> {
+    let mut raw_termios = MaybeUninit::uninit();
+    ffi!(unsafe { tcgetattr(fd, raw_termios.as_mut_ptr()) })?;
+    unsafe { cfmakeraw(raw_termios.as_mut_ptr()) };
+    unsafe { raw_termios.assume_init_mut().c_oflag |= OPOST };
+    ffi!(unsafe { tcsetattr(fd, TCSANOW, raw_termios.as_ptr()) })?;
+    Ok(())
+}
+
+fn set_nonblocking(fd: RawFd) -> Result<()> {
+    let flag = ffi!(unsafe { fcntl(fd, F_GETFL) })?;
+    ffi!(unsafe { fcntl(fd, F_SETFL, flag | O_NONBLOCK) })?;
+    Ok(())
+}
+
 #[derive(Debug)]
 pub struct StdioConsole {
-    _backup: StdinBackup,
+    _backup: TermiosBackup,
 }
 
 impl StdioConsole {
     pub fn new() -> Result<Self> {
-        let backup = StdinBackup::new();
-        let mut raw_termios = MaybeUninit::uninit();
-        ffi!(unsafe { tcgetattr(STDIN_FILENO, raw_termios.as_mut_ptr()) })?;
-        unsafe { cfmakeraw(raw_termios.as_mut_ptr()) };
-        unsafe { raw_termios.assume_init_mut().c_oflag |= OPOST };
-        ffi!(unsafe { tcsetattr(STDIN_FILENO, TCSANOW, raw_termios.as_ptr()) })?;
-
-        let flag = ffi!(unsafe { fcntl(STDIN_FILENO, F_GETFL) })?;
-        ffi!(unsafe { fcntl(STDIN_FILENO, F_SETFL, flag | O_NONBLOCK) })?;
+        let backup = TermiosBackup::new(STDIN_FILENO);
+        set_raw_mode(STDIN_FILENO)?;
+        set_nonblocking(STDIN_FILENO)?;
         Ok(StdioConsole { _backup: backup })
     }
 }
@@ -131,11 +182,371 @@ impl Console for StdioConsole {
     }
 }
 
+#[cfg(target_os = "linux")]
+fn pts_path(fd: RawFd) -> io::Result<PathBuf> {
+    let mut buf = [0u8; 128];
+    let ret = unsafe { libc::ptsname_r(fd, buf.as_mut_ptr() as _, buf.len()) };
+    if ret != 0 {
+        return Err(io::Error::from_raw_os_error(ret));
+    }
+    let name = CStr::from_bytes_until_nul(&buf).map_err(|_| ErrorKind::InvalidData)?;
+    Ok(PathBuf::from(name.to_string_lossy().as_ref()))
+}
+
+#[cfg(target_os = "macos")]
+fn pts_path(fd: RawFd) -> io::Result<PathBuf> {
+    let mut buf = [0u8; 128];
+    ffi!(unsafe { libc::ioctl(fd, libc::TIOCPTYGNAME as _, buf.as_mut_ptr()) })?;
+    let name = CStr::from_bytes_until_nul(&buf).map_err(|_| ErrorKind::InvalidData)?;
+    Ok(PathBuf::from(name.to_string_lossy().as_ref()))
+}
+
+#[derive(Debug)]
+pub struct PtyConsole {
+    primary: File,
+    // Keeps the pseudoterminal alive when no one has opened it.
+    _secondary: File,
+    path: Box<Path>,
+}
+
+impl PtyConsole {
+    pub fn new() -> Result<Self> {
+        let fd = ffi!(unsafe { posix_openpt(O_RDWR | O_NOCTTY | O_NONBLOCK) })?;
+        let primary = unsafe { File::from_raw_fd(fd) };
+        ffi!(unsafe { grantpt(fd) })?;
+        ffi!(unsafe { unlockpt(fd) })?;
+        let path = pts_path(fd)?;
+        let secondary = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .custom_flags(O_NOCTTY)
+            .open(&path)?;
+        set_raw_mode(secondary.as_raw_fd())?;
+        Ok(PtyConsole {
+            primary,
+            _secondary: secondary,
+            path: path.into(),
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Read for &PtyConsole {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        (&self.primary).read(buf)
+    }
+}
+
+impl Write for &PtyConsole {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match (&self.primary).write(buf) {
+            // Drops the output if no one is reading it.
+            Err(e) if e.kind() == ErrorKind::WouldBlock => Ok(buf.len()),
+            r => r,
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Console for PtyConsole {
+    const TOKEN_INPUT: Token = Token(0);
+
+    fn activate(&self, registry: &Registry) -> io::Result<()> {
+        registry.register(
+            &mut SourceFd(&self.primary.as_raw_fd()),
+            Self::TOKEN_INPUT,
+            Interest::READABLE,
+        )
+    }
+
+    fn deactivate(&self, registry: &Registry) -> io::Result<()> {
+        registry.deregister(&mut SourceFd(&self.primary.as_raw_fd()))
+    }
+}
+
+#[derive(Debug)]
+pub struct UdsConsole {
+    listener: UnixListener,
+    conn: Mutex<Option<UnixStream>>,
+    path: Box<Path>,
+}
+
+impl UdsConsole {
+    const TOKEN_LISTENER: Token = Token(1);
+
+    pub fn new(path: impl Into<Box<Path>>) -> Result<Self> {
+        let path = path.into();
+        let listener = UnixListener::bind(&path)?;
+        listener.set_nonblocking(true)?;
+        Ok(UdsConsole {
+            listener,
+            conn: Mutex::new(None),
+            path,
+        })
+    }
+}
+
+impl Drop for UdsConsole {
+    fn drop(&mut self) {
+        if let Err(e) = fs::remove_file(&self.path) {
+            log::error!("Removing {:?}: {e:?}", self.path);
+        }
+    }
+}
+
+impl Read for &UdsConsole {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let mut conn = self.conn.lock();
+        let Some(stream) = conn.as_mut() else {
+            return Ok(0);
+        };
+        match stream.read(buf) {
+            Ok(0) => {
+                log::info!("{:?}: client disconnected", self.path);
+                *conn = None;
+                Ok(0)
+            }
+            Ok(len) => Ok(len),
+            Err(e) if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::Interrupted) => Err(e),
+            Err(e) => {
+                log::info!("{:?}: client disconnected: {e}", self.path);
+                *conn = None;
+                Ok(0)
+            }
+        }
+    }
+}
+
+impl Write for &UdsConsole {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mut conn = self.conn.lock();
+        let Some(stream) = conn.as_mut() else {
+            return Ok(buf.len());
+        };
+        match stream.write(buf) {
+            Ok(len) => Ok(len),
+            Err(e) if e.kind() == ErrorKind::WouldBlock => Ok(buf.len()),
+            Err(e) => {
+                log::info!("{:?}: client disconnected: {e}", self.path);
+                *conn = None;
+                Ok(buf.len())
+            }
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Console for UdsConsole {
+    const TOKEN_INPUT: Token = Token(0);
+
+    fn activate(&self, registry: &Registry) -> io::Result<()> {
+        registry.register(
+            &mut SourceFd(&self.listener.as_raw_fd()),
+            Self::TOKEN_LISTENER,
+            Interest::READABLE,
+        )
+    }
+
+    fn deactivate(&self, registry: &Registry) -> io::Result<()> {
+        if let Some(stream) = self.conn.lock().take() {
+            registry.deregister(&mut SourceFd(&stream.as_raw_fd()))?;
+        }
+        registry.deregister(&mut SourceFd(&self.listener.as_raw_fd()))
+    }
+
+    fn handle_event(&self, token: Token, registry: &Registry) -> io::Result<()> {
+        if token != Self::TOKEN_LISTENER {
+            return Ok(());
+        }
+        loop {
+            let stream = match self.listener.accept() {
+                Ok((stream, _)) => stream,
+                Err(e) if e.kind() == ErrorKind::WouldBlock => break Ok(()),
+                Err(e) => break Err(e),
+            };
+            stream.set_nonblocking(true)?;
+            registry.register(
+                &mut SourceFd(&stream.as_raw_fd()),
+                Self::TOKEN_INPUT,
+                Interest::READABLE,
+            )?;
+            log::info!("{:?}: client connected", self.path);
+            if let Some(prev) = self.conn.lock().replace(stream) {
+                registry.deregister(&mut SourceFd(&prev.as_raw_fd()))?;
+            }
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct FileConsole {
+    file: File,
+}
+
+impl FileConsole {
+    pub fn new(path: impl AsRef<Path>) -> Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(FileConsole { file })
+    }
+}
+
+impl Read for &FileConsole {
+    fn read(&mut self, _: &mut [u8]) -> io::Result<usize> {
+        Ok(0)
+    }
+}
+
+impl Write for &FileConsole {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        (&self.file).write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        (&self.file).flush()
+    }
+}
+
+impl Console for FileConsole {
+    const TOKEN_INPUT: Token = Token(0);
+
+    fn activate(&self, _: &Registry) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn deactivate(&self, _: &Registry) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[derive(Debug)]
+pub struct NullConsole;
+
+impl Read for &NullConsole {
+    fn read(&mut self, _: &mut [u8]) -> io::Result<usize> {
+        Ok(0)
+    }
+}
+
+impl Write for &NullConsole {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Console for NullConsole {
+    const TOKEN_INPUT: Token = Token(0);
+
+    fn activate(&self, _: &Registry) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn deactivate(&self, _: &Registry) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// A console backend selected at runtime.
+#[derive(Debug)]
+pub enum ConsoleBackend {
+    Stdio(StdioConsole),
+    Pty(PtyConsole),
+    Uds(UdsConsole),
+    File(FileConsole),
+    Null(NullConsole),
+}
+
+impl ConsoleBackend {
+    pub fn new(param: &ConsoleParam) -> Result<Self> {
+        let backend = match param {
+            ConsoleParam::Stdio => ConsoleBackend::Stdio(StdioConsole::new()?),
+            ConsoleParam::Pty => ConsoleBackend::Pty(PtyConsole::new()?),
+            ConsoleParam::Uds(p) => ConsoleBackend::Uds(UdsConsole::new(p.path.clone())?),
+            ConsoleParam::File(p) => ConsoleBackend::File(FileConsole::new(&p.path)?),
+            ConsoleParam::Null => ConsoleBackend::Null(NullConsole),
+        };
+        Ok(backend)
+    }
+
+    /// Returns the path of the pseudoterminal if the backend is a pty.
+    pub fn pty_path(&self) -> Option<&Path> {
+        match self {
+            ConsoleBackend::Pty(c) => Some(c.path()),
+            _ => None,
+        }
+    }
+}
+
+macro_rules! dispatch {
+    ($backend:expr, $c:ident => $e:expr) => {
+        match $backend {
+            ConsoleBackend::Stdio($c) => $e,
+            ConsoleBackend::Pty($c) => $e,
+            ConsoleBackend::Uds($c) => $e,
+            ConsoleBackend::File($c) => $e,
+            ConsoleBackend::Null($c) => $e,
+        }
+    };
+}
+
+impl Read for &ConsoleBackend {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        dispatch!(self, c => Read::read(&mut &*c, buf))
+    }
+}
+
+impl Write for &ConsoleBackend {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        dispatch!(self, c => Write::write(&mut &*c, buf))
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        dispatch!(self, c => Write::flush(&mut &*c))
+    }
+}
+
+impl Console for ConsoleBackend {
+    const TOKEN_INPUT: Token = Token(0);
+
+    fn activate(&self, registry: &Registry) -> io::Result<()> {
+        dispatch!(self, c => c.activate(registry))
+    }
+
+    fn deactivate(&self, registry: &Registry) -> io::Result<()> {
+        dispatch!(self, c => c.deactivate(registry))
+    }
+
+    fn handle_event(&self, token: Token, registry: &Registry) -> io::Result<()> {
+        dispatch!(self, c => c.handle_event(token, registry))
+    }
+}
+
 pub trait UartRecv: Send + 'static {
     fn receive(&self, bytes: &[u8]);
+
+    /// Returns the time left before [`UartRecv::handle_timeout`] should be
+    /// called, or `None` if no timeout is pending.
+    fn next_timeout(&self) -> Option<Duration> {
+        None
+    }
+
+    fn handle_timeout(&self) {}
 }
 
 const TOKEN_SHUTDOWN: Token = Token(1 << 63);
+const TOKEN_WAKE: Token = Token((1 << 63) - 1);
 
 struct ThreadWorker<U, C> {
     name: Arc<str>,
@@ -157,6 +568,7 @@ where
             match self.console.as_ref().read(&mut buf) {
                 Ok(0) => break,
                 Err(e) if e.kind() == ErrorKind::WouldBlock => break,
+                Err(e) if e.kind() == ErrorKind::Interrupted => continue,
                 Ok(len) => {
                     self.uart.receive(&buf[0..len]);
                     total_size += len;
@@ -170,13 +582,20 @@ where
     fn do_work_inner(&mut self) -> Result<()> {
         let mut events = Events::with_capacity(16);
         loop {
-            self.poll.poll(&mut events, None)?;
+            let timeout = self.uart.next_timeout();
+            self.poll.poll(&mut events, timeout)?;
             for event in events.iter() {
-                if event.token() != C::TOKEN_INPUT {
-                    return Ok(());
+                match event.token() {
+                    TOKEN_SHUTDOWN => return Ok(()),
+                    // The timeout is recomputed in the next iteration.
+                    TOKEN_WAKE => {}
+                    token if token == C::TOKEN_INPUT => {
+                        self.read_input()?;
+                    }
+                    token => self.console.handle_event(token, self.poll.registry())?,
                 }
-                self.read_input()?;
             }
+            self.uart.handle_timeout();
         }
     }
 
@@ -193,6 +612,7 @@ pub struct ConsoleThread {
     pub name: Arc<str>,
     worker_thread: Option<JoinHandle<()>>,
     exit_notifier: Notifier,
+    wake_notifier: Notifier,
 }
 
 impl ConsoleThread {
@@ -206,6 +626,8 @@ impl ConsoleThread {
         let registry = poll.registry();
         let mut notifier = Notifier::new()?;
         registry.register(&mut notifier, TOKEN_SHUTDOWN, Interest::READABLE)?;
+        let mut wake_notifier = Notifier::new()?;
+        registry.register(&mut wake_notifier, TOKEN_WAKE, Interest::READABLE)?;
         console.activate(registry)?;
         let mut worker = ThreadWorker {
             name: name.clone(),
@@ -220,9 +642,17 @@ impl ConsoleThread {
             name,
             worker_thread: Some(worker_thread),
             exit_notifier: notifier,
+            wake_notifier,
         };
         Ok(console)
     }
+
+    /// Wakes up the thread to query [`UartRecv::next_timeout`] again.
+    pub fn wake(&self) {
+        if let Err(e) = self.wake_notifier.notify() {
+            log::error!("{}: {e:?}", self.name);
+        }
+    }
 }
 
 impl Drop for ConsoleThread {
@@ -13,13 +13,21 @@
 // limitations under the License.
 
 use std::collections::VecDeque;
+use std::fs::{self, OpenOptions};
 use std::io::{self, Read, Write};
+use std::os::unix::net::UnixStream;
+use std::sync::Arc;
+use std::sync::mpsc::{self, Sender};
+use std::time::Duration;
 
 use mio::Token;
 use parking_lot::Mutex;
+use tempfile::tempdir;
 
 use crate::device::Result;
-use crate::device::console::Console;
+use crate::device::console::{
+    Console, ConsoleThread, FileConsole, NullConsole, PtyConsole, UartRecv, UdsConsole,
+};
 use crate::sync::notifier::Notifier;
 
 #[derive(Debug)]
@@ -70,3 +78,86 @@ impl Console for TestConsole {
         registry.deregister(&mut *self.notifier.lock())
     }
 }
+
+struct ChannelRecv(Mutex<Sender<Vec<u8>>>);
+
+impl UartRecv for ChannelRecv {
+    fn receive(&self, bytes: &[u8]) {
+        self.0.lock().send(bytes.to_vec()).unwrap();
+    }
+}
+
+#[test]
+fn test_uds_console() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("console.sock");
+    let console = Arc::new(UdsConsole::new(path.as_path()).unwrap());
+    let (tx, rx) = mpsc::channel();
+    let recv = ChannelRecv(Mutex::new(tx));
+    let thread = ConsoleThread::new("uds".into(), recv, console.clone()).unwrap();
+
+    assert_eq!(console.as_ref().write(b"lost").unwrap(), 4);
+
+    let mut client = UnixStream::connect(&path).unwrap();
+    client.write_all(b"hello").unwrap();
+    let input = rx.recv_timeout(Duration::from_secs(5)).unwrap();
+    assert_eq!(input, b"hello");
+
+    console.as_ref().write_all(b"world").unwrap();
+    let mut buf = [0u8; 5];
+    client.read_exact(&mut buf).unwrap();
+    assert_eq!(&buf, b"world");
+
+    // Closing a socket with unread data resets the connection.
+    console.as_ref().write_all(b"unread").unwrap();
+    drop(client);
+
+    let mut client = UnixStream::connect(&path).unwrap();
+    client.write_all(b"again").unwrap();
+    let input = rx.recv_timeout(Duration::from_secs(5)).unwrap();
+    assert_eq!(input, b"again");
+
+    drop(thread);
+    drop(console);
+    assert!(!path.exists());
+}
+
+#[test]
+fn test_pty_console() {
+    let console = Arc::new(PtyConsole::new().unwrap());
+    let (tx, rx) = mpsc::channel();
+    let recv = ChannelRecv(Mutex::new(tx));
+    let _thread = ConsoleThread::new("pty".into(), recv, console.clone()).unwrap();
+
+    let mut pts = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open(console.path())
+        .unwrap();
+    pts.write_all(b"hello").unwrap();
+    let input = rx.recv_timeout(Duration::from_secs(5)).unwrap();
+    assert_eq!(input, b"hello");
+
+    console.as_ref().write_all(b"world").unwrap();
+    let mut buf = [0u8; 5];
+    pts.read_exact(&mut buf).unwrap();
+    assert_eq!(&buf, b"world");
+}
+
+#[test]
+fn test_file_console() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("console.log");
+    fs::write(&path, b"hello ").unwrap();
+
+    let console = FileConsole::new(&path).unwrap();
+    (&console).write_all(b"world").unwrap();
+    assert_eq!((&console).read(&mut [0u8; 4]).unwrap(), 0);
+    assert_eq!(fs::read(&path).unwrap(), b"hello world");
+}
+
+#[test]
+fn test_null_console() {
+    assert_eq!((&NullConsole).write(b"hello").unwrap(), 5);
+    assert_eq!((&NullConsole).read(&mut [0u8; 4]).unwrap(), 0);
+}
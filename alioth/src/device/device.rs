@@ -39,6 +39,12 @@ pub enum Error {
     System { error: std::io::Error },
     #[snafu(display("Device is not pausable"))]
     NotPausable,
+    #[snafu(display("Device does not support snapshots"))]
+    NotSnapshotable,
+    #[snafu(display("Invalid device snapshot"))]
+    InvalidSnapshot,
+    #[snafu(display("Virtio device error"), context(false))]
+    Virtio { source: Box<crate::virtio::Error> },
 }
 
 pub type Result<T, E = Error> = std::result::Result<T, E>;
@@ -52,4 +58,14 @@ pub trait Pause {
     }
 }
 
-pub trait MmioDev: Mmio + Pause {}
+/// Saves and restores the state of a device while the VM is paused.
+pub trait Snapshot {
+    fn snapshot(&self) -> Result<Vec<u8>> {
+        error::NotSnapshotable.fail()
+    }
+    fn restore(&self, _data: &[u8]) -> Result<()> {
+        error::NotSnapshotable.fail()
+    }
+}
+
+pub trait MmioDev: Mmio + Pause + Snapshot {}
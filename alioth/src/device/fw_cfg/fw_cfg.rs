@@ -34,7 +34,7 @@ use zerocopy::{FromBytes, Immutable, IntoBytes};
 use crate::arch::layout::{
     PORT_FW_CFG_DATA, PORT_FW_CFG_DMA_HI, PORT_FW_CFG_DMA_LO, PORT_FW_CFG_SELECTOR,
 };
-use crate::device::{self, MmioDev, Pause};
+use crate::device::{self, MmioDev, Pause, Snapshot};
 use crate::firmware::acpi::AcpiTable;
 #[cfg(target_arch = "x86_64")]
 use crate::loader::linux::bootparams::{
@@ -201,6 +201,15 @@ pub struct FwCfg {
     memory: Arc<RamBus>,
 }
 
+#[repr(C)]
+#[derive(Debug, IntoBytes, FromBytes, Immutable)]
+struct FwCfgState {
+    dma_address: u64,
+    data_offset: u32,
+    selector: u16,
+    _reserved: u16,
+}
+
 #[repr(C)]
 #[derive(Debug, IntoBytes, FromBytes, Immutable, Layout)]
 struct FwCfgDmaAccess {
@@ -288,7 +297,7 @@ impl FwCfg {
                 MemRegionType::Reserved => E820_RESERVED,
                 MemRegionType::Acpi => E820_ACPI,
                 MemRegionType::Pmem => E820_PMEM,
-                MemRegionType::Hidden => continue,
+                MemRegionType::Hidden | MemRegionType::Hotplug => continue,
             };
             let entry = BootE820Entry {
                 addr: *addr,
@@ -346,10 +355,20 @@ impl FwCfg {
         self.known_items[FW_CFG_CMDLINE_DATA as usize] = FwCfgContent::Bytes(bytes);
     }
 
+    /// Adds a file, or replaces the content of the file with the same name,
+    /// e.g. when the ACPI tables are re-created on a reboot.
     pub fn add_item(&mut self, item: FwCfgItem) -> Result<()> {
+        let size = item.content.size()?;
+        if let Some(index) = self.items.iter().position(|i| i.name == item.name) {
+            let offset = size_of::<FwCfgFilesHeader>() + index * size_of::<FwCfgFile>();
+            let file_dir = self.get_file_dir_mut();
+            file_dir[offset..offset + size_of::<Bu32>()]
+                .copy_from_slice(Bu32::from(size).as_bytes());
+            self.items[index] = item;
+            return Ok(());
+        }
         let index = self.items.len();
         let c_name = create_file_name(&item.name);
-        let size = item.content.size()?;
         let cfg_file = FwCfgFile {
             size: size.into(),
             select: (FW_CFG_FILE_FIRST + index as u16).into(),
@@ -534,6 +553,30 @@ impl Pause for Mutex<FwCfg> {
     }
 }
 
+impl Snapshot for Mutex<FwCfg> {
+    fn snapshot(&self) -> device::Result<Vec<u8>> {
+        let fw_cfg = self.lock();
+        let state = FwCfgState {
+            dma_address: fw_cfg.dma_address,
+            data_offset: fw_cfg.data_offset,
+            selector: fw_cfg.selector,
+            _reserved: 0,
+        };
+        Ok(state.as_bytes().to_vec())
+    }
+
+    fn restore(&self, data: &[u8]) -> device::Result<()> {
+        let Ok(state) = FwCfgState::read_from_bytes(data) else {
+            return device::error::InvalidSnapshot.fail();
+        };
+        let mut fw_cfg = self.lock();
+        fw_cfg.dma_address = state.dma_address;
+        fw_cfg.data_offset = state.data_offset;
+        fw_cfg.selector = state.selector;
+        Ok(())
+    }
+}
+
 impl MmioDev for Mutex<FwCfg> {}
 
 #[derive(Debug, PartialEq, Eq, Deserialize, Help)]
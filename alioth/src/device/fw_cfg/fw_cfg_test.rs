@@ -14,12 +14,18 @@
 
 use std::fs::{self, File};
 use std::io::{self, Read};
+use std::sync::Arc;
 
 use assert_matches::assert_matches;
+use parking_lot::Mutex;
 use rstest::rstest;
 use tempfile::TempDir;
 
-use crate::device::fw_cfg::FwCfgContent;
+use crate::arch::layout::{PORT_FW_CFG_DATA, PORT_FW_CFG_DMA_HI, PORT_FW_CFG_SELECTOR};
+use crate::device::fw_cfg::{FW_CFG_FILE_DIR, FW_CFG_SIGNATURE, FwCfg, FwCfgContent, FwCfgItem};
+use crate::device::{Error, Snapshot};
+use crate::mem::emulated::Mmio;
+use crate::mem::mapped::RamBus;
 
 fn create_file_with_content(content: &str) -> io::Result<File> {
     let tmp_dir = TempDir::new()?;
@@ -109,3 +115,45 @@ fn test_fw_cfg_content_file_access() {
     let _ = fw_cfg_content.access(120).read(&mut buf);
     assert_eq!(&buf[..4], [0, 0, 0, 0]);
 }
+
+#[test]
+fn test_fw_cfg_snapshot_restore() {
+    let memory = Arc::new(RamBus::new());
+    let fw_cfg = Mutex::new(FwCfg::new(memory.clone(), vec![]).unwrap());
+    let offset_data = (PORT_FW_CFG_DATA - PORT_FW_CFG_SELECTOR) as u64;
+    let offset_dma_hi = (PORT_FW_CFG_DMA_HI - PORT_FW_CFG_SELECTOR) as u64;
+    assert_matches!(fw_cfg.write(0, 2, FW_CFG_SIGNATURE as u64), Ok(_));
+    assert_matches!(fw_cfg.read(offset_data, 1), Ok(0x51));
+    assert_matches!(fw_cfg.read(offset_data, 1), Ok(0x45));
+    assert_matches!(fw_cfg.write(offset_dma_hi, 4, 0x1234_5678), Ok(_));
+    let data = fw_cfg.snapshot().unwrap();
+
+    let restored = Mutex::new(FwCfg::new(memory, vec![]).unwrap());
+    assert_matches!(restored.restore(&data), Ok(()));
+    assert_matches!(restored.read(offset_dma_hi, 4), Ok(0x1234_5678));
+    assert_matches!(restored.read(offset_data, 1), Ok(0x4d));
+
+    assert_matches!(restored.restore(&[]), Err(Error::InvalidSnapshot { .. }));
+}
+
+#[test]
+fn test_fw_cfg_replace_item() {
+    let memory = Arc::new(RamBus::new());
+    let mut fw_cfg = FwCfg::new(memory, vec![]).unwrap();
+    for content in [vec![0u8; 3], vec![0u8; 5]] {
+        let item = FwCfgItem {
+            name: "etc/e820".to_owned(),
+            content: FwCfgContent::Bytes(content),
+        };
+        fw_cfg.add_item(item).unwrap();
+    }
+    let fw_cfg = Mutex::new(fw_cfg);
+    let offset_data = (PORT_FW_CFG_DATA - PORT_FW_CFG_SELECTOR) as u64;
+    assert_matches!(fw_cfg.write(0, 2, FW_CFG_FILE_DIR as u64), Ok(_));
+    let mut dir = vec![];
+    for _ in 0..8 {
+        dir.push(fw_cfg.read(offset_data, 1).unwrap() as u8);
+    }
+    // count and the size of the first file, both in big endian
+    assert_eq!(dir, [0, 0, 0, 1, 0, 0, 0, 5]);
+}
@@ -14,7 +14,7 @@
 
 use parking_lot::Mutex;
 
-use crate::device::{MmioDev, Pause, Result};
+use crate::device::{MmioDev, Pause, Result, Snapshot};
 use crate::mem;
 use crate::mem::emulated::{Action, Mmio};
 
@@ -60,4 +60,15 @@ impl Pause for FwDbg {
     }
 }
 
+impl Snapshot for FwDbg {
+    fn snapshot(&self) -> Result<Vec<u8>> {
+        Ok(self.buffer.lock().clone())
+    }
+
+    fn restore(&self, data: &[u8]) -> Result<()> {
+        *self.buffer.lock() = data.to_vec();
+        Ok(())
+    }
+}
+
 impl MmioDev for FwDbg {}
@@ -16,6 +16,7 @@
 //! See: https://download.intel.com/design/chipsets/datashts/29056601.pdf chapter 3.2
 
 use parking_lot::Mutex;
+use zerocopy::{FromBytes, Immutable, IntoBytes};
 
 use crate::arch::x86_64::intr::{DestinationMode, MsiAddrLo, MsiData, TriggerMode};
 use crate::arch::x86_64::ioapic::{
@@ -23,7 +24,7 @@ use crate::arch::x86_64::ioapic::{
     NUM_PINS, RedirectEntry, RegId, RegVer,
 };
 use crate::arch::x86_64::layout::{APIC_START, IOAPIC_END, IOAPIC_START};
-use crate::device::{self, MmioDev, Pause};
+use crate::device::{self, MmioDev, Pause, Snapshot, error};
 use crate::hv::MsiSender;
 use crate::mem;
 use crate::mem::emulated::{Action, Mmio};
@@ -35,6 +36,15 @@ struct IoApicRegs {
     select: u8,
 }
 
+#[repr(C)]
+#[derive(Debug, Default, IntoBytes, FromBytes, Immutable)]
+struct IoApicState {
+    redirtbl: [u64; NUM_PINS as usize],
+    id: u32,
+    select: u8,
+    _reserved: [u8; 3],
+}
+
 #[derive(Debug)]
 pub struct IoApic<M: MsiSender> {
     regs: Mutex<IoApicRegs>,
@@ -49,6 +59,11 @@ impl<M: MsiSender> IoApic<M> {
         }
     }
 
+    /// Brings the registers back to their power-on values.
+    pub fn reset(&self) {
+        *self.regs.lock() = IoApicRegs::default();
+    }
+
     pub fn service_pin(&self, pin: u8) -> crate::hv::Result<()> {
         let regs = self.regs.lock();
         let Some(entry) = regs.redirtbl.get(pin as usize) else {
@@ -178,6 +193,30 @@ impl<M: MsiSender> Pause for IoApic<M> {
     }
 }
 
+impl<M: MsiSender> Snapshot for IoApic<M> {
+    fn snapshot(&self) -> device::Result<Vec<u8>> {
+        let regs = self.regs.lock();
+        let state = IoApicState {
+            redirtbl: regs.redirtbl.map(|entry| entry.0),
+            id: regs.id.0,
+            select: regs.select,
+            ..Default::default()
+        };
+        Ok(state.as_bytes().to_vec())
+    }
+
+    fn restore(&self, data: &[u8]) -> device::Result<()> {
+        let Ok(state) = IoApicState::read_from_bytes(data) else {
+            return error::InvalidSnapshot.fail();
+        };
+        let mut regs = self.regs.lock();
+        regs.redirtbl = state.redirtbl.map(RedirectEntry);
+        regs.id = RegId(state.id);
+        regs.select = state.select;
+        Ok(())
+    }
+}
+
 impl<M: MsiSender> MmioDev for IoApic<M> {}
 
 #[cfg(test)]
@@ -49,6 +49,17 @@ fn test_ioapic_read_write() {
     assert_eq!(regs.redirtbl[0].0, 0xabcdef0012345678);
 }
 
+#[test]
+fn test_ioapic_reset() {
+    let io_apic = IoApic::new(TestMsiSender::default());
+    io_apic.write(IOREGSEL, 4, 0x10).unwrap();
+    io_apic.write(IOWIN, 4, 0x12345678).unwrap();
+
+    io_apic.reset();
+    assert_eq!(io_apic.read(IOREGSEL, 4).unwrap(), 0);
+    assert_eq!(io_apic.regs.lock().redirtbl[0].0, 0);
+}
+
 /// Configure redirection table entry for pin `pin` with `vector` and `dest`.
 /// physical, edge triggered.
 pub(crate) fn enable_pin<M: MsiSender>(io_apci: &IoApic<M>, pin: u8, vector: u8, dest: u8) {
@@ -84,3 +95,29 @@ fn test_ioapic_service_pin() {
     // Expected data: (trigger_mode=0 << 15) | (delivery_mode=0 << 8) | vector=0x24 = 0x24
     assert_matches!(messages.as_slice(), [(0xfee02000, 0x24)]);
 }
+
+#[test]
+fn test_ioapic_snapshot_restore() {
+    use crate::device::{Error, Snapshot};
+
+    let io_apic = IoApic::new(TestMsiSender::default());
+    io_apic.write(IOREGSEL, 4, 0x0).unwrap();
+    io_apic.write(IOWIN, 4, 0x0f00_0000).unwrap();
+    enable_pin(&io_apic, 4, 0x24, 2);
+    let data = io_apic.snapshot().unwrap();
+
+    let msi_sender = TestMsiSender::default();
+    let messages = msi_sender.messages.clone();
+    let restored = IoApic::new(msi_sender);
+    assert_matches!(restored.restore(&data), Ok(()));
+    assert_eq!(restored.read(IOREGSEL, 4).unwrap(), 0x19);
+    restored.write(IOREGSEL, 4, 0x0).unwrap();
+    assert_eq!(restored.read(IOWIN, 4).unwrap(), 0x0f00_0000);
+    restored.service_pin(4).unwrap();
+    assert_matches!(messages.lock().as_slice(), [(0xfee02000, 0x24)]);
+
+    assert_matches!(
+        restored.restore(&data[1..]),
+        Err(Error::InvalidSnapshot { .. })
+    );
+}
@@ -17,9 +17,10 @@ use std::io::{Read, Write};
 use std::sync::Arc;
 
 use parking_lot::Mutex;
+use zerocopy::{FromBytes, Immutable, IntoBytes};
 
 use crate::device::console::{Console, ConsoleThread, UartRecv};
-use crate::device::{MmioDev, Pause, Result};
+use crate::device::{MmioDev, Pause, Result, Snapshot, error};
 use crate::hv::IrqSender;
 use crate::mem::emulated::{Action, Mmio};
 use crate::{bitflags, hv, mem};
@@ -140,6 +141,25 @@ struct Pl011Reg {
     interrupt_status: Interrupt,
 }
 
+/// Register values of [`Pl011Reg`] in a snapshot, followed by the bytes in
+/// the receive FIFO.
+#[repr(C)]
+#[derive(Debug, Default, IntoBytes, FromBytes, Immutable)]
+struct Pl011State {
+    lcr: u32,
+    rsr: u32,
+    cr: u32,
+    dmacr: u32,
+    ilpr: u32,
+    ibrd: u32,
+    fbrd: u32,
+    ifl: u32,
+    flag: u16,
+    interrupt_mask: u16,
+    interrupt_status: u16,
+    _reserved: u16,
+}
+
 ///  https://developer.arm.com/documentation/ddi0183/g
 #[derive(Debug)]
 pub struct Pl011<I, C> {
@@ -282,6 +302,54 @@ where
     }
 }
 
+impl<I, C> Snapshot for Pl011<I, C>
+where
+    I: IrqSender,
+    C: Console,
+    for<'a> &'a C: Read + Write,
+{
+    fn snapshot(&self) -> Result<Vec<u8>> {
+        let reg = self.reg.lock();
+        let state = Pl011State {
+            lcr: reg.lcr,
+            rsr: reg.rsr,
+            cr: reg.cr,
+            dmacr: reg.dmacr,
+            ilpr: reg.ilpr,
+            ibrd: reg.ibrd,
+            fbrd: reg.fbrd,
+            ifl: reg.ifl,
+            flag: reg.flag.bits(),
+            interrupt_mask: reg.interrupt_mask.bits(),
+            interrupt_status: reg.interrupt_status.bits(),
+            ..Default::default()
+        };
+        let mut data = state.as_bytes().to_vec();
+        data.extend(&reg.data);
+        Ok(data)
+    }
+
+    fn restore(&self, data: &[u8]) -> Result<()> {
+        let Ok((state, rx)) = Pl011State::read_from_prefix(data) else {
+            return error::InvalidSnapshot.fail();
+        };
+        let mut reg = self.reg.lock();
+        reg.lcr = state.lcr;
+        reg.rsr = state.rsr;
+        reg.cr = state.cr;
+        reg.dmacr = state.dmacr;
+        reg.ilpr = state.ilpr;
+        reg.ibrd = state.ibrd;
+        reg.fbrd = state.fbrd;
+        reg.ifl = state.ifl;
+        reg.flag = Flag::from_bits_retain(state.flag);
+        reg.interrupt_mask = Interrupt::from_bits_retain(state.interrupt_mask);
+        reg.interrupt_status = Interrupt::from_bits_retain(state.interrupt_status);
+        reg.data = VecDeque::from(rx.to_vec());
+        Ok(())
+    }
+}
+
 impl<I, C> MmioDev for Pl011<I, C>
 where
     I: IrqSender,
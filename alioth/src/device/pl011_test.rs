@@ -20,9 +20,9 @@ use assert_matches::assert_matches;
 use crate::arch::aarch64::layout::PL011_START;
 use crate::device::console::tests::TestConsole;
 use crate::device::pl011::{
-    Flag, Interrupt, Pl011, UART_DR, UART_FR, UART_IMSC, UART_MIS, UART_PCELL_ID0, UART_PCELL_ID1,
-    UART_PCELL_ID2, UART_PCELL_ID3, UART_PERIPH_ID0, UART_PERIPH_ID1, UART_PERIPH_ID2,
-    UART_PERIPH_ID3, UART_RIS,
+    Flag, Interrupt, Pl011, UART_DR, UART_FR, UART_IBRD, UART_IMSC, UART_LCR_H, UART_MIS,
+    UART_PCELL_ID0, UART_PCELL_ID1, UART_PCELL_ID2, UART_PCELL_ID3, UART_PERIPH_ID0,
+    UART_PERIPH_ID1, UART_PERIPH_ID2, UART_PERIPH_ID3, UART_RIS,
 };
 use crate::hv::tests::TestIrqSender;
 use crate::mem::emulated::Mmio;
@@ -149,3 +149,37 @@ fn test_pl011_tx() {
     assert_matches!(pl011.write(UART_DR, 4, 0x11), Ok(_));
     assert_matches!(pl011.console.outbound.lock().pop_back(), Some(0x11));
 }
+
+#[test]
+fn test_pl011_snapshot_restore() {
+    use crate::device::{Error, Snapshot};
+
+    let pl011 = fixture_pl011();
+    assert_matches!(pl011.write(UART_IBRD, 4, 0x27), Ok(_));
+    assert_matches!(pl011.write(UART_LCR_H, 4, 0x70), Ok(_));
+    assert_matches!(
+        pl011.write(UART_IMSC, 4, Interrupt::RXRIS.bits() as u64),
+        Ok(_)
+    );
+    {
+        let mut reg = pl011.reg.lock();
+        reg.data.extend(b"ab");
+        reg.flag.remove(Flag::RXFE);
+    }
+    let data = pl011.snapshot().unwrap();
+
+    let restored = fixture_pl011();
+    assert_matches!(restored.restore(&data), Ok(()));
+    assert_matches!(restored.read(UART_IBRD, 4), Ok(0x27));
+    assert_matches!(restored.read(UART_LCR_H, 4), Ok(0x70));
+    assert_matches!(restored.read(UART_IMSC, 4), Ok(m) if m == Interrupt::RXRIS.bits() as u64);
+    assert_matches!(restored.read(UART_FR, 4), Ok(f) if !Flag(f as u16).contains(Flag::RXFE));
+    assert_matches!(restored.read(UART_DR, 4), Ok(0x61));
+    assert_matches!(restored.read(UART_DR, 4), Ok(0x62));
+    assert_matches!(restored.read(UART_FR, 4), Ok(f) if Flag(f as u16).contains(Flag::RXFE));
+
+    assert_matches!(
+        restored.restore(&data[..8]),
+        Err(Error::InvalidSnapshot { .. })
+    );
+}
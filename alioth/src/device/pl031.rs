@@ -16,9 +16,10 @@
 //! See: https://developer.arm.com/documentation/ddi0224/c
 
 use parking_lot::Mutex;
+use zerocopy::{FromBytes, Immutable, IntoBytes};
 
 use crate::device::clock::Clock;
-use crate::device::{MmioDev, Pause, Result};
+use crate::device::{MmioDev, Pause, Result, Snapshot, error};
 use crate::mem::emulated::{Action, Mmio};
 use crate::{bitflags, mem};
 
@@ -50,7 +51,8 @@ bitflags! {
     }
 }
 
-#[derive(Debug, Default)]
+#[repr(C)]
+#[derive(Debug, Default, IntoBytes, FromBytes, Immutable)]
 struct Pl031Reg {
     mr: u32,
     lr: u32,
@@ -149,6 +151,20 @@ impl<C: Clock> Pause for Pl031<C> {
     }
 }
 
+impl<C: Clock> Snapshot for Pl031<C> {
+    fn snapshot(&self) -> Result<Vec<u8>> {
+        Ok(self.reg.lock().as_bytes().to_vec())
+    }
+
+    fn restore(&self, data: &[u8]) -> Result<()> {
+        let Ok(reg) = Pl031Reg::read_from_bytes(data) else {
+            return error::InvalidSnapshot.fail();
+        };
+        *self.reg.lock() = reg;
+        Ok(())
+    }
+}
+
 impl<C: Clock> MmioDev for Pl031<C> {}
 
 #[cfg(test)]
@@ -65,3 +65,23 @@ fn test_pl031() {
     assert_matches!(pl031.read(RTC_PCELL_ID2, 4), Ok(0x05));
     assert_matches!(pl031.read(RTC_PCELL_ID3, 4), Ok(0xb1));
 }
+
+#[test]
+fn test_pl031_snapshot_restore() {
+    use crate::device::{Error, Snapshot};
+
+    // Nov 21, 2025 at 15:16:59 GMT-08:00
+    let now = DateTime::from_timestamp_nanos(1_763_767_019_000_000_000);
+    let pl031 = Pl031::new(PL031_START, TestClock { now });
+    assert_matches!(pl031.write(RTC_LR, 4, 1763770619), Ok(_));
+    assert_matches!(pl031.write(RTC_MR, 4, 750), Ok(_));
+    let data = pl031.snapshot().unwrap();
+
+    let restored = Pl031::new(PL031_START, TestClock { now });
+    assert_matches!(restored.restore(&data), Ok(()));
+    assert_matches!(restored.read(RTC_LR, 4), Ok(1763770619));
+    assert_matches!(restored.read(RTC_MR, 4), Ok(750));
+    assert_matches!(restored.read(RTC_DR, 4), Ok(1763770619));
+
+    assert_matches!(restored.restore(&[]), Err(Error::InvalidSnapshot { .. }));
+}
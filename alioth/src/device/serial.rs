@@ -15,13 +15,19 @@
 use std::collections::VecDeque;
 use std::io::{Read, Write};
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 
 use bitfield::bitfield;
 use parking_lot::Mutex;
+use serde::Deserialize;
+use serde_aco::Help;
+use zerocopy::{FromBytes, Immutable, IntoBytes};
 
-use crate::device::console::{Console, ConsoleThread, UartRecv};
+#[cfg(target_arch = "x86_64")]
+use crate::arch::layout::{PORT_COM1, PORT_COM2, PORT_COM3, PORT_COM4};
+use crate::device::console::{Console, ConsoleParam, ConsoleThread, UartRecv};
 use crate::device::ioapic::IoApic;
-use crate::device::{self, MmioDev, Pause, Result};
+use crate::device::{self, MmioDev, Pause, Result, Snapshot, error};
 use crate::hv::MsiSender;
 use crate::mem::emulated::{Action, Mmio};
 use crate::{bitflags, mem};
@@ -39,6 +45,55 @@ const LINE_STATUS_REGISTER: u64 = 0x5;
 const MODEM_STATUS_REGISTER: u64 = 0x6;
 const SCRATCH_REGISTER: u64 = 0x7;
 
+const FIFO_SIZE: usize = 16;
+/// Baud rate with a divisor of 1.
+const BAUD_BASE: u64 = 115200;
+
+#[cfg(target_arch = "x86_64")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Help)]
+pub enum ComPort {
+    /// I/O port 0x3f8, IRQ 4.
+    #[serde(alias = "com1")]
+    Com1,
+    /// I/O port 0x2f8, IRQ 3.
+    #[serde(alias = "com2")]
+    Com2,
+    /// I/O port 0x3e8, IRQ 4.
+    #[serde(alias = "com3")]
+    Com3,
+    /// I/O port 0x2e8, IRQ 3.
+    #[serde(alias = "com4")]
+    Com4,
+}
+
+#[cfg(target_arch = "x86_64")]
+impl ComPort {
+    pub fn base_port(self) -> u16 {
+        match self {
+            ComPort::Com1 => PORT_COM1,
+            ComPort::Com2 => PORT_COM2,
+            ComPort::Com3 => PORT_COM3,
+            ComPort::Com4 => PORT_COM4,
+        }
+    }
+
+    pub fn irq(self) -> u8 {
+        match self {
+            ComPort::Com1 | ComPort::Com3 => 4,
+            ComPort::Com2 | ComPort::Com4 => 3,
+        }
+    }
+}
+
+#[cfg(target_arch = "x86_64")]
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Help)]
+pub struct SerialParam {
+    /// The UART to add.
+    pub port: ComPort,
+    /// Backend of the UART.
+    pub backend: ConsoleParam,
+}
+
 // offset 0x1, Interrupt Enable Register (IER)
 bitflags! {
     #[derive(Default)]
@@ -97,6 +152,14 @@ impl InterruptIdentification {
         self.0 = (self.0 & !0b1111) | 0b0100;
     }
 
+    pub fn set_rx_line_status(&mut self) {
+        self.0 = (self.0 & !0b1111) | 0b0110;
+    }
+
+    pub fn set_char_timeout(&mut self) {
+        self.0 = (self.0 & !0b1111) | 0b1100;
+    }
+
     pub fn set_tx_room_empty(&mut self) {
         self.0 = (self.0 & !0b1111) | 0b0010;
     }
@@ -169,8 +232,7 @@ impl Default for LineStatus {
 #[derive(Default, Debug)]
 struct SerialReg {
     interrupt_enable: InterruptEnable, // 0x1, Interrupt Enable Register (IER)
-    #[allow(dead_code)]
-    fifo_control: FifoControl, // 0x2, write, FIFO Control Register (FCR)
+    fifo_control: FifoControl,         // 0x2, write, FIFO Control Register (FCR)
     interrupt_identification: InterruptIdentification, // 0x2, read, Interrupt Identification Register
     line_control: LineControl,                         // 0x3, Line Control Register (LCR)
     modem_control: ModemControl,                       // 0x4, Modem Control Register (MCR)
@@ -179,6 +241,148 @@ struct SerialReg {
     scratch: u8,      // 0x7, Scratch Register (SCR)
     divisor: u16,
     data: VecDeque<u8>,
+    tx_empty_pending: bool,
+    /// When the character timeout expires if no byte is received or read.
+    rx_deadline: Option<Instant>,
+    rx_timeout: bool,
+}
+
+impl SerialReg {
+    fn rx_trigger_size(&self) -> usize {
+        if self.fifo_control.fifo_enabled() {
+            self.fifo_control.rx_trigger_size()
+        } else {
+            1
+        }
+    }
+
+    fn rx_capacity(&self) -> usize {
+        if self.fifo_control.fifo_enabled() {
+            FIFO_SIZE
+        } else {
+            1
+        }
+    }
+
+    /// Time to transfer 4 characters of 10 bits at the current baud rate.
+    fn char_timeout(&self) -> Duration {
+        let divisor = std::cmp::max(self.divisor, 1) as u64;
+        Duration::from_nanos(40 * divisor * 1_000_000_000 / BAUD_BASE)
+    }
+
+    fn push_rx(&mut self, bytes: &[u8]) {
+        if bytes.is_empty() {
+            return;
+        }
+        for byte in bytes {
+            if self.data.len() < self.rx_capacity() {
+                self.data.push_back(*byte);
+                continue;
+            }
+            // With FIFOs, the new byte in the shift register is lost.
+            // Without them, it overwrites the holding register.
+            if !self.fifo_control.fifo_enabled() {
+                self.data.pop_front();
+                self.data.push_back(*byte);
+            }
+            self.line_status |= LineStatus::OVERRUN_ERROR;
+        }
+        self.line_status |= LineStatus::DATA_READY;
+        self.rx_timeout = false;
+        self.restart_rx_timer();
+    }
+
+    /// Only the FIFO mode has a character timeout.
+    fn restart_rx_timer(&mut self) {
+        self.rx_deadline = if self.fifo_control.fifo_enabled() {
+            Some(Instant::now() + self.char_timeout())
+        } else {
+            None
+        };
+    }
+
+    fn pop_rx(&mut self) -> u8 {
+        let byte = self.data.pop_front().unwrap_or(0xff);
+        if self.data.is_empty() {
+            self.line_status &= !LineStatus::DATA_READY;
+            self.rx_deadline = None;
+            self.rx_timeout = false;
+        } else if !self.rx_timeout {
+            self.restart_rx_timer();
+        }
+        byte
+    }
+
+    /// Returns whether bytes have been in the RX FIFO with no byte received
+    /// or read for the character timeout.
+    fn rx_timed_out(&mut self) -> bool {
+        if let Some(deadline) = self.rx_deadline
+            && Instant::now() >= deadline
+        {
+            self.rx_deadline = None;
+            self.rx_timeout = true;
+        }
+        self.rx_timeout
+    }
+
+    fn set_fifo_control(&mut self, fcr: FifoControl) {
+        if fcr.fifo_enabled() != self.fifo_control.fifo_enabled() || fcr.rx_reset() {
+            self.data.clear();
+            self.line_status &= !LineStatus::DATA_READY;
+            self.rx_deadline = None;
+            self.rx_timeout = false;
+        }
+        // The reset bits are self-clearing.
+        self.fifo_control = FifoControl(fcr.0 & !0b110);
+        if fcr.fifo_enabled() {
+            self.interrupt_identification.set_fifo_enabled();
+        } else {
+            self.interrupt_identification.clear_fifi_enabled();
+        }
+    }
+
+    /// Recomputes the pending interrupt with the highest priority and returns
+    /// `true` if there is one.
+    fn update_interrupt(&mut self) -> bool {
+        let ier = self.interrupt_enable;
+        let rx_available = ier.contains(InterruptEnable::RECEIVED_DATA_AVAILABLE);
+        let trigger = self.rx_trigger_size();
+        let rx_timeout = !self.data.is_empty() && self.rx_timed_out();
+        let iir = &mut self.interrupt_identification;
+        if ier.contains(InterruptEnable::RECEIVER_LINE_STATUS)
+            && self.line_status.contains(LineStatus::OVERRUN_ERROR)
+        {
+            iir.set_rx_line_status();
+        } else if rx_available && self.data.len() >= trigger {
+            iir.set_rx_data_available();
+        } else if rx_available && rx_timeout {
+            iir.set_char_timeout();
+        } else if ier.contains(InterruptEnable::TX_HOLDING_REGISTER_EMPTY) && self.tx_empty_pending
+        {
+            iir.set_tx_room_empty();
+        } else {
+            iir.clear_interrupt();
+        }
+        !iir.no_pending()
+    }
+}
+
+/// Register values of [`SerialReg`] in a snapshot, followed by the bytes in
+/// the RX FIFO.
+#[repr(C)]
+#[derive(Debug, Default, IntoBytes, FromBytes, Immutable)]
+struct SerialState {
+    interrupt_enable: u8,
+    fifo_control: u8,
+    interrupt_identification: u8,
+    line_control: u8,
+    modem_control: u8,
+    line_status: u8,
+    modem_status: u8,
+    scratch: u8,
+    divisor: u16,
+    tx_empty_pending: u8,
+    _reserved: [u8; 5],
 }
 
 #[derive(Debug)]
@@ -188,7 +392,7 @@ pub struct Serial<M: MsiSender, C> {
     pin: u8,
     reg: Arc<Mutex<SerialReg>>,
     console: Arc<C>,
-    _thread: ConsoleThread,
+    thread: ConsoleThread,
 }
 
 impl<M, C> Mmio for Serial<M, C>
@@ -209,20 +413,30 @@ where
                 (reg.divisor >> 8) as u8
             }
             RX_BUFFER_REGISTER => {
-                if reg.data.len() <= 1 {
-                    reg.line_status &= !LineStatus::DATA_READY;
-                }
-                reg.data.pop_front().unwrap_or(0xff)
+                let byte = reg.pop_rx();
+                reg.update_interrupt();
+                byte
             }
             INTERRUPT_ENABLE_REGISTER => reg.interrupt_enable.bits(),
             INTERRUPT_IDENTIFICATION_REGISTER => {
+                reg.update_interrupt();
                 let ret = reg.interrupt_identification.0;
-                reg.interrupt_identification.clear_interrupt();
+                // Reading IIR acknowledges the THR empty interrupt.
+                if reg.interrupt_identification.interrupt_id() == 0b001 {
+                    reg.tx_empty_pending = false;
+                    reg.update_interrupt();
+                }
                 ret
             }
             LINE_CONTROL_REGISTER => reg.line_control.0,
             MODEM_CONTROL_REGISTER => reg.modem_control.0,
-            LINE_STATUS_REGISTER => reg.line_status.bits(),
+            LINE_STATUS_REGISTER => {
+                let ret = reg.line_status.bits();
+                // Reading LSR clears the error bits.
+                reg.line_status &= !LineStatus::OVERRUN_ERROR;
+                reg.update_interrupt();
+                ret
+            }
             MODEM_STATUS_REGISTER => reg.modem_status,
             SCRATCH_REGISTER => reg.scratch,
             _ => {
@@ -245,30 +459,33 @@ where
             }
             TX_HOLDING_REGISTER => {
                 if reg.modem_control.loop_back() {
-                    reg.data.push_back(byte);
-                    if reg
-                        .interrupt_enable
-                        .contains(InterruptEnable::RECEIVED_DATA_AVAILABLE)
-                    {
-                        reg.interrupt_identification.set_rx_data_available();
-                        self.send_irq();
-                    }
-                    reg.line_status |= LineStatus::DATA_READY;
+                    reg.push_rx(&[byte]);
+                    self.thread.wake();
                 } else {
                     let _ = self.console.as_ref().write(&[byte]);
-                    if reg
-                        .interrupt_enable
-                        .contains(InterruptEnable::TX_HOLDING_REGISTER_EMPTY)
-                    {
-                        reg.interrupt_identification.set_tx_room_empty();
-                        self.send_irq()
-                    }
+                }
+                reg.tx_empty_pending = true;
+                if reg.update_interrupt() {
+                    self.send_irq();
                 }
             }
             INTERRUPT_ENABLE_REGISTER => {
-                reg.interrupt_enable = InterruptEnable::from_bits_truncate(byte);
+                let ier = InterruptEnable::from_bits_truncate(byte);
+                let thre = InterruptEnable::TX_HOLDING_REGISTER_EMPTY;
+                // THR is always empty, so enabling its interrupt raises one
+                // immediately as on a real 16550.
+                if ier.contains(thre) && !reg.interrupt_enable.contains(thre) {
+                    reg.tx_empty_pending = true;
+                }
+                reg.interrupt_enable = ier;
+                if reg.update_interrupt() {
+                    self.send_irq();
+                }
+            }
+            FIFO_CONTROL_REGISTER => {
+                reg.set_fifo_control(FifoControl(byte));
+                reg.update_interrupt();
             }
-            FIFO_CONTROL_REGISTER => {}
             LINE_CONTROL_REGISTER => {
                 reg.line_control = LineControl(byte);
             }
@@ -299,6 +516,54 @@ where
     }
 }
 
+impl<M, C> Snapshot for Serial<M, C>
+where
+    M: MsiSender,
+{
+    fn snapshot(&self) -> Result<Vec<u8>> {
+        let reg = self.reg.lock();
+        let state = SerialState {
+            interrupt_enable: reg.interrupt_enable.bits(),
+            fifo_control: reg.fifo_control.0,
+            interrupt_identification: reg.interrupt_identification.0,
+            line_control: reg.line_control.0,
+            modem_control: reg.modem_control.0,
+            line_status: reg.line_status.bits(),
+            modem_status: reg.modem_status,
+            scratch: reg.scratch,
+            divisor: reg.divisor,
+            tx_empty_pending: reg.tx_empty_pending as u8,
+            ..Default::default()
+        };
+        let mut data = state.as_bytes().to_vec();
+        data.extend(&reg.data);
+        Ok(data)
+    }
+
+    fn restore(&self, data: &[u8]) -> Result<()> {
+        let Ok((state, rx)) = SerialState::read_from_prefix(data) else {
+            return error::InvalidSnapshot.fail();
+        };
+        let mut reg = self.reg.lock();
+        reg.interrupt_enable = InterruptEnable::from_bits_retain(state.interrupt_enable);
+        reg.fifo_control = FifoControl(state.fifo_control);
+        reg.interrupt_identification = InterruptIdentification(state.interrupt_identification);
+        reg.line_control = LineControl(state.line_control);
+        reg.modem_control = ModemControl(state.modem_control);
+        reg.line_status = LineStatus::from_bits_retain(state.line_status);
+        reg.modem_status = state.modem_status;
+        reg.scratch = state.scratch;
+        reg.divisor = state.divisor;
+        reg.tx_empty_pending = state.tx_empty_pending != 0;
+        reg.data.clear();
+        reg.push_rx(rx);
+        reg.line_status = LineStatus::from_bits_retain(state.line_status);
+        drop(reg);
+        self.thread.wake();
+        Ok(())
+    }
+}
+
 impl<M, C> MmioDev for Serial<M, C>
 where
     M: MsiSender,
@@ -317,17 +582,29 @@ struct SerialRecv<M: MsiSender> {
 impl<M: MsiSender> UartRecv for SerialRecv<M> {
     fn receive(&self, bytes: &[u8]) {
         let mut reg = self.reg.lock();
-        reg.data.extend(bytes);
-        if reg
-            .interrupt_enable
-            .contains(InterruptEnable::RECEIVED_DATA_AVAILABLE)
+        reg.push_rx(bytes);
+        if reg.update_interrupt()
+            && let Err(e) = self.io_apci.service_pin(self.pin)
         {
-            reg.interrupt_identification.set_rx_data_available();
-            if let Err(e) = self.io_apci.service_pin(self.pin) {
-                log::error!("{}: sending interrupt: {e:?}", self.name);
-            }
+            log::error!("{}: sending interrupt: {e:?}", self.name);
+        }
+    }
+
+    fn next_timeout(&self) -> Option<Duration> {
+        let deadline = self.reg.lock().rx_deadline?;
+        Some(deadline.saturating_duration_since(Instant::now()))
+    }
+
+    fn handle_timeout(&self) {
+        let mut reg = self.reg.lock();
+        if reg.rx_deadline.is_none_or(|d| Instant::now() < d) {
+            return;
+        }
+        if reg.update_interrupt()
+            && let Err(e) = self.io_apci.service_pin(self.pin)
+        {
+            log::error!("{}: sending interrupt: {e:?}", self.name);
         }
-        reg.line_status |= LineStatus::DATA_READY;
     }
 }
 
@@ -354,7 +631,7 @@ where
             pin,
             io_apci,
             console,
-            _thread: thread,
+            thread,
         };
         Ok(serial)
     }
@@ -96,11 +96,12 @@ fn test_serial_basic() {
 fn test_serial_tx() {
     let (serial, ioapic, messages) = fixture_serial();
 
-    // Enable TX empty interrupt
-    assert_matches!(serial.write(INTERRUPT_ENABLE_REGISTER, 1, 0x02), Ok(_));
-
     enable_pin(&ioapic, 4, 0x24, 2);
 
+    // Enable TX empty interrupt, which is raised immediately.
+    assert_matches!(serial.write(INTERRUPT_ENABLE_REGISTER, 1, 0x02), Ok(_));
+    assert_matches!(serial.read(INTERRUPT_IDENTIFICATION_REGISTER, 1), Ok(0x02));
+
     // Write a character
     assert_matches!(serial.write(TX_HOLDING_REGISTER, 1, b'A' as u64), Ok(_));
 
@@ -111,7 +112,35 @@ fn test_serial_tx() {
 
     // TX should send an IRQ through IOAPIC
     let messages_lock = messages.lock();
-    assert_matches!(messages_lock.as_slice(), [(0xfee02000, 0x24)]);
+    assert_matches!(
+        messages_lock.as_slice(),
+        [(0xfee02000, 0x24), (0xfee02000, 0x24)]
+    );
+}
+
+#[test]
+fn test_serial_tx_enable_after_ack() {
+    let (serial, ioapic, messages) = fixture_serial();
+    enable_pin(&ioapic, 4, 0x24, 2);
+
+    assert_matches!(serial.write(TX_HOLDING_REGISTER, 1, b'A' as u64), Ok(_));
+    assert_matches!(serial.read(INTERRUPT_IDENTIFICATION_REGISTER, 1), Ok(0x01));
+    assert!(messages.lock().is_empty());
+
+    // Enabling the THR empty interrupt raises it at once.
+    assert_matches!(serial.write(INTERRUPT_ENABLE_REGISTER, 1, 0x02), Ok(_));
+    assert_matches!(messages.lock().as_slice(), [(0xfee02000, 0x24)]);
+    assert_matches!(serial.read(INTERRUPT_IDENTIFICATION_REGISTER, 1), Ok(0x02));
+    assert_matches!(serial.read(INTERRUPT_IDENTIFICATION_REGISTER, 1), Ok(0x01));
+
+    // Rewriting IER without clearing the bit does not.
+    assert_matches!(serial.write(INTERRUPT_ENABLE_REGISTER, 1, 0x02), Ok(_));
+    assert_matches!(serial.read(INTERRUPT_IDENTIFICATION_REGISTER, 1), Ok(0x01));
+
+    assert_matches!(serial.write(INTERRUPT_ENABLE_REGISTER, 1, 0x00), Ok(_));
+    assert_matches!(serial.write(INTERRUPT_ENABLE_REGISTER, 1, 0x02), Ok(_));
+    assert_eq!(messages.lock().len(), 2);
+    assert_matches!(serial.read(INTERRUPT_IDENTIFICATION_REGISTER, 1), Ok(0x02));
 }
 
 #[test]
@@ -232,3 +261,191 @@ fn test_serial_pause_resume() {
     assert_matches!(serial.pause(), Ok(()));
     assert_matches!(serial.resume(), Ok(()));
 }
+
+#[test]
+fn test_serial_snapshot_restore() {
+    use crate::device::{Error, Snapshot};
+
+    let (serial, _, _) = fixture_serial();
+    assert_matches!(serial.write(LINE_CONTROL_REGISTER, 1, 0x80), Ok(_));
+    assert_matches!(serial.write(DIVISOR_LATCH_LSB, 1, 0x0c), Ok(_));
+    assert_matches!(serial.write(LINE_CONTROL_REGISTER, 1, 0x03), Ok(_));
+    assert_matches!(serial.write(INTERRUPT_ENABLE_REGISTER, 1, 0x01), Ok(_));
+    assert_matches!(serial.write(FIFO_CONTROL_REGISTER, 1, 0xc1), Ok(_));
+    assert_matches!(serial.write(SCRATCH_REGISTER, 1, 0x5a), Ok(_));
+    serial.reg.lock().push_rx(b"ab");
+    let data = serial.snapshot().unwrap();
+
+    let (restored, _, _) = fixture_serial();
+    assert_matches!(restored.restore(&data), Ok(()));
+    assert_matches!(restored.read(INTERRUPT_ENABLE_REGISTER, 1), Ok(0x01));
+    assert_matches!(restored.read(SCRATCH_REGISTER, 1), Ok(0x5a));
+    assert_matches!(restored.read(LINE_STATUS_REGISTER, 1), Ok(s) if s & 1 == 1);
+    assert_matches!(restored.read(RX_BUFFER_REGISTER, 1), Ok(b) if b == b'a' as u64);
+    assert_matches!(restored.read(RX_BUFFER_REGISTER, 1), Ok(b) if b == b'b' as u64);
+    assert_matches!(restored.read(LINE_STATUS_REGISTER, 1), Ok(s) if s & 1 == 0);
+    assert_matches!(restored.write(LINE_CONTROL_REGISTER, 1, 0x83), Ok(_));
+    assert_matches!(restored.read(DIVISOR_LATCH_LSB, 1), Ok(0x0c));
+
+    assert_matches!(
+        restored.restore(&data[..4]),
+        Err(Error::InvalidSnapshot { .. })
+    );
+}
+
+fn set_divisor(serial: &Serial<TestMsiSender, TestConsole>, divisor: u16) {
+    assert_matches!(serial.write(LINE_CONTROL_REGISTER, 1, 0x83), Ok(_));
+    assert_matches!(
+        serial.write(DIVISOR_LATCH_LSB, 1, divisor as u64 & 0xff),
+        Ok(_)
+    );
+    assert_matches!(
+        serial.write(DIVISOR_LATCH_MSB, 1, divisor as u64 >> 8),
+        Ok(_)
+    );
+    assert_matches!(serial.write(LINE_CONTROL_REGISTER, 1, 0x03), Ok(_));
+}
+
+#[test]
+fn test_serial_fifo_trigger() {
+    let (serial, ioapic, messages) = fixture_serial();
+    // At 1.76 baud, the character timeout does not expire during the test.
+    set_divisor(&serial, 0xffff);
+
+    assert_matches!(serial.write(INTERRUPT_ENABLE_REGISTER, 1, 0x01), Ok(_));
+    enable_pin(&ioapic, 4, 0x24, 2);
+
+    // Enable FIFOs with a 4-byte RX trigger level
+    assert_matches!(serial.write(FIFO_CONTROL_REGISTER, 1, 0x41), Ok(_));
+    assert_matches!(serial.read(INTERRUPT_IDENTIFICATION_REGISTER, 1), Ok(0xc1));
+
+    // Loop back bytes into the RX FIFO
+    assert_matches!(serial.write(MODEM_CONTROL_REGISTER, 1, 0x10), Ok(_));
+    for b in b"abc" {
+        assert_matches!(serial.write(TX_HOLDING_REGISTER, 1, *b as u64), Ok(_));
+    }
+
+    // Below the trigger level, nothing is reported before the timeout
+    assert_matches!(serial.read(INTERRUPT_IDENTIFICATION_REGISTER, 1), Ok(0xc1));
+    assert!(messages.lock().is_empty());
+
+    assert_matches!(serial.write(TX_HOLDING_REGISTER, 1, b'd' as u64), Ok(_));
+    assert_matches!(serial.read(INTERRUPT_IDENTIFICATION_REGISTER, 1), Ok(0xc4));
+
+    assert_matches!(serial.read(RX_BUFFER_REGISTER, 1), Ok(b) if b == b'a' as u64);
+    assert_matches!(serial.read(INTERRUPT_IDENTIFICATION_REGISTER, 1), Ok(0xc1));
+    for b in b"bcd" {
+        assert_matches!(serial.read(RX_BUFFER_REGISTER, 1), Ok(v) if v == *b as u64);
+    }
+    assert_matches!(serial.read(LINE_STATUS_REGISTER, 1), Ok(s) if s & 1 == 0);
+    assert_matches!(serial.read(INTERRUPT_IDENTIFICATION_REGISTER, 1), Ok(0xc1));
+
+    assert_eq!(messages.lock().len(), 1);
+}
+
+#[test]
+fn test_serial_char_timeout() {
+    let (serial, ioapic, messages) = fixture_serial();
+    // At 9600 baud, the character timeout is about 4ms.
+    set_divisor(&serial, 0x0c);
+
+    assert_matches!(serial.write(INTERRUPT_ENABLE_REGISTER, 1, 0x01), Ok(_));
+    enable_pin(&ioapic, 4, 0x24, 2);
+
+    // Enable FIFOs with an 8-byte RX trigger level
+    assert_matches!(serial.write(FIFO_CONTROL_REGISTER, 1, 0x81), Ok(_));
+    assert_matches!(serial.write(MODEM_CONTROL_REGISTER, 1, 0x10), Ok(_));
+    for b in b"ab" {
+        assert_matches!(serial.write(TX_HOLDING_REGISTER, 1, *b as u64), Ok(_));
+    }
+
+    // The timeout is raised by the console thread once the line is idle.
+    let now = Instant::now();
+    while messages.lock().is_empty() && now.elapsed() < Duration::from_secs(5) {
+        sleep(Duration::from_millis(1));
+    }
+    assert_matches!(messages.lock().as_slice(), [(0xfee02000, 0x24)]);
+    assert_matches!(serial.read(INTERRUPT_IDENTIFICATION_REGISTER, 1), Ok(0xcc));
+
+    // The timeout stays until the FIFO is empty.
+    assert_matches!(serial.read(RX_BUFFER_REGISTER, 1), Ok(b) if b == b'a' as u64);
+    assert_matches!(serial.read(INTERRUPT_IDENTIFICATION_REGISTER, 1), Ok(0xcc));
+    assert_matches!(serial.read(RX_BUFFER_REGISTER, 1), Ok(b) if b == b'b' as u64);
+    assert_matches!(serial.read(INTERRUPT_IDENTIFICATION_REGISTER, 1), Ok(0xc1));
+
+    // A new byte restarts the timer.
+    assert_matches!(serial.write(TX_HOLDING_REGISTER, 1, b'c' as u64), Ok(_));
+    assert_matches!(serial.read(INTERRUPT_IDENTIFICATION_REGISTER, 1), Ok(0xc1));
+    let now = Instant::now();
+    while messages.lock().len() < 2 && now.elapsed() < Duration::from_secs(5) {
+        sleep(Duration::from_millis(1));
+    }
+    assert_matches!(serial.read(INTERRUPT_IDENTIFICATION_REGISTER, 1), Ok(0xcc));
+}
+
+#[test]
+fn test_serial_rx_overrun() {
+    let (serial, ioapic, messages) = fixture_serial();
+
+    // Enable the RX available and receiver line status interrupts
+    assert_matches!(serial.write(INTERRUPT_ENABLE_REGISTER, 1, 0x05), Ok(_));
+    enable_pin(&ioapic, 4, 0x24, 2);
+    assert_matches!(serial.write(FIFO_CONTROL_REGISTER, 1, 0x01), Ok(_));
+
+    // The host sends more bytes than the FIFO holds
+    let input: Vec<u8> = (0..64).collect();
+    {
+        serial.console.inbound.lock().extend(&input);
+        serial.console.notifier.lock().notify().unwrap();
+    }
+    let now = Instant::now();
+    while !serial.console.inbound.lock().is_empty() && now.elapsed() < Duration::from_secs(5) {
+        sleep(Duration::from_millis(10));
+    }
+    while messages.lock().is_empty() && now.elapsed() < Duration::from_secs(5) {
+        sleep(Duration::from_millis(10));
+    }
+    assert_matches!(serial.read(INTERRUPT_IDENTIFICATION_REGISTER, 1), Ok(0xc6));
+
+    // Reading LSR clears the overrun error
+    assert_matches!(serial.read(LINE_STATUS_REGISTER, 1), Ok(s) if s & 0b11 == 0b11);
+    assert_matches!(serial.read(LINE_STATUS_REGISTER, 1), Ok(s) if s & 0b11 == 0b01);
+    assert_matches!(serial.read(INTERRUPT_IDENTIFICATION_REGISTER, 1), Ok(0xc4));
+
+    // The FIFO keeps the first 16 bytes
+    for b in &input[..16] {
+        assert_matches!(serial.read(RX_BUFFER_REGISTER, 1), Ok(v) if v == *b as u64);
+    }
+    assert_matches!(serial.read(LINE_STATUS_REGISTER, 1), Ok(s) if s & 0b11 == 0);
+
+    // Without FIFOs, a new byte overwrites the unread one
+    assert_matches!(serial.write(FIFO_CONTROL_REGISTER, 1, 0x00), Ok(_));
+    assert_matches!(serial.write(MODEM_CONTROL_REGISTER, 1, 0x10), Ok(_));
+    for b in b"xy" {
+        assert_matches!(serial.write(TX_HOLDING_REGISTER, 1, *b as u64), Ok(_));
+    }
+    assert_matches!(serial.read(INTERRUPT_IDENTIFICATION_REGISTER, 1), Ok(0x06));
+    assert_matches!(serial.read(LINE_STATUS_REGISTER, 1), Ok(s) if s & 0b11 == 0b11);
+    assert_matches!(serial.read(RX_BUFFER_REGISTER, 1), Ok(b) if b == b'y' as u64);
+    assert_matches!(serial.read(LINE_STATUS_REGISTER, 1), Ok(s) if s & 0b11 == 0);
+}
+
+#[test]
+fn test_serial_fifo_reset() {
+    let (serial, _, _) = fixture_serial();
+
+    assert_matches!(serial.write(FIFO_CONTROL_REGISTER, 1, 0xc1), Ok(_));
+    assert_matches!(serial.write(MODEM_CONTROL_REGISTER, 1, 0x10), Ok(_));
+    assert_matches!(serial.write(TX_HOLDING_REGISTER, 1, b'A' as u64), Ok(_));
+    assert_matches!(serial.read(LINE_STATUS_REGISTER, 1), Ok(s) if s & 1 == 1);
+
+    // Resetting the RX FIFO drops pending bytes
+    assert_matches!(serial.write(FIFO_CONTROL_REGISTER, 1, 0xc3), Ok(_));
+    assert_matches!(serial.read(LINE_STATUS_REGISTER, 1), Ok(s) if s & 1 == 0);
+
+    // Disabling the FIFOs also clears them and IIR[7:6]
+    assert_matches!(serial.write(TX_HOLDING_REGISTER, 1, b'B' as u64), Ok(_));
+    assert_matches!(serial.write(FIFO_CONTROL_REGISTER, 1, 0x00), Ok(_));
+    assert_matches!(serial.read(LINE_STATUS_REGISTER, 1), Ok(s) if s & 1 == 0);
+    assert_matches!(serial.read(INTERRUPT_IDENTIFICATION_REGISTER, 1), Ok(0x01));
+}
@@ -12,12 +12,13 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+pub mod aml;
 pub mod bindings;
 pub mod reg;
 
 use std::mem::{offset_of, size_of};
 
-use zerocopy::{FromBytes, IntoBytes, transmute};
+use zerocopy::{FromBytes, FromZeros, IntoBytes, transmute};
 
 #[cfg(target_arch = "x86_64")]
 use crate::arch::layout::{
@@ -27,12 +28,17 @@ use crate::arch::layout::{PCIE_CONFIG_START, PORT_ACPI_TIMER};
 use crate::firmware::acpi::bindings::AcpiFadtFlag;
 use crate::utils::wrapping_sum;
 
+use self::aml::Aml;
 use self::bindings::{
     AcpiGenericAddress, AcpiMadtIoApic, AcpiMadtLocalX2apic, AcpiMcfgAllocation,
-    AcpiSubtableHeader, AcpiTableFadt, AcpiTableHeader, AcpiTableMadt, AcpiTableMcfg1,
-    AcpiTableRsdp, AcpiTableXsdt3, FADT_MAJOR_VERSION, FADT_MINOR_VERSION, MADT_IO_APIC,
-    MADT_LOCAL_X2APIC, MADT_REVISION, MCFG_REVISION, RSDP_REVISION, SIG_FADT, SIG_MADT, SIG_MCFG,
-    SIG_RSDP, SIG_XSDT, XSDT_REVISION,
+    AcpiSratMemAffinity, AcpiSratX2apicCpuAffinity, AcpiSubtableHeader, AcpiTableFadt,
+    AcpiTableHeader, AcpiTableMadt, AcpiTableMcfg1, AcpiTableRsdp, AcpiTableSlit, AcpiTableSrat,
+    AcpiTableViot, AcpiViotHeader, AcpiViotPciRange, AcpiViotVirtioIommuPci, DSDT_REVISION,
+    FADT_MAJOR_VERSION, FADT_MINOR_VERSION, MADT_IO_APIC, MADT_LOCAL_X2APIC, MADT_REVISION,
+    MCFG_REVISION, RSDP_REVISION, SIG_DSDT, SIG_FADT, SIG_MADT, SIG_MCFG, SIG_RSDP, SIG_SLIT,
+    SIG_SRAT, SIG_VIOT, SIG_XSDT, SLIT_REVISION, SRAT_ENABLED, SRAT_MEMORY_AFFINITY, SRAT_REVISION,
+    SRAT_X2APIC_CPU_AFFINITY, VIOT_NODE_PCI_RANGE, VIOT_NODE_VIRTIO_IOMMU_PCI, VIOT_REVISION,
+    XSDT_REVISION,
 };
 use self::reg::FADT_RESET_VAL;
 
@@ -63,18 +69,19 @@ pub fn create_rsdp(xsdt_addr: u64) -> AcpiTableRsdp {
 }
 
 // https://uefi.org/htmlspecs/ACPI_Spec_6_4_html/05_ACPI_Software_Programming_Model/ACPI_Software_Programming_Model.html#extended-system-description-table-fields-xsdt
-pub fn create_xsdt(entries: [u64; 3]) -> AcpiTableXsdt3 {
-    let total_length = size_of::<AcpiTableHeader>() + size_of::<u64>() * 3;
-    let entries = entries.map(|e| transmute!(e));
-    AcpiTableXsdt3 {
-        header: AcpiTableHeader {
-            signature: SIG_XSDT,
-            length: total_length as u32,
-            revision: XSDT_REVISION,
-            ..default_header()
-        },
-        entries,
+pub fn create_xsdt(entries: &[u64]) -> Vec<u8> {
+    let total_length = size_of::<AcpiTableHeader>() + size_of_val(entries);
+    let header = AcpiTableHeader {
+        signature: SIG_XSDT,
+        length: total_length as u32,
+        revision: XSDT_REVISION,
+        ..default_header()
+    };
+    let mut bytes = header.as_bytes().to_vec();
+    for entry in entries {
+        bytes.extend(entry.to_le_bytes());
     }
+    bytes
 }
 
 // https://uefi.org/htmlspecs/ACPI_Spec_6_4_html/05_ACPI_Software_Programming_Model/ACPI_Software_Programming_Model.html#fadt-format
@@ -198,6 +205,141 @@ pub fn create_mcfg() -> AcpiTableMcfg1 {
     mcfg
 }
 
+/// Fills in the header of a table in `bytes`, with `header` providing the
+/// signature and revision.
+fn finish_table(header: AcpiTableHeader, bytes: &mut [u8]) {
+    let header = AcpiTableHeader {
+        length: bytes.len() as u32,
+        ..header
+    };
+    header.write_to_prefix(bytes).unwrap();
+    let checksum = 0u8.wrapping_sub(wrapping_sum(&*bytes));
+    bytes[offset_of!(AcpiTableHeader, checksum)] = checksum;
+}
+
+// https://uefi.org/htmlspecs/ACPI_Spec_6_4_html/05_ACPI_Software_Programming_Model/ACPI_Software_Programming_Model.html#differentiated-system-description-table-dsdt
+pub fn create_dsdt(objects: &[&dyn Aml]) -> Vec<u8> {
+    let mut bytes = AcpiTableHeader::new_zeroed().as_bytes().to_vec();
+    for obj in objects {
+        obj.encode(&mut bytes);
+    }
+    let header = AcpiTableHeader {
+        signature: SIG_DSDT,
+        revision: DSDT_REVISION,
+        ..default_header()
+    };
+    finish_table(header, &mut bytes);
+    bytes
+}
+
+// https://uefi.org/specs/ACPI/6.5/05_ACPI_Software_Programming_Model.html#system-resource-affinity-table-srat
+/// `cpus` are pairs of x2APIC IDs and proximity domains. `mems` are tuples
+/// of proximity domains, base addresses and lengths.
+pub fn create_srat(cpus: &[(u32, u32)], mems: &[(u32, u64, u64)]) -> Vec<u8> {
+    let mut srat = AcpiTableSrat::new_zeroed();
+    srat.table_revision = 1;
+    let mut bytes = srat.as_bytes().to_vec();
+    for (apic_id, node) in cpus {
+        let affinity = AcpiSratX2apicCpuAffinity {
+            header: AcpiSubtableHeader {
+                type_: SRAT_X2APIC_CPU_AFFINITY,
+                length: size_of::<AcpiSratX2apicCpuAffinity>() as u8,
+            },
+            proximity_domain: *node,
+            apic_id: *apic_id,
+            flags: SRAT_ENABLED,
+            ..Default::default()
+        };
+        bytes.extend(affinity.as_bytes());
+    }
+    for (node, base, len) in mems {
+        let affinity = AcpiSratMemAffinity {
+            header: AcpiSubtableHeader {
+                type_: SRAT_MEMORY_AFFINITY,
+                length: size_of::<AcpiSratMemAffinity>() as u8,
+            },
+            proximity_domain: transmute!(*node),
+            base_address: transmute!(*base),
+            length: transmute!(*len),
+            flags: SRAT_ENABLED,
+            ..Default::default()
+        };
+        bytes.extend(affinity.as_bytes());
+    }
+    let header = AcpiTableHeader {
+        signature: SIG_SRAT,
+        revision: SRAT_REVISION,
+        ..default_header()
+    };
+    finish_table(header, &mut bytes);
+    bytes
+}
+
+// https://uefi.org/specs/ACPI/6.5/05_ACPI_Software_Programming_Model.html#system-locality-information-table-slit
+/// `distances[i][j]` is the distance from locality `i` to `j`.
+pub fn create_slit(distances: &[&[u8]]) -> Vec<u8> {
+    let slit = AcpiTableSlit {
+        locality_count: transmute!(distances.len() as u64),
+        ..Default::default()
+    };
+    let mut bytes = slit.as_bytes().to_vec();
+    for row in distances {
+        bytes.extend(*row);
+    }
+    let header = AcpiTableHeader {
+        signature: SIG_SLIT,
+        revision: SLIT_REVISION,
+        ..default_header()
+    };
+    finish_table(header, &mut bytes);
+    bytes
+}
+
+// https://uefi.org/specs/ACPI/6.5/05_ACPI_Software_Programming_Model.html#virtual-i-o-translation-table-viot
+/// `ranges` are inclusive ranges of BDFs translated by the virtio-iommu
+/// device at `iommu_bdf`. Endpoint IDs are the same as BDFs.
+pub fn create_viot(iommu_bdf: u16, ranges: &[(u16, u16)]) -> Vec<u8> {
+    let node_offset = size_of::<AcpiTableViot>();
+    let viot = AcpiTableViot {
+        node_count: 1 + ranges.len() as u16,
+        node_offset: node_offset as u16,
+        ..Default::default()
+    };
+    let mut bytes = viot.as_bytes().to_vec();
+    let iommu = AcpiViotVirtioIommuPci {
+        header: AcpiViotHeader {
+            type_: VIOT_NODE_VIRTIO_IOMMU_PCI,
+            length: size_of::<AcpiViotVirtioIommuPci>() as u16,
+            ..Default::default()
+        },
+        bdf: iommu_bdf,
+        ..Default::default()
+    };
+    bytes.extend(iommu.as_bytes());
+    for (start, end) in ranges {
+        let range = AcpiViotPciRange {
+            header: AcpiViotHeader {
+                type_: VIOT_NODE_PCI_RANGE,
+                length: size_of::<AcpiViotPciRange>() as u16,
+                ..Default::default()
+            },
+            endpoint_start: u32::from(*start),
+            bdf_start: *start,
+            bdf_end: *end,
+            output_node: node_offset as u16,
+            ..Default::default()
+        };
+        bytes.extend(range.as_bytes());
+    }
+    let header = AcpiTableHeader {
+        signature: SIG_VIOT,
+        revision: VIOT_REVISION,
+        ..default_header()
+    };
+    finish_table(header, &mut bytes);
+    bytes
+}
+
 pub struct AcpiTable {
     pub(crate) rsdp: AcpiTableRsdp,
     pub(crate) tables: Vec<u8>,
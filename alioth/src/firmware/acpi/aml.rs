@@ -0,0 +1,891 @@
+// Copyright 2026 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Typed generation of ACPI Machine Language (AML).
+//!
+//! Each type encodes one AML object or resource descriptor. Containers like
+//! [`Device`] and [`Method`] collect their children with `with`. Integers
+//! are encoded in the shortest form, the same as iasl does.
+//!
+//! ACPI v6.5, Sec. 20.2, AML Grammar Definition.
+
+use std::fmt::Debug;
+
+const ZERO_OP: u8 = 0x00;
+const ONE_OP: u8 = 0x01;
+const ONES_OP: u8 = 0xff;
+const BYTE_PREFIX: u8 = 0x0a;
+const WORD_PREFIX: u8 = 0x0b;
+const DWORD_PREFIX: u8 = 0x0c;
+const STRING_PREFIX: u8 = 0x0d;
+const QWORD_PREFIX: u8 = 0x0e;
+const SCOPE_OP: u8 = 0x10;
+const BUFFER_OP: u8 = 0x11;
+const PACKAGE_OP: u8 = 0x12;
+const NAME_OP: u8 = 0x08;
+const METHOD_OP: u8 = 0x14;
+const DUAL_NAME_PREFIX: u8 = 0x2e;
+const MULTI_NAME_PREFIX: u8 = 0x2f;
+const EXT_OP_PREFIX: u8 = 0x5b;
+const OP_REGION_OP: u8 = 0x80;
+const FIELD_OP: u8 = 0x81;
+const DEVICE_OP: u8 = 0x82;
+const ROOT_CHAR: u8 = b'\\';
+const PARENT_PREFIX_CHAR: u8 = b'^';
+const LOCAL0_OP: u8 = 0x60;
+const ARG0_OP: u8 = 0x68;
+const STORE_OP: u8 = 0x70;
+const AND_OP: u8 = 0x7b;
+const OR_OP: u8 = 0x7d;
+const SHIFT_LEFT_OP: u8 = 0x79;
+const NOTIFY_OP: u8 = 0x86;
+const LAND_OP: u8 = 0x90;
+const LEQUAL_OP: u8 = 0x93;
+const LGREATER_OP: u8 = 0x94;
+const LLESS_OP: u8 = 0x95;
+const IF_OP: u8 = 0xa0;
+const ELSE_OP: u8 = 0xa1;
+const RETURN_OP: u8 = 0xa4;
+
+const DESC_IRQ_NO_FLAGS: u8 = 0x22;
+const DESC_IO: u8 = 0x47;
+const DESC_END_TAG: u8 = 0x79;
+const DESC_DWORD_ADDRESS_SPACE: u8 = 0x87;
+const DESC_WORD_ADDRESS_SPACE: u8 = 0x88;
+const DESC_EXTENDED_INTERRUPT: u8 = 0x89;
+const DESC_QWORD_ADDRESS_SPACE: u8 = 0x8a;
+
+pub trait Aml: Debug + Send + Sync {
+    /// Appends the AML encoding of the object to `bytes`.
+    fn encode(&self, bytes: &mut Vec<u8>);
+
+    fn to_aml_bytes(&self) -> Vec<u8> {
+        let mut bytes = vec![];
+        self.encode(&mut bytes);
+        bytes
+    }
+}
+
+// ACPI v6.5, Sec. 20.2.4, Package Length Encoding
+fn encode_length(len: usize, bytes: &mut Vec<u8>) {
+    let count = match len {
+        0..0x40 => 0,
+        0x40..0x1000 => 1,
+        0x1000..0x10_0000 => 2,
+        0x10_0000..0x1000_0000 => 3,
+        _ => panic!("{len:#x} exceeds the max package length"),
+    };
+    if count == 0 {
+        bytes.push(len as u8);
+        return;
+    }
+    bytes.push(((count as u8) << 6) | (len & 0xf) as u8);
+    for i in 0..count {
+        bytes.push((len >> (4 + 8 * i)) as u8);
+    }
+}
+
+fn encode_pkg(content: &[u8], bytes: &mut Vec<u8>) {
+    // The length includes the bytes encoding itself.
+    let len = content.len();
+    let total = if len + 1 < 0x40 {
+        len + 1
+    } else if len + 2 < 0x1000 {
+        len + 2
+    } else if len + 3 < 0x10_0000 {
+        len + 3
+    } else {
+        len + 4
+    };
+    encode_length(total, bytes);
+    bytes.extend(content);
+}
+
+fn encode_all(objects: &[Box<dyn Aml>]) -> Vec<u8> {
+    let mut bytes = vec![];
+    for obj in objects {
+        obj.encode(&mut bytes);
+    }
+    bytes
+}
+
+/// A name segment, padded with `_` to 4 characters.
+fn name_seg(seg: &str) -> [u8; 4] {
+    assert!(
+        (1..=4).contains(&seg.len()) && seg.bytes().all(|b| b.is_ascii_alphanumeric() || b == b'_'),
+        "invalid name segment {seg:?}"
+    );
+    let mut name = [b'_'; 4];
+    name[..seg.len()].copy_from_slice(seg.as_bytes());
+    name
+}
+
+/// A name string like `\_SB.PCI0.S01` or `^PCNT`.
+///
+/// ACPI v6.5, Sec. 20.2.2, Name Objects Encoding
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NameString {
+    prefix: Vec<u8>,
+    segs: Vec<[u8; 4]>,
+}
+
+impl NameString {
+    pub fn new(path: &str) -> Self {
+        let mut prefix = vec![];
+        let mut rest = path;
+        if let Some(s) = rest.strip_prefix('\\') {
+            prefix.push(ROOT_CHAR);
+            rest = s;
+        } else {
+            while let Some(s) = rest.strip_prefix('^') {
+                prefix.push(PARENT_PREFIX_CHAR);
+                rest = s;
+            }
+        }
+        let segs = if rest.is_empty() {
+            vec![]
+        } else {
+            rest.split('.').map(name_seg).collect()
+        };
+        NameString { prefix, segs }
+    }
+}
+
+impl Aml for NameString {
+    fn encode(&self, bytes: &mut Vec<u8>) {
+        bytes.extend(&self.prefix);
+        match self.segs.len() {
+            0 => bytes.push(ZERO_OP),
+            1 => {}
+            2 => bytes.push(DUAL_NAME_PREFIX),
+            n => bytes.extend([MULTI_NAME_PREFIX, n as u8]),
+        }
+        bytes.extend(self.segs.iter().flatten());
+    }
+}
+
+fn encode_integer(val: u64, bytes: &mut Vec<u8>) {
+    match val {
+        0 => bytes.push(ZERO_OP),
+        1 => bytes.push(ONE_OP),
+        2..=0xff => bytes.extend([BYTE_PREFIX, val as u8]),
+        0x100..=0xffff => {
+            bytes.push(WORD_PREFIX);
+            bytes.extend((val as u16).to_le_bytes());
+        }
+        0x1_0000..=0xffff_ffff => {
+            bytes.push(DWORD_PREFIX);
+            bytes.extend((val as u32).to_le_bytes());
+        }
+        _ => {
+            bytes.push(QWORD_PREFIX);
+            bytes.extend(val.to_le_bytes());
+        }
+    }
+}
+
+macro_rules! impl_aml_integer {
+    ($($ty:ty),+) => {
+        $(impl Aml for $ty {
+            fn encode(&self, bytes: &mut Vec<u8>) {
+                encode_integer(*self as u64, bytes)
+            }
+        })+
+    };
+}
+
+impl_aml_integer!(u8, u16, u32, u64);
+
+/// The integer with all bits set.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Ones;
+
+impl Aml for Ones {
+    fn encode(&self, bytes: &mut Vec<u8>) {
+        bytes.push(ONES_OP);
+    }
+}
+
+/// A string literal.
+impl Aml for &'static str {
+    fn encode(&self, bytes: &mut Vec<u8>) {
+        assert!(self.is_ascii() && !self.contains('\0'));
+        bytes.push(STRING_PREFIX);
+        bytes.extend(self.as_bytes());
+        bytes.push(0);
+    }
+}
+
+/// A compressed EISA ID like `PNP0A08`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EisaId(pub u32);
+
+impl EisaId {
+    pub fn new(id: &str) -> Self {
+        let id = id.as_bytes();
+        assert!(
+            id.len() == 7
+                && id[..3].iter().all(u8::is_ascii_uppercase)
+                && id[3..].iter().all(u8::is_ascii_hexdigit),
+            "invalid EISA ID"
+        );
+        let vendor = id[..3]
+            .iter()
+            .fold(0u16, |acc, c| (acc << 5) | (c - b'@') as u16);
+        let product = std::str::from_utf8(&id[3..]).unwrap();
+        let product = u16::from_str_radix(product, 16).unwrap();
+        let val = ((vendor as u32) << 16) | product as u32;
+        EisaId(val.swap_bytes())
+    }
+}
+
+impl Aml for EisaId {
+    fn encode(&self, bytes: &mut Vec<u8>) {
+        bytes.push(DWORD_PREFIX);
+        bytes.extend(self.0.to_le_bytes());
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Buffer(pub Vec<u8>);
+
+impl Aml for Buffer {
+    fn encode(&self, bytes: &mut Vec<u8>) {
+        let mut content = vec![];
+        encode_integer(self.0.len() as u64, &mut content);
+        content.extend(&self.0);
+        bytes.push(BUFFER_OP);
+        encode_pkg(&content, bytes);
+    }
+}
+
+/// A buffer holding the binary form of a UUID, as created by `ToUUID`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Uuid(pub [u8; 16]);
+
+impl Uuid {
+    pub fn new(uuid: &str) -> Self {
+        let hex: String = uuid.split('-').collect();
+        assert_eq!(hex.len(), 32, "invalid UUID {uuid:?}");
+        let val = u128::from_str_radix(&hex, 16).unwrap();
+        let mut b = val.to_be_bytes();
+        b[0..4].reverse();
+        b[4..6].reverse();
+        b[6..8].reverse();
+        Uuid(b)
+    }
+}
+
+impl Aml for Uuid {
+    fn encode(&self, bytes: &mut Vec<u8>) {
+        Buffer(self.0.to_vec()).encode(bytes)
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Package {
+    elements: Vec<Box<dyn Aml>>,
+}
+
+impl Package {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with(mut self, element: impl Aml + 'static) -> Self {
+        self.elements.push(Box::new(element));
+        self
+    }
+}
+
+impl Aml for Package {
+    fn encode(&self, bytes: &mut Vec<u8>) {
+        assert!(self.elements.len() <= 0xff);
+        let mut content = vec![self.elements.len() as u8];
+        content.extend(encode_all(&self.elements));
+        bytes.push(PACKAGE_OP);
+        encode_pkg(&content, bytes);
+    }
+}
+
+#[derive(Debug)]
+pub struct Name {
+    path: NameString,
+    value: Box<dyn Aml>,
+}
+
+impl Name {
+    pub fn new(path: &str, value: impl Aml + 'static) -> Self {
+        Name {
+            path: NameString::new(path),
+            value: Box::new(value),
+        }
+    }
+}
+
+impl Aml for Name {
+    fn encode(&self, bytes: &mut Vec<u8>) {
+        bytes.push(NAME_OP);
+        self.path.encode(bytes);
+        self.value.encode(bytes);
+    }
+}
+
+macro_rules! container {
+    ($(#[$attr:meta])* $name:ident, $($op:expr),+) => {
+        $(#[$attr])*
+        #[derive(Debug)]
+        pub struct $name {
+            path: NameString,
+            children: Vec<Box<dyn Aml>>,
+        }
+
+        impl $name {
+            pub fn new(path: &str) -> Self {
+                $name {
+                    path: NameString::new(path),
+                    children: vec![],
+                }
+            }
+
+            pub fn with(mut self, child: impl Aml + 'static) -> Self {
+                self.children.push(Box::new(child));
+                self
+            }
+        }
+
+        impl Aml for $name {
+            fn encode(&self, bytes: &mut Vec<u8>) {
+                let mut content = self.path.to_aml_bytes();
+                content.extend(encode_all(&self.children));
+                bytes.extend([$($op),+]);
+                encode_pkg(&content, bytes);
+            }
+        }
+    };
+}
+
+container!(Scope, SCOPE_OP);
+container!(Device, EXT_OP_PREFIX, DEVICE_OP);
+
+#[derive(Debug)]
+pub struct Method {
+    path: NameString,
+    args: u8,
+    serialized: bool,
+    body: Vec<Box<dyn Aml>>,
+}
+
+impl Method {
+    pub fn new(path: &str, args: u8, serialized: bool) -> Self {
+        assert!(args <= 7);
+        Method {
+            path: NameString::new(path),
+            args,
+            serialized,
+            body: vec![],
+        }
+    }
+
+    pub fn with(mut self, term: impl Aml + 'static) -> Self {
+        self.body.push(Box::new(term));
+        self
+    }
+}
+
+impl Aml for Method {
+    fn encode(&self, bytes: &mut Vec<u8>) {
+        let mut content = self.path.to_aml_bytes();
+        content.push(self.args | ((self.serialized as u8) << 3));
+        content.extend(encode_all(&self.body));
+        bytes.push(METHOD_OP);
+        encode_pkg(&content, bytes);
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum RegionSpace {
+    SystemMemory = 0,
+    SystemIo = 1,
+    PciConfig = 2,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OpRegion {
+    path: NameString,
+    space: RegionSpace,
+    offset: u64,
+    len: u64,
+}
+
+impl OpRegion {
+    pub fn new(path: &str, space: RegionSpace, offset: u64, len: u64) -> Self {
+        OpRegion {
+            path: NameString::new(path),
+            space,
+            offset,
+            len,
+        }
+    }
+}
+
+impl Aml for OpRegion {
+    fn encode(&self, bytes: &mut Vec<u8>) {
+        bytes.extend([EXT_OP_PREFIX, OP_REGION_OP]);
+        self.path.encode(bytes);
+        bytes.push(self.space as u8);
+        encode_integer(self.offset, bytes);
+        encode_integer(self.len, bytes);
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum FieldAccess {
+    Any = 0,
+    Byte = 1,
+    Word = 2,
+    DWord = 3,
+    QWord = 4,
+}
+
+/// Named fields of an [`OpRegion`], with `NoLock` and `Preserve` rules.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Field {
+    region: NameString,
+    access: FieldAccess,
+    entries: Vec<([u8; 4], usize)>,
+}
+
+impl Field {
+    pub fn new(region: &str, access: FieldAccess) -> Self {
+        Field {
+            region: NameString::new(region),
+            access,
+            entries: vec![],
+        }
+    }
+
+    pub fn with(mut self, name: &str, bits: usize) -> Self {
+        self.entries.push((name_seg(name), bits));
+        self
+    }
+}
+
+impl Aml for Field {
+    fn encode(&self, bytes: &mut Vec<u8>) {
+        let mut content = self.region.to_aml_bytes();
+        content.push(self.access as u8);
+        for (name, bits) in &self.entries {
+            content.extend(name);
+            encode_length(*bits, &mut content);
+        }
+        bytes.extend([EXT_OP_PREFIX, FIELD_OP]);
+        encode_pkg(&content, bytes);
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Local(pub u8);
+
+impl Aml for Local {
+    fn encode(&self, bytes: &mut Vec<u8>) {
+        assert!(self.0 <= 7);
+        bytes.push(LOCAL0_OP + self.0);
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Arg(pub u8);
+
+impl Aml for Arg {
+    fn encode(&self, bytes: &mut Vec<u8>) {
+        assert!(self.0 <= 6);
+        bytes.push(ARG0_OP + self.0);
+    }
+}
+
+/// `Store (src, dst)`, or `dst = src` in ASL 2.0.
+#[derive(Debug)]
+pub struct Store {
+    src: Box<dyn Aml>,
+    dst: Box<dyn Aml>,
+}
+
+impl Store {
+    pub fn new(src: impl Aml + 'static, dst: impl Aml + 'static) -> Self {
+        Store {
+            src: Box::new(src),
+            dst: Box::new(dst),
+        }
+    }
+}
+
+impl Aml for Store {
+    fn encode(&self, bytes: &mut Vec<u8>) {
+        bytes.push(STORE_OP);
+        self.src.encode(bytes);
+        self.dst.encode(bytes);
+    }
+}
+
+macro_rules! binary_op {
+    ($(#[$attr:meta])* $name:ident, $op:expr, $has_target:expr) => {
+        $(#[$attr])*
+        #[derive(Debug)]
+        pub struct $name {
+            a: Box<dyn Aml>,
+            b: Box<dyn Aml>,
+        }
+
+        impl $name {
+            pub fn new(a: impl Aml + 'static, b: impl Aml + 'static) -> Self {
+                $name {
+                    a: Box::new(a),
+                    b: Box::new(b),
+                }
+            }
+        }
+
+        impl Aml for $name {
+            fn encode(&self, bytes: &mut Vec<u8>) {
+                bytes.push($op);
+                self.a.encode(bytes);
+                self.b.encode(bytes);
+                if $has_target {
+                    bytes.push(ZERO_OP);
+                }
+            }
+        }
+    };
+}
+
+binary_op!(
+    /// `a & b`, without a target.
+    And,
+    AND_OP,
+    true
+);
+binary_op!(
+    /// `a | b`, without a target.
+    Or,
+    OR_OP,
+    true
+);
+binary_op!(
+    /// `a << b`, without a target.
+    ShiftLeft,
+    SHIFT_LEFT_OP,
+    true
+);
+binary_op!(
+    /// `a && b`
+    LAnd,
+    LAND_OP,
+    false
+);
+binary_op!(
+    /// `a == b`
+    LEqual,
+    LEQUAL_OP,
+    false
+);
+binary_op!(
+    /// `a > b`
+    LGreater,
+    LGREATER_OP,
+    false
+);
+binary_op!(
+    /// `a < b`
+    LLess,
+    LLESS_OP,
+    false
+);
+
+#[derive(Debug)]
+pub struct Notify {
+    object: NameString,
+    value: Box<dyn Aml>,
+}
+
+impl Notify {
+    pub fn new(object: &str, value: impl Aml + 'static) -> Self {
+        Notify {
+            object: NameString::new(object),
+            value: Box::new(value),
+        }
+    }
+}
+
+impl Aml for Notify {
+    fn encode(&self, bytes: &mut Vec<u8>) {
+        bytes.push(NOTIFY_OP);
+        self.object.encode(bytes);
+        self.value.encode(bytes);
+    }
+}
+
+#[derive(Debug)]
+pub struct Return(Box<dyn Aml>);
+
+impl Return {
+    pub fn new(value: impl Aml + 'static) -> Self {
+        Return(Box::new(value))
+    }
+}
+
+impl Aml for Return {
+    fn encode(&self, bytes: &mut Vec<u8>) {
+        bytes.push(RETURN_OP);
+        self.0.encode(bytes);
+    }
+}
+
+#[derive(Debug)]
+pub struct If {
+    predicate: Box<dyn Aml>,
+    body: Vec<Box<dyn Aml>>,
+    else_body: Option<Vec<Box<dyn Aml>>>,
+}
+
+impl If {
+    pub fn new(predicate: impl Aml + 'static) -> Self {
+        If {
+            predicate: Box::new(predicate),
+            body: vec![],
+            else_body: None,
+        }
+    }
+
+    pub fn with(mut self, term: impl Aml + 'static) -> Self {
+        self.body.push(Box::new(term));
+        self
+    }
+
+    /// Appends `term` to the `Else` branch.
+    pub fn with_else(mut self, term: impl Aml + 'static) -> Self {
+        let else_body = self.else_body.get_or_insert_default();
+        else_body.push(Box::new(term));
+        self
+    }
+}
+
+impl Aml for If {
+    fn encode(&self, bytes: &mut Vec<u8>) {
+        let mut content = self.predicate.to_aml_bytes();
+        content.extend(encode_all(&self.body));
+        bytes.push(IF_OP);
+        encode_pkg(&content, bytes);
+        if let Some(else_body) = &self.else_body {
+            bytes.push(ELSE_OP);
+            encode_pkg(&encode_all(else_body), bytes);
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct MethodCall {
+    path: NameString,
+    args: Vec<Box<dyn Aml>>,
+}
+
+impl MethodCall {
+    pub fn new(path: &str) -> Self {
+        MethodCall {
+            path: NameString::new(path),
+            args: vec![],
+        }
+    }
+
+    pub fn arg(mut self, arg: impl Aml + 'static) -> Self {
+        self.args.push(Box::new(arg));
+        self
+    }
+}
+
+impl Aml for MethodCall {
+    fn encode(&self, bytes: &mut Vec<u8>) {
+        self.path.encode(bytes);
+        bytes.extend(encode_all(&self.args));
+    }
+}
+
+/// A buffer of resource descriptors, terminated by an end tag.
+///
+/// ACPI v6.5, Sec. 6.4, Resource Data Types for ACPI
+#[derive(Debug, Default)]
+pub struct ResourceTemplate {
+    descriptors: Vec<Box<dyn Aml>>,
+}
+
+impl ResourceTemplate {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with(mut self, descriptor: impl Aml + 'static) -> Self {
+        self.descriptors.push(Box::new(descriptor));
+        self
+    }
+}
+
+impl Aml for ResourceTemplate {
+    fn encode(&self, bytes: &mut Vec<u8>) {
+        let mut data = encode_all(&self.descriptors);
+        // A zero checksum is treated as valid.
+        data.extend([DESC_END_TAG, 0]);
+        Buffer(data).encode(bytes)
+    }
+}
+
+/// An I/O port descriptor with 16-bit decoding.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Io {
+    pub min: u16,
+    pub max: u16,
+    pub align: u8,
+    pub len: u8,
+}
+
+impl Aml for Io {
+    fn encode(&self, bytes: &mut Vec<u8>) {
+        bytes.extend([DESC_IO, 1]);
+        bytes.extend(self.min.to_le_bytes());
+        bytes.extend(self.max.to_le_bytes());
+        bytes.extend([self.align, self.len]);
+    }
+}
+
+/// An edge-triggered, active-high ISA interrupt.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IrqNoFlags(pub u8);
+
+impl Aml for IrqNoFlags {
+    fn encode(&self, bytes: &mut Vec<u8>) {
+        assert!(self.0 < 16);
+        bytes.push(DESC_IRQ_NO_FLAGS);
+        bytes.extend((1u16 << self.0).to_le_bytes());
+    }
+}
+
+/// An extended interrupt descriptor consumed by the device. The interrupt
+/// is edge-triggered, active-high and exclusive.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Interrupt(pub u32);
+
+impl Aml for Interrupt {
+    fn encode(&self, bytes: &mut Vec<u8>) {
+        const CONSUMER: u8 = 1 << 0;
+        const EDGE: u8 = 1 << 1;
+        bytes.push(DESC_EXTENDED_INTERRUPT);
+        bytes.extend(6u16.to_le_bytes());
+        bytes.extend([CONSUMER | EDGE, 1]);
+        bytes.extend(self.0.to_le_bytes());
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum MemAttr {
+    NonCacheable = 0,
+    Cacheable = 1,
+    WriteCombining = 2,
+    Prefetchable = 3,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AddressSpaceType {
+    /// Read-write memory.
+    Memory(MemAttr),
+    /// I/O ports of the entire range.
+    Io,
+    BusNumber,
+}
+
+/// A Word, DWord or QWord address space descriptor, depending on `T`.
+///
+/// The range `min..=max` is produced by the device with fixed bounds,
+/// positive decoding and no translation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AddressSpace<T> {
+    pub ty: AddressSpaceType,
+    pub min: T,
+    pub max: T,
+}
+
+impl<T> AddressSpace<T> {
+    pub fn new_memory(attr: MemAttr, min: T, max: T) -> Self {
+        AddressSpace {
+            ty: AddressSpaceType::Memory(attr),
+            min,
+            max,
+        }
+    }
+
+    pub fn new_io(min: T, max: T) -> Self {
+        AddressSpace {
+            ty: AddressSpaceType::Io,
+            min,
+            max,
+        }
+    }
+
+    pub fn new_bus_number(min: T, max: T) -> Self {
+        AddressSpace {
+            ty: AddressSpaceType::BusNumber,
+            min,
+            max,
+        }
+    }
+
+    fn encode_header(&self, desc: u8, bytes: &mut Vec<u8>) {
+        const MIN_FIXED: u8 = 1 << 2;
+        const MAX_FIXED: u8 = 1 << 3;
+        const READ_WRITE: u8 = 1 << 0;
+        const ENTIRE_RANGE: u8 = 0b11;
+        let (ty, flags) = match self.ty {
+            AddressSpaceType::Memory(attr) => (0, READ_WRITE | ((attr as u8) << 1)),
+            AddressSpaceType::Io => (1, ENTIRE_RANGE),
+            AddressSpaceType::BusNumber => (2, 0),
+        };
+        let len = 3 + 5 * size_of::<T>() as u16;
+        bytes.push(desc);
+        bytes.extend(len.to_le_bytes());
+        bytes.extend([ty, MIN_FIXED | MAX_FIXED, flags]);
+    }
+}
+
+macro_rules! impl_address_space {
+    ($ty:ty, $desc:expr) => {
+        impl Aml for AddressSpace<$ty> {
+            fn encode(&self, bytes: &mut Vec<u8>) {
+                self.encode_header($desc, bytes);
+                let len = self.max.wrapping_sub(self.min).wrapping_add(1);
+                for val in [0, self.min, self.max, 0, len] {
+                    bytes.extend(val.to_le_bytes());
+                }
+            }
+        }
+    };
+}
+
+impl_address_space!(u16, DESC_WORD_ADDRESS_SPACE);
+impl_address_space!(u32, DESC_DWORD_ADDRESS_SPACE);
+impl_address_space!(u64, DESC_QWORD_ADDRESS_SPACE);
+
+#[cfg(test)]
+#[path = "aml_test.rs"]
+mod tests;
@@ -0,0 +1,252 @@
+// Copyright 2026 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use rstest::rstest;
+
+use super::{
+    AddressSpace, Aml, And, Arg, Buffer, Device, EisaId, Field, FieldAccess, If, Interrupt, Io,
+    IrqNoFlags, LEqual, Local, MemAttr, Method, MethodCall, Name, NameString, Notify, OpRegion,
+    Package, RegionSpace, ResourceTemplate, Return, Scope, Store, Uuid, encode_pkg,
+};
+
+// Most expected bytes are taken from the DSDT previously compiled by iasl.
+
+#[rstest]
+#[case(0, &[0x01])]
+#[case(0x3e, &[0x3f])]
+#[case(0x3f, &[0x41, 0x04])]
+#[case(0xffd, &[0x4f, 0xff])]
+#[case(0xffe, &[0x81, 0x00, 0x01])]
+#[case(0xf_fffc, &[0x8f, 0xff, 0xff])]
+#[case(0xf_fffd, &[0xc1, 0x00, 0x00, 0x01])]
+fn test_pkg_length(#[case] len: usize, #[case] expected: &[u8]) {
+    let mut bytes = vec![];
+    encode_pkg(&vec![0; len], &mut bytes);
+    assert_eq!(&bytes[..expected.len()], expected);
+    assert_eq!(bytes.len(), expected.len() + len);
+}
+
+#[rstest]
+#[case("PCI0", b"PCI0")]
+#[case("S01", b"S01_")]
+#[case("\\_SB.PWRB", b"\\\x2e_SB_PWRB")]
+#[case("\\_SB.PCI0.PCNT", b"\\\x2f\x03_SB_PCI0PCNT")]
+#[case("^^PCNT", b"^^PCNT")]
+#[case("\\", b"\\\x00")]
+fn test_name_string(#[case] path: &str, #[case] expected: &[u8]) {
+    assert_eq!(NameString::new(path).to_aml_bytes(), expected);
+}
+
+#[rstest]
+#[case(0, &[0x00])]
+#[case(1, &[0x01])]
+#[case(0x80, &[0x0a, 0x80])]
+#[case(0x8000, &[0x0b, 0x00, 0x80])]
+#[case(0x8000_0000, &[0x0c, 0x00, 0x00, 0x00, 0x80])]
+#[case(0x1_0000_0000, &[0x0e, 0x00, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00])]
+fn test_integer(#[case] val: u64, #[case] expected: &[u8]) {
+    assert_eq!(val.to_aml_bytes(), expected);
+}
+
+#[test]
+fn test_data_objects() {
+    assert_eq!(
+        EisaId::new("PNP0A08").to_aml_bytes(),
+        [0x0c, 0x41, 0xd0, 0x0a, 0x08]
+    );
+    assert_eq!(
+        "ACPI0013".to_aml_bytes(),
+        [0x0d, 0x41, 0x43, 0x50, 0x49, 0x30, 0x30, 0x31, 0x33, 0x00]
+    );
+    assert_eq!(
+        Uuid::new("e5c937d0-3553-4d7a-9117-ea4d19c3434d").to_aml_bytes(),
+        [
+            0x11, 0x13, 0x0a, 0x10, 0xd0, 0x37, 0xc9, 0xe5, 0x53, 0x35, 0x7a, 0x4d, 0x91, 0x17,
+            0xea, 0x4d, 0x19, 0xc3, 0x43, 0x4d,
+        ]
+    );
+    assert_eq!(
+        Name::new("_S5", Package::new().with(5u8)).to_aml_bytes(),
+        [0x08, 0x5f, 0x53, 0x35, 0x5f, 0x12, 0x04, 0x01, 0x0a, 0x05]
+    );
+}
+
+#[test]
+fn test_scope() {
+    let scope = Scope::new("\\_SB").with(Name::new("_UID", 0u8));
+    assert_eq!(
+        scope.to_aml_bytes(),
+        [
+            0x10, 0x0c, 0x5c, 0x5f, 0x53, 0x42, 0x5f, 0x08, 0x5f, 0x55, 0x49, 0x44, 0x00
+        ]
+    );
+}
+
+#[test]
+fn test_serial_device() {
+    let com1 = Device::new("_SB.COM1")
+        .with(Name::new("_HID", EisaId::new("PNP0501")))
+        .with(Name::new("_UID", 1u8))
+        .with(Name::new("_STA", 0xfu8))
+        .with(Name::new(
+            "_CRS",
+            ResourceTemplate::new()
+                .with(Io {
+                    min: 0x3f8,
+                    max: 0x3f8,
+                    align: 0,
+                    len: 8,
+                })
+                .with(IrqNoFlags(4)),
+        ));
+    assert_eq!(
+        com1.to_aml_bytes(),
+        [
+            0x5b, 0x82, 0x37, 0x2e, 0x5f, 0x53, 0x42, 0x5f, 0x43, 0x4f, 0x4d, 0x31, 0x08, 0x5f,
+            0x48, 0x49, 0x44, 0x0c, 0x41, 0xd0, 0x05, 0x01, 0x08, 0x5f, 0x55, 0x49, 0x44, 0x01,
+            0x08, 0x5f, 0x53, 0x54, 0x41, 0x0a, 0x0f, 0x08, 0x5f, 0x43, 0x52, 0x53, 0x11, 0x10,
+            0x0a, 0x0d, 0x47, 0x01, 0xf8, 0x03, 0xf8, 0x03, 0x00, 0x08, 0x22, 0x10, 0x00, 0x79,
+            0x00,
+        ]
+    );
+}
+
+#[test]
+fn test_address_space() {
+    let crs = ResourceTemplate::new()
+        .with(AddressSpace::<u16>::new_bus_number(0, 0))
+        .with(Io {
+            min: 0xcf8,
+            max: 0xcf8,
+            align: 1,
+            len: 8,
+        })
+        .with(AddressSpace::new_memory(
+            MemAttr::Prefetchable,
+            0x8000_0000u32,
+            0x9fff_ffff,
+        ))
+        .with(AddressSpace::new_memory(
+            MemAttr::NonCacheable,
+            0xa000_0000u32,
+            0xbfff_ffff,
+        ))
+        .with(AddressSpace::new_memory(
+            MemAttr::Prefetchable,
+            0x1_0000_0000u64,
+            0x100_ffff_ffff,
+        ))
+        .with(AddressSpace::<u16>::new_io(0x1000, 0xffff));
+    assert_eq!(
+        crs.to_aml_bytes(),
+        [
+            0x11, 0x40, 0x09, 0x0a, 0x8c, 0x88, 0x0d, 0x00, 0x02, 0x0c, 0x00, 0x00, 0x00, 0x00,
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x01, 0x00, 0x47, 0x01, 0xf8, 0x0c, 0xf8, 0x0c, 0x01,
+            0x08, 0x87, 0x17, 0x00, 0x00, 0x0c, 0x07, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+            0x80, 0xff, 0xff, 0xff, 0x9f, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x20, 0x87,
+            0x17, 0x00, 0x00, 0x0c, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xa0, 0xff,
+            0xff, 0xff, 0xbf, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x20, 0x8a, 0x2b, 0x00,
+            0x00, 0x0c, 0x07, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+            0x00, 0x01, 0x00, 0x00, 0x00, 0xff, 0xff, 0xff, 0xff, 0x00, 0x01, 0x00, 0x00, 0x00,
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x01, 0x00,
+            0x00, 0x88, 0x0d, 0x00, 0x01, 0x0c, 0x03, 0x00, 0x00, 0x00, 0x10, 0xff, 0xff, 0x00,
+            0x00, 0x00, 0xf0, 0x79, 0x00,
+        ]
+    );
+}
+
+#[test]
+fn test_method() {
+    let dsm = Method::new("_DSM", 4, false)
+        .with(
+            If::new(LEqual::new(
+                Arg(0),
+                Uuid::new("e5c937d0-3553-4d7a-9117-ea4d19c3434d"),
+            ))
+            .with(If::new(LEqual::new(Arg(2), 0u8)).with(Return::new(Buffer(vec![0x21]))))
+            .with(If::new(LEqual::new(Arg(2), 5u8)).with(Return::new(0u8))),
+        )
+        .with(Return::new(0u8));
+    assert_eq!(
+        dsm.to_aml_bytes(),
+        [
+            0x14, 0x32, 0x5f, 0x44, 0x53, 0x4d, 0x04, 0xa0, 0x29, 0x93, 0x68, 0x11, 0x13, 0x0a,
+            0x10, 0xd0, 0x37, 0xc9, 0xe5, 0x53, 0x35, 0x7a, 0x4d, 0x91, 0x17, 0xea, 0x4d, 0x19,
+            0xc3, 0x43, 0x4d, 0xa0, 0x09, 0x93, 0x6a, 0x00, 0xa4, 0x11, 0x03, 0x01, 0x21, 0xa0,
+            0x07, 0x93, 0x6a, 0x0a, 0x05, 0xa4, 0x00, 0xa4, 0x00,
+        ]
+    );
+
+    let pcnt = Method::new("PCNT", 0, true)
+        .with(Store::new(NameString::new("PCIU"), Local(0)))
+        .with(MethodCall::new("PCNF").arg(Local(0)).arg(1u8));
+    assert_eq!(
+        pcnt.to_aml_bytes(),
+        [
+            0x14, 0x12, 0x50, 0x43, 0x4e, 0x54, 0x08, 0x70, 0x50, 0x43, 0x49, 0x55, 0x60, 0x50,
+            0x43, 0x4e, 0x46, 0x60, 0x01,
+        ]
+    );
+
+    let branch = If::new(LEqual::new(Arg(0), 1u8))
+        .with(Return::new(1u8))
+        .with_else(Return::new(0u8));
+    assert_eq!(
+        branch.to_aml_bytes(),
+        [
+            0xa0, 0x06, 0x93, 0x68, 0x01, 0xa4, 0x01, 0xa1, 0x03, 0xa4, 0x00
+        ]
+    );
+}
+
+#[test]
+fn test_generic_event_device() {
+    let ged = Device::new("_SB.GED0")
+        .with(Name::new("_HID", "ACPI0013"))
+        .with(Name::new("_UID", 0u8))
+        .with(Name::new(
+            "_CRS",
+            ResourceTemplate::new().with(Interrupt(5)),
+        ))
+        .with(OpRegion::new("GEDR", RegionSpace::SystemIo, 0x620, 4))
+        .with(Field::new("GEDR", FieldAccess::DWord).with("GEDE", 32))
+        .with(
+            Method::new("_EVT", 1, false)
+                .with(Store::new(NameString::new("GEDE"), Local(0)))
+                .with(Store::new(Local(0), NameString::new("GEDE")))
+                .with(If::new(And::new(Local(0), 1u32)).with(Notify::new("\\_SB.PWRB", 0x80u8)))
+                .with(MethodCall::new("\\_SB.PCI0.PCNT")),
+        );
+    assert_eq!(
+        ged.to_aml_bytes(),
+        [
+            0x5b, 0x82, 0x42, 0x08, 0x2e, 0x5f, 0x53, 0x42, 0x5f, 0x47, 0x45, 0x44, 0x30, 0x08,
+            0x5f, 0x48, 0x49, 0x44, 0x0d, 0x41, 0x43, 0x50, 0x49, 0x30, 0x30, 0x31, 0x33, 0x00,
+            0x08, 0x5f, 0x55, 0x49, 0x44, 0x00, 0x08, 0x5f, 0x43, 0x52, 0x53, 0x11, 0x0e, 0x0a,
+            0x0b, 0x89, 0x06, 0x00, 0x03, 0x01, 0x05, 0x00, 0x00, 0x00, 0x79, 0x00, 0x5b, 0x80,
+            0x47, 0x45, 0x44, 0x52, 0x01, 0x0b, 0x20, 0x06, 0x0a, 0x04, 0x5b, 0x81, 0x0b, 0x47,
+            0x45, 0x44, 0x52, 0x03, 0x47, 0x45, 0x44, 0x45, 0x20, 0x14, 0x34, 0x5f, 0x45, 0x56,
+            0x54, 0x01, 0x70, 0x47, 0x45, 0x44, 0x45, 0x60, 0x70, 0x60, 0x47, 0x45, 0x44, 0x45,
+            0xa0, 0x12, 0x7b, 0x60, 0x01, 0x00, 0x86, 0x5c, 0x2e, 0x5f, 0x53, 0x42, 0x5f, 0x50,
+            0x57, 0x52, 0x42, 0x0a, 0x80, 0x5c, 0x2f, 0x03, 0x5f, 0x53, 0x42, 0x5f, 0x50, 0x43,
+            0x49, 0x30, 0x50, 0x43, 0x4e, 0x54,
+        ]
+    );
+}
+
+#[test]
+#[should_panic = "invalid name segment"]
+fn test_invalid_name() {
+    NameString::new("\\_SB.PCI00");
+}
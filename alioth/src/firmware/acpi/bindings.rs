@@ -22,8 +22,12 @@ pub const SIG_XSDT: [u8; 4] = *b"XSDT";
 pub const SIG_FADT: [u8; 4] = *b"FACP";
 pub const SIG_MADT: [u8; 4] = *b"APIC";
 pub const SIG_MCFG: [u8; 4] = *b"MCFG";
-#[allow(dead_code)]
 pub const SIG_DSDT: [u8; 4] = *b"DSDT";
+pub const SIG_SRAT: [u8; 4] = *b"SRAT";
+pub const SIG_SLIT: [u8; 4] = *b"SLIT";
+pub const SIG_VIOT: [u8; 4] = *b"VIOT";
+
+pub const DSDT_REVISION: u8 = 2;
 
 pub const RSDP_REVISION: u8 = 2;
 
@@ -232,6 +236,99 @@ pub struct AcpiTableMcfg3 {
     pub allocations: [AcpiMcfgAllocation; 3],
 }
 
+pub const SRAT_REVISION: u8 = 3;
+
+#[repr(C, align(4))]
+#[derive(Debug, Clone, Default, FromBytes, Immutable, IntoBytes)]
+pub struct AcpiTableSrat {
+    pub header: AcpiTableHeader,
+    pub table_revision: u32,
+    pub reserved: [u32; 2],
+}
+
+pub const SRAT_MEMORY_AFFINITY: u8 = 1;
+pub const SRAT_X2APIC_CPU_AFFINITY: u8 = 2;
+
+pub const SRAT_ENABLED: u32 = 1 << 0;
+
+#[repr(C, align(4))]
+#[derive(Debug, Clone, Default, FromBytes, Immutable, IntoBytes)]
+pub struct AcpiSratMemAffinity {
+    pub header: AcpiSubtableHeader,
+    pub proximity_domain: [u16; 2],
+    pub reserved: u16,
+    pub base_address: [u32; 2],
+    pub length: [u32; 2],
+    pub reserved1: u32,
+    pub flags: u32,
+    pub reserved2: [u32; 2],
+}
+
+#[repr(C, align(4))]
+#[derive(Debug, Clone, Default, FromBytes, Immutable, IntoBytes)]
+pub struct AcpiSratX2apicCpuAffinity {
+    pub header: AcpiSubtableHeader,
+    pub reserved: u16,
+    pub proximity_domain: u32,
+    pub apic_id: u32,
+    pub flags: u32,
+    pub clock_domain: u32,
+    pub reserved2: u32,
+}
+
+pub const SLIT_REVISION: u8 = 1;
+
+#[repr(C, align(4))]
+#[derive(Debug, Clone, Default, FromBytes, Immutable, IntoBytes)]
+pub struct AcpiTableSlit {
+    pub header: AcpiTableHeader,
+    pub locality_count: [u32; 2],
+}
+
+pub const VIOT_REVISION: u8 = 0;
+
+#[repr(C, align(4))]
+#[derive(Debug, Clone, Default, FromBytes, Immutable, IntoBytes)]
+pub struct AcpiTableViot {
+    pub header: AcpiTableHeader,
+    pub node_count: u16,
+    pub node_offset: u16,
+    pub reserved: [u8; 8],
+}
+
+pub const VIOT_NODE_PCI_RANGE: u8 = 1;
+pub const VIOT_NODE_VIRTIO_IOMMU_PCI: u8 = 3;
+
+#[repr(C, align(4))]
+#[derive(Debug, Clone, Default, FromBytes, Immutable, IntoBytes)]
+pub struct AcpiViotHeader {
+    pub type_: u8,
+    pub reserved: u8,
+    pub length: u16,
+}
+
+#[repr(C, align(4))]
+#[derive(Debug, Clone, Default, FromBytes, Immutable, IntoBytes)]
+pub struct AcpiViotPciRange {
+    pub header: AcpiViotHeader,
+    pub endpoint_start: u32,
+    pub segment_start: u16,
+    pub segment_end: u16,
+    pub bdf_start: u16,
+    pub bdf_end: u16,
+    pub output_node: u16,
+    pub reserved: [u8; 6],
+}
+
+#[repr(C, align(4))]
+#[derive(Debug, Clone, Default, FromBytes, Immutable, IntoBytes)]
+pub struct AcpiViotVirtioIommuPci {
+    pub header: AcpiViotHeader,
+    pub segment: u16,
+    pub bdf: u16,
+    pub reserved: [u8; 8],
+}
+
 bitfield! {
     /// Sleep Control Register
     ///
@@ -15,8 +15,10 @@
 use std::mem::size_of;
 
 use super::{
-    AcpiGenericAddress, AcpiMadtIoApic, AcpiMadtLocalX2apic, AcpiMcfgAllocation, AcpiTableFadt,
-    AcpiTableHeader, AcpiTableMadt, AcpiTableMcfg1, AcpiTableRsdp, AcpiTableXsdt,
+    AcpiGenericAddress, AcpiMadtIoApic, AcpiMadtLocalX2apic, AcpiMcfgAllocation,
+    AcpiSratMemAffinity, AcpiSratX2apicCpuAffinity, AcpiTableFadt, AcpiTableHeader, AcpiTableMadt,
+    AcpiTableMcfg1, AcpiTableRsdp, AcpiTableSlit, AcpiTableSrat, AcpiTableViot, AcpiTableXsdt,
+    AcpiViotHeader, AcpiViotPciRange, AcpiViotVirtioIommuPci,
 };
 
 #[test]
@@ -32,4 +34,12 @@ fn test_size() {
     assert_eq!(size_of::<AcpiTableMcfg1>(), 60);
     assert_eq!(size_of::<AcpiTableXsdt<0>>(), 36);
     assert_eq!(size_of::<AcpiTableXsdt<4>>(), 36 + 4 * 8);
+    assert_eq!(size_of::<AcpiTableSrat>(), 48);
+    assert_eq!(size_of::<AcpiSratMemAffinity>(), 40);
+    assert_eq!(size_of::<AcpiSratX2apicCpuAffinity>(), 24);
+    assert_eq!(size_of::<AcpiTableSlit>(), 44);
+    assert_eq!(size_of::<AcpiTableViot>(), 48);
+    assert_eq!(size_of::<AcpiViotHeader>(), 4);
+    assert_eq!(size_of::<AcpiViotPciRange>(), 24);
+    assert_eq!(size_of::<AcpiViotVirtioIommuPci>(), 16);
 }
@@ -13,6 +13,7 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use std::sync::atomic::{AtomicU32, Ordering};
 use std::time::Instant;
 
 use crate::firmware::acpi::bindings::FadtSleepControlReg;
@@ -64,6 +65,44 @@ impl Mmio for FadtSleepControl {
     }
 }
 
+/// The power button is pressed.
+pub const GED_EVENT_POWER_BUTTON: u32 = 1 << 0;
+
+/// Event register of the Generic Event Device (GED) in the DSDT.
+///
+/// The `_EVT` method of the GED reads the pending events and writes them
+/// back to acknowledge.
+#[derive(Debug, Default)]
+pub struct AcpiGed {
+    events: AtomicU32,
+}
+
+impl AcpiGed {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Marks `events` pending. The caller then raises the GED interrupt.
+    pub fn notify(&self, events: u32) {
+        self.events.fetch_or(events, Ordering::AcqRel);
+    }
+}
+
+impl Mmio for AcpiGed {
+    fn size(&self) -> u64 {
+        4
+    }
+
+    fn read(&self, _offset: u64, _size: u8) -> Result<u64> {
+        Ok(self.events.load(Ordering::Acquire) as u64)
+    }
+
+    fn write(&self, _offset: u64, _size: u8, val: u64) -> Result<Action> {
+        self.events.fetch_and(!(val as u32), Ordering::AcqRel);
+        Ok(Action::None)
+    }
+}
+
 // The following AcpiPmTimer implementation is derived from Cloud Hypervisor.
 // Copyright © 2019 Intel Corporation
 
@@ -12,8 +12,10 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use crate::firmware::acpi::reg::AcpiPmTimer;
-use crate::mem::emulated::Mmio;
+use assert_matches::assert_matches;
+
+use crate::firmware::acpi::reg::{AcpiGed, AcpiPmTimer, GED_EVENT_POWER_BUTTON};
+use crate::mem::emulated::{Action, Mmio};
 
 #[test]
 fn test_pm_timer() {
@@ -22,3 +24,17 @@ fn test_pm_timer() {
     let v2 = timer.read(0, 4).unwrap();
     assert!(v2 >= v1);
 }
+
+#[test]
+fn test_ged() {
+    let ged = AcpiGed::new();
+    assert_matches!(ged.read(0, 4), Ok(0));
+    ged.notify(GED_EVENT_POWER_BUTTON);
+    ged.notify(1 << 3);
+    assert_matches!(ged.read(0, 4), Ok(0b1001));
+    assert_matches!(
+        ged.write(0, 4, GED_EVENT_POWER_BUTTON as u64),
+        Ok(Action::None)
+    );
+    assert_matches!(ged.read(0, 4), Ok(0b1000));
+}
@@ -0,0 +1,769 @@
+// Copyright 2026 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! A stub of the GDB Remote Serial Protocol.
+//!
+//! The stub serves one debugger at a time over TCP or a Unix domain socket.
+//! While a debugger is attached, the board stays paused unless the debugger
+//! continues or steps it. Each VCPU is a thread of the debugger, with ID
+//! `index + 1`. Software breakpoints are planted in guest memory and
+//! reported by the hypervisor as debug exits.
+
+#[cfg(target_arch = "aarch64")]
+#[path = "gdb_aarch64.rs"]
+mod aarch64;
+#[cfg(target_arch = "x86_64")]
+#[path = "gdb_x86_64.rs"]
+mod x86_64;
+
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::fmt::Write as _;
+use std::fs;
+use std::io::{self, BufReader, ErrorKind, Read, Write};
+use std::net::{Ipv4Addr, SocketAddr, TcpListener, TcpStream};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+use snafu::{ResultExt, Snafu};
+
+use crate::arch::layout::PAGE_SIZE;
+use crate::board::{Board, BoardState};
+use crate::errors::{DebugTrace, trace_error};
+use crate::hv::{GuestDebug, Vm};
+use crate::mem::mapped::RamBus;
+
+#[cfg(target_arch = "aarch64")]
+use self::aarch64::{BREAKPOINT, Mmu, PC, TARGET_XML, read_regs, write_regs};
+#[cfg(target_arch = "x86_64")]
+use self::x86_64::{BREAKPOINT, Mmu, PC, TARGET_XML, read_regs, write_regs};
+
+#[trace_error]
+#[derive(Snafu, DebugTrace)]
+#[snafu(module, context(suffix(false)))]
+pub enum Error {
+    #[snafu(display("Failed to access the debugger connection"))]
+    Socket { error: std::io::Error },
+    #[snafu(display("Failed to control the board"), context(false))]
+    Board { source: Box<crate::board::Error> },
+    #[snafu(display("Hypervisor internal error"), context(false))]
+    HvError { source: Box<crate::hv::Error> },
+    #[snafu(display("The debugger disconnected"))]
+    Disconnected,
+    #[snafu(display("The board has shut down"))]
+    Stopped,
+}
+
+type Result<T, E = Error> = std::result::Result<T, E>;
+
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Byte sent by the debugger to interrupt a running target.
+const INTERRUPT: u8 = 0x03;
+
+/// Where the stub waits for a debugger.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum GdbAddr {
+    /// A TCP socket. A bare port number listens on `127.0.0.1`.
+    Tcp(SocketAddr),
+    /// A Unix domain socket.
+    Uds(Box<Path>),
+}
+
+impl FromStr for GdbAddr {
+    type Err = Infallible;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Ok(port) = s.parse::<u16>() {
+            Ok(GdbAddr::Tcp(SocketAddr::from((Ipv4Addr::LOCALHOST, port))))
+        } else if let Ok(addr) = s.parse::<SocketAddr>() {
+            Ok(GdbAddr::Tcp(addr))
+        } else {
+            Ok(GdbAddr::Uds(Path::new(s).into()))
+        }
+    }
+}
+
+#[derive(Debug)]
+enum GdbListener {
+    Tcp(TcpListener),
+    Uds(UnixListener),
+}
+
+impl GdbListener {
+    fn bind(addr: &GdbAddr) -> io::Result<Self> {
+        match addr {
+            GdbAddr::Tcp(addr) => TcpListener::bind(addr).map(GdbListener::Tcp),
+            GdbAddr::Uds(path) => UnixListener::bind(path).map(GdbListener::Uds),
+        }
+    }
+
+    fn accept(&self) -> io::Result<GdbStream> {
+        match self {
+            GdbListener::Tcp(l) => {
+                let (conn, _) = l.accept()?;
+                conn.set_nodelay(true)?;
+                Ok(GdbStream::Tcp(conn))
+            }
+            GdbListener::Uds(l) => {
+                let (conn, _) = l.accept()?;
+                Ok(GdbStream::Uds(conn))
+            }
+        }
+    }
+}
+
+#[derive(Debug)]
+enum GdbStream {
+    Tcp(TcpStream),
+    Uds(UnixStream),
+}
+
+impl GdbStream {
+    fn try_clone(&self) -> io::Result<Self> {
+        match self {
+            GdbStream::Tcp(s) => s.try_clone().map(GdbStream::Tcp),
+            GdbStream::Uds(s) => s.try_clone().map(GdbStream::Uds),
+        }
+    }
+
+    fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        match self {
+            GdbStream::Tcp(s) => s.set_read_timeout(timeout),
+            GdbStream::Uds(s) => s.set_read_timeout(timeout),
+        }
+    }
+}
+
+impl Read for GdbStream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            GdbStream::Tcp(s) => s.read(buf),
+            GdbStream::Uds(s) => s.read(buf),
+        }
+    }
+}
+
+impl Write for GdbStream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            GdbStream::Tcp(s) => s.write(buf),
+            GdbStream::Uds(s) => s.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            GdbStream::Tcp(s) => s.flush(),
+            GdbStream::Uds(s) => s.flush(),
+        }
+    }
+}
+
+fn checksum(data: &[u8]) -> u8 {
+    data.iter().fold(0, |sum, b| sum.wrapping_add(*b))
+}
+
+/// Escapes `#`, `$`, `}` and `*` in a packet.
+fn escape(data: &[u8]) -> Vec<u8> {
+    let mut escaped = Vec::with_capacity(data.len());
+    for &b in data {
+        if matches!(b, b'#' | b'$' | b'}' | b'*') {
+            escaped.extend([b'}', b ^ 0x20]);
+        } else {
+            escaped.push(b);
+        }
+    }
+    escaped
+}
+
+fn unescape(data: &[u8]) -> Vec<u8> {
+    let mut unescaped = Vec::with_capacity(data.len());
+    let mut iter = data.iter();
+    while let Some(&b) = iter.next() {
+        if b == b'}' {
+            if let Some(&b) = iter.next() {
+                unescaped.push(b ^ 0x20);
+            }
+        } else {
+            unescaped.push(b);
+        }
+    }
+    unescaped
+}
+
+fn encode_hex(data: &[u8]) -> String {
+    let mut s = String::with_capacity(data.len() * 2);
+    for b in data {
+        write!(s, "{b:02x}").unwrap();
+    }
+    s
+}
+
+fn decode_hex(s: &[u8]) -> Option<Vec<u8>> {
+    if !s.len().is_multiple_of(2) {
+        return None;
+    }
+    let digit = |b: u8| char::from(b).to_digit(16);
+    s.chunks_exact(2)
+        .map(|pair| Some((digit(pair[0])? << 4 | digit(pair[1])?) as u8))
+        .collect()
+}
+
+fn parse_hex(s: &[u8]) -> Option<u64> {
+    u64::from_str_radix(str::from_utf8(s).ok()?, 16).ok()
+}
+
+/// Parses `addr,len` of `m`, `M` and `X` packets.
+fn parse_addr_len(s: &[u8]) -> Option<(u64, u64)> {
+    let (addr, len) = s.split_at(s.iter().position(|b| *b == b',')?);
+    Some((parse_hex(addr)?, parse_hex(&len[1..])?))
+}
+
+/// Parses a thread ID into a VCPU index. `None` stands for `0` (any thread)
+/// and `-1` (all threads).
+fn parse_thread(s: &[u8]) -> Option<Option<u16>> {
+    match s {
+        b"0" | b"-1" => Some(None),
+        _ => {
+            let id = parse_hex(s)?;
+            Some(Some(u16::try_from(id.checked_sub(1)?).ok()?))
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Breakpoint {
+    gpa: u64,
+    orig: Vec<u8>,
+}
+
+enum Reply {
+    Packet(Vec<u8>),
+    /// The board has shut down or the debugger asked to kill it.
+    Exit(Option<Vec<u8>>),
+}
+
+impl From<&str> for Reply {
+    fn from(s: &str) -> Self {
+        Reply::Packet(s.as_bytes().to_vec())
+    }
+}
+
+impl From<String> for Reply {
+    fn from(s: String) -> Self {
+        Reply::Packet(s.into_bytes())
+    }
+}
+
+struct Session<'a, V: Vm> {
+    board: &'a Board<V>,
+    ram: Arc<RamBus>,
+    reader: BufReader<GdbStream>,
+    writer: GdbStream,
+    ack: bool,
+    swbreak: bool,
+    /// The VCPU for register and memory accesses.
+    current: u16,
+    breakpoints: HashMap<u64, Breakpoint>,
+}
+
+impl<'a, V: Vm> Session<'a, V> {
+    fn new(board: &'a Board<V>, conn: GdbStream) -> Result<Self> {
+        let writer = conn.try_clone().context(error::Socket)?;
+        Ok(Session {
+            board,
+            ram: board.memory.ram_bus(),
+            reader: BufReader::new(conn),
+            writer,
+            ack: true,
+            swbreak: false,
+            current: 0,
+            breakpoints: HashMap::new(),
+        })
+    }
+
+    fn read_byte(&mut self) -> Result<u8> {
+        let mut b = [0];
+        match self.reader.read(&mut b) {
+            Ok(0) => error::Disconnected.fail(),
+            Ok(_) => Ok(b[0]),
+            Err(e) => Err(e).context(error::Socket),
+        }
+    }
+
+    fn recv_packet(&mut self) -> Result<Vec<u8>> {
+        loop {
+            // Acknowledgments and interrupts of a halted board are ignored.
+            if self.read_byte()? != b'$' {
+                continue;
+            }
+            let mut data = vec![];
+            loop {
+                match self.read_byte()? {
+                    b'#' => break,
+                    b => data.push(b),
+                }
+            }
+            let sum = [self.read_byte()?, self.read_byte()?];
+            if !self.ack {
+                return Ok(unescape(&data));
+            }
+            if parse_hex(&sum) == Some(checksum(&data) as u64) {
+                self.writer.write_all(b"+").context(error::Socket)?;
+                return Ok(unescape(&data));
+            }
+            self.writer.write_all(b"-").context(error::Socket)?;
+        }
+    }
+
+    fn send_packet(&mut self, data: &[u8]) -> Result<()> {
+        let data = escape(data);
+        let mut packet = Vec::with_capacity(data.len() + 4);
+        packet.push(b'$');
+        packet.extend(&data);
+        write!(packet, "#{:02x}", checksum(&data)).unwrap();
+        self.writer.write_all(&packet).context(error::Socket)
+    }
+
+    fn run_on_vcpu<F, T>(&self, index: u16, func: F) -> Result<T>
+    where
+        F: FnOnce(&mut dyn crate::hv::Vcpu) -> crate::hv::Result<T> + Send + 'static,
+        T: Send + 'static,
+    {
+        Ok(self.board.run_on_vcpu(index, func)??)
+    }
+
+    fn num_vcpus(&self) -> u16 {
+        self.board.config.cpu.count
+    }
+
+    /// Pauses the board for the debugger.
+    fn halt(&self) -> Result<()> {
+        loop {
+            if self.board.is_halted() {
+                return Ok(());
+            }
+            match self.board.state() {
+                BoardState::Running => {
+                    let _ = self.board.pause();
+                }
+                BoardState::Shutdown => return error::Stopped.fail(),
+                _ => thread::sleep(POLL_INTERVAL),
+            }
+        }
+    }
+
+    /// Removes all breakpoints and lets the board run on its own.
+    fn detach(&mut self) -> Result<()> {
+        for (_, bp) in self.breakpoints.drain() {
+            if let Err(e) = self.ram.write(bp.gpa, &bp.orig) {
+                log::error!("GDB: failed to restore memory at {:#x}: {e}", bp.gpa);
+            }
+        }
+        for index in 0..self.num_vcpus() {
+            self.run_on_vcpu(index, |vcpu| vcpu.set_guest_debug(&GuestDebug::default()))?;
+        }
+        self.board.resume()?;
+        Ok(())
+    }
+
+    fn stop_reply(&self, signal: u8, index: u16, swbreak: bool) -> Vec<u8> {
+        let swbreak = if swbreak && self.swbreak {
+            "swbreak:;"
+        } else {
+            ""
+        };
+        format!("T{signal:02x}{swbreak}thread:{:x};", index + 1).into_bytes()
+    }
+
+    /// Resumes the board, with VCPU `step` executing a single instruction,
+    /// and waits for it to stop.
+    fn resume(&mut self, step: Option<u16>) -> Result<Reply> {
+        #[cfg(target_arch = "x86_64")]
+        let mut inject = None;
+        loop {
+            for index in 0..self.num_vcpus() {
+                let debug = GuestDebug {
+                    single_step: step == Some(index),
+                    sw_breakpoint: true,
+                    #[cfg(target_arch = "x86_64")]
+                    inject_breakpoint: inject == Some(index),
+                };
+                self.run_on_vcpu(index, move |vcpu| vcpu.set_guest_debug(&debug))?;
+            }
+            self.board.resume()?;
+            if !self.wait()? {
+                return Ok(Reply::Exit(Some(b"W00".to_vec())));
+            }
+            let Some(index) = self.board.take_debug_stop() else {
+                return Ok(Reply::Packet(self.stop_reply(2, self.current, false)));
+            };
+            self.current = index;
+            let pc = self.run_on_vcpu(index, |vcpu| vcpu.get_reg(PC))?;
+            if self.breakpoints.contains_key(&pc) {
+                return Ok(Reply::Packet(self.stop_reply(5, index, true)));
+            }
+            if step == Some(index) {
+                return Ok(Reply::Packet(self.stop_reply(5, index, false)));
+            }
+            // The guest executed a breakpoint instruction of its own.
+            #[cfg(target_arch = "x86_64")]
+            {
+                inject = Some(index);
+            }
+            #[cfg(not(target_arch = "x86_64"))]
+            return Ok(Reply::Packet(self.stop_reply(5, index, false)));
+        }
+    }
+
+    /// Waits until the board is paused, or returns false if it has shut
+    /// down.
+    fn wait(&mut self) -> Result<bool> {
+        let conn = self.reader.get_ref();
+        conn.set_read_timeout(Some(POLL_INTERVAL))
+            .context(error::Socket)?;
+        let ret = loop {
+            match self.board.state() {
+                BoardState::Paused => break Ok(true),
+                BoardState::Shutdown => break Ok(false),
+                BoardState::Running | BoardState::RebootPending => {}
+            }
+            match self.read_byte() {
+                Ok(INTERRUPT) => {
+                    let _ = self.board.pause();
+                }
+                Ok(_) => {}
+                Err(Error::Socket { error, .. })
+                    if matches!(error.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => {}
+                Err(e) => break Err(e),
+            }
+        };
+        let conn = self.reader.get_ref();
+        conn.set_read_timeout(None).context(error::Socket)?;
+        ret
+    }
+
+    fn mmu(&self) -> Result<Mmu> {
+        self.run_on_vcpu(self.current, |vcpu| Mmu::new(vcpu))
+    }
+
+    /// Reads up to `len` bytes at `gva`, stopping at the first unmapped
+    /// page.
+    fn read_mem(&self, gva: u64, len: u64) -> Result<Vec<u8>> {
+        let mmu = self.mmu()?;
+        let mut data = vec![];
+        let mut addr = gva;
+        while (data.len() as u64) < len {
+            let page_left = PAGE_SIZE - (addr & (PAGE_SIZE - 1));
+            let size = page_left.min(len - data.len() as u64);
+            let Ok(Some(gpa)) = mmu.translate(&self.ram, addr) else {
+                break;
+            };
+            let mut buf = vec![0; size as usize];
+            if self.ram.read(gpa, &mut buf).is_err() {
+                break;
+            }
+            data.extend(buf);
+            addr = addr.wrapping_add(size);
+        }
+        Ok(data)
+    }
+
+    /// Writes `data` at `gva`, or returns false if any page is unmapped.
+    fn write_mem(&self, gva: u64, data: &[u8]) -> Result<bool> {
+        let mmu = self.mmu()?;
+        let mut addr = gva;
+        let mut data = data;
+        while !data.is_empty() {
+            let page_left = PAGE_SIZE - (addr & (PAGE_SIZE - 1));
+            let (chunk, rest) = data.split_at((page_left as usize).min(data.len()));
+            let Ok(Some(gpa)) = mmu.translate(&self.ram, addr) else {
+                return Ok(false);
+            };
+            if self.ram.write(gpa, chunk).is_err() {
+                return Ok(false);
+            }
+            addr = addr.wrapping_add(chunk.len() as u64);
+            data = rest;
+        }
+        Ok(true)
+    }
+
+    fn insert_breakpoint(&mut self, gva: u64) -> Result<bool> {
+        if self.breakpoints.contains_key(&gva) {
+            return Ok(true);
+        }
+        let Ok(Some(gpa)) = self.mmu()?.translate(&self.ram, gva) else {
+            return Ok(false);
+        };
+        let mut orig = vec![0; BREAKPOINT.len()];
+        if self.ram.read(gpa, &mut orig).is_err() || self.ram.write(gpa, BREAKPOINT).is_err() {
+            return Ok(false);
+        }
+        self.breakpoints.insert(gva, Breakpoint { gpa, orig });
+        Ok(true)
+    }
+
+    fn remove_breakpoint(&mut self, gva: u64) -> bool {
+        let Some(bp) = self.breakpoints.remove(&gva) else {
+            return true;
+        };
+        self.ram.write(bp.gpa, &bp.orig).is_ok()
+    }
+
+    fn handle_query(&mut self, query: &[u8]) -> Result<Reply> {
+        let reply = if let Some(features) = query.strip_prefix(b"qSupported") {
+            self.swbreak = features
+                .split(|b| matches!(b, b':' | b';'))
+                .any(|f| f == b"swbreak+");
+            "PacketSize=1000;qXfer:features:read+;swbreak+;QStartNoAckMode+;vContSupported+".into()
+        } else if let Some(args) = query.strip_prefix(b"qXfer:features:read:target.xml:") {
+            let Some((offset, len)) = parse_addr_len(args) else {
+                return Ok("E01".into());
+            };
+            let xml = TARGET_XML.as_bytes();
+            let start = (offset as usize).min(xml.len());
+            let end = start.saturating_add(len as usize).min(xml.len());
+            let mut data = vec![if end == xml.len() { b'l' } else { b'm' }];
+            data.extend(&xml[start..end]);
+            Reply::Packet(data)
+        } else if query == b"QStartNoAckMode" {
+            "OK".into()
+        } else if query.starts_with(b"qAttached") {
+            "1".into()
+        } else if query == b"qC" {
+            format!("QC{:x}", self.current + 1).into()
+        } else if query == b"qfThreadInfo" {
+            let ids: Vec<_> = (1..=self.num_vcpus()).map(|id| format!("{id:x}")).collect();
+            format!("m{}", ids.join(",")).into()
+        } else if query == b"qsThreadInfo" {
+            "l".into()
+        } else if let Some(id) = query.strip_prefix(b"qThreadExtraInfo,") {
+            match parse_thread(id) {
+                Some(Some(index)) => encode_hex(format!("VCPU-{index}").as_bytes()).into(),
+                _ => "E01".into(),
+            }
+        } else {
+            "".into()
+        };
+        Ok(reply)
+    }
+
+    fn handle_vcont(&mut self, actions: &[u8]) -> Result<Reply> {
+        let mut step = None;
+        for action in actions.split(|b| *b == b';').skip(1) {
+            let (cmd, thread) = match action.iter().position(|b| *b == b':') {
+                Some(pos) => (&action[..pos], parse_thread(&action[pos + 1..])),
+                None => (action, Some(None)),
+            };
+            let Some(thread) = thread else {
+                return Ok("E01".into());
+            };
+            if matches!(cmd.first(), Some(b's' | b'S')) && step.is_none() {
+                step = Some(thread.unwrap_or(self.current));
+            }
+        }
+        self.resume(step)
+    }
+
+    fn handle_packet(&mut self, packet: &[u8]) -> Result<Reply> {
+        let Some((&cmd, args)) = packet.split_first() else {
+            return Ok("".into());
+        };
+        let reply = match cmd {
+            b'?' => Reply::Packet(self.stop_reply(5, self.current, false)),
+            b'q' | b'Q' => return self.handle_query(packet),
+            b'H' => match args.split_first().map(|(op, id)| (op, parse_thread(id))) {
+                Some((b'g', Some(Some(index)))) if index < self.num_vcpus() => {
+                    self.current = index;
+                    "OK".into()
+                }
+                Some((_, Some(_))) => "OK".into(),
+                _ => "E01".into(),
+            },
+            b'T' => match parse_thread(args) {
+                Some(Some(index)) if index < self.num_vcpus() => "OK".into(),
+                _ => "E01".into(),
+            },
+            b'g' => {
+                let regs = self.run_on_vcpu(self.current, |vcpu| read_regs(vcpu))?;
+                encode_hex(&regs).into()
+            }
+            b'G' => {
+                let Some(regs) = decode_hex(args) else {
+                    return Ok("E01".into());
+                };
+                self.run_on_vcpu(self.current, move |vcpu| write_regs(vcpu, &regs))?;
+                "OK".into()
+            }
+            b'm' => {
+                let Some((addr, len)) = parse_addr_len(args) else {
+                    return Ok("E01".into());
+                };
+                match self.read_mem(addr, len)? {
+                    data if data.is_empty() && len > 0 => "E14".into(),
+                    data => encode_hex(&data).into(),
+                }
+            }
+            b'M' | b'X' => {
+                let Some(pos) = args.iter().position(|b| *b == b':') else {
+                    return Ok("E01".into());
+                };
+                let (header, data) = (&args[..pos], &args[pos + 1..]);
+                let data = if cmd == b'M' {
+                    decode_hex(data)
+                } else {
+                    Some(data.to_vec())
+                };
+                let (Some((addr, _)), Some(data)) = (parse_addr_len(header), data) else {
+                    return Ok("E01".into());
+                };
+                if self.write_mem(addr, &data)? {
+                    "OK".into()
+                } else {
+                    "E14".into()
+                }
+            }
+            b'Z' | b'z' => {
+                let Some(args) = args.strip_prefix(b"0,") else {
+                    return Ok("".into());
+                };
+                let Some((addr, _)) = parse_addr_len(args) else {
+                    return Ok("E01".into());
+                };
+                let done = if cmd == b'Z' {
+                    self.insert_breakpoint(addr)?
+                } else {
+                    self.remove_breakpoint(addr)
+                };
+                if done { "OK".into() } else { "E14".into() }
+            }
+            b'c' => self.resume(None)?,
+            b's' => self.resume(Some(self.current))?,
+            b'v' => {
+                if args == b"Cont?" {
+                    "vCont;c;C;s;S".into()
+                } else if let Some(actions) = args.strip_prefix(b"Cont") {
+                    self.handle_vcont(actions)?
+                } else {
+                    "".into()
+                }
+            }
+            b'D' => {
+                self.detach()?;
+                Reply::Exit(Some(b"OK".to_vec()))
+            }
+            b'k' => {
+                self.breakpoints.clear();
+                self.board.shutdown()?;
+                Reply::Exit(None)
+            }
+            _ => "".into(),
+        };
+        Ok(reply)
+    }
+
+    fn serve(&mut self) -> Result<()> {
+        self.halt()?;
+        self.board.take_debug_stop();
+        loop {
+            let packet = self.recv_packet()?;
+            match self.handle_packet(&packet)? {
+                Reply::Packet(reply) => {
+                    self.send_packet(&reply)?;
+                    if packet == b"QStartNoAckMode" {
+                        self.ack = false;
+                    }
+                }
+                Reply::Exit(reply) => {
+                    if let Some(reply) = reply {
+                        self.send_packet(&reply)?;
+                    }
+                    return Ok(());
+                }
+            }
+        }
+    }
+}
+
+fn serve_client<V: Vm>(board: &Board<V>, conn: GdbStream) -> Result<()> {
+    let mut session = Session::new(board, conn)?;
+    let ret = session.serve();
+    if matches!(ret, Err(Error::Disconnected { .. } | Error::Socket { .. })) {
+        // Leave the board running without breakpoints.
+        session.halt()?;
+        session.detach()?;
+    }
+    ret
+}
+
+/// Serves a debugger until the process exits.
+#[derive(Debug)]
+pub struct GdbServer {
+    addr: GdbAddr,
+}
+
+impl GdbServer {
+    pub fn new<V>(addr: GdbAddr, board: Arc<Board<V>>) -> io::Result<Self>
+    where
+        V: Vm + Send + Sync + 'static,
+    {
+        let listener = GdbListener::bind(&addr)?;
+        thread::Builder::new()
+            .name("gdb".to_owned())
+            .spawn(move || {
+                loop {
+                    let conn = match listener.accept() {
+                        Ok(conn) => conn,
+                        Err(e) => {
+                            log::error!("GDB: failed to accept a debugger: {e}");
+                            continue;
+                        }
+                    };
+                    log::info!("GDB: debugger attached");
+                    match serve_client(&board, conn) {
+                        Ok(()) | Err(Error::Disconnected { .. }) => {
+                            log::info!("GDB: debugger detached")
+                        }
+                        Err(Error::Stopped { .. }) => break,
+                        Err(e) => log::error!("GDB: {e:?}"),
+                    }
+                    if board.state() == BoardState::Shutdown {
+                        break;
+                    }
+                }
+            })?;
+        Ok(GdbServer { addr })
+    }
+}
+
+impl Drop for GdbServer {
+    fn drop(&mut self) {
+        let GdbAddr::Uds(path) = &self.addr else {
+            return;
+        };
+        if let Err(e) = fs::remove_file(path) {
+            log::error!("GDB: failed to remove {}: {e}", path.display());
+        }
+    }
+}
+
+#[cfg(test)]
+#[path = "gdb_test.rs"]
+mod tests;
@@ -0,0 +1,197 @@
+// Copyright 2026 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::arch::reg::{Pstate, Reg, SReg};
+use crate::hv::{Result, Vcpu};
+use crate::mem;
+use crate::mem::mapped::RamBus;
+
+/// `BRK #0`
+pub const BREAKPOINT: &[u8] = &[0x00, 0x00, 0x20, 0xd4];
+
+pub const PC: Reg = Reg::Pc;
+
+/// Without any registers, GDB uses its default register layout of the
+/// architecture.
+pub const TARGET_XML: &str = concat!(
+    r#"<?xml version="1.0"?><!DOCTYPE target SYSTEM "gdb-target.dtd">"#,
+    "<target><architecture>aarch64</architecture></target>"
+);
+
+const REGS: [Reg; 31] = [
+    Reg::X0,
+    Reg::X1,
+    Reg::X2,
+    Reg::X3,
+    Reg::X4,
+    Reg::X5,
+    Reg::X6,
+    Reg::X7,
+    Reg::X8,
+    Reg::X9,
+    Reg::X10,
+    Reg::X11,
+    Reg::X12,
+    Reg::X13,
+    Reg::X14,
+    Reg::X15,
+    Reg::X16,
+    Reg::X17,
+    Reg::X18,
+    Reg::X19,
+    Reg::X20,
+    Reg::X21,
+    Reg::X22,
+    Reg::X23,
+    Reg::X24,
+    Reg::X25,
+    Reg::X26,
+    Reg::X27,
+    Reg::X28,
+    Reg::X29,
+    Reg::X30,
+];
+
+/// Returns the stack pointer selected by PSTATE.
+fn sp_reg(pstate: u64) -> Reg {
+    if Pstate::from_bits_retain(pstate as u32).contains(Pstate::EL_H) {
+        Reg::SpEl1
+    } else {
+        Reg::Sp
+    }
+}
+
+/// Reads the registers in the layout of the `g` packet, up to CPSR. GDB
+/// marks the FP/SIMD registers unavailable.
+pub fn read_regs(vcpu: &dyn Vcpu) -> Result<Vec<u8>> {
+    let mut data = Vec::new();
+    for reg in REGS {
+        data.extend(vcpu.get_reg(reg)?.to_le_bytes());
+    }
+    let pstate = vcpu.get_reg(Reg::Pstate)?;
+    data.extend(vcpu.get_reg(sp_reg(pstate))?.to_le_bytes());
+    data.extend(vcpu.get_reg(Reg::Pc)?.to_le_bytes());
+    data.extend((pstate as u32).to_le_bytes());
+    Ok(data)
+}
+
+/// Writes the registers in `data` of the `G` packet.
+pub fn write_regs(vcpu: &mut dyn Vcpu, data: &[u8]) -> Result<()> {
+    let mut vals = Vec::new();
+    let mut chunks = data.chunks_exact(8);
+    for (reg, chunk) in REGS.into_iter().zip(&mut chunks) {
+        vals.push((reg, u64::from_le_bytes(chunk.try_into().unwrap())));
+    }
+    let pstate = match data.get(264..268) {
+        Some(chunk) => {
+            let pstate = u32::from_le_bytes(chunk.try_into().unwrap()) as u64;
+            vals.push((Reg::Pstate, pstate));
+            pstate
+        }
+        None => vcpu.get_reg(Reg::Pstate)?,
+    };
+    for (reg, chunk) in [sp_reg(pstate), Reg::Pc].into_iter().zip(&mut chunks) {
+        vals.push((reg, u64::from_le_bytes(chunk.try_into().unwrap())));
+    }
+    vcpu.set_regs(&vals)
+}
+
+/// Translation registers of a VCPU.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Mmu {
+    sctlr: u64,
+    tcr: u64,
+    ttbr0: u64,
+    ttbr1: u64,
+}
+
+const ADDR_MASK: u64 = 0x0000_ffff_ffff_f000;
+
+impl Mmu {
+    pub fn new(vcpu: &dyn Vcpu) -> Result<Self> {
+        Ok(Mmu {
+            sctlr: vcpu.get_sreg(SReg::SCTLR_EL1)?,
+            tcr: vcpu.get_sreg(SReg::TCR_EL1)?,
+            ttbr0: vcpu.get_sreg(SReg::TTBR0_EL1)?,
+            ttbr1: vcpu.get_sreg(SReg::TTBR1_EL1)?,
+        })
+    }
+
+    /// Translates a guest virtual address to a guest physical address by
+    /// walking the stage 1 translation tables of EL1&0, or returns `None`
+    /// if it is not mapped.
+    pub fn translate(&self, ram: &RamBus, gva: u64) -> mem::Result<Option<u64>> {
+        // SCTLR_EL1.M
+        if self.sctlr & 1 == 0 {
+            return Ok(Some(gva));
+        }
+        let upper = (gva >> 55) & 1 == 1;
+        // Fields of TCR_EL1 for TTBR1_EL1 or TTBR0_EL1
+        let (ttbr, tsz, granule, disabled, tbi) = if upper {
+            let granule = match (self.tcr >> 30) & 0x3 {
+                0b01 => 14,
+                0b11 => 16,
+                _ => 12,
+            };
+            let tsz = (self.tcr >> 16) & 0x3f;
+            let epd = (self.tcr >> 23) & 1 == 1;
+            (self.ttbr1, tsz, granule, epd, (self.tcr >> 38) & 1 == 1)
+        } else {
+            let granule = match (self.tcr >> 14) & 0x3 {
+                0b01 => 16,
+                0b10 => 14,
+                _ => 12,
+            };
+            let epd = (self.tcr >> 7) & 1 == 1;
+            (
+                self.ttbr0,
+                self.tcr & 0x3f,
+                granule,
+                epd,
+                (self.tcr >> 37) & 1 == 1,
+            )
+        };
+        let va_bits = 64 - tsz.clamp(16, 48);
+        let mut top = if upper { !gva } else { gva };
+        if tbi {
+            top &= 0x00ff_ffff_ffff_ffff;
+        }
+        if disabled || top >> va_bits != 0 {
+            return Ok(None);
+        }
+        let stride = granule - 3;
+        let levels = (va_bits - granule).div_ceil(stride);
+        let mut table = ttbr & 0x0000_ffff_ffff_fffe;
+        for level in 0..levels {
+            let shift = granule + stride * (levels - 1 - level);
+            let index = (gva >> shift) & ((1 << (va_bits - shift).min(stride)) - 1);
+            let desc: u64 = ram.read_t(table + index * 8)?;
+            if desc & 1 == 0 {
+                return Ok(None);
+            }
+            let last = level == levels - 1;
+            if last && desc & 0b10 == 0 {
+                return Ok(None);
+            }
+            if last || desc & 0b10 == 0 {
+                let offset_mask = (1 << shift) - 1;
+                return Ok(Some(
+                    (desc & ADDR_MASK & !offset_mask) | (gva & offset_mask),
+                ));
+            }
+            table = desc & ADDR_MASK & !((1 << granule) - 1);
+        }
+        unreachable!()
+    }
+}
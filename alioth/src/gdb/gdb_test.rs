@@ -0,0 +1,73 @@
+// Copyright 2026 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::net::SocketAddr;
+use std::path::Path;
+
+use rstest::rstest;
+
+use crate::gdb::{
+    GdbAddr, checksum, decode_hex, encode_hex, escape, parse_addr_len, parse_thread, unescape,
+};
+
+#[rstest]
+#[case("1234", GdbAddr::Tcp(SocketAddr::from(([127, 0, 0, 1], 1234))))]
+#[case("0.0.0.0:1234", GdbAddr::Tcp(SocketAddr::from(([0, 0, 0, 0], 1234))))]
+#[case("/tmp/gdb.sock", GdbAddr::Uds(Path::new("/tmp/gdb.sock").into()))]
+fn test_gdb_addr(#[case] arg: &str, #[case] addr: GdbAddr) {
+    assert_eq!(arg.parse::<GdbAddr>().unwrap(), addr);
+}
+
+#[test]
+fn test_checksum() {
+    assert_eq!(checksum(b""), 0);
+    assert_eq!(checksum(b"OK"), 0x9a);
+    assert_eq!(checksum(b"qSupported"), 0x37);
+}
+
+#[rstest]
+#[case(b"OK", b"OK")]
+#[case(b"a#b$c}d*", b"a}\x03b}\x04c}]d}\x0a")]
+fn test_escape(#[case] data: &[u8], #[case] escaped: &[u8]) {
+    assert_eq!(escape(data), escaped);
+    assert_eq!(unescape(escaped), data);
+}
+
+#[test]
+fn test_hex() {
+    assert_eq!(encode_hex(&[0x00, 0xab, 0x7f]), "00ab7f");
+    assert_eq!(decode_hex(b"00ab7f"), Some(vec![0x00, 0xab, 0x7f]));
+    assert_eq!(decode_hex(b"0ab"), None);
+    assert_eq!(decode_hex(b"zz"), None);
+    assert_eq!(decode_hex(b"+a"), None);
+    assert_eq!(decode_hex("é".as_bytes()), None);
+}
+
+#[rstest]
+#[case(b"ffff800000001000,4", Some((0xffff_8000_0000_1000, 4)))]
+#[case(b"1000", None)]
+#[case(b"1000,", None)]
+fn test_parse_addr_len(#[case] s: &[u8], #[case] ret: Option<(u64, u64)>) {
+    assert_eq!(parse_addr_len(s), ret);
+}
+
+#[rstest]
+#[case(b"0", Some(None))]
+#[case(b"-1", Some(None))]
+#[case(b"1", Some(Some(0)))]
+#[case(b"a", Some(Some(9)))]
+#[case(b"x", None)]
+fn test_parse_thread(#[case] s: &[u8], #[case] thread: Option<Option<u16>>) {
+    assert_eq!(parse_thread(s), thread);
+}
@@ -0,0 +1,178 @@
+// Copyright 2026 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::arch::msr::Efer;
+use crate::arch::paging::Entry;
+use crate::arch::reg::{Cr0, Cr4, Reg, SReg, SegReg};
+use crate::hv::{Result, Vcpu};
+use crate::mem;
+use crate::mem::mapped::RamBus;
+
+/// `INT3`
+pub const BREAKPOINT: &[u8] = &[0xcc];
+
+pub const PC: Reg = Reg::Rip;
+
+/// Without any registers, GDB uses its default register layout of the
+/// architecture.
+pub const TARGET_XML: &str = concat!(
+    r#"<?xml version="1.0"?><!DOCTYPE target SYSTEM "gdb-target.dtd">"#,
+    "<target><architecture>i386:x86-64</architecture></target>"
+);
+
+/// General purpose registers in the order of GDB.
+const REGS: [Reg; 16] = [
+    Reg::Rax,
+    Reg::Rbx,
+    Reg::Rcx,
+    Reg::Rdx,
+    Reg::Rsi,
+    Reg::Rdi,
+    Reg::Rbp,
+    Reg::Rsp,
+    Reg::R8,
+    Reg::R9,
+    Reg::R10,
+    Reg::R11,
+    Reg::R12,
+    Reg::R13,
+    Reg::R14,
+    Reg::R15,
+];
+
+const SEG_REGS: [SegReg; 6] = [
+    SegReg::Cs,
+    SegReg::Ss,
+    SegReg::Ds,
+    SegReg::Es,
+    SegReg::Fs,
+    SegReg::Gs,
+];
+
+/// Reads the registers in the layout of the `g` packet, up to the segment
+/// selectors. GDB marks the x87 and SSE registers unavailable.
+pub fn read_regs(vcpu: &dyn Vcpu) -> Result<Vec<u8>> {
+    let mut data = Vec::new();
+    for reg in REGS {
+        data.extend(vcpu.get_reg(reg)?.to_le_bytes());
+    }
+    data.extend(vcpu.get_reg(Reg::Rip)?.to_le_bytes());
+    data.extend((vcpu.get_reg(Reg::Rflags)? as u32).to_le_bytes());
+    for reg in SEG_REGS {
+        let selector = vcpu.get_seg_reg(reg)?.selector;
+        data.extend((selector as u32).to_le_bytes());
+    }
+    Ok(data)
+}
+
+/// Writes the general purpose registers, RIP and RFLAGS in `data` of the
+/// `G` packet. Segment selectors are read-only.
+pub fn write_regs(vcpu: &mut dyn Vcpu, data: &[u8]) -> Result<()> {
+    let mut vals = Vec::new();
+    let mut chunks = data.chunks_exact(8);
+    for (reg, chunk) in REGS.into_iter().chain([Reg::Rip]).zip(&mut chunks) {
+        vals.push((reg, u64::from_le_bytes(chunk.try_into().unwrap())));
+    }
+    if let Some(chunk) = data.get(136..140) {
+        vals.push((
+            Reg::Rflags,
+            u32::from_le_bytes(chunk.try_into().unwrap()) as u64,
+        ));
+    }
+    vcpu.set_regs(&vals)
+}
+
+/// Paging registers of a VCPU.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Mmu {
+    cr0: u64,
+    cr3: u64,
+    cr4: u64,
+    efer: u64,
+}
+
+const ADDR_MASK: u64 = 0x000f_ffff_ffff_f000;
+
+impl Mmu {
+    pub fn new(vcpu: &dyn Vcpu) -> Result<Self> {
+        Ok(Mmu {
+            cr0: vcpu.get_sreg(SReg::Cr0)?,
+            cr3: vcpu.get_sreg(SReg::Cr3)?,
+            cr4: vcpu.get_sreg(SReg::Cr4)?,
+            efer: vcpu.get_sreg(SReg::Efer)?,
+        })
+    }
+
+    /// Translates a guest virtual address to a guest physical address by
+    /// walking the page tables, or returns `None` if it is not mapped.
+    pub fn translate(&self, ram: &RamBus, gva: u64) -> mem::Result<Option<u64>> {
+        let present = Entry::P.bits() as u64;
+        let page_size = Entry::PS.bits() as u64;
+        if !Cr0::from_bits_retain(self.cr0 as u32).contains(Cr0::PG) {
+            return Ok(Some(gva));
+        }
+        let cr4 = Cr4::from_bits_retain(self.cr4 as u32);
+        if !cr4.contains(Cr4::PAE) {
+            return self.translate_32(ram, gva);
+        }
+        let (mut table, levels) = if Efer::from_bits_retain(self.efer as u32).contains(Efer::LMA) {
+            let levels = if cr4.contains(Cr4::LA57) { 5 } else { 4 };
+            (self.cr3 & ADDR_MASK, levels)
+        } else {
+            // PAE paging: bits 31:30 select one of the 4 PDPTEs at CR3.
+            let pdpte: u64 = ram.read_t((self.cr3 & 0xffff_ffe0) + ((gva >> 30) & 0x3) * 8)?;
+            if pdpte & present == 0 {
+                return Ok(None);
+            }
+            (pdpte & ADDR_MASK, 2)
+        };
+        for level in (0..levels).rev() {
+            let shift = 12 + 9 * level;
+            let entry: u64 = ram.read_t(table + ((gva >> shift) & 0x1ff) * 8)?;
+            if entry & present == 0 {
+                return Ok(None);
+            }
+            if level == 0 || (level < 3 && entry & page_size != 0) {
+                let offset_mask = (1 << shift) - 1;
+                return Ok(Some(
+                    (entry & ADDR_MASK & !offset_mask) | (gva & offset_mask),
+                ));
+            }
+            table = entry & ADDR_MASK;
+        }
+        unreachable!()
+    }
+
+    fn translate_32(&self, ram: &RamBus, gva: u64) -> mem::Result<Option<u64>> {
+        let gva = gva as u32;
+        let pde: u32 = ram.read_t((self.cr3 & 0xffff_f000) + ((gva >> 22) as u64) * 4)?;
+        if !Entry::from_bits_retain(pde).contains(Entry::P) {
+            return Ok(None);
+        }
+        let cr4 = Cr4::from_bits_retain(self.cr4 as u32);
+        if cr4.contains(Cr4::PSE) && Entry::from_bits_retain(pde).contains(Entry::PS) {
+            return Ok(Some(((pde & 0xffc0_0000) | (gva & 0x3f_ffff)) as u64));
+        }
+        let table = (pde & 0xffff_f000) as u64;
+        let pte: u32 = ram.read_t(table + ((gva >> 12) & 0x3ff) as u64 * 4)?;
+        if !Entry::from_bits_retain(pte).contains(Entry::P) {
+            return Ok(None);
+        }
+        Ok(Some(((pte & 0xffff_f000) | (gva & 0xfff)) as u64))
+    }
+}
+
+#[cfg(test)]
+#[path = "gdb_x86_64_test.rs"]
+mod tests;
@@ -0,0 +1,104 @@
+// Copyright 2026 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use libc::{PROT_READ, PROT_WRITE};
+use rstest::rstest;
+
+use crate::arch::msr::Efer;
+use crate::arch::reg::{Cr0, Cr4};
+use crate::gdb::x86_64::Mmu;
+use crate::mem::mapped::{ArcMemPages, RamBus};
+
+fn ram_bus(entries: &[(u64, u64)], entry_size: usize) -> RamBus {
+    let bus = RamBus::new();
+    let prot = PROT_READ | PROT_WRITE;
+    let mem = ArcMemPages::from_anonymous(0x10000, Some(prot), None).unwrap();
+    bus.add(0, mem).unwrap();
+    for (gpa, entry) in entries {
+        bus.write(*gpa, &entry.to_le_bytes()[..entry_size]).unwrap();
+    }
+    bus
+}
+
+#[rstest]
+#[case(0x5123, Some(0x8123))]
+#[case(0x20_1234, Some(0x40_1234))]
+#[case(0x6000, None)]
+#[case(0x40_0000_0000, None)]
+#[case(0xffff_8000_0000_5000, Some(0x8000))]
+fn test_translate_4_level(#[case] gva: u64, #[case] gpa: Option<u64>) {
+    let ram = ram_bus(
+        &[
+            (0x1000, 0x2003),
+            (0x1000 + 256 * 8, 0x2003),
+            (0x2000, 0x3003),
+            (0x3000, 0x4003),
+            (0x3008, 0x40_0083),
+            (0x4000 + 5 * 8, 0x8003),
+        ],
+        8,
+    );
+    let mmu = Mmu {
+        cr0: (Cr0::PE | Cr0::PG).bits() as u64,
+        cr3: 0x1000,
+        cr4: Cr4::PAE.bits() as u64,
+        efer: (Efer::LME | Efer::LMA).bits() as u64,
+    };
+    assert_eq!(mmu.translate(&ram, gva).unwrap(), gpa);
+}
+
+#[rstest]
+#[case(0x3456, Some(0x7456))]
+#[case(0x40_1234, Some(0x80_1234))]
+#[case(0x4000, None)]
+#[case(0x80_0000, None)]
+fn test_translate_32_bit(#[case] gva: u64, #[case] gpa: Option<u64>) {
+    let ram = ram_bus(
+        &[(0x1000, 0x2003), (0x1004, 0x80_0083), (0x200c, 0x7003)],
+        4,
+    );
+    let mmu = Mmu {
+        cr0: (Cr0::PE | Cr0::PG).bits() as u64,
+        cr3: 0x1000,
+        cr4: Cr4::PSE.bits() as u64,
+        efer: 0,
+    };
+    assert_eq!(mmu.translate(&ram, gva).unwrap(), gpa);
+}
+
+#[rstest]
+#[case(0x1abc, Some(0x9abc))]
+#[case(0x4000_1abc, None)]
+fn test_translate_pae(#[case] gva: u64, #[case] gpa: Option<u64>) {
+    let ram = ram_bus(&[(0x1000, 0x2001), (0x2000, 0x3003), (0x3008, 0x9003)], 8);
+    let mmu = Mmu {
+        cr0: (Cr0::PE | Cr0::PG).bits() as u64,
+        cr3: 0x1000,
+        cr4: Cr4::PAE.bits() as u64,
+        efer: 0,
+    };
+    assert_eq!(mmu.translate(&ram, gva).unwrap(), gpa);
+}
+
+#[test]
+fn test_translate_no_paging() {
+    let ram = ram_bus(&[], 8);
+    let mmu = Mmu {
+        cr0: Cr0::PE.bits() as u64,
+        cr3: 0,
+        cr4: 0,
+        efer: 0,
+    };
+    assert_eq!(mmu.translate(&ram, 0xffff_fff0).unwrap(), Some(0xffff_fff0));
+}
@@ -41,6 +41,8 @@ use crate::arch::reg::{Reg, SReg};
 use crate::arch::sev::{SevPolicy, SevStatus, SnpPageType, SnpPolicy};
 #[cfg(target_arch = "x86_64")]
 use crate::arch::tdx::TdAttr;
+#[cfg(target_arch = "x86_64")]
+use crate::consts;
 use crate::errors::{DebugTrace, trace_error};
 
 #[cfg(target_os = "macos")]
@@ -65,6 +67,12 @@ pub enum Error {
         size: u64,
         error: std::io::Error,
     },
+    #[snafu(display("Failed to access the dirty log of gpa {gpa:#x}, size {size:#x}"))]
+    DirtyLog {
+        gpa: u64,
+        size: u64,
+        error: std::io::Error,
+    },
     #[snafu(display("Hypervisor is missing capability: {cap}"))]
     Capability { cap: &'static str },
     #[snafu(display("Failed to setup signal handlers"))]
@@ -77,8 +85,13 @@ pub enum Error {
     CreateDevice { error: std::io::Error },
     #[snafu(display("Failed to configure VM parameters"))]
     SetVmParam { error: std::io::Error },
+    #[cfg(target_arch = "x86_64")]
+    #[snafu(display("Failed to access the VM clock"))]
+    VmClock { error: std::io::Error },
     #[snafu(display("Failed to configure VCPU registers"))]
     VcpuReg { error: std::io::Error },
+    #[snafu(display("Failed to configure guest debugging"))]
+    GuestDebug { error: std::io::Error },
     #[snafu(display("Failed to configure the guest CPUID"))]
     GuestCpuid { error: std::io::Error },
     #[cfg(target_arch = "x86_64")]
@@ -175,6 +188,48 @@ impl Default for MemMapOption {
     }
 }
 
+/// Debugging features of a vCPU provided by the hypervisor.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct GuestDebug {
+    /// Exits with [`VmExit::Debug`] after executing one instruction.
+    pub single_step: bool,
+    /// Exits with [`VmExit::Debug`] at software breakpoint instructions,
+    /// instead of delivering the exceptions to the guest.
+    pub sw_breakpoint: bool,
+    /// Delivers the breakpoint exception of the last [`VmExit::Debug`] to
+    /// the guest at the next entry.
+    #[cfg(target_arch = "x86_64")]
+    pub inject_breakpoint: bool,
+}
+
+/// Registers of the local APIC of a vCPU, laid out as in the APIC MMIO page.
+#[cfg(target_arch = "x86_64")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LapicState(pub [u8; 1024]);
+
+/// FPU and extended register states of a vCPU, laid out as an XSAVE area.
+#[cfg(target_arch = "x86_64")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct XsaveState(pub [u32; 1024]);
+
+/// Pending exceptions, interrupts, and NMIs of a vCPU, in a layout defined
+/// by the hypervisor.
+#[cfg(target_arch = "x86_64")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct VcpuEvents(pub [u8; 64]);
+
+#[cfg(target_arch = "x86_64")]
+consts! {
+    /// Multiprocessor state of a vCPU.
+    pub struct MpState(u32) {
+        RUNNABLE = 0;
+        UNINITIALIZED = 1;
+        INIT_RECEIVED = 2;
+        HALTED = 3;
+        SIPI_RECEIVED = 4;
+    }
+}
+
 pub trait Vcpu {
     #[cfg(target_arch = "aarch64")]
     fn reset(&mut self, is_bsp: bool) -> Result<()>;
@@ -209,8 +264,48 @@ pub trait Vcpu {
     #[cfg(target_arch = "x86_64")]
     fn set_msrs(&mut self, msrs: &[(u32, u64)]) -> Result<()>;
 
+    /// Reads the MSRs whose indices are given in `msrs` and fills in their
+    /// values.
+    #[cfg(target_arch = "x86_64")]
+    fn get_msrs(&self, msrs: &mut [(u32, u64)]) -> Result<()>;
+
+    #[cfg(target_arch = "x86_64")]
+    fn get_lapic(&self) -> Result<LapicState>;
+
+    #[cfg(target_arch = "x86_64")]
+    fn set_lapic(&mut self, lapic: &LapicState) -> Result<()>;
+
+    #[cfg(target_arch = "x86_64")]
+    fn get_mp_state(&self) -> Result<MpState>;
+
+    #[cfg(target_arch = "x86_64")]
+    fn set_mp_state(&mut self, state: MpState) -> Result<()>;
+
+    #[cfg(target_arch = "x86_64")]
+    fn get_xsave(&self) -> Result<XsaveState>;
+
+    #[cfg(target_arch = "x86_64")]
+    fn set_xsave(&mut self, xsave: &XsaveState) -> Result<()>;
+
+    /// Returns the indices and values of extended control registers.
+    #[cfg(target_arch = "x86_64")]
+    fn get_xcrs(&self) -> Result<Vec<(u32, u64)>>;
+
+    #[cfg(target_arch = "x86_64")]
+    fn set_xcrs(&mut self, xcrs: &[(u32, u64)]) -> Result<()>;
+
+    #[cfg(target_arch = "x86_64")]
+    fn get_vcpu_events(&self) -> Result<VcpuEvents>;
+
+    #[cfg(target_arch = "x86_64")]
+    fn set_vcpu_events(&mut self, events: &VcpuEvents) -> Result<()>;
+
     fn dump(&self) -> Result<(), Error>;
 
+    /// Configures debugging of the vCPU. All features are disabled by
+    /// [`GuestDebug::default()`].
+    fn set_guest_debug(&mut self, debug: &GuestDebug) -> Result<()>;
+
     #[cfg(target_arch = "aarch64")]
     fn advance_pc(&mut self) -> Result<()> {
         let pc = self.get_reg(Reg::Pc)?;
@@ -258,6 +353,17 @@ pub trait VmMemory: Debug + Send + Sync + 'static {
     }
 
     fn mark_private_memory(&self, gpa: u64, size: u64, private: bool) -> Result<()>;
+
+    /// Starts logging writes to the memory mapped at `gpa`.
+    fn start_dirty_log(&self, gpa: u64, size: u64) -> Result<()>;
+
+    /// Stops logging writes to the memory mapped at `gpa`.
+    fn stop_dirty_log(&self, gpa: u64, size: u64) -> Result<()>;
+
+    /// Returns a bitmap of pages of the memory mapped at `gpa` written since
+    /// the last call, and clears the log. Bit `i` stands for the page at
+    /// `gpa + i * PAGE_SIZE`.
+    fn get_dirty_log(&self, gpa: u64, size: u64) -> Result<Vec<u64>>;
 }
 
 pub trait IoeventFd: Debug + Send + Sync + AsFd + 'static {}
@@ -355,6 +461,13 @@ pub trait Vm {
     fn create_ioeventfd_registry(&self) -> Result<Self::IoeventFdRegistry>;
     fn stop_vcpu<T>(&self, identity: u64, handle: &JoinHandle<T>) -> Result<(), Error>;
 
+    /// Returns the guest clock in nanoseconds.
+    #[cfg(target_arch = "x86_64")]
+    fn get_clock(&self) -> Result<u64>;
+
+    #[cfg(target_arch = "x86_64")]
+    fn set_clock(&self, ns: u64) -> Result<()>;
+
     #[cfg(target_arch = "x86_64")]
     fn sev_launch_start(&self, policy: SevPolicy) -> Result<()>;
 
@@ -442,6 +555,8 @@ pub enum VmExit {
     Reboot,
     Paused,
     Interrupted,
+    /// The vCPU hit a software breakpoint or finished a single step.
+    Debug,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
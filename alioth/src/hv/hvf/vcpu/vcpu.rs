@@ -27,7 +27,7 @@ use snafu::ResultExt;
 use crate::arch::reg::{MpidrEl1, Pstate, Reg, SReg};
 use crate::hv::hvf::check_ret;
 use crate::hv::hvf::vm::{HvfVm, VcpuEvent};
-use crate::hv::{Result, Vcpu, VmEntry, VmExit, error};
+use crate::hv::{GuestDebug, Result, Vcpu, VmEntry, VmExit, error};
 use crate::sys::hvf::{
     HvExitReason, HvReg, HvVcpuExit, hv_vcpu_create, hv_vcpu_destroy, hv_vcpu_get_reg,
     hv_vcpu_get_sys_reg, hv_vcpu_run, hv_vcpu_set_reg, hv_vcpu_set_sys_reg,
@@ -144,6 +144,7 @@ impl Reg {
             Reg::Sp => HvfReg::SReg(SReg::SP_EL0),
             Reg::Pc => HvfReg::Reg(HvReg::PC),
             Reg::Pstate => HvfReg::Reg(HvReg::CPSR),
+            Reg::SpEl1 => HvfReg::SReg(SReg::SP_EL1),
         }
     }
 }
@@ -158,6 +159,10 @@ impl Vcpu for HvfVcpu {
         unimplemented!()
     }
 
+    fn set_guest_debug(&mut self, _debug: &GuestDebug) -> Result<()> {
+        error::Capability { cap: "guest debug" }.fail()
+    }
+
     fn run(&mut self, entry: VmEntry) -> Result<VmExit> {
         match entry {
             VmEntry::None => {}
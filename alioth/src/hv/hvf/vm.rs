@@ -81,6 +81,18 @@ impl VmMemory for HvfMemory {
     fn reset(&self) -> Result<()> {
         Ok(())
     }
+
+    fn start_dirty_log(&self, _gpa: u64, _size: u64) -> Result<()> {
+        error::Capability { cap: "log dirty" }.fail()
+    }
+
+    fn stop_dirty_log(&self, _gpa: u64, _size: u64) -> Result<()> {
+        error::Capability { cap: "log dirty" }.fail()
+    }
+
+    fn get_dirty_log(&self, _gpa: u64, _size: u64) -> Result<Vec<u64>> {
+        error::Capability { cap: "log dirty" }.fail()
+    }
 }
 
 #[derive(Debug)]
@@ -42,8 +42,12 @@ use crate::arch::reg::{Reg, SReg};
 use crate::ffi;
 use crate::hv::kvm::vm::{KvmVm, VmInner};
 use crate::hv::kvm::{KvmError, kvm_error};
-use crate::hv::{Error, Result, Vcpu, VmEntry, VmExit, error};
-use crate::sys::kvm::{KvmExit, KvmRun, kvm_run};
+use crate::hv::{Error, GuestDebug, Result, Vcpu, VmEntry, VmExit, error};
+#[cfg(target_arch = "x86_64")]
+use crate::hv::{LapicState, MpState, VcpuEvents, XsaveState};
+use crate::sys::kvm::{
+    KvmExit, KvmGuestDebug, KvmGuestDebugFlag, KvmRun, kvm_run, kvm_set_guest_debug,
+};
 
 #[cfg(target_arch = "aarch64")]
 use self::aarch64::VcpuArch;
@@ -212,6 +216,7 @@ impl Vcpu for KvmVcpu {
                 KvmExit::HYPERCALL => self.handle_hypercall(),
                 KvmExit::MMIO => self.handle_mmio(),
                 KvmExit::SHUTDOWN => Ok(VmExit::Shutdown),
+                KvmExit::DEBUG => Ok(VmExit::Debug),
                 KvmExit::SYSTEM_EVENT => self.handle_system_event(),
                 reason => error::VmExit {
                     msg: format!("unkown kvm exit: {reason:#x?}"),
@@ -231,10 +236,88 @@ impl Vcpu for KvmVcpu {
         self.kvm_set_msrs(msrs)
     }
 
+    #[cfg(target_arch = "x86_64")]
+    fn get_msrs(&self, msrs: &mut [(u32, u64)]) -> Result<()> {
+        self.kvm_get_msrs(msrs)
+    }
+
+    #[cfg(target_arch = "x86_64")]
+    fn get_lapic(&self) -> Result<LapicState> {
+        self.kvm_get_lapic()
+    }
+
+    #[cfg(target_arch = "x86_64")]
+    fn set_lapic(&mut self, lapic: &LapicState) -> Result<()> {
+        self.kvm_set_lapic(lapic)
+    }
+
+    #[cfg(target_arch = "x86_64")]
+    fn get_mp_state(&self) -> Result<MpState> {
+        self.kvm_get_mp_state()
+    }
+
+    #[cfg(target_arch = "x86_64")]
+    fn set_mp_state(&mut self, state: MpState) -> Result<()> {
+        self.kvm_set_mp_state(state)
+    }
+
+    #[cfg(target_arch = "x86_64")]
+    fn get_xsave(&self) -> Result<XsaveState> {
+        self.kvm_get_xsave()
+    }
+
+    #[cfg(target_arch = "x86_64")]
+    fn set_xsave(&mut self, xsave: &XsaveState) -> Result<()> {
+        self.kvm_set_xsave(xsave)
+    }
+
+    #[cfg(target_arch = "x86_64")]
+    fn get_xcrs(&self) -> Result<Vec<(u32, u64)>> {
+        self.kvm_get_xcrs()
+    }
+
+    #[cfg(target_arch = "x86_64")]
+    fn set_xcrs(&mut self, xcrs: &[(u32, u64)]) -> Result<()> {
+        self.kvm_set_xcrs(xcrs)
+    }
+
+    #[cfg(target_arch = "x86_64")]
+    fn get_vcpu_events(&self) -> Result<VcpuEvents> {
+        self.kvm_get_vcpu_events()
+    }
+
+    #[cfg(target_arch = "x86_64")]
+    fn set_vcpu_events(&mut self, events: &VcpuEvents) -> Result<()> {
+        self.kvm_set_vcpu_events(events)
+    }
+
     fn dump(&self) -> Result<(), Error> {
         Ok(())
     }
 
+    fn set_guest_debug(&mut self, debug: &GuestDebug) -> Result<()> {
+        let mut control = KvmGuestDebugFlag::empty();
+        if debug.single_step {
+            control |= KvmGuestDebugFlag::SINGLESTEP;
+        }
+        if debug.sw_breakpoint {
+            control |= KvmGuestDebugFlag::USE_SW_BP;
+        }
+        if !control.is_empty() {
+            control |= KvmGuestDebugFlag::ENABLE;
+        }
+        #[cfg(target_arch = "x86_64")]
+        if debug.inject_breakpoint {
+            control |= KvmGuestDebugFlag::INJECT_BP;
+        }
+        let kvm_debug = KvmGuestDebug {
+            control,
+            ..Default::default()
+        };
+        unsafe { kvm_set_guest_debug(&self.fd, &kvm_debug) }.context(error::GuestDebug)?;
+        Ok(())
+    }
+
     #[cfg(target_arch = "x86_64")]
     fn tdx_init_vcpu(&self, hob: u64) -> Result<()> {
         KvmVcpu::tdx_init_vcpu(self, hob)
@@ -17,6 +17,7 @@ mod tdx;
 use std::arch::x86_64::CpuidResult;
 use std::collections::HashMap;
 use std::iter::zip;
+use std::mem::transmute;
 use std::os::fd::{FromRawFd, OwnedFd};
 
 use snafu::ResultExt;
@@ -26,11 +27,15 @@ use crate::arch::reg::{DtReg, DtRegVal, Reg, SReg, SegAccess, SegReg, SegRegVal}
 use crate::hv::kvm::kvm_error;
 use crate::hv::kvm::vcpu::KvmVcpu;
 use crate::hv::kvm::vm::KvmVm;
-use crate::hv::{Error, Result, error};
+use crate::hv::{Error, LapicState, MpState, Result, VcpuEvents, XsaveState, error};
 use crate::sys::kvm::{
-    KVM_MAX_CPUID_ENTRIES, KvmCpuid2, KvmCpuid2Flag, KvmCpuidEntry2, KvmMsrEntry, KvmMsrs, KvmRegs,
-    MAX_IO_MSRS, kvm_create_vcpu, kvm_get_regs, kvm_get_sregs, kvm_get_sregs2, kvm_kvmclock_ctrl,
-    kvm_set_cpuid2, kvm_set_msrs, kvm_set_regs, kvm_set_sregs, kvm_set_sregs2,
+    KVM_MAX_CPUID_ENTRIES, KvmCap, KvmCpuid2, KvmCpuid2Flag, KvmCpuidEntry2, KvmEnableCap,
+    KvmLapicState, KvmMpState, KvmMsrEntry, KvmMsrs, KvmRegs, KvmVcpuEventValid, KvmVcpuEvents,
+    KvmXcrs, KvmXsave, MAX_IO_MSRS, kvm_create_vcpu, kvm_enable_cap, kvm_get_lapic,
+    kvm_get_mp_state, kvm_get_msrs, kvm_get_regs, kvm_get_sregs, kvm_get_sregs2,
+    kvm_get_vcpu_events, kvm_get_xcrs, kvm_get_xsave, kvm_kvmclock_ctrl, kvm_set_cpuid2,
+    kvm_set_lapic, kvm_set_mp_state, kvm_set_msrs, kvm_set_regs, kvm_set_sregs, kvm_set_sregs2,
+    kvm_set_vcpu_events, kvm_set_xcrs, kvm_set_xsave,
 };
 
 #[derive(Debug)]
@@ -326,6 +331,17 @@ impl KvmVcpu {
             }
         }
         unsafe { kvm_set_cpuid2(&self.fd, &kvm_cpuid2) }.context(error::GuestCpuid)?;
+        // Paravirtual MSRs and hypercalls not advertised in CPUID then
+        // raise #GP in the guest.
+        let request = KvmEnableCap {
+            cap: KvmCap::ENFORCE_PV_FEATURE_CPUID,
+            args: [1, 0, 0, 0],
+            flags: 0,
+            pad: [0; 64],
+        };
+        if let Err(e) = unsafe { kvm_enable_cap(&self.fd, &request) } {
+            log::warn!("Failed to enforce paravirtual features in CPUID: {e}");
+        }
         Ok(())
     }
 
@@ -342,6 +358,92 @@ impl KvmVcpu {
         unsafe { kvm_set_msrs(&self.fd, &kvm_msrs) }.context(error::GuestMsr)?;
         Ok(())
     }
+
+    pub fn kvm_get_msrs(&self, msrs: &mut [(u32, u64)]) -> Result<()> {
+        let mut kvm_msrs = KvmMsrs {
+            nmsrs: msrs.len() as u32,
+            _pad: 0,
+            entries: [KvmMsrEntry::default(); MAX_IO_MSRS],
+        };
+        for (entry, (index, _)) in zip(&mut kvm_msrs.entries, msrs.iter()) {
+            entry.index = *index;
+        }
+        let n = unsafe { kvm_get_msrs(&self.fd, &mut kvm_msrs) }.context(error::GuestMsr)?;
+        if let Some((index, _)) = msrs.get(n as usize) {
+            let err = std::io::Error::other(format!("cannot read MSR {index:#x}"));
+            return Err(err).context(error::GuestMsr);
+        }
+        for (entry, (_, data)) in zip(&kvm_msrs.entries, msrs.iter_mut()) {
+            *data = entry.data;
+        }
+        Ok(())
+    }
+
+    pub fn kvm_get_lapic(&self) -> Result<LapicState> {
+        let kvm_lapic = unsafe { kvm_get_lapic(&self.fd) }.context(error::VcpuReg)?;
+        Ok(LapicState(kvm_lapic.regs))
+    }
+
+    pub fn kvm_set_lapic(&mut self, lapic: &LapicState) -> Result<()> {
+        let kvm_lapic = KvmLapicState { regs: lapic.0 };
+        unsafe { kvm_set_lapic(&self.fd, &kvm_lapic) }.context(error::VcpuReg)?;
+        Ok(())
+    }
+
+    pub fn kvm_get_mp_state(&self) -> Result<MpState> {
+        let state = unsafe { kvm_get_mp_state(&self.fd) }.context(error::VcpuReg)?;
+        Ok(MpState::from(state.raw()))
+    }
+
+    pub fn kvm_set_mp_state(&mut self, state: MpState) -> Result<()> {
+        let state = KvmMpState::from(state.raw());
+        unsafe { kvm_set_mp_state(&self.fd, &state) }.context(error::VcpuReg)?;
+        Ok(())
+    }
+
+    pub fn kvm_get_xsave(&self) -> Result<XsaveState> {
+        let kvm_xsave = unsafe { kvm_get_xsave(&self.fd) }.context(error::VcpuReg)?;
+        Ok(XsaveState(kvm_xsave.region))
+    }
+
+    pub fn kvm_set_xsave(&mut self, xsave: &XsaveState) -> Result<()> {
+        let kvm_xsave = KvmXsave { region: xsave.0 };
+        unsafe { kvm_set_xsave(&self.fd, &kvm_xsave) }.context(error::VcpuReg)?;
+        Ok(())
+    }
+
+    pub fn kvm_get_xcrs(&self) -> Result<Vec<(u32, u64)>> {
+        let kvm_xcrs = unsafe { kvm_get_xcrs(&self.fd) }.context(error::VcpuReg)?;
+        let xcrs = kvm_xcrs.xcrs.iter().take(kvm_xcrs.nr_xcrs as usize);
+        Ok(xcrs.map(|xcr| (xcr.xcr, xcr.value)).collect())
+    }
+
+    pub fn kvm_set_xcrs(&mut self, xcrs: &[(u32, u64)]) -> Result<()> {
+        let mut kvm_xcrs = KvmXcrs::default();
+        for (entry, (xcr, value)) in zip(&mut kvm_xcrs.xcrs, xcrs) {
+            entry.xcr = *xcr;
+            entry.value = *value;
+            kvm_xcrs.nr_xcrs += 1;
+        }
+        unsafe { kvm_set_xcrs(&self.fd, &kvm_xcrs) }.context(error::VcpuReg)?;
+        Ok(())
+    }
+
+    pub fn kvm_get_vcpu_events(&self) -> Result<VcpuEvents> {
+        let kvm_events = unsafe { kvm_get_vcpu_events(&self.fd) }.context(error::VcpuReg)?;
+        // KvmVcpuEvents is made of integers without padding.
+        let events = unsafe { transmute::<KvmVcpuEvents, [u8; 64]>(kvm_events) };
+        Ok(VcpuEvents(events))
+    }
+
+    pub fn kvm_set_vcpu_events(&mut self, events: &VcpuEvents) -> Result<()> {
+        let mut kvm_events = unsafe { transmute::<[u8; 64], KvmVcpuEvents>(events.0) };
+        // KVM leaves pending NMIs and the SIPI vector alone unless they
+        // are marked valid.
+        kvm_events.flags |= KvmVcpuEventValid::NMI_PENDING | KvmVcpuEventValid::SIPI_VECTOR;
+        unsafe { kvm_set_vcpu_events(&self.fd, &kvm_events) }.context(error::VcpuReg)?;
+        Ok(())
+    }
 }
 
 #[cfg(test)]
@@ -18,13 +18,13 @@ use std::ptr::null_mut;
 use assert_matches::assert_matches;
 use libc::{MAP_ANONYMOUS, MAP_FAILED, MAP_SHARED, PROT_EXEC, PROT_READ, PROT_WRITE, mmap};
 
-use crate::arch::msr::Efer;
+use crate::arch::msr::{Efer, IA32_PAT, IA32_SYSENTER_ESP, IA32_TSC};
 use crate::arch::paging::Entry;
 use crate::arch::reg::{Cr0, Cr4, Reg, SegAccess};
 use crate::ffi;
 use crate::hv::{
-    DtReg, DtRegVal, Hypervisor, Kvm, MemMapOption, SReg, SegReg, SegRegVal, Vcpu, Vm, VmEntry,
-    VmExit, VmMemory,
+    DtReg, DtRegVal, Hypervisor, Kvm, MemMapOption, MpState, SReg, SegReg, SegRegVal, Vcpu, Vm,
+    VmEntry, VmExit, VmMemory,
 };
 
 #[test]
@@ -175,6 +175,58 @@ fn test_vcpu_regs() {
     }
 }
 
+#[test]
+#[cfg_attr(not(feature = "test-hv"), ignore)]
+fn test_vcpu_snapshot_state() {
+    use crate::hv::VmConfig;
+    use crate::hv::kvm::KvmConfig;
+
+    let kvm = Kvm::new(KvmConfig::default()).unwrap();
+    let vm_config = VmConfig { coco: None };
+    let vm = kvm.create_vm(&vm_config).unwrap();
+    let mut vcpu = vm.create_vcpu(0, 0).unwrap();
+
+    let msrs = [
+        (IA32_TSC, 0x1234_5678),
+        (IA32_SYSENTER_ESP, 0xffff_8000_0000_1000),
+        (IA32_PAT, 0x0007_0406_0007_0406),
+    ];
+    vcpu.set_msrs(&msrs).unwrap();
+    let mut got = msrs.map(|(index, _)| (index, 0));
+    vcpu.get_msrs(&mut got).unwrap();
+    assert_eq!(got[1..], msrs[1..]);
+    assert!(got[0].1 >= msrs[0].1);
+    assert_matches!(vcpu.get_msrs(&mut [(0xdead_beef, 0)]), Err(_));
+
+    let mut lapic = vcpu.get_lapic().unwrap();
+    // Task priority register
+    lapic.0[0x80] = 0x20;
+    vcpu.set_lapic(&lapic).unwrap();
+    assert_eq!(vcpu.get_lapic().unwrap().0[0x80], 0x20);
+
+    vcpu.set_mp_state(MpState::HALTED).unwrap();
+    assert_eq!(vcpu.get_mp_state().unwrap(), MpState::HALTED);
+
+    // x87 only
+    vcpu.set_xcrs(&[(0, 0x1)]).unwrap();
+    assert_eq!(vcpu.get_xcrs().unwrap(), [(0, 0x1)]);
+
+    let mut xsave = vcpu.get_xsave().unwrap();
+    // FPU control word
+    assert_eq!(xsave.0[0] & 0xffff, 0x37f);
+    xsave.0[0] = (xsave.0[0] & !0xffff) | 0x27f;
+    // XSTATE_BV: x87 state is not in its initial configuration
+    xsave.0[128] |= 1;
+    vcpu.set_xsave(&xsave).unwrap();
+    assert_eq!(vcpu.get_xsave().unwrap().0[0] & 0xffff, 0x27f);
+
+    let mut events = vcpu.get_vcpu_events().unwrap();
+    // NMI masked
+    events.0[14] = 1;
+    vcpu.set_vcpu_events(&events).unwrap();
+    assert_eq!(vcpu.get_vcpu_events().unwrap().0[14], 1);
+}
+
 #[test]
 #[cfg_attr(not(feature = "test-hv"), ignore)]
 fn test_kvm_run() {
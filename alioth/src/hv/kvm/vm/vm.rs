@@ -37,6 +37,7 @@ use snafu::ResultExt;
 
 #[cfg(target_arch = "x86_64")]
 use crate::arch::cpuid::CpuidIn;
+use crate::arch::layout::PAGE_SIZE;
 #[cfg(target_arch = "x86_64")]
 use crate::arch::sev::{SevPolicy, SnpPageType, SnpPolicy};
 #[cfg(target_arch = "x86_64")]
@@ -53,13 +54,14 @@ use crate::sys::kvm::KVM_IRQCHIP_IOAPIC;
 #[cfg(target_arch = "aarch64")]
 use crate::sys::kvm::KvmMsiFlag;
 use crate::sys::kvm::{
-    KVM_IRQ_ROUTING_IRQCHIP, KVM_IRQ_ROUTING_MSI, KvmCap, KvmEnableCap, KvmEncRegion, KvmIoEventFd,
-    KvmIoEventFdFlag, KvmIrqRouting, KvmIrqRoutingEntry, KvmIrqRoutingIrqchip, KvmIrqRoutingMsi,
-    KvmIrqfd, KvmIrqfdFlag, KvmMemFlag, KvmMemoryAttribute, KvmMemoryAttributes, KvmMsi,
-    KvmUserspaceMemoryRegion, KvmUserspaceMemoryRegion2, kvm_create_vm, kvm_enable_cap,
-    kvm_get_vcpu_mmap_size, kvm_ioeventfd, kvm_irqfd, kvm_memory_encrypt_reg_region,
-    kvm_memory_encrypt_unreg_region, kvm_set_gsi_routing, kvm_set_memory_attributes,
-    kvm_set_user_memory_region, kvm_set_user_memory_region2, kvm_signal_msi,
+    KVM_IRQ_ROUTING_IRQCHIP, KVM_IRQ_ROUTING_MSI, KvmCap, KvmDirtyLog, KvmEnableCap, KvmEncRegion,
+    KvmIoEventFd, KvmIoEventFdFlag, KvmIrqRouting, KvmIrqRoutingEntry, KvmIrqRoutingIrqchip,
+    KvmIrqRoutingMsi, KvmIrqfd, KvmIrqfdFlag, KvmMemFlag, KvmMemoryAttribute, KvmMemoryAttributes,
+    KvmMsi, KvmUserspaceMemoryRegion, KvmUserspaceMemoryRegion2, kvm_create_vm, kvm_enable_cap,
+    kvm_get_dirty_log, kvm_get_vcpu_mmap_size, kvm_ioeventfd, kvm_irqfd,
+    kvm_memory_encrypt_reg_region, kvm_memory_encrypt_unreg_region, kvm_set_gsi_routing,
+    kvm_set_memory_attributes, kvm_set_user_memory_region, kvm_set_user_memory_region2,
+    kvm_signal_msi,
 };
 
 #[cfg(target_arch = "aarch64")]
@@ -152,7 +154,14 @@ impl Display for VmInner {
     }
 }
 
-type MemSlots = (u32, HashMap<(u64, u64), u32>);
+#[derive(Debug, Clone, Copy)]
+struct MemSlot {
+    id: u32,
+    hva: usize,
+    flags: KvmMemFlag,
+}
+
+type MemSlots = (u32, HashMap<(u64, u64), MemSlot>);
 
 #[derive(Debug)]
 pub struct KvmMemory {
@@ -168,6 +177,32 @@ impl KvmMemory {
         }
     }
 
+    fn set_slot(&self, slot: &MemSlot, gpa: u64, size: u64) -> std::io::Result<()> {
+        if let Some(memfd) = &self.vm.memfd {
+            let region = KvmUserspaceMemoryRegion2 {
+                slot: slot.id,
+                guest_phys_addr: gpa as _,
+                memory_size: size as _,
+                userspace_addr: slot.hva as _,
+                flags: slot.flags | KvmMemFlag::GUEST_MEMFD,
+                guest_memfd: memfd.as_raw_fd() as _,
+                guest_memfd_offset: gpa,
+                ..Default::default()
+            };
+            unsafe { kvm_set_user_memory_region2(&self.vm.fd, &region) }?;
+        } else {
+            let region = KvmUserspaceMemoryRegion {
+                slot: slot.id,
+                guest_phys_addr: gpa as _,
+                memory_size: size as _,
+                userspace_addr: slot.hva as _,
+                flags: slot.flags,
+            };
+            unsafe { kvm_set_user_memory_region(&self.vm.fd, &region) }?;
+        }
+        Ok(())
+    }
+
     fn unmap(&self, slot: u32, gpa: u64, size: u64) -> Result<()> {
         let flags = KvmMemFlag::empty();
         let region = KvmUserspaceMemoryRegion {
@@ -185,6 +220,22 @@ impl KvmMemory {
         );
         Ok(())
     }
+
+    fn update_dirty_log(&self, gpa: u64, size: u64, enabled: bool) -> Result<()> {
+        let (_, slots) = &mut *self.slots.lock();
+        let Some(slot) = slots.get_mut(&(gpa, size)) else {
+            return Err(ErrorKind::NotFound.into()).context(error::DirtyLog { gpa, size });
+        };
+        let mut new_slot = *slot;
+        new_slot.flags.set(KvmMemFlag::LOG_DIRTY_PAGES, enabled);
+        if new_slot.flags == slot.flags {
+            return Ok(());
+        }
+        self.set_slot(&new_slot, gpa, size)
+            .context(error::DirtyLog { gpa, size })?;
+        *slot = new_slot;
+        Ok(())
+    }
 }
 
 impl VmMemory for KvmMemory {
@@ -200,31 +251,14 @@ impl VmMemory for KvmMemory {
             flags |= KvmMemFlag::LOG_DIRTY_PAGES;
         }
         let (slot_id, slots) = &mut *self.slots.lock();
-        if let Some(memfd) = &self.vm.memfd {
-            flags |= KvmMemFlag::GUEST_MEMFD;
-            let region = KvmUserspaceMemoryRegion2 {
-                slot: *slot_id,
-                guest_phys_addr: gpa as _,
-                memory_size: size as _,
-                userspace_addr: hva as _,
-                flags,
-                guest_memfd: memfd.as_raw_fd() as _,
-                guest_memfd_offset: gpa,
-                ..Default::default()
-            };
-            unsafe { kvm_set_user_memory_region2(&self.vm.fd, &region) }
-        } else {
-            let region = KvmUserspaceMemoryRegion {
-                slot: *slot_id,
-                guest_phys_addr: gpa as _,
-                memory_size: size as _,
-                userspace_addr: hva as _,
-                flags,
-            };
-            unsafe { kvm_set_user_memory_region(&self.vm.fd, &region) }
-        }
-        .context(error::GuestMap { hva, gpa, size })?;
-        slots.insert((gpa, size), *slot_id);
+        let slot = MemSlot {
+            id: *slot_id,
+            hva,
+            flags,
+        };
+        self.set_slot(&slot, gpa, size)
+            .context(error::GuestMap { hva, gpa, size })?;
+        slots.insert((gpa, size), slot);
         log::trace!(
             "{}: slot-{slot_id}: mapped: {gpa:#018x} -> {hva:#018x}, size = {size:#x}",
             self.vm
@@ -238,7 +272,7 @@ impl VmMemory for KvmMemory {
         let Some(slot) = slots.remove(&(gpa, size)) else {
             return Err(ErrorKind::NotFound.into()).context(error::GuestUnmap { gpa, size });
         };
-        self.unmap(slot, gpa, size)
+        self.unmap(slot.id, gpa, size)
     }
 
     fn register_encrypted_range(&self, range: &[u8]) -> Result<()> {
@@ -279,11 +313,35 @@ impl VmMemory for KvmMemory {
     fn reset(&self) -> Result<()> {
         let (slot_id, slots) = &mut *self.slots.lock();
         for ((gpa, size), slot) in slots.drain() {
-            self.unmap(slot, gpa, size)?;
+            self.unmap(slot.id, gpa, size)?;
         }
         *slot_id = 0;
         Ok(())
     }
+
+    fn start_dirty_log(&self, gpa: u64, size: u64) -> Result<()> {
+        self.update_dirty_log(gpa, size, true)
+    }
+
+    fn stop_dirty_log(&self, gpa: u64, size: u64) -> Result<()> {
+        self.update_dirty_log(gpa, size, false)
+    }
+
+    fn get_dirty_log(&self, gpa: u64, size: u64) -> Result<Vec<u64>> {
+        let (_, slots) = &*self.slots.lock();
+        let Some(slot) = slots.get(&(gpa, size)) else {
+            return Err(ErrorKind::NotFound.into()).context(error::DirtyLog { gpa, size });
+        };
+        let num_pages = size.div_ceil(PAGE_SIZE);
+        let mut bitmap = vec![0u64; num_pages.div_ceil(64) as usize];
+        let log = KvmDirtyLog {
+            slot: slot.id,
+            _padding: 0,
+            dirty_bitmap: bitmap.as_mut_ptr() as u64,
+        };
+        unsafe { kvm_get_dirty_log(&self.vm.fd, &log) }.context(error::DirtyLog { gpa, size })?;
+        Ok(bitmap)
+    }
 }
 #[derive(Debug)]
 pub struct KvmIrqSender {
@@ -701,6 +759,16 @@ impl Vm for KvmVm {
         })
     }
 
+    #[cfg(target_arch = "x86_64")]
+    fn get_clock(&self) -> Result<u64> {
+        KvmVm::get_clock(self)
+    }
+
+    #[cfg(target_arch = "x86_64")]
+    fn set_clock(&self, ns: u64) -> Result<()> {
+        KvmVm::set_clock(self, ns)
+    }
+
     #[cfg(target_arch = "x86_64")]
     fn sev_launch_start(&self, policy: SevPolicy) -> Result<(), Error> {
         KvmVm::sev_launch_start(self, policy)
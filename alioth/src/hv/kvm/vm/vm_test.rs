@@ -67,3 +67,60 @@ fn test_mem_map() {
         .mem_map(0x0, 0x1000, user_mem as usize, option)
         .unwrap();
 }
+
+#[cfg(target_arch = "x86_64")]
+#[test]
+#[cfg_attr(not(feature = "test-hv"), ignore)]
+fn test_dirty_log() {
+    use crate::hv::{Vcpu, VmEntry, VmExit};
+
+    let kvm = Kvm::new(KvmConfig::default()).unwrap();
+    let vm_config = VmConfig { coco: None };
+    let mut vm = kvm.create_vm(&vm_config).unwrap();
+    let vm_memory = vm.create_vm_memory().unwrap();
+
+    let size = 0x10000;
+    let prot = PROT_WRITE | PROT_READ | PROT_EXEC;
+    let flag = MAP_ANONYMOUS | MAP_PRIVATE;
+    let user_mem = ffi!(
+        unsafe { mmap(null_mut(), size, prot, flag, -1, 0) },
+        MAP_FAILED
+    )
+    .unwrap();
+    #[rustfmt::skip]
+    const CODE: [u8; 8] = [
+        // mov byte cs:[0x2000], 0x1
+        0x2e, 0xc6, 0x06, 0x00, 0x20, 0x01,
+        // out 0x80, al
+        0xe6, 0x80,
+    ];
+    unsafe { ((user_mem as usize + 0xfff0) as *mut [u8; 8]).write(CODE) };
+
+    let gpa = 0xffff_0000;
+    let size = size as u64;
+    let option = MemMapOption {
+        read: true,
+        write: true,
+        exec: true,
+        log_dirty: false,
+    };
+    vm_memory
+        .mem_map(gpa, size, user_mem as usize, option)
+        .unwrap();
+    assert_matches!(
+        vm_memory.get_dirty_log(0x0, size),
+        Err(Error::DirtyLog { .. })
+    );
+    assert_matches!(vm_memory.start_dirty_log(gpa, size), Ok(()));
+
+    let mut vcpu = vm.create_vcpu(0, 0).unwrap();
+    assert_matches!(vcpu.run(VmEntry::None), Ok(VmExit::Io { port: 0x80, .. }));
+    assert_eq!(vm_memory.get_dirty_log(gpa, size).unwrap(), [1 << 2]);
+    assert_eq!(vm_memory.get_dirty_log(gpa, size).unwrap(), [0]);
+
+    assert_matches!(vm_memory.stop_dirty_log(gpa, size), Ok(()));
+    assert_matches!(
+        vm_memory.get_dirty_log(gpa, size),
+        Err(Error::DirtyLog { .. })
+    );
+}
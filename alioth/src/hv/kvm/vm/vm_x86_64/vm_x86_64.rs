@@ -26,8 +26,8 @@ use crate::hv::kvm::x86_64::sev::SevFd;
 use crate::hv::kvm::{KvmVm, kvm_error};
 use crate::hv::{Coco, Kvm, Result, VmConfig, error};
 use crate::sys::kvm::{
-    KvmCap, KvmCreateGuestMemfd, KvmVmType, KvmX2apicApiFlag, kvm_create_guest_memfd,
-    kvm_set_identity_map_addr, kvm_set_tss_addr,
+    KvmCap, KvmClockData, KvmCreateGuestMemfd, KvmVmType, KvmX2apicApiFlag, kvm_create_guest_memfd,
+    kvm_get_clock, kvm_set_clock, kvm_set_identity_map_addr, kvm_set_tss_addr,
 };
 
 pub fn translate_msi_addr(addr_lo: u32, addr_hi: u32) -> (u32, u32) {
@@ -113,6 +113,20 @@ impl KvmVm {
 
         Ok(())
     }
+
+    pub fn get_clock(&self) -> Result<u64> {
+        let data = unsafe { kvm_get_clock(&self.vm.fd) }.context(error::VmClock)?;
+        Ok(data.clock)
+    }
+
+    pub fn set_clock(&self, ns: u64) -> Result<()> {
+        let data = KvmClockData {
+            clock: ns,
+            ..Default::default()
+        };
+        unsafe { kvm_set_clock(&self.vm.fd, &data) }.context(error::VmClock)?;
+        Ok(())
+    }
 }
 
 #[cfg(test)]
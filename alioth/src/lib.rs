@@ -25,12 +25,16 @@ pub mod errors;
 pub mod firmware;
 #[path = "fuse/fuse.rs"]
 pub mod fuse;
+#[path = "gdb/gdb.rs"]
+pub mod gdb;
 #[path = "hv/hv.rs"]
 pub mod hv;
 #[path = "loader/loader.rs"]
 pub mod loader;
 #[path = "mem/mem.rs"]
 pub mod mem;
+#[path = "metrics/metrics.rs"]
+pub mod metrics;
 #[path = "pci/pci.rs"]
 pub mod pci;
 #[path = "sync/sync.rs"]
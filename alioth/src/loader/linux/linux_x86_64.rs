@@ -164,7 +164,7 @@ pub fn load<P: AsRef<Path>>(
             MemRegionType::Reserved => E820_RESERVED,
             MemRegionType::Acpi => E820_ACPI,
             MemRegionType::Pmem => E820_PMEM,
-            MemRegionType::Hidden => continue,
+            MemRegionType::Hidden | MemRegionType::Hotplug => continue,
         };
         boot_params.e820_table[region_index] = BootE820Entry {
             addr: *addr,
@@ -249,7 +249,7 @@ pub fn load<P: AsRef<Path>>(
             MemRegionType::Reserved => XEN_HVM_MEMMAP_TYPE_RESERVED,
             MemRegionType::Acpi => XEN_HVM_MEMMAP_TYPE_ACPI,
             MemRegionType::Pmem => XEN_HVM_MEMMAP_TYPE_PMEM,
-            MemRegionType::Hidden => continue,
+            MemRegionType::Hidden | MemRegionType::Hotplug => continue,
         };
         start_info_page.memory_map[index] = HvmMemmapTableEntry {
             addr: *addr,
@@ -14,6 +14,7 @@
 
 use std::fmt::Debug;
 use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
 
 use crate::mem::addressable::{Addressable, SlotBackend};
 use crate::mem::{Memory, Result};
@@ -61,6 +62,44 @@ impl SlotBackend for Arc<dyn Mmio> {
     }
 }
 
+/// An emulated range that counts the guest accesses to it.
+#[derive(Debug)]
+pub struct CountedMmio {
+    pub range: Arc<dyn Mmio>,
+    pub exits: AtomicU64,
+}
+
+impl CountedMmio {
+    pub fn new(range: Arc<dyn Mmio>) -> Self {
+        CountedMmio {
+            range,
+            exits: AtomicU64::new(0),
+        }
+    }
+}
+
+impl Mmio for CountedMmio {
+    fn read(&self, offset: u64, size: u8) -> Result<u64> {
+        self.exits.fetch_add(1, Ordering::Relaxed);
+        self.range.read(offset, size)
+    }
+
+    fn write(&self, offset: u64, size: u8, val: u64) -> Result<Action> {
+        self.exits.fetch_add(1, Ordering::Relaxed);
+        self.range.write(offset, size, val)
+    }
+
+    fn size(&self) -> u64 {
+        Mmio::size(self.range.as_ref())
+    }
+}
+
+impl SlotBackend for CountedMmio {
+    fn size(&self) -> u64 {
+        Mmio::size(self.range.as_ref())
+    }
+}
+
 #[macro_export]
 macro_rules! impl_mmio_for_zerocopy {
     ($ty:ident) => {
@@ -146,6 +185,10 @@ where
         self.inner.is_empty()
     }
 
+    pub fn iter(&self) -> impl DoubleEndedIterator<Item = (u64, &R)> {
+        self.inner.iter()
+    }
+
     pub fn add(&mut self, addr: u64, range: R) -> Result<()> {
         self.inner.add(addr, range)?;
         Ok(())
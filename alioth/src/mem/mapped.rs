@@ -13,6 +13,7 @@
 // limitations under the License.
 
 use std::cell::UnsafeCell;
+use std::collections::{BTreeMap, BTreeSet};
 #[cfg(target_os = "linux")]
 use std::ffi::CStr;
 use std::fmt::Debug;
@@ -24,17 +25,21 @@ use std::os::fd::FromRawFd;
 use std::os::fd::{AsFd, AsRawFd, BorrowedFd};
 use std::ptr::{NonNull, null_mut};
 use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
 
 #[cfg(target_os = "linux")]
-use libc::{MADV_HUGEPAGE, MFD_CLOEXEC};
 use libc::{
-    MAP_ANONYMOUS, MAP_FAILED, MAP_PRIVATE, MAP_SHARED, MS_ASYNC, PROT_READ, PROT_WRITE, c_void,
-    madvise, mmap, msync, munmap,
+    MADV_HUGEPAGE, MADV_POPULATE_WRITE, MFD_CLOEXEC, SYS_mbind, c_int, c_uint, c_ulong, syscall,
 };
-use parking_lot::{RwLock, RwLockReadGuard};
+use libc::{
+    MAP_ANONYMOUS, MAP_FAILED, MAP_PRIVATE, MAP_SHARED, MS_ASYNC, PROT_NONE, PROT_READ, PROT_WRITE,
+    c_void, madvise, mlock, mmap, mprotect, msync, munmap,
+};
+use parking_lot::{Mutex, RwLock, RwLockReadGuard};
 use snafu::ResultExt;
 use zerocopy::{FromBytes, Immutable, IntoBytes};
 
+use crate::arch::layout::PAGE_SIZE;
 use crate::ffi;
 use crate::mem::addressable::{Addressable, SlotBackend};
 use crate::mem::{Error, Result, error};
@@ -103,6 +108,43 @@ impl ArcMemPages {
         Ok(())
     }
 
+    /// Allocates all the pages in advance by writing to them.
+    #[cfg(target_os = "linux")]
+    pub fn populate(&self) -> Result<()> {
+        ffi!(unsafe { madvise(self.addr as *mut _, self.size, MADV_POPULATE_WRITE) })?;
+        Ok(())
+    }
+
+    pub fn mlock(&self) -> Result<()> {
+        ffi!(unsafe { mlock(self.addr as *const _, self.size) })?;
+        Ok(())
+    }
+
+    /// Allocates the pages in `offset..offset + len` from host NUMA node
+    /// `node` only. Pages allocated before the call are not moved.
+    #[cfg(target_os = "linux")]
+    pub fn mbind(&self, offset: u64, len: u64, node: u32) -> Result<()> {
+        const MPOL_BIND: c_int = 2;
+        let bits = c_ulong::BITS as usize;
+        let mut mask = vec![0 as c_ulong; node as usize / bits + 1];
+        mask[node as usize / bits] |= 1 << (node as usize % bits);
+        // The kernel reads maxnode - 1 bits of the mask.
+        let max_node = mask.len() * bits + 1;
+        let addr = self.addr + offset as usize;
+        ffi!(unsafe {
+            syscall(
+                SYS_mbind,
+                addr,
+                len as usize,
+                MPOL_BIND,
+                mask.as_ptr(),
+                max_node,
+                0,
+            )
+        })?;
+        Ok(())
+    }
+
     fn from_raw(addr: *mut c_void, len: usize, fd: Option<(File, u64)>) -> Self {
         let addr = NonNull::new(addr).expect("address from mmap() should not be null");
         ArcMemPages {
@@ -120,9 +162,26 @@ impl ArcMemPages {
         Ok(Self::from_raw(addr, len, Some((file, offset as u64))))
     }
 
+    /// Maps a file copy-on-write, so that writes to the pages never reach
+    /// the file.
+    pub fn from_file_private(file: &File, offset: i64, len: usize) -> Result<Self> {
+        let prot = PROT_READ | PROT_WRITE;
+        let addr = ffi!(
+            unsafe { mmap(null_mut(), len, prot, MAP_PRIVATE, file.as_raw_fd(), offset) },
+            MAP_FAILED
+        )?;
+        Ok(Self::from_raw(addr, len, None))
+    }
+
     #[cfg(target_os = "linux")]
-    pub fn from_memfd(name: &CStr, size: usize, prot: Option<i32>) -> Result<Self> {
-        let fd = ffi!(unsafe { libc::memfd_create(name.as_ptr(), MFD_CLOEXEC) })?;
+    pub fn from_memfd(
+        name: &CStr,
+        size: usize,
+        prot: Option<i32>,
+        flags: Option<c_uint>,
+    ) -> Result<Self> {
+        let flags = flags.unwrap_or(0) | MFD_CLOEXEC;
+        let fd = ffi!(unsafe { libc::memfd_create(name.as_ptr(), flags) })?;
         let prot = prot.unwrap_or(PROT_WRITE | PROT_READ);
         let addr = ffi!(
             unsafe { mmap(null_mut(), size, prot, MAP_SHARED, fd, 0) },
@@ -180,9 +239,40 @@ impl ArcMemPages {
     }
 }
 
+/// Guest pages written by the VMM while dirty tracking is on.
+#[derive(Debug, Default)]
+struct DirtyPages {
+    enabled: AtomicBool,
+    pages: Mutex<BTreeSet<u64>>,
+}
+
 #[derive(Debug)]
 pub struct Ram {
     inner: Addressable<ArcMemPages>,
+    dirty: DirtyPages,
+    /// Guest pages that cannot be accessed, as a map from the start of each
+    /// range to its end.
+    inaccessible: RwLock<BTreeMap<u64, u64>>,
+}
+
+/// Removes `[start, end)` from `ranges`, splitting the ranges that partially
+/// overlap with it.
+fn remove_range(ranges: &mut BTreeMap<u64, u64>, start: u64, end: u64) {
+    let overlaps: Vec<_> = ranges
+        .range(..end)
+        .rev()
+        .take_while(|(_, e)| **e > start)
+        .map(|(s, e)| (*s, *e))
+        .collect();
+    for (s, e) in overlaps {
+        ranges.remove(&s);
+        if s < start {
+            ranges.insert(s, start);
+        }
+        if e > end {
+            ranges.insert(end, e);
+        }
+    }
 }
 
 #[derive(Debug)]
@@ -251,7 +341,43 @@ impl Ram {
         }
     }
 
+    /// Returns the length of the accessible part of `[gpa, gpa + len)`
+    /// starting at `gpa`.
+    fn accessible_len(&self, gpa: u64, len: u64) -> Result<u64> {
+        let inaccessible = self.inaccessible.read();
+        if let Some((_, end)) = inaccessible.range(..=gpa).next_back()
+            && *end > gpa
+        {
+            return error::Inaccessible { addr: gpa }.fail();
+        }
+        match inaccessible.range(gpa..).next() {
+            Some((start, _)) => Ok(std::cmp::min(len, start - gpa)),
+            None => Ok(len),
+        }
+    }
+
+    /// Calls `f` with the host address and length of each host range
+    /// backing `[gpa, gpa + size)`, whether accessible or not.
+    fn for_each_host_range<F>(&self, gpa: u64, size: u64, mut f: F) -> Result<()>
+    where
+        F: FnMut(usize, usize) -> Result<()>,
+    {
+        let end = gpa + size;
+        let mut cur = gpa;
+        while cur < end {
+            let Some((start, user_mem)) = self.inner.search(cur) else {
+                return error::NotMapped { addr: cur }.fail();
+            };
+            let (addr, len) =
+                user_mem.get_valid_range((cur - start) as usize, (end - cur) as usize)?;
+            f(addr, len)?;
+            cur += len as u64;
+        }
+        Ok(())
+    }
+
     fn get_partial_slice(&self, gpa: u64, len: u64) -> Result<&[u8]> {
+        let len = self.accessible_len(gpa, len)?;
         let Some((start, user_mem)) = self.inner.search(gpa) else {
             return error::NotMapped { addr: gpa }.fail();
         };
@@ -259,6 +385,7 @@ impl Ram {
     }
 
     fn get_partial_slice_mut(&self, gpa: u64, len: u64) -> Result<&mut [u8]> {
+        let len = self.accessible_len(gpa, len)?;
         let Some((start, user_mem)) = self.inner.search(gpa) else {
             return error::NotMapped { addr: gpa }.fail();
         };
@@ -336,7 +463,6 @@ impl Ram {
         let host_ref = self.get_partial_slice_mut(gpa, len)?;
         if host_ref.len() == buf.len() {
             host_ref.copy_from_slice(buf);
-            Ok(())
         } else {
             let mut cur = 0;
             for r in self.slice_iter_mut(gpa, len) {
@@ -345,8 +471,9 @@ impl Ram {
                 s.copy_from_slice(&buf[cur..(cur + s_len)]);
                 cur += s_len;
             }
-            Ok(())
         }
+        self.mark_dirty(gpa, len);
+        Ok(())
     }
 
     pub fn write_t<T>(&self, gpa: u64, val: &T) -> Result<(), Error>
@@ -371,24 +498,83 @@ impl Ram {
         Ok(slices)
     }
 
+    /// Translates guest buffers the VMM is going to write to.
+    ///
+    /// The buffers are marked dirty here, and must be marked again with
+    /// [`Ram::mark_dirty_host`] once written, since the pages might have been
+    /// collected in between.
     pub fn translate_iov_mut<'a>(&'a self, iov: &[(u64, u64)]) -> Result<Vec<IoSliceMut<'a>>> {
         let mut slices = vec![];
         for (gpa, len) in iov {
             for r in self.slice_iter_mut(*gpa, *len) {
                 slices.push(IoSliceMut::new(r?));
             }
+            self.mark_dirty(*gpa, *len);
         }
         Ok(slices)
     }
 
+    /// Marks the guest pages in `[gpa, gpa + len)` dirty if dirty tracking
+    /// is on.
+    pub fn mark_dirty(&self, gpa: u64, len: u64) {
+        if len == 0 || !self.dirty.enabled.load(Ordering::Acquire) {
+            return;
+        }
+        let mut pages = self.dirty.pages.lock();
+        let end = gpa + len;
+        let mut page = gpa & !(PAGE_SIZE - 1);
+        while page < end {
+            pages.insert(page);
+            page += PAGE_SIZE;
+        }
+    }
+
+    /// Marks the guest pages backing host memory `[ptr, ptr + len)` dirty if
+    /// dirty tracking is on.
+    pub fn mark_dirty_host(&self, ptr: *const u8, len: usize) {
+        if len == 0 || !self.dirty.enabled.load(Ordering::Acquire) {
+            return;
+        }
+        let addr = ptr as usize;
+        for (gpa, pages) in self.inner.iter() {
+            if addr >= pages.addr() && addr - pages.addr() < pages.size() as usize {
+                self.mark_dirty(gpa + (addr - pages.addr()) as u64, len as u64);
+                return;
+            }
+        }
+    }
+
     pub fn iter(&self) -> impl DoubleEndedIterator<Item = (u64, &ArcMemPages)> {
         self.inner.iter()
     }
 
     pub fn madvise(&self, gpa: u64, size: u64, advice: i32) -> Result<()> {
-        for r in self.slice_iter_mut(gpa, size) {
-            let s = r?;
-            ffi!(unsafe { madvise(s.as_mut_ptr() as _, s.len(), advice) })?;
+        self.for_each_host_range(gpa, size, |addr, len| {
+            ffi!(unsafe { madvise(addr as _, len, advice) })?;
+            Ok(())
+        })?;
+        // Discarded pages read back as zeros.
+        self.mark_dirty(gpa, size);
+        Ok(())
+    }
+
+    /// Sets whether the guest pages in `[gpa, gpa + size)` can be accessed.
+    /// Accesses to inaccessible pages fail in the VMM. A guest access makes
+    /// KVM_RUN fail with EFAULT, which stops the VM.
+    pub fn set_accessible(&self, gpa: u64, size: u64, accessible: bool) -> Result<()> {
+        let prot = if accessible {
+            PROT_READ | PROT_WRITE
+        } else {
+            PROT_NONE
+        };
+        self.for_each_host_range(gpa, size, |addr, len| {
+            ffi!(unsafe { mprotect(addr as _, len, prot) })?;
+            Ok(())
+        })?;
+        let mut inaccessible = self.inaccessible.write();
+        remove_range(&mut inaccessible, gpa, gpa + size);
+        if !accessible {
+            inaccessible.insert(gpa, gpa + size);
         }
         Ok(())
     }
@@ -409,6 +595,8 @@ impl RamBus {
         Self {
             ram: RwLock::new(Ram {
                 inner: Addressable::default(),
+                dirty: DirtyPages::default(),
+                inaccessible: RwLock::new(BTreeMap::new()),
             }),
         }
     }
@@ -421,7 +609,26 @@ impl RamBus {
 
     pub(crate) fn remove(&self, gpa: u64) -> Result<ArcMemPages, Error> {
         let mut ram = self.ram.write();
-        ram.inner.remove(gpa)
+        let user_mem = ram.inner.remove(gpa)?;
+        remove_range(ram.inaccessible.get_mut(), gpa, gpa + user_mem.size());
+        Ok(user_mem)
+    }
+
+    /// Starts or stops tracking guest pages written by the VMM.
+    pub(crate) fn set_dirty_tracking(&self, enabled: bool) {
+        let ram = self.ram.read();
+        ram.dirty.enabled.store(enabled, Ordering::Release);
+        if !enabled {
+            ram.dirty.pages.lock().clear();
+        }
+    }
+
+    /// Returns the addresses of guest pages written by the VMM since the last
+    /// call.
+    pub(crate) fn take_dirty_pages(&self) -> BTreeSet<u64> {
+        let ram = self.ram.read();
+        let mut pages = ram.dirty.pages.lock();
+        std::mem::take(&mut *pages)
     }
 
     pub fn read(&self, gpa: u64, buf: &mut [u8]) -> Result<()> {
@@ -463,6 +670,7 @@ impl RamBus {
         for r in ram.slice_iter_mut(gpa, len) {
             src.read_exact(r?).context(error::Read)?;
         }
+        ram.mark_dirty(gpa, len);
         Ok(())
     }
 
@@ -491,7 +699,11 @@ impl RamBus {
                 iov.push(IoSliceMut::new(r?));
             }
         }
-        Ok(callback(&mut iov))
+        let ret = callback(&mut iov);
+        for (gpa, len) in bufs {
+            ram.mark_dirty(*gpa, *len);
+        }
+        Ok(ret)
     }
 }
 
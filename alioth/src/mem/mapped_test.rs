@@ -20,6 +20,7 @@ use libc::{PROT_READ, PROT_WRITE};
 use zerocopy::{FromBytes, Immutable, IntoBytes};
 
 use super::{ArcMemPages, RamBus};
+use crate::mem::Error;
 
 #[derive(Debug, IntoBytes, FromBytes, Immutable, PartialEq, Eq)]
 #[repr(C)]
@@ -91,3 +92,38 @@ fn test_ram_bus_read() {
     drop(locked_bus);
     bus.remove(0x0).unwrap();
 }
+
+#[test]
+fn test_ram_set_accessible() {
+    let bus = RamBus::new();
+    let mem = ArcMemPages::from_anonymous(4 * PAGE_SIZE as usize, None, None).unwrap();
+    bus.add(0, mem).unwrap();
+    let ram = bus.lock_layout();
+
+    ram.set_accessible(0, 4 * PAGE_SIZE, false).unwrap();
+    ram.set_accessible(PAGE_SIZE, PAGE_SIZE, true).unwrap();
+    bus.write_t(PAGE_SIZE, &1u32).unwrap();
+    assert_matches!(bus.read_t::<u32>(PAGE_SIZE), Ok(1));
+    for gpa in [0, 2 * PAGE_SIZE, 3 * PAGE_SIZE] {
+        assert_matches!(bus.read_t::<u32>(gpa), Err(Error::Inaccessible { addr, .. }) if addr == gpa);
+    }
+    // The accessible part ends before the inaccessible page.
+    assert_matches!(
+        ram.translate_iov(&[(PAGE_SIZE, 2 * PAGE_SIZE)]),
+        Err(Error::Inaccessible { addr, .. }) if addr == 2 * PAGE_SIZE
+    );
+
+    ram.set_accessible(2 * PAGE_SIZE, PAGE_SIZE, true).unwrap();
+    assert_matches!(bus.read_t::<u32>(2 * PAGE_SIZE), Ok(0));
+    assert_matches!(
+        bus.read_t::<u32>(3 * PAGE_SIZE),
+        Err(Error::Inaccessible { .. })
+    );
+    drop(ram);
+
+    // Removing the pages clears their access permission.
+    bus.remove(0).unwrap();
+    let mem = ArcMemPages::from_anonymous(4 * PAGE_SIZE as usize, None, None).unwrap();
+    bus.add(0, mem).unwrap();
+    assert_matches!(bus.read_t::<u32>(0), Ok(0));
+}
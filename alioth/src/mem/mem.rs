@@ -18,7 +18,9 @@ pub mod mapped;
 
 use std::any::{Any, type_name};
 use std::fmt::Debug;
+use std::path::Path;
 use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
 
 use parking_lot::{Mutex, RwLock};
 use serde::Deserialize;
@@ -27,11 +29,13 @@ use snafu::Snafu;
 
 #[cfg(target_arch = "aarch64")]
 use crate::arch::layout::IO_START;
+use crate::arch::layout::PAGE_SIZE;
 use crate::errors::{DebugTrace, trace_error};
 use crate::hv::{MemMapOption, VmEntry, VmMemory};
+use crate::metrics::RegionMetrics;
 
 use self::addressable::{Addressable, SlotBackend};
-use self::emulated::{Action, Mmio, MmioBus};
+use self::emulated::{Action, CountedMmio, Mmio, MmioBus};
 use self::mapped::{ArcMemPages, Ram, RamBus};
 
 #[trace_error]
@@ -51,6 +55,8 @@ pub enum Error {
     },
     #[snafu(display("{addr:#x} is not mapped"))]
     NotMapped { addr: u64 },
+    #[snafu(display("{addr:#x} is not accessible"))]
+    Inaccessible { addr: u64 },
     #[snafu(display("Sum of backend range sizes {sum:#x} exceeds the region total size"))]
     BackendTooBig { sum: u64, size: u64 },
     #[snafu(display("address {addr:#x} is not {align}-byte aligned"))]
@@ -81,7 +87,8 @@ fn default_memory_size() -> u64 {
     1 << 30
 }
 
-#[derive(Debug, Default, PartialEq, Eq, Deserialize, Help)]
+#[derive(Debug, PartialEq, Eq, Deserialize, Help)]
+#[serde(deny_unknown_fields)]
 pub struct MemConfig {
     /// Total guest memory size in bytes. [default: 1G]
     #[serde(default = "default_memory_size")]
@@ -97,6 +104,17 @@ pub struct MemConfig {
     #[cfg(target_os = "linux")]
     #[serde(default, alias = "thp")]
     pub transparent_hugepage: bool,
+    /// Allocate all guest memory before the guest starts. [default: false]
+    #[cfg(target_os = "linux")]
+    #[serde(default)]
+    pub prealloc: bool,
+    /// Lock guest memory in host RAM with mlock(). [default: false]
+    #[serde(default)]
+    pub mlock: bool,
+    /// Size of the guest physical region reserved for hotplugging memory
+    /// through virtio-mem. [default: 0]
+    #[serde(default)]
+    pub hotplug_size: u64,
 }
 
 #[derive(Debug, Default, PartialEq, Eq, Deserialize, Help)]
@@ -109,16 +127,87 @@ pub enum MemBackend {
     #[cfg(target_os = "linux")]
     #[serde(alias = "memfd")]
     Memfd,
+    /// Anonymous file of huge pages by memfd_create() with MFD_HUGETLB.
+    /// Always uses MAP_SHARED.
+    #[cfg(target_os = "linux")]
+    #[serde(alias = "hugetlb")]
+    Hugetlb(HugetlbParam),
+    /// A file on the host, e.g. on a tmpfs or a DAX device. Always uses
+    /// MAP_SHARED.
+    #[serde(alias = "file")]
+    File(MemFileParam),
+}
+
+#[cfg(target_os = "linux")]
+#[derive(Debug, Default, PartialEq, Eq, Deserialize, Help)]
+pub struct HugetlbParam {
+    /// Size of huge pages. [default: 2m]
+    #[serde(default)]
+    pub page_size: HugePageSize,
+}
+
+#[cfg(target_os = "linux")]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize, Help)]
+pub enum HugePageSize {
+    /// 2 MiB pages.
+    #[default]
+    #[serde(alias = "2m")]
+    Size2M,
+    /// 1 GiB pages.
+    #[serde(alias = "1g")]
+    Size1G,
+}
+
+#[cfg(target_os = "linux")]
+impl HugePageSize {
+    pub fn size(&self) -> u64 {
+        match self {
+            HugePageSize::Size2M => 2 << 20,
+            HugePageSize::Size1G => 1 << 30,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Deserialize, Help)]
+pub struct MemFileParam {
+    /// Path to the file. It is created if it does not exist.
+    pub path: Box<Path>,
+}
+
+impl Default for MemConfig {
+    fn default() -> Self {
+        MemConfig {
+            size: default_memory_size(),
+            backend: MemBackend::default(),
+            shared: false,
+            #[cfg(target_os = "linux")]
+            transparent_hugepage: false,
+            #[cfg(target_os = "linux")]
+            prealloc: false,
+            mlock: false,
+            hotplug_size: 0,
+        }
+    }
 }
 
 impl MemConfig {
     pub fn has_shared_fd(&self) -> bool {
         match &self.backend {
             #[cfg(target_os = "linux")]
-            MemBackend::Memfd => true,
+            MemBackend::Memfd | MemBackend::Hugetlb(_) => true,
+            MemBackend::File(_) => true,
             MemBackend::Anonymous => false,
         }
     }
+
+    /// Returns the size of host pages backing guest memory.
+    pub fn page_size(&self) -> u64 {
+        match &self.backend {
+            #[cfg(target_os = "linux")]
+            MemBackend::Hugetlb(param) => param.page_size.size(),
+            _ => PAGE_SIZE,
+        }
+    }
 }
 
 #[derive(Debug)]
@@ -146,6 +235,9 @@ pub enum MemRegionType {
     Reserved,
     Acpi,
     Pmem,
+    /// RAM plugged on demand, e.g. through virtio-mem. It is hidden from the
+    /// guest memory map and is not pinned for passthrough devices.
+    Hotplug,
 }
 
 #[derive(Debug, Clone, Copy)]
@@ -201,6 +293,12 @@ impl MemRegion {
         }
     }
 
+    fn is_hotplug(&self) -> bool {
+        self.entries
+            .iter()
+            .any(|e| e.type_ == MemRegionType::Hotplug)
+    }
+
     pub fn validate(&self) -> Result<()> {
         let entries_size = self.size();
         let ranges_size = self.ranges.iter().fold(0, |accu, r| accu + r.size());
@@ -245,16 +343,24 @@ impl SlotBackend for Arc<IoRegion> {
 pub trait LayoutChanged: Debug + Send + Sync + 'static {
     fn ram_added(&self, gpa: u64, pages: &ArcMemPages) -> Result<()>;
     fn ram_removed(&self, gpa: u64, pages: &ArcMemPages) -> Result<()>;
+    /// Returns whether the callback is also notified of RAM plugged on
+    /// demand, of which parts might be inaccessible.
+    fn hotplug(&self) -> bool {
+        true
+    }
 }
 
 pub trait LayoutUpdated: Debug + Send + Sync + 'static {
     fn ram_updated(&self, ram: &Ram) -> Result<()>;
 }
 
+/// A callback with the name of the device that registered it.
+type DevCallback<T> = (Option<Arc<str>>, Box<T>);
+
 #[derive(Debug, Default)]
 struct LayoutCallbacks {
-    changed: Vec<Box<dyn LayoutChanged>>,
-    updated: Vec<Box<dyn LayoutUpdated>>,
+    changed: Vec<DevCallback<dyn LayoutChanged>>,
+    updated: Vec<DevCallback<dyn LayoutUpdated>>,
 }
 
 // lock order: region -> callbacks -> bus
@@ -263,11 +369,12 @@ pub struct Memory {
     regions: Mutex<Addressable<Arc<MemRegion>>>,
     callbacks: Mutex<LayoutCallbacks>,
     ram_bus: Arc<RamBus>,
-    mmio_bus: RwLock<MmioBus>,
+    mmio_bus: RwLock<MmioBus<CountedMmio>>,
     vm_memory: Arc<dyn VmMemory>,
+    dirty_log: AtomicBool,
 
     #[cfg(target_arch = "x86_64")]
-    io_bus: RwLock<MmioBus>,
+    io_bus: RwLock<MmioBus<CountedMmio>>,
     io_regions: Mutex<Addressable<Arc<IoRegion>>>,
 }
 
@@ -279,6 +386,7 @@ impl Memory {
             ram_bus: Arc::new(RamBus::new()),
             mmio_bus: RwLock::new(MmioBus::new()),
             vm_memory,
+            dirty_log: AtomicBool::new(false),
             #[cfg(target_arch = "x86_64")]
             io_bus: RwLock::new(MmioBus::new()),
             io_regions: Mutex::new(Addressable::new()),
@@ -286,8 +394,29 @@ impl Memory {
     }
 
     pub fn register_change_callback(&self, callback: Box<dyn LayoutChanged>) -> Result<()> {
+        self.add_change_callback(None, callback)
+    }
+
+    /// Registers a callback of device `name`, which is removed by
+    /// [`Memory::remove_dev_callbacks`].
+    pub fn register_dev_change_callback(
+        &self,
+        name: Arc<str>,
+        callback: Box<dyn LayoutChanged>,
+    ) -> Result<()> {
+        self.add_change_callback(Some(name), callback)
+    }
+
+    fn add_change_callback(
+        &self,
+        owner: Option<Arc<str>>,
+        callback: Box<dyn LayoutChanged>,
+    ) -> Result<()> {
         let regions = self.regions.lock();
         for (addr, region) in regions.iter() {
+            if region.is_hotplug() && !callback.hotplug() {
+                continue;
+            }
             let mut offset = 0;
             for range in &region.ranges {
                 let gpa = addr + offset;
@@ -299,19 +428,46 @@ impl Memory {
             }
         }
         let mut callbacks = self.callbacks.lock();
-        callbacks.changed.push(callback);
+        callbacks.changed.push((owner, callback));
         Ok(())
     }
 
     pub fn register_update_callback(&self, callback: Box<dyn LayoutUpdated>) -> Result<()> {
+        self.add_update_callback(None, callback)
+    }
+
+    /// Registers a callback of device `name`, which is removed by
+    /// [`Memory::remove_dev_callbacks`].
+    pub fn register_dev_update_callback(
+        &self,
+        name: Arc<str>,
+        callback: Box<dyn LayoutUpdated>,
+    ) -> Result<()> {
+        self.add_update_callback(Some(name), callback)
+    }
+
+    fn add_update_callback(
+        &self,
+        owner: Option<Arc<str>>,
+        callback: Box<dyn LayoutUpdated>,
+    ) -> Result<()> {
         let _regions = self.regions.lock();
         let mut callbacks = self.callbacks.lock();
         let ram = self.ram_bus.lock_layout();
         callback.ram_updated(&ram)?;
-        callbacks.updated.push(callback);
+        callbacks.updated.push((owner, callback));
         Ok(())
     }
 
+    /// Removes the callbacks registered by device `name`.
+    pub fn remove_dev_callbacks(&self, name: &str) {
+        let _regions = self.regions.lock();
+        let mut callbacks = self.callbacks.lock();
+        let other_owner = |owner: &Option<Arc<str>>| owner.as_deref() != Some(name);
+        callbacks.changed.retain(|(owner, _)| other_owner(owner));
+        callbacks.updated.retain(|(owner, _)| other_owner(owner));
+    }
+
     pub fn reset(&self) -> Result<()> {
         self.clear()?;
         self.vm_memory.reset()?;
@@ -322,12 +478,12 @@ impl Memory {
         self.ram_bus.clone()
     }
 
-    fn map_to_vm(&self, gpa: u64, user_mem: &ArcMemPages) -> Result<(), Error> {
+    fn map_to_vm(&self, gpa: u64, user_mem: &ArcMemPages, log_dirty: bool) -> Result<(), Error> {
         let mem_options = MemMapOption {
             read: true,
             write: true,
             exec: true,
-            log_dirty: false,
+            log_dirty,
         };
         self.vm_memory
             .mem_map(gpa, user_mem.size(), user_mem.addr(), mem_options)?;
@@ -351,29 +507,33 @@ impl Memory {
         let mut offset = 0;
         let callbacks = self.callbacks.lock();
         let mut ram_updated = false;
+        let hotplug = region.is_hotplug();
         for range in &region.ranges {
             let gpa = addr + offset;
             match range {
                 MemRange::Emulated(r) => {
                     let mut mmio_bus = self.mmio_bus.write();
-                    mmio_bus.add(gpa, r.clone())?
+                    mmio_bus.add(gpa, CountedMmio::new(r.clone()))?
                 }
                 MemRange::Ram(r) => {
-                    self.map_to_vm(gpa, r)?;
-                    for callback in &callbacks.changed {
+                    self.map_to_vm(gpa, r, self.dirty_log.load(Ordering::Acquire))?;
+                    for (_, callback) in &callbacks.changed {
+                        if hotplug && !callback.hotplug() {
+                            continue;
+                        }
                         callback.ram_added(gpa, r)?;
                     }
                     self.ram_bus.add(gpa, r.clone())?;
                     ram_updated = true;
                 }
-                MemRange::DevMem(r) => self.map_to_vm(gpa, r)?,
+                MemRange::DevMem(r) => self.map_to_vm(gpa, r, false)?,
                 MemRange::Span(_) => {}
             }
             offset += range.size();
         }
         if ram_updated {
             let ram = self.ram_bus.lock_layout();
-            for update_callback in &callbacks.updated {
+            for (_, update_callback) in &callbacks.updated {
                 update_callback.ram_updated(&ram)?;
             }
         }
@@ -388,6 +548,7 @@ impl Memory {
         let mut offset = 0;
         let callbacks = self.callbacks.lock();
         let mut ram_updated = false;
+        let hotplug = region.is_hotplug();
         for range in &region.ranges {
             let gpa = addr + offset;
             match range {
@@ -397,7 +558,10 @@ impl Memory {
                 }
                 MemRange::Ram(r) => {
                     self.ram_bus.remove(gpa)?;
-                    for callback in callbacks.changed.iter().rev() {
+                    for (_, callback) in callbacks.changed.iter().rev() {
+                        if hotplug && !callback.hotplug() {
+                            continue;
+                        }
                         callback.ram_removed(gpa, r)?;
                     }
                     self.unmap_from_vm(gpa, r)?;
@@ -410,7 +574,7 @@ impl Memory {
         }
         if ram_updated {
             let ram = self.ram_bus.lock_layout();
-            for callback in &callbacks.updated {
+            for (_, callback) in &callbacks.updated {
                 callback.ram_updated(&ram)?;
             }
         }
@@ -456,6 +620,89 @@ impl Memory {
         entries
     }
 
+    fn set_vm_dirty_log(&self, gpa: u64, size: u64, enabled: bool) -> Result<()> {
+        if enabled {
+            self.vm_memory.start_dirty_log(gpa, size)?;
+        } else {
+            self.vm_memory.stop_dirty_log(gpa, size)?;
+        }
+        Ok(())
+    }
+
+    fn update_dirty_log(&self, enabled: bool) -> Result<()> {
+        let regions = self.regions.lock();
+        let mut updated = vec![];
+        for (addr, region) in regions.iter() {
+            let mut offset = 0;
+            for range in &region.ranges {
+                if let MemRange::Ram(r) = range {
+                    let gpa = addr + offset;
+                    if let Err(e) = self.set_vm_dirty_log(gpa, r.size(), enabled) {
+                        for (gpa, size) in updated {
+                            if let Err(e) = self.set_vm_dirty_log(gpa, size, !enabled) {
+                                log::error!("{gpa:#x}: failed to restore dirty logging: {e:?}");
+                            }
+                        }
+                        return Err(e);
+                    }
+                    updated.push((gpa, r.size()));
+                }
+                offset += range.size();
+            }
+        }
+        self.ram_bus.set_dirty_tracking(enabled);
+        self.dirty_log.store(enabled, Ordering::Release);
+        Ok(())
+    }
+
+    /// Starts logging writes to guest RAM, including RAM added later.
+    pub fn start_dirty_log(&self) -> Result<()> {
+        self.update_dirty_log(true)
+    }
+
+    pub fn stop_dirty_log(&self) -> Result<()> {
+        self.update_dirty_log(false)
+    }
+
+    /// Returns the guest pages written by the guest or the VMM since the last
+    /// call, for each region with RAM. Bit `i` of a bitmap stands for the page
+    /// at `addr + i * PAGE_SIZE` of the region at `addr`.
+    pub fn dirty_pages(&self) -> Result<Vec<(u64, Vec<u64>)>> {
+        let mut dirty_pages = vec![];
+        let regions = self.regions.lock();
+        let vmm_pages = self.ram_bus.take_dirty_pages();
+        for (addr, region) in regions.iter() {
+            let num_pages = region.size().div_ceil(PAGE_SIZE);
+            let mut bitmap = vec![0u64; num_pages.div_ceil(64) as usize];
+            let mut has_ram = false;
+            let mut offset = 0;
+            for range in &region.ranges {
+                if let MemRange::Ram(r) = range {
+                    let log = self.vm_memory.get_dirty_log(addr + offset, r.size())?;
+                    let base = offset / PAGE_SIZE;
+                    for (index, mut word) in log.into_iter().enumerate() {
+                        while word != 0 {
+                            let page = base + index as u64 * 64 + word.trailing_zeros() as u64;
+                            bitmap[(page / 64) as usize] |= 1 << (page % 64);
+                            word &= word - 1;
+                        }
+                    }
+                    let start = addr + offset;
+                    for gpa in vmm_pages.range(start..start + r.size()) {
+                        let page = (gpa - addr) / PAGE_SIZE;
+                        bitmap[(page / 64) as usize] |= 1 << (page % 64);
+                    }
+                    has_ram = true;
+                }
+                offset += range.size();
+            }
+            if has_ram {
+                dirty_pages.push((addr, bitmap));
+            }
+        }
+        Ok(dirty_pages)
+    }
+
     pub fn io_region_entries(&self) -> Vec<(u64, u64)> {
         let mut entries = vec![];
         let regions = self.io_regions.lock();
@@ -475,12 +722,13 @@ impl Memory {
         #[cfg(target_arch = "x86_64")]
         {
             let mut io_bus = self.io_bus.write();
-            io_bus.add(port as u64, region.range.clone())?;
+            io_bus.add(port as u64, CountedMmio::new(region.range.clone()))?;
         }
         #[cfg(target_arch = "aarch64")]
         {
             let mut mmio_bus = self.mmio_bus.write();
-            mmio_bus.add(IO_START + port as u64, region.range.clone())?;
+            let range = CountedMmio::new(region.range.clone());
+            mmio_bus.add(IO_START + port as u64, range)?;
         }
         let callbacks = region.callbacks.lock();
         for callback in callbacks.iter() {
@@ -592,6 +840,17 @@ impl Memory {
         }
     }
 
+    /// Returns the number of guest accesses to each emulated MMIO region.
+    pub fn mmio_metrics(&self) -> Vec<RegionMetrics> {
+        region_metrics(&self.mmio_bus.read())
+    }
+
+    /// Returns the number of guest accesses to each emulated port range.
+    #[cfg(target_arch = "x86_64")]
+    pub fn io_metrics(&self) -> Vec<RegionMetrics> {
+        region_metrics(&self.io_bus.read())
+    }
+
     #[cfg(target_arch = "x86_64")]
     pub fn handle_io(&self, port: u16, write: Option<u32>, size: u8) -> Result<VmEntry> {
         let io_bus = self.io_bus.read();
@@ -606,6 +865,15 @@ impl Memory {
     }
 }
 
+fn region_metrics(bus: &MmioBus<CountedMmio>) -> Vec<RegionMetrics> {
+    let region = |(start, r): (u64, &CountedMmio)| RegionMetrics {
+        start,
+        size: Mmio::size(r),
+        exits: r.exits.load(Ordering::Relaxed),
+    };
+    bus.iter().map(region).collect()
+}
+
 #[derive(Debug)]
 pub struct MarkPrivateMemory {
     pub memory: Arc<dyn VmMemory>,
@@ -621,3 +889,7 @@ impl LayoutChanged for MarkPrivateMemory {
         Ok(())
     }
 }
+
+#[cfg(test)]
+#[path = "mem_test.rs"]
+mod tests;
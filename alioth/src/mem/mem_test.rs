@@ -0,0 +1,358 @@
+// Copyright 2026 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::HashMap;
+use std::io::ErrorKind;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU32, Ordering};
+
+use assert_matches::assert_matches;
+use libc::{PROT_READ, PROT_WRITE};
+use parking_lot::Mutex;
+
+use crate::arch::layout::PAGE_SIZE;
+use crate::hv::{self, MemMapOption, VmMemory};
+use crate::mem::emulated::{Action, Mmio};
+use crate::mem::mapped::{ArcMemPages, Ram};
+use crate::mem::{
+    LayoutChanged, LayoutUpdated, MemRange, MemRegion, MemRegionEntry, MemRegionType, Memory,
+    Result,
+};
+use crate::metrics::RegionMetrics;
+
+// (gpa, size) -> (log_dirty, dirty bitmap)
+type FakeSlots = HashMap<(u64, u64), (bool, Vec<u64>)>;
+
+#[derive(Debug, Default)]
+struct FakeVmMemory {
+    slots: Mutex<FakeSlots>,
+    /// Logging cannot be started for the slot at this address.
+    broken_slot: Option<u64>,
+}
+
+impl FakeVmMemory {
+    fn log_dirty(&self, gpa: u64, size: u64) -> bool {
+        self.slots.lock()[&(gpa, size)].0
+    }
+
+    fn write(&self, gpa: u64, size: u64, page: u64) {
+        let mut slots = self.slots.lock();
+        let (log_dirty, bitmap) = slots.get_mut(&(gpa, size)).unwrap();
+        if *log_dirty {
+            bitmap[(page / 64) as usize] |= 1 << (page % 64);
+        }
+    }
+}
+
+impl VmMemory for FakeVmMemory {
+    fn mem_map(&self, gpa: u64, size: u64, _hva: usize, option: MemMapOption) -> hv::Result<()> {
+        let bitmap = vec![0; size.div_ceil(PAGE_SIZE).div_ceil(64) as usize];
+        self.slots
+            .lock()
+            .insert((gpa, size), (option.log_dirty, bitmap));
+        Ok(())
+    }
+
+    fn unmap(&self, gpa: u64, size: u64) -> hv::Result<()> {
+        self.slots.lock().remove(&(gpa, size));
+        Ok(())
+    }
+
+    fn mark_private_memory(&self, _gpa: u64, _size: u64, _private: bool) -> hv::Result<()> {
+        unreachable!()
+    }
+
+    fn reset(&self) -> hv::Result<()> {
+        self.slots.lock().clear();
+        Ok(())
+    }
+
+    fn start_dirty_log(&self, gpa: u64, size: u64) -> hv::Result<()> {
+        if self.broken_slot == Some(gpa) {
+            return Err(hv::Error::DirtyLog {
+                gpa,
+                size,
+                error: ErrorKind::Unsupported.into(),
+                _location: snafu::location!(),
+            });
+        }
+        self.slots.lock().get_mut(&(gpa, size)).unwrap().0 = true;
+        Ok(())
+    }
+
+    fn stop_dirty_log(&self, gpa: u64, size: u64) -> hv::Result<()> {
+        self.slots.lock().get_mut(&(gpa, size)).unwrap().0 = false;
+        Ok(())
+    }
+
+    fn get_dirty_log(&self, gpa: u64, size: u64) -> hv::Result<Vec<u64>> {
+        let mut slots = self.slots.lock();
+        let (_, bitmap) = slots.get_mut(&(gpa, size)).unwrap();
+        let log = bitmap.clone();
+        bitmap.fill(0);
+        Ok(log)
+    }
+}
+
+fn anon_pages(num_pages: u64) -> ArcMemPages {
+    let size = (num_pages * PAGE_SIZE) as usize;
+    ArcMemPages::from_anonymous(size, Some(PROT_READ | PROT_WRITE), None).unwrap()
+}
+
+#[test]
+fn test_dirty_pages() {
+    let vm_memory = Arc::new(FakeVmMemory::default());
+    let memory = Memory::new(vm_memory.clone());
+
+    let ram = MemRegion::with_ram(anon_pages(4), MemRegionType::Ram);
+    memory.add_region(0x0, Arc::new(ram)).unwrap();
+    let dev_mem = MemRegion::with_dev_mem(anon_pages(1), MemRegionType::Reserved);
+    memory.add_region(0x10_0000, Arc::new(dev_mem)).unwrap();
+    assert!(!vm_memory.log_dirty(0x0, 4 * PAGE_SIZE));
+
+    assert_matches!(memory.start_dirty_log(), Ok(()));
+    assert!(vm_memory.log_dirty(0x0, 4 * PAGE_SIZE));
+    assert!(!vm_memory.log_dirty(0x10_0000, PAGE_SIZE));
+
+    // RAM added after logging starts is logged as well.
+    let gpa = 0x20_0000;
+    let region = MemRegion {
+        ranges: vec![MemRange::Span(2 * PAGE_SIZE), MemRange::Ram(anon_pages(3))],
+        entries: vec![MemRegionEntry {
+            size: 5 * PAGE_SIZE,
+            type_: MemRegionType::Ram,
+        }],
+        callbacks: Mutex::new(vec![]),
+    };
+    memory.add_region(gpa, Arc::new(region)).unwrap();
+    let ram_gpa = gpa + 2 * PAGE_SIZE;
+    assert!(vm_memory.log_dirty(ram_gpa, 3 * PAGE_SIZE));
+
+    vm_memory.write(0x0, 4 * PAGE_SIZE, 1);
+    vm_memory.write(0x0, 4 * PAGE_SIZE, 3);
+    vm_memory.write(ram_gpa, 3 * PAGE_SIZE, 0);
+    assert_eq!(
+        memory.dirty_pages().unwrap(),
+        [(0x0, vec![0b1010]), (gpa, vec![0b100])]
+    );
+    assert_eq!(
+        memory.dirty_pages().unwrap(),
+        [(0x0, vec![0]), (gpa, vec![0])]
+    );
+
+    // Pages written by the VMM are reported as well.
+    let ram_bus = memory.ram_bus();
+    ram_bus.write_t(PAGE_SIZE - 4, &0u64).unwrap();
+    {
+        let ram = ram_bus.lock_layout();
+        let iov = ram
+            .translate_iov_mut(&[(ram_gpa + 2 * PAGE_SIZE, 8)])
+            .unwrap();
+        assert_eq!(
+            memory.dirty_pages().unwrap(),
+            [(0x0, vec![0b11]), (gpa, vec![0b10000])]
+        );
+        // The buffer is written after the page was collected.
+        ram.mark_dirty_host(iov[0].as_ptr(), iov[0].len());
+    }
+    assert_eq!(
+        memory.dirty_pages().unwrap(),
+        [(0x0, vec![0]), (gpa, vec![0b10000])]
+    );
+
+    assert_matches!(memory.stop_dirty_log(), Ok(()));
+    assert!(!vm_memory.log_dirty(0x0, 4 * PAGE_SIZE));
+    assert!(!vm_memory.log_dirty(ram_gpa, 3 * PAGE_SIZE));
+    vm_memory.write(0x0, 4 * PAGE_SIZE, 0);
+    ram_bus.write_t(0x0, &0u64).unwrap();
+    assert_eq!(
+        memory.dirty_pages().unwrap(),
+        [(0x0, vec![0]), (gpa, vec![0])]
+    );
+}
+
+#[test]
+fn test_start_dirty_log_rollback() {
+    let vm_memory = Arc::new(FakeVmMemory {
+        broken_slot: Some(0x10_0000),
+        ..Default::default()
+    });
+    let memory = Memory::new(vm_memory.clone());
+
+    let ram = MemRegion::with_ram(anon_pages(1), MemRegionType::Ram);
+    memory.add_region(0x0, Arc::new(ram)).unwrap();
+    let ram = MemRegion::with_ram(anon_pages(1), MemRegionType::Ram);
+    memory.add_region(0x10_0000, Arc::new(ram)).unwrap();
+
+    assert_matches!(memory.start_dirty_log(), Err(_));
+    assert!(!vm_memory.log_dirty(0x0, PAGE_SIZE));
+    assert!(!vm_memory.log_dirty(0x10_0000, PAGE_SIZE));
+
+    memory.ram_bus().write_t(0x0, &0u64).unwrap();
+    assert_eq!(
+        memory.dirty_pages().unwrap(),
+        [(0x0, vec![0]), (0x10_0000, vec![0])]
+    );
+}
+
+#[derive(Debug)]
+struct CountCallback(Arc<AtomicU32>);
+
+impl LayoutChanged for CountCallback {
+    fn ram_added(&self, _gpa: u64, _pages: &ArcMemPages) -> Result<()> {
+        self.0.fetch_add(1, Ordering::Relaxed);
+        Ok(())
+    }
+
+    fn ram_removed(&self, _gpa: u64, _pages: &ArcMemPages) -> Result<()> {
+        Ok(())
+    }
+}
+
+impl LayoutUpdated for CountCallback {
+    fn ram_updated(&self, _ram: &Ram) -> Result<()> {
+        self.0.fetch_add(1, Ordering::Relaxed);
+        Ok(())
+    }
+}
+
+#[test]
+fn test_remove_dev_callbacks() {
+    let memory = Memory::new(Arc::new(FakeVmMemory::default()));
+    let counts: [Arc<AtomicU32>; 3] = Default::default();
+    for (name, count) in [Some("dev-a"), Some("dev-b"), None]
+        .into_iter()
+        .zip(&counts)
+    {
+        let changed = Box::new(CountCallback(count.clone()));
+        let updated = Box::new(CountCallback(count.clone()));
+        match name {
+            Some(name) => {
+                memory
+                    .register_dev_change_callback(name.into(), changed)
+                    .unwrap();
+                memory
+                    .register_dev_update_callback(name.into(), updated)
+                    .unwrap();
+            }
+            None => {
+                memory.register_change_callback(changed).unwrap();
+                memory.register_update_callback(updated).unwrap();
+            }
+        }
+    }
+    let region = MemRegion::with_ram(anon_pages(1), MemRegionType::Ram);
+    memory.add_region(0x0, Arc::new(region)).unwrap();
+    let load = |count: &AtomicU32| count.load(Ordering::Relaxed);
+    assert_eq!(counts.each_ref().map(|c| load(c)), [3, 3, 3]);
+
+    memory.remove_dev_callbacks("dev-a");
+    memory.remove_dev_callbacks("dev-c");
+    let region = MemRegion::with_ram(anon_pages(1), MemRegionType::Ram);
+    memory.add_region(0x10_0000, Arc::new(region)).unwrap();
+    assert_eq!(counts.each_ref().map(|c| load(c)), [3, 5, 5]);
+}
+
+/// Records the guest physical addresses of RAM added and removed.
+#[derive(Debug)]
+struct RecordCallback {
+    hotplug: bool,
+    records: Arc<Mutex<Vec<(u64, bool)>>>,
+}
+
+impl LayoutChanged for RecordCallback {
+    fn ram_added(&self, gpa: u64, _pages: &ArcMemPages) -> Result<()> {
+        self.records.lock().push((gpa, true));
+        Ok(())
+    }
+
+    fn ram_removed(&self, gpa: u64, _pages: &ArcMemPages) -> Result<()> {
+        self.records.lock().push((gpa, false));
+        Ok(())
+    }
+
+    fn hotplug(&self) -> bool {
+        self.hotplug
+    }
+}
+
+#[test]
+fn test_hotplug_callbacks() {
+    let memory = Memory::new(Arc::new(FakeVmMemory::default()));
+    let region = MemRegion::with_ram(anon_pages(1), MemRegionType::Hotplug);
+    memory.add_region(0x10_0000, Arc::new(region)).unwrap();
+
+    let records: [Arc<Mutex<_>>; 2] = Default::default();
+    for (hotplug, records) in [true, false].into_iter().zip(&records) {
+        let records = records.clone();
+        let callback = Box::new(RecordCallback { hotplug, records });
+        memory.register_change_callback(callback).unwrap();
+    }
+    let region = MemRegion::with_ram(anon_pages(1), MemRegionType::Ram);
+    memory.add_region(0x0, Arc::new(region)).unwrap();
+    memory.remove_region(0x10_0000).unwrap();
+
+    assert_eq!(
+        *records[0].lock(),
+        [(0x10_0000, true), (0x0, true), (0x10_0000, false)]
+    );
+    assert_eq!(*records[1].lock(), [(0x0, true)]);
+}
+
+#[derive(Debug)]
+struct FakeMmio;
+
+impl Mmio for FakeMmio {
+    fn size(&self) -> u64 {
+        0x10
+    }
+
+    fn read(&self, _offset: u64, _size: u8) -> Result<u64> {
+        Ok(0)
+    }
+
+    fn write(&self, _offset: u64, _size: u8, _val: u64) -> Result<Action> {
+        Ok(Action::None)
+    }
+}
+
+#[test]
+fn test_mmio_metrics() {
+    let memory = Memory::new(Arc::new(FakeVmMemory::default()));
+    for gpa in [0x1000, 0x2000] {
+        let region = MemRegion::with_emulated(Arc::new(FakeMmio), MemRegionType::Reserved);
+        memory.add_region(gpa, Arc::new(region)).unwrap();
+    }
+
+    memory.handle_mmio(0x2004, None, 4).unwrap();
+    memory.handle_mmio(0x2008, Some(1), 4).unwrap();
+    memory.handle_mmio(0x1000, None, 1).unwrap();
+    memory.handle_mmio(0x3000, None, 1).unwrap();
+
+    assert_eq!(
+        memory.mmio_metrics(),
+        [
+            RegionMetrics {
+                start: 0x1000,
+                size: 0x10,
+                exits: 1,
+            },
+            RegionMetrics {
+                start: 0x2000,
+                size: 0x10,
+                exits: 2,
+            },
+        ]
+    );
+}
@@ -0,0 +1,102 @@
+// Copyright 2026 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Runtime counters of a VM, e.g. VM exits and VirtIO queue activities.
+
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use crate::hv::VmExit;
+
+/// Names of the counted [`VmExit`] variants.
+pub const EXIT_NAMES: [&str; 8] = [
+    "io",
+    "mmio",
+    "convert_memory",
+    "shutdown",
+    "reboot",
+    "paused",
+    "interrupted",
+    "debug",
+];
+
+/// Counts the VM exits of one VCPU by variant.
+#[derive(Debug, Default)]
+pub struct ExitCounters([AtomicU64; EXIT_NAMES.len()]);
+
+impl ExitCounters {
+    pub fn count(&self, exit: &VmExit) {
+        let index = match exit {
+            #[cfg(target_arch = "x86_64")]
+            VmExit::Io { .. } => 0,
+            VmExit::Mmio { .. } => 1,
+            VmExit::ConvertMemory { .. } => 2,
+            VmExit::Shutdown => 3,
+            VmExit::Reboot => 4,
+            VmExit::Paused => 5,
+            VmExit::Interrupted => 6,
+            VmExit::Debug => 7,
+        };
+        self.0[index].fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn get(&self) -> Vec<(&'static str, u64)> {
+        let counts = self.0.iter().map(|c| c.load(Ordering::Relaxed));
+        EXIT_NAMES.into_iter().zip(counts).collect()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VcpuMetrics {
+    pub index: u16,
+    pub exits: Vec<(&'static str, u64)>,
+}
+
+/// VM exits caused by guest accesses to an emulated region.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RegionMetrics {
+    pub start: u64,
+    pub size: u64,
+    pub exits: u64,
+}
+
+/// Counters of a VirtIO device, summed over its queues.
+///
+/// Queues offloaded to vhost or vhost-user backends are not counted.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct VirtioMetrics {
+    pub name: Arc<str>,
+    pub notifications: u64,
+    pub interrupts: u64,
+    pub requests: u64,
+    /// Bytes the device read from guest buffers.
+    pub read_bytes: u64,
+    /// Bytes the device wrote to guest buffers.
+    pub written_bytes: u64,
+    /// Statistics reported by the guest driver, e.g. memory statistics from
+    /// a balloon device.
+    pub guest_stats: Vec<(&'static str, u64)>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Metrics {
+    pub vcpus: Vec<VcpuMetrics>,
+    pub io_regions: Vec<RegionMetrics>,
+    pub mmio_regions: Vec<RegionMetrics>,
+    pub virtio: Vec<VirtioMetrics>,
+}
+
+#[cfg(test)]
+#[path = "metrics_test.rs"]
+mod tests;
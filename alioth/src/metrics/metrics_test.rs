@@ -0,0 +1,43 @@
+// Copyright 2026 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::hv::VmExit;
+use crate::metrics::ExitCounters;
+
+#[test]
+fn test_exit_counters() {
+    let counters = ExitCounters::default();
+    let mmio = VmExit::Mmio {
+        addr: 0x1000,
+        write: None,
+        size: 4,
+    };
+    counters.count(&mmio);
+    counters.count(&mmio);
+    counters.count(&VmExit::Interrupted);
+    counters.count(&VmExit::Shutdown);
+    assert_eq!(
+        counters.get(),
+        [
+            ("io", 0),
+            ("mmio", 2),
+            ("convert_memory", 0),
+            ("shutdown", 1),
+            ("reboot", 0),
+            ("paused", 0),
+            ("interrupted", 1),
+            ("debug", 0),
+        ]
+    );
+}
@@ -287,6 +287,18 @@ impl VmMemory for FakeVmMemory {
     fn reset(&self) -> hv::Result<()> {
         unreachable!()
     }
+
+    fn start_dirty_log(&self, _gpa: u64, _size: u64) -> hv::Result<()> {
+        unreachable!()
+    }
+
+    fn stop_dirty_log(&self, _gpa: u64, _size: u64) -> hv::Result<()> {
+        unreachable!()
+    }
+
+    fn get_dirty_log(&self, _gpa: u64, _size: u64) -> hv::Result<Vec<u64>> {
+        unreachable!()
+    }
 }
 
 #[test]
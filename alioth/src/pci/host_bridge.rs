@@ -12,7 +12,7 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use crate::device::{self, Pause};
+use crate::device::{self, Pause, Snapshot};
 use crate::pci;
 use crate::pci::cap::PciCapList;
 use crate::pci::config::{CommonHeader, DeviceHeader, EmulatedConfig, HeaderType, PciConfig};
@@ -58,6 +58,16 @@ impl Pause for HostBridge {
     }
 }
 
+impl Snapshot for HostBridge {
+    fn snapshot(&self) -> device::Result<Vec<u8>> {
+        Ok(vec![])
+    }
+
+    fn restore(&self, _data: &[u8]) -> device::Result<()> {
+        Ok(())
+    }
+}
+
 impl Pci for HostBridge {
     fn name(&self) -> &str {
         "host_bridge"
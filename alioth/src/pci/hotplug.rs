@@ -0,0 +1,187 @@
+// Copyright 2026 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! ACPI PCI hotplug controller for bus 0.
+//!
+//! The DSDT declares slot devices `S01`-`S31` under `PCI0` and a Generic
+//! Event Device (GED). To plug or unplug a device, the controller sets the
+//! bit of its slot in `PCIU` or `PCID` and raises the GED interrupt. The
+//! `_EVT` method of the GED acknowledges both registers and notifies the
+//! slots. The guest then ejects a slot by writing its bit to `B0EJ` from
+//! `_EJ0`.
+
+use std::fmt::{self, Debug, Formatter};
+use std::ops::RangeInclusive;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU32, Ordering};
+
+use parking_lot::Mutex;
+
+use crate::device::ioapic::IoApic;
+use crate::hv::MsiSender;
+use crate::mem::emulated::{Action, ChangeLayout, Mmio};
+use crate::mem::{self, Memory};
+use crate::pci::config::CommonHeader;
+use crate::pci::segment::PciSegment;
+use crate::pci::{Bdf, Pci};
+
+pub const HOTPLUG_PCIU: u64 = 0x0;
+pub const HOTPLUG_PCID: u64 = 0x4;
+pub const HOTPLUG_B0EJ: u64 = 0x8;
+
+/// Interrupt of the GED in the DSDT.
+pub const HOTPLUG_IRQ: u8 = 5;
+
+/// Device numbers on bus 0 that have a slot in the DSDT. Device 0 is the
+/// host bridge.
+pub const HOTPLUG_SLOTS: RangeInclusive<u8> = 1..=31;
+
+/// Called with the name of each device the guest ejects.
+pub type EjectListener = Arc<dyn Fn(&str) + Send + Sync>;
+
+struct EjectCallback {
+    devices: Vec<(Bdf, Arc<dyn Pci>)>,
+    listener: Option<EjectListener>,
+}
+
+impl Debug for EjectCallback {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("EjectCallback")
+            .field("devices", &self.devices)
+            .finish_non_exhaustive()
+    }
+}
+
+impl ChangeLayout for EjectCallback {
+    fn change(&self, memory: &Memory) -> mem::Result<()> {
+        let (offset, size) = CommonHeader::LAYOUT_COMMAND;
+        for (bdf, dev) in &self.devices {
+            // Disabling IO and memory decoding unmaps the BARs.
+            let action = dev.config().write(offset as u64, size as u8, 0)?;
+            if let Action::ChangeLayout { callback } = action {
+                callback.change(memory)?;
+            }
+            let name = dev.name();
+            memory.remove_dev_callbacks(name);
+            if let Some(listener) = &self.listener {
+                listener(name);
+            }
+            log::info!("{bdf}: ejected {name}");
+        }
+        Ok(())
+    }
+}
+
+pub struct PciHotplug<M: MsiSender> {
+    segment: Arc<PciSegment>,
+    io_apic: Arc<IoApic<M>>,
+    up: AtomicU32,
+    down: AtomicU32,
+    listener: Mutex<Option<EjectListener>>,
+}
+
+impl<M: MsiSender> Debug for PciHotplug<M> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PciHotplug")
+            .field("segment", &self.segment)
+            .field("io_apic", &self.io_apic)
+            .field("up", &self.up)
+            .field("down", &self.down)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<M: MsiSender> PciHotplug<M> {
+    pub fn new(segment: Arc<PciSegment>, io_apic: Arc<IoApic<M>>) -> Self {
+        Self {
+            segment,
+            io_apic,
+            up: AtomicU32::new(0),
+            down: AtomicU32::new(0),
+            listener: Mutex::new(None),
+        }
+    }
+
+    /// Sets the function called after the guest ejects a device.
+    pub fn set_eject_listener(&self, listener: EjectListener) {
+        *self.listener.lock() = Some(listener);
+    }
+
+    /// Notifies the guest of a new device at `bdf`.
+    pub fn plug(&self, bdf: Bdf) -> crate::hv::Result<()> {
+        self.up.fetch_or(1 << bdf.dev(), Ordering::AcqRel);
+        self.io_apic.service_pin(HOTPLUG_IRQ)
+    }
+
+    /// Asks the guest to release the device at `bdf`. The device is removed
+    /// once the guest ejects it.
+    pub fn unplug(&self, bdf: Bdf) -> crate::hv::Result<()> {
+        self.down.fetch_or(1 << bdf.dev(), Ordering::AcqRel);
+        self.io_apic.service_pin(HOTPLUG_IRQ)
+    }
+
+    fn eject(&self, slots: u32) -> Action {
+        let mut devices = vec![];
+        for slot in HOTPLUG_SLOTS {
+            if slots & (1 << slot) == 0 {
+                continue;
+            }
+            let bdf = Bdf::new(0, slot, 0);
+            if let Some(dev) = self.segment.remove(bdf) {
+                devices.push((bdf, dev));
+            }
+        }
+        if devices.is_empty() {
+            Action::None
+        } else {
+            let listener = self.listener.lock().clone();
+            let callback = Box::new(EjectCallback { devices, listener });
+            Action::ChangeLayout { callback }
+        }
+    }
+}
+
+impl<M: MsiSender> Mmio for PciHotplug<M> {
+    fn size(&self) -> u64 {
+        12
+    }
+
+    fn read(&self, offset: u64, _size: u8) -> mem::Result<u64> {
+        let val = match offset {
+            HOTPLUG_PCIU => self.up.load(Ordering::Acquire),
+            HOTPLUG_PCID => self.down.load(Ordering::Acquire),
+            _ => 0,
+        };
+        Ok(val as u64)
+    }
+
+    fn write(&self, offset: u64, _size: u8, val: u64) -> mem::Result<Action> {
+        let val = val as u32;
+        match offset {
+            HOTPLUG_PCIU => {
+                self.up.fetch_and(!val, Ordering::AcqRel);
+            }
+            HOTPLUG_PCID => {
+                self.down.fetch_and(!val, Ordering::AcqRel);
+            }
+            HOTPLUG_B0EJ => return Ok(self.eject(val)),
+            _ => log::warn!("PCI hotplug: write {val:#x} to unknown offset {offset:#x}"),
+        }
+        Ok(Action::None)
+    }
+}
+
+#[cfg(test)]
+#[path = "hotplug_test.rs"]
+mod tests;
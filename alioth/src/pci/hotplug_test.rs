@@ -0,0 +1,139 @@
+// Copyright 2025 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::sync::Arc;
+
+use assert_matches::assert_matches;
+use parking_lot::Mutex;
+
+use crate::arch::x86_64::ioapic::{IOREDTBL_BASE, IOREGSEL, IOWIN};
+use crate::device::ioapic::IoApic;
+use crate::hv::tests::TestMsiSender;
+use crate::hv::{self, MemMapOption, VmMemory};
+use crate::mem::emulated::{Action, Mmio};
+use crate::mem::{MemRegionEntry, Memory};
+use crate::pci::config::{Command, CommonHeader, offset_bar};
+use crate::pci::hotplug::{HOTPLUG_B0EJ, HOTPLUG_IRQ, HOTPLUG_PCID, HOTPLUG_PCIU, PciHotplug};
+use crate::pci::pvpanic::PvPanic;
+use crate::pci::segment::PciSegment;
+use crate::pci::{Bdf, Pci};
+
+#[derive(Debug)]
+struct FakeVmMemory;
+
+impl VmMemory for FakeVmMemory {
+    fn mem_map(&self, _gpa: u64, _size: u64, _hva: usize, _option: MemMapOption) -> hv::Result<()> {
+        unreachable!()
+    }
+
+    fn unmap(&self, _gpa: u64, _size: u64) -> hv::Result<()> {
+        unreachable!()
+    }
+
+    fn mark_private_memory(&self, _gpa: u64, _size: u64, _private: bool) -> hv::Result<()> {
+        unreachable!()
+    }
+
+    fn reset(&self) -> hv::Result<()> {
+        unreachable!()
+    }
+
+    fn start_dirty_log(&self, _gpa: u64, _size: u64) -> hv::Result<()> {
+        unreachable!()
+    }
+
+    fn stop_dirty_log(&self, _gpa: u64, _size: u64) -> hv::Result<()> {
+        unreachable!()
+    }
+
+    fn get_dirty_log(&self, _gpa: u64, _size: u64) -> hv::Result<Vec<u64>> {
+        unreachable!()
+    }
+}
+
+fn fixture_hotplug() -> (PciHotplug<TestMsiSender>, Arc<PciSegment>, TestMsiSender) {
+    let msi_sender = TestMsiSender::default();
+    let messages = msi_sender.messages.clone();
+    let io_apic = Arc::new(IoApic::new(msi_sender));
+    let select = IOREDTBL_BASE + HOTPLUG_IRQ * 2;
+    io_apic.write(IOREGSEL, 1, select as u64).unwrap();
+    io_apic.write(IOWIN, 4, 0x30).unwrap();
+
+    let segment = Arc::new(PciSegment::new());
+    let hotplug = PciHotplug::new(segment.clone(), io_apic);
+    (hotplug, segment, TestMsiSender { messages })
+}
+
+#[test]
+fn test_plug_unplug() {
+    let (hotplug, _, msi_sender) = fixture_hotplug();
+    let bdf = Bdf::new(0, 3, 0);
+
+    assert_matches!(hotplug.plug(bdf), Ok(()));
+    assert_matches!(hotplug.read(HOTPLUG_PCIU, 4), Ok(0b1000));
+    assert_eq!(msi_sender.messages.lock().len(), 1);
+    assert_matches!(hotplug.write(HOTPLUG_PCIU, 4, 0b1000), Ok(Action::None));
+    assert_matches!(hotplug.read(HOTPLUG_PCIU, 4), Ok(0));
+
+    assert_matches!(hotplug.unplug(bdf), Ok(()));
+    assert_matches!(hotplug.unplug(Bdf::new(0, 4, 0)), Ok(()));
+    assert_matches!(hotplug.read(HOTPLUG_PCID, 4), Ok(0b11000));
+    assert_eq!(msi_sender.messages.lock().len(), 3);
+    assert_matches!(hotplug.write(HOTPLUG_PCID, 4, 0b1000), Ok(Action::None));
+    assert_matches!(hotplug.read(HOTPLUG_PCID, 4), Ok(0b10000));
+}
+
+#[test]
+fn test_eject() {
+    let (hotplug, segment, _) = fixture_hotplug();
+    let memory = Memory::new(Arc::new(FakeVmMemory));
+    let ejected = Arc::new(Mutex::new(Vec::new()));
+    let names = ejected.clone();
+    hotplug.set_eject_listener(Arc::new(move |name| names.lock().push(name.to_owned())));
+    let bdf = Bdf::new(0, 3, 0);
+    let dev = Arc::new(PvPanic::new());
+    assert_matches!(segment.add(bdf, dev.clone()), None);
+
+    let config = dev.config();
+    assert_matches!(
+        config.write(offset_bar(0) as u64, 4, 0xe000_0000),
+        Ok(Action::None)
+    );
+    let (offset, size) = CommonHeader::LAYOUT_COMMAND;
+    let val = Command::MEM.bits() as u64;
+    let callback = assert_matches!(
+        config.write(offset as u64, size as u8, val),
+        Ok(Action::ChangeLayout { callback }) => callback
+    );
+    assert_matches!(callback.change(&memory), Ok(()));
+    assert_matches!(
+        &memory.mem_region_entries()[..],
+        [(0xe000_0000, MemRegionEntry { .. })]
+    );
+
+    // The host bridge and empty slots cannot be ejected.
+    assert_matches!(hotplug.write(HOTPLUG_B0EJ, 4, 0b10001), Ok(Action::None));
+
+    let callback = assert_matches!(
+        hotplug.write(HOTPLUG_B0EJ, 4, 0b1000),
+        Ok(Action::ChangeLayout { callback }) => callback
+    );
+    assert!(segment.devices().is_empty());
+    assert!(ejected.lock().is_empty());
+    assert_matches!(callback.change(&memory), Ok(()));
+    assert_matches!(&memory.mem_region_entries()[..], []);
+    assert_eq!(*ejected.lock(), ["pvpanic"]);
+    drop(callback);
+    assert_eq!(Arc::strong_count(&dev), 1);
+}
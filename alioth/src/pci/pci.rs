@@ -16,6 +16,8 @@ pub mod bus;
 pub mod cap;
 pub mod config;
 pub mod host_bridge;
+#[cfg(target_arch = "x86_64")]
+pub mod hotplug;
 pub mod pvpanic;
 pub mod segment;
 
@@ -25,7 +27,7 @@ use std::sync::Arc;
 use bitfield::bitfield;
 use snafu::Snafu;
 
-use crate::device::Pause;
+use crate::device::{Pause, Snapshot};
 use crate::errors::{DebugTrace, trace_error};
 use crate::mem::{IoRegion, MemRegion};
 
@@ -61,7 +63,7 @@ pub enum Error {
 
 pub type Result<T, E = Error> = std::result::Result<T, E>;
 
-pub trait Pci: Debug + Send + Sync + Pause + 'static {
+pub trait Pci: Debug + Send + Sync + Pause + Snapshot + 'static {
     fn name(&self) -> &str;
     fn config(&self) -> &dyn PciConfig;
     fn reset(&self) -> Result<()>;
@@ -15,7 +15,7 @@
 use std::sync::Arc;
 
 use crate::bitflags;
-use crate::device::{self, Pause};
+use crate::device::{self, Pause, Snapshot};
 use crate::mem::emulated::{Action, Mmio};
 use crate::mem::{self, MemRegion};
 use crate::pci::cap::PciCapList;
@@ -101,6 +101,16 @@ impl Pause for PvPanic {
     }
 }
 
+impl Snapshot for PvPanic {
+    fn snapshot(&self) -> device::Result<Vec<u8>> {
+        Ok(vec![])
+    }
+
+    fn restore(&self, _data: &[u8]) -> device::Result<()> {
+        Ok(())
+    }
+}
+
 impl Pci for PvPanic {
     fn name(&self) -> &str {
         "pvpanic"
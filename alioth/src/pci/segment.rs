@@ -18,7 +18,7 @@ use std::sync::Arc;
 
 use parking_lot::{Mutex, RwLock};
 
-use crate::device::{self, Pause};
+use crate::device::{self, Pause, Snapshot};
 use crate::mem::emulated::{Action, Mmio};
 use crate::pci::config::{BAR_IO, BAR_MEM64, BAR_PREFETCHABLE, PciConfig};
 use crate::pci::{Bdf, Pci, Result};
@@ -37,6 +37,16 @@ impl Pause for EmptyDevice {
     }
 }
 
+impl Snapshot for EmptyDevice {
+    fn snapshot(&self) -> device::Result<Vec<u8>> {
+        Ok(vec![])
+    }
+
+    fn restore(&self, _data: &[u8]) -> device::Result<()> {
+        Ok(())
+    }
+}
+
 impl Pci for EmptyDevice {
     fn name(&self) -> &str {
         "empty_device"
@@ -85,6 +95,29 @@ impl PciSegment {
         }
     }
 
+    /// Removes the device at `bdf`. Returns `None` if there is no device or
+    /// `bdf` is only reserved.
+    pub fn remove(&self, bdf: Bdf) -> Option<Arc<dyn Pci>> {
+        let dev = self.devices.write().remove(&bdf)?;
+        if Arc::ptr_eq(&dev, &self.placeholder) {
+            None
+        } else {
+            Some(dev)
+        }
+    }
+
+    /// Returns the devices on this segment, sorted by their BDFs.
+    pub fn devices(&self) -> Vec<(Bdf, Arc<dyn Pci>)> {
+        let devices = self.devices.read();
+        let mut list: Vec<_> = devices
+            .iter()
+            .filter(|(_, dev)| !Arc::ptr_eq(dev, &self.placeholder))
+            .map(|(bdf, dev)| (*bdf, dev.clone()))
+            .collect();
+        list.sort_by_key(|(bdf, _)| *bdf);
+        list
+    }
+
     pub fn reserve(&self, bdf: Option<Bdf>) -> Option<Bdf> {
         let mut empty_dev = self.placeholder.clone();
         match bdf {
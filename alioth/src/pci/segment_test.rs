@@ -91,3 +91,44 @@ fn test_pci_segment_next_bdf_wrapping() {
 
     assert_eq!(segment.reserve(None), Some(Bdf::new(0, 1, 0)));
 }
+
+#[test]
+fn test_pci_segment_devices() {
+    let segment = PciSegment::new();
+    assert_matches!(
+        segment.add(Bdf::new(0, 2, 0), Arc::new(PvPanic::new())),
+        None
+    );
+    assert_eq!(segment.reserve(None), Some(Bdf::new(0, 0, 0)));
+    assert_matches!(
+        segment.add(Bdf::new(0, 1, 0), Arc::new(PvPanic::new())),
+        None
+    );
+
+    let devices = segment.devices();
+    let bdfs: Vec<_> = devices.iter().map(|(bdf, _)| *bdf).collect();
+    assert_eq!(bdfs, [Bdf::new(0, 1, 0), Bdf::new(0, 2, 0)]);
+    assert_eq!(devices[0].1.name(), "pvpanic");
+}
+
+#[test]
+fn test_pci_segment_remove() {
+    let segment = PciSegment::new();
+    assert_matches!(
+        segment.add(Bdf::new(0, 1, 0), Arc::new(PvPanic::new())),
+        None
+    );
+    assert_eq!(
+        segment.reserve(Some(Bdf::new(0, 2, 0))),
+        Some(Bdf::new(0, 2, 0))
+    );
+
+    assert_matches!(segment.remove(Bdf::new(0, 1, 0)), Some(dev) if dev.name() == "pvpanic");
+    assert_matches!(segment.remove(Bdf::new(0, 1, 0)), None);
+    assert_matches!(segment.remove(Bdf::new(0, 2, 0)), None);
+    assert!(segment.devices().is_empty());
+    assert_eq!(
+        segment.reserve(Some(Bdf::new(0, 2, 0))),
+        Some(Bdf::new(0, 2, 0))
+    );
+}
@@ -91,6 +91,7 @@ bitflags! {
         MSI_EXT_DEST_ID = 1 << 15;
         HC_MAP_GPA_RANGE = 1 << 16;
         MIGRATION_CONTROL = 1 << 17;
+        CLOCKSOURCE_STABLE_BIT = 1 << 24;
     }
 }
 
@@ -112,6 +113,85 @@ pub struct KvmMsrs<const N: usize> {
 
 pub const MAX_IO_MSRS: usize = 256;
 
+#[repr(C)]
+#[derive(Debug, Copy, Clone, Default)]
+pub struct KvmClockData {
+    pub clock: u64,
+    pub flags: u32,
+    pub _pad0: u32,
+    pub realtime: u64,
+    pub host_tsc: u64,
+    pub _pad: [u32; 4],
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct KvmLapicState {
+    pub regs: [u8; 1024],
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct KvmXsave {
+    pub region: [u32; 1024],
+}
+
+#[repr(C)]
+#[derive(Debug, Default, Copy, Clone)]
+pub struct KvmXcr {
+    pub xcr: u32,
+    pub reserved: u32,
+    pub value: u64,
+}
+
+pub const KVM_MAX_XCRS: usize = 16;
+
+#[repr(C)]
+#[derive(Debug, Default, Copy, Clone)]
+pub struct KvmXcrs {
+    pub nr_xcrs: u32,
+    pub flags: u32,
+    pub xcrs: [KvmXcr; KVM_MAX_XCRS],
+    pub padding: [u64; 16],
+}
+
+bitflags! {
+    #[derive(Default)]
+    pub struct KvmVcpuEventValid(u32) {
+        NMI_PENDING = 1 << 0;
+        SIPI_VECTOR = 1 << 1;
+        SHADOW = 1 << 2;
+        SMM = 1 << 3;
+        PAYLOAD = 1 << 4;
+        TRIPLE_FAULT = 1 << 5;
+    }
+}
+
+#[repr(C)]
+#[derive(Debug, Default, Copy, Clone)]
+pub struct KvmVcpuEvents {
+    pub exception: [u8; 8],
+    pub interrupt: [u8; 4],
+    pub nmi: [u8; 4],
+    pub sipi_vector: u32,
+    pub flags: KvmVcpuEventValid,
+    pub smi: [u8; 4],
+    pub triple_fault: u8,
+    pub reserved: [u8; 26],
+    pub exception_has_payload: u8,
+    pub exception_payload: u64,
+}
+
+consts! {
+    pub struct KvmMpState(u32) {
+        RUNNABLE = 0;
+        UNINITIALIZED = 1;
+        INIT_RECEIVED = 2;
+        HALTED = 3;
+        SIPI_RECEIVED = 4;
+    }
+}
+
 bitflags! {
     #[derive(Default)]
     pub struct KvmMemFlag(u32) {
@@ -145,6 +225,14 @@ pub struct KvmUserspaceMemoryRegion2 {
     pub _pad2: [u64; 14],
 }
 
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct KvmDirtyLog {
+    pub slot: u32,
+    pub _padding: u32,
+    pub dirty_bitmap: u64,
+}
+
 bitflags! {
     #[derive(Default)]
     pub struct KvmMemoryAttribute(u64) {
@@ -269,6 +357,7 @@ consts! {
     pub struct KvmExit(u32) {
         IO = 2;
         HYPERCALL = 3;
+        DEBUG = 4;
         MMIO = 6;
         SHUTDOWN = 8;
         SYSTEM_EVENT = 24;
@@ -380,6 +469,42 @@ pub union KvmSyncRegsBlock {
     pub padding: [u8; 2048],
 }
 
+bitflags! {
+    #[derive(Default)]
+    pub struct KvmGuestDebugFlag(u32) {
+        ENABLE = 1 << 0;
+        SINGLESTEP = 1 << 1;
+        USE_SW_BP = 1 << 16;
+        #[cfg(target_arch = "x86_64")]
+        INJECT_BP = 1 << 19;
+    }
+}
+
+#[cfg(target_arch = "x86_64")]
+#[repr(C)]
+#[derive(Debug, Default, Copy, Clone)]
+pub struct KvmGuestDebugArch {
+    pub debugreg: [u64; 8],
+}
+
+#[cfg(target_arch = "aarch64")]
+#[repr(C)]
+#[derive(Debug, Default, Copy, Clone)]
+pub struct KvmGuestDebugArch {
+    pub dbg_bcr: [u64; 16],
+    pub dbg_bvr: [u64; 16],
+    pub dbg_wcr: [u64; 16],
+    pub dbg_wvr: [u64; 16],
+}
+
+#[repr(C)]
+#[derive(Debug, Default, Copy, Clone)]
+pub struct KvmGuestDebug {
+    pub control: KvmGuestDebugFlag,
+    pub pad: u32,
+    pub arch: KvmGuestDebugArch,
+}
+
 bitflags! {
     #[derive(Default)]
     pub struct KvmIrqfdFlag(u32) {
@@ -507,6 +632,7 @@ consts! {
         ARM_PSCI_0_2 = 102;
         SPLIT_IRQCHIP = 121;
         X2APIC_API = 129;
+        ENFORCE_PV_FEATURE_CPUID = 190;
         EXIT_HYPERCALL = 201;
         // GUEST_MEMFD = 234;
         // VM_TYPES = 235;
@@ -663,6 +789,7 @@ ioctl_none!(kvm_get_vcpu_mmap_size, KVMIO, 0x04);
 ioctl_writeread_buf!(kvm_get_supported_cpuid, KVMIO, 0x05, KvmCpuid2);
 
 ioctl_write_val!(kvm_create_vcpu, KVMIO, 0x41, u32);
+ioctl_write_ptr!(kvm_get_dirty_log, KVMIO, 0x42, KvmDirtyLog);
 ioctl_write_ptr!(
     kvm_set_user_memory_region,
     KVMIO,
@@ -683,18 +810,35 @@ ioctl_write_buf!(kvm_set_gsi_routing, KVMIO, 0x6a, KvmIrqRouting);
 
 ioctl_write_ptr!(kvm_irqfd, KVMIO, 0x76, KvmIrqfd);
 ioctl_write_ptr!(kvm_ioeventfd, KVMIO, 0x79, KvmIoEventFd);
+ioctl_write_ptr!(kvm_set_clock, KVMIO, 0x7b, KvmClockData);
+ioctl_read!(kvm_get_clock, KVMIO, 0x7c, KvmClockData);
 
 ioctl_none!(kvm_run, KVMIO, 0x80);
 ioctl_read!(kvm_get_regs, KVMIO, 0x81, KvmRegs);
 ioctl_write_ptr!(kvm_set_regs, KVMIO, 0x82, KvmRegs);
 ioctl_read!(kvm_get_sregs, KVMIO, 0x83, KvmSregs);
 ioctl_write_ptr!(kvm_set_sregs, KVMIO, 0x84, KvmSregs);
+ioctl_writeread_buf!(kvm_get_msrs, KVMIO, 0x88, KvmMsrs);
 ioctl_write_buf!(kvm_set_msrs, KVMIO, 0x89, KvmMsrs);
+ioctl_read!(kvm_get_lapic, KVMIO, 0x8e, KvmLapicState);
+ioctl_write_ptr!(kvm_set_lapic, KVMIO, 0x8f, KvmLapicState);
 
 ioctl_write_buf!(kvm_set_cpuid2, KVMIO, 0x90, KvmCpuid2);
 
+ioctl_read!(kvm_get_mp_state, KVMIO, 0x98, KvmMpState);
+ioctl_write_ptr!(kvm_set_mp_state, KVMIO, 0x99, KvmMpState);
+
+ioctl_write_ptr!(kvm_set_guest_debug, KVMIO, 0x9b, KvmGuestDebug);
+
+ioctl_read!(kvm_get_vcpu_events, KVMIO, 0x9f, KvmVcpuEvents);
+ioctl_write_ptr!(kvm_set_vcpu_events, KVMIO, 0xa0, KvmVcpuEvents);
+
 ioctl_write_ptr!(kvm_enable_cap, KVMIO, 0xa3, KvmEnableCap);
+ioctl_read!(kvm_get_xsave, KVMIO, 0xa4, KvmXsave);
+ioctl_write_ptr!(kvm_set_xsave, KVMIO, 0xa5, KvmXsave);
 ioctl_write_ptr!(kvm_signal_msi, KVMIO, 0xa5, KvmMsi);
+ioctl_read!(kvm_get_xcrs, KVMIO, 0xa6, KvmXcrs);
+ioctl_write_ptr!(kvm_set_xcrs, KVMIO, 0xa7, KvmXcrs);
 
 ioctl_write_ptr!(kvm_get_one_reg, KVMIO, 0xab, KvmOneReg);
 ioctl_write_ptr!(kvm_set_one_reg, KVMIO, 0xac, KvmOneReg);
@@ -0,0 +1,116 @@
+// Copyright 2026 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::io::{Error, ErrorKind, Result};
+use std::mem::{size_of, zeroed};
+
+use libc::{
+    CPU_SET, CPU_SETSIZE, SCHED_FIFO, SCHED_OTHER, SCHED_RR, cpu_set_t, pthread_setaffinity_np,
+    pthread_setschedparam, pthread_t, sched_param,
+};
+use serde::Deserialize;
+use serde_aco::Help;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Help)]
+pub enum SchedPolicy {
+    /// The default time-sharing policy, SCHED_OTHER.
+    #[default]
+    #[serde(alias = "other")]
+    Other,
+    /// Real-time first-in, first-out policy, SCHED_FIFO.
+    #[serde(alias = "fifo")]
+    Fifo,
+    /// Real-time round-robin policy, SCHED_RR.
+    #[serde(alias = "rr")]
+    RoundRobin,
+}
+
+impl SchedPolicy {
+    /// Returns whether `priority` is valid for the policy: 1 to 99 for the
+    /// real-time policies and 0 for other.
+    pub fn is_valid_priority(self, priority: u8) -> bool {
+        match self {
+            SchedPolicy::Other => priority == 0,
+            SchedPolicy::Fifo | SchedPolicy::RoundRobin => (1..=99).contains(&priority),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize, Help)]
+pub struct ThreadConfig {
+    /// Host CPUs the threads may run on, e.g. `cpus=id_cpus` with
+    /// `-o id_cpus,2,3`. [default: all]
+    #[serde(default)]
+    pub cpus: Vec<u32>,
+    /// Scheduling policy. [default: other]
+    #[serde(default)]
+    pub sched_policy: SchedPolicy,
+    /// Real-time priority from 1 to 99, only for fifo and rr. [default: 0]
+    #[serde(default)]
+    pub sched_priority: u8,
+}
+
+impl ThreadConfig {
+    pub fn apply(&self, thread: pthread_t) -> Result<()> {
+        if !self.cpus.is_empty() {
+            set_affinity(thread, &self.cpus)?;
+        }
+        if self.sched_policy != SchedPolicy::Other || self.sched_priority != 0 {
+            set_sched(thread, self.sched_policy, self.sched_priority)?;
+        }
+        Ok(())
+    }
+}
+
+fn pthread_result(ret: i32) -> Result<()> {
+    if ret == 0 {
+        Ok(())
+    } else {
+        Err(Error::from_raw_os_error(ret))
+    }
+}
+
+pub fn cpu_set(cpus: &[u32]) -> Result<cpu_set_t> {
+    let mut set: cpu_set_t = unsafe { zeroed() };
+    for &cpu in cpus {
+        if cpu as usize >= CPU_SETSIZE as usize {
+            return Err(ErrorKind::InvalidInput.into());
+        }
+        unsafe { CPU_SET(cpu as usize, &mut set) };
+    }
+    Ok(set)
+}
+
+pub fn set_affinity(thread: pthread_t, cpus: &[u32]) -> Result<()> {
+    let set = cpu_set(cpus)?;
+    let ret = unsafe { pthread_setaffinity_np(thread, size_of::<cpu_set_t>(), &set) };
+    pthread_result(ret)
+}
+
+pub fn set_sched(thread: pthread_t, policy: SchedPolicy, priority: u8) -> Result<()> {
+    let policy = match policy {
+        SchedPolicy::Other => SCHED_OTHER,
+        SchedPolicy::Fifo => SCHED_FIFO,
+        SchedPolicy::RoundRobin => SCHED_RR,
+    };
+    let param = sched_param {
+        sched_priority: priority as i32,
+    };
+    let ret = unsafe { pthread_setschedparam(thread, policy, &param) };
+    pthread_result(ret)
+}
+
+#[cfg(test)]
+#[path = "thread_test.rs"]
+mod tests;
@@ -0,0 +1,66 @@
+// Copyright 2026 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::io::{ErrorKind, Result};
+use std::mem::{size_of, zeroed};
+use std::thread;
+
+use libc::{CPU_COUNT, CPU_ISSET, cpu_set_t, pthread_getaffinity_np, pthread_self};
+use rstest::rstest;
+
+use crate::utils::thread::{SchedPolicy, ThreadConfig, pthread_result, set_affinity};
+
+/// Returns the host CPUs the current thread may run on.
+fn current_affinity() -> Result<cpu_set_t> {
+    let mut set: cpu_set_t = unsafe { zeroed() };
+    let ret = unsafe { pthread_getaffinity_np(pthread_self(), size_of::<cpu_set_t>(), &mut set) };
+    pthread_result(ret)?;
+    Ok(set)
+}
+
+#[test]
+fn test_set_affinity() {
+    let handle = thread::spawn(|| {
+        let cpus = current_affinity().unwrap();
+        let cpu = (0..).find(|cpu| unsafe { CPU_ISSET(*cpu, &cpus) }).unwrap();
+        let config = ThreadConfig {
+            cpus: vec![cpu as u32],
+            ..Default::default()
+        };
+        config.apply(unsafe { pthread_self() }).unwrap();
+
+        let cpus = current_affinity().unwrap();
+        assert_eq!(unsafe { CPU_COUNT(&cpus) }, 1);
+        assert!(unsafe { CPU_ISSET(cpu, &cpus) });
+    });
+    handle.join().unwrap();
+}
+
+#[test]
+fn test_set_affinity_invalid() {
+    let thread = unsafe { pthread_self() };
+    let err = set_affinity(thread, &[u32::MAX]).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::InvalidInput);
+}
+
+#[rstest]
+#[case(SchedPolicy::Other, 0, true)]
+#[case(SchedPolicy::Other, 1, false)]
+#[case(SchedPolicy::Fifo, 0, false)]
+#[case(SchedPolicy::Fifo, 1, true)]
+#[case(SchedPolicy::RoundRobin, 99, true)]
+#[case(SchedPolicy::RoundRobin, 100, false)]
+fn test_is_valid_priority(#[case] policy: SchedPolicy, #[case] priority: u8, #[case] valid: bool) {
+    assert_eq!(policy.is_valid_priority(priority), valid);
+}
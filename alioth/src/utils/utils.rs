@@ -14,6 +14,8 @@
 
 pub mod endian;
 #[cfg(target_os = "linux")]
+pub mod thread;
+#[cfg(target_os = "linux")]
 pub mod uds;
 
 use std::sync::atomic::{AtomicU64, Ordering};
@@ -17,7 +17,7 @@ use std::fs::{File, OpenOptions};
 use std::mem::size_of;
 use std::os::fd::AsRawFd;
 use std::path::Path;
-use std::sync::Arc;
+use std::sync::{Arc, Weak};
 
 use snafu::ResultExt;
 
@@ -31,7 +31,7 @@ use crate::vfio::{Result, error};
 
 #[derive(Debug)]
 pub struct Cdev {
-    fd: File,
+    fd: Arc<File>,
     ioas: Option<Arc<Ioas>>,
 }
 
@@ -44,7 +44,18 @@ impl Cdev {
             .context(error::AccessDevice {
                 path: path.as_ref(),
             })?;
-        Ok(Cdev { fd, ioas: None })
+        Ok(Cdev {
+            fd: Arc::new(fd),
+            ioas: None,
+        })
+    }
+
+    /// Returns a handle to move the device to other IOASes after it has
+    /// been attached to one.
+    pub fn pt_handle(&self) -> CdevPt {
+        CdevPt {
+            fd: Arc::downgrade(&self.fd),
+        }
     }
 }
 
@@ -80,6 +91,29 @@ impl Cdev {
     }
 }
 
+/// Attaches a device to a page table. It does not keep the device open.
+#[derive(Debug)]
+pub struct CdevPt {
+    fd: Weak<File>,
+}
+
+impl CdevPt {
+    /// Replaces the IOAS the device is attached to. Does nothing if the
+    /// device has been closed.
+    pub fn attach(&self, ioas: &Ioas) -> Result<()> {
+        let Some(fd) = self.fd.upgrade() else {
+            return Ok(());
+        };
+        let attach = VfioDeviceAttachIommufdPt {
+            argsz: size_of::<VfioDeviceAttachIommufdPt>() as u32,
+            pt_id: ioas.id,
+            ..Default::default()
+        };
+        unsafe { vfio_device_attach_iommufd_pt(&*fd, &attach) }?;
+        Ok(())
+    }
+}
+
 impl Device for Cdev {
     fn fd(&self) -> &File {
         &self.fd
@@ -119,4 +119,9 @@ impl LayoutChanged for UpdateContainerMapping {
         ret.box_trace(mem::error::ChangeLayout)?;
         Ok(())
     }
+
+    fn hotplug(&self) -> bool {
+        // Pinning would populate unplugged memory.
+        false
+    }
 }
@@ -12,22 +12,28 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use std::collections::HashMap;
 use std::fs::File;
+use std::io::ErrorKind;
 use std::mem::size_of;
 use std::os::fd::AsRawFd;
 use std::path::Path;
-use std::sync::Arc;
+use std::sync::{Arc, OnceLock};
 
+use parking_lot::Mutex;
 use snafu::ResultExt;
 
 use crate::errors::BoxTrace;
-use crate::mem::mapped::ArcMemPages;
+use crate::mem::mapped::{ArcMemPages, RamBus};
 use crate::mem::{self, LayoutChanged};
 use crate::sys::vfio::{
     IommuDestroy, IommuIoasAlloc, IommuIoasMap, IommuIoasMapFlag, IommuIoasUnmap, iommu_destroy,
     iommu_ioas_alloc, iommu_ioas_map, iommu_ioas_unmap,
 };
+use crate::vfio::cdev::CdevPt;
 use crate::vfio::{Result, error};
+use crate::virtio;
+use crate::virtio::dev::iommu::HostIommu;
 
 #[derive(Debug)]
 pub struct Iommu {
@@ -137,4 +143,136 @@ impl LayoutChanged for UpdateIommuIoas {
         ret.box_trace(mem::error::ChangeLayout)?;
         Ok(())
     }
+
+    fn hotplug(&self) -> bool {
+        // Pinning would populate unplugged memory.
+        false
+    }
+}
+
+/// Mirrors the domains of a virtio-iommu device to IOASes, so that VFIO
+/// devices see the same I/O virtual addresses as emulated devices.
+#[derive(Debug)]
+pub struct IoasDomains {
+    memory: Arc<RamBus>,
+    iommu: OnceLock<Arc<Iommu>>,
+    /// Devices and the IOASes mapping all guest memory they are attached to
+    /// outside of any domain.
+    devices: Mutex<HashMap<u32, (CdevPt, Arc<Ioas>)>>,
+    domains: Mutex<HashMap<u32, Ioas>>,
+    /// An IOAS without mappings, blocking the DMA of devices attached to it.
+    blocking: Mutex<Option<Ioas>>,
+}
+
+impl IoasDomains {
+    pub fn new(memory: Arc<RamBus>) -> Self {
+        IoasDomains {
+            memory,
+            iommu: OnceLock::new(),
+            devices: Mutex::new(HashMap::new()),
+            domains: Mutex::new(HashMap::new()),
+            blocking: Mutex::new(None),
+        }
+    }
+
+    /// Adds a device attached to `ioas` as `endpoint`.
+    pub fn add_device(&self, endpoint: u32, cdev: CdevPt, ioas: Arc<Ioas>) {
+        self.iommu.get_or_init(|| ioas.iommu.clone());
+        self.devices.lock().insert(endpoint, (cdev, ioas));
+    }
+
+    fn create_ioas(&self, domain: u32) -> Result<()> {
+        let Some(iommu) = self.iommu.get() else {
+            return Err(std::io::Error::from(ErrorKind::NotFound).into());
+        };
+        let ioas = Ioas::alloc_on(iommu.clone())?;
+        self.domains.lock().insert(domain, ioas);
+        Ok(())
+    }
+
+    fn map_ioas(&self, domain: u32, iova: u64, gpa: u64, size: u64) -> Result<()> {
+        let domains = self.domains.lock();
+        let Some(ioas) = domains.get(&domain) else {
+            return Err(std::io::Error::from(ErrorKind::NotFound).into());
+        };
+        let ram = self.memory.lock_layout();
+        let mut offset = 0;
+        for slice in ram.translate_iov(&[(gpa, size)])? {
+            ioas.map(slice.as_ptr() as usize, iova + offset, slice.len() as u64)?;
+            offset += slice.len() as u64;
+        }
+        Ok(())
+    }
+
+    fn unmap_ioas(&self, domain: u32, iova: u64, size: u64) -> Result<()> {
+        let domains = self.domains.lock();
+        let Some(ioas) = domains.get(&domain) else {
+            return Err(std::io::Error::from(ErrorKind::NotFound).into());
+        };
+        ioas.unmap(iova, size)
+    }
+
+    fn attach_ioas(&self, endpoint: u32, domain: Option<u32>) -> Result<()> {
+        let devices = self.devices.lock();
+        let Some((cdev, identity)) = devices.get(&endpoint) else {
+            return Ok(());
+        };
+        let domains = self.domains.lock();
+        let ioas = match domain {
+            Some(domain) => domains.get(&domain),
+            None => Some(&**identity),
+        };
+        let Some(ioas) = ioas else {
+            return Err(std::io::Error::from(ErrorKind::NotFound).into());
+        };
+        cdev.attach(ioas)
+    }
+
+    fn block_ioas(&self, endpoint: u32) -> Result<()> {
+        let devices = self.devices.lock();
+        let Some((cdev, _)) = devices.get(&endpoint) else {
+            return Ok(());
+        };
+        let Some(iommu) = self.iommu.get() else {
+            return Err(std::io::Error::from(ErrorKind::NotFound).into());
+        };
+        let blocking = &mut *self.blocking.lock();
+        let ioas = match blocking {
+            Some(ioas) => ioas,
+            None => blocking.insert(Ioas::alloc_on(iommu.clone())?),
+        };
+        cdev.attach(ioas)
+    }
+}
+
+impl HostIommu for IoasDomains {
+    fn create_domain(&self, domain: u32) -> virtio::Result<()> {
+        let ret = self.create_ioas(domain);
+        ret.context(virtio::error::HostIommu)
+    }
+
+    fn destroy_domain(&self, domain: u32) -> virtio::Result<()> {
+        self.domains.lock().remove(&domain);
+        Ok(())
+    }
+
+    fn map(&self, domain: u32, iova: u64, gpa: u64, size: u64) -> virtio::Result<()> {
+        let ret = self.map_ioas(domain, iova, gpa, size);
+        ret.context(virtio::error::HostIommu)
+    }
+
+    fn unmap(&self, domain: u32, iova: u64, size: u64) -> virtio::Result<()> {
+        let ret = self.unmap_ioas(domain, iova, size);
+        ret.context(virtio::error::HostIommu)
+    }
+
+    fn attach(&self, endpoint: u32, domain: Option<u32>) -> virtio::Result<()> {
+        let ret = self.attach_ioas(endpoint, domain);
+        ret.context(virtio::error::HostIommu)
+    }
+
+    fn block(&self, endpoint: u32) -> virtio::Result<()> {
+        let ret = self.block_ioas(endpoint);
+        ret.context(virtio::error::HostIommu)
+    }
 }
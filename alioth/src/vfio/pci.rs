@@ -26,7 +26,7 @@ use libc::{PROT_READ, PROT_WRITE};
 use parking_lot::{Mutex, RwLock};
 use zerocopy::{FromBytes, IntoBytes};
 
-use crate::device::Pause;
+use crate::device::{Pause, Snapshot};
 use crate::errors::BoxTrace;
 use crate::hv::{IrqFd, MsiSender};
 use crate::mem::emulated::{Action, Mmio, MmioBus};
@@ -352,6 +352,13 @@ where
 {
 }
 
+impl<M, D> Snapshot for VfioPciDev<M, D>
+where
+    M: MsiSender,
+    D: Device,
+{
+}
+
 impl<M, D> Pci for VfioPciDev<M, D>
 where
     D: Device,
@@ -13,15 +13,32 @@
 // limitations under the License.
 
 use std::fmt::Debug;
+#[cfg(target_os = "linux")]
+use std::fs::File;
+#[cfg(target_os = "linux")]
+use std::io::Read;
 use std::io::{IoSlice, IoSliceMut};
+#[cfg(target_os = "linux")]
+use std::os::fd::{AsRawFd, FromRawFd};
+#[cfg(target_os = "linux")]
+use std::ptr::null_mut;
 use std::sync::Arc;
 use std::sync::mpsc::Receiver;
 use std::thread::JoinHandle;
 
 use alioth_macros::Layout;
 use libc::{_SC_PAGESIZE, sysconf};
+#[cfg(target_os = "linux")]
+use libc::{
+    CLOCK_MONOTONIC, TFD_CLOEXEC, TFD_NONBLOCK, itimerspec, timerfd_create, timerfd_settime,
+    timespec,
+};
 use mio::Registry;
 use mio::event::Event;
+#[cfg(target_os = "linux")]
+use mio::unix::SourceFd;
+#[cfg(target_os = "linux")]
+use mio::{Interest, Token};
 use parking_lot::RwLock;
 use serde::Deserialize;
 use serde_aco::Help;
@@ -34,7 +51,7 @@ use crate::sync::notifier::Notifier;
 use crate::virtio::dev::{DevParam, DeviceId, Virtio, WakeEvent};
 use crate::virtio::queue::{QueueReg, Status, VirtQueue};
 use crate::virtio::worker::mio::{ActiveMio, Mio, VirtioMio};
-use crate::virtio::{FEATURE_BUILT_IN, IrqSender, Result};
+use crate::virtio::{FEATURE_BUILT_IN, IrqSender, Result, error};
 use crate::{bitflags, consts, ffi, impl_mmio_for_zerocopy, mem};
 
 #[repr(C, align(8))]
@@ -48,10 +65,48 @@ pub struct BalloonConfig {
 
 impl_mmio_for_zerocopy!(BalloonConfig);
 
+/// Sizes of a balloon in bytes and guest memory statistics.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BalloonStatus {
+    /// Memory the guest is asked to give up.
+    pub size: u64,
+    /// Memory the guest has given up.
+    pub actual: u64,
+    pub stats: Vec<(BalloonStats, u64)>,
+}
+
 #[derive(Debug)]
 pub struct BalloonConfigMmio {
     name: Arc<str>,
     config: RwLock<BalloonConfig>,
+    stats: RwLock<Vec<(BalloonStats, u64)>>,
+}
+
+impl BalloonConfigMmio {
+    /// Returns the memory statistics last reported by the guest.
+    pub fn stats(&self) -> Vec<(BalloonStats, u64)> {
+        self.stats.read().clone()
+    }
+
+    /// Returns the number of pages the guest is asked to give up.
+    pub fn num_pages(&self) -> u32 {
+        self.config.read().num_pages
+    }
+
+    /// Returns the number of pages the guest has given up.
+    pub fn actual(&self) -> u32 {
+        self.config.read().actual
+    }
+
+    /// Updates the target number of pages and returns whether it has
+    /// changed.
+    pub fn set_num_pages(&self, num_pages: u32) -> bool {
+        let config = &mut *self.config.write();
+        let old = config.num_pages;
+        config.num_pages = num_pages;
+        log::info!("{}: num_pages: {old:#x} -> {num_pages:#x}", self.name);
+        old != num_pages
+    }
 }
 
 impl Mmio for BalloonConfigMmio {
@@ -104,10 +159,23 @@ consts! {
         CACHES = 7;
         HTLB_PGALLOC = 8;
         HTLB_PGFAIL = 9;
+        OOM_KILLS = 10;
+        ALLOC_STALLS = 11;
+        ASYNC_SCANS = 12;
+        DIRECT_SCANS = 13;
+        ASYNC_RECLAIMS = 14;
+        DIRECT_RECLAIMS = 15;
     }
 }
 
-#[derive(Debug, Clone, Copy)]
+#[repr(C, packed)]
+#[derive(Debug, Clone, FromBytes, Immutable)]
+struct BalloonStat {
+    tag: u16,
+    val: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 enum BalloonQueue {
     Inflate,
     Deflate,
@@ -117,12 +185,43 @@ enum BalloonQueue {
     NotExist,
 }
 
+/// Returns the number of pages of a balloon of `size` bytes, which must not
+/// exceed `limit`.
+pub fn balloon_num_pages(size: u64, limit: u64) -> Result<u32> {
+    if size & 0xfff != 0 || size > limit || size >> 12 > u32::MAX as u64 {
+        return error::InvalidBalloonSize { size }.fail();
+    }
+    Ok((size >> 12) as u32)
+}
+
+/// Creates a timer expiring every `interval` seconds.
+#[cfg(target_os = "linux")]
+fn new_stats_timer(interval: u64) -> std::io::Result<File> {
+    let fd = ffi!(unsafe { timerfd_create(CLOCK_MONOTONIC, TFD_CLOEXEC | TFD_NONBLOCK) })?;
+    let timer = unsafe { File::from_raw_fd(fd) };
+    let period = timespec {
+        tv_sec: interval as _,
+        tv_nsec: 0,
+    };
+    let spec = itimerspec {
+        it_interval: period,
+        it_value: period,
+    };
+    ffi!(unsafe { timerfd_settime(fd, 0, &spec, null_mut()) })?;
+    Ok(timer)
+}
+
 #[derive(Debug)]
 pub struct Balloon {
     name: Arc<str>,
     config: Arc<BalloonConfigMmio>,
     feature: BalloonFeature,
     queues: [BalloonQueue; 5],
+    stats_desc: Option<u16>,
+    #[cfg(target_os = "linux")]
+    stats_interval: u64,
+    #[cfg(target_os = "linux")]
+    stats_timer: Option<File>,
 }
 
 impl Balloon {
@@ -132,22 +231,31 @@ impl Balloon {
             Err(std::io::Error::from(err))?;
         }
         let config = BalloonConfig {
-            num_pages: 0,
+            num_pages: balloon_num_pages(param.size, u64::MAX)?,
             ..Default::default()
         };
         let mut feature = BalloonFeature::all();
         if !param.free_page_reporting {
             feature.remove(BalloonFeature::PAGE_REPORTING);
         };
+        if !param.deflate_on_oom {
+            feature.remove(BalloonFeature::DEFLATE_ON_OOM);
+        }
         let name = name.into();
         Ok(Balloon {
             name: name.clone(),
             config: Arc::new(BalloonConfigMmio {
                 config: RwLock::new(config),
                 name,
+                stats: RwLock::new(Vec::new()),
             }),
             feature,
             queues: [BalloonQueue::NotExist; 5],
+            stats_desc: None,
+            #[cfg(target_os = "linux")]
+            stats_interval: param.stats_interval,
+            #[cfg(target_os = "linux")]
+            stats_timer: None,
         })
     }
 
@@ -171,6 +279,21 @@ impl Balloon {
         }
     }
 
+    fn record_stats(&self, desc: &[IoSlice]) {
+        let mut stats = vec![];
+        for buf in desc {
+            for bytes in buf.chunks(size_of::<BalloonStat>()) {
+                let Ok(stat) = BalloonStat::read_from_bytes(bytes) else {
+                    log::error!("{}: invalid stat bytes: {bytes:02x?}", self.name);
+                    continue;
+                };
+                stats.push((BalloonStats::from(stat.tag), stat.val));
+            }
+        }
+        log::trace!("{}: stats: {stats:x?}", self.name);
+        *self.config.stats.write() = stats;
+    }
+
     fn free_reporting(&self, desc: &mut [IoSliceMut]) {
         for buf in desc.iter_mut() {
             let addr = buf.as_mut_ptr();
@@ -221,13 +344,30 @@ impl Virtio for Balloon {
     fn feature(&self) -> u128 {
         FEATURE_BUILT_IN | self.feature.bits()
     }
+
+    fn snapshotable(&self) -> bool {
+        true
+    }
+
+    fn save_state(&self) -> Vec<u8> {
+        self.config.config.read().as_bytes().to_vec()
+    }
+
+    fn load_state(&mut self, data: &[u8]) -> Result<()> {
+        let Ok(config) = BalloonConfig::read_from_bytes(data) else {
+            return error::InvalidDeviceState.fail();
+        };
+        *self.config.config.write() = config;
+        Ok(())
+    }
 }
 
 impl VirtioMio for Balloon {
+    #[cfg_attr(not(target_os = "linux"), allow(unused_variables))]
     fn activate<'m, Q, S, E>(
         &mut self,
         feature: u128,
-        _active_mio: &mut ActiveMio<'_, '_, 'm, Q, S, E>,
+        active_mio: &mut ActiveMio<'_, '_, 'm, Q, S, E>,
     ) -> Result<()>
     where
         Q: VirtQueue<'m>,
@@ -241,6 +381,16 @@ impl VirtioMio for Balloon {
         if feature.contains(BalloonFeature::STATS_VQ) {
             self.queues[index] = BalloonQueue::Stats;
             index += 1;
+            // Return the stats buffer periodically so that the guest keeps
+            // refreshing its stats.
+            #[cfg(target_os = "linux")]
+            if self.stats_interval != 0 {
+                let timer = new_stats_timer(self.stats_interval)?;
+                let registry = active_mio.poll.registry();
+                let fd = timer.as_raw_fd();
+                registry.register(&mut SourceFd(&fd), Token(0), Interest::READABLE)?;
+                self.stats_timer = Some(timer);
+            }
         }
         if feature.contains(BalloonFeature::FREE_PAGE_HINT) {
             self.queues[index] = BalloonQueue::FreePage;
@@ -272,8 +422,15 @@ impl VirtioMio for Balloon {
         };
         match ballon_q {
             BalloonQueue::Stats => {
-                log::info!("{}: VQ_STATES available", self.name);
-                return Ok(());
+                // Hold the buffer until the next update so that the driver
+                // does not report stats in a busy loop.
+                return queue.handle_desc(index, active_mio.irq_sender, |chain| {
+                    self.record_stats(&chain.readable);
+                    if let Some(id) = self.stats_desc.replace(chain.id()) {
+                        log::warn!("{}: stats buffer {id} replaced", self.name);
+                    }
+                    Ok(Status::Deferred)
+                });
             }
             BalloonQueue::FreePage => {
                 log::info!("{}: VQ_FREE_PAGE available", self.name);
@@ -295,29 +452,97 @@ impl VirtioMio for Balloon {
         })
     }
 
+    #[cfg_attr(not(target_os = "linux"), allow(unused_variables))]
     fn handle_event<'a, 'm, Q, S, E>(
         &mut self,
         _event: &Event,
-        _active_mio: &mut ActiveMio<'_, '_, 'm, Q, S, E>,
+        active_mio: &mut ActiveMio<'_, '_, 'm, Q, S, E>,
     ) -> Result<()>
     where
         Q: VirtQueue<'m>,
         S: IrqSender,
         E: IoeventFd,
     {
+        #[cfg(target_os = "linux")]
+        if let Some(mut timer) = self.stats_timer.as_ref() {
+            let mut expirations = [0u8; 8];
+            timer.read_exact(&mut expirations)?;
+            self.update_stats(active_mio)?;
+        }
         Ok(())
     }
 
-    fn reset(&mut self, _registry: &Registry) {
+    fn update_stats<'m, Q, S, E>(
+        &mut self,
+        active_mio: &mut ActiveMio<'_, '_, 'm, Q, S, E>,
+    ) -> Result<()>
+    where
+        Q: VirtQueue<'m>,
+        S: IrqSender,
+        E: IoeventFd,
+    {
+        let Some(index) = self.queues.iter().position(|q| *q == BalloonQueue::Stats) else {
+            return Ok(());
+        };
+        let Some(id) = self.stats_desc.take() else {
+            return Ok(());
+        };
+        let Some(Some(queue)) = active_mio.queues.get_mut(index) else {
+            return Ok(());
+        };
+        queue.handle_deferred(id, index as u16, active_mio.irq_sender, |_| Ok(0))
+    }
+
+    #[cfg_attr(not(target_os = "linux"), allow(unused_variables))]
+    fn reset(&mut self, registry: &Registry) {
         self.queues = [BalloonQueue::NotExist; 5];
+        self.stats_desc = None;
+        #[cfg(target_os = "linux")]
+        if let Some(timer) = self.stats_timer.take() {
+            let _ = registry.deregister(&mut SourceFd(&timer.as_raw_fd()));
+        }
     }
 }
 
-#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize, Help)]
+const fn default_deflate_on_oom() -> bool {
+    true
+}
+
+#[cfg(target_os = "linux")]
+const fn default_stats_interval() -> u64 {
+    5
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Help)]
 pub struct BalloonParam {
     /// Enable free page reporting. [default: false]
     #[serde(default)]
     pub free_page_reporting: bool,
+    /// Initial size of the balloon, i.e. memory the guest is asked to give
+    /// up. [default: 0]
+    #[serde(default)]
+    pub size: u64,
+    /// Let the guest deflate the balloon when it runs out of memory.
+    /// [default: true]
+    #[serde(default = "default_deflate_on_oom")]
+    pub deflate_on_oom: bool,
+    /// Interval in seconds at which the guest refreshes its memory
+    /// statistics, or 0 to refresh them only when queried. [default: 5]
+    #[cfg(target_os = "linux")]
+    #[serde(default = "default_stats_interval")]
+    pub stats_interval: u64,
+}
+
+impl Default for BalloonParam {
+    fn default() -> Self {
+        BalloonParam {
+            free_page_reporting: false,
+            size: 0,
+            deflate_on_oom: default_deflate_on_oom(),
+            #[cfg(target_os = "linux")]
+            stats_interval: default_stats_interval(),
+        }
+    }
 }
 
 impl DevParam for BalloonParam {
@@ -327,3 +552,7 @@ impl DevParam for BalloonParam {
         Balloon::new(self, name)
     }
 }
+
+#[cfg(test)]
+#[path = "balloon_test.rs"]
+mod tests;
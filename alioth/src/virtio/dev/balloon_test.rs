@@ -0,0 +1,143 @@
+// Copyright 2026 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::io::IoSlice;
+
+use assert_matches::assert_matches;
+use rstest::rstest;
+
+use crate::mem::emulated::Mmio;
+use crate::virtio::dev::Virtio;
+use crate::virtio::dev::balloon::{
+    Balloon, BalloonFeature, BalloonParam, BalloonStats, balloon_num_pages,
+};
+use crate::virtio::{Error, FEATURE_BUILT_IN};
+
+fn stat_bytes(tag: BalloonStats, val: u64) -> Vec<u8> {
+    let mut bytes = tag.raw().to_le_bytes().to_vec();
+    bytes.extend_from_slice(&val.to_le_bytes());
+    bytes
+}
+
+#[test]
+fn test_balloon_stats() {
+    let balloon = Balloon::new(BalloonParam::default(), "balloon").unwrap();
+    assert_eq!(balloon.config().stats(), []);
+
+    let mut buf = stat_bytes(BalloonStats::MEMFREE, 0x1000);
+    buf.extend(stat_bytes(BalloonStats::MEMTOT, 0x4000));
+    let avail = stat_bytes(BalloonStats::AVAIL, 0x2000);
+    balloon.record_stats(&[IoSlice::new(&buf), IoSlice::new(&avail)]);
+    assert_eq!(
+        balloon.config().stats(),
+        [
+            (BalloonStats::MEMFREE, 0x1000),
+            (BalloonStats::MEMTOT, 0x4000),
+            (BalloonStats::AVAIL, 0x2000),
+        ]
+    );
+
+    balloon.record_stats(&[IoSlice::new(&stat_bytes(BalloonStats::SWAP_IN, 1))]);
+    assert_eq!(balloon.config().stats(), [(BalloonStats::SWAP_IN, 1)]);
+}
+
+#[test]
+fn test_balloon_size() {
+    let param = BalloonParam {
+        size: 1 << 30,
+        ..Default::default()
+    };
+    let balloon = Balloon::new(param, "balloon").unwrap();
+    let feature = BalloonFeature::all() - BalloonFeature::PAGE_REPORTING;
+    assert_eq!(balloon.feature(), FEATURE_BUILT_IN | feature.bits());
+
+    let config = balloon.config();
+    assert_eq!(config.num_pages(), 1 << 18);
+    assert_matches!(config.read(0, 4), Ok(0x40000));
+
+    assert!(config.set_num_pages(1 << 16));
+    assert!(!config.set_num_pages(1 << 16));
+    assert_matches!(config.read(0, 4), Ok(0x10000));
+
+    config.write(4, 4, 0x8000).unwrap();
+    assert_eq!(config.actual(), 0x8000);
+}
+
+#[test]
+fn test_balloon_state() {
+    let balloon = Balloon::new(BalloonParam::default(), "balloon").unwrap();
+    let config = balloon.config();
+    config.set_num_pages(0x100);
+    config.write(4, 4, 0x80).unwrap();
+    let state = balloon.save_state();
+
+    let param = BalloonParam {
+        size: 1 << 30,
+        ..Default::default()
+    };
+    let mut restored = Balloon::new(param, "balloon").unwrap();
+    restored.load_state(&state).unwrap();
+    assert_eq!(restored.config().num_pages(), 0x100);
+    assert_eq!(restored.config().actual(), 0x80);
+
+    assert_matches!(
+        restored.load_state(&state[1..]),
+        Err(Error::InvalidDeviceState { .. })
+    );
+}
+
+#[test]
+fn test_balloon_feature() {
+    let balloon = Balloon::new(BalloonParam::default(), "balloon").unwrap();
+    let feature = BalloonFeature::all() - BalloonFeature::PAGE_REPORTING;
+    assert_eq!(balloon.feature(), FEATURE_BUILT_IN | feature.bits());
+
+    let param = BalloonParam {
+        deflate_on_oom: false,
+        ..Default::default()
+    };
+    let balloon = Balloon::new(param, "balloon").unwrap();
+    let feature = feature - BalloonFeature::DEFLATE_ON_OOM;
+    assert_eq!(balloon.feature(), FEATURE_BUILT_IN | feature.bits());
+}
+
+#[rstest]
+#[case(0x800)]
+#[case(1 << 44)]
+fn test_balloon_invalid_size(#[case] size: u64) {
+    let param = BalloonParam {
+        size,
+        ..Default::default()
+    };
+    assert_matches!(
+        Balloon::new(param, "balloon"),
+        Err(Error::InvalidBalloonSize { .. })
+    );
+}
+
+#[rstest]
+#[case(0, 0, Some(0))]
+#[case(1 << 30, 1 << 30, Some(1 << 18))]
+#[case(1 << 30, (1 << 30) - 1, None)]
+#[case(0x1800, 1 << 30, None)]
+#[case(1 << 44, u64::MAX, None)]
+fn test_balloon_num_pages(#[case] size: u64, #[case] limit: u64, #[case] pages: Option<u32>) {
+    match pages {
+        Some(pages) => assert_matches!(balloon_num_pages(size, limit), Ok(p) if p == pages),
+        None => assert_matches!(
+            balloon_num_pages(size, limit),
+            Err(Error::InvalidBalloonSize { size: s, .. }) if s == size
+        ),
+    }
+}
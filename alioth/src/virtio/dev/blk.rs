@@ -304,6 +304,10 @@ impl Virtio for Block {
         self.feature.bits() | FEATURE_BUILT_IN
     }
 
+    fn snapshotable(&self) -> bool {
+        true
+    }
+
     fn spawn_worker<S, E>(
         self,
         event_rx: Receiver<WakeEvent<S, E>>,
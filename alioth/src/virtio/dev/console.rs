@@ -0,0 +1,543 @@
+// Copyright 2026 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::VecDeque;
+use std::fmt::Debug;
+use std::io::{Read, Write};
+use std::os::fd::{AsFd, AsRawFd};
+use std::path::Path;
+use std::sync::Arc;
+use std::sync::mpsc::Receiver;
+use std::thread::JoinHandle;
+
+use mio::event::Event;
+use mio::unix::SourceFd;
+use mio::{Interest, Registry, Token};
+use parking_lot::Mutex;
+use serde::Deserialize;
+use serde_aco::Help;
+use snafu::ResultExt;
+use zerocopy::{FromBytes, Immutable, IntoBytes, KnownLayout};
+
+use crate::device::console::{ConsoleBackend, ConsoleParam, ConsoleThread, UartRecv};
+use crate::hv::IoeventFd;
+use crate::mem::mapped::RamBus;
+use crate::sync::notifier::Notifier;
+use crate::virtio::dev::{DevParam, DeviceId, Virtio, WakeEvent};
+use crate::virtio::queue::{DescChain, Queue, QueueReg, Status, VirtQueue};
+use crate::virtio::worker::mio::{ActiveMio, Mio, VirtioMio};
+use crate::virtio::{FEATURE_BUILT_IN, IrqSender, Result, error};
+use crate::{bitflags, consts, impl_mmio_for_zerocopy};
+
+#[repr(C)]
+#[derive(Debug, Default, FromBytes, Immutable, IntoBytes)]
+pub struct ConsoleConfig {
+    pub cols: u16,
+    pub rows: u16,
+    pub max_nr_ports: u32,
+    pub emerg_wr: u32,
+}
+
+impl_mmio_for_zerocopy!(ConsoleConfig);
+
+bitflags! {
+    pub struct ConsoleFeature(u128) {
+        SIZE = 1 << 0;
+        MULTIPORT = 1 << 1;
+        EMERG_WRITE = 1 << 2;
+    }
+}
+
+consts! {
+    #[derive(Default, FromBytes, Immutable, IntoBytes, KnownLayout)]
+    pub struct ControlEvent(u16) {
+        DEVICE_READY = 0;
+        DEVICE_ADD = 1;
+        DEVICE_REMOVE = 2;
+        PORT_READY = 3;
+        CONSOLE_PORT = 4;
+        RESIZE = 5;
+        PORT_OPEN = 6;
+        PORT_NAME = 7;
+    }
+}
+
+#[repr(C)]
+#[derive(Debug, Default, FromBytes, Immutable, IntoBytes, KnownLayout)]
+pub struct ControlMsg {
+    pub id: u32,
+    pub event: ControlEvent,
+    pub value: u16,
+}
+
+/// Maximum number of bytes buffered for a port before the guest reads them.
+const PORT_INPUT_LIMIT: usize = 1 << 16;
+
+const QUEUE_CTRL_RX: u16 = 2;
+const QUEUE_CTRL_TX: u16 = 3;
+
+fn port_rx_queue(id: u32) -> u16 {
+    if id == 0 { 0 } else { (id as u16 + 1) << 1 }
+}
+
+fn push_bytes(state: &mut Vec<u8>, bytes: impl ExactSizeIterator<Item = u8>) {
+    state.extend((bytes.len() as u32).to_le_bytes());
+    state.extend(bytes);
+}
+
+fn pop_bytes<'a>(state: &mut &'a [u8]) -> Result<&'a [u8]> {
+    let Ok((len, rest)) = u32::read_from_prefix(state) else {
+        return error::InvalidDeviceState.fail();
+    };
+    let Some((bytes, rest)) = rest.split_at_checked(len as usize) else {
+        return error::InvalidDeviceState.fail();
+    };
+    *state = rest;
+    Ok(bytes)
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Help)]
+pub struct ConsolePortParam {
+    /// Name of the port, visible in the guest as /dev/virtio-ports/NAME.
+    pub name: Option<String>,
+    /// Expose the port to the guest as a console, e.g. /dev/hvc0.
+    #[serde(default)]
+    pub console: bool,
+    /// Backend of the port.
+    pub backend: ConsoleParam,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Help)]
+pub struct VirtioConsoleParam {
+    /// Ports of the device. Drivers without multiport support only use the
+    /// first one, as a console.
+    pub ports: Vec<ConsolePortParam>,
+}
+
+impl DevParam for VirtioConsoleParam {
+    type Device = VirtioConsole;
+
+    fn build(self, name: impl Into<Arc<str>>) -> Result<Self::Device> {
+        VirtioConsole::new(self, name)
+    }
+}
+
+#[derive(Debug)]
+struct PortRecv {
+    input: Arc<Mutex<VecDeque<u8>>>,
+    notifier: Arc<Notifier>,
+}
+
+impl UartRecv for PortRecv {
+    fn receive(&self, bytes: &[u8]) {
+        let mut input = self.input.lock();
+        let len = std::cmp::min(bytes.len(), PORT_INPUT_LIMIT.saturating_sub(input.len()));
+        input.extend(&bytes[..len]);
+        drop(input);
+        if len < bytes.len() {
+            log::warn!(
+                "port input buffer is full, dropped {} bytes",
+                bytes.len() - len
+            );
+        }
+        if let Err(e) = self.notifier.notify() {
+            log::error!("notifying port input: {e:?}");
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Port {
+    name: Option<String>,
+    console: bool,
+    backend: Arc<ConsoleBackend>,
+    input: Arc<Mutex<VecDeque<u8>>>,
+    notifier: Arc<Notifier>,
+    _thread: ConsoleThread,
+}
+
+#[derive(Debug)]
+pub struct VirtioConsole {
+    name: Arc<str>,
+    config: Arc<ConsoleConfig>,
+    ports: Vec<Port>,
+    ctrl_out: VecDeque<Vec<u8>>,
+    multiport: bool,
+}
+
+impl VirtioConsole {
+    /// Returns the indexes and the paths of the ports backed by
+    /// pseudoterminals.
+    pub fn pty_paths(&self) -> Vec<(usize, Box<Path>)> {
+        let ports = self.ports.iter().enumerate();
+        let paths = ports.filter_map(|(index, p)| Some((index, Box::from(p.backend.pty_path()?))));
+        paths.collect()
+    }
+
+    pub fn new(param: VirtioConsoleParam, name: impl Into<Arc<str>>) -> Result<Self> {
+        let name = name.into();
+        if param.ports.is_empty() {
+            return error::NoConsolePort.fail();
+        }
+        let mut ports = Vec::with_capacity(param.ports.len());
+        for (index, p) in param.ports.into_iter().enumerate() {
+            let backend = ConsoleBackend::new(&p.backend).context(error::CreateConsole)?;
+            let backend = Arc::new(backend);
+            let input = Arc::new(Mutex::new(VecDeque::new()));
+            let notifier = Arc::new(Notifier::new()?);
+            let recv = PortRecv {
+                input: input.clone(),
+                notifier: notifier.clone(),
+            };
+            let thread_name = format!("{name}-port{index}");
+            let thread = ConsoleThread::new(thread_name.into(), recv, backend.clone())
+                .context(error::CreateConsole)?;
+            ports.push(Port {
+                name: p.name,
+                console: p.console,
+                backend,
+                input,
+                notifier,
+                _thread: thread,
+            });
+        }
+        let config = ConsoleConfig {
+            max_nr_ports: ports.len() as u32,
+            ..Default::default()
+        };
+        Ok(VirtioConsole {
+            name,
+            config: Arc::new(config),
+            ports,
+            ctrl_out: VecDeque::new(),
+            multiport: false,
+        })
+    }
+
+    fn push_ctrl_msg(&mut self, id: u32, event: ControlEvent, value: u16, extra: &[u8]) {
+        let msg = ControlMsg { id, event, value };
+        let mut buf = msg.as_bytes().to_vec();
+        buf.extend_from_slice(extra);
+        self.ctrl_out.push_back(buf);
+    }
+
+    fn handle_ctrl_msg(&mut self, msg: &ControlMsg) {
+        let id = msg.id;
+        match msg.event {
+            ControlEvent::DEVICE_READY => {
+                if msg.value != 1 {
+                    log::error!("{}: driver failed to initialize", self.name);
+                    return;
+                }
+                for id in 0..self.ports.len() as u32 {
+                    self.push_ctrl_msg(id, ControlEvent::DEVICE_ADD, 0, &[]);
+                }
+            }
+            ControlEvent::PORT_READY => {
+                let Some(port) = self.ports.get(id as usize) else {
+                    log::error!("{}: invalid port {id}", self.name);
+                    return;
+                };
+                if msg.value != 1 {
+                    log::error!("{}: driver failed to add port {id}", self.name);
+                    return;
+                }
+                let console = port.console;
+                let name = port.name.clone();
+                if console {
+                    self.push_ctrl_msg(id, ControlEvent::CONSOLE_PORT, 1, &[]);
+                }
+                if let Some(name) = name {
+                    self.push_ctrl_msg(id, ControlEvent::PORT_NAME, 0, name.as_bytes());
+                }
+                self.push_ctrl_msg(id, ControlEvent::PORT_OPEN, 1, &[]);
+            }
+            ControlEvent::PORT_OPEN => {
+                let state = if msg.value == 1 { "opened" } else { "closed" };
+                log::info!("{}: guest {state} port {id}", self.name);
+            }
+            event => log::error!("{}: unknown control event {event:?}", self.name),
+        }
+    }
+
+    fn flush_ctrl_msgs<'m, Q, S>(
+        &mut self,
+        queue: &mut Queue<'_, 'm, Q>,
+        irq_sender: &S,
+    ) -> Result<()>
+    where
+        Q: VirtQueue<'m>,
+        S: IrqSender,
+    {
+        queue.handle_desc(QUEUE_CTRL_RX, irq_sender, |chain| {
+            let Some(msg) = self.ctrl_out.pop_front() else {
+                return Ok(Status::Break);
+            };
+            let len = (&*msg).read_vectored(&mut chain.writable)?;
+            Ok(Status::Done { len: len as u32 })
+        })
+    }
+
+    fn handle_ctrl_tx(&mut self, chain: &mut DescChain) -> Result<Status> {
+        let data: Vec<u8> = chain
+            .readable
+            .iter()
+            .flat_map(|b| b.iter().copied())
+            .collect();
+        match ControlMsg::read_from_prefix(&data) {
+            Ok((msg, _)) => self.handle_ctrl_msg(&msg),
+            Err(_) => log::error!("{}: invalid control message", self.name),
+        }
+        Ok(Status::Done { len: 0 })
+    }
+
+    fn handle_port_tx(&self, id: u32, chain: &mut DescChain) -> Result<Status> {
+        let Some(port) = self.ports.get(id as usize) else {
+            return Ok(Status::Done { len: 0 });
+        };
+        for buf in &chain.readable {
+            if let Err(e) = port.backend.as_ref().write_all(buf) {
+                log::error!("{}: port {id}: {e:?}", self.name);
+            }
+        }
+        Ok(Status::Done { len: 0 })
+    }
+
+    fn handle_port_rx<'m, Q, S>(
+        &self,
+        id: u32,
+        queue: &mut Queue<'_, 'm, Q>,
+        irq_sender: &S,
+    ) -> Result<()>
+    where
+        Q: VirtQueue<'m>,
+        S: IrqSender,
+    {
+        let Some(port) = self.ports.get(id as usize) else {
+            return Ok(());
+        };
+        let mut input = port.input.lock();
+        queue.handle_desc(port_rx_queue(id), irq_sender, |chain| {
+            if input.is_empty() {
+                return Ok(Status::Break);
+            }
+            let mut len = 0;
+            for buf in chain.writable.iter_mut() {
+                len += input.read(buf)?;
+            }
+            Ok(Status::Done { len: len as u32 })
+        })
+    }
+
+    fn num_active_ports(&self) -> u32 {
+        if self.multiport {
+            self.ports.len() as u32
+        } else {
+            1
+        }
+    }
+}
+
+impl Virtio for VirtioConsole {
+    type Config = ConsoleConfig;
+    type Feature = ConsoleFeature;
+
+    fn id(&self) -> DeviceId {
+        DeviceId::CONSOLE
+    }
+
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn num_queues(&self) -> u16 {
+        (self.ports.len() as u16 + 1) << 1
+    }
+
+    fn config(&self) -> Arc<ConsoleConfig> {
+        self.config.clone()
+    }
+
+    fn feature(&self) -> u128 {
+        ConsoleFeature::MULTIPORT.bits() | FEATURE_BUILT_IN
+    }
+
+    fn spawn_worker<S, E>(
+        self,
+        event_rx: Receiver<WakeEvent<S, E>>,
+        memory: Arc<RamBus>,
+        queue_regs: Arc<[QueueReg]>,
+    ) -> Result<(JoinHandle<()>, Arc<Notifier>)>
+    where
+        S: IrqSender,
+        E: IoeventFd,
+    {
+        Mio::spawn_worker(self, event_rx, memory, queue_regs)
+    }
+
+    fn snapshotable(&self) -> bool {
+        true
+    }
+
+    /// Saves the control messages not yet received by the driver and the
+    /// input of each port not yet read, each prefixed with its length.
+    fn save_state(&self) -> Vec<u8> {
+        let mut state = Vec::new();
+        state.extend((self.ctrl_out.len() as u32).to_le_bytes());
+        for msg in &self.ctrl_out {
+            push_bytes(&mut state, msg.iter().copied());
+        }
+        for port in &self.ports {
+            push_bytes(&mut state, port.input.lock().iter().copied());
+        }
+        state
+    }
+
+    fn load_state(&mut self, mut data: &[u8]) -> Result<()> {
+        let Ok((count, rest)) = u32::read_from_prefix(data) else {
+            return error::InvalidDeviceState.fail();
+        };
+        data = rest;
+        let mut ctrl_out = VecDeque::new();
+        for _ in 0..count {
+            ctrl_out.push_back(pop_bytes(&mut data)?.to_vec());
+        }
+        let mut inputs = Vec::with_capacity(self.ports.len());
+        for _ in &self.ports {
+            inputs.push(pop_bytes(&mut data)?);
+        }
+        if !data.is_empty() {
+            return error::InvalidDeviceState.fail();
+        }
+        for (port, bytes) in self.ports.iter().zip(inputs) {
+            let mut input = port.input.lock();
+            let buffered = std::mem::take(&mut *input);
+            input.extend(bytes);
+            input.extend(buffered);
+        }
+        self.ctrl_out = ctrl_out;
+        Ok(())
+    }
+}
+
+impl VirtioMio for VirtioConsole {
+    fn activate<'m, Q, S, E>(
+        &mut self,
+        feature: u128,
+        active_mio: &mut ActiveMio<'_, '_, 'm, Q, S, E>,
+    ) -> Result<()>
+    where
+        Q: VirtQueue<'m>,
+        S: IrqSender,
+        E: IoeventFd,
+    {
+        let feature = ConsoleFeature::from_bits_truncate(feature);
+        self.multiport = feature.contains(ConsoleFeature::MULTIPORT);
+        let registry = active_mio.poll.registry();
+        for (id, port) in self
+            .ports
+            .iter()
+            .take(self.num_active_ports() as usize)
+            .enumerate()
+        {
+            registry.register(
+                &mut SourceFd(&port.notifier.as_fd().as_raw_fd()),
+                Token(id),
+                Interest::READABLE,
+            )?;
+        }
+        Ok(())
+    }
+
+    fn handle_queue<'m, Q, S, E>(
+        &mut self,
+        index: u16,
+        active_mio: &mut ActiveMio<'_, '_, 'm, Q, S, E>,
+    ) -> Result<()>
+    where
+        Q: VirtQueue<'m>,
+        S: IrqSender,
+        E: IoeventFd,
+    {
+        let irq_sender = active_mio.irq_sender;
+        let queues = &mut active_mio.queues;
+        match index {
+            QUEUE_CTRL_RX | QUEUE_CTRL_TX if self.multiport => {
+                if index == QUEUE_CTRL_TX {
+                    let Some(Some(queue)) = queues.get_mut(index as usize) else {
+                        log::error!("{}: invalid queue index {index}", self.name);
+                        return Ok(());
+                    };
+                    queue.handle_desc(index, irq_sender, |chain| self.handle_ctrl_tx(chain))?;
+                }
+                let Some(Some(queue)) = queues.get_mut(QUEUE_CTRL_RX as usize) else {
+                    return Ok(());
+                };
+                self.flush_ctrl_msgs(queue, irq_sender)
+            }
+            QUEUE_CTRL_RX | QUEUE_CTRL_TX => Ok(()),
+            _ => {
+                let id = if index < 2 {
+                    0
+                } else {
+                    (index as u32 >> 1) - 1
+                };
+                if id >= self.num_active_ports() {
+                    log::error!("{}: invalid queue index {index}", self.name);
+                    return Ok(());
+                }
+                let Some(Some(queue)) = queues.get_mut(index as usize) else {
+                    log::error!("{}: invalid queue index {index}", self.name);
+                    return Ok(());
+                };
+                if index & 1 == 0 {
+                    self.handle_port_rx(id, queue, irq_sender)
+                } else {
+                    queue.handle_desc(index, irq_sender, |chain| self.handle_port_tx(id, chain))
+                }
+            }
+        }
+    }
+
+    fn handle_event<'m, Q, S, E>(
+        &mut self,
+        event: &Event,
+        active_mio: &mut ActiveMio<'_, '_, 'm, Q, S, E>,
+    ) -> Result<()>
+    where
+        Q: VirtQueue<'m>,
+        S: IrqSender,
+        E: IoeventFd,
+    {
+        let id = event.token().0 as u32;
+        let q_index = port_rx_queue(id);
+        let Some(Some(queue)) = active_mio.queues.get_mut(q_index as usize) else {
+            log::error!("{}: invalid port {id}", self.name);
+            return Ok(());
+        };
+        self.handle_port_rx(id, queue, active_mio.irq_sender)
+    }
+
+    fn reset(&mut self, registry: &Registry) {
+        for port in self.ports.iter().take(self.num_active_ports() as usize) {
+            let _ = registry.deregister(&mut SourceFd(&port.notifier.as_fd().as_raw_fd()));
+        }
+        self.ctrl_out.clear();
+        self.multiport = false;
+    }
+}
+
+#[cfg(test)]
+#[path = "console_test.rs"]
+mod tests;
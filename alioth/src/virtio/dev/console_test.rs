@@ -0,0 +1,251 @@
+// Copyright 2026 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::fs;
+use std::io::{Read, Write};
+use std::os::unix::net::UnixStream;
+use std::sync::Arc;
+use std::sync::mpsc::{self, Receiver};
+use std::time::Duration;
+
+use assert_matches::assert_matches;
+use rstest::rstest;
+use tempfile::TempDir;
+use zerocopy::{FromBytes, IntoBytes};
+
+use crate::device::console::{ConsoleParam, ConsolePathParam};
+use crate::mem::emulated::Mmio;
+use crate::mem::mapped::{Ram, RamBus};
+use crate::virtio::dev::console::{
+    ConsoleFeature, ConsolePortParam, ControlEvent, ControlMsg, VirtioConsoleParam,
+};
+use crate::virtio::dev::{DevParam, StartParam, Virtio, WakeEvent};
+use crate::virtio::queue::QueueReg;
+use crate::virtio::queue::split::SplitQueue;
+use crate::virtio::queue::tests::{GuestQueue, VirtQueueGuest};
+use crate::virtio::tests::{
+    DATA_ADDR, FakeIoeventFd, FakeIrqSender, fixture_queues, fixture_ram_bus,
+};
+use crate::virtio::{DeviceId, Error, FEATURE_BUILT_IN, VirtioFeature};
+
+fn recv_irqs(irq_rx: &Receiver<u16>, count: usize) -> Vec<u16> {
+    let mut irqs: Vec<u16> = (0..count)
+        .map(|_| irq_rx.recv_timeout(Duration::from_secs(5)).unwrap())
+        .collect();
+    irqs.sort();
+    irqs
+}
+
+fn send_ctrl<'m, Q>(ram: &Ram, guest_q: &mut GuestQueue<'m, Q>, addr: u64, msg: ControlMsg)
+where
+    Q: VirtQueueGuest<'m>,
+{
+    ram.write(addr, msg.as_bytes()).unwrap();
+    guest_q.add_desc(&[(addr, size_of::<ControlMsg>() as u32)], &[]);
+}
+
+fn recv_ctrl<'m, Q>(
+    ram: &Ram,
+    guest_q: &mut GuestQueue<'m, Q>,
+    addr: u64,
+) -> (u32, ControlEvent, u16, Vec<u8>)
+where
+    Q: VirtQueueGuest<'m>,
+{
+    let used = guest_q.get_used().unwrap();
+    let mut buf = vec![0u8; used.len as usize];
+    ram.read(addr + used.id as u64 * 0x100, &mut buf).unwrap();
+    let (msg, extra) = ControlMsg::read_from_prefix(&buf).unwrap();
+    (msg.id, msg.event, msg.value, extra.to_vec())
+}
+
+#[test]
+fn test_console_no_port() {
+    let param = VirtioConsoleParam { ports: vec![] };
+    assert!(param.build("console").is_err());
+}
+
+#[test]
+fn test_console_state() {
+    let port = ConsolePortParam {
+        name: None,
+        console: false,
+        backend: ConsoleParam::Null,
+    };
+    let param = VirtioConsoleParam {
+        ports: vec![port.clone(), port],
+    };
+    let mut dev = param.clone().build("console").unwrap();
+    dev.push_ctrl_msg(1, ControlEvent::PORT_NAME, 0, b"org.test.0");
+    dev.push_ctrl_msg(1, ControlEvent::PORT_OPEN, 1, &[]);
+    dev.ports[1].input.lock().extend(b"hello");
+    let state = dev.save_state();
+
+    let mut restored = param.clone().build("console").unwrap();
+    restored.ports[1].input.lock().extend(b" world");
+    restored.load_state(&state).unwrap();
+    assert_eq!(restored.ctrl_out, dev.ctrl_out);
+    assert!(restored.ports[0].input.lock().is_empty());
+    assert_eq!(
+        restored.ports[1]
+            .input
+            .lock()
+            .iter()
+            .copied()
+            .collect::<Vec<_>>(),
+        b"hello world"
+    );
+
+    let mut restored = param.build("console").unwrap();
+    for data in [&state[..state.len() - 1], &[&state[..], &[0]].concat()] {
+        assert_matches!(
+            restored.load_state(data),
+            Err(Error::InvalidDeviceState { .. })
+        );
+    }
+}
+
+#[rstest]
+fn test_console_multiport(fixture_ram_bus: RamBus, #[with(6)] fixture_queues: Box<[QueueReg]>) {
+    let ram_bus = Arc::new(fixture_ram_bus);
+    let ram = ram_bus.lock_layout();
+    let regs: Arc<[QueueReg]> = Arc::from(fixture_queues);
+    let mut guest_qs: Vec<_> = regs
+        .iter()
+        .map(|reg| {
+            GuestQueue::new(
+                SplitQueue::new(reg, &ram, false, None).unwrap().unwrap(),
+                reg,
+            )
+        })
+        .collect();
+
+    let temp_dir = TempDir::new().unwrap();
+    let log_path = temp_dir.path().join("port0.log");
+    let sock_path = temp_dir.path().join("port1.sock");
+    let param = VirtioConsoleParam {
+        ports: vec![
+            ConsolePortParam {
+                name: None,
+                console: true,
+                backend: ConsoleParam::File(ConsolePathParam {
+                    path: log_path.clone().into(),
+                }),
+            },
+            ConsolePortParam {
+                name: Some("org.test.0".into()),
+                console: false,
+                backend: ConsoleParam::Uds(ConsolePathParam {
+                    path: sock_path.clone().into(),
+                }),
+            },
+        ],
+    };
+    let dev = param.build("console").unwrap();
+
+    assert_matches!(dev.id(), DeviceId::CONSOLE);
+    assert_eq!(dev.num_queues(), 6);
+    assert_eq!(
+        dev.feature(),
+        ConsoleFeature::MULTIPORT.bits() | FEATURE_BUILT_IN
+    );
+    assert_matches!(dev.config().read(4, 4), Ok(2));
+
+    let (tx, rx) = mpsc::channel();
+    let (handle, notifier) = dev.spawn_worker(rx, ram_bus.clone(), regs).unwrap();
+    let (irq_tx, irq_rx) = mpsc::channel();
+    let irq_sender = Arc::new(FakeIrqSender { q_tx: irq_tx });
+    let start_param = StartParam {
+        feature: VirtioFeature::VERSION_1.bits() | ConsoleFeature::MULTIPORT.bits(),
+        irq_sender,
+        ioeventfds: Option::<Arc<[FakeIoeventFd]>>::None,
+        iotlb: None,
+        restored: None,
+        worker_cpus: [].into(),
+    };
+    tx.send(WakeEvent::Start { param: start_param }).unwrap();
+    notifier.notify().unwrap();
+
+    let notify = |q_index| {
+        tx.send(WakeEvent::Notify { q_index }).unwrap();
+        notifier.notify().unwrap();
+    };
+
+    let ctrl_rx_addr = DATA_ADDR;
+    for i in 0..4 {
+        guest_qs[2].add_desc(&[], &[(ctrl_rx_addr + i * 0x100, 0x100)]);
+    }
+
+    let ctrl_tx_addr = DATA_ADDR + 0x1000;
+
+    let ready = ControlMsg {
+        id: 0,
+        event: ControlEvent::DEVICE_READY,
+        value: 1,
+    };
+    send_ctrl(&ram, &mut guest_qs[3], ctrl_tx_addr, ready);
+    notify(3);
+    assert_eq!(recv_irqs(&irq_rx, 2), [2, 3]);
+    assert_eq!(recv_ctrl(&ram, &mut guest_qs[2], ctrl_rx_addr).0, 0);
+    let (id, event, _, _) = recv_ctrl(&ram, &mut guest_qs[2], ctrl_rx_addr);
+    assert_eq!((id, event), (1, ControlEvent::DEVICE_ADD));
+    assert!(guest_qs[3].get_used().is_some());
+
+    let port_ready = ControlMsg {
+        id: 1,
+        event: ControlEvent::PORT_READY,
+        value: 1,
+    };
+    send_ctrl(&ram, &mut guest_qs[3], ctrl_tx_addr, port_ready);
+    notify(3);
+    assert_eq!(recv_irqs(&irq_rx, 2), [2, 3]);
+    let (id, event, _, name) = recv_ctrl(&ram, &mut guest_qs[2], ctrl_rx_addr);
+    assert_eq!((id, event), (1, ControlEvent::PORT_NAME));
+    assert_eq!(name, b"org.test.0");
+    let (id, event, value, _) = recv_ctrl(&ram, &mut guest_qs[2], ctrl_rx_addr);
+    assert_eq!((id, event, value), (1, ControlEvent::PORT_OPEN, 1));
+
+    let port0_out = DATA_ADDR + 0x2000;
+    ram.write(port0_out, b"boot log").unwrap();
+    guest_qs[1].add_desc(&[(port0_out, 8)], &[]);
+    notify(1);
+    assert_eq!(recv_irqs(&irq_rx, 1), [1]);
+
+    let port1_in = DATA_ADDR + 0x3000;
+    let id_in = guest_qs[4].add_desc(&[], &[(port1_in, 0x100)]);
+    notify(4);
+    let mut client = UnixStream::connect(&sock_path).unwrap();
+    client.write_all(b"ping").unwrap();
+    assert_eq!(recv_irqs(&irq_rx, 1), [4]);
+    let used = guest_qs[4].get_used().unwrap();
+    assert_eq!((used.id, used.len), (id_in, 4));
+    let mut buf = [0u8; 4];
+    ram.read(port1_in, &mut buf).unwrap();
+    assert_eq!(&buf, b"ping");
+
+    let port1_out = DATA_ADDR + 0x4000;
+    ram.write(port1_out, b"pong").unwrap();
+    guest_qs[5].add_desc(&[(port1_out, 4)], &[]);
+    notify(5);
+    assert_eq!(recv_irqs(&irq_rx, 1), [5]);
+    let mut buf = [0u8; 4];
+    client.read_exact(&mut buf).unwrap();
+    assert_eq!(&buf, b"pong");
+
+    tx.send(WakeEvent::Shutdown).unwrap();
+    notifier.notify().unwrap();
+    handle.join().unwrap();
+
+    assert_eq!(fs::read(&log_path).unwrap(), b"boot log");
+}
@@ -14,31 +14,41 @@
 
 pub mod balloon;
 pub mod blk;
+pub mod console;
 pub mod entropy;
 #[path = "fs/fs.rs"]
 pub mod fs;
+pub mod iommu;
+pub mod mem;
 #[path = "net/net.rs"]
 pub mod net;
+pub mod pmem;
 #[path = "vsock/vsock.rs"]
 pub mod vsock;
 
 use std::fmt::Debug;
+#[cfg(target_os = "linux")]
+use std::os::unix::thread::JoinHandleExt;
 use std::sync::Arc;
-use std::sync::atomic::{AtomicU8, AtomicU16, AtomicU32};
+use std::sync::atomic::{AtomicU8, AtomicU16, AtomicU32, Ordering};
 use std::sync::mpsc::{self, Receiver, Sender};
 use std::thread::JoinHandle;
 
 use bitflags::Flags;
+#[cfg(target_os = "linux")]
+use libc::pthread_t;
+use parking_lot::Mutex;
 use snafu::ResultExt;
 
 use crate::hv::IoeventFd;
 use crate::mem::emulated::Mmio;
 use crate::mem::mapped::{Ram, RamBus};
 use crate::mem::{LayoutChanged, LayoutUpdated, MemRegion};
+use crate::metrics::VirtioMetrics;
 use crate::sync::notifier::Notifier;
 use crate::virtio::queue::packed::PackedQueue;
 use crate::virtio::queue::split::SplitQueue;
-use crate::virtio::queue::{QUEUE_SIZE_MAX, Queue, QueueReg, VirtQueue};
+use crate::virtio::queue::{Iotlb, QUEUE_SIZE_MAX, Queue, QueueReg, VirtQueue};
 #[cfg(target_os = "linux")]
 use crate::virtio::vu::conn::VuChannel;
 use crate::virtio::{DeviceId, IrqSender, Result, VirtioFeature, error};
@@ -64,6 +74,32 @@ pub trait Virtio: Debug + Send + Sync + 'static {
     fn ioeventfd_offloaded(&self, _q_index: u16) -> Result<bool> {
         Ok(false)
     }
+    /// Returns false if the device accesses guest memory outside of the
+    /// queues of its worker, e.g. through vhost, such that a virtual IOMMU
+    /// cannot translate its DMA.
+    fn dma_translatable(&self) -> bool {
+        true
+    }
+    /// Returns true if the configuration space, the queues, and
+    /// [`Virtio::save_state`] hold all the state of the device, such that it
+    /// can be saved in a snapshot.
+    fn snapshotable(&self) -> bool {
+        false
+    }
+    /// Returns the state of the device beyond the queues, e.g. settings made
+    /// by the driver. Called by the worker while the device is paused.
+    fn save_state(&self) -> Vec<u8> {
+        Vec::new()
+    }
+    /// Loads a state returned by [`Virtio::save_state`] before the worker
+    /// activates a device restored from a snapshot.
+    fn load_state(&mut self, data: &[u8]) -> Result<()> {
+        if data.is_empty() {
+            Ok(())
+        } else {
+            error::InvalidDeviceState.fail()
+        }
+    }
     fn mem_update_callback(&self) -> Option<Box<dyn LayoutUpdated>> {
         None
     }
@@ -82,6 +118,7 @@ pub struct Register {
     pub driver_feature_sel: AtomicU8,
     pub queue_sel: AtomicU16,
     pub status: AtomicU8,
+    pub config_generation: AtomicU8,
 }
 
 const TOKEN_WARKER: u64 = 1 << 63;
@@ -95,6 +132,21 @@ where
     pub(crate) feature: u128,
     pub(crate) irq_sender: Arc<S>,
     pub(crate) ioeventfds: Option<Arc<[E]>>,
+    /// Translates DMA addresses if the device is behind a virtual IOMMU.
+    pub(crate) iotlb: Option<Arc<dyn Iotlb>>,
+    /// State to resume from, if the device is restored from a snapshot.
+    pub(crate) restored: Option<PausedState>,
+    /// Host CPUs for threads created by the worker, e.g. io_uring async
+    /// workers. Empty for all CPUs.
+    pub(crate) worker_cpus: Arc<[u32]>,
+}
+
+/// Positions of the queues and the state of a paused device, which are
+/// empty if the device has not started.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PausedState {
+    pub queue_index: Box<[u16]>,
+    pub dev_state: Box<[u8]>,
 }
 
 #[derive(Debug, Clone)]
@@ -115,6 +167,14 @@ where
         param: StartParam<S, E>,
     },
     Reset,
+    /// Asks the device to refresh the statistics reported by the guest.
+    UpdateStats,
+    /// Asks the worker to complete the buffers it has taken and to wait for
+    /// [`WakeEvent::Resume`]. The worker replies with its state.
+    Pause {
+        tx: Sender<PausedState>,
+    },
+    Resume,
 }
 
 #[derive(Debug, PartialEq, Eq)]
@@ -146,9 +206,14 @@ where
     pub device_feature: u128,
     pub queue_regs: Arc<[QueueReg]>,
     pub shared_mem_regions: Option<Arc<MemRegion>>,
+    pub snapshotable: bool,
+    /// Host CPUs of the worker thread. Empty for all CPUs.
+    pub worker_cpus: Arc<[u32]>,
     pub notifier: Arc<Notifier>,
     pub event_tx: Sender<WakeEvent<S, E>>,
     worker_handle: Option<JoinHandle<()>>,
+    /// State reported by the worker while it is paused.
+    paused: Mutex<Option<PausedState>>,
 }
 
 impl<S, E> VirtioDevice<S, E>
@@ -168,11 +233,19 @@ where
         Ok(())
     }
 
+    /// Returns the worker thread, if it is running.
+    #[cfg(target_os = "linux")]
+    pub fn worker_thread(&self) -> Option<pthread_t> {
+        let handle = self.worker_handle.as_ref()?;
+        Some(handle.as_pthread_t())
+    }
+
     pub fn new<D>(
         name: impl Into<Arc<str>>,
         dev: D,
         memory: Arc<RamBus>,
         restricted_memory: bool,
+        worker_cpus: Arc<[u32]>,
     ) -> Result<Self>
     where
         D: Virtio,
@@ -194,6 +267,7 @@ where
         let queue_regs = queue_regs.collect::<Arc<_>>();
 
         let shared_mem_regions = dev.shared_mem_regions();
+        let snapshotable = dev.snapshotable();
         let (event_tx, event_rx) = mpsc::channel();
         let (handle, notifier) = dev.spawn_worker(event_rx, memory, queue_regs.clone())?;
         log::debug!(
@@ -211,9 +285,85 @@ where
             notifier,
             device_config,
             shared_mem_regions,
+            snapshotable,
+            worker_cpus,
+            paused: Mutex::new(None),
         };
         Ok(virtio_dev)
     }
+
+    /// Returns the counters of the device, summed over its queues.
+    pub fn metrics(&self) -> VirtioMetrics {
+        let mut metrics = VirtioMetrics {
+            name: self.name.clone(),
+            ..Default::default()
+        };
+        for reg in self.queue_regs.iter() {
+            let stats = &reg.stats;
+            metrics.notifications += stats.notifications.load(Ordering::Relaxed);
+            metrics.interrupts += stats.interrupts.load(Ordering::Relaxed);
+            metrics.requests += stats.requests.load(Ordering::Relaxed);
+            metrics.read_bytes += stats.read_bytes.load(Ordering::Relaxed);
+            metrics.written_bytes += stats.written_bytes.load(Ordering::Relaxed);
+        }
+        metrics
+    }
+
+    /// Asks the device to refresh the statistics reported by the guest.
+    pub fn update_stats(&self) {
+        if self.event_tx.send(WakeEvent::UpdateStats).is_err() {
+            return;
+        }
+        if let Err(e) = self.notifier.notify() {
+            log::error!("{}: failed to notify the worker: {e:?}", self.name);
+        }
+    }
+
+    /// Asks the worker to process the buffers of a queue, e.g. buffers it
+    /// has left in the queue.
+    pub fn notify_queue(&self, q_index: u16) {
+        if self.event_tx.send(WakeEvent::Notify { q_index }).is_err() {
+            return;
+        }
+        if let Err(e) = self.notifier.notify() {
+            log::error!("{}: failed to notify the worker: {e:?}", self.name);
+        }
+    }
+
+    /// Stops the worker after it completes the buffers it has taken, so
+    /// that it no longer accesses guest memory.
+    pub fn pause(&self) -> Result<()> {
+        let mut paused = self.paused.lock();
+        if paused.is_some() {
+            return Ok(());
+        }
+        let (tx, rx) = mpsc::channel();
+        if self.event_tx.send(WakeEvent::Pause { tx }).is_err() {
+            return error::WorkerStopped.fail();
+        }
+        self.notifier.notify()?;
+        let Ok(state) = rx.recv() else {
+            return error::WorkerStopped.fail();
+        };
+        *paused = Some(state);
+        Ok(())
+    }
+
+    pub fn resume(&self) -> Result<()> {
+        if self.paused.lock().take().is_none() {
+            return Ok(());
+        }
+        // The paused worker blocks on the channel and needs no notification.
+        if self.event_tx.send(WakeEvent::Resume).is_err() {
+            return error::WorkerStopped.fail();
+        }
+        Ok(())
+    }
+
+    /// Returns the state of the worker if it is paused.
+    pub fn paused_state(&self) -> Option<PausedState> {
+        self.paused.lock().clone()
+    }
 }
 
 impl<S, E> Drop for VirtioDevice<S, E>
@@ -252,6 +402,12 @@ pub trait ActiveBackend<D: Virtio> {
     type Event: BackendEvent;
     fn handle_event(&mut self, dev: &mut D, event: &Self::Event) -> Result<()>;
     fn handle_queue(&mut self, dev: &mut D, index: u16) -> Result<()>;
+    fn update_stats(&mut self, dev: &mut D) -> Result<()>;
+    /// Waits for the buffers the device has taken and returns the positions
+    /// of the queues.
+    fn quiesce(&mut self, dev: &mut D) -> Result<Box<[u16]>>;
+    /// Continues processing the queues after [`ActiveBackend::quiesce`].
+    fn resume(&mut self, dev: &mut D) -> Result<()>;
 }
 
 #[derive(Debug)]
@@ -279,7 +435,13 @@ where
     {
         while let Ok(event) = self.event_rx.try_recv() {
             match event {
-                WakeEvent::Notify { q_index } => backend.handle_queue(&mut self.dev, q_index)?,
+                WakeEvent::Notify { q_index } => {
+                    if let Some(reg) = self.queue_regs.get(q_index as usize) {
+                        reg.stats.notifications.fetch_add(1, Ordering::Relaxed);
+                    }
+                    backend.handle_queue(&mut self.dev, q_index)?
+                }
+                WakeEvent::UpdateStats => backend.update_stats(&mut self.dev)?,
                 WakeEvent::Shutdown => {
                     self.state = WorkerState::Shutdown;
                     break;
@@ -294,15 +456,54 @@ where
                     self.state = WorkerState::Pending;
                     break;
                 }
+                WakeEvent::Pause { tx } => {
+                    let queue_index = backend.quiesce(&mut self.dev)?;
+                    let dev_state = self.dev.save_state().into();
+                    let _ = tx.send(PausedState {
+                        queue_index,
+                        dev_state,
+                    });
+                    self.wait_resume();
+                    if self.state != WorkerState::Running {
+                        break;
+                    }
+                    backend.resume(&mut self.dev)?;
+                }
+                WakeEvent::Resume => {
+                    log::error!("{}: device is not paused", self.dev.name())
+                }
             }
         }
         Ok(())
     }
 
+    /// Blocks until the worker is resumed, reset, or shut down.
+    fn wait_resume(&mut self) {
+        for wake_event in self.event_rx.iter() {
+            match wake_event {
+                WakeEvent::Resume => return,
+                WakeEvent::Reset => {
+                    self.state = WorkerState::Pending;
+                    return;
+                }
+                WakeEvent::Shutdown => break,
+                #[cfg(target_os = "linux")]
+                WakeEvent::VuChannel { channel } => self.dev.set_vu_channel(channel),
+                event => {
+                    log::error!("{}: unexpected {event:?} while paused", self.dev.name())
+                }
+            }
+        }
+        self.state = WorkerState::Shutdown;
+    }
+
     fn wait_start(&mut self) -> Option<StartParam<S, E>> {
         for wake_event in self.event_rx.iter() {
             match wake_event {
-                WakeEvent::Reset => {}
+                WakeEvent::Reset | WakeEvent::UpdateStats | WakeEvent::Resume => {}
+                WakeEvent::Pause { tx } => {
+                    let _ = tx.send(PausedState::default());
+                }
                 WakeEvent::Start { param } => {
                     self.state = WorkerState::Running;
                     return Some(param);
@@ -393,27 +594,48 @@ where
         };
         let memory = self.context.memory.clone();
         let ram = memory.lock_layout();
-        let feature = param.feature & !VirtioFeature::ACCESS_PLATFORM.bits();
         let queue_regs = self.context.queue_regs.clone();
-        let feature = VirtioFeature::from_bits_retain(feature);
+        let feature = VirtioFeature::from_bits_retain(param.feature);
         let event_idx = feature.contains(VirtioFeature::EVENT_IDX);
+        let iotlb = if feature.contains(VirtioFeature::ACCESS_PLATFORM) {
+            param.iotlb.clone()
+        } else {
+            None
+        };
+        if let Some(restored) = &param.restored {
+            self.context.dev.load_state(&restored.dev_state)?;
+        }
+        let queue_index = |index: usize| {
+            let restored = param.restored.as_ref()?;
+            restored.queue_index.get(index).copied()
+        };
         if feature.contains(VirtioFeature::RING_PACKED) {
-            let new_queue = |reg| {
-                let Some(split_queue) = PackedQueue::new(reg, &ram, event_idx)? else {
+            let new_queue = |(index, reg)| {
+                let Some(split_queue) = PackedQueue::new(reg, &ram, event_idx, iotlb.clone())?
+                else {
                     return Ok(None);
                 };
-                Ok(Some(Queue::new(split_queue, reg, &ram)))
+                let mut queue = Queue::new(split_queue, reg, &ram);
+                if let Some(position) = queue_index(index) {
+                    queue.set_index(position);
+                }
+                Ok(Some(queue))
             };
-            let queues: Result<Box<_>> = queue_regs.iter().map(new_queue).collect();
+            let queues: Result<Box<_>> = queue_regs.iter().enumerate().map(new_queue).collect();
             self.event_loop(&mut (queues?), &ram, &param)?;
         } else {
-            let new_queue = |reg| {
-                let Some(split_queue) = SplitQueue::new(reg, &ram, event_idx)? else {
+            let new_queue = |(index, reg)| {
+                let Some(split_queue) = SplitQueue::new(reg, &ram, event_idx, iotlb.clone())?
+                else {
                     return Ok(None);
                 };
-                Ok(Some(Queue::new(split_queue, reg, &ram)))
+                let mut queue = Queue::new(split_queue, reg, &ram);
+                if let Some(position) = queue_index(index) {
+                    queue.set_index(position);
+                }
+                Ok(Some(queue))
             };
-            let queues: Result<Box<_>> = queue_regs.iter().map(new_queue).collect();
+            let queues: Result<Box<_>> = queue_regs.iter().enumerate().map(new_queue).collect();
             self.event_loop(&mut (queues?), &ram, &param)?;
         };
         self.backend.reset(&mut self.context.dev)?;
@@ -116,6 +116,10 @@ impl Virtio for Entropy {
     fn feature(&self) -> u128 {
         FEATURE_BUILT_IN
     }
+
+    fn snapshotable(&self) -> bool {
+        true
+    }
 }
 
 impl VirtioMio for Entropy {
@@ -53,14 +53,18 @@ fn entropy_test(fixture_ram_bus: RamBus, fixture_queues: Box<[QueueReg]>) {
     let regs: Arc<[QueueReg]> = Arc::from(fixture_queues);
 
     let mut guest_q = GuestQueue::new(
-        SplitQueue::new(&regs[0], &ram, false).unwrap().unwrap(),
+        SplitQueue::new(&regs[0], &ram, false, None)
+            .unwrap()
+            .unwrap(),
         &regs[0],
     );
 
     let buf0_addr = DATA_ADDR;
     let buf1_addr = buf0_addr + (4 << 10);
+    let buf2_addr = buf1_addr + (4 << 10);
     let s0 = "Hello, World!";
     let s1 = "Goodbye, World!";
+    let s2 = "Hello again!";
 
     let temp_dir = TempDir::new().unwrap();
     let pipe_path = temp_dir.path().join("urandom");
@@ -70,7 +74,7 @@ fn entropy_test(fixture_ram_bus: RamBus, fixture_queues: Box<[QueueReg]>) {
     let param = EntropyParam {
         source: Some(pipe_path.clone().into()),
     };
-    let dev = param.build("entropy").unwrap();
+    let dev = param.clone().build("entropy").unwrap();
 
     assert_matches!(dev.id(), DeviceId::ENTROPY);
     assert_eq!(dev.name(), "entropy");
@@ -79,13 +83,16 @@ fn entropy_test(fixture_ram_bus: RamBus, fixture_queues: Box<[QueueReg]>) {
     assert_eq!(dev.feature(), FEATURE_BUILT_IN);
 
     let (tx, rx) = mpsc::channel();
-    let (handle, notifier) = dev.spawn_worker(rx, ram_bus.clone(), regs).unwrap();
+    let (handle, notifier) = dev.spawn_worker(rx, ram_bus.clone(), regs.clone()).unwrap();
     let (irq_tx, irq_rx) = mpsc::channel();
     let irq_sender = Arc::new(FakeIrqSender { q_tx: irq_tx });
     let start_param = StartParam {
         feature: VirtioFeature::VERSION_1.bits(),
         irq_sender,
         ioeventfds: Option::<Arc<[FakeIoeventFd]>>::None,
+        iotlb: None,
+        restored: None,
+        worker_cpus: [].into(),
     };
     tx.send(WakeEvent::Start { param: start_param }).unwrap();
     notifier.notify().unwrap();
@@ -121,11 +128,51 @@ fn entropy_test(fixture_ram_bus: RamBus, fixture_queues: Box<[QueueReg]>) {
     assert_eq!(used1.id, id1);
     assert_eq!(used1.len, s1.len() as u32);
 
+    let (pause_tx, pause_rx) = mpsc::channel();
+    tx.send(WakeEvent::Pause { tx: pause_tx }).unwrap();
+    notifier.notify().unwrap();
+    let state = pause_rx.recv_timeout(Duration::from_secs(1)).unwrap();
+    assert_eq!(&*state.queue_index, [2]);
+    assert!(state.dev_state.is_empty());
+    tx.send(WakeEvent::Resume).unwrap();
+
+    tx.send(WakeEvent::Shutdown).unwrap();
+    notifier.notify().unwrap();
+    handle.join().unwrap();
+
+    // A restored worker resumes from the saved queue position.
+    let dev = param.build("entropy").unwrap();
+    let (tx, rx) = mpsc::channel();
+    let (handle, notifier) = dev.spawn_worker(rx, ram_bus.clone(), regs).unwrap();
+    let (irq_tx, irq_rx) = mpsc::channel();
+    let irq_sender = Arc::new(FakeIrqSender { q_tx: irq_tx });
+    let start_param = StartParam {
+        feature: VirtioFeature::VERSION_1.bits(),
+        irq_sender,
+        ioeventfds: Option::<Arc<[FakeIoeventFd]>>::None,
+        iotlb: None,
+        restored: Some(state),
+        worker_cpus: [].into(),
+    };
+    tx.send(WakeEvent::Start { param: start_param }).unwrap();
+    notifier.notify().unwrap();
+
+    writer.write_all(s2.as_bytes()).unwrap();
+    writer.flush().unwrap();
+    let id2 = guest_q.add_desc(&[], &[(buf2_addr, 4 << 10)]);
+    tx.send(WakeEvent::Notify { q_index: 0 }).unwrap();
+    notifier.notify().unwrap();
+    assert_eq!(irq_rx.recv_timeout(Duration::from_secs(1)).unwrap(), 0);
+
+    let used2 = guest_q.get_used().unwrap();
+    assert_eq!(used2.id, id2);
+    assert_eq!(used2.len, s2.len() as u32);
+
     tx.send(WakeEvent::Shutdown).unwrap();
     notifier.notify().unwrap();
     handle.join().unwrap();
 
-    for (s, addr) in [(s0, buf0_addr), (s1, buf1_addr)] {
+    for (s, addr) in [(s0, buf0_addr), (s1, buf1_addr), (s2, buf2_addr)] {
         let mut buf = vec![0u8; s.len()];
         ram.read(addr, &mut buf).unwrap();
         assert_eq!(String::from_utf8_lossy(buf.as_slice()), s);
@@ -167,6 +167,10 @@ impl Virtio for VuFs {
         self.frontend.ioeventfd_offloaded(q_index)
     }
 
+    fn dma_translatable(&self) -> bool {
+        false
+    }
+
     fn shared_mem_regions(&self) -> Option<Arc<MemRegion>> {
         let dax_region = self.dax_region.as_ref()?;
         Some(Arc::new(MemRegion::with_dev_mem(
@@ -0,0 +1,831 @@
+// Copyright 2026 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fmt::Debug;
+use std::mem::offset_of;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::Receiver;
+use std::thread::JoinHandle;
+
+use mio::Registry;
+use mio::event::Event;
+use parking_lot::RwLock;
+use serde::Deserialize;
+use serde_aco::Help;
+use zerocopy::{FromBytes, Immutable, IntoBytes};
+
+#[cfg(target_arch = "x86_64")]
+use crate::arch::layout::APIC_START;
+#[cfg(target_arch = "aarch64")]
+use crate::arch::layout::GIC_MSI_START;
+use crate::hv::IoeventFd;
+use crate::mem::emulated::{Action, Mmio};
+use crate::mem::mapped::RamBus;
+use crate::sync::notifier::Notifier;
+use crate::virtio::dev::{DevParam, DeviceId, Virtio, WakeEvent};
+use crate::virtio::queue::{Iotlb, QueueReg, Status, VirtQueue};
+use crate::virtio::worker::mio::{ActiveMio, Mio, VirtioMio};
+use crate::virtio::{FEATURE_BUILT_IN, IrqSender, Result, error};
+use crate::{bitflags, consts, impl_mmio_for_zerocopy, mem};
+
+#[repr(C, align(8))]
+#[derive(Debug, Clone, Default, FromBytes, IntoBytes, Immutable)]
+pub struct IommuConfig {
+    page_size_mask: u64,
+    input_start: u64,
+    input_end: u64,
+    domain_start: u32,
+    domain_end: u32,
+    probe_size: u32,
+    bypass: u8,
+    _reserved: [u8; 3],
+}
+
+impl_mmio_for_zerocopy!(IommuConfig);
+
+const PAGE_SIZE_MASK: u64 = !0xfff;
+const INPUT_END: u64 = (1 << 48) - 1;
+const PROBE_SIZE: u32 = 512;
+
+#[cfg(target_arch = "x86_64")]
+const MSI_START: u64 = APIC_START;
+#[cfg(target_arch = "x86_64")]
+const MSI_END: u64 = APIC_START + (1 << 20) - 1;
+#[cfg(target_arch = "aarch64")]
+const MSI_START: u64 = GIC_MSI_START;
+#[cfg(target_arch = "aarch64")]
+const MSI_END: u64 = GIC_MSI_START + (128 << 10) - 1;
+
+#[derive(Debug)]
+pub struct IommuConfigMmio {
+    name: Arc<str>,
+    domains: Arc<IommuDomains>,
+}
+
+impl Mmio for IommuConfigMmio {
+    fn size(&self) -> u64 {
+        size_of::<IommuConfig>() as u64
+    }
+
+    fn read(&self, offset: u64, size: u8) -> mem::Result<u64> {
+        let config = IommuConfig {
+            page_size_mask: PAGE_SIZE_MASK,
+            input_end: INPUT_END,
+            domain_end: u32::MAX,
+            probe_size: PROBE_SIZE,
+            bypass: self.domains.bypass() as u8,
+            ..Default::default()
+        };
+        Mmio::read(&config, offset, size)
+    }
+
+    fn write(&self, offset: u64, size: u8, val: u64) -> mem::Result<Action> {
+        if offset == offset_of!(IommuConfig, bypass) as u64 && size == 1 {
+            self.domains.set_bypass(val != 0);
+        } else {
+            log::error!(
+                "{}: write to read-only config: offset = {offset:#x}, size = {size}, val = {val:#x}",
+                self.name
+            );
+        }
+        Ok(Action::None)
+    }
+}
+
+bitflags! {
+    pub struct IommuFeature(u128) {
+        INPUT_RANGE = 1 << 0;
+        DOMAIN_RANGE = 1 << 1;
+        MAP_UNMAP = 1 << 2;
+        BYPASS = 1 << 3;
+        PROBE = 1 << 4;
+        MMIO = 1 << 5;
+        BYPASS_CONFIG = 1 << 6;
+    }
+}
+
+consts! {
+    pub struct IommuReqType(u8) {
+        ATTACH = 1;
+        DETACH = 2;
+        MAP = 3;
+        UNMAP = 4;
+        PROBE = 5;
+    }
+}
+
+consts! {
+    pub struct IommuStatus(u8) {
+        OK = 0;
+        IOERR = 1;
+        UNSUPP = 2;
+        DEVERR = 3;
+        INVAL = 4;
+        RANGE = 5;
+        NOENT = 6;
+        FAULT = 7;
+        NOMEM = 8;
+    }
+}
+
+bitflags! {
+    pub struct IommuAttachFlag(u32) {
+        BYPASS = 1 << 0;
+    }
+}
+
+bitflags! {
+    pub struct IommuMapFlag(u32) {
+        READ = 1 << 0;
+        WRITE = 1 << 1;
+        MMIO = 1 << 2;
+    }
+}
+
+consts! {
+    pub struct IommuProbeType(u16) {
+        NONE = 0;
+        RESV_MEM = 1;
+    }
+}
+
+consts! {
+    pub struct IommuResvMemType(u8) {
+        RESERVED = 0;
+        MSI = 1;
+    }
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default, FromBytes, IntoBytes, Immutable)]
+pub struct IommuReqHead {
+    pub type_: u8,
+    pub reserved: [u8; 3],
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Default, FromBytes, IntoBytes, Immutable)]
+pub struct IommuReqTail {
+    pub status: u8,
+    pub reserved: [u8; 3],
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Default, FromBytes, IntoBytes, Immutable)]
+pub struct IommuReqAttach {
+    pub head: IommuReqHead,
+    pub domain: u32,
+    pub endpoint: u32,
+    pub flags: u32,
+    pub reserved: [u8; 4],
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Default, FromBytes, IntoBytes, Immutable)]
+pub struct IommuReqDetach {
+    pub head: IommuReqHead,
+    pub domain: u32,
+    pub endpoint: u32,
+    pub reserved: [u8; 8],
+}
+
+#[repr(C, packed)]
+#[derive(Debug, Clone, Copy, Default, FromBytes, IntoBytes, Immutable)]
+pub struct IommuReqMap {
+    pub head: IommuReqHead,
+    pub domain: u32,
+    pub virt_start: u64,
+    pub virt_end: u64,
+    pub phys_start: u64,
+    pub flags: u32,
+}
+
+#[repr(C, packed)]
+#[derive(Debug, Clone, Copy, Default, FromBytes, IntoBytes, Immutable)]
+pub struct IommuReqUnmap {
+    pub head: IommuReqHead,
+    pub domain: u32,
+    pub virt_start: u64,
+    pub virt_end: u64,
+    pub reserved: [u8; 4],
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, FromBytes, IntoBytes, Immutable)]
+pub struct IommuReqProbe {
+    pub head: IommuReqHead,
+    pub endpoint: u32,
+    pub reserved: [u8; 64],
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Default, FromBytes, IntoBytes, Immutable)]
+pub struct IommuProbeResvMem {
+    pub type_: u16,
+    pub length: u16,
+    pub subtype: u8,
+    pub reserved: [u8; 3],
+    pub start: u64,
+    pub end: u64,
+}
+
+/// Mirrors the domains of a virtio-iommu device to the host IOMMU, for
+/// endpoints whose DMA does not go through the VMM, e.g. VFIO devices.
+pub trait HostIommu: Debug + Send + Sync + 'static {
+    fn create_domain(&self, domain: u32) -> Result<()>;
+    fn destroy_domain(&self, domain: u32) -> Result<()>;
+    fn map(&self, domain: u32, iova: u64, gpa: u64, size: u64) -> Result<()>;
+    fn unmap(&self, domain: u32, iova: u64, size: u64) -> Result<()>;
+    /// Attaches `endpoint` to `domain`, or lets it access all guest memory
+    /// if `domain` is `None`.
+    fn attach(&self, endpoint: u32, domain: Option<u32>) -> Result<()>;
+    /// Blocks all DMA of `endpoint`.
+    fn block(&self, endpoint: u32) -> Result<()>;
+}
+
+#[derive(Debug, Default)]
+struct Endpoint {
+    domain: Option<u32>,
+    host: bool,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Mapping {
+    gpa: u64,
+    size: u64,
+    flags: IommuMapFlag,
+}
+
+#[derive(Debug, Default)]
+struct Domain {
+    bypass: bool,
+    mappings: BTreeMap<u64, Mapping>,
+    endpoints: BTreeSet<u32>,
+    /// Whether the domain has been created in the host IOMMU.
+    mirrored: bool,
+}
+
+#[derive(Debug, Default)]
+struct IommuState {
+    endpoints: HashMap<u32, Endpoint>,
+    domains: HashMap<u32, Domain>,
+}
+
+/// The address spaces programmed by the guest through a virtio-iommu
+/// device, shared by the device and its endpoints.
+#[derive(Debug)]
+pub struct IommuDomains {
+    name: Arc<str>,
+    default_bypass: bool,
+    bypass: AtomicBool,
+    state: RwLock<IommuState>,
+    host: Option<Arc<dyn HostIommu>>,
+}
+
+impl IommuDomains {
+    fn new(name: Arc<str>, bypass: bool, host: Option<Arc<dyn HostIommu>>) -> Self {
+        IommuDomains {
+            name,
+            default_bypass: bypass,
+            bypass: AtomicBool::new(bypass),
+            state: RwLock::new(IommuState::default()),
+            host,
+        }
+    }
+
+    pub fn bypass(&self) -> bool {
+        self.bypass.load(Ordering::Acquire)
+    }
+
+    fn set_bypass(&self, bypass: bool) {
+        log::info!("{}: bypass: {bypass}", self.name);
+        let state = self.state.read();
+        if self.bypass.swap(bypass, Ordering::AcqRel) == bypass {
+            return;
+        }
+        for (id, endpoint) in &state.endpoints {
+            if endpoint.host
+                && endpoint.domain.is_none()
+                && let Err(e) = self.detach_host(*id)
+            {
+                self.log_host_error("bypass", e);
+            }
+        }
+    }
+
+    /// Lets host endpoint `id`, which is not in any domain, access all guest
+    /// memory in bypass mode, or blocks its DMA otherwise.
+    fn detach_host(&self, id: u32) -> Result<()> {
+        let Some(host) = &self.host else {
+            return Ok(());
+        };
+        if self.bypass() {
+            host.attach(id, None)
+        } else {
+            host.block(id)
+        }
+    }
+
+    /// Registers an endpoint, identified by its PCI requester ID. `host` is
+    /// true if its DMA is translated by the host IOMMU.
+    pub fn add_endpoint(&self, id: u32, host: bool) {
+        let state = &mut *self.state.write();
+        if let Some(Endpoint {
+            domain: Some(domain),
+            ..
+        }) = state.endpoints.remove(&id)
+        {
+            self.leave(state, domain, id);
+        }
+        state.endpoints.insert(id, Endpoint { domain: None, host });
+        if host && let Err(e) = self.detach_host(id) {
+            self.log_host_error("add endpoint", e);
+        }
+    }
+
+    /// Returns the guest physical ranges backing `len` bytes at `iova` of
+    /// `endpoint`.
+    pub fn translate(
+        &self,
+        endpoint: u32,
+        iova: u64,
+        len: u64,
+        write: bool,
+    ) -> Result<Vec<(u64, u64)>> {
+        let state = self.state.read();
+        let domain = state.endpoints.get(&endpoint).and_then(|e| e.domain);
+        let Some(domain) = domain.and_then(|d| state.domains.get(&d)) else {
+            if self.bypass() {
+                return Ok(vec![(iova, len)]);
+            }
+            return error::IommuFault { iova, len }.fail();
+        };
+        if domain.bypass {
+            return Ok(vec![(iova, len)]);
+        }
+        let Some(end) = iova.checked_add(len) else {
+            return error::IommuFault { iova, len }.fail();
+        };
+        let perm = if write {
+            IommuMapFlag::WRITE
+        } else {
+            IommuMapFlag::READ
+        };
+        let mut ranges: Vec<(u64, u64)> = Vec::new();
+        let mut addr = iova;
+        while addr < end {
+            let mapping = domain.mappings.range(..=addr).next_back();
+            let Some((start, m)) = mapping.filter(|(s, m)| addr - *s < m.size) else {
+                return error::IommuFault { iova, len }.fail();
+            };
+            if !m.flags.contains(perm) {
+                return error::IommuFault { iova, len }.fail();
+            }
+            let offset = addr - start;
+            let size = std::cmp::min(m.size - offset, end - addr);
+            let gpa = m.gpa + offset;
+            match ranges.last_mut() {
+                Some((last, last_size)) if *last + *last_size == gpa => *last_size += size,
+                _ => ranges.push((gpa, size)),
+            }
+            addr += size;
+        }
+        Ok(ranges)
+    }
+
+    fn log_host_error(&self, op: &str, e: crate::virtio::Error) -> IommuStatus {
+        log::error!("{}: {op}: {e:?}", self.name);
+        IommuStatus::IOERR
+    }
+
+    /// Removes `endpoint` from `domain` and frees the domain once it has no
+    /// endpoints left.
+    fn leave(&self, state: &mut IommuState, domain_id: u32, endpoint: u32) {
+        let Some(domain) = state.domains.get_mut(&domain_id) else {
+            return;
+        };
+        domain.endpoints.remove(&endpoint);
+        if !domain.endpoints.is_empty() {
+            return;
+        }
+        let domain = state.domains.remove(&domain_id).unwrap();
+        if let (true, Some(host)) = (domain.mirrored, &self.host)
+            && let Err(e) = host.destroy_domain(domain_id)
+        {
+            self.log_host_error("destroy domain", e);
+        }
+    }
+
+    fn attach_host(&self, domain_id: u32, domain: &mut Domain, endpoint: u32) -> Result<()> {
+        let Some(host) = &self.host else {
+            return Ok(());
+        };
+        if domain.bypass {
+            return host.attach(endpoint, None);
+        }
+        if !domain.mirrored {
+            host.create_domain(domain_id)?;
+            domain.mirrored = true;
+            for (iova, m) in &domain.mappings {
+                host.map(domain_id, *iova, m.gpa, m.size)?;
+            }
+        }
+        host.attach(endpoint, Some(domain_id))
+    }
+
+    fn attach(&self, req: &IommuReqAttach) -> IommuStatus {
+        let (domain_id, endpoint_id) = (req.domain, req.endpoint);
+        let Some(flags) = IommuAttachFlag::from_bits(req.flags) else {
+            return IommuStatus::INVAL;
+        };
+        let bypass = flags.contains(IommuAttachFlag::BYPASS);
+        let state = &mut *self.state.write();
+        let Some(endpoint) = state.endpoints.get(&endpoint_id) else {
+            return IommuStatus::NOENT;
+        };
+        let (old, host) = (endpoint.domain, endpoint.host);
+        if old == Some(domain_id) {
+            return IommuStatus::OK;
+        }
+        let domain = state.domains.entry(domain_id).or_insert_with(|| Domain {
+            bypass,
+            ..Default::default()
+        });
+        if domain.bypass != bypass {
+            return IommuStatus::INVAL;
+        }
+        if host && let Err(e) = self.attach_host(domain_id, domain, endpoint_id) {
+            if domain.endpoints.is_empty() {
+                self.leave(state, domain_id, endpoint_id);
+            }
+            return self.log_host_error("attach", e);
+        }
+        domain.endpoints.insert(endpoint_id);
+        if let Some(endpoint) = state.endpoints.get_mut(&endpoint_id) {
+            endpoint.domain = Some(domain_id);
+        }
+        if let Some(old) = old {
+            self.leave(state, old, endpoint_id);
+        }
+        IommuStatus::OK
+    }
+
+    fn detach(&self, req: &IommuReqDetach) -> IommuStatus {
+        let (domain_id, endpoint_id) = (req.domain, req.endpoint);
+        let state = &mut *self.state.write();
+        let Some(endpoint) = state.endpoints.get_mut(&endpoint_id) else {
+            return IommuStatus::NOENT;
+        };
+        if endpoint.domain != Some(domain_id) {
+            return IommuStatus::INVAL;
+        }
+        if endpoint.host
+            && let Err(e) = self.detach_host(endpoint_id)
+        {
+            return self.log_host_error("detach", e);
+        }
+        endpoint.domain = None;
+        self.leave(state, domain_id, endpoint_id);
+        IommuStatus::OK
+    }
+
+    fn map(&self, req: &IommuReqMap) -> IommuStatus {
+        let (domain_id, start, end, gpa) =
+            (req.domain, req.virt_start, req.virt_end, req.phys_start);
+        let Some(flags) = IommuMapFlag::from_bits(req.flags) else {
+            return IommuStatus::INVAL;
+        };
+        if flags.contains(IommuMapFlag::MMIO) {
+            return IommuStatus::INVAL;
+        }
+        let state = &mut *self.state.write();
+        let Some(domain) = state.domains.get_mut(&domain_id) else {
+            return IommuStatus::NOENT;
+        };
+        if domain.bypass {
+            return IommuStatus::INVAL;
+        }
+        if start > end || end > INPUT_END || (start | (end + 1) | gpa) & !PAGE_SIZE_MASK != 0 {
+            return IommuStatus::RANGE;
+        }
+        let size = end - start + 1;
+        if let Some((s, m)) = domain.mappings.range(..=end).next_back()
+            && s + m.size > start
+        {
+            return IommuStatus::INVAL;
+        }
+        if let (true, Some(host)) = (domain.mirrored, &self.host)
+            && let Err(e) = host.map(domain_id, start, gpa, size)
+        {
+            return self.log_host_error("map", e);
+        }
+        domain.mappings.insert(start, Mapping { gpa, size, flags });
+        IommuStatus::OK
+    }
+
+    fn unmap(&self, req: &IommuReqUnmap) -> IommuStatus {
+        let (domain_id, start, end) = (req.domain, req.virt_start, req.virt_end);
+        let state = &mut *self.state.write();
+        let Some(domain) = state.domains.get_mut(&domain_id) else {
+            return IommuStatus::NOENT;
+        };
+        if domain.bypass {
+            return IommuStatus::INVAL;
+        }
+        let mut iovas = Vec::new();
+        for (s, m) in domain.mappings.range(..=end).rev() {
+            if s + m.size <= start {
+                break;
+            }
+            if *s < start || s + m.size - 1 > end {
+                return IommuStatus::RANGE;
+            }
+            iovas.push(*s);
+        }
+        let mut status = IommuStatus::OK;
+        for iova in iovas {
+            let m = domain.mappings.remove(&iova).unwrap();
+            if let (true, Some(host)) = (domain.mirrored, &self.host)
+                && let Err(e) = host.unmap(domain_id, iova, m.size)
+            {
+                status = self.log_host_error("unmap", e);
+            }
+        }
+        status
+    }
+
+    fn probe(&self, req: &IommuReqProbe, props: &mut [u8]) -> IommuStatus {
+        let endpoint = req.endpoint;
+        if !self.state.read().endpoints.contains_key(&endpoint) {
+            return IommuStatus::NOENT;
+        }
+        props.fill(0);
+        let resv_mem = IommuProbeResvMem {
+            type_: IommuProbeType::RESV_MEM.raw(),
+            length: (size_of::<IommuProbeResvMem>() - 4) as u16,
+            subtype: IommuResvMemType::MSI.raw(),
+            start: MSI_START,
+            end: MSI_END,
+            ..Default::default()
+        };
+        if resv_mem.write_to_prefix(props).is_err() {
+            return IommuStatus::INVAL;
+        }
+        IommuStatus::OK
+    }
+
+    /// Handles a request in `req`. Properties of a probe request are written
+    /// to `props`.
+    pub fn handle_req(&self, req: &[u8], props: &mut [u8]) -> IommuStatus {
+        fn parse<T: FromBytes>(req: &[u8]) -> Option<T> {
+            T::read_from_prefix(req).ok().map(|(r, _)| r)
+        }
+        let Some(head) = parse::<IommuReqHead>(req) else {
+            return IommuStatus::INVAL;
+        };
+        let req_type = IommuReqType::from(head.type_);
+        let status = match req_type {
+            IommuReqType::ATTACH => parse(req).map(|r| self.attach(&r)),
+            IommuReqType::DETACH => parse(req).map(|r| self.detach(&r)),
+            IommuReqType::MAP => parse(req).map(|r| self.map(&r)),
+            IommuReqType::UNMAP => parse(req).map(|r| self.unmap(&r)),
+            IommuReqType::PROBE => parse(req).map(|r| self.probe(&r, props)),
+            _ => Some(IommuStatus::UNSUPP),
+        };
+        let status = status.unwrap_or(IommuStatus::INVAL);
+        log::trace!("{}: {req_type:?}: {status:?}", self.name);
+        status
+    }
+
+    /// Detaches all endpoints and frees all domains.
+    fn reset(&self) {
+        let state = &mut *self.state.write();
+        self.bypass.store(self.default_bypass, Ordering::Release);
+        for (id, endpoint) in state.endpoints.iter_mut() {
+            endpoint.domain = None;
+            if endpoint.host
+                && let Err(e) = self.detach_host(*id)
+            {
+                self.log_host_error("detach", e);
+            }
+        }
+        for (id, domain) in state.domains.drain() {
+            if let (true, Some(host)) = (domain.mirrored, &self.host)
+                && let Err(e) = host.destroy_domain(id)
+            {
+                self.log_host_error("destroy domain", e);
+            }
+        }
+    }
+}
+
+/// Translates the DMA addresses of one endpoint of a virtio-iommu device.
+#[derive(Debug)]
+pub struct IommuEndpoint {
+    id: u32,
+    domains: Arc<IommuDomains>,
+}
+
+impl IommuEndpoint {
+    pub fn new(id: u32, domains: Arc<IommuDomains>) -> Self {
+        IommuEndpoint { id, domains }
+    }
+}
+
+impl Iotlb for IommuEndpoint {
+    fn translate(&self, iova: u64, len: u64, write: bool) -> Result<Vec<(u64, u64)>> {
+        self.domains.translate(self.id, iova, len, write)
+    }
+}
+
+#[derive(Debug)]
+pub struct VirtioIommu {
+    name: Arc<str>,
+    config: Arc<IommuConfigMmio>,
+    domains: Arc<IommuDomains>,
+}
+
+impl VirtioIommu {
+    pub fn new(
+        param: VirtioIommuParam,
+        name: impl Into<Arc<str>>,
+        host: Option<Arc<dyn HostIommu>>,
+    ) -> Self {
+        let name = name.into();
+        let domains = Arc::new(IommuDomains::new(name.clone(), param.bypass, host));
+        VirtioIommu {
+            name: name.clone(),
+            config: Arc::new(IommuConfigMmio {
+                name,
+                domains: domains.clone(),
+            }),
+            domains,
+        }
+    }
+
+    pub fn domains(&self) -> Arc<IommuDomains> {
+        self.domains.clone()
+    }
+}
+
+impl Virtio for VirtioIommu {
+    type Config = IommuConfigMmio;
+    type Feature = IommuFeature;
+
+    fn id(&self) -> DeviceId {
+        DeviceId::IOMMU
+    }
+
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn spawn_worker<S, E>(
+        self,
+        event_rx: Receiver<WakeEvent<S, E>>,
+        memory: Arc<RamBus>,
+        queue_regs: Arc<[QueueReg]>,
+    ) -> Result<(JoinHandle<()>, Arc<Notifier>)>
+    where
+        S: IrqSender,
+        E: IoeventFd,
+    {
+        Mio::spawn_worker(self, event_rx, memory, queue_regs)
+    }
+
+    fn num_queues(&self) -> u16 {
+        2
+    }
+
+    fn config(&self) -> Arc<IommuConfigMmio> {
+        self.config.clone()
+    }
+
+    fn feature(&self) -> u128 {
+        let feature = IommuFeature::INPUT_RANGE
+            | IommuFeature::DOMAIN_RANGE
+            | IommuFeature::MAP_UNMAP
+            | IommuFeature::PROBE
+            | IommuFeature::BYPASS_CONFIG;
+        FEATURE_BUILT_IN | feature.bits()
+    }
+}
+
+impl VirtioMio for VirtioIommu {
+    fn activate<'m, Q, S, E>(
+        &mut self,
+        _feature: u128,
+        _active_mio: &mut ActiveMio<'_, '_, 'm, Q, S, E>,
+    ) -> Result<()>
+    where
+        Q: VirtQueue<'m>,
+        S: IrqSender,
+        E: IoeventFd,
+    {
+        Ok(())
+    }
+
+    fn handle_queue<'m, Q, S, E>(
+        &mut self,
+        index: u16,
+        active_mio: &mut ActiveMio<'_, '_, 'm, Q, S, E>,
+    ) -> Result<()>
+    where
+        Q: VirtQueue<'m>,
+        S: IrqSender,
+        E: IoeventFd,
+    {
+        if index != 0 {
+            log::error!("{}: unexpected notification of queue {index}", self.name);
+            return Ok(());
+        }
+        let Some(Some(queue)) = active_mio.queues.get_mut(index as usize) else {
+            log::error!("{}: invalid queue index {index}", self.name);
+            return Ok(());
+        };
+        queue.handle_desc(index, active_mio.irq_sender, |chain| {
+            let Some(req) = chain.readable.first() else {
+                return error::InvalidBuffer.fail();
+            };
+            let Some(resp) = chain.writable.first_mut() else {
+                return error::InvalidBuffer.fail();
+            };
+            let len = resp.len();
+            let Some(tail_offset) = len.checked_sub(size_of::<IommuReqTail>()) else {
+                return error::InvalidBuffer.fail();
+            };
+            let (props, tail) = resp.split_at_mut(tail_offset);
+            let status = self.domains.handle_req(req, props);
+            let tail_val = IommuReqTail {
+                status: status.raw(),
+                ..Default::default()
+            };
+            if tail_val.write_to_prefix(tail).is_err() {
+                return error::InvalidBuffer.fail();
+            }
+            Ok(Status::Done { len: len as u32 })
+        })
+    }
+
+    fn handle_event<'a, 'm, Q, S, E>(
+        &mut self,
+        _event: &Event,
+        _active_mio: &mut ActiveMio<'_, '_, 'm, Q, S, E>,
+    ) -> Result<()>
+    where
+        Q: VirtQueue<'m>,
+        S: IrqSender,
+        E: IoeventFd,
+    {
+        Ok(())
+    }
+
+    fn reset(&mut self, _registry: &Registry) {
+        self.domains.reset();
+    }
+}
+
+const fn default_bypass() -> bool {
+    true
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Help)]
+pub struct VirtioIommuParam {
+    /// Let devices not attached to any domain access all guest memory.
+    /// [default: true]
+    #[serde(default = "default_bypass")]
+    pub bypass: bool,
+}
+
+impl Default for VirtioIommuParam {
+    fn default() -> Self {
+        VirtioIommuParam {
+            bypass: default_bypass(),
+        }
+    }
+}
+
+impl DevParam for VirtioIommuParam {
+    type Device = VirtioIommu;
+
+    fn build(self, name: impl Into<Arc<str>>) -> Result<Self::Device> {
+        Ok(VirtioIommu::new(self, name, None))
+    }
+}
+
+#[cfg(test)]
+#[path = "iommu_test.rs"]
+mod tests;
@@ -0,0 +1,383 @@
+// Copyright 2026 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::sync::Arc;
+
+use assert_matches::assert_matches;
+use parking_lot::Mutex;
+use zerocopy::{FromBytes, FromZeros, Immutable, IntoBytes};
+
+use crate::mem::emulated::Mmio;
+use crate::virtio::dev::Virtio;
+use crate::virtio::dev::iommu::{
+    HostIommu, IommuAttachFlag, IommuDomains, IommuFeature, IommuMapFlag, IommuProbeResvMem,
+    IommuProbeType, IommuReqAttach, IommuReqDetach, IommuReqHead, IommuReqMap, IommuReqProbe,
+    IommuReqType, IommuReqUnmap, IommuResvMemType, IommuStatus, MSI_END, MSI_START, VirtioIommu,
+    VirtioIommuParam,
+};
+use crate::virtio::{Error, FEATURE_BUILT_IN, Result};
+
+fn head(type_: IommuReqType) -> IommuReqHead {
+    IommuReqHead {
+        type_: type_.raw(),
+        ..Default::default()
+    }
+}
+
+fn send(domains: &IommuDomains, req: impl IntoBytes + Immutable) -> IommuStatus {
+    domains.handle_req(req.as_bytes(), &mut [])
+}
+
+fn attach(domains: &IommuDomains, domain: u32, endpoint: u32, flags: IommuAttachFlag) -> u8 {
+    let req = IommuReqAttach {
+        head: head(IommuReqType::ATTACH),
+        domain,
+        endpoint,
+        flags: flags.bits(),
+        ..Default::default()
+    };
+    send(domains, req).raw()
+}
+
+fn detach(domains: &IommuDomains, domain: u32, endpoint: u32) -> u8 {
+    let req = IommuReqDetach {
+        head: head(IommuReqType::DETACH),
+        domain,
+        endpoint,
+        ..Default::default()
+    };
+    send(domains, req).raw()
+}
+
+fn map(domains: &IommuDomains, domain: u32, iova: u64, gpa: u64, size: u64, flags: u32) -> u8 {
+    let req = IommuReqMap {
+        head: head(IommuReqType::MAP),
+        domain,
+        virt_start: iova,
+        virt_end: iova.wrapping_add(size).wrapping_sub(1),
+        phys_start: gpa,
+        flags,
+    };
+    send(domains, req).raw()
+}
+
+fn unmap(domains: &IommuDomains, domain: u32, iova: u64, size: u64) -> u8 {
+    let req = IommuReqUnmap {
+        head: head(IommuReqType::UNMAP),
+        domain,
+        virt_start: iova,
+        virt_end: iova + size - 1,
+        ..Default::default()
+    };
+    send(domains, req).raw()
+}
+
+const OK: u8 = IommuStatus::OK.raw();
+const RW: u32 = IommuMapFlag::READ.bits() | IommuMapFlag::WRITE.bits();
+
+#[test]
+fn test_iommu_config() {
+    let dev = VirtioIommu::new(VirtioIommuParam::default(), "iommu", None);
+    assert_eq!(dev.num_queues(), 2);
+    let feature = IommuFeature::INPUT_RANGE
+        | IommuFeature::DOMAIN_RANGE
+        | IommuFeature::MAP_UNMAP
+        | IommuFeature::PROBE
+        | IommuFeature::BYPASS_CONFIG;
+    assert_eq!(dev.feature(), FEATURE_BUILT_IN | feature.bits());
+
+    let config = dev.config();
+    assert_eq!(config.size(), 40);
+    assert_matches!(config.read(0, 8), Ok(0xffff_ffff_ffff_f000));
+    assert_matches!(config.read(8, 8), Ok(0));
+    assert_matches!(config.read(16, 8), Ok(0xffff_ffff_ffff));
+    assert_matches!(config.read(24, 4), Ok(0));
+    assert_matches!(config.read(28, 4), Ok(0xffff_ffff));
+    assert_matches!(config.read(32, 4), Ok(512));
+    assert_matches!(config.read(36, 1), Ok(1));
+
+    config.write(36, 1, 0).unwrap();
+    assert_matches!(config.read(36, 1), Ok(0));
+    config.write(32, 4, 0).unwrap();
+    assert_matches!(config.read(32, 4), Ok(512));
+
+    dev.domains().reset();
+    assert_matches!(config.read(36, 1), Ok(1));
+}
+
+#[test]
+fn test_iommu_translate() {
+    let dev = VirtioIommu::new(VirtioIommuParam { bypass: false }, "iommu", None);
+    let domains = dev.domains();
+    domains.add_endpoint(8, false);
+
+    assert_matches!(
+        domains.translate(8, 0x1000, 0x10, false),
+        Err(Error::IommuFault { .. })
+    );
+    domains.set_bypass(true);
+    assert_matches!(domains.translate(8, 0x1000, 0x10, false), Ok(v) if v == [(0x1000, 0x10)]);
+
+    assert_eq!(
+        attach(&domains, 1, 9, IommuAttachFlag::empty()),
+        IommuStatus::NOENT.raw()
+    );
+    assert_eq!(attach(&domains, 1, 8, IommuAttachFlag::empty()), OK);
+    assert_eq!(attach(&domains, 1, 8, IommuAttachFlag::empty()), OK);
+    assert_matches!(
+        domains.translate(8, 0x1000, 0x10, false),
+        Err(Error::IommuFault { .. })
+    );
+
+    assert_eq!(
+        map(&domains, 2, 0x1000, 0x8000, 0x1000, RW),
+        IommuStatus::NOENT.raw()
+    );
+    assert_eq!(
+        map(&domains, 1, 0x1800, 0x8000, 0x1000, RW),
+        IommuStatus::RANGE.raw()
+    );
+    assert_eq!(
+        map(&domains, 1, 1 << 48, 0x8000, 0x1000, RW),
+        IommuStatus::RANGE.raw()
+    );
+    assert_eq!(
+        map(&domains, 1, 0x1000, 0x8000, 0x1000, 1 << 3),
+        IommuStatus::INVAL.raw()
+    );
+    assert_eq!(map(&domains, 1, 0x1000, 0x8000, 0x1000, RW), OK);
+    assert_eq!(map(&domains, 1, 0x2000, 0x9000, 0x1000, RW), OK);
+    let read = IommuMapFlag::READ.bits();
+    assert_eq!(map(&domains, 1, 0x3000, 0x20000, 0x1000, read), OK);
+    assert_eq!(
+        map(&domains, 1, 0x2000, 0xa000, 0x2000, RW),
+        IommuStatus::INVAL.raw()
+    );
+
+    assert_matches!(
+        domains.translate(8, 0x1800, 0x1000, true),
+        Ok(v) if v == [(0x8800, 0x1000)]
+    );
+    assert_matches!(
+        domains.translate(8, 0x2800, 0x1000, false),
+        Ok(v) if v == [(0x9800, 0x800), (0x20000, 0x800)]
+    );
+    assert_matches!(
+        domains.translate(8, 0x3000, 0x10, true),
+        Err(Error::IommuFault { .. })
+    );
+    assert_matches!(
+        domains.translate(8, 0x3800, 0x1000, false),
+        Err(Error::IommuFault { .. })
+    );
+
+    assert_eq!(unmap(&domains, 1, 0x1000, 0x1000), OK);
+    assert_eq!(unmap(&domains, 1, 0x2000, 0x800), IommuStatus::RANGE.raw());
+    assert_eq!(unmap(&domains, 1, 0x1000, 0x1000), OK);
+    assert_matches!(
+        domains.translate(8, 0x1000, 0x10, false),
+        Err(Error::IommuFault { .. })
+    );
+    assert_eq!(unmap(&domains, 1, 0, 1 << 20), OK);
+    assert_matches!(
+        domains.translate(8, 0x2000, 0x10, false),
+        Err(Error::IommuFault { .. })
+    );
+
+    assert_eq!(detach(&domains, 2, 8), IommuStatus::INVAL.raw());
+    assert_eq!(detach(&domains, 1, 8), OK);
+    assert_matches!(domains.translate(8, 0x1000, 0x10, false), Ok(v) if v == [(0x1000, 0x10)]);
+    // The domain is freed once its last endpoint is detached.
+    assert_eq!(
+        map(&domains, 1, 0x1000, 0x8000, 0x1000, RW),
+        IommuStatus::NOENT.raw()
+    );
+}
+
+#[test]
+fn test_iommu_bypass_domain() {
+    let dev = VirtioIommu::new(VirtioIommuParam { bypass: false }, "iommu", None);
+    let domains = dev.domains();
+    domains.add_endpoint(8, false);
+    domains.add_endpoint(16, false);
+
+    assert_eq!(attach(&domains, 1, 8, IommuAttachFlag::BYPASS), OK);
+    assert_matches!(domains.translate(8, 0x1000, 0x10, true), Ok(v) if v == [(0x1000, 0x10)]);
+    assert_eq!(
+        map(&domains, 1, 0x1000, 0x8000, 0x1000, RW),
+        IommuStatus::INVAL.raw()
+    );
+    assert_eq!(
+        attach(&domains, 1, 16, IommuAttachFlag::empty()),
+        IommuStatus::INVAL.raw()
+    );
+
+    assert_eq!(attach(&domains, 2, 16, IommuAttachFlag::empty()), OK);
+    assert_eq!(attach(&domains, 2, 8, IommuAttachFlag::empty()), OK);
+    assert_matches!(
+        domains.translate(8, 0x1000, 0x10, true),
+        Err(Error::IommuFault { .. })
+    );
+    assert_eq!(attach(&domains, 1, 16, IommuAttachFlag::empty()), OK);
+}
+
+#[test]
+fn test_iommu_probe() {
+    let dev = VirtioIommu::new(VirtioIommuParam::default(), "iommu", None);
+    let domains = dev.domains();
+    domains.add_endpoint(8, false);
+
+    let mut req = IommuReqProbe::new_zeroed();
+    req.head = head(IommuReqType::PROBE);
+    req.endpoint = 9;
+    let mut props = vec![0xff; 512];
+    let status = domains.handle_req(req.as_bytes(), &mut props);
+    assert_eq!(status, IommuStatus::NOENT);
+
+    req.endpoint = 8;
+    let status = domains.handle_req(req.as_bytes(), &mut props);
+    assert_eq!(status, IommuStatus::OK);
+    let (resv_mem, rest) = IommuProbeResvMem::read_from_prefix(&props).unwrap();
+    assert_eq!(resv_mem.type_, IommuProbeType::RESV_MEM.raw());
+    assert_eq!(resv_mem.length, 20);
+    assert_eq!(resv_mem.subtype, IommuResvMemType::MSI.raw());
+    assert_eq!(resv_mem.start, MSI_START);
+    assert_eq!(resv_mem.end, MSI_END);
+    assert!(rest.iter().all(|b| *b == 0));
+
+    let status = domains.handle_req(&[IommuReqType::PROBE.raw()], &mut props);
+    assert_eq!(status, IommuStatus::INVAL);
+    let status = domains.handle_req(&[0xff, 0, 0, 0], &mut props);
+    assert_eq!(status, IommuStatus::UNSUPP);
+}
+
+#[derive(Debug, Default)]
+struct FakeHost {
+    ops: Mutex<Vec<String>>,
+}
+
+impl HostIommu for FakeHost {
+    fn create_domain(&self, domain: u32) -> Result<()> {
+        self.ops.lock().push(format!("create {domain}"));
+        Ok(())
+    }
+
+    fn destroy_domain(&self, domain: u32) -> Result<()> {
+        self.ops.lock().push(format!("destroy {domain}"));
+        Ok(())
+    }
+
+    fn map(&self, domain: u32, iova: u64, gpa: u64, size: u64) -> Result<()> {
+        let op = format!("map {domain} {iova:#x} {gpa:#x} {size:#x}");
+        self.ops.lock().push(op);
+        Ok(())
+    }
+
+    fn unmap(&self, domain: u32, iova: u64, size: u64) -> Result<()> {
+        self.ops
+            .lock()
+            .push(format!("unmap {domain} {iova:#x} {size:#x}"));
+        Ok(())
+    }
+
+    fn attach(&self, endpoint: u32, domain: Option<u32>) -> Result<()> {
+        self.ops
+            .lock()
+            .push(format!("attach {endpoint} {domain:?}"));
+        Ok(())
+    }
+
+    fn block(&self, endpoint: u32) -> Result<()> {
+        self.ops.lock().push(format!("block {endpoint}"));
+        Ok(())
+    }
+}
+
+#[test]
+fn test_iommu_host() {
+    let host = Arc::new(FakeHost::default());
+    let dev = VirtioIommu::new(VirtioIommuParam::default(), "iommu", Some(host.clone()));
+    let domains = dev.domains();
+    domains.add_endpoint(8, false);
+    domains.add_endpoint(16, true);
+
+    assert_eq!(attach(&domains, 1, 8, IommuAttachFlag::empty()), OK);
+    assert_eq!(map(&domains, 1, 0x1000, 0x8000, 0x1000, RW), OK);
+    assert_eq!(*host.ops.lock(), ["attach 16 None"]);
+
+    assert_eq!(attach(&domains, 1, 16, IommuAttachFlag::empty()), OK);
+    assert_eq!(map(&domains, 1, 0x2000, 0x9000, 0x1000, RW), OK);
+    assert_eq!(unmap(&domains, 1, 0x1000, 0x1000), OK);
+    assert_eq!(attach(&domains, 2, 16, IommuAttachFlag::BYPASS), OK);
+    assert_eq!(attach(&domains, 3, 16, IommuAttachFlag::empty()), OK);
+    assert_eq!(detach(&domains, 1, 8), OK);
+    domains.reset();
+
+    assert_eq!(
+        *host.ops.lock(),
+        [
+            "attach 16 None",
+            "create 1",
+            "map 1 0x1000 0x8000 0x1000",
+            "attach 16 Some(1)",
+            "map 1 0x2000 0x9000 0x1000",
+            "unmap 1 0x1000 0x1000",
+            "attach 16 None",
+            "create 3",
+            "attach 16 Some(3)",
+            "destroy 1",
+            "attach 16 None",
+            "destroy 3",
+        ]
+    );
+}
+
+#[test]
+fn test_iommu_host_blocked() {
+    let host = Arc::new(FakeHost::default());
+    let param = VirtioIommuParam { bypass: false };
+    let dev = VirtioIommu::new(param, "iommu", Some(host.clone()));
+    let config = dev.config();
+    let domains = dev.domains();
+    domains.add_endpoint(8, false);
+    domains.add_endpoint(16, true);
+
+    assert_eq!(attach(&domains, 1, 16, IommuAttachFlag::empty()), OK);
+    assert_eq!(detach(&domains, 1, 16), OK);
+    config.write(36, 1, 1).unwrap();
+    config.write(36, 1, 1).unwrap();
+    assert_eq!(attach(&domains, 2, 16, IommuAttachFlag::empty()), OK);
+    config.write(36, 1, 0).unwrap();
+    assert_eq!(detach(&domains, 2, 16), OK);
+    config.write(36, 1, 1).unwrap();
+    domains.reset();
+
+    assert_eq!(
+        *host.ops.lock(),
+        [
+            "block 16",
+            "create 1",
+            "attach 16 Some(1)",
+            "block 16",
+            "destroy 1",
+            "attach 16 None",
+            "create 2",
+            "attach 16 Some(2)",
+            "block 16",
+            "destroy 2",
+            "attach 16 None",
+            "block 16",
+        ]
+    );
+}
@@ -0,0 +1,484 @@
+// Copyright 2026 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::fmt::Debug;
+use std::sync::Arc;
+use std::sync::mpsc::Receiver;
+use std::thread::JoinHandle;
+
+use mio::Registry;
+use mio::event::Event;
+use parking_lot::RwLock;
+use serde::Deserialize;
+use serde_aco::Help;
+use zerocopy::{FromBytes, Immutable, IntoBytes};
+
+use crate::hv::IoeventFd;
+use crate::mem::emulated::{Action, Mmio};
+use crate::mem::mapped::{Ram, RamBus};
+use crate::sync::notifier::Notifier;
+use crate::virtio::dev::{DeviceId, Virtio, WakeEvent};
+use crate::virtio::queue::{QueueReg, Status, VirtQueue};
+use crate::virtio::worker::mio::{ActiveMio, Mio, VirtioMio};
+use crate::virtio::{FEATURE_BUILT_IN, IrqSender, Result, error};
+use crate::{bitflags, consts, impl_mmio_for_zerocopy, mem};
+
+#[repr(C, align(8))]
+#[derive(Debug, Clone, Default, FromBytes, IntoBytes, Immutable)]
+pub struct VirtioMemConfig {
+    block_size: u64,
+    node_id: u16,
+    _padding: [u8; 6],
+    addr: u64,
+    region_size: u64,
+    usable_region_size: u64,
+    plugged_size: u64,
+    requested_size: u64,
+}
+
+impl_mmio_for_zerocopy!(VirtioMemConfig);
+
+#[derive(Debug)]
+pub struct VirtioMemConfigMmio {
+    name: Arc<str>,
+    config: RwLock<VirtioMemConfig>,
+}
+
+impl VirtioMemConfigMmio {
+    pub fn block_size(&self) -> u64 {
+        self.config.read().block_size
+    }
+
+    pub fn region_size(&self) -> u64 {
+        self.config.read().region_size
+    }
+
+    pub fn plugged_size(&self) -> u64 {
+        self.config.read().plugged_size
+    }
+
+    pub fn requested_size(&self) -> u64 {
+        self.config.read().requested_size
+    }
+
+    /// Updates the size the guest is asked to plug and returns whether it
+    /// has changed.
+    pub fn set_requested_size(&self, size: u64) -> bool {
+        let config = &mut *self.config.write();
+        let old = config.requested_size;
+        config.requested_size = size;
+        log::info!("{}: requested size: {old:#x} -> {size:#x}", self.name);
+        old != size
+    }
+}
+
+impl Mmio for VirtioMemConfigMmio {
+    fn size(&self) -> u64 {
+        size_of::<VirtioMemConfig>() as u64
+    }
+
+    fn read(&self, offset: u64, size: u8) -> mem::Result<u64> {
+        let config = self.config.read();
+        Mmio::read(&*config, offset, size)
+    }
+
+    fn write(&self, offset: u64, size: u8, val: u64) -> mem::Result<Action> {
+        log::error!(
+            "{}: write to read-only config: offset = {offset:#x}, size = {size}, val = {val:#x}",
+            self.name
+        );
+        Ok(Action::None)
+    }
+}
+
+bitflags! {
+    pub struct VirtioMemFeature(u128) {
+        ACPI_PXM = 1 << 0;
+        UNPLUGGED_INACCESSIBLE = 1 << 1;
+    }
+}
+
+consts! {
+    pub struct VirtioMemReqType(u16) {
+        PLUG = 0;
+        UNPLUG = 1;
+        UNPLUG_ALL = 2;
+        STATE = 3;
+    }
+}
+
+consts! {
+    pub struct VirtioMemRespType(u16) {
+        ACK = 0;
+        NACK = 1;
+        BUSY = 2;
+        ERROR = 3;
+    }
+}
+
+consts! {
+    pub struct VirtioMemState(u16) {
+        PLUGGED = 0;
+        UNPLUGGED = 1;
+        MIXED = 2;
+    }
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Default, FromBytes, IntoBytes, Immutable)]
+pub struct VirtioMemReq {
+    type_: u16,
+    _padding0: [u16; 3],
+    addr: u64,
+    nb_blocks: u16,
+    _padding1: [u16; 3],
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Default, FromBytes, IntoBytes, Immutable)]
+pub struct VirtioMemResp {
+    type_: u16,
+    _padding: [u16; 3],
+    state: u16,
+}
+
+/// The guest physical region managed by a virtio-mem device.
+#[derive(Debug, Clone, Copy)]
+pub struct VirtioMemRegion {
+    pub addr: u64,
+    pub size: u64,
+    /// Size of host pages backing the region.
+    pub page_size: u64,
+    /// Whether the host pages are shared, in which case unplugged blocks
+    /// are removed from the backing file.
+    pub shared: bool,
+}
+
+#[derive(Debug)]
+pub struct VirtioMem {
+    name: Arc<str>,
+    config: Arc<VirtioMemConfigMmio>,
+    feature: VirtioMemFeature,
+    plugged: Vec<bool>,
+    discard_advice: i32,
+}
+
+impl VirtioMem {
+    pub fn new(
+        param: VirtioMemParam,
+        name: impl Into<Arc<str>>,
+        region: VirtioMemRegion,
+    ) -> Result<Self> {
+        let block_size = param.block_size;
+        if !block_size.is_power_of_two() || block_size < region.page_size {
+            let msg = format!(
+                "block size {block_size:#x} is not a power of 2 or is smaller than page size {:#x}",
+                region.page_size
+            );
+            return error::InvalidMemParam { msg }.fail();
+        }
+        if region.addr & (block_size - 1) != 0 || region.size & (block_size - 1) != 0 {
+            let msg = format!(
+                "region {:#x}+{:#x} is not aligned to block size {block_size:#x}",
+                region.addr, region.size
+            );
+            return error::InvalidMemParam { msg }.fail();
+        }
+        if param.requested_size > region.size || param.requested_size & (block_size - 1) != 0 {
+            let msg = format!("invalid requested size {:#x}", param.requested_size);
+            return error::InvalidMemParam { msg }.fail();
+        }
+        let mut feature = VirtioMemFeature::UNPLUGGED_INACCESSIBLE;
+        if param.node.is_some() {
+            feature |= VirtioMemFeature::ACPI_PXM;
+        }
+        let config = VirtioMemConfig {
+            block_size,
+            node_id: param.node.unwrap_or(0),
+            addr: region.addr,
+            region_size: region.size,
+            usable_region_size: region.size,
+            requested_size: param.requested_size,
+            ..Default::default()
+        };
+        let name = name.into();
+        #[cfg(target_os = "linux")]
+        let discard_advice = if region.shared {
+            libc::MADV_REMOVE
+        } else {
+            libc::MADV_DONTNEED
+        };
+        #[cfg(not(target_os = "linux"))]
+        let discard_advice = libc::MADV_DONTNEED;
+        Ok(VirtioMem {
+            name: name.clone(),
+            config: Arc::new(VirtioMemConfigMmio {
+                name,
+                config: RwLock::new(config),
+            }),
+            feature,
+            plugged: vec![false; (region.size / block_size) as usize],
+            discard_advice,
+        })
+    }
+
+    fn plug(&self, ram: &Ram, gpa: u64, size: u64) -> bool {
+        if let Err(e) = ram.set_accessible(gpa, size, true) {
+            log::error!("{}: plug {gpa:#x}+{size:#x}: {e:?}", self.name);
+            return false;
+        }
+        true
+    }
+
+    /// Discards the blocks in `[gpa, gpa + size)` and makes them
+    /// inaccessible.
+    fn unplug(&self, ram: &Ram, gpa: u64, size: u64) {
+        if let Err(e) = ram.madvise(gpa, size, self.discard_advice) {
+            log::error!("{}: discard {gpa:#x}+{size:#x}: {e:?}", self.name);
+        }
+        if let Err(e) = ram.set_accessible(gpa, size, false) {
+            log::error!("{}: unplug {gpa:#x}+{size:#x}: {e:?}", self.name);
+        }
+    }
+
+    fn unplug_all(&mut self, ram: &Ram) {
+        let config = &mut *self.config.config.write();
+        for (index, plugged) in self.plugged.iter().enumerate() {
+            if *plugged {
+                let gpa = config.addr + index as u64 * config.block_size;
+                self.unplug(ram, gpa, config.block_size);
+            }
+        }
+        self.plugged.fill(false);
+        config.plugged_size = 0;
+    }
+
+    fn handle_req(&mut self, req: &VirtioMemReq, ram: &Ram) -> VirtioMemResp {
+        let mut resp = VirtioMemResp {
+            type_: VirtioMemRespType::ACK.raw(),
+            ..Default::default()
+        };
+        let req_type = VirtioMemReqType::from(req.type_);
+        if req_type == VirtioMemReqType::UNPLUG_ALL {
+            log::info!("{}: unplug all", self.name);
+            self.unplug_all(ram);
+            return resp;
+        }
+
+        let (block_size, region_start, requested_size, plugged_size) = {
+            let config = self.config.config.read();
+            (
+                config.block_size,
+                config.addr,
+                config.requested_size,
+                config.plugged_size,
+            )
+        };
+        let first = (req.addr.wrapping_sub(region_start) / block_size) as usize;
+        let count = req.nb_blocks as usize;
+        let size = count as u64 * block_size;
+        let blocks = if req.addr & (block_size - 1) != 0 || req.addr < region_start || count == 0 {
+            None
+        } else {
+            self.plugged.get(first..).and_then(|b| b.get(..count))
+        };
+        let Some(blocks) = blocks else {
+            log::error!("{}: {req_type:?}: invalid range {req:x?}", self.name);
+            resp.type_ = VirtioMemRespType::ERROR.raw();
+            return resp;
+        };
+
+        let all_plugged = blocks.iter().all(|p| *p);
+        let all_unplugged = blocks.iter().all(|p| !*p);
+        match req_type {
+            VirtioMemReqType::PLUG if !all_unplugged => {
+                resp.type_ = VirtioMemRespType::ERROR.raw();
+            }
+            VirtioMemReqType::PLUG if plugged_size + size > requested_size => {
+                resp.type_ = VirtioMemRespType::NACK.raw();
+            }
+            VirtioMemReqType::PLUG if !self.plug(ram, req.addr, size) => {
+                resp.type_ = VirtioMemRespType::ERROR.raw();
+            }
+            VirtioMemReqType::PLUG => {
+                self.plugged[first..first + count].fill(true);
+                self.config.config.write().plugged_size += size;
+            }
+            VirtioMemReqType::UNPLUG if !all_plugged => {
+                resp.type_ = VirtioMemRespType::ERROR.raw();
+            }
+            VirtioMemReqType::UNPLUG => {
+                self.unplug(ram, req.addr, size);
+                self.plugged[first..first + count].fill(false);
+                self.config.config.write().plugged_size -= size;
+            }
+            VirtioMemReqType::STATE => {
+                let state = if all_plugged {
+                    VirtioMemState::PLUGGED
+                } else if all_unplugged {
+                    VirtioMemState::UNPLUGGED
+                } else {
+                    VirtioMemState::MIXED
+                };
+                resp.state = state.raw();
+            }
+            _ => {
+                log::error!("{}: unknown request {req:x?}", self.name);
+                resp.type_ = VirtioMemRespType::ERROR.raw();
+            }
+        }
+        log::trace!(
+            "{}: {req_type:?} {:#x}+{size:#x}: {:?}",
+            self.name,
+            req.addr,
+            VirtioMemRespType::from(resp.type_)
+        );
+        resp
+    }
+}
+
+impl Virtio for VirtioMem {
+    type Config = VirtioMemConfigMmio;
+    type Feature = VirtioMemFeature;
+
+    fn id(&self) -> DeviceId {
+        DeviceId::MEM
+    }
+
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn spawn_worker<S, E>(
+        self,
+        event_rx: Receiver<WakeEvent<S, E>>,
+        memory: Arc<RamBus>,
+        queue_regs: Arc<[QueueReg]>,
+    ) -> Result<(JoinHandle<()>, Arc<Notifier>)>
+    where
+        S: IrqSender,
+        E: IoeventFd,
+    {
+        Mio::spawn_worker(self, event_rx, memory, queue_regs)
+    }
+
+    fn num_queues(&self) -> u16 {
+        1
+    }
+
+    fn config(&self) -> Arc<VirtioMemConfigMmio> {
+        self.config.clone()
+    }
+
+    fn feature(&self) -> u128 {
+        FEATURE_BUILT_IN | self.feature.bits()
+    }
+}
+
+impl VirtioMio for VirtioMem {
+    fn activate<'m, Q, S, E>(
+        &mut self,
+        _feature: u128,
+        active_mio: &mut ActiveMio<'_, '_, 'm, Q, S, E>,
+    ) -> Result<()>
+    where
+        Q: VirtQueue<'m>,
+        S: IrqSender,
+        E: IoeventFd,
+    {
+        // A new driver starts with all memory unplugged.
+        self.unplug_all(active_mio.mem);
+        Ok(())
+    }
+
+    fn handle_queue<'m, Q, S, E>(
+        &mut self,
+        index: u16,
+        active_mio: &mut ActiveMio<'_, '_, 'm, Q, S, E>,
+    ) -> Result<()>
+    where
+        Q: VirtQueue<'m>,
+        S: IrqSender,
+        E: IoeventFd,
+    {
+        let Some(Some(queue)) = active_mio.queues.get_mut(index as usize) else {
+            log::error!("{}: invalid queue index {index}", self.name);
+            return Ok(());
+        };
+        let ram = active_mio.mem;
+        queue.handle_desc(index, active_mio.irq_sender, |chain| {
+            let Some(buf) = chain.readable.first() else {
+                return error::InvalidBuffer.fail();
+            };
+            let Ok((req, _)) = VirtioMemReq::read_from_prefix(buf) else {
+                return error::InvalidBuffer.fail();
+            };
+            let resp = self.handle_req(&req, ram);
+            let Some(buf) = chain.writable.first_mut() else {
+                return error::InvalidBuffer.fail();
+            };
+            if resp.write_to_prefix(buf).is_err() {
+                return error::InvalidBuffer.fail();
+            }
+            Ok(Status::Done {
+                len: size_of::<VirtioMemResp>() as u32,
+            })
+        })
+    }
+
+    fn handle_event<'a, 'm, Q, S, E>(
+        &mut self,
+        _event: &Event,
+        _active_mio: &mut ActiveMio<'_, '_, 'm, Q, S, E>,
+    ) -> Result<()>
+    where
+        Q: VirtQueue<'m>,
+        S: IrqSender,
+        E: IoeventFd,
+    {
+        Ok(())
+    }
+
+    fn reset(&mut self, _registry: &Registry) {}
+}
+
+const fn default_block_size() -> u64 {
+    2 << 20
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Help)]
+pub struct VirtioMemParam {
+    /// Granularity of plugging and unplugging memory. [default: 2M]
+    #[serde(default = "default_block_size")]
+    pub block_size: u64,
+    /// Size of memory the guest is asked to plug at boot. [default: 0]
+    #[serde(default)]
+    pub requested_size: u64,
+    /// Guest NUMA node of the memory. [default: none]
+    pub node: Option<u16>,
+}
+
+impl Default for VirtioMemParam {
+    fn default() -> Self {
+        VirtioMemParam {
+            block_size: default_block_size(),
+            requested_size: 0,
+            node: None,
+        }
+    }
+}
+
+#[cfg(test)]
+#[path = "mem_test.rs"]
+mod tests;
@@ -0,0 +1,200 @@
+// Copyright 2026 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use assert_matches::assert_matches;
+use rstest::rstest;
+
+use crate::mem;
+use crate::mem::emulated::Mmio;
+use crate::mem::mapped::{ArcMemPages, RamBus};
+use crate::virtio::dev::Virtio;
+use crate::virtio::dev::mem::{
+    VirtioMem, VirtioMemFeature, VirtioMemParam, VirtioMemRegion, VirtioMemReq, VirtioMemReqType,
+    VirtioMemRespType, VirtioMemState,
+};
+use crate::virtio::{Error, FEATURE_BUILT_IN};
+
+const REGION_START: u64 = 1 << 30;
+const BLOCK_SIZE: u64 = 4 << 10;
+const REGION_SIZE: u64 = 16 * BLOCK_SIZE;
+
+fn region() -> VirtioMemRegion {
+    VirtioMemRegion {
+        addr: REGION_START,
+        size: REGION_SIZE,
+        page_size: 4 << 10,
+        shared: false,
+    }
+}
+
+fn new_dev(requested_size: u64) -> VirtioMem {
+    let param = VirtioMemParam {
+        block_size: BLOCK_SIZE,
+        requested_size,
+        node: None,
+    };
+    VirtioMem::new(param, "virtio-mem", region()).unwrap()
+}
+
+fn req(type_: VirtioMemReqType, addr: u64, nb_blocks: u16) -> VirtioMemReq {
+    VirtioMemReq {
+        type_: type_.raw(),
+        addr,
+        nb_blocks,
+        ..Default::default()
+    }
+}
+
+#[rstest]
+#[case(3 << 10, 0)]
+#[case(2 << 10, 0)]
+#[case(BLOCK_SIZE, REGION_SIZE + BLOCK_SIZE)]
+#[case(BLOCK_SIZE, BLOCK_SIZE / 2)]
+#[case(REGION_SIZE * 2, 0)]
+fn test_virtio_mem_invalid_param(#[case] block_size: u64, #[case] requested_size: u64) {
+    let param = VirtioMemParam {
+        block_size,
+        requested_size,
+        node: None,
+    };
+    assert_matches!(
+        VirtioMem::new(param, "virtio-mem", region()),
+        Err(Error::InvalidMemParam { .. })
+    );
+}
+
+#[test]
+fn test_virtio_mem_config() {
+    let param = VirtioMemParam {
+        block_size: BLOCK_SIZE,
+        requested_size: 2 * BLOCK_SIZE,
+        node: Some(1),
+    };
+    let dev = VirtioMem::new(param, "virtio-mem", region()).unwrap();
+    assert_eq!(
+        dev.feature(),
+        FEATURE_BUILT_IN
+            | VirtioMemFeature::ACPI_PXM.bits()
+            | VirtioMemFeature::UNPLUGGED_INACCESSIBLE.bits()
+    );
+
+    let config = dev.config();
+    assert_eq!(config.size(), 56);
+    assert_matches!(config.read(0, 8), Ok(BLOCK_SIZE));
+    assert_matches!(config.read(8, 2), Ok(1));
+    assert_matches!(config.read(16, 8), Ok(REGION_START));
+    assert_matches!(config.read(24, 8), Ok(REGION_SIZE));
+    assert_matches!(config.read(32, 8), Ok(REGION_SIZE));
+    assert_matches!(config.read(40, 8), Ok(0));
+    assert_matches!(config.read(48, 8), Ok(s) if s == 2 * BLOCK_SIZE);
+
+    config.write(48, 8, 0).unwrap();
+    assert_eq!(config.requested_size(), 2 * BLOCK_SIZE);
+
+    assert!(config.set_requested_size(4 * BLOCK_SIZE));
+    assert!(!config.set_requested_size(4 * BLOCK_SIZE));
+    assert_matches!(config.read(48, 8), Ok(s) if s == 4 * BLOCK_SIZE);
+}
+
+#[test]
+fn test_virtio_mem_requests() {
+    let ram_bus = RamBus::new();
+    let pages = ArcMemPages::from_anonymous(REGION_SIZE as usize, None, None).unwrap();
+    ram_bus.add(REGION_START, pages).unwrap();
+    let ram = ram_bus.lock_layout();
+    ram.set_accessible(REGION_START, REGION_SIZE, false)
+        .unwrap();
+    assert_matches!(
+        ram.read_t::<u32>(REGION_START),
+        Err(mem::Error::Inaccessible { .. })
+    );
+
+    let mut dev = new_dev(3 * BLOCK_SIZE);
+    let config = dev.config();
+
+    let resp = dev.handle_req(&req(VirtioMemReqType::PLUG, REGION_START, 2), &ram);
+    assert_eq!(resp.type_, VirtioMemRespType::ACK.raw());
+    assert_eq!(config.plugged_size(), 2 * BLOCK_SIZE);
+    assert_matches!(ram.read_t::<u32>(REGION_START + BLOCK_SIZE), Ok(0));
+    assert_matches!(
+        ram.read_t::<u32>(REGION_START + 2 * BLOCK_SIZE),
+        Err(mem::Error::Inaccessible { .. })
+    );
+
+    // Exceeds the requested size.
+    let addr = REGION_START + 4 * BLOCK_SIZE;
+    let resp = dev.handle_req(&req(VirtioMemReqType::PLUG, addr, 2), &ram);
+    assert_eq!(resp.type_, VirtioMemRespType::NACK.raw());
+
+    // Already plugged.
+    let addr = REGION_START + BLOCK_SIZE;
+    let resp = dev.handle_req(&req(VirtioMemReqType::PLUG, addr, 1), &ram);
+    assert_eq!(resp.type_, VirtioMemRespType::ERROR.raw());
+
+    for (addr, nb_blocks) in [
+        (REGION_START + 1, 1),
+        (REGION_START - BLOCK_SIZE, 1),
+        (REGION_START + 15 * BLOCK_SIZE, 2),
+        (REGION_START, 0),
+    ] {
+        let resp = dev.handle_req(&req(VirtioMemReqType::STATE, addr, nb_blocks), &ram);
+        assert_eq!(resp.type_, VirtioMemRespType::ERROR.raw());
+    }
+
+    for (nb_blocks, state) in [
+        (2, VirtioMemState::PLUGGED),
+        (3, VirtioMemState::MIXED),
+        (16, VirtioMemState::MIXED),
+    ] {
+        let resp = dev.handle_req(&req(VirtioMemReqType::STATE, REGION_START, nb_blocks), &ram);
+        assert_eq!(resp.type_, VirtioMemRespType::ACK.raw());
+        assert_eq!(resp.state, state.raw());
+    }
+    let addr = REGION_START + 2 * BLOCK_SIZE;
+    let resp = dev.handle_req(&req(VirtioMemReqType::STATE, addr, 14), &ram);
+    assert_eq!(resp.state, VirtioMemState::UNPLUGGED.raw());
+
+    ram.write_t(REGION_START + BLOCK_SIZE, &0xdeadbeef_u32)
+        .unwrap();
+
+    // Not all plugged.
+    let addr = REGION_START + BLOCK_SIZE;
+    let resp = dev.handle_req(&req(VirtioMemReqType::UNPLUG, addr, 2), &ram);
+    assert_eq!(resp.type_, VirtioMemRespType::ERROR.raw());
+
+    let resp = dev.handle_req(&req(VirtioMemReqType::UNPLUG, addr, 1), &ram);
+    assert_eq!(resp.type_, VirtioMemRespType::ACK.raw());
+    assert_eq!(config.plugged_size(), BLOCK_SIZE);
+    assert_matches!(
+        ram.read_t::<u32>(addr),
+        Err(mem::Error::Inaccessible { .. })
+    );
+
+    let resp = dev.handle_req(&req(VirtioMemReqType::PLUG, addr, 1), &ram);
+    assert_eq!(resp.type_, VirtioMemRespType::ACK.raw());
+    assert_matches!(ram.read_t::<u32>(addr), Ok(0));
+
+    let resp = dev.handle_req(&req(VirtioMemReqType::UNPLUG_ALL, 0, 0), &ram);
+    assert_eq!(resp.type_, VirtioMemRespType::ACK.raw());
+    assert_eq!(config.plugged_size(), 0);
+    let resp = dev.handle_req(&req(VirtioMemReqType::STATE, REGION_START, 16), &ram);
+    assert_eq!(resp.state, VirtioMemState::UNPLUGGED.raw());
+    assert_matches!(
+        ram.read_t::<u32>(REGION_START),
+        Err(mem::Error::Inaccessible { .. })
+    );
+
+    let resp = dev.handle_req(&req(VirtioMemReqType::from(7), REGION_START, 1), &ram);
+    assert_eq!(resp.type_, VirtioMemRespType::ERROR.raw());
+}
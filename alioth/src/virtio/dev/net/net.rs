@@ -12,17 +12,22 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+pub mod rss;
 #[cfg(target_os = "linux")]
 pub mod tap;
 #[cfg(target_os = "macos")]
 pub mod vmnet;
 
 use std::fmt::Debug;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
 
+use parking_lot::RwLock;
 use zerocopy::{FromBytes, Immutable, IntoBytes, KnownLayout};
 
 use crate::device::net::MacAddr;
-use crate::{bitflags, consts, impl_mmio_for_zerocopy};
+use crate::mem::emulated::{Action, Mmio};
+use crate::{bitflags, consts, impl_mmio_for_zerocopy, mem};
 
 #[repr(C, align(8))]
 #[derive(Debug, Default, FromBytes, Immutable, IntoBytes)]
@@ -40,6 +45,92 @@ pub struct NetConfig {
 
 impl_mmio_for_zerocopy!(NetConfig);
 
+bitflags! {
+    #[derive(Default)]
+    pub struct NetStatus(u16) {
+        LINK_UP = 1 << 0;
+        ANNOUNCE = 1 << 1;
+    }
+}
+
+#[derive(Debug)]
+pub struct NetConfigMmio {
+    name: Arc<str>,
+    config: RwLock<NetConfig>,
+    announce: AtomicBool,
+}
+
+impl NetConfigMmio {
+    pub fn new(name: Arc<str>, config: NetConfig) -> Self {
+        NetConfigMmio {
+            name,
+            config: RwLock::new(config),
+            announce: AtomicBool::new(false),
+        }
+    }
+
+    pub fn max_queue_pairs(&self) -> u16 {
+        self.config.read().max_queue_pairs
+    }
+
+    pub fn link_up(&self) -> bool {
+        let status = NetStatus::from_bits_retain(self.config.read().status);
+        status.contains(NetStatus::LINK_UP)
+    }
+
+    /// Updates the link state and returns whether the status field has changed.
+    ///
+    /// If the driver has negotiated `GUEST_ANNOUNCE`, bringing the link up
+    /// also asks the guest to send gratuitous packets.
+    pub fn set_link_up(&self, up: bool) -> bool {
+        let config = &mut *self.config.write();
+        let old = NetStatus::from_bits_retain(config.status);
+        let mut status = old;
+        status.set(NetStatus::LINK_UP, up);
+        if up && !old.contains(NetStatus::LINK_UP) && self.announce.load(Ordering::Acquire) {
+            status |= NetStatus::ANNOUNCE;
+        }
+        config.status = status.bits();
+        log::info!("{}: link status: {old:?} -> {status:?}", self.name);
+        old != status
+    }
+
+    pub(crate) fn set_announce(&self, enabled: bool) {
+        self.announce.store(enabled, Ordering::Release);
+    }
+
+    pub(crate) fn status(&self) -> NetStatus {
+        NetStatus::from_bits_retain(self.config.read().status)
+    }
+
+    pub(crate) fn set_status(&self, status: NetStatus) {
+        self.config.write().status = status.bits();
+    }
+
+    pub(crate) fn ack_announce(&self) {
+        let config = &mut *self.config.write();
+        let mut status = NetStatus::from_bits_retain(config.status);
+        status.remove(NetStatus::ANNOUNCE);
+        config.status = status.bits();
+    }
+}
+
+impl Mmio for NetConfigMmio {
+    fn size(&self) -> u64 {
+        size_of::<NetConfig>() as u64
+    }
+
+    fn read(&self, offset: u64, size: u8) -> mem::Result<u64> {
+        let config = self.config.read();
+        Mmio::read(&*config, offset, size)
+    }
+
+    fn write(&self, offset: u64, size: u8, val: u64) -> mem::Result<Action> {
+        let config = self.config.read();
+        Mmio::write(&*config, offset, size, val)
+    }
+}
+
 consts! {
     #[derive(Default, FromBytes, Immutable, IntoBytes)]
     pub struct CtrlAck(u8) {
@@ -51,14 +142,24 @@ consts! {
 consts! {
     #[derive(Default, FromBytes, Immutable, IntoBytes)]
     pub struct CtrlClass(u8) {
+        ANNOUNCE = 3;
         MQ = 4;
     }
 }
 
+consts! {
+    #[derive(Default, FromBytes, Immutable, IntoBytes)]
+    pub struct CtrlAnnounce(u8) {
+        ACK = 0;
+    }
+}
+
 consts! {
     #[derive(Default, FromBytes, Immutable, IntoBytes)]
     pub struct CtrlMq(u8) {
         VQ_PARIS_SET = 0;
+        RSS_CONFIG = 1;
+        HASH_CONFIG = 2;
     }
 }
 
@@ -121,3 +222,16 @@ pub struct VirtioNetHdr {
     pub csum_offset: u16,
     pub num_buffers: u16,
 }
+
+#[repr(C)]
+#[derive(Debug, Default, FromBytes, IntoBytes, KnownLayout, Immutable)]
+pub struct VirtioNetHdrHash {
+    pub hdr: VirtioNetHdr,
+    pub hash_value: u32,
+    pub hash_report: u16,
+    pub padding_reserved: u16,
+}
+
+#[cfg(test)]
+#[path = "net_test.rs"]
+mod tests;
@@ -0,0 +1,88 @@
+// Copyright 2026 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use assert_matches::assert_matches;
+
+use crate::device::net::MacAddr;
+use crate::mem::emulated::{Action, Mmio};
+use crate::virtio::dev::net::{NetConfig, NetConfigMmio, NetStatus};
+
+const OFFSET_STATUS: u64 = 6;
+
+fn new_config(status: NetStatus) -> NetConfigMmio {
+    let config = NetConfig {
+        mac: MacAddr([0x06, 0x3a, 0x76, 0x53, 0xda, 0x3d]),
+        status: status.bits(),
+        max_queue_pairs: 2,
+        ..Default::default()
+    };
+    NetConfigMmio::new("net".into(), config)
+}
+
+#[test]
+fn test_net_config_read() {
+    let config = new_config(NetStatus::LINK_UP);
+
+    assert_eq!(config.size(), size_of::<NetConfig>() as u64);
+    assert_matches!(config.read(0, 1), Ok(0x06));
+    assert_matches!(config.read(5, 1), Ok(0x3d));
+    assert_matches!(config.read(OFFSET_STATUS, 2), Ok(1));
+    assert_matches!(config.read(8, 2), Ok(2));
+    assert_eq!(config.max_queue_pairs(), 2);
+
+    assert_matches!(config.write(OFFSET_STATUS, 2, 0), Ok(Action::None));
+    assert!(config.link_up());
+}
+
+#[test]
+fn test_net_link() {
+    let config = new_config(NetStatus::LINK_UP);
+
+    assert!(!config.set_link_up(true));
+
+    assert!(config.set_link_up(false));
+    assert!(!config.link_up());
+    assert_matches!(config.read(OFFSET_STATUS, 2), Ok(0));
+
+    assert!(config.set_link_up(true));
+    assert!(config.link_up());
+    assert_matches!(config.read(OFFSET_STATUS, 2), Ok(1));
+}
+
+#[test]
+fn test_net_status() {
+    let config = new_config(NetStatus::LINK_UP);
+    assert_eq!(config.status(), NetStatus::LINK_UP);
+
+    config.set_status(NetStatus::ANNOUNCE);
+    assert_eq!(config.status(), NetStatus::ANNOUNCE);
+    assert!(!config.link_up());
+    assert_matches!(config.read(OFFSET_STATUS, 2), Ok(2));
+}
+
+#[test]
+fn test_net_link_announce() {
+    let config = new_config(NetStatus::empty());
+    config.set_announce(true);
+
+    assert!(config.set_link_up(true));
+    let status = (NetStatus::LINK_UP | NetStatus::ANNOUNCE).bits() as u64;
+    assert_matches!(config.read(OFFSET_STATUS, 2), Ok(s) if s == status);
+
+    config.ack_announce();
+    assert_matches!(config.read(OFFSET_STATUS, 2), Ok(1));
+
+    assert!(!config.set_link_up(true));
+    assert_matches!(config.read(OFFSET_STATUS, 2), Ok(1));
+}
@@ -0,0 +1,248 @@
+// Copyright 2026 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use zerocopy::{FromBytes, Immutable, IntoBytes, KnownLayout};
+
+use crate::{bitflags, consts};
+
+pub const RSS_MAX_KEY_SIZE: u8 = 40;
+pub const RSS_MAX_INDIRECTION_TABLE_LENGTH: u16 = 128;
+
+bitflags! {
+    #[derive(Default)]
+    pub struct HashType(u32) {
+        IPV4 = 1 << 0;
+        TCPV4 = 1 << 1;
+        UDPV4 = 1 << 2;
+        IPV6 = 1 << 3;
+        TCPV6 = 1 << 4;
+        UDPV6 = 1 << 5;
+        IP_EX = 1 << 6;
+        TCP_EX = 1 << 7;
+        UDP_EX = 1 << 8;
+    }
+}
+
+pub const SUPPORTED_HASH_TYPES: HashType = HashType::IPV4
+    .union(HashType::TCPV4)
+    .union(HashType::UDPV4)
+    .union(HashType::IPV6)
+    .union(HashType::TCPV6)
+    .union(HashType::UDPV6);
+
+consts! {
+    #[derive(Default, FromBytes, Immutable, IntoBytes)]
+    pub struct HashReport(u16) {
+        NONE = 0;
+        IPV4 = 1;
+        TCPV4 = 2;
+        UDPV4 = 3;
+        IPV6 = 4;
+        TCPV6 = 5;
+        UDPV6 = 6;
+        IPV6_EX = 7;
+        TCPV6_EX = 8;
+        UDPV6_EX = 9;
+    }
+}
+
+#[repr(C)]
+#[derive(Debug, Default, FromBytes, Immutable, IntoBytes, KnownLayout)]
+pub struct CtrlRssConfig {
+    pub hash_types: u32,
+    pub indirection_table_mask: u16,
+    pub unclassified_queue: u16,
+}
+
+#[repr(C)]
+#[derive(Debug, Default, FromBytes, Immutable, IntoBytes, KnownLayout)]
+pub struct CtrlHashConfig {
+    pub hash_types: u32,
+    pub reserved: [u16; 4],
+}
+
+const ETH_P_IP: u16 = 0x0800;
+const ETH_P_IPV6: u16 = 0x86dd;
+const ETH_P_8021Q: u16 = 0x8100;
+
+const IPPROTO_TCP: u8 = 6;
+const IPPROTO_UDP: u8 = 17;
+
+const HASH_INPUT_MAX_LEN: usize = 36;
+
+/// Computes the Toeplitz hash of `input` with `key`.
+pub fn toeplitz_hash(key: &[u8], input: &[u8]) -> u32 {
+    let key_bit = |n: usize| match key.get(n >> 3) {
+        Some(b) => ((b >> (7 - (n & 7))) & 1) as u32,
+        None => 0,
+    };
+    let mut window = 0u32;
+    for n in 0..32 {
+        window = (window << 1) | key_bit(n);
+    }
+    let mut hash = 0;
+    for (index, byte) in input.iter().enumerate() {
+        for bit in 0..8 {
+            if byte & (0x80 >> bit) != 0 {
+                hash ^= window;
+            }
+            window = (window << 1) | key_bit(index * 8 + bit + 32);
+        }
+    }
+    hash
+}
+
+fn parse_ethernet(frame: &[u8]) -> Option<(u16, &[u8])> {
+    let ether_type = |offset: usize| {
+        let b = frame.get(offset..offset + 2)?;
+        Some(u16::from_be_bytes([b[0], b[1]]))
+    };
+    match ether_type(12)? {
+        ETH_P_8021Q => Some((ether_type(16)?, frame.get(18..)?)),
+        t => Some((t, frame.get(14..)?)),
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Rss {
+    pub hash_types: HashType,
+    pub key: Box<[u8]>,
+    pub indirection_table: Box<[u16]>,
+    pub unclassified_queue: u16,
+}
+
+impl Rss {
+    /// Parses the payload of `VIRTIO_NET_CTRL_MQ_RSS_CONFIG`.
+    ///
+    /// Returns the new configuration and `max_tx_vq`.
+    pub fn from_rss_config(data: &[u8]) -> Option<(Self, u16)> {
+        let (config, remain) = CtrlRssConfig::read_from_prefix(data).ok()?;
+        let table_len = config.indirection_table_mask as usize + 1;
+        if !table_len.is_power_of_two() || table_len > RSS_MAX_INDIRECTION_TABLE_LENGTH as usize {
+            return None;
+        }
+        let (table, remain) = remain.split_at_checked(table_len * size_of::<u16>())?;
+        let indirection_table = table
+            .chunks_exact(size_of::<u16>())
+            .map(|b| u16::from_le_bytes([b[0], b[1]]))
+            .collect();
+        let (max_tx_vq, remain) = u16::read_from_prefix(remain).ok()?;
+        let key = Self::parse_key(remain)?;
+        let rss = Rss {
+            hash_types: HashType::from_bits_truncate(config.hash_types) & SUPPORTED_HASH_TYPES,
+            key,
+            indirection_table,
+            unclassified_queue: config.unclassified_queue,
+        };
+        Some((rss, max_tx_vq))
+    }
+
+    /// Parses the payload of `VIRTIO_NET_CTRL_MQ_HASH_CONFIG`.
+    pub fn from_hash_config(data: &[u8]) -> Option<Self> {
+        let (config, remain) = CtrlHashConfig::read_from_prefix(data).ok()?;
+        let key = Self::parse_key(remain)?;
+        let rss = Rss {
+            hash_types: HashType::from_bits_truncate(config.hash_types) & SUPPORTED_HASH_TYPES,
+            key,
+            ..Default::default()
+        };
+        Some(rss)
+    }
+
+    fn parse_key(data: &[u8]) -> Option<Box<[u8]>> {
+        let (&key_len, remain) = data.split_first()?;
+        if key_len > RSS_MAX_KEY_SIZE {
+            return None;
+        }
+        remain.get(..key_len as usize).map(Box::from)
+    }
+
+    fn hash_input(
+        &self,
+        frame: &[u8],
+        input: &mut [u8; HASH_INPUT_MAX_LEN],
+    ) -> Option<(usize, HashReport)> {
+        let (ether_type, packet) = parse_ethernet(frame)?;
+        let types = self.hash_types;
+        match ether_type {
+            ETH_P_IP => {
+                let ihl = ((packet.first()? & 0xf) as usize) << 2;
+                let header = packet.get(..ihl).filter(|h| h.len() >= 20)?;
+                let fragmented = u16::from_be_bytes([header[6], header[7]]) & 0x3fff != 0;
+                let ports = if fragmented {
+                    None
+                } else {
+                    packet.get(ihl..ihl + 4)
+                };
+                input[..8].copy_from_slice(&header[12..20]);
+                match (header[9], ports) {
+                    (IPPROTO_TCP, Some(ports)) if types.contains(HashType::TCPV4) => {
+                        input[8..12].copy_from_slice(ports);
+                        Some((12, HashReport::TCPV4))
+                    }
+                    (IPPROTO_UDP, Some(ports)) if types.contains(HashType::UDPV4) => {
+                        input[8..12].copy_from_slice(ports);
+                        Some((12, HashReport::UDPV4))
+                    }
+                    _ if types.contains(HashType::IPV4) => Some((8, HashReport::IPV4)),
+                    _ => None,
+                }
+            }
+            ETH_P_IPV6 => {
+                let header = packet.get(..40)?;
+                let ports = packet.get(40..44);
+                input[..32].copy_from_slice(&header[8..40]);
+                match (header[6], ports) {
+                    (IPPROTO_TCP, Some(ports)) if types.contains(HashType::TCPV6) => {
+                        input[32..36].copy_from_slice(ports);
+                        Some((36, HashReport::TCPV6))
+                    }
+                    (IPPROTO_UDP, Some(ports)) if types.contains(HashType::UDPV6) => {
+                        input[32..36].copy_from_slice(ports);
+                        Some((36, HashReport::UDPV6))
+                    }
+                    _ if types.contains(HashType::IPV6) => Some((32, HashReport::IPV6)),
+                    _ => None,
+                }
+            }
+            _ => None,
+        }
+    }
+
+    /// Computes the hash of an Ethernet frame according to the enabled hash
+    /// types.
+    pub fn hash(&self, frame: &[u8]) -> Option<(u32, HashReport)> {
+        let mut input = [0u8; HASH_INPUT_MAX_LEN];
+        let (len, report) = self.hash_input(frame, &mut input)?;
+        Some((toeplitz_hash(&self.key, &input[..len]), report))
+    }
+
+    /// Selects a receive queue for a packet with `hash`.
+    ///
+    /// Returns `None` if the indirection table has not been configured.
+    pub fn select_queue(&self, hash: Option<u32>) -> Option<u16> {
+        let table = &self.indirection_table;
+        if table.is_empty() {
+            return None;
+        }
+        match hash {
+            Some(hash) => Some(table[hash as usize & (table.len() - 1)]),
+            None => Some(self.unclassified_queue),
+        }
+    }
+}
+
+#[cfg(test)]
+#[path = "rss_test.rs"]
+mod tests;
@@ -0,0 +1,231 @@
+// Copyright 2026 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::net::{Ipv4Addr, Ipv6Addr};
+
+use assert_matches::assert_matches;
+use rstest::rstest;
+use zerocopy::IntoBytes;
+
+use crate::virtio::dev::net::rss::{
+    CtrlHashConfig, CtrlRssConfig, HashReport, HashType, Rss, SUPPORTED_HASH_TYPES, toeplitz_hash,
+};
+
+// The default key from the Microsoft RSS verification suite.
+const KEY: [u8; 40] = [
+    0x6d, 0x5a, 0x56, 0xda, 0x25, 0x5b, 0x0e, 0xc2, 0x41, 0x67, 0x25, 0x3d, 0x43, 0xa3, 0x8f, 0xb0,
+    0xd0, 0xca, 0x2b, 0xcb, 0xae, 0x7b, 0x30, 0xb4, 0x77, 0xcb, 0x2d, 0xa3, 0x80, 0x30, 0xf2, 0x0c,
+    0x6a, 0x42, 0xb7, 0x3b, 0xbe, 0xac, 0x01, 0xfa,
+];
+
+fn ipv4_frame(src: Ipv4Addr, dst: Ipv4Addr, protocol: u8, ports: (u16, u16), frag: u16) -> Vec<u8> {
+    let mut frame = vec![0u8; 12];
+    frame.extend_from_slice(&0x0800u16.to_be_bytes());
+    let mut header = [0u8; 20];
+    header[0] = 0x45;
+    header[6..8].copy_from_slice(&frag.to_be_bytes());
+    header[9] = protocol;
+    header[12..16].copy_from_slice(&src.octets());
+    header[16..20].copy_from_slice(&dst.octets());
+    frame.extend_from_slice(&header);
+    frame.extend_from_slice(&ports.0.to_be_bytes());
+    frame.extend_from_slice(&ports.1.to_be_bytes());
+    frame.extend_from_slice(&[0u8; 16]);
+    frame
+}
+
+fn ipv6_frame(src: Ipv6Addr, dst: Ipv6Addr, next_header: u8, ports: (u16, u16)) -> Vec<u8> {
+    let mut frame = vec![0u8; 12];
+    frame.extend_from_slice(&0x8100u16.to_be_bytes());
+    frame.extend_from_slice(&[0, 1]);
+    frame.extend_from_slice(&0x86ddu16.to_be_bytes());
+    let mut header = [0u8; 40];
+    header[0] = 0x60;
+    header[6] = next_header;
+    header[8..24].copy_from_slice(&src.octets());
+    header[24..40].copy_from_slice(&dst.octets());
+    frame.extend_from_slice(&header);
+    frame.extend_from_slice(&ports.0.to_be_bytes());
+    frame.extend_from_slice(&ports.1.to_be_bytes());
+    frame
+}
+
+fn new_rss(hash_types: HashType) -> Rss {
+    Rss {
+        hash_types,
+        key: Box::new(KEY),
+        ..Default::default()
+    }
+}
+
+#[rstest]
+#[case("66.9.149.187", "161.142.100.80", 2794, 1766, 0x323e8fc2, 0x51ccc178)]
+#[case("199.92.111.2", "65.69.140.83", 14230, 4739, 0xd718262a, 0xc626b0ea)]
+#[case("24.19.198.95", "12.22.207.184", 12898, 38024, 0xd2d0a5de, 0x5c2b394a)]
+#[case("38.27.205.30", "209.142.163.6", 48228, 2217, 0x82989176, 0xafc7327f)]
+#[case("153.39.163.191", "202.188.127.2", 44251, 1303, 0x5d1809c5, 0x10e828a2)]
+fn test_toeplitz_ipv4(
+    #[case] src: Ipv4Addr,
+    #[case] dst: Ipv4Addr,
+    #[case] src_port: u16,
+    #[case] dst_port: u16,
+    #[case] ip_hash: u32,
+    #[case] tcp_hash: u32,
+) {
+    let mut input = [src.octets(), dst.octets()].concat();
+    assert_eq!(toeplitz_hash(&KEY, &input), ip_hash);
+    input.extend_from_slice(&src_port.to_be_bytes());
+    input.extend_from_slice(&dst_port.to_be_bytes());
+    assert_eq!(toeplitz_hash(&KEY, &input), tcp_hash);
+}
+
+#[rstest]
+#[case(
+    "3ffe:2501:200:1fff::7",
+    "3ffe:2501:200:3::1",
+    2794,
+    1766,
+    0x2cc18cd5,
+    0x40207d3d
+)]
+#[case(
+    "3ffe:501:8::260:97ff:fe40:efab",
+    "ff02::1",
+    14230,
+    4739,
+    0x0f0c461c,
+    0xdde51bbf
+)]
+#[case(
+    "3ffe:1900:4545:3:200:f8ff:fe21:67cf",
+    "fe80::200:f8ff:fe21:67cf",
+    44251,
+    38024,
+    0x4b61e985,
+    0x02d1feef
+)]
+fn test_toeplitz_ipv6(
+    #[case] src: Ipv6Addr,
+    #[case] dst: Ipv6Addr,
+    #[case] src_port: u16,
+    #[case] dst_port: u16,
+    #[case] ip_hash: u32,
+    #[case] tcp_hash: u32,
+) {
+    let mut input = [src.octets(), dst.octets()].concat();
+    assert_eq!(toeplitz_hash(&KEY, &input), ip_hash);
+    input.extend_from_slice(&src_port.to_be_bytes());
+    input.extend_from_slice(&dst_port.to_be_bytes());
+    assert_eq!(toeplitz_hash(&KEY, &input), tcp_hash);
+}
+
+#[rstest]
+#[case(SUPPORTED_HASH_TYPES, 6, 0, Some((0x51ccc178, HashReport::TCPV4)))]
+#[case(SUPPORTED_HASH_TYPES, 17, 0, Some((0x51ccc178, HashReport::UDPV4)))]
+#[case(SUPPORTED_HASH_TYPES, 1, 0, Some((0x323e8fc2, HashReport::IPV4)))]
+#[case(SUPPORTED_HASH_TYPES, 6, 0x2000, Some((0x323e8fc2, HashReport::IPV4)))]
+#[case(HashType::IPV4, 6, 0, Some((0x323e8fc2, HashReport::IPV4)))]
+#[case(HashType::TCPV4, 17, 0, None)]
+#[case(HashType::IPV6, 6, 0, None)]
+fn test_rss_hash_ipv4(
+    #[case] hash_types: HashType,
+    #[case] protocol: u8,
+    #[case] frag: u16,
+    #[case] expected: Option<(u32, HashReport)>,
+) {
+    let rss = new_rss(hash_types);
+    let src = Ipv4Addr::new(66, 9, 149, 187);
+    let dst = Ipv4Addr::new(161, 142, 100, 80);
+    let frame = ipv4_frame(src, dst, protocol, (2794, 1766), frag);
+    assert_eq!(rss.hash(&frame), expected);
+}
+
+#[rstest]
+#[case(SUPPORTED_HASH_TYPES, 6, Some((0x40207d3d, HashReport::TCPV6)))]
+#[case(SUPPORTED_HASH_TYPES, 17, Some((0x40207d3d, HashReport::UDPV6)))]
+#[case(HashType::IPV6 | HashType::UDPV6, 6, Some((0x2cc18cd5, HashReport::IPV6)))]
+#[case(HashType::IPV4, 6, None)]
+fn test_rss_hash_ipv6(
+    #[case] hash_types: HashType,
+    #[case] next_header: u8,
+    #[case] expected: Option<(u32, HashReport)>,
+) {
+    let rss = new_rss(hash_types);
+    let src = "3ffe:2501:200:1fff::7".parse().unwrap();
+    let dst = "3ffe:2501:200:3::1".parse().unwrap();
+    let frame = ipv6_frame(src, dst, next_header, (2794, 1766));
+    assert_eq!(rss.hash(&frame), expected);
+}
+
+#[test]
+fn test_rss_hash_invalid() {
+    let rss = new_rss(SUPPORTED_HASH_TYPES);
+    assert_eq!(rss.hash(&[]), None);
+    assert_eq!(rss.hash(&[0u8; 20]), None);
+
+    let frame = ipv4_frame(Ipv4Addr::LOCALHOST, Ipv4Addr::LOCALHOST, 6, (1, 2), 0);
+    assert_eq!(rss.hash(&frame[..30]), None);
+}
+
+#[test]
+fn test_rss_config() {
+    let config = CtrlRssConfig {
+        hash_types: (HashType::TCPV4 | HashType::IP_EX).bits(),
+        indirection_table_mask: 3,
+        unclassified_queue: 1,
+    };
+    let mut data = config.as_bytes().to_vec();
+    for q in [0u16, 1, 2, 3] {
+        data.extend_from_slice(&q.to_le_bytes());
+    }
+    data.extend_from_slice(&4u16.to_le_bytes());
+    data.push(KEY.len() as u8);
+    data.extend_from_slice(&KEY);
+
+    let (rss, max_tx_vq) = Rss::from_rss_config(&data).unwrap();
+    assert_eq!(max_tx_vq, 4);
+    assert_eq!(rss.hash_types, HashType::TCPV4);
+    assert_eq!(&*rss.key, &KEY);
+    assert_eq!(&*rss.indirection_table, &[0, 1, 2, 3]);
+    assert_eq!(rss.unclassified_queue, 1);
+
+    assert_eq!(rss.select_queue(None), Some(1));
+    assert_eq!(rss.select_queue(Some(0x51ccc178)), Some(0));
+    assert_eq!(rss.select_queue(Some(0xd718262a)), Some(2));
+
+    assert_matches!(Rss::from_rss_config(&data[..data.len() - 1]), None);
+
+    let mut data_invalid_mask = data.clone();
+    data_invalid_mask[4] = 2;
+    assert_matches!(Rss::from_rss_config(&data_invalid_mask), None);
+}
+
+#[test]
+fn test_hash_config() {
+    let config = CtrlHashConfig {
+        hash_types: SUPPORTED_HASH_TYPES.bits(),
+        reserved: [0; 4],
+    };
+    let mut data = config.as_bytes().to_vec();
+    data.push(KEY.len() as u8);
+    data.extend_from_slice(&KEY);
+
+    let rss = Rss::from_hash_config(&data).unwrap();
+    assert_eq!(rss.hash_types, SUPPORTED_HASH_TYPES);
+    assert_eq!(&*rss.key, &KEY);
+    assert_eq!(rss.select_queue(Some(1)), None);
+
+    data[12] = 41;
+    assert_matches!(Rss::from_hash_config(&data), None);
+}
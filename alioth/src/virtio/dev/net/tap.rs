@@ -15,7 +15,7 @@
 use std::cmp::max;
 use std::fmt::Debug;
 use std::fs::{File, OpenOptions};
-use std::io::{ErrorKind, IoSlice};
+use std::io::{ErrorKind, IoSlice, Read};
 use std::mem::MaybeUninit;
 use std::os::fd::{AsFd, AsRawFd};
 use std::os::unix::prelude::OpenOptionsExt;
@@ -33,15 +33,20 @@ use mio::unix::SourceFd;
 use mio::{Interest, Registry, Token};
 use serde::Deserialize;
 use serde_aco::Help;
-use zerocopy::{FromBytes, IntoBytes};
+use zerocopy::{FromBytes, Immutable, IntoBytes, KnownLayout};
 
 use crate::device::net::MacAddr;
 use crate::hv::IoeventFd;
 use crate::mem::mapped::RamBus;
 use crate::sync::notifier::Notifier;
 use crate::sys::if_tun::{TunFeature, tun_set_iff, tun_set_offload, tun_set_vnet_hdr_sz};
+use crate::virtio::dev::net::rss::{
+    HashReport, HashType, RSS_MAX_INDIRECTION_TABLE_LENGTH, RSS_MAX_KEY_SIZE, Rss,
+    SUPPORTED_HASH_TYPES,
+};
 use crate::virtio::dev::net::{
-    CtrlAck, CtrlClass, CtrlHdr, CtrlMq, CtrlMqParisSet, NetConfig, NetFeature, VirtioNetHdr,
+    CtrlAck, CtrlAnnounce, CtrlClass, CtrlHdr, CtrlMq, CtrlMqParisSet, NetConfig, NetConfigMmio,
+    NetFeature, NetStatus, VirtioNetHdr, VirtioNetHdrHash,
 };
 use crate::virtio::dev::{DevParam, DeviceId, Result, Virtio, WakeEvent};
 use crate::virtio::queue::{
@@ -55,15 +60,45 @@ use crate::virtio::{FEATURE_BUILT_IN, IrqSender, error};
 #[derive(Debug)]
 pub struct Net {
     name: Arc<str>,
-    config: Arc<NetConfig>,
+    config: Arc<NetConfigMmio>,
     tap_sockets: Vec<File>,
     feature: NetFeature,
     driver_feature: NetFeature,
     dev_tap: Option<Box<Path>>,
     if_name: Option<String>,
     api: WorkerApi,
+    rss: Rss,
+    rx_buf: Vec<u8>,
+    rx_pending: Vec<PendingPacket>,
+    restored_pairs: Option<u16>,
+}
+
+/// State of a [`Net`] saved in a snapshot, followed by the RSS key and the
+/// indirection table.
+#[repr(C)]
+#[derive(Debug, Default, FromBytes, Immutable, IntoBytes, KnownLayout)]
+struct NetState {
+    status: u16,
+    queue_pairs: u16,
+    hash_types: u32,
+    unclassified_queue: u16,
+    key_len: u16,
+    table_len: u16,
+    reserved: u16,
 }
 
+/// A packet read from a tap queue while its receive queue had no buffers.
+///
+/// The tap queue is not read again until the packet is delivered.
+#[derive(Debug)]
+struct PendingPacket {
+    tap: usize,
+    rx_queue: usize,
+    data: Box<[u8]>,
+}
+
+const RX_BUF_SIZE: usize = (1 << 16) + size_of::<VirtioNetHdrHash>();
+
 #[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize, Help)]
 pub struct NetTapParam {
     /// MAC address of the virtual NIC, e.g. 06:3a:76:53:da:3d.
@@ -114,7 +149,13 @@ impl Net {
             matches!(param.api, WorkerApi::IoUring),
         )?;
         let max_queue_pairs = max(param.queue_pairs, 1);
-        setup_socket(&mut socket, param.if_name.as_deref(), max_queue_pairs > 1)?;
+        let hdr_len = size_of::<VirtioNetHdr>();
+        setup_socket(
+            &mut socket,
+            param.if_name.as_deref(),
+            max_queue_pairs > 1,
+            hdr_len,
+        )?;
         let mut dev_feat = NetFeature::MAC
             | NetFeature::MTU
             | NetFeature::CSUM
@@ -124,28 +165,253 @@ impl Net {
             | NetFeature::HOST_UFO
             | NetFeature::HOST_USO
             | NetFeature::CTRL_VQ
+            | NetFeature::STATUS
+            | NetFeature::GUEST_ANNOUNCE
             | detect_tap_offload(&socket);
         if max_queue_pairs > 1 {
             dev_feat |= NetFeature::MQ;
         }
+        let mut config = NetConfig {
+            mac: param.mac,
+            status: NetStatus::LINK_UP.bits(),
+            max_queue_pairs,
+            mtu: param.mtu,
+            ..Default::default()
+        };
+        // Packets are steered in software, which is only implemented in the
+        // mio worker.
+        if param.api == WorkerApi::Mio {
+            dev_feat |= NetFeature::HASH_REPORT;
+            config.rss_max_key_size = RSS_MAX_KEY_SIZE;
+            config.supported_hash_types = SUPPORTED_HASH_TYPES.bits();
+            if max_queue_pairs > 1 {
+                dev_feat |= NetFeature::RSS;
+                config.rss_max_indirection_table_length = RSS_MAX_INDIRECTION_TABLE_LENGTH;
+            }
+        }
+        let name = name.into();
         let net = Net {
-            name: name.into(),
-            config: Arc::new(NetConfig {
-                mac: param.mac,
-                max_queue_pairs,
-                mtu: param.mtu,
-                ..Default::default()
-            }),
+            name: name.clone(),
+            config: Arc::new(NetConfigMmio::new(name, config)),
             tap_sockets: vec![socket],
             feature: dev_feat,
             driver_feature: NetFeature::empty(),
             dev_tap: param.tap,
             if_name: param.if_name,
             api: param.api,
+            rss: Rss::default(),
+            rx_buf: Vec::new(),
+            rx_pending: Vec::new(),
+            restored_pairs: None,
         };
         Ok(net)
     }
 
+    fn hdr_len(&self) -> usize {
+        if self.driver_feature.contains(NetFeature::HASH_REPORT) {
+            size_of::<VirtioNetHdrHash>()
+        } else {
+            size_of::<VirtioNetHdr>()
+        }
+    }
+
+    fn steering(&self) -> bool {
+        self.driver_feature
+            .intersects(NetFeature::RSS | NetFeature::HASH_REPORT)
+    }
+
+    fn set_queue_pairs(&mut self, pairs: usize, registry: Option<&Registry>) -> Result<()> {
+        self.tap_sockets.truncate(pairs);
+        for index in self.tap_sockets.len()..pairs {
+            let mut socket = new_socket(
+                self.dev_tap.as_deref(),
+                matches!(self.api, WorkerApi::IoUring),
+            )?;
+            setup_socket(&mut socket, self.if_name.as_deref(), true, self.hdr_len())?;
+            enable_tap_offload(&mut socket, self.driver_feature)?;
+            if let Some(r) = registry {
+                r.register(
+                    &mut SourceFd(&socket.as_raw_fd()),
+                    Token(index),
+                    Interest::READABLE | Interest::WRITABLE,
+                )?;
+            }
+            self.tap_sockets.push(socket);
+        }
+        log::info!("{}: using {pairs} pairs of queues", self.name);
+        Ok(())
+    }
+
+    fn set_rss_config(&mut self, data: &[u8], registry: Option<&Registry>) -> Result<CtrlAck> {
+        if !self.driver_feature.contains(NetFeature::RSS) {
+            return Ok(CtrlAck::ERR);
+        }
+        let Some((rss, max_tx_vq)) = Rss::from_rss_config(data) else {
+            return Ok(CtrlAck::ERR);
+        };
+        let max_pairs = self.config.max_queue_pairs();
+        let rx_queues = rss
+            .indirection_table
+            .iter()
+            .chain([&rss.unclassified_queue]);
+        let Some(max_rx_vq) = rx_queues.max().map(|q| q + 1) else {
+            return Ok(CtrlAck::ERR);
+        };
+        if max_tx_vq == 0 || max_tx_vq > max_pairs || max_rx_vq > max_pairs {
+            return Ok(CtrlAck::ERR);
+        }
+        self.set_queue_pairs(max(max_tx_vq, max_rx_vq) as usize, registry)?;
+        log::info!("{}: RSS hash types: {:?}", self.name, rss.hash_types);
+        self.rss = rss;
+        Ok(CtrlAck::OK)
+    }
+
+    fn set_hash_config(&mut self, data: &[u8]) -> CtrlAck {
+        if !self.driver_feature.contains(NetFeature::HASH_REPORT) {
+            return CtrlAck::ERR;
+        }
+        let Some(rss) = Rss::from_hash_config(data) else {
+            return CtrlAck::ERR;
+        };
+        log::info!("{}: hash report types: {:?}", self.name, rss.hash_types);
+        self.rss = rss;
+        CtrlAck::OK
+    }
+
+    /// Reads and drops packets from a tap queue while the link is down.
+    fn discard_packets(&mut self, index: usize) -> Result<()> {
+        let Some(mut socket) = self.tap_sockets.get(index) else {
+            log::error!("{}: cannot find tap queue {index}", self.name);
+            return Ok(());
+        };
+        self.rx_pending.retain(|p| p.tap != index);
+        if self.rx_buf.is_empty() {
+            self.rx_buf = vec![0; RX_BUF_SIZE];
+        }
+        loop {
+            match socket.read(&mut self.rx_buf) {
+                Ok(0) => break,
+                Ok(len) => log::trace!("{}: link down, dropped {len} bytes", self.name),
+                Err(e) if e.kind() == ErrorKind::WouldBlock => break,
+                Err(e) => return Err(e.into()),
+            }
+        }
+        Ok(())
+    }
+
+    /// Copies a packet to a receive queue and returns whether it has been
+    /// consumed.
+    fn deliver_packet<'m, Q, S, E>(
+        &self,
+        rx_queue_index: usize,
+        data: &[u8],
+        active_mio: &mut ActiveMio<'_, '_, 'm, Q, S, E>,
+    ) -> Result<bool>
+    where
+        Q: VirtQueue<'m>,
+        S: IrqSender,
+        E: IoeventFd,
+    {
+        let Some(Some(queue)) = active_mio.queues.get_mut(rx_queue_index) else {
+            log::error!("{}: cannot find rx queue {rx_queue_index}", self.name);
+            return Ok(true);
+        };
+        let mut packet = Some(data);
+        queue.handle_desc(rx_queue_index as u16, active_mio.irq_sender, |chain| {
+            let Some(mut data) = packet.take() else {
+                return Ok(Status::Break);
+            };
+            let len = data.read_vectored(&mut chain.writable)?;
+            Ok(Status::Done { len: len as u32 })
+        })?;
+        Ok(packet.is_none())
+    }
+
+    fn steer_packets<'m, Q, S, E>(
+        &mut self,
+        index: usize,
+        active_mio: &mut ActiveMio<'_, '_, 'm, Q, S, E>,
+    ) -> Result<()>
+    where
+        Q: VirtQueue<'m>,
+        S: IrqSender,
+        E: IoeventFd,
+    {
+        if self.rx_pending.iter().any(|p| p.tap == index) {
+            return Ok(());
+        }
+        let Some(mut socket) = self.tap_sockets.get(index) else {
+            log::error!("{}: cannot find tap queue {index}", self.name);
+            return Ok(());
+        };
+        if self.rx_buf.is_empty() {
+            self.rx_buf = vec![0; RX_BUF_SIZE];
+        }
+        let hdr_len = self.hdr_len();
+        let hash_report = self.driver_feature.contains(NetFeature::HASH_REPORT);
+        loop {
+            let len = match socket.read(&mut self.rx_buf) {
+                Ok(len) if len >= hdr_len => len,
+                Ok(_) => break,
+                Err(e) if e.kind() == ErrorKind::WouldBlock => break,
+                Err(e) => return Err(e.into()),
+            };
+            let hash = self.rss.hash(&self.rx_buf[hdr_len..len]);
+            if hash_report {
+                let (mut hdr, _) = VirtioNetHdrHash::read_from_prefix(&self.rx_buf).unwrap();
+                let (value, report) = hash.unwrap_or((0, HashReport::NONE));
+                hdr.hdr.num_buffers = 1;
+                hdr.hash_value = value;
+                hdr.hash_report = report.raw();
+                hdr.padding_reserved = 0;
+                hdr.write_to_prefix(&mut self.rx_buf).unwrap();
+            }
+            let rx_index = self.rss.select_queue(hash.map(|(v, _)| v));
+            let rx_queue_index = (rx_index.unwrap_or(index as u16) as usize) << 1;
+            if !self.deliver_packet(rx_queue_index, &self.rx_buf[..len], active_mio)? {
+                log::trace!("{}: rx queue {rx_queue_index} is full", self.name);
+                self.rx_pending.push(PendingPacket {
+                    tap: index,
+                    rx_queue: rx_queue_index,
+                    data: self.rx_buf[..len].into(),
+                });
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    /// Delivers packets held back for full receive queues and resumes
+    /// reading from their tap queues.
+    fn flush_pending<'m, Q, S, E>(
+        &mut self,
+        active_mio: &mut ActiveMio<'_, '_, 'm, Q, S, E>,
+    ) -> Result<()>
+    where
+        Q: VirtQueue<'m>,
+        S: IrqSender,
+        E: IoeventFd,
+    {
+        let mut resumed = vec![];
+        for packet in std::mem::take(&mut self.rx_pending) {
+            let pairs = self.tap_sockets.len();
+            if packet.tap >= pairs {
+                continue;
+            }
+            if packet.rx_queue >> 1 >= pairs {
+                log::trace!("{}: rx queue {} is disabled", self.name, packet.rx_queue);
+            } else if !self.deliver_packet(packet.rx_queue, &packet.data, active_mio)? {
+                self.rx_pending.push(packet);
+                continue;
+            }
+            resumed.push(packet.tap);
+        }
+        for tap in resumed {
+            self.steer_packets(tap, active_mio)?;
+        }
+        Ok(())
+    }
+
     fn handle_ctrl_queue(
         &mut self,
         desc: &mut DescChain,
@@ -162,33 +428,36 @@ impl Net {
             return error::InvalidBuffer.fail();
         };
         let ack = match header.class {
+            CtrlClass::ANNOUNCE => match CtrlAnnounce(header.command) {
+                CtrlAnnounce::ACK => {
+                    self.config.ack_announce();
+                    CtrlAck::OK
+                }
+                _ => CtrlAck::ERR,
+            },
             CtrlClass::MQ => match CtrlMq(header.command) {
                 CtrlMq::VQ_PARIS_SET => {
                     let to_set = |b: &IoSlice| CtrlMqParisSet::read_from_bytes(b).ok();
                     let Some(data) = desc.readable.get(1).and_then(to_set) else {
                         return error::InvalidBuffer.fail();
                     };
-                    let pairs = data.virtq_pairs as usize;
-                    self.tap_sockets.truncate(pairs);
-                    for index in self.tap_sockets.len()..pairs {
-                        let mut socket = new_socket(
-                            self.dev_tap.as_deref(),
-                            matches!(self.api, WorkerApi::IoUring),
-                        )?;
-                        setup_socket(&mut socket, self.if_name.as_deref(), true)?;
-                        enable_tap_offload(&mut socket, self.driver_feature)?;
-                        if let Some(r) = registry {
-                            r.register(
-                                &mut SourceFd(&socket.as_raw_fd()),
-                                Token(index),
-                                Interest::READABLE | Interest::WRITABLE,
-                            )?;
-                        }
-                        self.tap_sockets.push(socket);
-                    }
-                    log::info!("{}: using {pairs} pairs of queues", self.name);
+                    self.set_queue_pairs(data.virtq_pairs as usize, registry)?;
                     CtrlAck::OK
                 }
+                CtrlMq::RSS_CONFIG => {
+                    let data: Vec<u8> = desc.readable[1..]
+                        .iter()
+                        .flat_map(|b| b.iter().copied())
+                        .collect();
+                    self.set_rss_config(&data, registry)?
+                }
+                CtrlMq::HASH_CONFIG => {
+                    let data: Vec<u8> = desc.readable[1..]
+                        .iter()
+                        .flat_map(|b| b.iter().copied())
+                        .collect();
+                    self.set_hash_config(&data)
+                }
                 _ => CtrlAck::ERR,
             },
             _ => CtrlAck::ERR,
@@ -199,7 +468,7 @@ impl Net {
 }
 
 impl Virtio for Net {
-    type Config = NetConfig;
+    type Config = NetConfigMmio;
     type Feature = NetFeature;
 
     fn id(&self) -> DeviceId {
@@ -211,7 +480,7 @@ impl Virtio for Net {
     }
 
     fn num_queues(&self) -> u16 {
-        let data_queues = self.config.max_queue_pairs << 1;
+        let data_queues = self.config.max_queue_pairs() << 1;
         if self.feature.contains(NetFeature::CTRL_VQ) {
             data_queues + 1
         } else {
@@ -219,7 +488,7 @@ impl Virtio for Net {
         }
     }
 
-    fn config(&self) -> Arc<NetConfig> {
+    fn config(&self) -> Arc<NetConfigMmio> {
         self.config.clone()
     }
 
@@ -242,10 +511,77 @@ impl Virtio for Net {
             WorkerApi::IoUring => IoUring::spawn_worker(self, event_rx, memory, queue_regs),
         }
     }
+
+    /// Buffers canceled by the io_uring worker on pause are used out of
+    /// order, which cannot be expressed by the queue positions in a
+    /// snapshot.
+    fn snapshotable(&self) -> bool {
+        self.api == WorkerApi::Mio
+    }
+
+    /// Saves the link status, the number of queue pairs in use, and the RSS
+    /// configuration. Packets held back for full receive queues are not
+    /// saved, as if they were dropped by the network.
+    fn save_state(&self) -> Vec<u8> {
+        let state = NetState {
+            status: self.config.status().bits(),
+            queue_pairs: self.tap_sockets.len() as u16,
+            hash_types: self.rss.hash_types.bits(),
+            unclassified_queue: self.rss.unclassified_queue,
+            key_len: self.rss.key.len() as u16,
+            table_len: self.rss.indirection_table.len() as u16,
+            ..Default::default()
+        };
+        let mut data = state.as_bytes().to_vec();
+        data.extend(&self.rss.key);
+        let table = self.rss.indirection_table.iter();
+        data.extend(table.flat_map(|q| q.to_le_bytes()));
+        data
+    }
+
+    fn load_state(&mut self, data: &[u8]) -> Result<()> {
+        let Ok((state, data)) = NetState::read_from_prefix(data) else {
+            return error::InvalidDeviceState.fail();
+        };
+        let max_pairs = self.config.max_queue_pairs();
+        let key_len = state.key_len as usize;
+        let table_len = state.table_len as usize;
+        if state.queue_pairs == 0
+            || state.queue_pairs > max_pairs
+            || key_len > RSS_MAX_KEY_SIZE as usize
+            || table_len > RSS_MAX_INDIRECTION_TABLE_LENGTH as usize
+            || data.len() != key_len + table_len * size_of::<u16>()
+        {
+            return error::InvalidDeviceState.fail();
+        }
+        let (key, table) = data.split_at(key_len);
+        let indirection_table: Box<[u16]> = table
+            .chunks_exact(size_of::<u16>())
+            .map(|b| u16::from_le_bytes([b[0], b[1]]))
+            .collect();
+        let rx_queues = indirection_table.iter().chain([&state.unclassified_queue]);
+        if rx_queues.max().is_some_and(|&q| q >= max_pairs) {
+            return error::InvalidDeviceState.fail();
+        }
+        self.config
+            .set_status(NetStatus::from_bits_retain(state.status));
+        self.rss = Rss {
+            hash_types: HashType::from_bits_truncate(state.hash_types) & SUPPORTED_HASH_TYPES,
+            key: key.into(),
+            indirection_table,
+            unclassified_queue: state.unclassified_queue,
+        };
+        self.restored_pairs = Some(state.queue_pairs);
+        Ok(())
+    }
 }
 
 impl VirtioMio for Net {
     fn reset(&mut self, registry: &Registry) {
+        self.config.set_announce(false);
+        self.rss = Rss::default();
+        self.rx_pending.clear();
+        self.restored_pairs = None;
         self.tap_sockets.truncate(1);
         let _ = registry.deregister(&mut SourceFd(&self.tap_sockets[0].as_raw_fd()));
     }
@@ -261,13 +597,21 @@ impl VirtioMio for Net {
         E: IoeventFd,
     {
         self.driver_feature = NetFeature::from_bits_retain(feature);
+        let announce = self.driver_feature.contains(NetFeature::GUEST_ANNOUNCE);
+        self.config.set_announce(announce);
+        let hdr_len = self.hdr_len();
         let socket = &mut self.tap_sockets[0];
+        unsafe { tun_set_vnet_hdr_sz(&*socket, &(hdr_len as _)) }?;
         enable_tap_offload(socket, self.driver_feature)?;
-        active_mio.poll.registry().register(
+        let registry = active_mio.poll.registry();
+        registry.register(
             &mut SourceFd(&socket.as_raw_fd()),
             Token(0),
             Interest::READABLE | Interest::WRITABLE,
         )?;
+        if let Some(pairs) = self.restored_pairs.take() {
+            self.set_queue_pairs(pairs as usize, Some(registry))?;
+        }
         Ok(())
     }
 
@@ -282,8 +626,11 @@ impl VirtioMio for Net {
         E: IoeventFd,
     {
         let token = event.token().0;
-        let irq_sender = active_mio.irq_sender;
-        if event.is_readable() {
+        if event.is_readable() && !self.config.link_up() {
+            self.discard_packets(token)?;
+        } else if event.is_readable() && self.steering() {
+            self.steer_packets(token, active_mio)?;
+        } else if event.is_readable() {
             let rx_queue_index = token << 1;
             let Some(Some(queue)) = active_mio.queues.get_mut(rx_queue_index) else {
                 log::error!("{}: cannot find rx queue {rx_queue_index}", self.name);
@@ -293,6 +640,7 @@ impl VirtioMio for Net {
                 log::error!("{}: cannot find tap queue {token}", self.name);
                 return Ok(());
             };
+            let irq_sender = active_mio.irq_sender;
             queue.handle_desc(rx_queue_index as u16, irq_sender, copy_from_reader(socket))?;
         }
         if event.is_writable() {
@@ -305,7 +653,12 @@ impl VirtioMio for Net {
                 log::error!("{}: cannot find tap queue {token}", self.name);
                 return Ok(());
             };
-            queue.handle_desc(tx_queue_index as u16, irq_sender, copy_to_writer(socket))?;
+            let irq_sender = active_mio.irq_sender;
+            if self.config.link_up() {
+                queue.handle_desc(tx_queue_index as u16, irq_sender, copy_to_writer(socket))?;
+            } else {
+                queue.handle_desc(tx_queue_index as u16, irq_sender, drop_packet)?;
+            }
         }
         Ok(())
     }
@@ -320,26 +673,40 @@ impl VirtioMio for Net {
         S: IrqSender,
         E: IoeventFd,
     {
+        let ctrl_queue = index == self.config.max_queue_pairs() * 2;
+        if !ctrl_queue && index & 1 == 0 && self.steering() && self.config.link_up() {
+            // Other packets are read from the tap device when it becomes
+            // readable.
+            return self.flush_pending(active_mio);
+        }
         let Some(Some(queue)) = active_mio.queues.get_mut(index as usize) else {
             log::error!("{}: invalid queue index {index}", self.name);
             return Ok(());
         };
         let irq_sender = active_mio.irq_sender;
         let registry = active_mio.poll.registry();
-        if index == self.config.max_queue_pairs * 2 {
-            return queue.handle_desc(index, irq_sender, |chain| {
+        if ctrl_queue {
+            queue.handle_desc(index, irq_sender, |chain| {
                 let len = self.handle_ctrl_queue(chain, Some(registry))?;
                 Ok(Status::Done { len })
-            });
+            })?;
+            // Changes of queue pairs or RSS can leave packets for disabled
+            // queues.
+            return self.flush_pending(active_mio);
         }
         let Some(socket) = self.tap_sockets.get(index as usize >> 1) else {
             log::error!("{}: invalid tap queue {}", self.name, index >> 1);
             return Ok(());
         };
-        if index & 1 == 0 {
+        if index & 1 == 0 && !self.config.link_up() {
+            // Packets are dropped when the tap device becomes readable.
+            Ok(())
+        } else if index & 1 == 0 {
             queue.handle_desc(index, irq_sender, copy_from_reader(socket))
-        } else {
+        } else if self.config.link_up() {
             queue.handle_desc(index, irq_sender, copy_to_writer(socket))
+        } else {
+            queue.handle_desc(index, irq_sender, drop_packet)
         }
     }
 }
@@ -356,13 +723,15 @@ impl VirtioIoUring for Net {
         E: IoeventFd,
     {
         self.driver_feature = NetFeature::from_bits_retain(feature);
+        let announce = self.driver_feature.contains(NetFeature::GUEST_ANNOUNCE);
+        self.config.set_announce(announce);
         let socket = &mut self.tap_sockets[0];
         enable_tap_offload(socket, self.driver_feature)?;
         Ok(())
     }
 
     fn handle_desc(&mut self, q_index: u16, chain: &mut DescChain) -> Result<BufferAction> {
-        if q_index == self.config.max_queue_pairs * 2 {
+        if q_index == self.config.max_queue_pairs() * 2 {
             let len = self.handle_ctrl_queue(chain, None)?;
             return Ok(BufferAction::Written(len));
         }
@@ -370,6 +739,12 @@ impl VirtioIoUring for Net {
             log::error!("{}: invalid tap queue {}", self.name, q_index >> 1);
             return Ok(BufferAction::Written(0));
         };
+        if q_index & 1 == 0 && !self.config.link_up() {
+            // Buffers are used after the link comes up.
+            return Ok(BufferAction::Hold);
+        } else if q_index & 1 == 1 && !self.config.link_up() {
+            return Ok(BufferAction::Written(0));
+        }
         let entry = if q_index & 1 == 0 {
             let writable = &chain.writable;
             opcode::Readv::new(
@@ -403,9 +778,17 @@ impl VirtioIoUring for Net {
             Ok(0)
         }
     }
+
+    fn cancel_on_pause(&self, q_index: u16) -> bool {
+        q_index & 1 == 0 && q_index != self.config.max_queue_pairs() * 2
+    }
+}
+
+fn drop_packet(_: &mut DescChain) -> Result<Status> {
+    Ok(Status::Done { len: 0 })
 }
 
-fn setup_socket(file: &mut File, if_name: Option<&str>, mq: bool) -> Result<()> {
+fn setup_socket(file: &mut File, if_name: Option<&str>, mq: bool, hdr_len: usize) -> Result<()> {
     let mut tap_ifconfig = unsafe { MaybeUninit::<libc::ifreq>::zeroed().assume_init() };
 
     if let Some(name) = if_name {
@@ -430,7 +813,7 @@ fn setup_socket(file: &mut File, if_name: Option<&str>, mq: bool) -> Result<()>
         }
     })?;
 
-    unsafe { tun_set_vnet_hdr_sz(file, &(size_of::<VirtioNetHdr>() as _)) }?;
+    unsafe { tun_set_vnet_hdr_sz(file, &(hdr_len as _)) }?;
     Ok(())
 }
 
@@ -0,0 +1,287 @@
+// Copyright 2026 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::fmt::Debug;
+use std::fs::{File, OpenOptions};
+use std::path::Path;
+use std::sync::Arc;
+use std::sync::mpsc::Receiver;
+use std::thread::JoinHandle;
+
+use libc::{PROT_READ, PROT_WRITE};
+use mio::Registry;
+use mio::event::Event;
+use parking_lot::RwLock;
+use serde::Deserialize;
+use serde_aco::Help;
+use snafu::ResultExt;
+use zerocopy::{FromBytes, Immutable, IntoBytes};
+
+use crate::hv::IoeventFd;
+use crate::mem::emulated::{Action, Mmio};
+use crate::mem::mapped::{ArcMemPages, RamBus};
+use crate::mem::{MemRegion, MemRegionCallback, MemRegionType};
+use crate::sync::notifier::Notifier;
+use crate::virtio::dev::{DevParam, DeviceId, Virtio, WakeEvent};
+use crate::virtio::queue::{QueueReg, Status, VirtQueue};
+use crate::virtio::worker::mio::{ActiveMio, Mio, VirtioMio};
+use crate::virtio::{FEATURE_BUILT_IN, IrqSender, Result, error};
+use crate::{bitflags, consts, impl_mmio_for_zerocopy, mem};
+
+#[repr(C, align(8))]
+#[derive(Debug, Clone, Default, FromBytes, IntoBytes, Immutable)]
+pub struct PmemConfig {
+    start: u64,
+    size: u64,
+}
+
+impl_mmio_for_zerocopy!(PmemConfig);
+
+#[derive(Debug)]
+pub struct PmemConfigMmio {
+    name: Arc<str>,
+    config: RwLock<PmemConfig>,
+}
+
+impl Mmio for PmemConfigMmio {
+    fn size(&self) -> u64 {
+        size_of::<PmemConfig>() as u64
+    }
+
+    fn read(&self, offset: u64, size: u8) -> mem::Result<u64> {
+        let config = self.config.read();
+        Mmio::read(&*config, offset, size)
+    }
+
+    fn write(&self, offset: u64, size: u8, val: u64) -> mem::Result<Action> {
+        log::error!(
+            "{}: write to read-only config: offset = {offset:#x}, size = {size}, val = {val:#x}",
+            self.name
+        );
+        Ok(Action::None)
+    }
+}
+
+/// Reports the guest physical address of the shared memory region to
+/// drivers that read it from the config space.
+#[derive(Debug)]
+struct PmemRegionCallback {
+    config: Arc<PmemConfigMmio>,
+}
+
+impl MemRegionCallback for PmemRegionCallback {
+    fn mapped(&self, addr: u64) -> mem::Result<()> {
+        self.config.config.write().start = addr;
+        log::info!("{}: mapped at {addr:#x}", self.config.name);
+        Ok(())
+    }
+}
+
+bitflags! {
+    pub struct PmemFeature(u128) {
+        SHMEM_REGION = 1 << 0;
+    }
+}
+
+consts! {
+    pub struct PmemReqType(u32) {
+        FLUSH = 0;
+    }
+}
+
+#[derive(Debug)]
+pub struct Pmem {
+    name: Arc<str>,
+    file: File,
+    config: Arc<PmemConfigMmio>,
+    region: Arc<MemRegion>,
+}
+
+impl Pmem {
+    pub fn new(param: PmemParam, name: impl Into<Arc<str>>) -> Result<Self> {
+        let name = name.into();
+        let path = param.path.as_ref();
+        let file = OpenOptions::new()
+            .read(true)
+            .write(!param.readonly)
+            .open(path)
+            .context(error::AccessFile { path })?;
+        let size = file.metadata().context(error::AccessFile { path })?.len();
+        if size == 0 || size & 0xfff != 0 {
+            return error::InvalidPmemSize { path, size }.fail();
+        }
+        let pages = if param.readonly {
+            ArcMemPages::from_file_private(&file, 0, size as usize)
+        } else {
+            let file = file.try_clone().context(error::AccessFile { path })?;
+            ArcMemPages::from_file(file, 0, size as usize, PROT_READ | PROT_WRITE)
+        }?;
+        let config = Arc::new(PmemConfigMmio {
+            name: name.clone(),
+            config: RwLock::new(PmemConfig { start: 0, size }),
+        });
+        let region = MemRegion::with_dev_mem(pages, MemRegionType::Hidden);
+        region.callbacks.lock().push(Box::new(PmemRegionCallback {
+            config: config.clone(),
+        }));
+        Ok(Pmem {
+            name,
+            file,
+            config,
+            region: Arc::new(region),
+        })
+    }
+
+    fn flush(&self) -> u32 {
+        match self.file.sync_all() {
+            Ok(()) => 0,
+            Err(e) => {
+                log::error!("{}: flush: {e:?}", self.name);
+                1
+            }
+        }
+    }
+}
+
+impl Virtio for Pmem {
+    type Config = PmemConfigMmio;
+    type Feature = PmemFeature;
+
+    fn id(&self) -> DeviceId {
+        DeviceId::PMEM
+    }
+
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn spawn_worker<S, E>(
+        self,
+        event_rx: Receiver<WakeEvent<S, E>>,
+        memory: Arc<RamBus>,
+        queue_regs: Arc<[QueueReg]>,
+    ) -> Result<(JoinHandle<()>, Arc<Notifier>)>
+    where
+        S: IrqSender,
+        E: IoeventFd,
+    {
+        Mio::spawn_worker(self, event_rx, memory, queue_regs)
+    }
+
+    fn num_queues(&self) -> u16 {
+        1
+    }
+
+    fn config(&self) -> Arc<PmemConfigMmio> {
+        self.config.clone()
+    }
+
+    fn feature(&self) -> u128 {
+        FEATURE_BUILT_IN | PmemFeature::SHMEM_REGION.bits()
+    }
+
+    fn shared_mem_regions(&self) -> Option<Arc<MemRegion>> {
+        Some(self.region.clone())
+    }
+}
+
+impl VirtioMio for Pmem {
+    fn activate<'m, Q, S, E>(
+        &mut self,
+        _feature: u128,
+        _active_mio: &mut ActiveMio<'_, '_, 'm, Q, S, E>,
+    ) -> Result<()>
+    where
+        Q: VirtQueue<'m>,
+        S: IrqSender,
+        E: IoeventFd,
+    {
+        Ok(())
+    }
+
+    fn handle_queue<'m, Q, S, E>(
+        &mut self,
+        index: u16,
+        active_mio: &mut ActiveMio<'_, '_, 'm, Q, S, E>,
+    ) -> Result<()>
+    where
+        Q: VirtQueue<'m>,
+        S: IrqSender,
+        E: IoeventFd,
+    {
+        let Some(Some(queue)) = active_mio.queues.get_mut(index as usize) else {
+            log::error!("{}: invalid queue index {index}", self.name);
+            return Ok(());
+        };
+        queue.handle_desc(index, active_mio.irq_sender, |chain| {
+            let Some(Ok((type_, _))) = chain.readable.first().map(|b| u32::read_from_prefix(b))
+            else {
+                return error::InvalidBuffer.fail();
+            };
+            let ret = match PmemReqType::from(type_) {
+                PmemReqType::FLUSH => self.flush(),
+                t => {
+                    log::error!("{}: unknown request {t:?}", self.name);
+                    1
+                }
+            };
+            let Some(buf) = chain.writable.first_mut() else {
+                return error::InvalidBuffer.fail();
+            };
+            if ret.write_to_prefix(buf).is_err() {
+                return error::InvalidBuffer.fail();
+            }
+            Ok(Status::Done {
+                len: size_of::<u32>() as u32,
+            })
+        })
+    }
+
+    fn handle_event<'a, 'm, Q, S, E>(
+        &mut self,
+        _event: &Event,
+        _active_mio: &mut ActiveMio<'_, '_, 'm, Q, S, E>,
+    ) -> Result<()>
+    where
+        Q: VirtQueue<'m>,
+        S: IrqSender,
+        E: IoeventFd,
+    {
+        Ok(())
+    }
+
+    fn reset(&mut self, _registry: &Registry) {}
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Help)]
+pub struct PmemParam {
+    /// Path to a host file. Its size must be a multiple of 4KiB.
+    pub path: Box<Path>,
+    /// Map the file copy-on-write, so that guest writes never reach the
+    /// file. [default: false]
+    #[serde(default)]
+    pub readonly: bool,
+}
+
+impl DevParam for PmemParam {
+    type Device = Pmem;
+
+    fn build(self, name: impl Into<Arc<str>>) -> Result<Self::Device> {
+        Pmem::new(self, name)
+    }
+}
+
+#[cfg(test)]
+#[path = "pmem_test.rs"]
+mod tests;
@@ -0,0 +1,81 @@
+// Copyright 2026 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::fs;
+
+use assert_matches::assert_matches;
+use rstest::rstest;
+use tempfile::TempDir;
+
+use crate::mem::MemRange;
+use crate::mem::emulated::Mmio;
+use crate::virtio::dev::pmem::{Pmem, PmemFeature, PmemParam};
+use crate::virtio::dev::{DevParam, Virtio};
+use crate::virtio::{DeviceId, Error, FEATURE_BUILT_IN};
+
+#[rstest]
+#[case(false, 0x5a)]
+#[case(true, 0)]
+fn test_pmem(#[case] readonly: bool, #[case] byte: u8) {
+    let temp_dir = TempDir::new().unwrap();
+    let path = temp_dir.path().join("pmem.img");
+    fs::write(&path, vec![0u8; 8 << 10]).unwrap();
+
+    let param = PmemParam {
+        path: path.clone().into(),
+        readonly,
+    };
+    let dev = param.build("pmem").unwrap();
+    assert_eq!(dev.id(), DeviceId::PMEM);
+    assert_eq!(dev.num_queues(), 1);
+    assert_eq!(
+        dev.feature(),
+        FEATURE_BUILT_IN | PmemFeature::SHMEM_REGION.bits()
+    );
+
+    let config = dev.config();
+    assert_eq!(config.size(), 16);
+    assert_matches!(config.read(0, 8), Ok(0));
+    assert_matches!(config.read(8, 8), Ok(0x2000));
+
+    let region = dev.shared_mem_regions().unwrap();
+    assert_eq!(region.size(), 8 << 10);
+    for callback in region.callbacks.lock().iter() {
+        callback.mapped(0x8000_0000).unwrap();
+    }
+    assert_matches!(config.read(0, 4), Ok(0x8000_0000));
+    assert_matches!(config.read(4, 4), Ok(0));
+
+    let MemRange::DevMem(pages) = &region.ranges[0] else {
+        panic!("{:?}", region.ranges[0]);
+    };
+    pages.clone().as_slice_mut()[0x1000] = 0x5a;
+    assert_eq!(dev.flush(), 0);
+    assert_eq!(fs::read(&path).unwrap()[0x1000], byte);
+}
+
+#[rstest]
+#[case(0)]
+#[case(0x1800)]
+fn test_pmem_invalid_size(#[case] size: usize) {
+    let temp_dir = TempDir::new().unwrap();
+    let path = temp_dir.path().join("pmem.img");
+    fs::write(&path, vec![0u8; size]).unwrap();
+
+    let param = PmemParam {
+        path: path.into(),
+        readonly: true,
+    };
+    assert_matches!(Pmem::new(param, "pmem"), Err(Error::InvalidPmemSize { .. }));
+}
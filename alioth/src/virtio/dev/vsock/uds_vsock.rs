@@ -30,8 +30,8 @@ use crate::hv::IoeventFd;
 use crate::mem::mapped::RamBus;
 use crate::sync::notifier::Notifier;
 use crate::virtio::dev::vsock::{
-    ShutdownFlag, VSOCK_CID_HOST, VsockConfig, VsockFeature, VsockHeader, VsockOp, VsockType,
-    VsockVirtq,
+    ShutdownFlag, VSOCK_CID_HOST, VsockConfig, VsockEvent, VsockEventId, VsockFeature, VsockHeader,
+    VsockOp, VsockType, VsockVirtq,
 };
 use crate::virtio::dev::{DevParam, Virtio, WakeEvent};
 use crate::virtio::queue::{DescChain, Queue, QueueReg, Status, VirtQueue};
@@ -75,6 +75,7 @@ pub struct UdsVsock {
     sockets: HashMap<Token, UnixStream>,
     host_ports: HashMap<u32, u32>,
     next_port: u32,
+    transport_reset: bool,
 }
 
 fn get_buf_size(stream: &UnixStream) -> Result<usize> {
@@ -579,6 +580,35 @@ impl UdsVsock {
         Ok(())
     }
 
+    /// Tells the driver that all connections are gone after the device is
+    /// restored from a snapshot.
+    fn send_transport_reset<'m, Q, S, E>(
+        &mut self,
+        active_mio: &mut ActiveMio<'_, '_, 'm, Q, S, E>,
+    ) -> Result<()>
+    where
+        Q: VirtQueue<'m>,
+        S: IrqSender,
+        E: IoeventFd,
+    {
+        let index = VsockVirtq::EVENT.raw();
+        let Some(Some(event_q)) = active_mio.queues.get_mut(index as usize) else {
+            return error::InvalidQueueIndex { index }.fail();
+        };
+        event_q.handle_desc(index, active_mio.irq_sender, |chain| {
+            if !self.transport_reset {
+                return Ok(Status::Break);
+            }
+            let event = VsockEvent {
+                id: VsockEventId::TRANSPORT_RESET,
+            };
+            let len = event.as_bytes().read_vectored(&mut chain.writable)?;
+            self.transport_reset = false;
+            log::info!("{}: sent transport reset event", self.name);
+            Ok(Status::Done { len: len as u32 })
+        })
+    }
+
     fn transfer_tx_data(
         &mut self,
         hdr: &VsockHeader,
@@ -685,6 +715,7 @@ impl UdsVsock {
             ports: HashMap::new(),
             host_ports: HashMap::new(),
             next_port: 1024,
+            transport_reset: false,
         };
         Ok(vsock)
     }
@@ -726,6 +757,20 @@ impl Virtio for UdsVsock {
     {
         Mio::spawn_worker(self, event_rx, memory, queue_regs)
     }
+
+    fn snapshotable(&self) -> bool {
+        true
+    }
+
+    /// Connections to the host cannot be saved. A device restored from a
+    /// snapshot resets the transport of the driver instead.
+    fn load_state(&mut self, data: &[u8]) -> Result<()> {
+        if !data.is_empty() {
+            return error::InvalidDeviceState.fail();
+        }
+        self.transport_reset = true;
+        Ok(())
+    }
 }
 
 impl VirtioMio for UdsVsock {
@@ -792,6 +837,7 @@ impl VirtioMio for UdsVsock {
         match index {
             VsockVirtq::TX => self.handle_tx(active_mio)?,
             VsockVirtq::RX => log::debug!("{name}: queue RX buffer available"),
+            VsockVirtq::EVENT if self.transport_reset => self.send_transport_reset(active_mio)?,
             VsockVirtq::EVENT => log::debug!("{name}: queue EVENT buffer available"),
             _ => log::error!("{name}: unknown queue index {index:?}"),
         }
@@ -815,6 +861,7 @@ impl VirtioMio for UdsVsock {
         }
         self.host_ports.clear();
         self.next_port = 1024;
+        self.transport_reset = false;
     }
 }
 
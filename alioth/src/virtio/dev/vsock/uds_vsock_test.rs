@@ -15,7 +15,7 @@
 use std::io::{BufRead, BufReader, ErrorKind, Read, Write};
 use std::mem::size_of;
 use std::os::unix::net::{UnixListener, UnixStream};
-use std::sync::mpsc::{Receiver, Sender, TryRecvError};
+use std::sync::mpsc::{Receiver, RecvTimeoutError, Sender, TryRecvError};
 use std::sync::{Arc, mpsc};
 use std::time::Duration;
 
@@ -28,10 +28,10 @@ use crate::mem::emulated::{Action, Mmio};
 use crate::mem::mapped::{Ram, RamBus};
 use crate::sync::notifier::Notifier;
 use crate::virtio::dev::vsock::{
-    ShutdownFlag, UdsVsockParam, VSOCK_CID_HOST, VsockConfig, VsockFeature, VsockHeader, VsockOp,
-    VsockType, VsockVirtq,
+    ShutdownFlag, UdsVsockParam, VSOCK_CID_HOST, VsockConfig, VsockEvent, VsockEventId,
+    VsockFeature, VsockHeader, VsockOp, VsockType, VsockVirtq,
 };
-use crate::virtio::dev::{DevParam, StartParam, Virtio, WakeEvent};
+use crate::virtio::dev::{DevParam, PausedState, StartParam, Virtio, WakeEvent};
 use crate::virtio::queue::QueueReg;
 use crate::virtio::queue::split::SplitQueue;
 use crate::virtio::queue::tests::{GuestQueue, VirtQueueGuest};
@@ -106,11 +106,15 @@ fn vsock_conn_test(fixture_ram_bus: RamBus, #[with(3)] fixture_queues: Box<[Queu
     let reg_tx = &regs[VsockVirtq::TX.raw() as usize];
     let reg_rx = &regs[VsockVirtq::RX.raw() as usize];
     let mut rx_q = GuestQueue::new(
-        SplitQueue::new(reg_rx, &*ram, false).unwrap().unwrap(),
+        SplitQueue::new(reg_rx, &*ram, false, None)
+            .unwrap()
+            .unwrap(),
         reg_rx,
     );
     let mut tx_q = GuestQueue::new(
-        SplitQueue::new(reg_tx, &*ram, false).unwrap().unwrap(),
+        SplitQueue::new(reg_tx, &*ram, false, None)
+            .unwrap()
+            .unwrap(),
         reg_tx,
     );
 
@@ -141,6 +145,9 @@ fn vsock_conn_test(fixture_ram_bus: RamBus, #[with(3)] fixture_queues: Box<[Queu
         feature: VirtioFeature::VERSION_1.bits(),
         irq_sender,
         ioeventfds: Option::<Arc<[FakeIoeventFd]>>::None,
+        iotlb: None,
+        restored: None,
+        worker_cpus: [].into(),
     };
     tx.send(WakeEvent::Start { param: start_param }).unwrap();
 
@@ -386,3 +393,75 @@ fn vsock_conn_test(fixture_ram_bus: RamBus, #[with(3)] fixture_queues: Box<[Queu
     notifier.notify().unwrap();
     handle.join().unwrap();
 }
+
+#[rstest]
+fn vsock_restore_test(fixture_ram_bus: RamBus, #[with(3)] fixture_queues: Box<[QueueReg]>) {
+    let ram_bus = Arc::new(fixture_ram_bus);
+    let ram = ram_bus.lock_layout();
+    let regs: Arc<[QueueReg]> = Arc::from(fixture_queues);
+    let reg_event = &regs[VsockVirtq::EVENT.raw() as usize];
+    let mut event_q = GuestQueue::new(
+        SplitQueue::new(reg_event, &*ram, false, None)
+            .unwrap()
+            .unwrap(),
+        reg_event,
+    );
+
+    let temp_dir = TempDir::new().unwrap();
+    let param = UdsVsockParam {
+        cid: 3,
+        path: temp_dir.path().join("vsock.sock").into(),
+    };
+    let dev = param.build("vsock").unwrap();
+    assert!(dev.snapshotable());
+    assert!(dev.save_state().is_empty());
+
+    let (tx, rx) = mpsc::channel();
+    let (handle, notifier) = dev.spawn_worker(rx, ram_bus.clone(), regs).unwrap();
+    let (irq_tx, irq_rx) = mpsc::channel();
+    let start_param = StartParam {
+        feature: VirtioFeature::VERSION_1.bits(),
+        irq_sender: Arc::new(FakeIrqSender { q_tx: irq_tx }),
+        ioeventfds: Option::<Arc<[FakeIoeventFd]>>::None,
+        iotlb: None,
+        restored: Some(PausedState {
+            queue_index: [0; 3].into(),
+            dev_state: [].into(),
+        }),
+        worker_cpus: [].into(),
+    };
+    tx.send(WakeEvent::Start { param: start_param }).unwrap();
+
+    let buf_id = event_q.add_desc(&[], &[(DATA_ADDR, 8)]);
+    event_q.add_desc(&[], &[(DATA_ADDR + 8, 8)]);
+    tx.send(WakeEvent::Notify {
+        q_index: VsockVirtq::EVENT.raw(),
+    })
+    .unwrap();
+    notifier.notify().unwrap();
+    assert_eq!(
+        irq_rx.recv_timeout(Duration::from_secs(1)).unwrap(),
+        VsockVirtq::EVENT.raw()
+    );
+    let used = event_q.get_used().unwrap();
+    assert_eq!(used.id, buf_id);
+    assert_eq!(used.len as usize, size_of::<VsockEvent>());
+    let mut event = VsockEvent::new_zeroed();
+    ram.read(DATA_ADDR, event.as_mut_bytes()).unwrap();
+    assert_eq!(event.id, VsockEventId::TRANSPORT_RESET);
+
+    // The event is sent only once.
+    tx.send(WakeEvent::Notify {
+        q_index: VsockVirtq::EVENT.raw(),
+    })
+    .unwrap();
+    notifier.notify().unwrap();
+    assert_matches!(
+        irq_rx.recv_timeout(Duration::from_millis(100)),
+        Err(RecvTimeoutError::Timeout)
+    );
+
+    tx.send(WakeEvent::Shutdown).unwrap();
+    notifier.notify().unwrap();
+    handle.join().unwrap();
+}
@@ -130,6 +130,10 @@ impl Virtio for VhostVsock {
         }
     }
 
+    fn dma_translatable(&self) -> bool {
+        false
+    }
+
     fn mem_update_callback(&self) -> Option<Box<dyn LayoutUpdated>> {
         Some(Box::new(UpdateVsockMem {
             dev: self.vhost_dev.clone(),
@@ -92,6 +92,19 @@ pub struct VsockHeader {
     fwd_cnt: Wrapping<u32>,
 }
 
+consts! {
+    #[derive(Default, FromBytes, Immutable, IntoBytes)]
+    pub struct VsockEventId(u32) {
+        TRANSPORT_RESET = 0;
+    }
+}
+
+#[derive(Debug, Clone, Default, FromBytes, IntoBytes, Immutable)]
+#[repr(C)]
+pub struct VsockEvent {
+    pub id: VsockEventId,
+}
+
 bitflags! {
     pub struct ShutdownFlag(u32) {
         RECEIVE = 1 << 0;
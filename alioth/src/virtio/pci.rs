@@ -22,15 +22,15 @@ use std::sync::mpsc::Sender;
 
 use alioth_macros::Layout;
 use parking_lot::{Mutex, RwLock};
-use zerocopy::{FromZeros, Immutable, IntoBytes};
+use zerocopy::{FromBytes, FromZeros, Immutable, IntoBytes};
 
-use crate::device::Pause;
+use crate::device::{self, Pause, Snapshot};
 use crate::hv::{self, IoeventFd, IoeventFdRegistry, IrqFd, MsiSender};
 use crate::mem::emulated::{Action, Mmio};
 use crate::mem::{MemRange, MemRegion, MemRegionCallback, MemRegionEntry};
 use crate::pci::cap::{
     MsixCap, MsixCapMmio, MsixCapOffset, MsixMsgCtrl, MsixTableEntry, MsixTableMmio,
-    MsixTableMmioEntry, PciCap, PciCapHdr, PciCapId, PciCapList,
+    MsixTableMmioEntry, MsixVectorCtrl, PciCap, PciCapHdr, PciCapId, PciCapList,
 };
 use crate::pci::config::{
     BAR_MEM32, BAR_MEM64, BAR_PREFETCHABLE, CommonHeader, DeviceHeader, EmulatedConfig, HeaderType,
@@ -39,8 +39,8 @@ use crate::pci::config::{
 use crate::pci::{self, Pci, PciBar};
 use crate::sync::notifier::Notifier;
 use crate::utils::{get_atomic_high32, get_atomic_low32, set_atomic_high32, set_atomic_low32};
-use crate::virtio::dev::{Register, StartParam, VirtioDevice, WakeEvent};
-use crate::virtio::queue::QueueReg;
+use crate::virtio::dev::{PausedState, Register, StartParam, VirtioDevice, WakeEvent};
+use crate::virtio::queue::{Iotlb, QueueReg};
 use crate::virtio::{DevStatus, DeviceId, IrqSender, Result, error};
 use crate::{consts, impl_mmio_for_zerocopy, mem};
 
@@ -194,6 +194,8 @@ where
     queues: Arc<[QueueReg]>,
     irq_sender: Arc<PciIrqSender<M>>,
     ioeventfds: Option<Arc<[E]>>,
+    iotlb: Option<Arc<dyn Iotlb>>,
+    worker_cpus: Arc<[u32]>,
     event_tx: Sender<WakeEvent<PciIrqSender<M>, E>>,
     notifier: Arc<Notifier>,
 }
@@ -217,6 +219,21 @@ where
         }
     }
 
+    fn start_param(&self, restored: Option<PausedState>) -> StartParam<PciIrqSender<M>, E> {
+        let mut feature = 0;
+        for (i, v) in self.reg.driver_feature.iter().enumerate() {
+            feature |= (v.load(Ordering::Acquire) as u128) << (i << 5);
+        }
+        StartParam {
+            feature,
+            irq_sender: self.irq_sender.clone(),
+            ioeventfds: self.ioeventfds.clone(),
+            iotlb: self.iotlb.clone(),
+            restored,
+            worker_cpus: self.worker_cpus.clone(),
+        }
+    }
+
     fn reset(&self) {
         let config_msix = &self.irq_sender.msix_vector.config;
         config_msix.store(VIRTIO_MSI_NO_VECTOR, Ordering::Release);
@@ -287,7 +304,7 @@ where
             VirtioCommonCfg::LAYOUT_NUM_QUEUES => self.queues.len() as u64,
             VirtioCommonCfg::LAYOUT_DEVICE_STATUS => reg.status.load(Ordering::Acquire) as u64,
             VirtioCommonCfg::LAYOUT_CONFIG_GENERATION => {
-                0 // TODO: support device config change at runtime
+                reg.config_generation.load(Ordering::Acquire) as u64
             }
             VirtioCommonCfg::LAYOUT_QUEUE_SELECT => reg.queue_sel.load(Ordering::Acquire) as u64,
             VirtioCommonCfg::LAYOUT_QUEUE_SIZE => {
@@ -421,15 +438,7 @@ where
                 let old = DevStatus::from_bits_retain(old);
                 if (old ^ status).contains(DevStatus::DRIVER_OK) {
                     let event = if status.contains(DevStatus::DRIVER_OK) {
-                        let mut feature = 0;
-                        for (i, v) in reg.driver_feature.iter().enumerate() {
-                            feature |= (v.load(Ordering::Acquire) as u128) << (i << 5);
-                        }
-                        let param = StartParam {
-                            feature,
-                            irq_sender: self.irq_sender.clone(),
-                            ioeventfds: self.ioeventfds.clone(),
-                        };
+                        let param = self.start_param(None);
                         WakeEvent::Start { param }
                     } else {
                         self.reset();
@@ -673,6 +682,9 @@ where
     pub dev: VirtioDevice<PciIrqSender<M>, E>,
     pub config: EmulatedConfig,
     pub registers: Arc<VirtioPciRegisterMmio<M, E>>,
+    /// State restored from a snapshot. The worker starts from it when the
+    /// board resumes.
+    restored: Mutex<Option<PausedState>>,
 }
 
 impl<M, E> VirtioPciDevice<M, E>
@@ -684,6 +696,7 @@ where
         dev: VirtioDevice<PciIrqSender<M>, E>,
         msi_sender: M,
         ioeventfd_reg: R,
+        iotlb: Option<Arc<dyn Iotlb>>,
     ) -> Result<Self>
     where
         R: IoeventFdRegistry<IoeventFd = E>,
@@ -873,6 +886,8 @@ where
                 msi_sender,
             }),
             ioeventfds: ioeventfds.clone(),
+            iotlb,
+            worker_cpus: dev.worker_cpus.clone(),
         });
         bar0.ranges.push(MemRange::Emulated(msix_table));
         bar0.ranges
@@ -913,8 +928,19 @@ where
             dev,
             config,
             registers,
+            restored: Mutex::new(None),
         })
     }
+
+    /// Notifies the driver that the device configuration space has changed.
+    pub fn config_changed(&self) {
+        let reg = &self.registers.reg;
+        reg.config_generation.fetch_add(1, Ordering::AcqRel);
+        let status = DevStatus::from_bits_retain(reg.status.load(Ordering::Acquire));
+        if status.contains(DevStatus::DRIVER_OK) {
+            self.registers.irq_sender.config_irq();
+        }
+    }
 }
 
 impl<M, E> Pause for VirtioPciDevice<M, E>
@@ -922,6 +948,199 @@ where
     M: MsiSender,
     E: IoeventFd,
 {
+    fn pause(&self) -> device::Result<()> {
+        self.dev.pause()?;
+        Ok(())
+    }
+
+    fn resume(&self) -> device::Result<()> {
+        self.dev.resume()?;
+        let Some(restored) = self.restored.lock().take() else {
+            return Ok(());
+        };
+        let registers = &self.registers;
+        let param = registers.start_param(Some(restored));
+        registers.wake_up_dev(WakeEvent::Start { param });
+        // Notifications the source worker had not handled are lost.
+        for (q_index, q) in registers.queues.iter().enumerate() {
+            if q.enabled.load(Ordering::Acquire) {
+                let q_index = q_index as u16;
+                registers.wake_up_dev(WakeEvent::Notify { q_index });
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Common configuration saved in a snapshot, followed by a [`VirtioQueueState`]
+/// per queue, the MSI-X table, and the state of the device.
+#[repr(C)]
+#[derive(Debug, Default, FromBytes, Immutable, IntoBytes)]
+struct VirtioPciState {
+    driver_feature: [u32; 4],
+    device_feature_sel: u8,
+    driver_feature_sel: u8,
+    status: u8,
+    config_generation: u8,
+    queue_sel: u16,
+    config_msix_vector: u16,
+    num_queues: u16,
+    reserved: u16,
+    dev_state_len: u32,
+}
+
+#[repr(C)]
+#[derive(Debug, Default, FromBytes, Immutable, IntoBytes)]
+struct VirtioQueueState {
+    desc: u64,
+    driver: u64,
+    device: u64,
+    size: u16,
+    enabled: u16,
+    msix_vector: u16,
+    /// Position of the queue in the paused worker.
+    index: u16,
+}
+
+impl<M, E> Snapshot for VirtioPciDevice<M, E>
+where
+    M: MsiSender,
+    E: IoeventFd,
+{
+    fn snapshot(&self) -> device::Result<Vec<u8>> {
+        if !self.dev.snapshotable {
+            return device::error::NotSnapshotable.fail();
+        }
+        let paused = match self.dev.paused_state() {
+            Some(paused) => paused,
+            None => {
+                self.dev.pause()?;
+                let paused = self.dev.paused_state();
+                self.dev.resume()?;
+                paused.unwrap_or_default()
+            }
+        };
+        let registers = &self.registers;
+        let reg = &registers.reg;
+        let msix_vector = &registers.irq_sender.msix_vector;
+
+        let mut state = VirtioPciState {
+            device_feature_sel: reg.device_feature_sel.load(Ordering::Acquire),
+            driver_feature_sel: reg.driver_feature_sel.load(Ordering::Acquire),
+            status: reg.status.load(Ordering::Acquire),
+            config_generation: reg.config_generation.load(Ordering::Acquire),
+            queue_sel: reg.queue_sel.load(Ordering::Acquire),
+            config_msix_vector: msix_vector.config.load(Ordering::Acquire),
+            num_queues: registers.queues.len() as u16,
+            dev_state_len: paused.dev_state.len() as u32,
+            ..Default::default()
+        };
+        for (v, feature) in state.driver_feature.iter_mut().zip(&reg.driver_feature) {
+            *v = feature.load(Ordering::Acquire);
+        }
+        let mut data = state.as_bytes().to_vec();
+
+        let queues = registers.queues.iter().zip(&msix_vector.queues);
+        for (index, (q, vector)) in queues.enumerate() {
+            let q_state = VirtioQueueState {
+                desc: q.desc.load(Ordering::Acquire),
+                driver: q.driver.load(Ordering::Acquire),
+                device: q.device.load(Ordering::Acquire),
+                size: q.size.load(Ordering::Acquire),
+                enabled: q.enabled.load(Ordering::Acquire) as u16,
+                msix_vector: vector.load(Ordering::Acquire),
+                index: paused.queue_index.get(index).copied().unwrap_or(0),
+            };
+            data.extend(q_state.as_bytes());
+        }
+
+        let entries = registers.irq_sender.msix_table.entries.read();
+        for entry in entries.iter() {
+            let masked = entry.get_masked() as u32;
+            let val = [
+                entry.get_addr_lo(),
+                entry.get_addr_hi(),
+                entry.get_data(),
+                masked,
+            ];
+            data.extend(val.as_bytes());
+        }
+        data.extend(&paused.dev_state);
+        Ok(data)
+    }
+
+    fn restore(&self, data: &[u8]) -> device::Result<()> {
+        if !self.dev.snapshotable {
+            return device::error::NotSnapshotable.fail();
+        }
+        let registers = &self.registers;
+        let reg = &registers.reg;
+        let msix_vector = &registers.irq_sender.msix_vector;
+
+        let Ok((state, mut data)) = VirtioPciState::read_from_prefix(data) else {
+            return device::error::InvalidSnapshot.fail();
+        };
+        if state.num_queues as usize != registers.queues.len() {
+            return device::error::InvalidSnapshot.fail();
+        }
+        for (feature, v) in reg.driver_feature.iter().zip(state.driver_feature) {
+            feature.store(v, Ordering::Release);
+        }
+        reg.device_feature_sel
+            .store(state.device_feature_sel, Ordering::Release);
+        reg.driver_feature_sel
+            .store(state.driver_feature_sel, Ordering::Release);
+        reg.status.store(state.status, Ordering::Release);
+        reg.config_generation
+            .store(state.config_generation, Ordering::Release);
+        reg.queue_sel.store(state.queue_sel, Ordering::Release);
+        msix_vector
+            .config
+            .store(state.config_msix_vector, Ordering::Release);
+
+        let mut queue_index = Vec::with_capacity(registers.queues.len());
+        for (q, vector) in registers.queues.iter().zip(&msix_vector.queues) {
+            let Ok((q_state, remain)) = VirtioQueueState::read_from_prefix(data) else {
+                return device::error::InvalidSnapshot.fail();
+            };
+            q.desc.store(q_state.desc, Ordering::Release);
+            q.driver.store(q_state.driver, Ordering::Release);
+            q.device.store(q_state.device, Ordering::Release);
+            q.size.store(q_state.size, Ordering::Release);
+            q.enabled.store(q_state.enabled != 0, Ordering::Release);
+            vector.store(q_state.msix_vector, Ordering::Release);
+            queue_index.push(q_state.index);
+            data = remain;
+        }
+
+        let mut entries = registers.irq_sender.msix_table.entries.write();
+        for entry in entries.iter_mut() {
+            let Ok(([addr_lo, addr_hi, msg_data, masked], remain)) =
+                <[u32; 4]>::read_from_prefix(data)
+            else {
+                return device::error::InvalidSnapshot.fail();
+            };
+            *entry = MsixTableMmioEntry::Entry(MsixTableEntry {
+                addr_lo,
+                addr_hi,
+                data: msg_data,
+                control: MsixVectorCtrl(masked),
+            });
+            data = remain;
+        }
+        if data.len() != state.dev_state_len as usize {
+            return device::error::InvalidSnapshot.fail();
+        }
+
+        let status = DevStatus::from_bits_retain(state.status);
+        if status.contains(DevStatus::DRIVER_OK) {
+            *self.restored.lock() = Some(PausedState {
+                queue_index: queue_index.into(),
+                dev_state: data.into(),
+            });
+        }
+        Ok(())
+    }
 }
 
 impl<M, E> Pci for VirtioPciDevice<M, E>
@@ -944,3 +1163,7 @@ where
         Ok(())
     }
 }
+
+#[cfg(test)]
+#[path = "pci_test.rs"]
+mod tests;
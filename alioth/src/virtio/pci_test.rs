@@ -0,0 +1,125 @@
+// Copyright 2026 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::sync::Arc;
+use std::sync::atomic::Ordering;
+
+use assert_matches::assert_matches;
+use rstest::rstest;
+
+use crate::device::{self, Snapshot};
+use crate::hv::tests::TestMsiSender;
+use crate::hv::{IoeventFdRegistry, Result};
+use crate::mem::mapped::RamBus;
+use crate::pci::cap::{MsixTableEntry, MsixTableMmioEntry, MsixVectorCtrl};
+use crate::virtio::DevStatus;
+use crate::virtio::dev::entropy::EntropyParam;
+use crate::virtio::dev::{DevParam, VirtioDevice};
+use crate::virtio::pci::VirtioPciDevice;
+use crate::virtio::tests::{FakeIoeventFd, fixture_ram_bus};
+
+#[derive(Debug)]
+struct FakeIoeventFdRegistry;
+
+impl IoeventFdRegistry for FakeIoeventFdRegistry {
+    type IoeventFd = FakeIoeventFd;
+
+    fn create(&self) -> Result<Self::IoeventFd> {
+        Ok(FakeIoeventFd)
+    }
+
+    fn register(&self, _: &Self::IoeventFd, _: u64, _: u8, _: Option<u64>) -> Result<()> {
+        Ok(())
+    }
+
+    fn deregister(&self, _: &Self::IoeventFd) -> Result<()> {
+        Ok(())
+    }
+}
+
+fn new_entropy_device(ram_bus: Arc<RamBus>) -> VirtioPciDevice<TestMsiSender, FakeIoeventFd> {
+    let param = EntropyParam { source: None };
+    let dev = param.build("entropy").unwrap();
+    let dev = VirtioDevice::new("entropy", dev, ram_bus, false, [].into()).unwrap();
+    VirtioPciDevice::new(dev, TestMsiSender::default(), FakeIoeventFdRegistry, None).unwrap()
+}
+
+#[rstest]
+fn test_snapshot_restore(fixture_ram_bus: RamBus) {
+    let ram_bus = Arc::new(fixture_ram_bus);
+    let src = new_entropy_device(ram_bus.clone());
+    let dst = new_entropy_device(ram_bus);
+
+    let reg = &src.registers.reg;
+    reg.driver_feature[1].store(0x1, Ordering::Release);
+    reg.driver_feature_sel.store(1, Ordering::Release);
+    reg.queue_sel.store(0, Ordering::Release);
+    let status = DevStatus::ACK | DevStatus::DRIVER | DevStatus::FEATURES_OK;
+    reg.status.store(status.bits(), Ordering::Release);
+    reg.config_generation.store(3, Ordering::Release);
+    let q = &src.registers.queues[0];
+    q.desc.store(0x1000, Ordering::Release);
+    q.driver.store(0x2000, Ordering::Release);
+    q.device.store(0x3000, Ordering::Release);
+    q.size.store(64, Ordering::Release);
+    q.enabled.store(true, Ordering::Release);
+    let msix_vector = &src.registers.irq_sender.msix_vector;
+    msix_vector.config.store(0, Ordering::Release);
+    msix_vector.queues[0].store(1, Ordering::Release);
+    src.registers.irq_sender.msix_table.entries.write()[1] =
+        MsixTableMmioEntry::Entry(MsixTableEntry {
+            addr_lo: 0xfee0_0000,
+            addr_hi: 0,
+            data: 0x31,
+            control: MsixVectorCtrl(0),
+        });
+
+    let data = src.snapshot().unwrap();
+    dst.restore(&data).unwrap();
+
+    let reg = &dst.registers.reg;
+    assert_eq!(reg.driver_feature[1].load(Ordering::Acquire), 0x1);
+    assert_eq!(reg.driver_feature_sel.load(Ordering::Acquire), 1);
+    assert_eq!(reg.status.load(Ordering::Acquire), status.bits());
+    assert_eq!(reg.config_generation.load(Ordering::Acquire), 3);
+    let q = &dst.registers.queues[0];
+    assert_eq!(q.desc.load(Ordering::Acquire), 0x1000);
+    assert_eq!(q.driver.load(Ordering::Acquire), 0x2000);
+    assert_eq!(q.device.load(Ordering::Acquire), 0x3000);
+    assert_eq!(q.size.load(Ordering::Acquire), 64);
+    assert!(q.enabled.load(Ordering::Acquire));
+    let msix_vector = &dst.registers.irq_sender.msix_vector;
+    assert_eq!(msix_vector.config.load(Ordering::Acquire), 0);
+    assert_eq!(msix_vector.queues[0].load(Ordering::Acquire), 1);
+    let entries = dst.registers.irq_sender.msix_table.entries.read();
+    assert_eq!(entries[1].get_addr_lo(), 0xfee0_0000);
+    assert_eq!(entries[1].get_data(), 0x31);
+    assert!(!entries[1].get_masked());
+    assert!(entries[0].get_masked());
+    drop(entries);
+
+    // DRIVER_OK is not set, so the worker is not started on resume.
+    assert!(dst.restored.lock().is_none());
+
+    assert_matches!(
+        dst.restore(&data[..data.len() - 1]),
+        Err(device::Error::InvalidSnapshot { .. })
+    );
+    let mut long = data.clone();
+    long.push(0);
+    assert_matches!(
+        dst.restore(&long),
+        Err(device::Error::InvalidSnapshot { .. })
+    );
+}
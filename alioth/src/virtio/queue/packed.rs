@@ -13,6 +13,7 @@
 // limitations under the License.
 
 use std::marker::PhantomData;
+use std::sync::Arc;
 use std::sync::atomic::Ordering;
 
 use bitfield::bitfield;
@@ -21,7 +22,9 @@ use zerocopy::{FromBytes, Immutable, IntoBytes};
 use crate::consts;
 use crate::mem::mapped::Ram;
 use crate::virtio::Result;
-use crate::virtio::queue::{DescChain, DescFlag, QueueReg, VirtQueue};
+use crate::virtio::queue::{
+    DescChain, DescFlag, Iotlb, QueueReg, VirtQueue, translate_iov, translate_ring,
+};
 
 #[repr(C, align(16))]
 #[derive(Debug, Clone, Default, FromBytes, Immutable, IntoBytes)]
@@ -70,6 +73,18 @@ impl WrappedIndex {
     }
 }
 
+impl From<u16> for WrappedIndex {
+    fn from(val: u16) -> Self {
+        WrappedIndex(val)
+    }
+}
+
+impl From<WrappedIndex> for u16 {
+    fn from(index: WrappedIndex) -> Self {
+        index.0
+    }
+}
+
 consts! {
     struct EventFlag(u16) {
         ENABLE = 0;
@@ -90,23 +105,37 @@ pub struct PackedQueue<'m> {
     enable_event_idx: bool,
     notification: *mut DescEvent,
     interrupt: *mut DescEvent,
+    iotlb: Option<Arc<dyn Iotlb>>,
     _phantom: PhantomData<&'m ()>,
 }
 
 impl<'m> PackedQueue<'m> {
-    pub fn new(reg: &QueueReg, ram: &'m Ram, event_idx: bool) -> Result<Option<PackedQueue<'m>>> {
+    pub fn new(
+        reg: &QueueReg,
+        ram: &'m Ram,
+        event_idx: bool,
+        iotlb: Option<Arc<dyn Iotlb>>,
+    ) -> Result<Option<PackedQueue<'m>>> {
         if !reg.enabled.load(Ordering::Acquire) {
             return Ok(None);
         }
         let size = reg.size.load(Ordering::Acquire);
+        let desc_len = size as u64 * size_of::<Desc>() as u64;
         let desc = reg.desc.load(Ordering::Acquire);
-        let notification: *mut DescEvent = ram.get_ptr(reg.device.load(Ordering::Acquire))?;
+        let desc = translate_ring(iotlb.as_ref(), desc, desc_len, true)?;
+        let event_len = size_of::<DescEvent>() as u64;
+        let device = reg.device.load(Ordering::Acquire);
+        let device = translate_ring(iotlb.as_ref(), device, event_len, true)?;
+        let driver = reg.driver.load(Ordering::Acquire);
+        let driver = translate_ring(iotlb.as_ref(), driver, event_len, false)?;
+        let notification: *mut DescEvent = ram.get_ptr(device)?;
         Ok(Some(PackedQueue {
             size,
             desc: ram.get_ptr(desc)?,
             enable_event_idx: event_idx,
             notification,
-            interrupt: ram.get_ptr(reg.driver.load(Ordering::Acquire))?,
+            interrupt: ram.get_ptr(driver)?,
+            iotlb,
             _phantom: PhantomData,
         }))
     }
@@ -151,6 +180,8 @@ impl<'m> VirtQueue<'m> for PackedQueue<'m> {
             if flag.contains(DescFlag::INDIRECT) {
                 for i in 0..(desc.len as usize / size_of::<Desc>()) {
                     let addr = desc.addr + (i * size_of::<Desc>()) as u64;
+                    let len = size_of::<Desc>() as u64;
+                    let addr = translate_ring(self.iotlb.as_ref(), addr, len, false)?;
                     let desc: Desc = ram.read_t(addr)?;
                     let flag = DescFlag::from_bits_retain(desc.flag);
                     if flag.contains(DescFlag::WRITE) {
@@ -170,6 +201,8 @@ impl<'m> VirtQueue<'m> for PackedQueue<'m> {
             }
             offset = (offset + 1) % self.size;
         };
+        let readable = translate_iov(self.iotlb.as_ref(), readable, false)?;
+        let writeable = translate_iov(self.iotlb.as_ref(), writeable, true)?;
         Ok(Some(DescChain {
             id,
             delta,
@@ -216,6 +249,12 @@ impl<'m> VirtQueue<'m> for PackedQueue<'m> {
     fn index_add(&self, index: Self::Index, delta: u16) -> Self::Index {
         index.wrapping_add(delta, self.size)
     }
+
+    fn mark_rings_dirty(&self, ram: &Ram) {
+        let desc_len = self.size as usize * size_of::<Desc>();
+        ram.mark_dirty_host(self.desc as *const u8, desc_len);
+        ram.mark_dirty_host(self.notification as *const u8, size_of::<DescEvent>());
+    }
 }
 
 #[cfg(test)]
@@ -13,14 +13,18 @@
 // limitations under the License.
 
 use std::collections::HashMap;
+use std::ptr::eq as ptr_eq;
 use std::sync::atomic::Ordering;
 
 use assert_matches::assert_matches;
 use rstest::rstest;
 
 use crate::mem::mapped::RamBus;
-use crate::virtio::queue::packed::{DescEvent, EventFlag, PackedQueue, WrappedIndex};
-use crate::virtio::queue::tests::{GuestQueue, UsedDesc, VirtQueueGuest};
+use crate::virtio::Error;
+use crate::virtio::queue::packed::{Desc, DescEvent, EventFlag, PackedQueue, WrappedIndex};
+use crate::virtio::queue::tests::{
+    DATA_IOVA, GuestQueue, RING_IOVA, UsedDesc, VirtQueueGuest, setup_iotlb,
+};
 use crate::virtio::queue::{DescFlag, QueueReg, VirtQueue};
 use crate::virtio::tests::{DATA_ADDR, QUEUE_SIZE, fixture_queues, fixture_ram_bus};
 
@@ -123,7 +127,7 @@ fn disabled_queue(fixture_ram_bus: RamBus, fixture_queues: Box<[QueueReg]>) {
     let ram = fixture_ram_bus.lock_layout();
     let reg = &fixture_queues[0];
     reg.enabled.store(false, Ordering::Relaxed);
-    let split_queue = PackedQueue::new(reg, &*ram, false);
+    let split_queue = PackedQueue::new(reg, &*ram, false, None);
     assert_matches!(split_queue, Ok(None));
 }
 
@@ -131,8 +135,11 @@ fn disabled_queue(fixture_ram_bus: RamBus, fixture_queues: Box<[QueueReg]>) {
 fn enabled_queue(fixture_ram_bus: RamBus, fixture_queues: Box<[QueueReg]>) {
     let ram = fixture_ram_bus.lock_layout();
     let reg = &fixture_queues[0];
-    let q = PackedQueue::new(reg, &*ram, false).unwrap().unwrap();
-    let mut guest_q = GuestQueue::new(PackedQueue::new(reg, &*ram, false).unwrap().unwrap(), reg);
+    let q = PackedQueue::new(reg, &*ram, false, None).unwrap().unwrap();
+    let mut guest_q = GuestQueue::new(
+        PackedQueue::new(reg, &*ram, false, None).unwrap().unwrap(),
+        reg,
+    );
 
     let str_0 = "Hello, World!";
     let str_1 = "Goodbye, World!";
@@ -190,7 +197,7 @@ fn enabled_queue(fixture_ram_bus: RamBus, fixture_queues: Box<[QueueReg]>) {
 fn enable_notification(fixture_ram_bus: RamBus, fixture_queues: Box<[QueueReg]>) {
     let ram = fixture_ram_bus.lock_layout();
     let reg = &fixture_queues[0];
-    let q = PackedQueue::new(reg, &*ram, false).unwrap().unwrap();
+    let q = PackedQueue::new(reg, &*ram, false, None).unwrap().unwrap();
 
     q.enable_notification(false);
     assert_eq!(unsafe { &*q.notification }.flag, EventFlag::DISABLE);
@@ -224,7 +231,7 @@ fn is_interrupt_enabled(
 ) {
     let ram = fixture_ram_bus.lock_layout();
     let reg = &fixture_queues[0];
-    let q = PackedQueue::new(reg, &*ram, enable_event_idx)
+    let q = PackedQueue::new(reg, &*ram, enable_event_idx, None)
         .unwrap()
         .unwrap();
 
@@ -238,3 +245,111 @@ fn is_interrupt_enabled(
         expected
     );
 }
+
+#[rstest]
+fn iotlb_queue(fixture_ram_bus: RamBus, #[by_ref] fixture_queues: &[QueueReg]) {
+    let ram = fixture_ram_bus.lock_layout();
+    let reg = &fixture_queues[0];
+    let desc_gpa = reg.desc.load(Ordering::Acquire);
+    let device_gpa = reg.device.load(Ordering::Acquire);
+    let iotlb = setup_iotlb(reg);
+    assert_eq!(reg.desc.load(Ordering::Acquire), RING_IOVA);
+
+    let q = PackedQueue::new(reg, &ram, false, Some(iotlb.clone()))
+        .unwrap()
+        .unwrap();
+    assert!(ptr_eq(q.desc, ram.get_ptr(desc_gpa).unwrap()));
+    assert!(ptr_eq(q.notification, ram.get_ptr(device_gpa).unwrap()));
+    let mut guest_q = GuestQueue::new(
+        PackedQueue::new(reg, &ram, false, Some(iotlb))
+            .unwrap()
+            .unwrap(),
+        reg,
+    );
+
+    // DATA_IOVA + 0xff0 .. DATA_IOVA + 0x1010 is split into 2 segments.
+    let str_0 = "Hello, World!   ";
+    let str_1 = "Goodbye, World! ";
+    ram.write(DATA_ADDR + 0x1ff0, str_0.as_bytes()).unwrap();
+    ram.write(DATA_ADDR, str_1.as_bytes()).unwrap();
+    let mut avail_index = WrappedIndex::INIT;
+    let id = guest_q.add_desc(&[(DATA_IOVA + 0xff0, 0x20)], &[(DATA_IOVA + 0x100, 4)]);
+    let mut chain = q.get_avail(avail_index, &ram).unwrap().unwrap();
+    assert_eq!(chain.id, id);
+    assert_eq!(chain.readable.len(), 2);
+    assert_eq!(&*chain.readable[0], str_0.as_bytes());
+    assert_eq!(&*chain.readable[1], str_1.as_bytes());
+    assert_eq!(chain.writable.len(), 1);
+    chain.writable[0].copy_from_slice(b"done");
+    q.set_used(avail_index, chain.id, 4);
+    avail_index = q.index_add(avail_index, chain.delta);
+    let used = guest_q.get_used().unwrap();
+    assert_eq!(used.id, id);
+    assert_eq!(used.len, 4);
+    assert_eq!(ram.read_t::<[u8; 4]>(DATA_ADDR + 0x1100).unwrap(), *b"done");
+
+    // The indirect table is at DATA_IOVA + 0x800.
+    let table = [
+        Desc {
+            addr: DATA_IOVA + 0x200,
+            len: 4,
+            id: 0,
+            flag: 0,
+        },
+        Desc {
+            addr: DATA_IOVA + 0x1000,
+            len: 8,
+            id: 0,
+            flag: DescFlag::WRITE.bits(),
+        },
+    ];
+    ram.write_t(DATA_ADDR + 0x1800, &table).unwrap();
+    ram.write(DATA_ADDR + 0x1200, b"ping").unwrap();
+    let id = guest_q.add_desc(&[(DATA_IOVA + 0x800, size_of_val(&table) as u32)], &[]);
+    let desc_addr = desc_gpa + avail_index.offset() as u64 * size_of::<Desc>() as u64;
+    let mut desc: Desc = ram.read_t(desc_addr).unwrap();
+    desc.flag |= DescFlag::INDIRECT.bits();
+    ram.write_t(desc_addr, &desc).unwrap();
+
+    let chain = q.get_avail(avail_index, &ram).unwrap().unwrap();
+    assert_eq!(chain.id, id);
+    assert_eq!(chain.delta, 1);
+    assert_eq!(chain.readable.len(), 1);
+    assert_eq!(&*chain.readable[0], b"ping");
+    assert_eq!(chain.writable.len(), 1);
+    assert_eq!(chain.writable[0].len(), 8);
+    assert!(ptr_eq(
+        chain.writable[0].as_ptr(),
+        ram.get_ptr(DATA_ADDR).unwrap()
+    ));
+}
+
+#[rstest]
+fn iotlb_fault(fixture_ram_bus: RamBus, #[by_ref] fixture_queues: &[QueueReg]) {
+    let ram = fixture_ram_bus.lock_layout();
+    let reg = &fixture_queues[0];
+    let iotlb = setup_iotlb(reg);
+
+    let device = reg.device.load(Ordering::Acquire);
+    reg.device.store(DATA_IOVA + 0x2000, Ordering::Release);
+    assert_matches!(
+        PackedQueue::new(reg, &ram, false, Some(iotlb.clone())),
+        Err(Error::IommuFault { iova, .. }) if iova == DATA_IOVA + 0x2000
+    );
+    reg.device.store(device, Ordering::Release);
+
+    let q = PackedQueue::new(reg, &ram, false, Some(iotlb.clone()))
+        .unwrap()
+        .unwrap();
+    let mut guest_q = GuestQueue::new(
+        PackedQueue::new(reg, &ram, false, Some(iotlb))
+            .unwrap()
+            .unwrap(),
+        reg,
+    );
+    guest_q.add_desc(&[(DATA_IOVA + 0x1ffc, 8)], &[]);
+    assert_matches!(
+        q.get_avail(WrappedIndex::INIT, &ram),
+        Err(Error::IommuFault { iova, len: 8, .. }) if iova == DATA_IOVA + 0x1ffc
+    );
+}
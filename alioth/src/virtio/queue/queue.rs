@@ -18,6 +18,7 @@ pub mod split;
 use std::collections::HashMap;
 use std::fmt::Debug;
 use std::io::{ErrorKind, IoSlice, IoSliceMut, Read, Write};
+use std::sync::Arc;
 use std::sync::atomic::{AtomicBool, AtomicU16, AtomicU64, Ordering, fence};
 
 use crate::bitflags;
@@ -36,6 +37,17 @@ bitflags! {
     }
 }
 
+#[derive(Debug, Default)]
+pub struct QueueStats {
+    pub notifications: AtomicU64,
+    pub interrupts: AtomicU64,
+    pub requests: AtomicU64,
+    /// Bytes the device read from guest buffers.
+    pub read_bytes: AtomicU64,
+    /// Bytes the device wrote to guest buffers.
+    pub written_bytes: AtomicU64,
+}
+
 #[derive(Debug, Default)]
 pub struct QueueReg {
     pub size: AtomicU16,
@@ -43,6 +55,7 @@ pub struct QueueReg {
     pub driver: AtomicU64,
     pub device: AtomicU64,
     pub enabled: AtomicBool,
+    pub stats: QueueStats,
 }
 
 #[derive(Debug)]
@@ -59,8 +72,43 @@ impl DescChain<'_> {
     }
 }
 
+/// Translates the I/O virtual addresses a driver puts in a queue to guest
+/// physical addresses, for devices behind a virtual IOMMU.
+pub trait Iotlb: Debug + Send + Sync + 'static {
+    /// Returns the guest physical ranges backing `len` bytes at `iova`.
+    /// `write` is true if the device writes to the range.
+    fn translate(&self, iova: u64, len: u64, write: bool) -> Result<Vec<(u64, u64)>>;
+}
+
+/// Translates a ring or a descriptor table, which the device accesses as
+/// one piece of guest memory.
+fn translate_ring(iotlb: Option<&Arc<dyn Iotlb>>, iova: u64, len: u64, write: bool) -> Result<u64> {
+    let Some(iotlb) = iotlb else {
+        return Ok(iova);
+    };
+    match *iotlb.translate(iova, len, write)? {
+        [(gpa, _)] => Ok(gpa),
+        _ => error::IommuFault { iova, len }.fail(),
+    }
+}
+
+fn translate_iov(
+    iotlb: Option<&Arc<dyn Iotlb>>,
+    iov: Vec<(u64, u64)>,
+    write: bool,
+) -> Result<Vec<(u64, u64)>> {
+    let Some(iotlb) = iotlb else {
+        return Ok(iov);
+    };
+    let mut gpas = Vec::with_capacity(iov.len());
+    for (iova, len) in iov {
+        gpas.extend(iotlb.translate(iova, len, write)?);
+    }
+    Ok(gpas)
+}
+
 pub trait VirtQueue<'m> {
-    type Index: Clone + Copy;
+    type Index: Clone + Copy + From<u16> + Into<u16>;
     const INIT_INDEX: Self::Index;
     fn desc_avail(&self, index: Self::Index) -> bool;
     fn get_avail(&self, index: Self::Index, ram: &'m Ram) -> Result<Option<DescChain<'m>>>;
@@ -68,6 +116,8 @@ pub trait VirtQueue<'m> {
     fn enable_notification(&self, enabled: bool);
     fn interrupt_enabled(&self, index: Self::Index, delta: u16) -> bool;
     fn index_add(&self, index: Self::Index, delta: u16) -> Self::Index;
+    /// Marks the parts of the rings written by the device dirty.
+    fn mark_rings_dirty(&self, ram: &Ram);
 }
 
 #[derive(Debug)]
@@ -108,7 +158,38 @@ where
         self.reg
     }
 
+    /// Returns the position of the queue, valid once the device has used all
+    /// buffers it took.
+    pub fn index(&self) -> u16 {
+        self.used.into()
+    }
+
+    /// Returns the IDs of the buffers taken by the device but not used yet.
+    pub fn deferred_ids(&self) -> impl Iterator<Item = u16> + '_ {
+        self.deferred.keys().copied()
+    }
+
+    pub fn deferred_mut(&mut self, id: u16) -> Option<&mut DescChain<'m>> {
+        self.deferred.get_mut(&id)
+    }
+
+    /// Resumes the queue from a position returned by [`Queue::index`].
+    pub fn set_index(&mut self, index: u16) {
+        self.avail = index.into();
+        self.used = index.into();
+    }
+
     fn push_used(&mut self, chain: DescChain, len: u32) {
+        let stats = &self.reg.stats;
+        let read_bytes: usize = chain.readable.iter().map(|s| s.len()).sum();
+        stats.requests.fetch_add(1, Ordering::Relaxed);
+        stats
+            .read_bytes
+            .fetch_add(read_bytes as u64, Ordering::Relaxed);
+        stats.written_bytes.fetch_add(len as u64, Ordering::Relaxed);
+        for s in &chain.writable {
+            self.ram.mark_dirty_host(s.as_ptr(), s.len());
+        }
         self.q.set_used(self.used, chain.id, len);
         self.used = self.q.index_add(self.used, chain.delta);
     }
@@ -126,7 +207,9 @@ where
         let len = op(&mut chain)?;
         let delta = chain.delta;
         self.push_used(chain, len);
+        self.q.mark_rings_dirty(self.ram);
         if self.q.interrupt_enabled(self.used, delta) {
+            self.reg.stats.interrupts.fetch_add(1, Ordering::Relaxed);
             irq_sender.queue_irq(q_index);
         }
         Ok(())
@@ -167,8 +250,10 @@ where
             self.q.enable_notification(true);
             fence(Ordering::SeqCst);
         }
+        self.q.mark_rings_dirty(self.ram);
         if send_irq {
             fence(Ordering::SeqCst);
+            self.reg.stats.interrupts.fetch_add(1, Ordering::Relaxed);
             irq_sender.queue_irq(q_index);
         }
         ret
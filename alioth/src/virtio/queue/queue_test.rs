@@ -12,9 +12,10 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::io::{ErrorKind, IoSlice, IoSliceMut, Read, Write};
 use std::ptr::eq as ptr_eq;
+use std::sync::Arc;
 use std::sync::atomic::Ordering;
 use std::sync::mpsc::{self, TryRecvError};
 
@@ -22,12 +23,12 @@ use assert_matches::assert_matches;
 use rstest::rstest;
 
 use crate::mem::mapped::RamBus;
-use crate::virtio::Error;
 use crate::virtio::queue::split::SplitQueue;
 use crate::virtio::queue::{
-    DescChain, Queue, QueueReg, Status, VirtQueue, copy_from_reader, copy_to_writer,
+    DescChain, Iotlb, Queue, QueueReg, Status, VirtQueue, copy_from_reader, copy_to_writer,
 };
 use crate::virtio::tests::{DATA_ADDR, FakeIrqSender, fixture_queues, fixture_ram_bus};
+use crate::virtio::{Error, Result, error};
 
 pub struct UsedDesc {
     pub id: u16,
@@ -113,6 +114,51 @@ where
     }
 }
 
+pub const RING_IOVA: u64 = 0x1_0000_0000;
+pub const DATA_IOVA: u64 = 0x2_0000_0000;
+
+/// Maps I/O virtual addresses to guest physical addresses.
+#[derive(Debug, Default)]
+pub struct FakeIotlb {
+    /// IOVA -> (GPA, size)
+    mappings: BTreeMap<u64, (u64, u64)>,
+}
+
+impl Iotlb for FakeIotlb {
+    fn translate(&self, iova: u64, len: u64, _write: bool) -> Result<Vec<(u64, u64)>> {
+        let mut ranges = vec![];
+        let mut addr = iova;
+        while addr < iova + len {
+            let Some((start, (gpa, size))) = self.mappings.range(..=addr).next_back() else {
+                return error::IommuFault { iova, len }.fail();
+            };
+            if addr >= start + size {
+                return error::IommuFault { iova, len }.fail();
+            }
+            let count = std::cmp::min(iova + len, start + size) - addr;
+            ranges.push((gpa + (addr - start), count));
+            addr += count;
+        }
+        Ok(ranges)
+    }
+}
+
+/// Moves the rings of `reg` to `RING_IOVA` and maps 2 pages at `DATA_IOVA`
+/// to 2 guest pages in reverse order, so that a buffer across the page
+/// boundary is split into 2 segments.
+pub fn setup_iotlb(reg: &QueueReg) -> Arc<dyn Iotlb> {
+    let base = reg.desc.load(Ordering::Acquire);
+    for addr in [&reg.desc, &reg.driver, &reg.device] {
+        let gpa = addr.load(Ordering::Acquire);
+        addr.store(RING_IOVA + (gpa - base), Ordering::Release);
+    }
+    let mut mappings = BTreeMap::new();
+    mappings.insert(RING_IOVA, (base, 0x2000));
+    mappings.insert(DATA_IOVA, (DATA_ADDR + 0x1000, 0x1000));
+    mappings.insert(DATA_IOVA + 0x1000, (DATA_ADDR, 0x1000));
+    Arc::new(FakeIotlb { mappings })
+}
+
 #[derive(Debug)]
 enum ReaderData<'a> {
     Buf(&'a [u8]),
@@ -176,11 +222,14 @@ fn test_copy_from_reader(fixture_ram_bus: RamBus, fixture_queues: Box<[QueueReg]
     let ram = fixture_ram_bus.lock_layout();
     let reg = &fixture_queues[0];
     let mut host_q = Queue::new(
-        SplitQueue::new(reg, &*ram, false).unwrap().unwrap(),
+        SplitQueue::new(reg, &*ram, false, None).unwrap().unwrap(),
         reg,
         &ram,
     );
-    let mut guest_q = GuestQueue::new(SplitQueue::new(reg, &*ram, false).unwrap().unwrap(), reg);
+    let mut guest_q = GuestQueue::new(
+        SplitQueue::new(reg, &*ram, false, None).unwrap().unwrap(),
+        reg,
+    );
     assert!(ptr_eq(host_q.reg(), reg));
 
     let (irq_tx, irq_rx) = mpsc::channel();
@@ -333,11 +382,14 @@ fn test_copy_to_writer(fixture_ram_bus: RamBus, fixture_queues: Box<[QueueReg]>)
     let ram = fixture_ram_bus.lock_layout();
     let reg = &fixture_queues[0];
     let mut host_q = Queue::new(
-        SplitQueue::new(reg, &*ram, false).unwrap().unwrap(),
+        SplitQueue::new(reg, &*ram, false, None).unwrap().unwrap(),
         reg,
         &ram,
     );
-    let mut guest_q = GuestQueue::new(SplitQueue::new(reg, &*ram, false).unwrap().unwrap(), reg);
+    let mut guest_q = GuestQueue::new(
+        SplitQueue::new(reg, &*ram, false, None).unwrap().unwrap(),
+        reg,
+    );
     let (irq_tx, irq_rx) = mpsc::channel();
     let irq_sender = FakeIrqSender { q_tx: irq_tx };
 
@@ -463,11 +515,14 @@ fn test_handle_deferred(fixture_ram_bus: RamBus, fixture_queues: Box<[QueueReg]>
     let ram = fixture_ram_bus.lock_layout();
     let reg = &fixture_queues[0];
     let mut host_q = Queue::new(
-        SplitQueue::new(reg, &ram, false).unwrap().unwrap(),
+        SplitQueue::new(reg, &ram, false, None).unwrap().unwrap(),
         reg,
         &ram,
     );
-    let mut guest_q = GuestQueue::new(SplitQueue::new(reg, &ram, false).unwrap().unwrap(), reg);
+    let mut guest_q = GuestQueue::new(
+        SplitQueue::new(reg, &ram, false, None).unwrap().unwrap(),
+        reg,
+    );
     let (irq_tx, irq_rx) = mpsc::channel();
     let irq_sender = FakeIrqSender { q_tx: irq_tx };
 
@@ -521,4 +576,12 @@ fn test_handle_deferred(fixture_ram_bus: RamBus, fixture_queues: Box<[QueueReg]>
             Ok(0)
         })
         .unwrap();
+
+    let stats = &reg.stats;
+    assert_eq!(stats.requests.load(Ordering::Relaxed), 2);
+    let read_bytes = str_0.len() + str_1.len() + str_2.len();
+    assert_eq!(stats.read_bytes.load(Ordering::Relaxed), read_bytes as u64);
+    assert_eq!(stats.written_bytes.load(Ordering::Relaxed), 0);
+    let irqs = irq_rx.try_iter().count();
+    assert_eq!(stats.interrupts.load(Ordering::Relaxed), irqs as u64);
 }
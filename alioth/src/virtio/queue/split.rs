@@ -14,6 +14,7 @@
 
 use std::marker::PhantomData;
 use std::mem::size_of;
+use std::sync::Arc;
 use std::sync::atomic::{Ordering, fence};
 
 use alioth_macros::Layout;
@@ -21,7 +22,9 @@ use zerocopy::{FromBytes, Immutable, IntoBytes};
 
 use crate::bitflags;
 use crate::mem::mapped::Ram;
-use crate::virtio::queue::{DescChain, DescFlag, QueueReg, VirtQueue};
+use crate::virtio::queue::{
+    DescChain, DescFlag, Iotlb, QueueReg, VirtQueue, translate_iov, translate_ring,
+};
 use crate::virtio::{Result, error};
 
 #[repr(C, align(16))]
@@ -76,6 +79,7 @@ pub struct SplitQueue<'m> {
     used_ring: *mut UsedElem,
     avail_event: Option<*mut u16>,
     desc: *mut Desc,
+    iotlb: Option<Arc<dyn Iotlb>>,
     _phantom: PhantomData<&'m ()>,
 }
 
@@ -120,15 +124,26 @@ impl SplitQueue<'_> {
 }
 
 impl<'m> SplitQueue<'m> {
-    pub fn new(reg: &QueueReg, ram: &'m Ram, event_idx: bool) -> Result<Option<SplitQueue<'m>>> {
+    pub fn new(
+        reg: &QueueReg,
+        ram: &'m Ram,
+        event_idx: bool,
+        iotlb: Option<Arc<dyn Iotlb>>,
+    ) -> Result<Option<SplitQueue<'m>>> {
         if !reg.enabled.load(Ordering::Acquire) {
             return Ok(None);
         }
         let size = reg.size.load(Ordering::Acquire) as u64;
         let mut avail_event = None;
         let mut used_event = None;
+        let used_len = (size_of::<UsedHeader>() + size_of::<u16>()) as u64
+            + size * size_of::<UsedElem>() as u64;
         let used = reg.device.load(Ordering::Acquire);
+        let used = translate_ring(iotlb.as_ref(), used, used_len, true)?;
+        let avail_len =
+            (size_of::<AvailHeader>() + size_of::<u16>()) as u64 + size * size_of::<u16>() as u64;
         let avail = reg.driver.load(Ordering::Acquire);
+        let avail = translate_ring(iotlb.as_ref(), avail, avail_len, false)?;
         if event_idx {
             let avail_event_gpa =
                 used + size_of::<UsedHeader>() as u64 + size * size_of::<UsedElem>() as u64;
@@ -141,6 +156,8 @@ impl<'m> SplitQueue<'m> {
         let avail_ring_gpa = avail + size_of::<AvailHeader>() as u64;
         let used_ring_gpa = used + size_of::<UsedHeader>() as u64;
         let desc = reg.desc.load(Ordering::Acquire);
+        let desc_len = size * size_of::<Desc>() as u64;
+        let desc = translate_ring(iotlb.as_ref(), desc, desc_len, false)?;
         Ok(Some(SplitQueue {
             size: size as u16,
             avail_hdr: ram.get_ptr(avail)?,
@@ -150,6 +167,7 @@ impl<'m> SplitQueue<'m> {
             used_ring: ram.get_ptr(used_ring_gpa)?,
             avail_event,
             desc: ram.get_ptr(desc)?,
+            iotlb,
             _phantom: PhantomData,
         }))
     }
@@ -181,6 +199,8 @@ impl<'m> VirtQueue<'m> for SplitQueue<'m> {
                 let mut id = 0;
                 loop {
                     let addr = desc.addr + id as u64 * size_of::<Desc>() as u64;
+                    let len = size_of::<Desc>() as u64;
+                    let addr = translate_ring(self.iotlb.as_ref(), addr, len, false)?;
                     let desc: Desc = ram.read_t(addr)?;
                     let flag = DescFlag::from_bits_retain(desc.flag);
                     assert!(!flag.contains(DescFlag::INDIRECT));
@@ -206,6 +226,8 @@ impl<'m> VirtQueue<'m> for SplitQueue<'m> {
                 break;
             }
         }
+        let readable = translate_iov(self.iotlb.as_ref(), readable, false)?;
+        let writable = translate_iov(self.iotlb.as_ref(), writable, true)?;
         let readable = ram.translate_iov(&readable)?;
         let writable = ram.translate_iov_mut(&writable)?;
         Ok(Some(DescChain {
@@ -256,6 +278,14 @@ impl<'m> VirtQueue<'m> for SplitQueue<'m> {
     fn index_add(&self, index: Self::Index, _: u16) -> Self::Index {
         index.wrapping_add(1)
     }
+
+    fn mark_rings_dirty(&self, ram: &Ram) {
+        let mut len = size_of::<UsedHeader>() + self.size as usize * size_of::<UsedElem>();
+        if self.avail_event.is_some() {
+            len += size_of::<u16>();
+        }
+        ram.mark_dirty_host(self.used_hdr as *const u8, len);
+    }
 }
 
 #[cfg(test)]
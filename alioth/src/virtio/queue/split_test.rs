@@ -13,14 +13,18 @@
 // limitations under the License.
 
 use std::collections::HashMap;
+use std::ptr::eq as ptr_eq;
 use std::sync::atomic::Ordering;
 
 use assert_matches::assert_matches;
 use rstest::rstest;
 
 use crate::mem::mapped::RamBus;
+use crate::virtio::Error;
 use crate::virtio::queue::split::{Desc, DescFlag, SplitQueue};
-use crate::virtio::queue::tests::{GuestQueue, UsedDesc, VirtQueueGuest};
+use crate::virtio::queue::tests::{
+    DATA_IOVA, GuestQueue, RING_IOVA, UsedDesc, VirtQueueGuest, setup_iotlb,
+};
 use crate::virtio::queue::{QueueReg, VirtQueue};
 use crate::virtio::tests::{DATA_ADDR, fixture_queues, fixture_ram_bus};
 
@@ -76,7 +80,7 @@ fn disabled_queue(fixture_ram_bus: RamBus, fixture_queues: Box<[QueueReg]>) {
     let ram = fixture_ram_bus.lock_layout();
     let reg = &fixture_queues[0];
     reg.enabled.store(false, Ordering::Relaxed);
-    let split_queue = SplitQueue::new(reg, &*ram, false);
+    let split_queue = SplitQueue::new(reg, &*ram, false, None);
     assert_matches!(split_queue, Ok(None));
 }
 
@@ -84,8 +88,11 @@ fn disabled_queue(fixture_ram_bus: RamBus, fixture_queues: Box<[QueueReg]>) {
 fn enabled_queue(fixture_ram_bus: RamBus, fixture_queues: Box<[QueueReg]>) {
     let ram = fixture_ram_bus.lock_layout();
     let reg = &fixture_queues[0];
-    let q = SplitQueue::new(reg, &*ram, false).unwrap().unwrap();
-    let mut guest_q = GuestQueue::new(SplitQueue::new(reg, &*ram, false).unwrap().unwrap(), reg);
+    let q = SplitQueue::new(reg, &*ram, false, None).unwrap().unwrap();
+    let mut guest_q = GuestQueue::new(
+        SplitQueue::new(reg, &*ram, false, None).unwrap().unwrap(),
+        reg,
+    );
 
     let str_0 = "Hello, World!";
     let str_1 = "Goodbye, World!";
@@ -134,10 +141,113 @@ fn enabled_queue(fixture_ram_bus: RamBus, fixture_queues: Box<[QueueReg]>) {
 fn event_idx_enabled(fixture_ram_bus: RamBus, fixture_queues: Box<[QueueReg]>) {
     let ram = fixture_ram_bus.lock_layout();
     let reg = &fixture_queues[0];
-    let q = SplitQueue::new(reg, &*ram, true).unwrap().unwrap();
+    let q = SplitQueue::new(reg, &*ram, true, None).unwrap().unwrap();
     unsafe { *q.used_event.unwrap() = 1 };
     assert_eq!(q.used_event(), Some(1));
 
     assert!(q.set_avail_event(|event| *event = 12));
     assert_eq!(unsafe { *q.avail_event.unwrap() }, 12);
 }
+
+#[rstest]
+fn iotlb_queue(fixture_ram_bus: RamBus, #[by_ref] fixture_queues: &[QueueReg]) {
+    let ram = fixture_ram_bus.lock_layout();
+    let reg = &fixture_queues[0];
+    let desc_gpa = reg.desc.load(Ordering::Acquire);
+    let iotlb = setup_iotlb(reg);
+    assert_eq!(reg.desc.load(Ordering::Acquire), RING_IOVA);
+
+    let q = SplitQueue::new(reg, &ram, false, Some(iotlb.clone()))
+        .unwrap()
+        .unwrap();
+    assert!(ptr_eq(q.desc, ram.get_ptr(desc_gpa).unwrap()));
+    let mut guest_q = GuestQueue::new(
+        SplitQueue::new(reg, &ram, false, Some(iotlb))
+            .unwrap()
+            .unwrap(),
+        reg,
+    );
+
+    // DATA_IOVA + 0xff0 .. DATA_IOVA + 0x1010 is split into 2 segments.
+    let str_0 = "Hello, World!   ";
+    let str_1 = "Goodbye, World! ";
+    ram.write(DATA_ADDR + 0x1ff0, str_0.as_bytes()).unwrap();
+    ram.write(DATA_ADDR, str_1.as_bytes()).unwrap();
+    let id = guest_q.add_desc(&[(DATA_IOVA + 0xff0, 0x20)], &[(DATA_IOVA + 0x100, 4)]);
+    let mut chain = q.get_avail(0, &ram).unwrap().unwrap();
+    assert_eq!(chain.id, id);
+    assert_eq!(chain.readable.len(), 2);
+    assert_eq!(&*chain.readable[0], str_0.as_bytes());
+    assert_eq!(&*chain.readable[1], str_1.as_bytes());
+    assert_eq!(chain.writable.len(), 1);
+    chain.writable[0].copy_from_slice(b"done");
+    q.set_used(0, chain.id, 4);
+    let used = guest_q.get_used().unwrap();
+    assert_eq!(used.id, id);
+    assert_eq!(used.len, 4);
+    assert_eq!(ram.read_t::<[u8; 4]>(DATA_ADDR + 0x1100).unwrap(), *b"done");
+
+    // The indirect table is at DATA_IOVA + 0x800.
+    let table = [
+        Desc {
+            addr: DATA_IOVA + 0x200,
+            len: 4,
+            flag: DescFlag::NEXT.bits(),
+            next: 1,
+        },
+        Desc {
+            addr: DATA_IOVA + 0x1000,
+            len: 8,
+            flag: DescFlag::WRITE.bits(),
+            next: 0,
+        },
+    ];
+    ram.write_t(DATA_ADDR + 0x1800, &table).unwrap();
+    ram.write(DATA_ADDR + 0x1200, b"ping").unwrap();
+    let id = guest_q.add_desc(&[(DATA_IOVA + 0x800, size_of_val(&table) as u32)], &[]);
+    let desc_addr = desc_gpa + id as u64 * size_of::<Desc>() as u64;
+    let mut desc: Desc = ram.read_t(desc_addr).unwrap();
+    desc.flag |= DescFlag::INDIRECT.bits();
+    ram.write_t(desc_addr, &desc).unwrap();
+
+    let chain = q.get_avail(1, &ram).unwrap().unwrap();
+    assert_eq!(chain.id, id);
+    assert_eq!(chain.readable.len(), 1);
+    assert_eq!(&*chain.readable[0], b"ping");
+    assert_eq!(chain.writable.len(), 1);
+    assert_eq!(chain.writable[0].len(), 8);
+    assert!(ptr_eq(
+        chain.writable[0].as_ptr(),
+        ram.get_ptr(DATA_ADDR).unwrap()
+    ));
+}
+
+#[rstest]
+fn iotlb_fault(fixture_ram_bus: RamBus, #[by_ref] fixture_queues: &[QueueReg]) {
+    let ram = fixture_ram_bus.lock_layout();
+    let reg = &fixture_queues[0];
+    let iotlb = setup_iotlb(reg);
+
+    let driver = reg.driver.load(Ordering::Acquire);
+    reg.driver.store(DATA_IOVA + 0x2000, Ordering::Release);
+    assert_matches!(
+        SplitQueue::new(reg, &ram, false, Some(iotlb.clone())),
+        Err(Error::IommuFault { iova, .. }) if iova == DATA_IOVA + 0x2000
+    );
+    reg.driver.store(driver, Ordering::Release);
+
+    let q = SplitQueue::new(reg, &ram, false, Some(iotlb.clone()))
+        .unwrap()
+        .unwrap();
+    let mut guest_q = GuestQueue::new(
+        SplitQueue::new(reg, &ram, false, Some(iotlb))
+            .unwrap()
+            .unwrap(),
+        reg,
+    );
+    guest_q.add_desc(&[(DATA_IOVA + 0x1ffc, 8)], &[]);
+    assert_matches!(
+        q.get_avail(0, &ram),
+        Err(Error::IommuFault { iova, len: 8, .. }) if iova == DATA_IOVA + 0x1ffc
+    );
+}
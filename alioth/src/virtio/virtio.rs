@@ -36,7 +36,7 @@ use crate::{bitflags, consts};
 
 #[trace_error]
 #[derive(Snafu, DebugTrace)]
-#[snafu(module, context(suffix(false)))]
+#[snafu(module, visibility(pub(crate)), context(suffix(false)))]
 pub enum Error {
     #[snafu(display("Hypervisor internal error"), context(false))]
     HvError { source: Box<crate::hv::Error> },
@@ -64,6 +64,8 @@ pub enum Error {
     PollEvents { error: std::io::Error },
     #[snafu(display("Failed to create a worker thread"))]
     WorkerThread { error: std::io::Error },
+    #[snafu(display("Worker thread has stopped"))]
+    WorkerStopped,
     #[snafu(display("Invalid descriptor id {id}"))]
     InvalidDescriptor { id: u16 },
     #[snafu(display("Invalid queue index {index}"))]
@@ -72,6 +74,23 @@ pub enum Error {
     InvalidMsixVector { vector: u16 },
     #[snafu(display("Invalid virtq buffer"))]
     InvalidBuffer,
+    #[snafu(display("Invalid device state in a snapshot"))]
+    InvalidDeviceState,
+    #[snafu(display("Failed to create a console"))]
+    CreateConsole { source: Box<crate::device::Error> },
+    #[snafu(display("A console device needs at least one port"))]
+    NoConsolePort,
+    #[snafu(display("Invalid balloon size {size:#x}"))]
+    InvalidBalloonSize { size: u64 },
+    #[snafu(display("Size {size:#x} of {path:?} is not a non-zero multiple of 4KiB"))]
+    InvalidPmemSize { path: Box<Path>, size: u64 },
+    #[snafu(display("Invalid virtio-mem parameter: {msg}"))]
+    InvalidMemParam { msg: String },
+    #[snafu(display("IOMMU fault at I/O virtual address {iova:#x}, size {len:#x}"))]
+    IommuFault { iova: u64, len: u64 },
+    #[cfg(target_os = "linux")]
+    #[snafu(display("Failed to update the host IOMMU"))]
+    HostIommu { source: Box<crate::vfio::Error> },
     #[cfg(target_os = "linux")]
     #[snafu(display("vhost-user error"), context(false))]
     Vu { source: Box<vu::Error> },
@@ -82,7 +101,7 @@ pub enum Error {
     Fuse { source: Box<crate::fuse::Error> },
 }
 
-type Result<T, E = Error> = std::result::Result<T, E>;
+pub(crate) type Result<T, E = Error> = std::result::Result<T, E>;
 
 bitflags! {
     pub struct VirtioFeature(u128) {
@@ -103,6 +122,7 @@ consts! {
     pub struct DeviceId(u16) {
         NET = 1;
         BLOCK = 2;
+        CONSOLE = 3;
         ENTROPY = 4;
         BALLOON = 5;
         SOCKET = 19;
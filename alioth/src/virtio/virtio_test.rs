@@ -47,6 +47,7 @@ pub fn fixture_queues(#[default(1)] count: u16) -> Box<[QueueReg]> {
                 driver: AtomicU64::new(base + 0x1000),
                 device: AtomicU64::new(base + 0x1400),
                 enabled: AtomicBool::new(true),
+                stats: Default::default(),
             }
         })
         .collect()
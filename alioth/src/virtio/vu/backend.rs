@@ -256,6 +256,9 @@ impl VuBackend {
             feature: self.init.drv_feat as u128,
             irq_sender: Arc::new(irq_sender),
             ioeventfds: Some(ioeventfds.into()),
+            iotlb: None,
+            restored: None,
+            worker_cpus: [].into(),
         })
     }
 
@@ -273,6 +273,10 @@ impl Virtio for VuFrontend {
         }
     }
 
+    fn dma_translatable(&self) -> bool {
+        false
+    }
+
     fn shared_mem_regions(&self) -> Option<Arc<MemRegion>> {
         None
     }
@@ -15,6 +15,7 @@
 use std::iter;
 use std::os::fd::{AsFd, AsRawFd};
 use std::sync::Arc;
+use std::sync::atomic::Ordering;
 use std::sync::mpsc::Receiver;
 use std::thread::JoinHandle;
 
@@ -25,6 +26,7 @@ use io_uring::{SubmissionQueue, opcode, types};
 use crate::hv::IoeventFd;
 use crate::mem::mapped::{Ram, RamBus};
 use crate::sync::notifier::Notifier;
+use crate::utils::thread::cpu_set;
 use crate::virtio::dev::{
     ActiveBackend, Backend, BackendEvent, Context, StartParam, Virtio, WakeEvent, Worker,
     WorkerState,
@@ -35,6 +37,8 @@ use crate::virtio::{IrqSender, Result};
 pub enum BufferAction {
     Sqe(Sqe),
     Written(u32),
+    /// Leaves the buffer and the ones after it in the queue.
+    Hold,
 }
 
 pub trait VirtioIoUring: Virtio {
@@ -51,10 +55,19 @@ pub trait VirtioIoUring: Virtio {
     fn handle_desc(&mut self, q_index: u16, chain: &mut DescChain) -> Result<BufferAction>;
 
     fn complete_desc(&mut self, q_index: u16, chain: &mut DescChain, cqe: &Cqe) -> Result<u32>;
+
+    /// Returns true if the buffers of a queue can wait indefinitely for
+    /// external events, e.g. receive buffers of a network device. Such
+    /// buffers are canceled when the device pauses and submitted again when
+    /// it resumes.
+    fn cancel_on_pause(&self, _q_index: u16) -> bool {
+        false
+    }
 }
 
 const TOKEN_QUEUE: u64 = 1 << 62;
 const TOKEN_DESCRIPTOR: u64 = (1 << 62) | (1 << 61);
+const TOKEN_CANCEL: u64 = 1 << 60;
 
 pub struct IoUring {
     notifier: Arc<Notifier>,
@@ -124,15 +137,22 @@ where
         Q: VirtQueue<'m>,
         E: IoeventFd,
     {
+        let ring = io_uring::IoUring::new(RING_SIZE as u32)?;
+        if !param.worker_cpus.is_empty() {
+            let cpus = cpu_set(&param.worker_cpus)?;
+            ring.submitter().register_iowq_aff(&cpus)?;
+        }
         let submit_counts = iter::repeat_n(0, queues.len()).collect();
         let mut active_ring = ActiveIoUring {
-            ring: io_uring::IoUring::new(RING_SIZE as u32)?,
+            ring,
             shared_count: RING_SIZE - 1,
             irq_sender: &*param.irq_sender,
             ioeventfds: param.ioeventfds.as_deref().unwrap_or(&[]),
             mem: memory,
             queues,
             submit_counts,
+            paused: false,
+            canceled: Vec::new(),
         };
         self.submit_notifier(&mut active_ring.ring.submission())?;
         context.dev.activate(param.feature, &mut active_ring)?;
@@ -175,6 +195,9 @@ where
     pub mem: &'m Ram,
     shared_count: u16,
     submit_counts: Box<[u16]>,
+    paused: bool,
+    /// Buffers canceled by a pause, as (queue index, descriptor ID).
+    canceled: Vec<(u16, u16)>,
 }
 
 fn submit_queue_ioeventfd<E>(index: u16, fd: &E, sq: &mut SubmissionQueue) -> Result<()>
@@ -196,10 +219,50 @@ where
     S: IrqSender,
     E: IoeventFd,
 {
+    /// Submits the buffers canceled by a pause again and returns false if
+    /// some of them have to wait.
+    fn resubmit_canceled<D>(&mut self, dev: &mut D, q_index: u16) -> Result<bool>
+    where
+        D: VirtioIoUring,
+    {
+        let Some(Some(q)) = self.queues.get_mut(q_index as usize) else {
+            return Ok(true);
+        };
+        let submit_count = self.submit_counts.get_mut(q_index as usize).unwrap();
+        while let Some(pos) = self.canceled.iter().position(|(q, _)| *q == q_index) {
+            if *submit_count >= QUEUE_RESERVE_SIZE && self.shared_count == 0 {
+                return Ok(false);
+            }
+            let (_, id) = self.canceled[pos];
+            let Some(chain) = q.deferred_mut(id) else {
+                self.canceled.swap_remove(pos);
+                continue;
+            };
+            match dev.handle_desc(q_index, chain)? {
+                BufferAction::Sqe(sqe) => {
+                    if !push_sqe(&mut self.ring, sqe, q_index, id) {
+                        log::error!("{}: queue-{q_index}: unexpected full queue", dev.name());
+                        return Ok(false);
+                    }
+                    count_submit(submit_count, &mut self.shared_count);
+                }
+                BufferAction::Written(len) => {
+                    q.handle_deferred(id, q_index, self.irq_sender, |_| Ok(len))?;
+                }
+                BufferAction::Hold => return Ok(false),
+            }
+            self.canceled.swap_remove(pos);
+        }
+        Ok(true)
+    }
+
     fn submit_buffers<D>(&mut self, dev: &mut D, q_index: u16) -> Result<()>
     where
         D: VirtioIoUring,
     {
+        if self.paused || !self.resubmit_canceled(dev, q_index)? {
+            return Ok(());
+        }
         let Some(Some(q)) = self.queues.get_mut(q_index as usize) else {
             log::error!("{}: invalid queue index {q_index}", dev.name());
             return Ok(());
@@ -213,24 +276,35 @@ where
             };
             match dev.handle_desc(q_index, chain)? {
                 BufferAction::Sqe(sqe) => {
-                    let buffer_key = ((chain.id() as u64) << 16) | q_index as u64;
-                    let sqe = sqe.user_data(buffer_key | TOKEN_DESCRIPTOR);
-                    if unsafe { self.ring.submission().push(&sqe) }.is_err() {
+                    if !push_sqe(&mut self.ring, sqe, q_index, chain.id()) {
                         log::error!("{}: queue-{q_index}: unexpected full queue", dev.name());
                         return Ok(Status::Break);
                     }
-                    *submit_count += 1;
-                    if *submit_count > QUEUE_RESERVE_SIZE {
-                        self.shared_count -= 1;
-                    }
+                    count_submit(submit_count, &mut self.shared_count);
                     Ok(Status::Deferred)
                 }
                 BufferAction::Written(len) => Ok(Status::Done { len }),
+                BufferAction::Hold => Ok(Status::Break),
             }
         })
     }
 }
 
+/// Pushes the entry of a buffer and returns false if the submission queue
+/// is full.
+fn push_sqe(ring: &mut io_uring::IoUring, sqe: Sqe, q_index: u16, id: u16) -> bool {
+    let buffer_key = ((id as u64) << 16) | q_index as u64;
+    let sqe = sqe.user_data(buffer_key | TOKEN_DESCRIPTOR);
+    unsafe { ring.submission().push(&sqe) }.is_ok()
+}
+
+fn count_submit(submit_count: &mut u16, shared_count: &mut u16) {
+    *submit_count += 1;
+    if *submit_count > QUEUE_RESERVE_SIZE {
+        *shared_count -= 1;
+    }
+}
+
 impl<'m, D, Q, S, E> ActiveBackend<D> for ActiveIoUring<'_, '_, 'm, Q, S, E>
 where
     D: VirtioIoUring,
@@ -255,6 +329,11 @@ where
                 self.shared_count += 1;
             }
             *submit_count -= 1;
+            let ret = event.result();
+            if self.paused && (ret == -libc::ECANCELED || ret == -libc::EINTR) {
+                self.canceled.push((q_index, chain_id));
+                return Ok(());
+            }
             queue.handle_deferred(chain_id, q_index, self.irq_sender, |chain| {
                 dev.complete_desc(q_index, chain, event)
             })?;
@@ -262,7 +341,13 @@ where
             self.submit_buffers(dev, q_index)
         } else if token & TOKEN_QUEUE == TOKEN_QUEUE {
             let index = token as u16;
+            if let Some(Some(queue)) = self.queues.get(index as usize) {
+                let stats = &queue.reg().stats;
+                stats.notifications.fetch_add(1, Ordering::Relaxed);
+            }
             self.submit_buffers(dev, index)
+        } else if token == TOKEN_CANCEL {
+            Ok(())
         } else {
             unreachable!()
         }
@@ -271,4 +356,59 @@ where
     fn handle_queue(&mut self, dev: &mut D, index: u16) -> Result<()> {
         self.submit_buffers(dev, index)
     }
+
+    fn update_stats(&mut self, _dev: &mut D) -> Result<()> {
+        Ok(())
+    }
+
+    fn quiesce(&mut self, dev: &mut D) -> Result<Box<[u16]>> {
+        self.paused = true;
+        for (index, q) in self.queues.iter().enumerate() {
+            let Some(q) = q else {
+                continue;
+            };
+            if !dev.cancel_on_pause(index as u16) {
+                continue;
+            }
+            for id in q.deferred_ids() {
+                let buffer_key = ((id as u64) << 16) | index as u64;
+                let cancel = opcode::AsyncCancel::new(buffer_key | TOKEN_DESCRIPTOR);
+                let entry = cancel.build().user_data(TOKEN_CANCEL);
+                while unsafe { self.ring.submission().push(&entry) }.is_err() {
+                    self.ring.submit()?;
+                }
+            }
+        }
+        while self.submit_counts.iter().any(|count| *count > 0) {
+            self.ring.submit_and_wait(1)?;
+            loop {
+                let Some(entry) = self.ring.completion().next() else {
+                    break;
+                };
+                // Wake events are still queued in the channel and are
+                // handled by the caller.
+                if entry.user_data() & TOKEN_QUEUE == TOKEN_QUEUE {
+                    self.handle_event(dev, &entry)?;
+                }
+            }
+        }
+        let queues = self.queues.iter();
+        Ok(queues
+            .map(|q| q.as_ref().map_or(0, |q| q.index()))
+            .collect())
+    }
+
+    fn resume(&mut self, dev: &mut D) -> Result<()> {
+        self.paused = false;
+        for index in 0..self.queues.len() {
+            if self.queues[index].is_some() {
+                self.submit_buffers(dev, index as u16)?;
+            }
+        }
+        Ok(())
+    }
 }
+
+#[cfg(test)]
+#[path = "io_uring_test.rs"]
+mod tests;
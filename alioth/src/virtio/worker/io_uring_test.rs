@@ -0,0 +1,269 @@
+// Copyright 2026 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::fs;
+use std::io::{PipeReader, Write};
+use std::mem::{size_of, zeroed};
+use std::os::fd::AsRawFd;
+use std::sync::mpsc::{self, Receiver};
+use std::sync::{Arc, mpsc::RecvTimeoutError};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use io_uring::cqueue::Entry as Cqe;
+use io_uring::opcode;
+use io_uring::squeue::Flags;
+use io_uring::types::Fd;
+use libc::{CPU_ISSET, CPU_SETSIZE, cpu_set_t, sched_getaffinity};
+use rstest::rstest;
+
+use crate::hv::IoeventFd;
+use crate::mem::mapped::RamBus;
+use crate::sync::notifier::Notifier;
+use crate::virtio::dev::entropy::{EntropyConfig, EntropyFeature};
+use crate::virtio::dev::{StartParam, Virtio, WakeEvent};
+use crate::virtio::queue::split::SplitQueue;
+use crate::virtio::queue::tests::GuestQueue;
+use crate::virtio::queue::{DescChain, QueueReg, VirtQueue};
+use crate::virtio::tests::{
+    DATA_ADDR, FakeIoeventFd, FakeIrqSender, fixture_queues, fixture_ram_bus,
+};
+use crate::virtio::worker::io_uring::{ActiveIoUring, BufferAction, IoUring, VirtioIoUring};
+use crate::virtio::{DeviceId, IrqSender, Result, VirtioFeature};
+
+/// A device that fills buffers with data read from a pipe.
+#[derive(Debug)]
+struct PipeDev {
+    name: &'static str,
+    reader: PipeReader,
+    /// Issues the reads from io_uring async workers.
+    force_async: bool,
+}
+
+impl Virtio for PipeDev {
+    type Config = EntropyConfig;
+    type Feature = EntropyFeature;
+
+    fn name(&self) -> &str {
+        self.name
+    }
+
+    fn id(&self) -> DeviceId {
+        DeviceId::ENTROPY
+    }
+
+    fn num_queues(&self) -> u16 {
+        1
+    }
+
+    fn config(&self) -> Arc<EntropyConfig> {
+        Arc::new(EntropyConfig)
+    }
+
+    fn feature(&self) -> u128 {
+        0
+    }
+
+    fn spawn_worker<S, E>(
+        self,
+        event_rx: Receiver<WakeEvent<S, E>>,
+        memory: Arc<RamBus>,
+        queue_regs: Arc<[QueueReg]>,
+    ) -> Result<(JoinHandle<()>, Arc<Notifier>)>
+    where
+        S: IrqSender,
+        E: IoeventFd,
+    {
+        IoUring::spawn_worker(self, event_rx, memory, queue_regs)
+    }
+}
+
+impl VirtioIoUring for PipeDev {
+    fn activate<'m, Q, S, E>(
+        &mut self,
+        _feature: u128,
+        _ring: &mut ActiveIoUring<'_, '_, 'm, Q, S, E>,
+    ) -> Result<()>
+    where
+        Q: VirtQueue<'m>,
+        S: IrqSender,
+        E: IoeventFd,
+    {
+        Ok(())
+    }
+
+    fn handle_desc(&mut self, _q_index: u16, chain: &mut DescChain) -> Result<BufferAction> {
+        let writable = &chain.writable;
+        let entry = opcode::Readv::new(
+            Fd(self.reader.as_raw_fd()),
+            writable.as_ptr() as *const _,
+            writable.len() as _,
+        );
+        let entry = if self.force_async {
+            entry.build().flags(Flags::ASYNC)
+        } else {
+            entry.build()
+        };
+        Ok(BufferAction::Sqe(entry))
+    }
+
+    fn complete_desc(&mut self, _q_index: u16, _chain: &mut DescChain, cqe: &Cqe) -> Result<u32> {
+        assert!(cqe.result() >= 0);
+        Ok(cqe.result() as u32)
+    }
+
+    fn cancel_on_pause(&self, _q_index: u16) -> bool {
+        true
+    }
+}
+
+#[rstest]
+fn test_pause_cancel(fixture_ram_bus: RamBus, fixture_queues: Box<[QueueReg]>) {
+    let ram_bus = Arc::new(fixture_ram_bus);
+    let ram = ram_bus.lock_layout();
+    let regs: Arc<[QueueReg]> = Arc::from(fixture_queues);
+    let mut guest_q = GuestQueue::new(
+        SplitQueue::new(&regs[0], &ram, false, None)
+            .unwrap()
+            .unwrap(),
+        &regs[0],
+    );
+
+    let (reader, mut writer) = std::io::pipe().unwrap();
+    let dev = PipeDev {
+        name: "pipe",
+        reader,
+        force_async: false,
+    };
+    let (tx, rx) = mpsc::channel();
+    let (handle, notifier) = dev.spawn_worker(rx, ram_bus.clone(), regs.clone()).unwrap();
+    let (irq_tx, irq_rx) = mpsc::channel();
+    let start_param = StartParam {
+        feature: VirtioFeature::VERSION_1.bits(),
+        irq_sender: Arc::new(FakeIrqSender { q_tx: irq_tx }),
+        ioeventfds: Option::<Arc<[FakeIoeventFd]>>::None,
+        iotlb: None,
+        restored: None,
+        worker_cpus: [].into(),
+    };
+    tx.send(WakeEvent::Start { param: start_param }).unwrap();
+    notifier.notify().unwrap();
+
+    let id = guest_q.add_desc(&[], &[(DATA_ADDR, 4 << 10)]);
+    tx.send(WakeEvent::Notify { q_index: 0 }).unwrap();
+    notifier.notify().unwrap();
+    let timeout = Duration::from_millis(100);
+    assert_eq!(irq_rx.recv_timeout(timeout), Err(RecvTimeoutError::Timeout));
+
+    // The pending read is canceled instead of blocking the pause.
+    let (pause_tx, pause_rx) = mpsc::channel();
+    tx.send(WakeEvent::Pause { tx: pause_tx }).unwrap();
+    notifier.notify().unwrap();
+    let state = pause_rx.recv_timeout(Duration::from_secs(1)).unwrap();
+    assert_eq!(&*state.queue_index, [0]);
+
+    writer.write_all(b"hello").unwrap();
+    assert_eq!(irq_rx.recv_timeout(timeout), Err(RecvTimeoutError::Timeout));
+
+    // The buffer is submitted again on resume.
+    tx.send(WakeEvent::Resume).unwrap();
+    assert_eq!(irq_rx.recv_timeout(Duration::from_secs(1)), Ok(0));
+    let used = guest_q.get_used().unwrap();
+    assert_eq!(used.id, id);
+    assert_eq!(used.len, 5);
+    let mut buf = [0u8; 5];
+    ram.read(DATA_ADDR, &mut buf).unwrap();
+    assert_eq!(&buf, b"hello");
+
+    tx.send(WakeEvent::Shutdown).unwrap();
+    notifier.notify().unwrap();
+    handle.join().unwrap();
+}
+
+/// Waits for a thread of the current process named `name` and returns its
+/// ID.
+fn wait_thread(name: &str) -> u32 {
+    let deadline = Instant::now() + Duration::from_secs(5);
+    loop {
+        for entry in fs::read_dir("/proc/self/task").unwrap() {
+            let entry = entry.unwrap();
+            let Ok(comm) = fs::read_to_string(entry.path().join("comm")) else {
+                continue;
+            };
+            if comm.trim_end() == name {
+                return entry.file_name().to_str().unwrap().parse().unwrap();
+            }
+        }
+        assert!(Instant::now() < deadline);
+        thread::sleep(Duration::from_millis(10));
+    }
+}
+
+#[rstest]
+fn test_worker_cpus(fixture_ram_bus: RamBus, fixture_queues: Box<[QueueReg]>) {
+    let ram_bus = Arc::new(fixture_ram_bus);
+    let ram = ram_bus.lock_layout();
+    let regs: Arc<[QueueReg]> = Arc::from(fixture_queues);
+    let mut guest_q = GuestQueue::new(
+        SplitQueue::new(&regs[0], &ram, false, None)
+            .unwrap()
+            .unwrap(),
+        &regs[0],
+    );
+
+    let mut set: cpu_set_t = unsafe { zeroed() };
+    unsafe { sched_getaffinity(0, size_of::<cpu_set_t>(), &mut set) };
+    let cpu = (0..CPU_SETSIZE as usize)
+        .find(|&cpu| unsafe { CPU_ISSET(cpu, &set) })
+        .unwrap();
+
+    let (reader, mut writer) = std::io::pipe().unwrap();
+    let name = "pipe-cpus";
+    let dev = PipeDev {
+        name,
+        reader,
+        force_async: true,
+    };
+    let (tx, rx) = mpsc::channel();
+    let (handle, notifier) = dev.spawn_worker(rx, ram_bus.clone(), regs.clone()).unwrap();
+    let (irq_tx, irq_rx) = mpsc::channel();
+    let start_param = StartParam {
+        feature: VirtioFeature::VERSION_1.bits(),
+        irq_sender: Arc::new(FakeIrqSender { q_tx: irq_tx }),
+        ioeventfds: Option::<Arc<[FakeIoeventFd]>>::None,
+        iotlb: None,
+        restored: None,
+        worker_cpus: [cpu as u32].into(),
+    };
+    tx.send(WakeEvent::Start { param: start_param }).unwrap();
+    notifier.notify().unwrap();
+
+    guest_q.add_desc(&[], &[(DATA_ADDR, 4 << 10)]);
+    tx.send(WakeEvent::Notify { q_index: 0 }).unwrap();
+    notifier.notify().unwrap();
+
+    // The async worker of the ring runs on the CPU of the worker thread.
+    let worker_tid = wait_thread(name);
+    let async_tid = wait_thread(&format!("iou-wrk-{worker_tid}"));
+    let status = fs::read_to_string(format!("/proc/self/task/{async_tid}/status")).unwrap();
+    let cpus = format!("Cpus_allowed_list:\t{cpu}\n");
+    assert!(status.contains(&cpus), "{status}");
+
+    writer.write_all(b"hello").unwrap();
+    assert_eq!(irq_rx.recv_timeout(Duration::from_secs(1)), Ok(0));
+
+    tx.send(WakeEvent::Shutdown).unwrap();
+    notifier.notify().unwrap();
+    handle.join().unwrap();
+}
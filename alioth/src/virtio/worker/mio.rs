@@ -14,6 +14,7 @@
 
 use std::os::fd::AsRawFd;
 use std::sync::Arc;
+use std::sync::atomic::Ordering;
 use std::sync::mpsc::Receiver;
 use std::thread::JoinHandle;
 
@@ -63,6 +64,18 @@ pub trait VirtioMio: Virtio {
         S: IrqSender,
         E: IoeventFd;
 
+    fn update_stats<'m, Q, S, E>(
+        &mut self,
+        _active_mio: &mut ActiveMio<'_, '_, 'm, Q, S, E>,
+    ) -> Result<()>
+    where
+        Q: VirtQueue<'m>,
+        S: IrqSender,
+        E: IoeventFd,
+    {
+        Ok(())
+    }
+
     fn reset(&mut self, registry: &Registry);
 }
 
@@ -195,7 +208,12 @@ where
     fn handle_event(&mut self, dev: &mut D, event: &Self::Event) -> Result<()> {
         let token = event.token().0 as u64;
         if token & TOKEN_QUEUE == TOKEN_QUEUE {
-            dev.handle_queue(token as u16, self)
+            let index = token as u16;
+            if let Some(Some(queue)) = self.queues.get(index as usize) {
+                let stats = &queue.reg().stats;
+                stats.notifications.fetch_add(1, Ordering::Relaxed);
+            }
+            dev.handle_queue(index, self)
         } else {
             dev.handle_event(event, self)
         }
@@ -204,4 +222,19 @@ where
     fn handle_queue(&mut self, dev: &mut D, index: u16) -> Result<()> {
         dev.handle_queue(index, self)
     }
+
+    fn update_stats(&mut self, dev: &mut D) -> Result<()> {
+        dev.update_stats(self)
+    }
+
+    fn quiesce(&mut self, _dev: &mut D) -> Result<Box<[u16]>> {
+        let queues = self.queues.iter();
+        Ok(queues
+            .map(|q| q.as_ref().map_or(0, |q| q.index()))
+            .collect())
+    }
+
+    fn resume(&mut self, _dev: &mut D) -> Result<()> {
+        Ok(())
+    }
 }
@@ -12,26 +12,26 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-#[cfg(target_os = "linux")]
+use std::any::Any;
+use std::collections::HashMap;
 use std::path::Path;
-use std::sync::Arc;
 use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Weak};
 use std::thread;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
-#[cfg(target_os = "linux")]
 use parking_lot::Mutex;
 use snafu::{ResultExt, Snafu};
 
 #[cfg(target_arch = "aarch64")]
 use crate::arch::layout::{PL011_START, PL031_START};
 #[cfg(target_arch = "x86_64")]
-use crate::arch::layout::{PORT_CMOS_REG, PORT_COM1, PORT_FW_CFG_SELECTOR, PORT_FWDBG};
-use crate::board::{Board, BoardConfig};
+use crate::arch::layout::{PORT_CMOS_REG, PORT_FW_CFG_SELECTOR, PORT_FWDBG};
+use crate::board::{Board, BoardConfig, BoardState, IommuTopology, MigrationAddr};
 use crate::device::clock::SystemClock;
 #[cfg(target_arch = "x86_64")]
 use crate::device::cmos::Cmos;
-use crate::device::console::StdioConsole;
+use crate::device::console::{ConsoleBackend, ConsoleParam};
 #[cfg(target_arch = "x86_64")]
 use crate::device::fw_cfg::{FwCfg, FwCfgItemParam};
 #[cfg(target_arch = "x86_64")]
@@ -41,10 +41,15 @@ use crate::device::pl011::Pl011;
 #[cfg(target_arch = "aarch64")]
 use crate::device::pl031::Pl031;
 #[cfg(target_arch = "x86_64")]
-use crate::device::serial::Serial;
+use crate::device::serial::{ComPort, Serial};
 use crate::errors::{DebugTrace, trace_error};
+use crate::gdb::{GdbAddr, GdbServer};
 use crate::hv::{Hypervisor, IoeventFdRegistry, Vm};
 use crate::loader::Payload;
+use crate::mem::MemRegionEntry;
+use crate::metrics::{Metrics, VcpuMetrics};
+#[cfg(target_arch = "x86_64")]
+use crate::pci::hotplug::HOTPLUG_SLOTS;
 use crate::pci::pvpanic::PvPanic;
 use crate::pci::{Bdf, Pci};
 #[cfg(target_os = "linux")]
@@ -56,13 +61,19 @@ use crate::vfio::container::{Container, UpdateContainerMapping};
 #[cfg(target_os = "linux")]
 use crate::vfio::group::{DevFd, Group};
 #[cfg(target_os = "linux")]
-use crate::vfio::iommu::{Ioas, Iommu, UpdateIommuIoas};
+use crate::vfio::iommu::{Ioas, IoasDomains, Iommu, UpdateIommuIoas};
 #[cfg(target_os = "linux")]
 use crate::vfio::pci::VfioPciDev;
 #[cfg(target_os = "linux")]
 use crate::vfio::{CdevParam, ContainerParam, GroupParam, IoasParam};
+use crate::virtio::dev::balloon::{BalloonConfigMmio, BalloonStatus, balloon_num_pages};
+use crate::virtio::dev::console::VirtioConsoleParam;
+use crate::virtio::dev::iommu::{IommuDomains, IommuEndpoint, VirtioIommu, VirtioIommuParam};
+use crate::virtio::dev::mem::{VirtioMem, VirtioMemConfigMmio, VirtioMemParam, VirtioMemRegion};
+use crate::virtio::dev::net::NetConfigMmio;
 use crate::virtio::dev::{DevParam, Virtio, VirtioDevice};
 use crate::virtio::pci::VirtioPciDevice;
+use crate::virtio::queue::Iotlb;
 
 #[trace_error]
 #[derive(Snafu, DebugTrace)]
@@ -92,12 +103,66 @@ pub enum Error {
     },
     #[snafu(display("Failed to configure guest memory"), context(false))]
     Memory { source: Box<crate::mem::Error> },
-    #[cfg(target_os = "linux")]
     #[snafu(display("{name:?} already exists"))]
     AlreadyExists { name: Box<str> },
-    #[cfg(target_os = "linux")]
     #[snafu(display("{name:?} does not exist"))]
     NotExist { name: Box<str> },
+    #[snafu(display("{name:?} is not a VirtIO net device with link control"))]
+    NotNetDev { name: Box<str> },
+    #[snafu(display("{name:?} is not a VirtIO balloon device"))]
+    NotBalloonDev { name: Box<str> },
+    #[snafu(display("Failed to resize balloon {name:?}"))]
+    ResizeBalloon {
+        name: Box<str>,
+        source: Box<crate::virtio::Error>,
+    },
+    #[snafu(display("{name:?} is not a virtio-mem device"))]
+    NotMemDev { name: Box<str> },
+    #[snafu(display("Memory hotplug is not configured"))]
+    NoMemHotplug,
+    #[snafu(display("VFIO devices cannot be used with memory hotplug"))]
+    VfioMemHotplug,
+    #[snafu(display(
+        "Size {size:#x} exceeds the hotplug region or is not aligned to block size {block_size:#x}"
+    ))]
+    InvalidMemHotplugSize { size: u64, block_size: u64 },
+    #[snafu(display("Failed to change the state of the VM"))]
+    ChangeState { source: Box<crate::board::Error> },
+    #[snafu(display("Failed to take a snapshot of the VM"))]
+    Snapshot { source: Box<crate::board::Error> },
+    #[snafu(display("Failed to restore the VM from a snapshot"))]
+    Restore { source: Box<crate::board::Error> },
+    #[snafu(display("Failed to set up the migration socket {addr:?}"))]
+    MigrationSocket {
+        addr: MigrationAddr,
+        error: std::io::Error,
+    },
+    #[snafu(display("Failed to migrate the VM"))]
+    Migrate { source: Box<crate::board::Error> },
+    #[snafu(display("Failed to receive an incoming migration"))]
+    Incoming { source: Box<crate::board::Error> },
+    #[snafu(display("Failed to set up the GDB socket {addr:?}"))]
+    GdbSocket {
+        addr: GdbAddr,
+        error: std::io::Error,
+    },
+    #[snafu(display("The VM is not running"))]
+    NotRunning,
+    #[snafu(display("Failed to create the power-off thread"))]
+    PowerOffThread { error: std::io::Error },
+    #[snafu(display("Failed to configure the worker thread of {name:?}"))]
+    WorkerThread {
+        name: Box<str>,
+        error: std::io::Error,
+    },
+    #[snafu(display("No free PCI slot for hot-plugging"))]
+    NoHotplugSlot,
+    #[snafu(display("{name:?} cannot be hot-unplugged"))]
+    NotHotpluggable { name: Box<str> },
+    #[snafu(display("virtio-iommu is not supported in confidential VMs"))]
+    IommuCoco,
+    #[snafu(display("Whether {name:?} is behind the virtio-iommu device does not match {bdf}"))]
+    IommuMismatch { name: Box<str>, bdf: Bdf },
 }
 
 type Result<T, E = Error> = std::result::Result<T, E>;
@@ -109,10 +174,30 @@ where
     board: Arc<Board<H::Vm>>,
     #[cfg(target_os = "linux")]
     iommu: Mutex<Option<Arc<Iommu>>>,
-    event_rx: Receiver<u16>,
+    virtio_devs: VirtioDevs<H::Vm>,
+    virtio_iommu: Mutex<Option<VirtioIommuHandle>>,
+    event_rx: Mutex<Receiver<u16>>,
     _event_tx: Sender<u16>,
 }
 
+struct VirtioDevHandle<V>
+where
+    V: Vm,
+{
+    dev:
+        Weak<VirtioPciDevice<V::MsiSender, <V::IoeventFdRegistry as IoeventFdRegistry>::IoeventFd>>,
+    config: Arc<dyn Any + Send + Sync>,
+}
+
+type VirtioDevs<V> = Arc<Mutex<HashMap<Arc<str>, VirtioDevHandle<V>>>>;
+
+struct VirtioIommuHandle {
+    name: Arc<str>,
+    domains: Arc<IommuDomains>,
+    #[cfg(target_os = "linux")]
+    host: Arc<IoasDomains>,
+}
+
 pub type VirtioPciDev<H> = VirtioPciDevice<
     <<H as Hypervisor>::Vm as Vm>::MsiSender,
     <<<H as Hypervisor>::Vm as Vm>::IoeventFdRegistry as IoeventFdRegistry>::IoeventFd,
@@ -145,9 +230,20 @@ where
 
         board.arch_init()?;
 
+        let virtio_devs: VirtioDevs<H::Vm> = Arc::default();
+        #[cfg(target_arch = "x86_64")]
+        {
+            let virtio_devs = virtio_devs.clone();
+            board.pci_hotplug.set_eject_listener(Arc::new(move |name| {
+                virtio_devs.lock().remove(name);
+            }));
+        }
+
         let vm = Machine {
             board,
-            event_rx,
+            virtio_devs,
+            virtio_iommu: Mutex::new(None),
+            event_rx: Mutex::new(event_rx),
             _event_tx: event_tx,
             #[cfg(target_os = "linux")]
             iommu: Mutex::new(None),
@@ -156,13 +252,32 @@ where
         Ok(vm)
     }
 
+    /// Adds a 16550A UART at `port` connected to the console backend
+    /// described by `param`.
+    ///
+    /// Returns the path of the pseudoterminal if `param` is
+    /// [`ConsoleParam::Pty`].
     #[cfg(target_arch = "x86_64")]
-    pub fn add_com1(&self) -> Result<(), Error> {
+    pub fn add_serial(
+        &self,
+        port: ComPort,
+        param: &ConsoleParam,
+    ) -> Result<Option<Box<Path>>, Error> {
+        let base_port = port.base_port();
+        let mut io_devs = self.board.io_devs.write();
+        if io_devs.iter().any(|(p, _)| *p == base_port) {
+            return error::AlreadyExists {
+                name: format!("{port:?}"),
+            }
+            .fail();
+        }
         let io_apic = self.board.arch.io_apic.clone();
-        let console = StdioConsole::new().context(error::CreateConsole)?;
-        let com1 = Serial::new(PORT_COM1, io_apic, 4, console).context(error::CreateConsole)?;
-        self.board.io_devs.write().push((PORT_COM1, Arc::new(com1)));
-        Ok(())
+        let console = ConsoleBackend::new(param).context(error::CreateConsole)?;
+        let pty = console.pty_path().map(Box::from);
+        let serial =
+            Serial::new(base_port, io_apic, port.irq(), console).context(error::CreateConsole)?;
+        io_devs.push((base_port, Arc::new(serial)));
+        Ok(pty)
     }
 
     #[cfg(target_arch = "x86_64")]
@@ -179,14 +294,20 @@ where
         Ok(())
     }
 
+    /// Adds a PL011 UART connected to the console backend described by
+    /// `param`.
+    ///
+    /// Returns the path of the pseudoterminal if `param` is
+    /// [`ConsoleParam::Pty`].
     #[cfg(target_arch = "aarch64")]
-    pub fn add_pl011(&self) -> Result<(), Error> {
+    pub fn add_pl011(&self, param: &ConsoleParam) -> Result<Option<Box<Path>>, Error> {
         let irq_line = self.board.vm.create_irq_sender(1)?;
-        let console = StdioConsole::new().context(error::CreateConsole)?;
+        let console = ConsoleBackend::new(param).context(error::CreateConsole)?;
+        let pty = console.pty_path().map(Box::from);
         let pl011_dev = Pl011::new(PL011_START, irq_line, console).context(error::CreateConsole)?;
         let mut mmio_devs = self.board.mmio_devs.write();
         mmio_devs.push((PL011_START, Arc::new(pl011_dev)));
-        Ok(())
+        Ok(pty)
     }
 
     #[cfg(target_arch = "aarch64")]
@@ -237,6 +358,20 @@ where
         name: impl Into<Arc<str>>,
         param: P,
     ) -> Result<Arc<VirtioPciDev<H>>, Error>
+    where
+        P: DevParam<Device = D>,
+        D: Virtio,
+    {
+        let bdf = self.board.pci_bus.reserve(None).unwrap();
+        self.add_virtio_dev_at(bdf, name.into(), param)
+    }
+
+    fn add_virtio_dev_at<D, P>(
+        &self,
+        bdf: Bdf,
+        name: Arc<str>,
+        param: P,
+    ) -> Result<Arc<VirtioPciDev<H>>, Error>
     where
         P: DevParam<Device = D>,
         D: Virtio,
@@ -244,32 +379,265 @@ where
         if param.needs_mem_shared_fd() && !self.board.config.mem.has_shared_fd() {
             return error::MemNotSharedFd.fail();
         }
-        let name = name.into();
-        let bdf = self.board.pci_bus.reserve(None).unwrap();
         let dev = param.build(name.clone())?;
+        self.attach_virtio_dev(bdf, name, dev)
+    }
+
+    fn attach_virtio_dev<D>(
+        &self,
+        bdf: Bdf,
+        name: Arc<str>,
+        dev: D,
+    ) -> Result<Arc<VirtioPciDev<H>>, Error>
+    where
+        D: Virtio,
+    {
+        let memory = &self.board.memory;
         if let Some(callback) = dev.mem_update_callback() {
-            self.board.memory.register_update_callback(callback)?;
+            memory.register_dev_update_callback(name.clone(), callback)?;
         }
         if let Some(callback) = dev.mem_change_callback() {
-            self.board.memory.register_change_callback(callback)?;
+            memory.register_dev_change_callback(name.clone(), callback)?;
         }
+        let config = dev.config();
         let registry = self.board.vm.create_ioeventfd_registry()?;
+        let iotlb = self.add_iommu_endpoint(bdf, dev.dma_translatable());
         let virtio_dev = VirtioDevice::new(
             name.clone(),
             dev,
             self.board.memory.ram_bus(),
-            self.board.config.coco.is_some(),
+            self.board.config.coco.is_some() || iotlb.is_some(),
+            self.board.config.worker.cpus.as_slice().into(),
         )?;
+        // The io_uring async workers are created later by the kernel for the
+        // worker thread. They do not inherit its CPU affinity, so the worker
+        // binds them to the same CPUs when it starts.
+        #[cfg(target_os = "linux")]
+        if let Some(thread) = virtio_dev.worker_thread() {
+            let config = &self.board.config.worker;
+            config
+                .apply(thread)
+                .context(error::WorkerThread { name: &*name })?;
+        }
         let msi_sender = self.board.vm.create_msi_sender(
             #[cfg(target_arch = "aarch64")]
             u32::from(bdf.0),
         )?;
-        let dev = VirtioPciDevice::new(virtio_dev, msi_sender, registry)?;
+        let dev = VirtioPciDevice::new(virtio_dev, msi_sender, registry, iotlb)?;
         let dev = Arc::new(dev);
         self.add_pci_dev(Some(bdf), dev.clone())?;
+        let handle = VirtioDevHandle {
+            dev: Arc::downgrade(&dev),
+            config,
+        };
+        self.virtio_devs.lock().insert(name, handle);
         Ok(dev)
     }
 
+    /// Adds a VirtIO console device.
+    ///
+    /// Returns the indexes and the paths of the ports connected to
+    /// pseudoterminals.
+    pub fn add_virtio_console(
+        &self,
+        name: impl Into<Arc<str>>,
+        param: VirtioConsoleParam,
+    ) -> Result<Vec<(usize, Box<Path>)>, Error> {
+        let name = name.into();
+        let dev = param.build(name.clone())?;
+        let ptys = dev.pty_paths();
+        let bdf = self.board.pci_bus.reserve(None).unwrap();
+        self.attach_virtio_dev(bdf, name, dev)?;
+        Ok(ptys)
+    }
+
+    /// Adds a virtio-iommu device. VirtIO and VFIO devices added after it
+    /// are placed behind it, while existing devices keep accessing guest
+    /// memory directly.
+    pub fn add_virtio_iommu(
+        &self,
+        name: impl Into<Arc<str>>,
+        param: VirtioIommuParam,
+    ) -> Result<Arc<VirtioPciDev<H>>, Error> {
+        if self.board.config.coco.is_some() {
+            return error::IommuCoco.fail();
+        }
+        if let Some(handle) = &*self.virtio_iommu.lock() {
+            return error::AlreadyExists {
+                name: &*handle.name,
+            }
+            .fail();
+        }
+        let name = name.into();
+        #[cfg(target_os = "linux")]
+        let host = Arc::new(IoasDomains::new(self.board.memory.ram_bus()));
+        #[cfg(target_os = "linux")]
+        let dev = VirtioIommu::new(param, name.clone(), Some(host.clone()));
+        #[cfg(not(target_os = "linux"))]
+        let dev = VirtioIommu::new(param, name.clone(), None);
+        let domains = dev.domains();
+        let bypass = self.pci_devices().into_iter().map(|(bdf, _)| bdf);
+        let bypass = bypass.collect();
+        let bdf = self.board.pci_bus.reserve(None).unwrap();
+        let dev = self.attach_virtio_dev(bdf, name.clone(), dev)?;
+        *self.board.iommu.lock() = Some(IommuTopology { bdf, bypass });
+        *self.virtio_iommu.lock() = Some(VirtioIommuHandle {
+            name,
+            domains,
+            #[cfg(target_os = "linux")]
+            host,
+        });
+        Ok(dev)
+    }
+
+    /// Places the device at `bdf` behind the virtio-iommu device, if any.
+    ///
+    /// Returns the IOTLB translating the DMA addresses of the device, or
+    /// `None` if the device accesses guest memory directly.
+    fn add_iommu_endpoint(&self, bdf: Bdf, translatable: bool) -> Option<Arc<dyn Iotlb>> {
+        let domains = self.virtio_iommu.lock().as_ref()?.domains.clone();
+        if !translatable {
+            self.bypass_iommu(bdf);
+            return None;
+        }
+        let id = u32::from(bdf.0);
+        domains.add_endpoint(id, false);
+        Some(Arc::new(IommuEndpoint::new(id, domains)))
+    }
+
+    /// Excludes the device at `bdf` from the virtio-iommu device, if any.
+    fn bypass_iommu(&self, bdf: Bdf) {
+        if let Some(topology) = &mut *self.board.iommu.lock() {
+            topology.bypass.insert(bdf);
+        }
+    }
+
+    /// Sets the link state of a VirtIO net device and notifies the guest.
+    pub fn set_net_link(&self, name: &str, up: bool) -> Result<()> {
+        let virtio_devs = self.virtio_devs.lock();
+        let Some(handle) = virtio_devs.get(name) else {
+            return error::NotExist { name }.fail();
+        };
+        let Some(dev) = handle.dev.upgrade() else {
+            return error::NotExist { name }.fail();
+        };
+        let Ok(config) = handle.config.clone().downcast::<NetConfigMmio>() else {
+            return error::NotNetDev { name }.fail();
+        };
+        if !config.set_link_up(up) {
+            return Ok(());
+        }
+        dev.config_changed();
+        if up {
+            // Workers may have left receive buffers in the queues while the
+            // link was down.
+            for q_index in (0..config.max_queue_pairs() * 2).step_by(2) {
+                dev.dev.notify_queue(q_index);
+            }
+        }
+        Ok(())
+    }
+
+    /// Sets the size of a VirtIO balloon device, i.e. memory the guest is
+    /// asked to give up, and notifies the guest.
+    pub fn set_balloon_size(&self, name: &str, size: u64) -> Result<()> {
+        let virtio_devs = self.virtio_devs.lock();
+        let Some(handle) = virtio_devs.get(name) else {
+            return error::NotExist { name }.fail();
+        };
+        let Some(dev) = handle.dev.upgrade() else {
+            return error::NotExist { name }.fail();
+        };
+        let Ok(config) = handle.config.clone().downcast::<BalloonConfigMmio>() else {
+            return error::NotBalloonDev { name }.fail();
+        };
+        let mem = &self.board.config.mem;
+        let num_pages = balloon_num_pages(size, mem.size + mem.hotplug_size)
+            .context(error::ResizeBalloon { name })?;
+        if config.set_num_pages(num_pages) {
+            dev.config_changed();
+        }
+        Ok(())
+    }
+
+    /// Returns the target and actual sizes of a VirtIO balloon device, and
+    /// the memory statistics last reported by the guest.
+    ///
+    /// The guest refreshes its statistics every `stats_interval` seconds, and
+    /// is also asked to refresh them here for the next call.
+    pub fn balloon_status(&self, name: &str) -> Result<BalloonStatus> {
+        let virtio_devs = self.virtio_devs.lock();
+        let Some(handle) = virtio_devs.get(name) else {
+            return error::NotExist { name }.fail();
+        };
+        let Some(dev) = handle.dev.upgrade() else {
+            return error::NotExist { name }.fail();
+        };
+        let Some(config) = handle.config.downcast_ref::<BalloonConfigMmio>() else {
+            return error::NotBalloonDev { name }.fail();
+        };
+        let status = BalloonStatus {
+            size: (config.num_pages() as u64) << 12,
+            actual: (config.actual() as u64) << 12,
+            stats: config.stats(),
+        };
+        dev.dev.update_stats();
+        Ok(status)
+    }
+
+    /// Adds a virtio-mem device that manages the memory hotplug region.
+    pub fn add_virtio_mem(
+        &self,
+        name: impl Into<Arc<str>>,
+        param: VirtioMemParam,
+    ) -> Result<Arc<VirtioPciDev<H>>, Error> {
+        let config = &self.board.config;
+        if config.mem.hotplug_size == 0 {
+            return error::NoMemHotplug.fail();
+        }
+        let virtio_devs = self.virtio_devs.lock();
+        if let Some((name, _)) = virtio_devs
+            .iter()
+            .find(|(_, h)| h.config.is::<VirtioMemConfigMmio>())
+        {
+            return error::AlreadyExists { name: &**name }.fail();
+        }
+        drop(virtio_devs);
+        let region = VirtioMemRegion {
+            addr: config.mem_hotplug_start(),
+            size: config.mem.hotplug_size,
+            page_size: config.mem.page_size(),
+            shared: config.mem.shared || config.mem.has_shared_fd(),
+        };
+        let name = name.into();
+        let dev = VirtioMem::new(param, name.clone(), region)?;
+        let bdf = self.board.pci_bus.reserve(None).unwrap();
+        self.attach_virtio_dev(bdf, name, dev)
+    }
+
+    /// Changes the size of memory the guest is asked to plug through a
+    /// virtio-mem device.
+    pub fn resize_virtio_mem(&self, name: &str, size: u64) -> Result<()> {
+        let virtio_devs = self.virtio_devs.lock();
+        let Some(handle) = virtio_devs.get(name) else {
+            return error::NotExist { name }.fail();
+        };
+        let Some(dev) = handle.dev.upgrade() else {
+            return error::NotExist { name }.fail();
+        };
+        let Ok(config) = handle.config.clone().downcast::<VirtioMemConfigMmio>() else {
+            return error::NotMemDev { name }.fail();
+        };
+        let block_size = config.block_size();
+        if size > config.region_size() || size & (block_size - 1) != 0 {
+            return error::InvalidMemHotplugSize { size, block_size }.fail();
+        }
+        if config.set_requested_size(size) {
+            dev.config_changed();
+        }
+        Ok(())
+    }
+
     pub fn add_payload(&self, payload: Payload) {
         *self.board.payload.write() = Some(payload)
     }
@@ -279,11 +647,245 @@ where
         Ok(())
     }
 
+    pub fn state(&self) -> BoardState {
+        self.board.state()
+    }
+
+    pub fn pause(&self) -> Result<()> {
+        self.board.pause().context(error::ChangeState)
+    }
+
+    pub fn resume(&self) -> Result<()> {
+        self.board.resume().context(error::ChangeState)
+    }
+
+    /// Stops the VM immediately, without notifying the guest.
+    pub fn shutdown(&self) -> Result<()> {
+        self.board.shutdown().context(error::ChangeState)
+    }
+
+    /// Presses the power button so that the guest can shut itself down.
+    /// The VM is stopped if it is still running after `timeout`, or right
+    /// away if the board has no power button.
+    pub fn power_off(&self, timeout: Duration) -> Result<()> {
+        match self.board.press_power_button() {
+            Ok(()) => {}
+            Err(e @ crate::board::Error::NoPowerButton { .. }) => {
+                log::warn!("{e}, stopping the VM");
+                return self.shutdown();
+            }
+            Err(e) => return Err(e).context(error::ChangeState),
+        }
+        log::info!("Pressed the power button, waiting {timeout:?} for the guest");
+        let board = self.board.clone();
+        thread::Builder::new()
+            .name("power-off".to_owned())
+            .spawn(move || {
+                let deadline = Instant::now() + timeout;
+                while Instant::now() < deadline {
+                    if board.state() == BoardState::Shutdown {
+                        return;
+                    }
+                    thread::sleep(Duration::from_millis(100));
+                }
+                log::warn!("Guest did not power off in {timeout:?}, stopping the VM");
+                if let Err(e) = board.shutdown() {
+                    log::error!("Failed to stop the VM: {e:?}");
+                }
+            })
+            .context(error::PowerOffThread)?;
+        Ok(())
+    }
+
+    /// Resets the VM and boots it again from the payload.
+    pub fn reboot(&self) -> Result<()> {
+        self.board.reboot().context(error::ChangeState)
+    }
+
+    /// Writes a snapshot of the paused VM to `dir`.
+    pub fn snapshot(&self, dir: &Path) -> Result<()> {
+        self.board.snapshot(dir).context(error::Snapshot)
+    }
+
+    /// Boots the VM from the snapshot in `dir` instead of the payload.
+    pub fn restore(&self, dir: &Path) -> Result<()> {
+        self.board.load_snapshot(dir).context(error::Restore)?;
+        self.boot()
+    }
+
+    /// Migrates the running VM to a destination waiting at `addr`. The VM
+    /// shuts down once the destination has taken over.
+    pub fn migrate(&self, addr: &MigrationAddr) -> Result<()> {
+        let mut stream = addr
+            .connect()
+            .context(error::MigrationSocket { addr: addr.clone() })?;
+        self.board.migrate(&mut stream).context(error::Migrate)
+    }
+
+    /// Waits for a migration source to connect to `addr`, and boots the VM
+    /// from the state it sends.
+    pub fn incoming(&self, addr: &MigrationAddr) -> Result<()> {
+        log::info!("Waiting for an incoming migration at {addr:?}");
+        let stream = addr
+            .accept()
+            .context(error::MigrationSocket { addr: addr.clone() })?;
+        self.board
+            .accept_migration(stream)
+            .context(error::Incoming)?;
+        self.boot()
+    }
+
+    /// Serves a debugger at `addr`. The VM halts before its first
+    /// instruction until a debugger attaches and continues it.
+    pub fn add_gdb_stub(&self, addr: &GdbAddr) -> Result<GdbServer> {
+        let server = GdbServer::new(addr.clone(), self.board.clone())
+            .context(error::GdbSocket { addr: addr.clone() })?;
+        self.board.halt_on_boot();
+        log::info!("Waiting for a debugger at {addr:?}");
+        Ok(server)
+    }
+
+    /// Adds a VirtIO device to the running VM and notifies the guest.
+    pub fn hotplug_virtio_dev<D, P>(&self, name: impl Into<Arc<str>>, param: P) -> Result<()>
+    where
+        P: DevParam<Device = D>,
+        D: Virtio,
+    {
+        let name = name.into();
+        self.hotplug(&name, |bdf| {
+            self.add_virtio_dev_at(bdf, name.clone(), param)?;
+            Ok(())
+        })
+    }
+
+    /// Adds a VFIO device to the running VM and notifies the guest.
+    #[cfg(target_os = "linux")]
+    pub fn hotplug_vfio_cdev(&self, name: Arc<str>, param: CdevParam) -> Result<()> {
+        self.hotplug(&name, |bdf| self.add_vfio_cdev_at(bdf, name.clone(), param))
+    }
+
+    /// Asks the guest to release a PCI device. The device is removed once
+    /// the guest ejects it.
+    pub fn unplug_pci_dev(&self, name: &str) -> Result<()> {
+        let Some((bdf, _)) = self.find_pci_dev(name) else {
+            return error::NotExist { name }.fail();
+        };
+        #[cfg(target_arch = "x86_64")]
+        if bdf.bus() == 0 && bdf.func() == 0 && HOTPLUG_SLOTS.contains(&bdf.dev()) {
+            self.check_running()?;
+            self.board.pci_hotplug.unplug(bdf)?;
+            return Ok(());
+        }
+        log::error!("{bdf}: {name} is not in a hotplug slot");
+        error::NotHotpluggable { name }.fail()
+    }
+
+    fn hotplug(&self, name: &str, add: impl FnOnce(Bdf) -> Result<()>) -> Result<()> {
+        self.check_running()?;
+        if self.find_pci_dev(name).is_some() {
+            return error::AlreadyExists { name }.fail();
+        }
+        let bdf = self.reserve_hotplug_slot()?;
+        // The guest learns which devices are behind the virtio-iommu device
+        // from firmware tables built at boot, so a new device must agree
+        // with the slot it is plugged into.
+        let was_bypass = self.take_iommu_bypass(bdf);
+        let mut ret = add(bdf);
+        let is_bypass = self.take_iommu_bypass(bdf);
+        if ret.is_ok() && is_bypass != was_bypass {
+            ret = error::IommuMismatch { name, bdf }.fail();
+        }
+        if was_bypass {
+            self.bypass_iommu(bdf);
+        }
+        if let Err(e) = ret {
+            self.board.pci_bus.segment.remove(bdf);
+            return Err(e);
+        }
+        #[cfg(target_arch = "x86_64")]
+        self.board.pci_hotplug.plug(bdf)?;
+        Ok(())
+    }
+
+    fn take_iommu_bypass(&self, bdf: Bdf) -> bool {
+        let mut iommu = self.board.iommu.lock();
+        iommu.as_mut().is_some_and(|t| t.bypass.remove(&bdf))
+    }
+
+    fn check_running(&self) -> Result<()> {
+        match self.board.state() {
+            BoardState::Running | BoardState::Paused => Ok(()),
+            BoardState::Shutdown | BoardState::RebootPending => error::NotRunning.fail(),
+        }
+    }
+
+    fn reserve_hotplug_slot(&self) -> Result<Bdf> {
+        #[cfg(target_arch = "x86_64")]
+        for slot in HOTPLUG_SLOTS {
+            if let Some(bdf) = self.board.pci_bus.reserve(Some(Bdf::new(0, slot, 0))) {
+                return Ok(bdf);
+            }
+        }
+        error::NoHotplugSlot.fail()
+    }
+
+    fn find_pci_dev(&self, name: &str) -> Option<(Bdf, Arc<dyn Pci>)> {
+        let mut devices = self.pci_devices().into_iter();
+        devices.find(|(_, dev)| dev.name() == name)
+    }
+
+    /// Returns the PCI devices of the VM, sorted by their BDFs.
+    pub fn pci_devices(&self) -> Vec<(Bdf, Arc<dyn Pci>)> {
+        self.board.pci_bus.segment.devices()
+    }
+
+    /// Returns the configured memory size and the layout of guest memory.
+    pub fn mem_regions(&self) -> (u64, Vec<(u64, MemRegionEntry)>) {
+        let size = self.board.config.mem.size;
+        (size, self.board.memory.mem_region_entries())
+    }
+
+    /// Returns the runtime counters of the VM.
+    ///
+    /// Balloon devices are also asked to refresh their guest memory
+    /// statistics, so the values reported by the guest are those from the
+    /// previous call.
+    pub fn metrics(&self) -> Metrics {
+        let vcpus = self.board.vcpu_exits.iter().enumerate();
+        let vcpus = vcpus.map(|(index, exits)| VcpuMetrics {
+            index: index as u16,
+            exits: exits.get(),
+        });
+        let mut metrics = Metrics {
+            vcpus: vcpus.collect(),
+            #[cfg(target_arch = "x86_64")]
+            io_regions: self.board.memory.io_metrics(),
+            mmio_regions: self.board.memory.mmio_metrics(),
+            ..Default::default()
+        };
+        let virtio_devs = self.virtio_devs.lock();
+        for handle in virtio_devs.values() {
+            let Some(dev) = handle.dev.upgrade() else {
+                continue;
+            };
+            let mut dev_metrics = dev.dev.metrics();
+            if let Some(config) = handle.config.downcast_ref::<BalloonConfigMmio>() {
+                let stats = config.stats().into_iter();
+                dev_metrics.guest_stats = stats.map(|(tag, val)| (tag.name(), val)).collect();
+                dev.dev.update_stats();
+            }
+            metrics.virtio.push(dev_metrics);
+        }
+        metrics.virtio.sort_by(|a, b| a.name.cmp(&b.name));
+        metrics
+    }
+
     pub fn wait(&self) -> Result<()> {
-        self.event_rx.recv().unwrap();
+        let event_rx = self.event_rx.lock();
+        event_rx.recv().unwrap();
         let vcpus = self.board.vcpus.read();
         for _ in 1..vcpus.len() {
-            self.event_rx.recv().unwrap();
+            event_rx.recv().unwrap();
         }
         drop(vcpus);
         let mut vcpus = self.board.vcpus.write();
@@ -310,7 +912,17 @@ where
 {
     const DEFAULT_NAME: &str = "default";
 
+    /// VFIO maps and pins all guest memory, but pages of the hotplug region
+    /// are inaccessible until the guest plugs them.
+    fn check_mem_hotplug(&self) -> Result<()> {
+        if self.board.config.mem.hotplug_size != 0 {
+            return error::VfioMemHotplug.fail();
+        }
+        Ok(())
+    }
+
     pub fn add_vfio_ioas(&self, param: IoasParam) -> Result<Arc<Ioas>, Error> {
+        self.check_mem_hotplug()?;
         let mut ioases = self.board.vfio_ioases.lock();
         if ioases.contains_key(&param.name) {
             return error::AlreadyExists { name: param.name }.fail();
@@ -351,12 +963,21 @@ where
     }
 
     pub fn add_vfio_cdev(&self, name: Arc<str>, param: CdevParam) -> Result<(), Error> {
+        let bdf = self.board.pci_bus.reserve(None).unwrap();
+        self.add_vfio_cdev_at(bdf, name, param)
+    }
+
+    fn add_vfio_cdev_at(&self, bdf: Bdf, name: Arc<str>, param: CdevParam) -> Result<(), Error> {
         let ioas = self.get_ioas(param.ioas.as_deref())?;
 
         let mut cdev = Cdev::new(&param.path)?;
         cdev.attach_iommu_ioas(ioas.clone())?;
+        if let Some(handle) = &*self.virtio_iommu.lock() {
+            let id = u32::from(bdf.0);
+            handle.host.add_device(id, cdev.pt_handle(), ioas);
+            handle.domains.add_endpoint(id, true);
+        }
 
-        let bdf = self.board.pci_bus.reserve(None).unwrap();
         let msi_sender = self.board.vm.create_msi_sender(
             #[cfg(target_arch = "aarch64")]
             u32::from(bdf.0),
@@ -367,6 +988,7 @@ where
     }
 
     pub fn add_vfio_container(&self, param: ContainerParam) -> Result<Arc<Container>, Error> {
+        self.check_mem_hotplug()?;
         let mut containers = self.board.vfio_containers.lock();
         if containers.contains_key(&param.name) {
             return error::AlreadyExists { name: param.name }.fail();
@@ -418,8 +1040,11 @@ where
         Ok(())
     }
 
+    /// Devices in VFIO groups are attached to containers, which cannot
+    /// follow the domains of the virtio-iommu device.
     fn add_vfio_devfd(&self, name: Arc<str>, devfd: DevFd) -> Result<()> {
         let bdf = self.board.pci_bus.reserve(None).unwrap();
+        self.bypass_iommu(bdf);
         let msi_sender = self.board.vm.create_msi_sender(
             #[cfg(target_arch = "aarch64")]
             u32::from(bdf.0),
@@ -428,3 +1053,7 @@ where
         self.add_pci_dev(Some(bdf), Arc::new(dev))
     }
 }
+
+#[cfg(test)]
+#[path = "vm_test.rs"]
+mod tests;
@@ -0,0 +1,243 @@
+// Copyright 2026 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::fs;
+use std::path::Path;
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use assert_matches::assert_matches;
+use tempfile::TempDir;
+
+use crate::board::{BoardConfig, BoardState, MigrationAddr, MigrationUdsParam};
+use crate::hv::{Kvm, KvmConfig};
+use crate::loader::Payload;
+use crate::mem::MemConfig;
+use crate::mem::emulated::Mmio;
+use crate::mem::mapped::RamBus;
+use crate::vfio::{ContainerParam, IoasParam};
+use crate::virtio::DevStatus;
+use crate::virtio::dev::balloon::BalloonParam;
+use crate::virtio::dev::entropy::EntropyParam;
+use crate::virtio::pci::{VirtioCommonCfg, VirtioPciRegister};
+use crate::virtio::queue::DescFlag;
+use crate::virtio::queue::split::Desc;
+use crate::vm::{Error, Machine, VirtioPciDev};
+
+const COUNTER: u64 = 0x500;
+const QUEUE_SIZE: u16 = 4;
+const DESC: u64 = 0x1_0000;
+const AVAIL: u64 = 0x1_1000;
+const USED: u64 = 0x1_2000;
+const BUF: u64 = 0x2_0000;
+const BUF_SIZE: u32 = 16;
+
+/// Writes a firmware that keeps incrementing the 16-bit counter at
+/// [`COUNTER`].
+fn write_firmware(path: &Path) {
+    let mut rom = vec![0u8; 4 << 10];
+    // inc word [0x500]; jmp $-4
+    rom[0xff0..0xff6].copy_from_slice(&[0xff, 0x06, 0x00, 0x05, 0xeb, 0xfa]);
+    fs::write(path, rom).unwrap();
+}
+
+/// Creates a machine with an entropy device and a balloon device.
+fn new_machine(kvm: &Kvm, firmware: &Path) -> (Machine<Kvm>, [Arc<VirtioPciDev<Kvm>>; 2]) {
+    let config = BoardConfig {
+        mem: MemConfig {
+            size: 64 << 20,
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    let vm = Machine::new(kvm, config).unwrap();
+    let dev = vm
+        .add_virtio_dev("entropy", EntropyParam { source: None })
+        .unwrap();
+    let balloon = vm
+        .add_virtio_dev("balloon", BalloonParam::default())
+        .unwrap();
+    vm.add_payload(Payload {
+        firmware: Some(firmware.into()),
+        executable: None,
+        initramfs: None,
+        cmdline: None,
+    });
+    (vm, [dev, balloon])
+}
+
+fn write_common_cfg(dev: &VirtioPciDev<Kvm>, layout: (usize, usize), val: u64) {
+    let (offset, size) = layout;
+    let offset = (VirtioPciRegister::OFFSET_COMMON + offset) as u64;
+    dev.registers.write(offset, size as u8, val).unwrap();
+}
+
+/// Negotiates features and, if `queue` is true, sets up queue 0 the way a
+/// driver does.
+fn init_driver(dev: &VirtioPciDev<Kvm>, queue: bool) {
+    let status = DevStatus::ACK | DevStatus::DRIVER;
+    write_common_cfg(
+        dev,
+        VirtioCommonCfg::LAYOUT_DEVICE_STATUS,
+        status.bits() as u64,
+    );
+    write_common_cfg(dev, VirtioCommonCfg::LAYOUT_DRIVER_FEATURE_SELECT, 1);
+    // VIRTIO_F_VERSION_1
+    write_common_cfg(dev, VirtioCommonCfg::LAYOUT_DRIVER_FEATURE, 1);
+    let status = status | DevStatus::FEATURES_OK;
+    write_common_cfg(
+        dev,
+        VirtioCommonCfg::LAYOUT_DEVICE_STATUS,
+        status.bits() as u64,
+    );
+    if queue {
+        write_common_cfg(dev, VirtioCommonCfg::LAYOUT_QUEUE_SELECT, 0);
+        write_common_cfg(dev, VirtioCommonCfg::LAYOUT_QUEUE_SIZE, QUEUE_SIZE as u64);
+        write_common_cfg(dev, VirtioCommonCfg::LAYOUT_QUEUE_DESC_LO, DESC);
+        write_common_cfg(dev, VirtioCommonCfg::LAYOUT_QUEUE_DRIVER_LO, AVAIL);
+        write_common_cfg(dev, VirtioCommonCfg::LAYOUT_QUEUE_DEVICE_LO, USED);
+        write_common_cfg(dev, VirtioCommonCfg::LAYOUT_QUEUE_ENABLE, 1);
+    }
+    let status = status | DevStatus::DRIVER_OK;
+    write_common_cfg(
+        dev,
+        VirtioCommonCfg::LAYOUT_DEVICE_STATUS,
+        status.bits() as u64,
+    );
+}
+
+/// Makes buffer `id` available and returns the length the device used.
+fn request_entropy(ram_bus: &RamBus, dev: &VirtioPciDev<Kvm>, id: u16) -> u32 {
+    let desc = Desc {
+        addr: BUF + id as u64 * BUF_SIZE as u64,
+        len: BUF_SIZE,
+        flag: DescFlag::WRITE.bits(),
+        next: 0,
+    };
+    ram_bus.write_t(DESC + id as u64 * 16, &desc).unwrap();
+    ram_bus.write_t(AVAIL + 4 + id as u64 * 2, &id).unwrap();
+    ram_bus.write_t(AVAIL + 2, &(id + 1)).unwrap();
+    let offset = VirtioPciRegister::OFFSET_QUEUE_NOTIFY as u64;
+    dev.registers.write(offset, 4, 0).unwrap();
+
+    let deadline = Instant::now() + Duration::from_secs(5);
+    while ram_bus.read_t::<u16>(USED + 2).unwrap() != id + 1 {
+        assert!(Instant::now() < deadline);
+        thread::sleep(Duration::from_millis(10));
+    }
+    let [used_id, len] = ram_bus
+        .read_t::<[u32; 2]>(USED + 4 + id as u64 * 8)
+        .unwrap();
+    assert_eq!(used_id, id as u32);
+    len
+}
+
+fn wait_counter_change(ram_bus: &RamBus) {
+    let deadline = Instant::now() + Duration::from_secs(5);
+    // RAM is created by the boot VCPU.
+    let counter = loop {
+        if let Ok(counter) = ram_bus.read_t::<u16>(COUNTER) {
+            break counter;
+        }
+        assert!(Instant::now() < deadline);
+        thread::sleep(Duration::from_millis(10));
+    };
+    while ram_bus.read_t::<u16>(COUNTER).unwrap() == counter {
+        assert!(Instant::now() < deadline);
+        thread::sleep(Duration::from_millis(10));
+    }
+}
+
+#[test]
+#[cfg_attr(not(feature = "test-hv"), ignore)]
+fn test_migrate_with_virtio_dev() {
+    let temp_dir = TempDir::new().unwrap();
+    let firmware = temp_dir.path().join("firmware.bin");
+    write_firmware(&firmware);
+    let addr = MigrationAddr::Uds(MigrationUdsParam {
+        path: temp_dir.path().join("m.sock").into(),
+    });
+
+    let kvm = Kvm::new(KvmConfig::default()).unwrap();
+    let (src, [src_dev, src_balloon]) = new_machine(&kvm, &firmware);
+    let (dst, [dst_dev, _]) = new_machine(&kvm, &firmware);
+
+    src.boot().unwrap();
+    let src_ram = src.board.memory.ram_bus();
+    wait_counter_change(&src_ram);
+    init_driver(&src_dev, true);
+    assert_eq!(request_entropy(&src_ram, &src_dev, 0), BUF_SIZE);
+    // The guest has given up 1 MiB of the 8 MiB the balloon asks for.
+    init_driver(&src_balloon, false);
+    src.set_balloon_size("balloon", 8 << 20).unwrap();
+    src_balloon.dev.device_config.write(4, 4, 0x100).unwrap();
+    let mut src_buf = [0u8; BUF_SIZE as usize];
+    src_ram.read(BUF, &mut src_buf).unwrap();
+
+    let incoming = thread::scope(|s| {
+        let incoming = s.spawn(|| dst.incoming(&addr));
+        while !fs::exists(temp_dir.path().join("m.sock")).unwrap() {
+            thread::sleep(Duration::from_millis(10));
+        }
+        src.migrate(&addr).unwrap();
+        incoming.join().unwrap()
+    });
+    incoming.unwrap();
+    src.wait().unwrap();
+    assert_eq!(src.state(), BoardState::Shutdown);
+
+    let dst_ram = dst.board.memory.ram_bus();
+    let mut dst_buf = [0u8; BUF_SIZE as usize];
+    dst_ram.read(BUF, &mut dst_buf).unwrap();
+    assert_eq!(src_buf, dst_buf);
+
+    // The guest keeps running and the device resumes from where it was.
+    wait_counter_change(&dst_ram);
+    assert_eq!(request_entropy(&dst_ram, &dst_dev, 1), BUF_SIZE);
+    let status = dst.balloon_status("balloon").unwrap();
+    assert_eq!(status.size, 8 << 20);
+    assert_eq!(status.actual, 1 << 20);
+
+    dst.shutdown().unwrap();
+    dst.wait().unwrap();
+}
+
+#[test]
+#[cfg_attr(not(feature = "test-hv"), ignore)]
+fn test_vfio_with_mem_hotplug() {
+    let kvm = Kvm::new(KvmConfig::default()).unwrap();
+    let config = BoardConfig {
+        mem: MemConfig {
+            size: 64 << 20,
+            hotplug_size: 64 << 20,
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    let vm = Machine::new(&kvm, config).unwrap();
+    let ioas = IoasParam {
+        name: "ioas".into(),
+        dev_iommu: None,
+    };
+    assert_matches!(vm.add_vfio_ioas(ioas), Err(Error::VfioMemHotplug { .. }));
+    let container = ContainerParam {
+        name: "container".into(),
+        dev_vfio: None,
+    };
+    assert_matches!(
+        vm.add_vfio_container(container),
+        Err(Error::VfioMemHotplug { .. })
+    );
+}